@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use futures::{StreamExt, stream};
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBError, DCBEvent, DCBEventStoreAsync};
+use umadb_server::start_server;
+use uuid::Uuid;
+
+const ADDR: &str = "127.0.0.1:50088";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const DATA_LEN: usize = 200 * 1024 * 1024;
+
+// Synthetic data, generated a chunk at a time
+fn byte_at(i: usize, j: usize) -> u8 {
+    ((i * 31 + j) % 251) as u8
+}
+
+fn chunk(i: usize) -> Vec<u8> {
+    (0..CHUNK_SIZE).map(|j| byte_at(i, j)).collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn append_stream_stores_a_large_event() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let uuid = Uuid::new_v4();
+    let event = DCBEvent::new()
+        .event_type("Large")
+        .tags(["large"])
+        .uuid(uuid);
+    let chunks = stream::iter((0..DATA_LEN / CHUNK_SIZE).map(chunk));
+    let position = client
+        .append_stream(event, DATA_LEN as u64, chunks)
+        .await
+        .expect("append stream");
+    assert_eq!(1, position);
+
+    let found = client.read_by_uuid(uuid).await.unwrap().unwrap();
+    assert_eq!(position, found.position);
+    assert_eq!("Large", found.event.event_type);
+    assert_eq!(DATA_LEN, found.event.data.len());
+    for (i, data) in found.event.data.chunks(CHUNK_SIZE).enumerate() {
+        assert_eq!(chunk(i), data, "chunk {i}");
+    }
+
+    // Streamed back a page of its overflow chain at a time
+    let mut data = std::pin::pin!(client.read_event_data_stream(position));
+    let (mut offset, mut count) = (0, 0);
+    while let Some(piece) = data.next().await {
+        let piece = piece.unwrap();
+        assert!(piece.len() < CHUNK_SIZE);
+        let expected = (offset..offset + piece.len())
+            .map(|at| byte_at(at / CHUNK_SIZE, at % CHUNK_SIZE))
+            .collect::<Vec<u8>>();
+        assert!(expected == piece, "piece at offset {offset}");
+        offset += piece.len();
+        count += 1;
+    }
+    assert_eq!(DATA_LEN, offset);
+    assert!(count > DATA_LEN / CHUNK_SIZE);
+
+    let mut missing = std::pin::pin!(client.read_event_data_stream(position + 1));
+    assert!(matches!(
+        missing.next().await,
+        Some(Err(DCBError::EventNotFound(_)))
+    ));
+
+    // Fewer bytes than declared are rejected, and nothing is appended
+    let chunks = stream::iter((0..2).map(chunk));
+    let result = client
+        .append_stream(
+            DCBEvent::new().event_type("Short"),
+            3 * CHUNK_SIZE as u64,
+            chunks,
+        )
+        .await;
+    assert!(matches!(result, Err(DCBError::SerializationError(_))));
+    assert_eq!(Some(position), client.head().await.unwrap());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBError, DCBEvent, DCBEventStoreAsync};
+use umadb_server::start_server;
+use uuid::Uuid;
+
+const ADDR: &str = "127.0.0.1:50080";
+
+async fn spawn_server(db_path: PathBuf) -> (oneshot::Sender<()>, JoinHandle<()>) {
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    (shutdown_tx, server_task)
+}
+
+fn event(i: usize, uuid: Option<Uuid>) -> DCBEvent {
+    DCBEvent {
+        event_type: "ReconnectEvent".to_string(),
+        data: format!("data-{i}").into_bytes(),
+        tags: vec!["reconnect".to_string()],
+        uuid,
+        client_timestamp: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn appends_resume_after_server_restart() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, server_task) = spawn_server(db_path.clone()).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .max_retries(10)
+        .retry_backoff(Duration::from_millis(50))
+        .without_sigint_handler()
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    for i in 0..5 {
+        client
+            .append(vec![event(i, Some(Uuid::new_v4()))], None)
+            .await
+            .expect("append before restart");
+    }
+
+    // Kill the server
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+
+    // An append without a UUID isn't known to be idempotent, so isn't retried
+    match client.append(vec![event(5, None)], None).await {
+        Err(DCBError::TransportError(_)) => {}
+        other => panic!("Expected TransportError, got {other:?}"),
+    }
+
+    // Restart the server while the next append is being retried
+    let restart = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        spawn_server(db_path).await
+    });
+    let position = client
+        .append(vec![event(6, Some(Uuid::new_v4()))], None)
+        .await
+        .expect("append retried until the server restarted");
+    assert_eq!(6, position);
+    let (shutdown_tx, server_task) = restart.await.unwrap();
+
+    // Subsequent appends and reads succeed on the reconnected channel
+    for i in 7..10 {
+        client
+            .append(vec![event(i, Some(Uuid::new_v4()))], None)
+            .await
+            .expect("append after restart");
+    }
+    assert_eq!(Some(9), client.head().await.unwrap());
+
+    // Appending an event with a recorded UUID again doesn't record it twice
+    let uuid = Uuid::new_v4();
+    let first = client
+        .append(vec![event(10, Some(uuid))], None)
+        .await
+        .unwrap();
+    let again = client
+        .append(vec![event(10, Some(uuid))], None)
+        .await
+        .unwrap();
+    assert_eq!(first, again);
+    assert_eq!(Some(10), client.head().await.unwrap());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
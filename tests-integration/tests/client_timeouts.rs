@@ -0,0 +1,61 @@
+use std::net::{Ipv4Addr, TcpListener};
+use std::time::{Duration, Instant};
+
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBError, DCBEventStoreAsync};
+
+// A listener that never accepts: connections are queued by the OS, but
+// nothing ever responds on them
+fn unresponsive_listener() -> (TcpListener, u16) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("bind :0");
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn connect_fails_within_connect_timeout() {
+    let (_listener, port) = unresponsive_listener();
+
+    // The TLS handshake never completes, so connecting can't finish
+    let started = Instant::now();
+    let result = UmaDBClient::new(format!("https://localhost:{port}"))
+        .connect_timeout(Duration::from_millis(300))
+        .without_sigint_handler()
+        .connect_async()
+        .await;
+    let elapsed = started.elapsed();
+
+    match result {
+        Err(DCBError::Timeout(msg)) => assert!(msg.contains("connect"), "{msg}"),
+        Err(other) => panic!("Expected Timeout, got {other:?}"),
+        Ok(_) => panic!("Expected Timeout, but connected"),
+    }
+    assert!(elapsed < Duration::from_secs(2), "took {elapsed:?}");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn requests_fail_within_request_timeout() {
+    let (_listener, port) = unresponsive_listener();
+
+    let client = UmaDBClient::new(format!("http://127.0.0.1:{port}"))
+        .request_timeout(Duration::from_millis(300))
+        .without_sigint_handler()
+        .connect_async()
+        .await
+        .expect("plaintext connect only needs TCP");
+
+    let started = Instant::now();
+    match client.head().await {
+        Err(DCBError::Timeout(_)) => {}
+        other => panic!("Expected Timeout, got {other:?}"),
+    }
+    match client.append(vec![], None).await {
+        Err(DCBError::Timeout(_)) => {}
+        other => panic!("Expected Timeout, got {other:?}"),
+    }
+    assert!(
+        client.read(None, None, false, None, false).await.is_err(),
+        "read should time out"
+    );
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
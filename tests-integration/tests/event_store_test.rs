@@ -31,6 +31,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
         data: b"data1".to_vec(),
         tags: vec!["tagX".to_string()],
         uuid: None,
+        client_timestamp: None,
     };
     let position = event_store.append(vec![event1.clone()], None).unwrap();
 
@@ -119,6 +120,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type1".to_string()],
             tags: vec![],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type1.clone()), None, false, None)
@@ -141,6 +143,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type2".to_string()],
             tags: vec![],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type2.clone()), None, false, None)
@@ -161,6 +164,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec![],
             tags: vec!["tagX".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_x.clone()), None, false, None)
@@ -183,6 +187,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec![],
             tags: vec!["tagY".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_y.clone()), None, false, None)
@@ -202,6 +207,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type1".to_string()],
             tags: vec!["tagX".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type1_tag_x.clone()), None, false, None)
@@ -222,6 +228,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type1".to_string()],
             tags: vec!["tagY".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type1_tag_y.clone()), None, false, None)
@@ -241,6 +248,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type2".to_string()],
             tags: vec!["tagX".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type2_tag_x.clone()), None, false, None)
@@ -260,12 +268,14 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
         data: b"data2".to_vec(),
         tags: vec!["tagA".to_string(), "tagB".to_string()],
         uuid: None,
+        client_timestamp: None,
     };
     let event3 = DCBEvent {
         event_type: "type3".to_string(),
         data: b"data3".to_vec(),
         tags: vec!["tagA".to_string(), "tagC".to_string()],
         uuid: None,
+        client_timestamp: None,
     };
     let position = event_store
         .append(vec![event2.clone(), event3.clone()], None)
@@ -429,6 +439,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec![],
             tags: vec!["tagA".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_a.clone()), None, false, None)
@@ -453,6 +464,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec![],
             tags: vec!["tagA".to_string(), "tagB".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_a_and_b.clone()), None, false, None)
@@ -481,6 +493,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                 tags: vec!["tagC".to_string()],
             },
         ],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_b_or_c.clone()), None, false, None)
@@ -511,6 +524,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                 tags: vec!["tagY".to_string()],
             },
         ],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_tag_x_or_y.clone()), None, false, None)
@@ -533,6 +547,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["type2".to_string()],
             tags: vec!["tagA".to_string()],
         }],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(query_type2_tag_a.clone()), None, false, None)
@@ -576,6 +591,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                 tags: vec!["tagC".to_string()],
             },
         ],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(
@@ -629,6 +645,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                 tags: vec!["tagB".to_string()],
             },
         ],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(
@@ -676,6 +693,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
         data: b"data4".to_vec(),
         tags: vec![],
         uuid: None,
+        client_timestamp: None,
     };
 
     // Fail because condition matches all.
@@ -803,6 +821,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
             types: vec!["typeN".to_string()],
             tags: vec![],
         }],
+        case_insensitive: false,
     };
     let position = event_store
         .append(
@@ -879,17 +898,19 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
     let student_id = format!("student1-{}", Uuid::new_v4());
     let student_registered = DCBEvent {
         event_type: "StudentRegistered".to_string(),
-        data: format!(r#"{{"name": "Student1", "max_courses": 10}}"#).into_bytes(),
+        data: r#"{"name": "Student1", "max_courses": 10}"#.to_string().into_bytes(),
         tags: vec![student_id.clone()],
         uuid: None,
+        client_timestamp: None,
     };
 
     let course_id = format!("course1-{}", Uuid::new_v4());
     let course_registered = DCBEvent {
         event_type: "CourseRegistered".to_string(),
-        data: format!(r#"{{"name": "Course1", "places": 10}}"#).into_bytes(),
+        data: r#"{"name": "Course1", "places": 10}"#.to_string().into_bytes(),
         tags: vec![course_id.clone()],
         uuid: None,
+        client_timestamp: None,
     };
 
     let student_joined_course = DCBEvent {
@@ -901,6 +922,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
         .into_bytes(),
         tags: vec![course_id.clone(), student_id.clone()],
         uuid: None,
+        client_timestamp: None,
     };
 
     let _position = event_store
@@ -912,6 +934,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         types: vec!["StudentRegistered".to_string()],
                         tags: student_registered.tags.clone(),
                     }],
+                    case_insensitive: false,
                 },
                 after: Some(3),
             }),
@@ -927,6 +950,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         types: vec![],
                         tags: course_registered.tags.clone(),
                     }],
+                    case_insensitive: false,
                 },
                 after: Some(3),
             }),
@@ -942,6 +966,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         types: vec![],
                         tags: student_joined_course.tags.clone(),
                     }],
+                    case_insensitive: false,
                 },
                 after: Some(3),
             }),
@@ -987,6 +1012,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -1006,6 +1032,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             true,
@@ -1025,6 +1052,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -1044,6 +1072,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             true,
@@ -1065,6 +1094,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         student_joined_course.tags[1].clone(),
                     ],
                 }],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -1082,6 +1112,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1099,6 +1130,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             true,
@@ -1116,6 +1148,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1133,6 +1166,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             true,
@@ -1153,6 +1187,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         student_joined_course.tags[1].clone(),
                     ],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1173,6 +1208,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         student_joined_course.tags[1].clone(),
                     ],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             true,
@@ -1190,6 +1226,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1207,6 +1244,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![student_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(13),
             true,
@@ -1224,6 +1262,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1241,6 +1280,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: vec![course_id.clone()],
                 }],
+                case_insensitive: false,
             }),
             Some(13),
             true,
@@ -1261,6 +1301,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         student_joined_course.tags[1].clone(),
                     ],
                 }],
+                case_insensitive: false,
             }),
             Some(3),
             false,
@@ -1281,6 +1322,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         student_joined_course.tags[1].clone(),
                     ],
                 }],
+                case_insensitive: false,
             }),
             Some(13),
             true,
@@ -1308,6 +1350,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                 tags: vec![course_id.clone()],
             },
         ],
+        case_insensitive: false,
     };
     let (result, head) = event_store
         .read_with_head(Some(consistency_boundary.clone()), None, false, None)
@@ -1338,6 +1381,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
         data: b"data5".to_vec(),
         tags: vec!["tag5".to_string()],
         uuid: Some(Uuid::new_v4()),
+        client_timestamp: None,
     };
 
     let commit_position5 = event_store
@@ -1349,6 +1393,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         types: vec![],
                         tags: event5.tags.clone(),
                     }],
+                    case_insensitive: false,
                 },
                 after: Some(13),
             }),
@@ -1362,6 +1407,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: event5.tags.clone(),
                 }],
+                case_insensitive: false,
             }),
             Some(14),
             false,
@@ -1383,6 +1429,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                         types: vec![],
                         tags: event5.tags.clone(),
                     }],
+                    case_insensitive: false,
                 },
                 after: Some(13),
             }),
@@ -1398,6 +1445,7 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
                     types: vec![],
                     tags: event5.tags.clone(),
                 }],
+                case_insensitive: false,
             }),
             Some(14),
             false,
@@ -1412,6 +1460,8 @@ pub fn dcb_event_store_test<T: DCBEventStoreSync>(event_store: &T) {
 
 #[test]
 fn test_direct_event_store() {
+    // The embedded store blocks on the Mvcc directly, no async runtime is involved
+    assert!(tokio::runtime::Handle::try_current().is_err());
     let temp_dir = tempdir().unwrap();
     let event_store = UmaDB::new(temp_dir.path()).unwrap();
     dcb_event_store_test(&event_store);
@@ -1482,12 +1532,14 @@ fn test_tag_hash_collision() {
         data: b"student-data".to_vec(),
         tags: vec![student_tag.clone()],
         uuid: None,
+        client_timestamp: None,
     };
     let ev_course = DCBEvent {
         event_type: "CourseEvent".to_string(),
         data: b"course-data".to_vec(),
         tags: vec![course_tag.clone()],
         uuid: None,
+        client_timestamp: None,
     };
 
     let _ = store.append(vec![ev_student.clone()], None).unwrap();
@@ -1501,6 +1553,7 @@ fn test_tag_hash_collision() {
                     types: vec![],
                     tags: vec![student_tag.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -1523,6 +1576,7 @@ fn test_tag_hash_collision() {
                     types: vec![],
                     tags: vec![course_tag.clone()],
                 }],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -1551,6 +1605,7 @@ fn test_tag_hash_collision() {
                         tags: vec![course_tag.clone()],
                     },
                 ],
+                case_insensitive: false,
             }),
             None,
             false,
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use tonic::Code;
+use umadb_client::UmaDBClient;
+use umadb_dcb::{
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBQuery, DCBQueryItem,
+};
+use umadb_proto::{
+    AppendConditionProto, AppendRequestProto, EventProto, QueryItemProto, QueryProto,
+    UmaDbServiceClient,
+};
+use umadb_server::start_server;
+
+const ADDR: &str = "127.0.0.1:50084";
+
+fn event(i: usize) -> DCBEvent {
+    DCBEvent {
+        event_type: "StatusEvent".to_string(),
+        data: format!("data-{i}").into_bytes(),
+        tags: vec!["status".to_string()],
+        uuid: None,
+        client_timestamp: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn failed_append_conditions_are_failed_precondition() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+    client.append(vec![event(0)], None).await.expect("append");
+
+    // The raw status of a conflicting append
+    let mut raw = UmaDbServiceClient::connect(format!("http://{ADDR}"))
+        .await
+        .expect("raw connect");
+    let request = AppendRequestProto {
+        events: vec![EventProto {
+            event_type: "StatusEvent".to_string(),
+            tags: vec!["status".to_string()],
+            data: b"conflict".to_vec(),
+            uuid: String::new(),
+            client_timestamp: None,
+        }],
+        condition: Some(AppendConditionProto {
+            fail_if_events_match: Some(QueryProto {
+                items: vec![QueryItemProto {
+                    types: vec![],
+                    tags: vec!["status".to_string()],
+                }],
+                case_insensitive: false,
+            }),
+            after: None,
+        }),
+    };
+    let status = raw.append(request).await.expect_err("conflict");
+    assert_eq!(Code::FailedPrecondition, status.code());
+
+    // Which the client turns back into an integrity error
+    let condition = DCBAppendCondition {
+        fail_if_events_match: DCBQuery {
+            items: vec![DCBQueryItem {
+                types: vec![],
+                tags: vec!["status".to_string()],
+            }],
+            case_insensitive: false,
+        },
+        after: None,
+    };
+    match client.append(vec![event(1)], Some(condition)).await {
+        Err(DCBError::IntegrityError(_)) => {}
+        other => panic!("Expected IntegrityError, got {other:?}"),
+    }
+
+    // An event with a malformed UUID is the client's mistake
+    let request = AppendRequestProto {
+        events: vec![EventProto {
+            event_type: "StatusEvent".to_string(),
+            tags: vec![],
+            data: vec![],
+            uuid: "not-a-uuid".to_string(),
+            client_timestamp: None,
+        }],
+        condition: None,
+    };
+    let status = raw.append(request).await.expect_err("invalid uuid");
+    assert_eq!(Code::InvalidArgument, status.code());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
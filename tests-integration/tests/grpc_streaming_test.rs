@@ -1,5 +1,5 @@
 use umadb_client::UmaDBClient;
-use umadb_dcb::{DCBEvent, DCBEventStoreAsync};
+use umadb_dcb::{DCBEvent, DCBEventStoreAsync, DCBQuery, DCBQueryItem};
 use umadb_server::start_server;
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
@@ -12,7 +12,7 @@ async fn grpc_async_streams_large_reads_total_count() {
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_task = tokio::spawn(async move {
-        let _ = start_server(db_path, &addr, shutdown_rx).await;
+        let _ = start_server(db_path, addr, shutdown_rx).await;
     });
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
 
@@ -29,6 +29,7 @@ async fn grpc_async_streams_large_reads_total_count() {
             data: format!("data-{i}").into_bytes(),
             tags: vec!["grpc-test".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let last_pos = client
@@ -68,7 +69,7 @@ async fn grpc_async_does_not_stream_past_starting_head() {
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_task = tokio::spawn(async move {
-        let _ = start_server(db_path, &addr, shutdown_rx).await;
+        let _ = start_server(db_path, addr, shutdown_rx).await;
     });
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
 
@@ -84,6 +85,7 @@ async fn grpc_async_does_not_stream_past_starting_head() {
             data: format!("data-{i}").into_bytes(),
             tags: vec!["grpc-boundary".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -104,6 +106,7 @@ async fn grpc_async_does_not_stream_past_starting_head() {
             data: format!("new-{i}").into_bytes(),
             tags: vec!["grpc-boundary".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -137,7 +140,7 @@ async fn grpc_async_subscription_catch_up_and_continue() {
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_task = tokio::spawn(async move {
-        let _ = start_server(db_path, &addr, shutdown_rx).await;
+        let _ = start_server(db_path, addr, shutdown_rx).await;
     });
     tokio::time::sleep(std::time::Duration::from_millis(200)).await;
 
@@ -154,6 +157,7 @@ async fn grpc_async_subscription_catch_up_and_continue() {
             data: format!("init-{i}").into_bytes(),
             tags: vec!["grpc-sub".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -194,6 +198,7 @@ async fn grpc_async_subscription_catch_up_and_continue() {
             data: format!("new-{i}").into_bytes(),
             tags: vec!["grpc-sub".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -232,7 +237,7 @@ async fn grpc_async_stream_catch_up_and_continue() {
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_task = tokio::spawn(async move {
-        let _ = start_server(db_path, &addr, shutdown_rx).await;
+        let _ = start_server(db_path, addr, shutdown_rx).await;
     });
 
     sleep(TokioDuration::from_millis(200)).await;
@@ -250,6 +255,7 @@ async fn grpc_async_stream_catch_up_and_continue() {
             data: format!("init-{i}").into_bytes(),
             tags: vec!["grpc-async".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -285,6 +291,7 @@ async fn grpc_async_stream_catch_up_and_continue() {
             data: format!("new-{i}").into_bytes(),
             tags: vec!["grpc-async".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let _ = client
@@ -310,3 +317,410 @@ async fn grpc_async_stream_catch_up_and_continue() {
     let _ = shutdown_tx.send(());
     let _ = server_task.await;
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_streams_events_in_order_from_position() {
+    // Arrange: start a gRPC server backed by a temporary directory
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50076";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    // Append 10k events in two commits, every other one tagged "even"
+    let mut events: Vec<DCBEvent> = (0..10_000)
+        .map(|i| DCBEvent {
+            event_type: "TestEvent".to_string(),
+            data: format!("data-{i}").into_bytes(),
+            tags: if i % 2 == 0 {
+                vec!["even".to_string()]
+            } else {
+                vec![]
+            },
+            uuid: None,
+            client_timestamp: None,
+        })
+        .collect();
+    let second = events.split_off(5_000);
+    client
+        .append(events, None)
+        .await
+        .expect("append first 5k events");
+    let last_pos = client
+        .append(second, None)
+        .await
+        .expect("append second 5k events");
+    assert_eq!(10_000, last_pos);
+
+    // Act: stream everything back, batch by batch
+    let mut resp = client
+        .read(None, None, false, None, false)
+        .await
+        .expect("read_stream");
+    let mut positions = Vec::new();
+    let mut tsns = Vec::new();
+    loop {
+        let batch = resp.next_batch().await.expect("next_batch");
+        if batch.is_empty() {
+            break;
+        }
+        for event in batch {
+            assert_eq!(
+                format!("data-{}", event.position - 1).into_bytes(),
+                event.event.data
+            );
+            positions.push(event.position);
+            tsns.push(event.tsn.expect("event carries the Tsn of its commit"));
+        }
+    }
+    assert_eq!((1..=10_000).collect::<Vec<u64>>(), positions);
+    // The events of each commit share its Tsn, and later commits have larger ones
+    assert!(tsns[..5_000].iter().all(|&tsn| tsn == tsns[0]));
+    assert!(tsns[5_000..].iter().all(|&tsn| tsn == tsns[5_000]));
+    assert!(tsns[0] < tsns[5_000]);
+
+    // Act: replay the tagged events forward from a position
+    let query = DCBQuery::new().item(DCBQueryItem::new().tags(["even"]));
+    let mut resp = client
+        .read(Some(query), Some(5_001), false, None, false)
+        .await
+        .expect("read_stream from position");
+    let mut positions = Vec::new();
+    loop {
+        let batch = resp.next_batch().await.expect("next_batch");
+        if batch.is_empty() {
+            break;
+        }
+        positions.extend(batch.iter().map(|e| e.position));
+    }
+    assert_eq!((5_001..=10_000).step_by(2).collect::<Vec<u64>>(), positions);
+
+    // Cleanup
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_subscribe_receives_new_matching_events() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50077";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let event = |event_type: &str, tag: &str| DCBEvent {
+        event_type: event_type.to_string(),
+        data: vec![],
+        tags: vec![tag.to_string()],
+        uuid: None,
+        client_timestamp: None,
+    };
+    client
+        .append(
+            vec![event("Old", "watched"), event("Other", "ignored")],
+            None,
+        )
+        .await
+        .expect("append initial events");
+
+    // Subscribe from position 1, so the historical match arrives first
+    let query = DCBQuery::new().item(DCBQueryItem::new().tags(["watched"]));
+    let mut resp = client
+        .subscribe(Some(query), Some(1))
+        .await
+        .expect("subscription stream");
+    let timeout = std::time::Duration::from_secs(5);
+    let batch = tokio::time::timeout(timeout, resp.next_batch())
+        .await
+        .expect("historical event within timeout")
+        .expect("next_batch");
+    assert_eq!(
+        vec![1],
+        batch.iter().map(|e| e.position).collect::<Vec<_>>()
+    );
+
+    // Only the new matching event is delivered
+    client
+        .append(
+            vec![event("Other", "ignored"), event("New", "watched")],
+            None,
+        )
+        .await
+        .expect("append during subscription");
+    let batch = tokio::time::timeout(timeout, resp.next_batch())
+        .await
+        .expect("new event within timeout")
+        .expect("next_batch");
+    assert_eq!(1, batch.len());
+    assert_eq!(4, batch[0].position);
+    assert_eq!("New", batch[0].event.event_type);
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_count_matches_events_streamed() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50078";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let events: Vec<DCBEvent> = (0..2000)
+        .map(|i| DCBEvent {
+            event_type: "CountEvent".to_string(),
+            data: vec![],
+            tags: vec![format!("bucket-{}", i % 7)],
+            uuid: None,
+            client_timestamp: None,
+        })
+        .collect();
+    client.append(events, None).await.expect("append events");
+
+    for query in [
+        None,
+        Some(DCBQuery::new().item(DCBQueryItem::new().tags(["bucket-3"]))),
+        Some(DCBQuery::new().item(DCBQueryItem::new().tags(["missing"]))),
+    ] {
+        let (events, _) = client
+            .read_with_head(query.clone(), None, false, None)
+            .await
+            .expect("read events");
+        let count = client.count(query).await.expect("count");
+        assert_eq!(events.len() as u64, count);
+    }
+    assert_eq!(2000, client.count(None).await.expect("count all"));
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_read_range_boundaries() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50079";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let events: Vec<DCBEvent> = (0..1000)
+        .map(|i| DCBEvent {
+            event_type: "RangeEvent".to_string(),
+            data: format!("data-{i}").into_bytes(),
+            tags: vec![],
+            uuid: None,
+            client_timestamp: None,
+        })
+        .collect();
+    client.append(events, None).await.expect("append events");
+
+    for (start, end, expected) in [
+        (500, 600, (500..600).collect::<Vec<u64>>()),
+        (500, 501, vec![500]),
+        (500, 500, vec![]),
+        (0, 2, vec![1]),
+        (999, 2000, vec![999, 1000]),
+    ] {
+        let (events, _) = client
+            .read_range(start, end)
+            .await
+            .expect("read_range")
+            .collect_with_head()
+            .await
+            .expect("collect");
+        let positions: Vec<u64> = events.iter().map(|e| e.position).collect();
+        assert_eq!(expected, positions, "range {start}..{end}");
+    }
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_stats_reflect_appended_events() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50081";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let before = client.stats().await.expect("stats");
+    assert_eq!(0, before.event_count);
+    assert_eq!(1, before.events_tree_height);
+
+    // Append enough events over several commits to split the events tree and free pages
+    for batch in 0..10 {
+        let events: Vec<DCBEvent> = (0..500)
+            .map(|i| DCBEvent {
+                event_type: "StatsEvent".to_string(),
+                data: format!("data-{batch}-{i}").into_bytes(),
+                tags: vec![format!("stats-{}", i % 5)],
+                uuid: None,
+                client_timestamp: None,
+            })
+            .collect();
+        client.append(events, None).await.expect("append events");
+    }
+
+    let after = client.stats().await.expect("stats");
+    assert_eq!(5000, after.event_count);
+    assert!(after.events_tree_height > 1);
+    assert!(after.total_pages > before.total_pages);
+    assert!(after.free_pages > 0);
+    assert!(after.free_pages < after.total_pages);
+    assert!(after.file_size >= after.total_pages * 4096);
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_read_by_uuid_returns_the_appended_event() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50085";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let uuid = umadb_dcb::new_event_uuid();
+    let events = vec![
+        DCBEvent::new().event_type("Other"),
+        DCBEvent::new()
+            .event_type("Target")
+            .data(b"payload")
+            .tags(["uuid-test"])
+            .uuid(uuid),
+    ];
+    client.append(events, None).await.expect("append events");
+
+    let found = client
+        .read_by_uuid(uuid)
+        .await
+        .expect("read by uuid")
+        .expect("event with uuid");
+    assert_eq!(2, found.position);
+    assert_eq!("Target", found.event.event_type);
+    assert_eq!(b"payload".to_vec(), found.event.data);
+    assert_eq!(vec!["uuid-test".to_string()], found.event.tags);
+    assert_eq!(Some(uuid), found.event.uuid);
+
+    let missing = client
+        .read_by_uuid(umadb_dcb::new_event_uuid())
+        .await
+        .expect("read by uuid");
+    assert!(missing.is_none());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_async_export_ndjson_writes_every_event() {
+    // Arrange
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let addr = "127.0.0.1:50086";
+    let addr_http = format!("http://{}", addr);
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, addr, shutdown_rx).await;
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(addr_http.clone())
+        .batch_size(100)
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let events: Vec<DCBEvent> = (0..2000)
+        .map(|i| {
+            DCBEvent::new()
+                .event_type("ExportEvent")
+                .data(format!("data-{i}").into_bytes())
+                .tags([format!("export-{}", i % 3)])
+        })
+        .collect();
+    client.append(events, None).await.expect("append events");
+
+    let mut out = Vec::new();
+    assert_eq!(2000, client.export_ndjson(&mut out).await.expect("export"));
+    let text = String::from_utf8(out).unwrap();
+    for (i, line) in text.lines().enumerate() {
+        let event = umadb_dcb::ndjson::parse_ndjson_event(line).unwrap();
+        assert_eq!(i as u64 + 1, event.position);
+        assert_eq!(format!("data-{i}").into_bytes(), event.event.data);
+        assert_eq!(vec![format!("export-{}", i % 3)], event.event.tags);
+    }
+    assert_eq!(2000, text.lines().count());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
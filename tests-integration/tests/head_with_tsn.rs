@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBEvent, DCBEventStoreAsync};
+use umadb_server::start_server;
+
+const ADDR: &str = "127.0.0.1:50087";
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn head_with_tsn_advances_with_each_commit() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+    assert_eq!(None, client.head_with_tsn().await.unwrap());
+
+    let event = DCBEvent::new().event_type("HeadEvent");
+    let position = client.append(vec![event.clone()], None).await.unwrap();
+    let (head, tsn) = client.head_with_tsn().await.unwrap().unwrap();
+    assert_eq!(position, head);
+    assert_eq!(Some(head), client.head().await.unwrap());
+
+    // Each append is its own commit
+    let position = client.append(vec![event], None).await.unwrap();
+    assert_eq!(
+        Some((position, tsn + 1)),
+        client.head_with_tsn().await.unwrap()
+    );
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBEvent, DCBEventStoreAsync};
+use umadb_server::start_server_with_metrics;
+
+const ADDR: &str = "127.0.0.1:50082";
+const METRICS_ADDR: &str = "127.0.0.1:50083";
+
+async fn scrape(path: &str) -> String {
+    let mut stream = TcpStream::connect(METRICS_ADDR)
+        .await
+        .expect("connect metrics");
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {METRICS_ADDR}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+fn metric(response: &str, name: &str) -> Option<f64> {
+    response.lines().find_map(|line| {
+        let (line_name, value) = line.split_once(' ')?;
+        (line_name == name).then(|| value.parse().unwrap())
+    })
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn appending_increments_the_append_counter() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server_with_metrics(db_path, ADDR, METRICS_ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let before = scrape("/metrics").await;
+    assert!(before.starts_with("HTTP/1.1 200 OK"), "{before}");
+    assert_eq!(Some(0.0), metric(&before, "umadb_append_requests_total"));
+
+    for i in 0..3 {
+        let event = DCBEvent {
+            event_type: "MetricsEvent".to_string(),
+            data: format!("data-{i}").into_bytes(),
+            tags: vec!["metrics".to_string()],
+            uuid: None,
+            client_timestamp: None,
+        };
+        client.append(vec![event], None).await.expect("append");
+    }
+
+    let after = scrape("/metrics").await;
+    assert_eq!(Some(3.0), metric(&after, "umadb_append_requests_total"));
+    assert_eq!(Some(3.0), metric(&after, "umadb_appended_events_total"));
+    assert_eq!(
+        Some(3.0),
+        metric(&after, "umadb_append_duration_seconds_count")
+    );
+    assert_eq!(Some(3.0), metric(&after, "umadb_events"));
+    assert!(metric(&after, "umadb_commit_syncs_total").unwrap() >= 3.0);
+    assert!(metric(&after, "umadb_events_tree_height").is_some());
+    assert!(metric(&after, "umadb_free_pages").is_some());
+
+    assert!(scrape("/other").await.starts_with("HTTP/1.1 404 Not Found"));
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use umadb_client::UmaDBClient;
+use umadb_dcb::{
+    DCBError, DCBEvent, DCBEventStoreAsync, DCBQuery, DCBQueryItem, DCBSequencedEvent,
+};
+use umadb_server::start_server;
+
+const ADDR: &str = "127.0.0.1:50090";
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_as_of_sees_only_earlier_commits() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    let event = |event_type: &str| DCBEvent::new().event_type(event_type).tags(["history"]);
+    client
+        .append(vec![event("First1"), event("First2")], None)
+        .await
+        .unwrap();
+    let (_, first) = client.head_with_tsn().await.unwrap().unwrap();
+    client.append(vec![event("Second")], None).await.unwrap();
+    let (_, second) = client.head_with_tsn().await.unwrap().unwrap();
+
+    let types = |events: Vec<DCBSequencedEvent>| -> Vec<String> {
+        events.into_iter().map(|e| e.event.event_type).collect()
+    };
+    assert_eq!(
+        vec!["First1", "First2"],
+        types(client.read_as_of(first, None).await.unwrap())
+    );
+    assert_eq!(
+        vec!["First1", "First2", "Second"],
+        types(client.read_as_of(second, None).await.unwrap())
+    );
+    let query = DCBQuery::new().item(DCBQueryItem::new().types(["First2", "Second"]));
+    assert_eq!(
+        vec!["First2"],
+        types(client.read_as_of(first, Some(query)).await.unwrap())
+    );
+
+    // A commit that was never made isn't in the history
+    match client.read_as_of(second + 1, None).await {
+        Err(DCBError::InvalidArgument(_)) => {}
+        other => panic!("Expected InvalidArgument, got {other:?}"),
+    }
+
+    // Commits older than the server's history are out of retention
+    assert!(client.oldest_tsn().await.unwrap() <= first);
+    for i in 0..64 {
+        client
+            .append(vec![event(&format!("Later{i}"))], None)
+            .await
+            .unwrap();
+    }
+    assert!(client.oldest_tsn().await.unwrap() > first);
+    match client.read_as_of(first, None).await {
+        Err(DCBError::OutOfRetention(_)) => {}
+        other => panic!("Expected OutOfRetention, got {other:?}"),
+    }
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use umadb_client::UmaDBClient;
+use umadb_dcb::{DCBEvent, DCBEventStoreAsync, DCBQuery, DCBQueryItem};
+use umadb_server::start_server;
+
+const ADDR: &str = "127.0.0.1:50089";
+
+fn by_tag(tag: &str) -> DCBQuery {
+    DCBQuery::new().item(DCBQueryItem::new().tags([tag]))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_multi_attributes_events_to_overlapping_queries() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server(db_path, ADDR, shutdown_rx).await;
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = UmaDBClient::new(format!("http://{ADDR}"))
+        .connect_async()
+        .await
+        .expect("client connect");
+
+    // Enough events for the matches to span several response batches
+    let events: Vec<DCBEvent> = (1..=300u64)
+        .map(|i| {
+            let mut tags = vec![format!("mod-2-{}", i % 2)];
+            if i % 3 == 0 {
+                tags.push("three".to_string());
+            }
+            let event_type = if i % 5 == 0 { "Fifth" } else { "Other" };
+            DCBEvent::new().event_type(event_type).tags(tags)
+        })
+        .collect();
+    client.append(events, None).await.unwrap();
+
+    let queries = vec![
+        by_tag("mod-2-0"),
+        by_tag("three"),
+        DCBQuery::new().item(DCBQueryItem::new().types(["Fifth"])),
+    ];
+    let expected = |after: u64| -> Vec<(u64, Vec<usize>)> {
+        (after + 1..=300)
+            .map(|i| {
+                let matched: Vec<usize> = [i % 2 == 0, i % 3 == 0, i % 5 == 0]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, m)| **m)
+                    .map(|(index, _)| index)
+                    .collect();
+                (i, matched)
+            })
+            .filter(|(_, matched)| !matched.is_empty())
+            .collect()
+    };
+    for after in [None, Some(150)] {
+        let events = client.read_multi(queries.clone(), after).await.unwrap();
+        let matched: Vec<(u64, Vec<usize>)> = events
+            .into_iter()
+            .map(|e| (e.event.position, e.query_indices))
+            .collect();
+        assert_eq!(expected(after.unwrap_or(0)), matched);
+    }
+    assert!(client.read_multi(vec![], None).await.unwrap().is_empty());
+
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -4,7 +4,7 @@ use std::time::Duration;
 use rcgen::generate_simple_self_signed;
 use tempfile::tempdir;
 use tokio::time::sleep;
-use umadb_client::{AsyncUmaDBClient, ClientTlsOptions};
+use umadb_client::{AsyncUmaDBClient, ClientTlsOptions, UmaDBClient};
 use umadb_dcb::{DCBEvent, DCBEventStoreAsync};
 use umadb_server::start_server_secure;
 
@@ -55,6 +55,7 @@ async fn secure_grpc_end_to_end_append_and_read() {
     let tls = ClientTlsOptions {
         domain: Some("localhost".to_string()),
         ca_pem: Some(cert_pem.clone()),
+        identity_pem: None,
     };
 
     // Retry connect loop to avoid race with server startup
@@ -91,6 +92,7 @@ async fn secure_grpc_end_to_end_append_and_read() {
             data: format!("tls-data-{}", i).into_bytes(),
             tags: vec!["secure".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let last_pos = client.append(events, None).await.expect("append");
@@ -122,3 +124,53 @@ async fn secure_grpc_end_to_end_append_and_read() {
     let _ = shutdown_tx.send(());
     let _ = server_task.await;
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn secure_grpc_builder_tls_client_connects_and_plaintext_is_rejected() {
+    // Arrange: secure server with a self-signed cert
+    let temp_dir = tempdir().unwrap();
+    let db_path = temp_dir.path().to_path_buf();
+    let port = get_free_port();
+    let addr = format!("127.0.0.1:{}", port);
+
+    let (cert_pem, key_pem) = generate_self_signed_cert();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_cert_pem = cert_pem.clone();
+    let server_task = tokio::spawn(async move {
+        let _ = start_server_secure(db_path, &addr, shutdown_rx, server_cert_pem, key_pem).await;
+    });
+
+    // A client trusting the cert connects, retrying to avoid a race with server startup
+    let (client_cert_pem, client_key_pem) = generate_self_signed_cert();
+    let tls_client = UmaDBClient::new(format!("https://localhost:{}", port))
+        .ca_pem(cert_pem)
+        .identity(client_cert_pem, client_key_pem)
+        .without_sigint_handler();
+    let mut client = None;
+    for _ in 0..40 {
+        if let Ok(c) = tls_client.connect_async().await {
+            client = Some(c);
+            break;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    let client = client.expect("TLS client connects");
+    let event = DCBEvent {
+        event_type: "SecureTest".to_string(),
+        data: vec![],
+        tags: vec![],
+        uuid: None,
+        client_timestamp: None,
+    };
+    assert_eq!(1, client.append(vec![event], None).await.expect("append"));
+
+    // A plaintext client can't talk to the server
+    let plaintext = UmaDBClient::new(format!("http://localhost:{}", port)).without_sigint_handler();
+    if let Ok(client) = plaintext.connect_async().await {
+        assert!(client.head().await.is_err());
+    }
+
+    // Cleanup
+    let _ = shutdown_tx.send(());
+    let _ = server_task.await;
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use umadb_core::db::UmaDB;
+use umadb_dcb::{DCBEvent, DCBEventStoreSync};
+
+type SpanFields = HashMap<String, String>;
+
+// Records the name and fields of every span
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<HashMap<u64, (String, SpanFields)>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+struct FieldVisitor<'a>(&'a mut SpanFields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl SpanRecorder {
+    fn spans_named(&self, name: &str) -> Vec<SpanFields> {
+        self.spans
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(span_name, _)| span_name == name)
+            .map(|(_, fields)| fields.clone())
+            .collect()
+    }
+}
+
+impl Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut fields = HashMap::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id, (span.metadata().name().to_string(), fields));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some((_, fields)) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn commit_span_records_dirty_page_count() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let store = UmaDB::new(temp_dir.path()).unwrap();
+    let recorder = SpanRecorder::default();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let events = (0..3)
+            .map(|i| DCBEvent {
+                event_type: "TracedEvent".to_string(),
+                data: format!("data-{i}").into_bytes(),
+                tags: vec!["traced".to_string()],
+                uuid: None,
+                client_timestamp: None,
+            })
+            .collect();
+        store.append(events, None).unwrap();
+    });
+
+    let commits = recorder.spans_named("commit");
+    assert_eq!(1, commits.len());
+    let dirty_pages: usize = commits[0]["dirty_pages"].parse().unwrap();
+    assert!(dirty_pages > 0);
+    let bytes: usize = commits[0]["bytes"].parse().unwrap();
+    assert_eq!(dirty_pages * 4096, bytes);
+    assert!(commits[0].contains_key("tsn"));
+    assert_eq!(3, recorder.spans_named("events_tree_descent").len());
+}
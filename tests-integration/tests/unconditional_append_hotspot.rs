@@ -77,6 +77,7 @@ fn profile_event_store_append() {
                 data: payload.clone(),
                 tags,
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(event);
         }
@@ -86,6 +87,7 @@ fn profile_event_store_append() {
                     types: vec![],
                     tags: vec!["foo".to_string()],
                 }],
+                case_insensitive: false,
             },
             after: Some(0),
         };
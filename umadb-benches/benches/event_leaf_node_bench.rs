@@ -9,7 +9,8 @@ pub fn event_leaf_node_benchmark(c: &mut Criterion) {
 
     // for &keys in &[1usize, 8, 64, 256, 1024, 4096] {
     // for &keys in &[1usize, 8, 64, 256] {
-    for &keys in &[64] {
+    {
+        let keys = 64;
         // 64 bytes payload, 2 tags per value
         let mut bench = BenchEventLeafInline::new(keys, 64, 2);
         // Ensure buffer populated once for the deserialize-only bench
@@ -30,6 +31,13 @@ pub fn event_leaf_node_benchmark(c: &mut Criterion) {
                 let _ = black_box(bench.deserialize_check()).expect("deserialize ok");
             })
         });
+
+        inline.bench_function(BenchmarkId::new("view", keys), |b| {
+            b.iter(|| {
+                // walk a borrowed view of the existing buffer without allocating
+                let _ = black_box(bench.view_check()).expect("view ok");
+            })
+        });
     }
     inline.finish();
 
@@ -39,7 +47,8 @@ pub fn event_leaf_node_benchmark(c: &mut Criterion) {
 
     // for &keys in &[1usize, 8, 64, 256, 1024, 4096] {
     // for &keys in &[1usize, 8, 64, 256] {
-    for &keys in &[64] {
+    {
+        let keys = 64;
         // 128KiB logical data_len, 3 tags per value
         let mut bench = BenchEventLeafOverflow::new(keys, 128 * 1024, 3);
         // serialize once to have a buffer for deserialize bench
@@ -45,6 +45,7 @@ fn init_db_with_events(num_events: usize) -> (tempfile::TempDir, String) {
                 data: format!("init-{}", i).into_bytes(),
                 tags: vec!["init".to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -68,7 +69,7 @@ pub fn grpc_append_benchmark(c: &mut Criterion) {
     let thread_counts: Vec<usize> = all_threads
         .iter()
         .copied()
-        .filter(|&t| max_threads.map_or(true, |max| t <= max))
+        .filter(|&t| max_threads.is_none_or(|max| t <= max))
         .collect();
 
     for &threads in &thread_counts {
@@ -153,6 +154,7 @@ pub fn grpc_append_benchmark(c: &mut Criterion) {
                         tags: vec!["append".to_string()],
                         // tags: vec![format!("append-{i}").to_string()],
                         uuid: None,
+                        client_timestamp: None,
                     })
                     .collect();
 
@@ -48,6 +48,7 @@ fn init_db_with_events(num_events: usize) -> (tempfile::TempDir, String, u64) {
                 data: format!("init-{}", i).into_bytes(),
                 tags: vec!["init".to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -71,7 +72,7 @@ pub fn grpc_append_cond_benchmark(c: &mut Criterion) {
     let thread_counts: Vec<usize> = all_threads
         .iter()
         .copied()
-        .filter(|&t| max_threads.map_or(true, |max| t <= max))
+        .filter(|&t| max_threads.is_none_or(|max| t <= max))
         .collect();
 
     for &threads in &thread_counts {
@@ -156,6 +157,7 @@ pub fn grpc_append_cond_benchmark(c: &mut Criterion) {
                         tags: vec!["append".to_string()],
                         // tags: vec![format!("append-{i}").to_string()],
                         uuid: None,
+                        client_timestamp: None,
                     })
                     .collect();
 
@@ -172,6 +174,7 @@ pub fn grpc_append_cond_benchmark(c: &mut Criterion) {
                                         types: vec![],
                                         tags: vec!["init".to_string()],
                                     }],
+                                    case_insensitive: false,
                                 },
                                 after: Some(last_init_pos),
                             };
@@ -35,6 +35,7 @@ fn init_db_with_events(num_events: usize) -> (tempfile::TempDir, String) {
                 data: format!("init-{}", i).into_bytes(),
                 tags: vec!["init".to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -122,8 +123,8 @@ pub fn grpc_append_with_readers_benchmark(c: &mut Criterion) {
 
         // Spawn continuous reader tasks
         let mut reader_handles = Vec::with_capacity(READER_COUNT);
-        for i in 0..READER_COUNT {
-            let client = reader_clients[i].clone();
+        for reader_client in reader_clients.iter() {
+            let client = reader_client.clone();
             let running = readers_running.clone();
             let handle = readers_rt.spawn(async move {
                 while running.load(Ordering::Relaxed) {
@@ -186,6 +187,7 @@ pub fn grpc_append_with_readers_benchmark(c: &mut Criterion) {
                         data: format!("data-{}", i).into_bytes(),
                         tags: vec!["append".to_string()],
                         uuid: None,
+                        client_timestamp: None,
                     })
                     .collect();
 
@@ -45,6 +45,7 @@ fn init_db_with_events(num_events: usize) -> (tempfile::TempDir, String) {
                 data: format!("event-{}", i).into_bytes(),
                 tags: vec!["tag1".to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -121,7 +122,7 @@ pub fn grpc_read_benchmark(c: &mut Criterion) {
     let thread_counts: Vec<usize> = all_threads
         .iter()
         .copied()
-        .filter(|&t| max_threads.map_or(true, |max| t <= max))
+        .filter(|&t| max_threads.is_none_or(|max| t <= max))
         .collect();
 
     for &threads in &thread_counts {
@@ -173,7 +174,7 @@ pub fn grpc_read_benchmark(c: &mut Criterion) {
                                     let _evt = black_box(item);
                                     count += 1;
                                 }
-                                if throttled && count % 1000 == 0 {
+                                if throttled && count.is_multiple_of(1000) {
                                     tokio::time::sleep(Duration::from_millis(90)).await;
                                 }
                             }
@@ -38,6 +38,7 @@ fn init_db_with_events() -> (tempfile::TempDir, String) {
                 data: format!("event-{}", j).into_bytes(),
                 tags: vec![format!("tag-{}", j).to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -106,7 +107,7 @@ pub fn grpc_read_cond_benchmark(c: &mut Criterion) {
     let thread_counts: Vec<usize> = all_threads
         .iter()
         .copied()
-        .filter(|&t| max_threads.map_or(true, |max| t <= max))
+        .filter(|&t| max_threads.is_none_or(|max| t <= max))
         .collect();
 
     for &threads in &thread_counts {
@@ -36,6 +36,7 @@ fn main() -> std::io::Result<()> {
                     data: format!("event-{}", i).into_bytes(),
                     tags: vec!["tag1".to_string()],
                     uuid: None,
+                    client_timestamp: None,
                 };
                 events.push(ev);
             }
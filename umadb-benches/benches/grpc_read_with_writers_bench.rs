@@ -41,6 +41,7 @@ fn init_db_with_events(num_events: usize) -> (tempfile::TempDir, String) {
                 data: format!("event-{}", i).into_bytes(),
                 tags: vec!["tag1".to_string()],
                 uuid: None,
+                client_timestamp: None,
             };
             events.push(ev);
         }
@@ -127,14 +128,15 @@ pub fn grpc_read_with_writers_benchmark(c: &mut Criterion) {
             data: format!("w-{}", i).into_bytes(),
             tags: vec!["w".to_string()],
             uuid: None,
+            client_timestamp: None,
         })
         .collect();
     let writer_batch = Arc::new(writer_batch);
 
     // Spawn continuous writer tasks
     let mut writer_handles = Vec::with_capacity(WRITER_COUNT);
-    for i in 0..WRITER_COUNT {
-        let client = writer_clients[i].clone();
+    for writer_client in writer_clients.iter() {
+        let client = writer_client.clone();
         let running = writers_running.clone();
         let batch = writer_batch.clone();
         let handle = writers_rt.spawn(async move {
@@ -157,7 +159,7 @@ pub fn grpc_read_with_writers_benchmark(c: &mut Criterion) {
     let thread_counts: Vec<usize> = all_threads
         .iter()
         .copied()
-        .filter(|&t| max_threads.map_or(true, |max| t <= max))
+        .filter(|&t| max_threads.is_none_or(|max| t <= max))
         .collect();
 
     for &threads in &thread_counts {
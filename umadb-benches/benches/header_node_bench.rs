@@ -7,26 +7,29 @@ use umadb_core::header_node::HeaderNode;
 static HEADER: HeaderNode = HeaderNode {
     tsn: Tsn(42),
     next_page_id: PageID(123),
-    free_lists_tree_root_id: PageID(456),
+    free_lists_tree_root_id: PageID(452),
     events_tree_root_id: PageID(789),
     tags_tree_root_id: PageID(321),
     next_position: Position(9876543210),
+    page_size: 4096,
+    overflow_chunk_size: 0,
+    encryption: None,
 };
 
 pub fn header_node_benchmarks(c: &mut Criterion) {
     let mut group = c.benchmark_group("header_node");
 
     // Known constant sizes for header serialization
-    let header_size_bytes: u64 = 48;
+    let header_size_bytes: u64 = 54;
     group.throughput(Throughput::Bytes(header_size_bytes));
 
     // Benchmark serialization (alloc + encode)
     group.bench_function(BenchmarkId::new("serialize", header_size_bytes), |b| {
         b.iter(|| {
-            let mut v = black_box(Vec::<u8>::with_capacity(48));
-            // ensure vec has length 48 so we can serialize into it
+            let mut v = black_box(Vec::<u8>::with_capacity(54));
+            // ensure vec has length 54 so we can serialize into it
             unsafe {
-                v.set_len(48);
+                v.set_len(54);
             }
             black_box(&HEADER).serialize_into(black_box(&mut v));
             black_box(v)
@@ -36,7 +39,7 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
     // Allocation-only: separate the Vec allocation cost
     group.bench_function(BenchmarkId::new("alloc_only", header_size_bytes), |b| {
         b.iter(|| {
-            let v = black_box(Vec::<u8>::with_capacity(48));
+            let v = black_box(Vec::<u8>::with_capacity(54));
             black_box(v)
         })
     });
@@ -45,7 +48,7 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
     group.bench_function(
         BenchmarkId::new("serialize_into_stack", header_size_bytes),
         |b| {
-            let mut buf = [0u8; 48];
+            let mut buf = [0u8; 54];
             b.iter(|| {
                 black_box(&HEADER).serialize_into(black_box(&mut buf));
                 black_box(&buf);
@@ -54,7 +57,7 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
     );
 
     // Prepare serialized bytes once for deserialization benchmark (outside iter)
-    let mut serialized = [0u8; 48];
+    let mut serialized = [0u8; 54];
     HEADER.serialize_into(&mut serialized);
 
     // Benchmark deserialization reusing the same bytes each iteration (pure from_slice; no cloning/allocation)
@@ -75,11 +78,11 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
         |b| {
             b.iter_batched(
                 || {
-                    let mut buf = [0u8; 48];
+                    let mut buf = [0u8; 54];
                     HEADER.serialize_into(&mut buf);
                     buf
                 },
-                |bytes: [u8; 48]| {
+                |bytes: [u8; 54]| {
                     let node =
                         HeaderNode::from_slice(black_box(&bytes)).expect("valid header bytes");
                     black_box(node)
@@ -93,7 +96,7 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
     group.bench_function(
         BenchmarkId::new("round_trip_no_alloc", header_size_bytes),
         |b| {
-            let mut buf = [0u8; 48];
+            let mut buf = [0u8; 54];
             b.iter(|| {
                 let header = black_box(&HEADER);
                 header.serialize_into(black_box(&mut buf));
@@ -106,7 +109,7 @@ pub fn header_node_benchmarks(c: &mut Criterion) {
     // Benchmark serialize + deserialize round trip (alloc + encode + decode)
     group.bench_function(BenchmarkId::new("round_trip", header_size_bytes), |b| {
         b.iter(|| {
-            let mut bytes = [0u8; 48];
+            let mut bytes = [0u8; 54];
             black_box(&HEADER).serialize_into(black_box(&mut bytes));
             // Black-box the bytes to prevent the compiler from fusing serialize+deserialize
             let node = HeaderNode::from_slice(black_box(&bytes)).unwrap();
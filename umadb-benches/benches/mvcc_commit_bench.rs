@@ -2,6 +2,7 @@ use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
 use std::cell::RefCell;
 use tempfile::tempdir;
 use umadb_benches::bench_api::BenchDb;
+use umadb_core::mvcc::DurabilityMode;
 
 pub fn mvcc_commit_benchmarks(c: &mut Criterion) {
     let page_size = 4096usize;
@@ -16,6 +17,20 @@ pub fn mvcc_commit_benchmarks(c: &mut Criterion) {
         })
     });
 
+    // Benchmark: commit_empty under each durability mode
+    for (name, mode) in [
+        ("no_sync", DurabilityMode::NoSync),
+        ("sync_data", DurabilityMode::SyncData),
+        ("sync", DurabilityMode::Sync),
+    ] {
+        let db = fresh_db_with_durability(page_size, mode);
+        group.bench_function(BenchmarkId::new("commit_empty_durability", name), |b| {
+            b.iter(|| {
+                db.commit_empty().unwrap();
+            })
+        });
+    }
+
     // Setup once: persistent DB and writer reused across iterations
     let dir = tempdir().expect("tempdir");
     let db_path = dir.path().join("umadb.bench");
@@ -57,6 +72,13 @@ fn fresh_db(page_size: usize) -> BenchDb {
     BenchDb::new(&db_path, page_size).expect("BenchDb::new")
 }
 
+/// Helper: create a fresh temporary BenchDb instance with the given durability mode.
+fn fresh_db_with_durability(page_size: usize, durability: DurabilityMode) -> BenchDb {
+    let dir = tempdir().expect("tempdir");
+    let db_path = dir.path().join("umadb.commit.bench");
+    BenchDb::new_with_durability(&db_path, page_size, durability).expect("BenchDb::new")
+}
+
 // =============================================
 // ================ CRITERION CONFIG ===========
 // =============================================
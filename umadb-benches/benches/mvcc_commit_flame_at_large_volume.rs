@@ -65,6 +65,7 @@ fn main() -> std::io::Result<()> {
                     data: "batch-data".to_string().into_bytes(),
                     tags: vec![format!("tag-{}", Uuid::new_v4())],
                     uuid: None,
+                    client_timestamp: None,
                 };
 
                 unconditional_append(&db.mvcc, &mut w, vec![event]).expect("Failed to append");
@@ -80,7 +81,7 @@ fn main() -> std::io::Result<()> {
             }
         }
 
-        profile_to_svg(&format!("append_at_large_volume"), || {
+        profile_to_svg("append_at_large_volume", || {
             let start = Instant::now();
 
             for _ in 0..1000 {
@@ -90,6 +91,7 @@ fn main() -> std::io::Result<()> {
                     data: "batch-data".to_string().into_bytes(),
                     tags: vec![format!("tag-{}", Uuid::new_v4())],
                     uuid: None,
+                    client_timestamp: None,
                 };
 
                 unconditional_append(&db.mvcc, &mut w, vec![event]).expect("Failed to append");
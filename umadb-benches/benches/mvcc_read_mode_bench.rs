@@ -0,0 +1,51 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::sync::Arc;
+use tempfile::tempdir;
+use umadb_core::db::{DEFAULT_PAGE_SIZE, UmaDB};
+use umadb_core::mvcc::{DurabilityMode, Mvcc};
+use umadb_dcb::{DCBEvent, DCBEventStoreSync};
+
+const NUM_EVENTS: usize = 10_000;
+
+pub fn mvcc_read_mode_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mvcc_read_mode");
+    group.throughput(Throughput::Elements(NUM_EVENTS as u64));
+
+    // Setup once: a database with events to scan
+    let dir = tempdir().expect("tempdir");
+    let db_path = dir.path().join("umadb.read.bench");
+    {
+        let db = UmaDB::new_with_durability(&db_path, DurabilityMode::NoSync).unwrap();
+        for _ in 0..NUM_EVENTS / 100 {
+            let events = (0..100)
+                .map(|_| DCBEvent {
+                    event_type: "scan-type".to_string(),
+                    data: "scan-data".to_string().into_bytes(),
+                    tags: vec!["scan-tag".to_string()],
+                    uuid: None,
+                    client_timestamp: None,
+                })
+                .collect();
+            db.append(events, None).unwrap();
+        }
+    }
+
+    // Benchmark: sequential scan of all events, reading pages with mmap and with pread
+    for (name, use_mmap) in [("mmap", true), ("pread", false)] {
+        let mvcc = Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync)
+            .unwrap()
+            .with_mmap(use_mmap);
+        let db = UmaDB::from_arc(Arc::new(mvcc));
+        group.bench_function(BenchmarkId::new("sequential_scan", name), |b| {
+            b.iter(|| {
+                let (events, _) = db.read_with_head(None, None, false, None).unwrap();
+                assert_eq!(NUM_EVENTS, events.len());
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, mvcc_read_mode_benchmarks);
+criterion_main!(benches);
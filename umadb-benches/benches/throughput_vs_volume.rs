@@ -37,6 +37,7 @@ async fn main() -> std::io::Result<()> {
                     data: "batch-data".to_string().into_bytes(),
                     tags: vec![tag.clone()],
                     uuid: None,
+                    client_timestamp: None,
                 })
                 .collect();
 
@@ -57,6 +58,7 @@ async fn main() -> std::io::Result<()> {
                 data: "batch-data".to_string().into_bytes(),
                 tags: vec![tag.clone()],
                 uuid: None,
+                client_timestamp: None,
             };
 
             client
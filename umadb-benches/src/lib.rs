@@ -2,11 +2,10 @@
 pub mod bench_api {
     use std::path::Path;
     use umadb_core::common::{PageID, Position};
-    use umadb_core::db::DEFAULT_PAGE_SIZE;
-    use umadb_core::events_tree_nodes::{EventLeafNode, EventRecord, EventValue};
-    use umadb_core::mvcc::{Mvcc, Writer};
+    use umadb_core::events_tree_nodes::{EventLeafNode, EventRecord, EventValue, EventValueRef};
+    use umadb_core::mvcc::{DurabilityMode, Mvcc, Writer};
     use umadb_core::node::Node;
-    use umadb_core::page::{PAGE_HEADER_SIZE, Page};
+    use umadb_core::page::Page;
     use umadb_dcb::DCBResult;
 
     /// Minimal public wrapper to allow Criterion benches to measure commit paths
@@ -16,14 +15,23 @@ pub mod bench_api {
 
     impl BenchDb {
         pub fn new(path: &Path, page_size: usize) -> DCBResult<Self> {
-            let mvcc = Mvcc::new(path, page_size, false)?;
+            Self::new_with_durability(path, page_size, DurabilityMode::default())
+        }
+
+        pub fn new_with_durability(
+            path: &Path,
+            page_size: usize,
+            durability: DurabilityMode,
+        ) -> DCBResult<Self> {
+            let mvcc = Mvcc::new(path, page_size, durability)?;
             Ok(BenchDb { mvcc })
         }
 
         /// Commit with no dirty pages: exercises header write + flush.
         pub fn commit_empty(&self) -> DCBResult<()> {
             let mut w = self.mvcc.writer()?;
-            self.mvcc.commit(&mut w)
+            self.mvcc.commit(&mut w)?;
+            Ok(())
         }
 
         pub fn writer(&self) -> Writer {
@@ -37,7 +45,7 @@ pub mod bench_api {
             const DATA_LEN: u64 = 1024; // pretend payload size for overflow metadata
 
             w.dirty.clear();
-            for i in 0..n {
+            let pages = (0..n).map(|i| {
                 let id = PageID(i as u64);
 
                 // Build keys [0..KEYS_PER_LEAF)
@@ -55,23 +63,23 @@ pub mod bench_api {
                         tags: tags.clone(),
                         root_id,
                         uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
                     });
                 }
 
-                let node = Node::EventLeaf(EventLeafNode { keys, values });
-                let node_size = node.calc_serialized_size();
-                let max_node_size = DEFAULT_PAGE_SIZE - PAGE_HEADER_SIZE;
-                if node_size > max_node_size {
-                    panic!("The node {node_size} is too big (max: {max_node_size:?})");
-                }
-                let page = Page::new(id, node);
-                w.insert_dirty(page)?;
-            }
+                // insert_dirty_batch rejects a node that is too big for the page
+                let node = Node::EventLeaf(EventLeafNode::new(keys, values));
+                Page::new(id, node)
+            });
+            w.insert_dirty_batch(pages)?;
             Ok(())
         }
 
         pub fn commit_with_dirty(&self, w: &mut Writer) -> DCBResult<()> {
-            self.mvcc.commit(w)
+            self.mvcc.commit(w)?;
+            Ok(())
         }
     }
 
@@ -93,13 +101,13 @@ pub mod bench_api {
                     data: data.clone(),
                     tags: tags.clone(),
                     uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }));
             }
             let keys_vec: Vec<Position> = (0..keys).map(|i| Position(i as u64)).collect();
-            let node = EventLeafNode {
-                keys: keys_vec,
-                values,
-            };
+            let node = EventLeafNode::new(keys_vec, values);
             let cap = node.calc_serialized_size();
             BenchEventLeafInline {
                 node,
@@ -122,6 +130,18 @@ pub mod bench_api {
             let out = EventLeafNode::from_slice(&self.buf[..size])?;
             Ok(out)
         }
+
+        /// Walks a borrowed view of the buffer, returning the total inline data length.
+        pub fn view_check(&self) -> DCBResult<usize> {
+            let size = self.last_size.min(self.buf.len());
+            let mut total = 0;
+            for item in EventLeafNode::view(&self.buf[..size])?.iter() {
+                if let (_, EventValueRef::Inline { data, .. }) = item? {
+                    total += data.len();
+                }
+            }
+            Ok(total)
+        }
     }
 
     /// Helper for Criterion to benchmark EventLeafNode overflow (out-of-page) metadata serde.
@@ -142,13 +162,13 @@ pub mod bench_api {
                     tags: tags.clone(),
                     root_id: PageID(1 + i as u64),
                     uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 });
             }
             let keys_vec: Vec<Position> = (0..keys).map(|i| Position(i as u64)).collect();
-            let node = EventLeafNode {
-                keys: keys_vec,
-                values,
-            };
+            let node = EventLeafNode::new(keys_vec, values);
             let cap = node.calc_serialized_size();
             BenchEventLeafOverflow {
                 node,
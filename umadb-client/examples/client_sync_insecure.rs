@@ -20,7 +20,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut read_response = client.read(Some(boundary.clone()), None, false, None, false)?;
 
     // Build decision model
-    while let Some(result) = read_response.next() {
+    for result in read_response.by_ref() {
         match result {
             Ok(event) => {
                 println!(
@@ -80,10 +80,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Subscribe to all events for a projection
-    let mut subscription = client.read(None, None, false, None, true)?;
+    let subscription = client.read(None, None, false, None, true)?;
 
     // Build an up-to-date view
-    while let Some(result) = subscription.next() {
+    for result in subscription {
         match result {
             Ok(ev) => {
                 println!("Processing event at {}: {:?}", ev.position, ev.event);
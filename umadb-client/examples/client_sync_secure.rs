@@ -17,13 +17,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             types: vec!["example".to_string()],
             tags: vec!["tag1".to_string(), "tag2".to_string()],
         }],
+        case_insensitive: false,
     };
 
     // Read events for a decision model
     let mut read_response = client.read(Some(cb.clone()), None, false, None, false)?;
 
     // Build decision model
-    while let Some(result) = read_response.next() {
+    for result in read_response.by_ref() {
         match result {
             Ok(event) => {
                 println!(
@@ -45,6 +46,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         tags: vec!["tag1".to_string(), "tag2".to_string()],
         data: b"Hello, world!".to_vec(),
         uuid: Some(Uuid::new_v4()),
+        client_timestamp: None,
     };
 
     // Append event in consistency boundary
@@ -64,6 +66,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         tags: vec!["tag1".to_string(), "tag2".to_string()],
         data: b"Hello, world!".to_vec(),
         uuid: Some(Uuid::new_v4()), // different UUID
+        client_timestamp: None,
     };
 
     let conflicting_result = client.append(
@@ -105,10 +108,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Subscribe to all events for a projection
-    let mut subscription = client.read(None, None, false, None, true)?;
+    let subscription = client.read(None, None, false, None, true)?;
 
     // Build an up-to-date view
-    while let Some(result) = subscription.next() {
+    for result in subscription {
         match result {
             Ok(ev) => {
                 println!("Processing event at {}: {:?}", ev.position, ev.event);
@@ -1,25 +1,37 @@
 use async_trait::async_trait;
-use futures::Stream;
+use bytes::Bytes;
 use futures::ready;
+use futures::{Stream, StreamExt};
 use std::collections::VecDeque;
 use std::fs;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use std::time::Duration;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
 
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tokio::runtime::{Handle, Runtime};
 use umadb_dcb::{
-    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBEventStoreSync, DCBQuery,
-    DCBReadResponseAsync, DCBReadResponseSync, DCBResult, DCBSequencedEvent,
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreAsync, DCBEventStoreSync,
+    DCBMultiQueryEvent, DCBQuery, DCBReadResponseAsync, DCBReadResponseSync, DCBResult,
+    DCBSequencedEvent, DbStats, ndjson::write_ndjson_event,
 };
 use umadb_proto::{
-    AppendConditionProto, AppendRequestProto, EventProto, HeadRequestProto, ReadRequestProto,
-    ReadResponseProto, UmaDbServiceClient, dcb_error_from_status,
+    AppendConditionProto, AppendRequestProto, AppendStreamHeaderProto, AppendStreamPartProto,
+    AppendStreamRequestProto, CountRequestProto, EventProto, HeadRequestProto,
+    OldestTsnRequestProto, QueryProto, ReadAsOfRequestProto, ReadByUuidRequestProto,
+    ReadEventDataRequestProto, ReadMultiRequestProto, ReadRequestProto, ReadResponseProto,
+    StatsRequestProto, UmaDbServiceClient, dcb_error_from_status,
 };
 
 use std::sync::{Once, OnceLock};
 use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Bytes read from the reader for each chunk of a streamed append.
+const APPEND_STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// A global watch channel for shutdown/cancel signals.
 static CANCEL_SENDER: OnceLock<watch::Sender<()>> = OnceLock::new();
@@ -59,7 +71,11 @@ pub fn register_cancel_sigint_handler() {
 pub struct UmaDBClient {
     url: String,
     ca_path: Option<String>,
+    ca_pem: Option<Vec<u8>>,
+    identity_pem: Option<(Vec<u8>, Vec<u8>)>,
     batch_size: Option<u32>,
+    timeouts: ClientTimeouts,
+    retry_policy: ClientRetryPolicy,
     without_sigint_handler: bool,
 }
 
@@ -68,7 +84,11 @@ impl UmaDBClient {
         Self {
             url,
             ca_path: None,
+            ca_pem: None,
+            identity_pem: None,
             batch_size: None,
+            timeouts: ClientTimeouts::default(),
+            retry_policy: ClientRetryPolicy::default(),
             without_sigint_handler: false,
         }
     }
@@ -80,6 +100,22 @@ impl UmaDBClient {
         }
     }
 
+    /// Trust the given PEM-encoded CA certificate, instead of reading one from ca_path.
+    pub fn ca_pem(self, ca_pem: Vec<u8>) -> Self {
+        Self {
+            ca_pem: Some(ca_pem),
+            ..self
+        }
+    }
+
+    /// Present the given PEM-encoded certificate and key, for servers that authenticate clients.
+    pub fn identity(self, cert_pem: Vec<u8>, key_pem: Vec<u8>) -> Self {
+        Self {
+            identity_pem: Some((cert_pem, key_pem)),
+            ..self
+        }
+    }
+
     pub fn batch_size(self, batch_size: u32) -> Self {
         Self {
             batch_size: Some(batch_size),
@@ -87,6 +123,52 @@ impl UmaDBClient {
         }
     }
 
+    /// Fail to connect with DCBError::Timeout if the server isn't reached in time.
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeouts: ClientTimeouts {
+                connect: Some(timeout),
+                ..self.timeouts
+            },
+            ..self
+        }
+    }
+
+    /// Fail requests with DCBError::Timeout if the server doesn't respond in time.
+    /// For reads this applies to the start of the response, not the whole stream.
+    pub fn request_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeouts: ClientTimeouts {
+                request: Some(timeout),
+                ..self.timeouts
+            },
+            ..self
+        }
+    }
+
+    /// Retry requests that fail with a transport error up to this many times,
+    /// while the channel reconnects. Zero disables retries.
+    pub fn max_retries(self, max_retries: u32) -> Self {
+        Self {
+            retry_policy: ClientRetryPolicy {
+                max_retries,
+                ..self.retry_policy
+            },
+            ..self
+        }
+    }
+
+    /// The delay before the first retry, which doubles for each further retry.
+    pub fn retry_backoff(self, initial_backoff: Duration) -> Self {
+        Self {
+            retry_policy: ClientRetryPolicy {
+                initial_backoff,
+                ..self.retry_policy
+            },
+            ..self
+        }
+    }
+
     pub fn without_sigint_handler(self) -> Self {
         Self {
             without_sigint_handler: true,
@@ -94,9 +176,27 @@ impl UmaDBClient {
         }
     }
 
+    fn tls_options(&self) -> DCBResult<ClientTlsOptions> {
+        let ca_pem = match (&self.ca_pem, &self.ca_path) {
+            (Some(ca_pem), _) => Some(ca_pem.clone()),
+            (None, Some(ca_path)) => Some(fs::read(ca_path)?),
+            (None, None) => None,
+        };
+        Ok(ClientTlsOptions {
+            domain: None,
+            ca_pem,
+            identity_pem: self.identity_pem.clone(),
+        })
+    }
+
     pub fn connect(&self) -> DCBResult<SyncUmaDBClient> {
-        let client =
-            SyncUmaDBClient::connect(self.url.clone(), self.ca_path.clone(), self.batch_size);
+        let client = SyncUmaDBClient::connect_with_timeouts(
+            self.url.clone(),
+            Some(self.tls_options()?),
+            self.batch_size,
+            self.timeouts,
+        )
+        .map(|client| client.with_retry_policy(self.retry_policy));
         if !self.without_sigint_handler
             && let Ok(client) = &client
         {
@@ -105,9 +205,14 @@ impl UmaDBClient {
         client
     }
     pub async fn connect_async(&self) -> DCBResult<AsyncUmaDBClient> {
-        let client =
-            AsyncUmaDBClient::connect(self.url.clone(), self.ca_path.clone(), self.batch_size)
-                .await;
+        let client = AsyncUmaDBClient::connect_with_timeouts(
+            self.url.clone(),
+            Some(self.tls_options()?),
+            self.batch_size,
+            self.timeouts,
+        )
+        .await
+        .map(|client| client.with_retry_policy(self.retry_policy));
         if !self.without_sigint_handler
             && let Ok(client) = &client
         {
@@ -143,12 +248,22 @@ impl SyncUmaDBClient {
         url: String,
         tls_options: Option<ClientTlsOptions>,
         batch_size: Option<u32>,
+    ) -> DCBResult<Self> {
+        Self::connect_with_timeouts(url, tls_options, batch_size, ClientTimeouts::default())
+    }
+
+    pub fn connect_with_timeouts(
+        url: String,
+        tls_options: Option<ClientTlsOptions>,
+        batch_size: Option<u32>,
+        timeouts: ClientTimeouts,
     ) -> DCBResult<Self> {
         let (rt, handle) = Self::get_rt_handle();
-        let async_client = handle.block_on(AsyncUmaDBClient::connect_with_tls_options(
+        let async_client = handle.block_on(AsyncUmaDBClient::connect_with_timeouts(
             url,
             tls_options,
             batch_size,
+            timeouts,
         ))?;
         Ok(Self {
             async_client,
@@ -157,6 +272,13 @@ impl SyncUmaDBClient {
         })
     }
 
+    pub fn with_retry_policy(self, retry_policy: ClientRetryPolicy) -> Self {
+        Self {
+            async_client: self.async_client.with_retry_policy(retry_policy),
+            ..self
+        }
+    }
+
     fn get_rt_handle() -> (Option<Runtime>, Handle) {
         let (rt, handle) = {
             // Try to use an existing runtime first
@@ -176,6 +298,140 @@ impl SyncUmaDBClient {
         self.handle
             .block_on(self.async_client.register_cancel_sigint_handler());
     }
+
+    /// Reads events matching the query from the start position, then stays open
+    /// and yields matching events as they are appended.
+    pub fn subscribe(
+        &self,
+        query: Option<DCBQuery>,
+        start: Option<u64>,
+    ) -> DCBResult<Box<dyn DCBReadResponseSync + 'static>> {
+        self.read(query, start, false, None, true)
+    }
+
+    /// Returns the number of events matching the query, without reading them.
+    pub fn count(&self, query: Option<DCBQuery>) -> DCBResult<u64> {
+        self.handle.block_on(self.async_client.count(query))
+    }
+
+    /// Returns the event with the given UUID, if there is one.
+    pub fn read_by_uuid(&self, uuid: Uuid) -> DCBResult<Option<DCBSequencedEvent>> {
+        self.handle.block_on(self.async_client.read_by_uuid(uuid))
+    }
+
+    /// Returns the events that match the query as they were at the commit with the
+    /// TSN, as returned by head_with_tsn(). The server keeps a bounded history of
+    /// recent commits, and older ones fail with an out of retention error.
+    pub fn read_as_of(
+        &self,
+        tsn: u64,
+        query: Option<DCBQuery>,
+    ) -> DCBResult<Vec<DCBSequencedEvent>> {
+        self.handle
+            .block_on(self.async_client.read_as_of(tsn, query))
+    }
+
+    /// Returns the events after `after` that match any of the queries, in position
+    /// order, each with the indices of the queries it matched.
+    pub fn read_multi(
+        &self,
+        queries: Vec<DCBQuery>,
+        after: Option<u64>,
+    ) -> DCBResult<Vec<DCBMultiQueryEvent>> {
+        self.handle
+            .block_on(self.async_client.read_multi(queries, after))
+    }
+
+    /// Appends an event whose data is read from `reader` and streamed to the server
+    /// in chunks, so that it is never held in memory as a whole. The event's own data
+    /// must be empty, and the reader must yield exactly `data_len` bytes.
+    pub fn append_stream<R: Read + Send + 'static>(
+        &self,
+        event: DCBEvent,
+        data_len: u64,
+        mut reader: R,
+    ) -> DCBResult<u64> {
+        let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+        let read_thread = std::thread::spawn(move || -> std::io::Result<()> {
+            loop {
+                let mut chunk = vec![0u8; APPEND_STREAM_CHUNK_SIZE];
+                let len = reader.read(&mut chunk)?;
+                if len == 0 {
+                    return Ok(());
+                }
+                chunk.truncate(len);
+                if chunk_tx.blocking_send(chunk).is_err() {
+                    return Ok(());
+                }
+            }
+        });
+        let chunks = futures::stream::poll_fn(move |cx| chunk_rx.poll_recv(cx));
+        let result = self
+            .handle
+            .block_on(self.async_client.append_stream(event, data_len, chunks));
+        match read_thread.join() {
+            Ok(Ok(())) => result,
+            Ok(Err(err)) => Err(DCBError::Io(err)),
+            Err(_) => Err(DCBError::InternalError(
+                "thread reading streamed event data panicked".to_string(),
+            )),
+        }
+    }
+
+    /// Writes the data of the event at the position to `writer` as it is streamed
+    /// from the server, a chunk at a time, and returns the number of bytes written.
+    /// Fails with DCBError::EventNotFound if there is no event at the position.
+    pub fn read_event_data_to<W: Write>(&self, position: u64, mut writer: W) -> DCBResult<u64> {
+        self.handle.block_on(async {
+            let mut chunks = std::pin::pin!(self.async_client.read_event_data_stream(position));
+            let mut written = 0;
+            while let Some(chunk) = chunks.next().await {
+                let chunk = chunk?;
+                writer.write_all(&chunk)?;
+                written += chunk.len() as u64;
+            }
+            Ok(written)
+        })
+    }
+
+    /// Returns the head position together with the TSN of the commit it was read
+    /// from, or None if there are no events.
+    pub fn head_with_tsn(&self) -> DCBResult<Option<(u64, u64)>> {
+        self.handle.block_on(self.async_client.head_with_tsn())
+    }
+
+    /// Returns the TSN of the oldest commit that read_as_of() can read. Commits from
+    /// it up to the latest one are retained, though older ones may be forgotten at
+    /// any time.
+    pub fn oldest_tsn(&self) -> DCBResult<u64> {
+        self.handle.block_on(self.async_client.oldest_tsn())
+    }
+
+    /// Returns storage statistics for the server's database.
+    pub fn stats(&self) -> DCBResult<DbStats> {
+        self.handle.block_on(self.async_client.stats())
+    }
+
+    /// Writes every event to `writer` as newline-delimited JSON, in position order,
+    /// reading a batch at a time. Returns the number of events written.
+    pub fn export_ndjson<W: Write>(&self, mut writer: W) -> DCBResult<u64> {
+        let mut response = DCBEventStoreSync::read(self, None, None, false, None, false)?;
+        let mut count = 0;
+        loop {
+            let batch = response.next_batch()?;
+            if batch.is_empty() {
+                break;
+            }
+            let mut lines = Vec::new();
+            for event in &batch {
+                write_ndjson_event(&mut lines, event)?;
+            }
+            writer.write_all(&lines)?;
+            count += batch.len() as u64;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
 }
 
 impl DCBEventStoreSync for SyncUmaDBClient {
@@ -281,6 +537,8 @@ impl DCBReadResponseSync for SyncClientReadResponse {
 pub struct AsyncUmaDBClient {
     client: UmaDbServiceClient<Channel>,
     batch_size: Option<u32>,
+    request_timeout: Option<Duration>,
+    retry_policy: ClientRetryPolicy,
 }
 
 impl AsyncUmaDBClient {
@@ -305,6 +563,7 @@ impl AsyncUmaDBClient {
         let client_tls_options = Some(ClientTlsOptions {
             domain: None,
             ca_pem,
+            identity_pem: None,
         });
 
         Self::connect_with_tls_options(url, client_tls_options, batch_size).await
@@ -315,10 +574,29 @@ impl AsyncUmaDBClient {
         tls_options: Option<ClientTlsOptions>,
         batch_size: Option<u32>,
     ) -> DCBResult<Self> {
-        match new_channel(url, tls_options).await {
+        Self::connect_with_timeouts(url, tls_options, batch_size, ClientTimeouts::default()).await
+    }
+
+    pub async fn connect_with_timeouts(
+        url: String,
+        tls_options: Option<ClientTlsOptions>,
+        batch_size: Option<u32>,
+        timeouts: ClientTimeouts,
+    ) -> DCBResult<Self> {
+        let connecting = new_channel(url, tls_options, timeouts.connect);
+        let connected = match timeouts.connect {
+            Some(timeout) => tokio::time::timeout(timeout, connecting)
+                .await
+                .map_err(|_| DCBError::Timeout(format!("failed to connect within {timeout:?}")))?,
+            None => connecting.await,
+        };
+        match connected {
             Ok(channel) => Ok(Self {
-                client: UmaDbServiceClient::new(channel),
+                // Events of any size can be stored, so a read response may hold a large one
+                client: UmaDbServiceClient::new(channel).max_decoding_message_size(usize::MAX),
                 batch_size,
+                request_timeout: timeouts.request,
+                retry_policy: ClientRetryPolicy::default(),
             }),
             Err(err) => Err(DCBError::TransportError(format!(
                 "failed to connect: {:?}",
@@ -327,9 +605,300 @@ impl AsyncUmaDBClient {
         }
     }
 
+    pub fn with_retry_policy(self, retry_policy: ClientRetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    // Makes the request, and if it's idempotent, makes it again with backoff while
+    // it fails with a transport error, giving the channel time to reconnect
+    async fn with_retries<T, F, Fut>(&self, idempotent: bool, mut request: F) -> DCBResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = DCBResult<T>>,
+    {
+        let mut retries = 0;
+        let mut backoff = self.retry_policy.initial_backoff;
+        loop {
+            match self.with_request_timeout(request()).await {
+                Err(DCBError::TransportError(_))
+                    if idempotent && retries < self.retry_policy.max_retries =>
+                {
+                    tokio::time::sleep(backoff).await;
+                    retries += 1;
+                    backoff = (backoff * 2).min(self.retry_policy.max_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Fails with DCBError::Timeout if the request timeout elapses first
+    async fn with_request_timeout<T>(
+        &self,
+        request: impl Future<Output = DCBResult<T>>,
+    ) -> DCBResult<T> {
+        match self.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(DCBError::Timeout(format!(
+                        "no response from server within {timeout:?}"
+                    )))
+                }),
+            None => request.await,
+        }
+    }
+
     pub async fn register_cancel_sigint_handler(&self) {
         register_cancel_sigint_handler();
     }
+
+    /// Reads events matching the query from the start position, then stays open
+    /// and yields matching events as they are appended.
+    pub async fn subscribe(
+        &self,
+        query: Option<DCBQuery>,
+        start: Option<u64>,
+    ) -> DCBResult<Box<dyn DCBReadResponseAsync + Send + 'static>> {
+        self.read(query, start, false, None, true).await
+    }
+
+    /// Returns the number of events matching the query, without reading them.
+    pub async fn count(&self, query: Option<DCBQuery>) -> DCBResult<u64> {
+        let request = CountRequestProto {
+            query: query.map(|q| q.into()),
+        };
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            let request = request.clone();
+            async move {
+                match client.count(request).await {
+                    Ok(response) => Ok(response.into_inner().count),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns the event with the given UUID, if there is one.
+    pub async fn read_by_uuid(&self, uuid: Uuid) -> DCBResult<Option<DCBSequencedEvent>> {
+        let request = ReadByUuidRequestProto {
+            uuid: uuid.to_string(),
+        };
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            let request = request.clone();
+            async move {
+                match client.read_by_uuid(request).await {
+                    Ok(response) => response
+                        .into_inner()
+                        .event
+                        .map(DCBSequencedEvent::try_from)
+                        .transpose(),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns the events that match the query as they were at the commit with the
+    /// TSN, as returned by head_with_tsn(). The server keeps a bounded history of
+    /// recent commits, and older ones fail with an out of retention error.
+    pub async fn read_as_of(
+        &self,
+        tsn: u64,
+        query: Option<DCBQuery>,
+    ) -> DCBResult<Vec<DCBSequencedEvent>> {
+        let request = ReadAsOfRequestProto {
+            tsn,
+            query: query.map(QueryProto::from),
+        };
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            let request = request.clone();
+            async move {
+                let mut stream = match client.read_as_of(request).await {
+                    Ok(response) => response.into_inner(),
+                    Err(status) => return Err(dcb_error_from_status(status)),
+                };
+                let mut events = Vec::new();
+                while let Some(message) = stream.next().await {
+                    let message = message.map_err(dcb_error_from_status)?;
+                    for event in message.events {
+                        events.push(DCBSequencedEvent::try_from(event)?);
+                    }
+                }
+                Ok(events)
+            }
+        })
+        .await
+    }
+
+    /// Returns the events after `after` that match any of the queries, in position
+    /// order, each with the indices of the queries it matched. The server scans the
+    /// events once for all the queries, rather than once for each.
+    pub async fn read_multi(
+        &self,
+        queries: Vec<DCBQuery>,
+        after: Option<u64>,
+    ) -> DCBResult<Vec<DCBMultiQueryEvent>> {
+        let request = ReadMultiRequestProto {
+            queries: queries.into_iter().map(QueryProto::from).collect(),
+            after,
+        };
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            let request = request.clone();
+            async move {
+                let mut stream = match client.read_multi(request).await {
+                    Ok(response) => response.into_inner(),
+                    Err(status) => return Err(dcb_error_from_status(status)),
+                };
+                let mut events = Vec::new();
+                while let Some(message) = stream.next().await {
+                    let message = message.map_err(dcb_error_from_status)?;
+                    for event in message.events {
+                        events.push(DCBMultiQueryEvent::try_from(event)?);
+                    }
+                }
+                Ok(events)
+            }
+        })
+        .await
+    }
+
+    /// Appends an event whose data is streamed to the server in chunks, so that it
+    /// is never held in memory as a whole. The event's own data must be empty, and
+    /// the chunks must add up to `data_len` bytes. A stream can't be replayed, so it
+    /// isn't retried, and since an upload may take any time the request timeout
+    /// doesn't apply.
+    pub async fn append_stream<S>(
+        &self,
+        event: DCBEvent,
+        data_len: u64,
+        chunks: S,
+    ) -> DCBResult<u64>
+    where
+        S: Stream<Item = Vec<u8>> + Send + 'static,
+    {
+        let header = AppendStreamRequestProto {
+            part: Some(AppendStreamPartProto::Header(AppendStreamHeaderProto {
+                event: Some(EventProto::from(event)),
+                data_len,
+            })),
+        };
+        let chunks = chunks.map(|chunk| AppendStreamRequestProto {
+            part: Some(AppendStreamPartProto::Chunk(chunk)),
+        });
+        let request = futures::stream::once(async { header }).chain(chunks);
+        match self.client.clone().append_stream(request).await {
+            Ok(response) => Ok(response.into_inner().position),
+            Err(status) => Err(dcb_error_from_status(status)),
+        }
+    }
+
+    /// Streams the data of the event at the position from the server, a chunk at a
+    /// time, so that the data of a large event is never held in memory as a whole.
+    /// The stream yields DCBError::EventNotFound if there is no event at the position.
+    pub fn read_event_data_stream(
+        &self,
+        position: u64,
+    ) -> impl Stream<Item = DCBResult<Bytes>> + Send + 'static {
+        let mut client = self.client.clone();
+        let request = ReadEventDataRequestProto { position };
+        futures::stream::once(async move { client.read_event_data(request).await })
+            .map(|response| match response {
+                Ok(response) => response
+                    .into_inner()
+                    .map(|message| match message {
+                        Ok(message) => Ok(Bytes::from(message.chunk)),
+                        Err(status) => Err(dcb_error_from_status(status)),
+                    })
+                    .left_stream(),
+                Err(status) => {
+                    futures::stream::once(async move { Err(dcb_error_from_status(status)) })
+                        .right_stream()
+                }
+            })
+            .flatten()
+    }
+
+    /// Returns the head position together with the TSN of the commit it was read
+    /// from, or None if there are no events.
+    pub async fn head_with_tsn(&self) -> DCBResult<Option<(u64, u64)>> {
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            async move {
+                match client.head(HeadRequestProto {}).await {
+                    Ok(response) => {
+                        let response = response.into_inner();
+                        Ok(response.position.map(|position| (position, response.tsn)))
+                    }
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns the TSN of the oldest commit that read_as_of() can read. Commits from
+    /// it up to the latest one are retained, though older ones may be forgotten at
+    /// any time.
+    pub async fn oldest_tsn(&self) -> DCBResult<u64> {
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            async move {
+                match client.oldest_tsn(OldestTsnRequestProto {}).await {
+                    Ok(response) => Ok(response.into_inner().tsn),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns storage statistics for the server's database.
+    pub async fn stats(&self) -> DCBResult<DbStats> {
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            async move {
+                match client.stats(StatsRequestProto {}).await {
+                    Ok(response) => Ok(response.into_inner().into()),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Writes every event to `writer` as newline-delimited JSON, in position order,
+    /// streaming a batch at a time. Returns the number of events written.
+    pub async fn export_ndjson<W: AsyncWrite + Unpin + Send>(
+        &self,
+        writer: &mut W,
+    ) -> DCBResult<u64> {
+        let mut response = self.read(None, None, false, None, false).await?;
+        let mut count = 0;
+        loop {
+            let batch = response.next_batch().await?;
+            if batch.is_empty() {
+                break;
+            }
+            let mut lines = Vec::new();
+            for event in &batch {
+                write_ndjson_event(&mut lines, event)?;
+            }
+            writer.write_all(&lines).await?;
+            count += batch.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(count)
+    }
 }
 
 #[async_trait]
@@ -352,18 +921,28 @@ impl DCBEventStoreAsync for AsyncUmaDBClient {
             subscribe: Some(subscribe),
             batch_size: self.batch_size,
         };
-        let mut client = self.client.clone();
-        let response = client.read(request).await.map_err(dcb_error_from_status)?;
+        let response = self
+            .with_retries(true, || {
+                let mut client = self.client.clone();
+                let request = request.clone();
+                async move { client.read(request).await.map_err(dcb_error_from_status) }
+            })
+            .await?;
         let stream = response.into_inner();
         Ok(Box::new(AsyncClientReadResponse::new(stream)))
     }
 
     async fn head(&self) -> DCBResult<Option<u64>> {
-        let mut client = self.client.clone();
-        match client.head(HeadRequestProto {}).await {
-            Ok(response) => Ok(response.into_inner().position),
-            Err(status) => Err(dcb_error_from_status(status)),
-        }
+        self.with_retries(true, || {
+            let mut client = self.client.clone();
+            async move {
+                match client.head(HeadRequestProto {}).await {
+                    Ok(response) => Ok(response.into_inner().position),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
     }
 
     async fn append(
@@ -371,6 +950,8 @@ impl DCBEventStoreAsync for AsyncUmaDBClient {
         events: Vec<DCBEvent>,
         condition: Option<DCBAppendCondition>,
     ) -> DCBResult<u64> {
+        // The server deduplicates events by UUID, so appending them again is safe
+        let idempotent = !events.is_empty() && events.iter().all(|event| event.uuid.is_some());
         let events_proto: Vec<EventProto> = events.into_iter().map(EventProto::from).collect();
         let condition_proto = condition.map(|c| AppendConditionProto {
             fail_if_events_match: Some(c.fail_if_events_match.into()),
@@ -380,11 +961,17 @@ impl DCBEventStoreAsync for AsyncUmaDBClient {
             events: events_proto,
             condition: condition_proto,
         };
-        let mut client = self.client.clone();
-        match client.append(request).await {
-            Ok(response) => Ok(response.into_inner().position),
-            Err(status) => Err(dcb_error_from_status(status)),
-        }
+        self.with_retries(idempotent, || {
+            let mut client = self.client.clone();
+            let request = request.clone();
+            async move {
+                match client.append(request).await {
+                    Ok(response) => Ok(response.into_inner().position),
+                    Err(status) => Err(dcb_error_from_status(status)),
+                }
+            }
+        })
+        .await
     }
 }
 
@@ -428,7 +1015,12 @@ impl AsyncClientReadResponse {
                         for e in resp.events {
                             if let Some(ev) = e.event {
                                 let event = DCBEvent::try_from(ev)?;
-                                buffered.push_back(DCBSequencedEvent { position: e.position, event });
+                                buffered.push_back(DCBSequencedEvent {
+                                    position: e.position,
+                                    event,
+                                    commit_timestamp: e.commit_timestamp,
+                                    tsn: e.tsn,
+                                });
                             }
                         }
                         self.buffered = buffered;
@@ -502,6 +1094,8 @@ impl Stream for AsyncClientReadResponse {
                             buffered.push_back(DCBSequencedEvent {
                                 position: e.position,
                                 event,
+                                commit_timestamp: e.commit_timestamp,
+                                tsn: e.tsn,
                             });
                         }
                     }
@@ -530,25 +1124,56 @@ impl Stream for AsyncClientReadResponse {
     }
 }
 
+/// Connect and request timeouts, where None waits indefinitely.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClientTimeouts {
+    pub connect: Option<Duration>,
+    pub request: Option<Duration>,
+}
+
+/// How requests that fail with a transport error are retried. Reads, head and
+/// count are always retried, appends only when every event has a UUID.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientRetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ClientRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct ClientTlsOptions {
     pub domain: Option<String>,
     pub ca_pem: Option<Vec<u8>>, // trusted CA cert in PEM for self-signed setups
+    pub identity_pem: Option<(Vec<u8>, Vec<u8>)>, // client cert and key in PEM, for client authentication
 }
 
 async fn new_channel(
     url: String,
     tls: Option<ClientTlsOptions>,
+    connect_timeout: Option<Duration>,
 ) -> Result<Channel, tonic::transport::Error> {
-    new_endpoint(url, tls)?.connect().await
+    let mut endpoint = new_endpoint(url, tls)?;
+    // Also applies when the channel reconnects
+    if let Some(timeout) = connect_timeout {
+        endpoint = endpoint.connect_timeout(timeout);
+    }
+    endpoint.connect().await
 }
 
 fn new_endpoint(
     url: String,
     tls: Option<ClientTlsOptions>,
 ) -> Result<Endpoint, tonic::transport::Error> {
-    use std::time::Duration;
-
     // Accept grpcs:// as an alias for https://
     let mut url_owned = url.to_string();
     if url_owned.starts_with("grpcs://") {
@@ -570,6 +1195,9 @@ fn new_endpoint(
         if let Some(ca) = opts.ca_pem {
             cfg = cfg.ca_certificate(Certificate::from_pem(ca));
         }
+        if let Some((cert, key)) = opts.identity_pem {
+            cfg = cfg.identity(Identity::from_pem(cert, key));
+        }
         endpoint = endpoint.tls_config(cfg)?;
     } else if url.starts_with("https://") {
         // When using https without explicit options, still enable default TLS.
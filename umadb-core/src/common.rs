@@ -1,3 +1,5 @@
+use umadb_dcb::{DCBError, DCBResult};
+
 // NewType definitions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PageID(pub u64);
@@ -7,3 +9,43 @@ pub struct Tsn(pub u64);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Position(pub u64);
+
+/// Where a full event tree node is split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitStrategy {
+    /// Moves only the last key to the new node, so that nodes filled by appends
+    /// stay full.
+    #[default]
+    RightBiased,
+    /// Moves the upper half of the keys to the new node, so that both halves have
+    /// room for inserts.
+    Middle,
+}
+
+/// Version of the node layout, written as the first byte of every event and
+/// free-list node encoding.
+pub const NODE_FORMAT_VERSION: u8 = 1;
+
+/// Checks the leading format-version byte of a node encoding and returns the
+/// remaining bytes.
+pub fn strip_node_format_version<'a>(node_name: &str, slice: &'a [u8]) -> DCBResult<&'a [u8]> {
+    read_node_format_version(node_name, slice, NODE_FORMAT_VERSION).map(|(_, rest)| rest)
+}
+
+/// Reads the leading format-version byte of a node encoding, accepting versions
+/// 1 to `latest`, and returns it with the remaining bytes.
+pub fn read_node_format_version<'a>(
+    node_name: &str,
+    slice: &'a [u8],
+    latest: u8,
+) -> DCBResult<(u8, &'a [u8])> {
+    match slice.first() {
+        None => Err(DCBError::DeserializationError(format!(
+            "{node_name}: missing format version byte"
+        ))),
+        Some(&version) if (1..=latest).contains(&version) => Ok((version, &slice[1..])),
+        Some(&version) => Err(DCBError::DeserializationError(format!(
+            "{node_name}: unsupported format version {version}"
+        ))),
+    }
+}
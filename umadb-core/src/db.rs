@@ -1,47 +1,433 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::common::{PageID, Position};
-use crate::events_tree::{EventIterator, event_tree_append, event_tree_lookup};
-use crate::events_tree_nodes::EventRecord;
-use crate::mvcc::{Mvcc, Writer};
+use crate::common::{PageID, Position, Tsn};
+use crate::events_tree::{
+    EventDataChunks, EventIterator, OverflowChainStream, event_tree_append,
+    event_tree_append_value, event_tree_lookup, event_tree_lookup_value,
+    event_tree_visit_types_and_tags,
+};
+use crate::events_tree_nodes::{EventLeafNode, EventRecord, EventValue};
+use crate::mvcc::{DurabilityMode, Mvcc, Reader, Writer};
+use crate::node::Node;
 use crate::page::Page;
 use crate::tags_tree::{TagsTreeIterator, tags_tree_insert};
 use crate::tags_tree_nodes::TagHash;
+use crate::wal::WalConfig;
 use itertools::Itertools;
+use rayon::prelude::*;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use umadb_dcb::{
-    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreSync, DCBQuery, DCBReadResponseSync,
-    DCBResult, DCBSequencedEvent,
+    DCBAppendCondition, DCBError, DCBEvent, DCBEventStoreSync, DCBMultiQueryEvent, DCBQuery,
+    DCBQueryMatcher, DCBReadResponseSync, DCBResult, DCBSequencedEvent, positions_ending_at,
 };
 use uuid::Uuid;
 
 pub static DEFAULT_PAGE_SIZE: usize = 4096;
 pub const DEFAULT_DB_FILENAME: &str = "uma.db";
 
+/// Resolves the database file path, using "uma.db" inside the path if it is a directory.
+fn resolve_db_file_path(p: &Path) -> PathBuf {
+    if p.is_dir() {
+        p.join(DEFAULT_DB_FILENAME)
+    } else {
+        p.to_path_buf()
+    }
+}
+
+/// Limits on the size of each appended event. Events that break them are
+/// rejected with an invalid argument error before anything is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventLimits {
+    /// Largest total length of an event's type and tags, None for no limit.
+    pub max_metadata_bytes: Option<usize>,
+    /// Largest length of an event's data, None for no limit.
+    pub max_data_bytes: Option<u64>,
+}
+
 /// EventStore implementing the DCBEventStoreSync interface
 pub struct UmaDB {
     mvcc: Arc<Mvcc>,
+    deduplicate_uuids: bool,
+    event_limits: EventLimits,
 }
 
 impl UmaDB {
     /// Create a new EventStore at the given directory or file path.
     /// If a directory path is provided, a file named "uma.db" will be used inside it.
     pub fn new<P: AsRef<Path>>(path: P) -> DCBResult<Self> {
-        let p = path.as_ref();
-        let file_path = if p.is_dir() {
-            p.join(DEFAULT_DB_FILENAME)
-        } else {
-            p.to_path_buf()
-        };
-        let mvcc = Mvcc::new(&file_path, DEFAULT_PAGE_SIZE, false)?;
+        let mvcc = Mvcc::new(
+            &resolve_db_file_path(path.as_ref()),
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::default(),
+        )?;
+        Ok(Self {
+            mvcc: Arc::new(mvcc),
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
+        })
+    }
+
+    /// Create a new EventStore that syncs commits to disk according to the given mode.
+    pub fn new_with_durability<P: AsRef<Path>>(
+        path: P,
+        durability: DurabilityMode,
+    ) -> DCBResult<Self> {
+        let mvcc = Mvcc::new(
+            &resolve_db_file_path(path.as_ref()),
+            DEFAULT_PAGE_SIZE,
+            durability,
+        )?;
+        Ok(Self {
+            mvcc: Arc::new(mvcc),
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
+        })
+    }
+
+    /// Create a new EventStore that zstd-compresses inline event data at the given level.
+    pub fn new_with_compression_level<P: AsRef<Path>>(path: P, level: i32) -> DCBResult<Self> {
+        let mvcc = Mvcc::new(
+            &resolve_db_file_path(path.as_ref()),
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::default(),
+        )?
+        .with_compression_level(level)?;
+        Ok(Self {
+            mvcc: Arc::new(mvcc),
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
+        })
+    }
+
+    /// Create a new EventStore whose pages are encrypted at rest with a key derived
+    /// from `key`. An existing database must have been created with the same key.
+    pub fn new_with_encryption<P: AsRef<Path>>(path: P, key: &[u8]) -> DCBResult<Self> {
+        let mvcc = Mvcc::new_with_encryption(
+            &resolve_db_file_path(path.as_ref()),
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::default(),
+            key,
+        )?;
+        Ok(Self {
+            mvcc: Arc::new(mvcc),
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
+        })
+    }
+
+    /// Create a new EventStore that syncs commits to a write-ahead log, and syncs the
+    /// database file when the log is checkpointed.
+    pub fn new_with_wal<P: AsRef<Path>>(path: P, config: WalConfig) -> DCBResult<Self> {
+        let mvcc = Mvcc::new(
+            &resolve_db_file_path(path.as_ref()),
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::default(),
+        )?
+        .with_wal(config)?;
         Ok(Self {
             mvcc: Arc::new(mvcc),
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
         })
     }
 
     pub fn from_arc(mvcc: Arc<Mvcc>) -> Self {
-        Self { mvcc }
+        Self {
+            mvcc,
+            deduplicate_uuids: false,
+            event_limits: EventLimits::default(),
+        }
+    }
+
+    /// When enabled, appending an event whose UUID is already stored skips the
+    /// event, so that retrying an append whose outcome is unknown is safe.
+    pub fn with_uuid_deduplication(mut self, enabled: bool) -> Self {
+        self.deduplicate_uuids = enabled;
+        self
+    }
+
+    /// Rejects appended events that are larger than the given limits.
+    pub fn with_event_limits(mut self, limits: EventLimits) -> Self {
+        self.event_limits = limits;
+        self
+    }
+
+    /// Returns the committed positions of events carrying the given tag, in
+    /// ascending order from start (or descending if backwards), using the tags
+    /// tree rather than scanning the events tree.
+    ///
+    /// The tags tree is keyed by tag hash, so positions of events whose tags
+    /// collide with the given tag are also returned.
+    pub fn tag_positions(
+        &self,
+        tag: &str,
+        start: Option<u64>,
+        backwards: bool,
+    ) -> DCBResult<impl Iterator<Item = u64> + use<>> {
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader()?;
+        let positions: Vec<u64> = TagsTreeIterator::new(
+            mvcc,
+            &HashMap::new(),
+            reader.tags_tree_root_id,
+            tag_to_hash(tag),
+            start.map(Position),
+            backwards,
+        )
+        .map(|p| p.0)
+        .collect();
+        Ok(positions.into_iter())
+    }
+
+    /// Returns the number of committed events matching the query, without
+    /// collecting them. An empty query matches every event.
+    pub fn count(&self, query: Option<DCBQuery>) -> DCBResult<u64> {
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader()?;
+        count_conditional(
+            mvcc,
+            &HashMap::new(),
+            reader.events_tree_root_id,
+            reader.tags_tree_root_id,
+            reader.next_position,
+            query.unwrap_or_default(),
+        )
+    }
+
+    /// Returns the committed events after `after` that match any of the queries, in
+    /// position order, each with the indices of the queries it matched. The events
+    /// are scanned once for all the queries.
+    pub fn read_multi(
+        &self,
+        queries: &[DCBQuery],
+        after: Option<u64>,
+    ) -> DCBResult<Vec<DCBMultiQueryEvent>> {
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader()?;
+        read_multi_conditional(
+            mvcc,
+            &HashMap::new(),
+            reader.events_tree_root_id,
+            queries,
+            after.map(Position),
+            None,
+        )
+    }
+
+    /// Returns the events that match the query, if one is given, as they were once the
+    /// commit with the given Tsn was made. The commit must be the latest one, or be
+    /// kept by Mvcc::with_history_retention().
+    pub fn read_as_of(
+        &self,
+        tsn: Tsn,
+        query: Option<DCBQuery>,
+    ) -> DCBResult<Vec<DCBSequencedEvent>> {
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader_as_of(tsn)?;
+        read_conditional(
+            mvcc,
+            &HashMap::new(),
+            reader.events_tree_root_id,
+            reader.tags_tree_root_id,
+            query.unwrap_or_default(),
+            None,
+            false,
+            None,
+            false,
+        )
+    }
+
+    /// Returns the committed events whose commit time, in milliseconds since the Unix
+    /// epoch, is at least `from_millis` and before `to_millis`, and which match the
+    /// query if one is given. Events appended before commit times were recorded are
+    /// never returned.
+    ///
+    /// The events tree is ordered by position rather than time, so this scans every
+    /// committed event, and its cost grows with the size of the database however
+    /// narrow the range is.
+    pub fn read_time_range(
+        &self,
+        from_millis: u64,
+        to_millis: u64,
+        query: Option<DCBQuery>,
+    ) -> DCBResult<Vec<DCBSequencedEvent>> {
+        const SCAN_BATCH_SIZE: u32 = 256;
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader()?;
+        let dirty = HashMap::new();
+        let matcher = query.as_ref().map(DCBQuery::matcher);
+        let mut iter = EventIterator::new(mvcc, &dirty, reader.events_tree_root_id, None, false);
+        let mut out = Vec::new();
+        loop {
+            let batch = iter.next_batch(SCAN_BATCH_SIZE)?;
+            if batch.is_empty() {
+                return Ok(out);
+            }
+            for (pos, rec) in batch {
+                let in_range = rec
+                    .timestamp
+                    .is_some_and(|timestamp| (from_millis..to_millis).contains(&timestamp));
+                if in_range
+                    && matcher
+                        .as_ref()
+                        .is_none_or(|matcher| matcher.matches(&rec.event_type, &rec.tags))
+                {
+                    out.push(rec.into_sequenced_event(pos));
+                }
+            }
+        }
+    }
+
+    /// Returns the committed event with the given UUID, if there is one. Events are
+    /// found by UUID whether or not UUID deduplication is enabled, and if several
+    /// events have the UUID the first is returned.
+    pub fn read_by_uuid(&self, uuid: Uuid) -> DCBResult<Option<DCBSequencedEvent>> {
+        let mvcc = &self.mvcc;
+        let reader = mvcc.reader()?;
+        let found = find_event_by_uuid(
+            mvcc,
+            &HashMap::new(),
+            reader.events_tree_root_id,
+            reader.tags_tree_root_id,
+            uuid,
+        )?;
+        Ok(found.map(|(position, rec)| rec.into_sequenced_event(position)))
+    }
+
+    /// Appends an event whose data arrives in chunks, such as from a network stream,
+    /// and returns its position. The chunks are written to an overflow chain as they
+    /// arrive, so the data is never held in memory as a whole. The event's own data
+    /// must be empty, and the chunks must add up to `data_len` bytes.
+    ///
+    /// With UUID deduplication, an event whose UUID is already stored isn't
+    /// appended again, and its position is returned without reading the chunks.
+    pub fn append_stream<I>(&self, event: DCBEvent, data_len: u64, chunks: I) -> DCBResult<u64>
+    where
+        I: IntoIterator<Item = DCBResult<Vec<u8>>>,
+    {
+        if !event.data.is_empty() {
+            return Err(DCBError::SerializationError(
+                "streamed event has data besides its chunks".to_string(),
+            ));
+        }
+        check_event_size(&self.mvcc, &self.event_limits, &event, data_len)?;
+        let mvcc = &self.mvcc;
+        let mut writer = mvcc.writer()?;
+        if self.deduplicate_uuids
+            && let Some(uuid) = event.uuid
+            && let Some(position) = find_position_by_uuid(
+                mvcc,
+                &writer.dirty,
+                writer.events_tree_root_id,
+                writer.tags_tree_root_id,
+                uuid,
+            )?
+        {
+            return Ok(position);
+        }
+
+        let mut stream = OverflowChainStream::new(mvcc, &mut writer, data_len)?;
+        for chunk in chunks {
+            let chunk = chunk?;
+            if stream.data_len() + chunk.len() as u64 > data_len {
+                return Err(DCBError::SerializationError(format!(
+                    "streamed event data is longer than its declared {data_len} bytes"
+                )));
+            }
+            stream.write(mvcc, &mut writer, &chunk)?;
+        }
+        let (root_id, streamed_len) = stream.finish(mvcc, &mut writer)?;
+        if streamed_len != data_len {
+            return Err(DCBError::SerializationError(format!(
+                "streamed {streamed_len} bytes of event data, declared {data_len}"
+            )));
+        }
+
+        let position = writer.issue_position();
+        for tag in event.tags.iter() {
+            tags_tree_insert(mvcc, &mut writer, tag_to_hash(tag), position)?;
+        }
+        if let Some(uuid) = event.uuid {
+            tags_tree_insert(mvcc, &mut writer, uuid_to_hash(&uuid), position)?;
+        }
+        let value = EventValue::Overflow {
+            event_type: event.event_type,
+            data_len,
+            tags: event.tags,
+            root_id,
+            uuid: event.uuid,
+            timestamp: Some(commit_timestamp()),
+            client_timestamp: event.client_timestamp,
+            tsn: Some(writer.tsn.0),
+        };
+        event_tree_append_value(mvcc, &mut writer, value, position)?;
+        mvcc.commit(&mut writer)?;
+        Ok(position.0)
+    }
+
+    /// Returns the data of the committed event at the position a chunk at a time, so
+    /// that the data of a large event is never held in memory as a whole. Returns
+    /// None if there is no event at the position.
+    pub fn read_event_data(&self, position: u64) -> DCBResult<Option<CommittedEventData<'_>>> {
+        committed_event_data(&self.mvcc, self.mvcc.reader()?, position)
+    }
+
+    /// Returns the head position together with the TSN of the commit it was read
+    /// from, or None if there are no events. Both come from the same committed header.
+    pub fn head_with_tsn(&self) -> DCBResult<Option<(u64, Tsn)>> {
+        let (_, header) = self.mvcc.get_latest_header()?;
+        let last = header.next_position.0.saturating_sub(1);
+        Ok((last != 0).then_some((last, header.tsn)))
+    }
+
+    /// Folds the committed events matching the query, scanning the subtrees under the
+    /// root of the events tree in parallel. Each subtree is folded into its own
+    /// accumulator, starting from `A::default()`, and the accumulators are combined
+    /// in position order. An empty query matches every event.
+    pub fn scan_parallel<A, F, C>(
+        &self,
+        query: Option<DCBQuery>,
+        fold: F,
+        combine: C,
+    ) -> DCBResult<A>
+    where
+        A: Default + Send,
+        F: Fn(A, DCBSequencedEvent) -> A + Sync,
+        C: Fn(A, A) -> A + Sync + Send,
+    {
+        const SCAN_BATCH_SIZE: u32 = 256;
+        let mvcc = &self.mvcc;
+        // Holding the reader keeps the snapshot's pages from being reused
+        let reader = mvcc.reader()?;
+        let matcher = query.unwrap_or_default().matcher();
+        let subtree_ids = match mvcc.read_page(reader.events_tree_root_id)?.node {
+            Node::EventInternal(internal) => internal.child_ids,
+            _ => vec![reader.events_tree_root_id],
+        };
+        let result = subtree_ids
+            .into_par_iter()
+            .map(|subtree_id| {
+                let dirty = HashMap::new();
+                let mut iter = EventIterator::new(mvcc, &dirty, subtree_id, None, false);
+                let mut acc = A::default();
+                loop {
+                    let batch = iter.next_batch(SCAN_BATCH_SIZE)?;
+                    if batch.is_empty() {
+                        break;
+                    }
+                    for (pos, rec) in batch.into_iter() {
+                        if matcher.matches(&rec.event_type, &rec.tags) {
+                            acc = fold(acc, rec.into_sequenced_event(pos));
+                        }
+                    }
+                }
+                Ok(acc)
+            })
+            .try_reduce(A::default, |a, b| Ok(combine(a, b)));
+        drop(reader);
+        result
     }
 
     /// Appends a batch of (events, condition) using a single writer/transaction.
@@ -56,61 +442,98 @@ impl UmaDB {
         items: Vec<(Vec<DCBEvent>, Option<DCBAppendCondition>)>,
         force_sequential_read: bool,
     ) -> DCBResult<Vec<DCBResult<u64>>> {
+        let results = self.append_batch_with_positions(items, force_sequential_read)?;
+        Ok(results
+            .into_iter()
+            .map(|result| result.map(|positions| positions.into_iter().max().unwrap_or(0)))
+            .collect())
+    }
+
+    /// Like append_batch(), but records the position of each event for each item.
+    pub fn append_batch_with_positions(
+        &self,
+        items: Vec<(Vec<DCBEvent>, Option<DCBAppendCondition>)>,
+        force_sequential_read: bool,
+    ) -> DCBResult<Vec<DCBResult<Vec<u64>>>> {
         // println!("Processing batch of {} items", items.len());
 
         let mvcc = &self.mvcc;
+        // Conditions are checked against the writer's state, so appends must not
+        // interleave between checking a condition and committing
+        let _writer_guard = mvcc.writer_lock.lock().unwrap();
         let mut writer = mvcc.writer()?;
-        let mut results: Vec<DCBResult<u64>> = Vec::with_capacity(items.len());
+        let mut results: Vec<DCBResult<Vec<u64>>> = Vec::with_capacity(items.len());
 
         for (events, condition) in items.into_iter() {
-            // Check condition using read_conditional (limit 1), starting after the provided position
+            // Reject the whole item before writing any of its events
+            if let Err(err) = events.iter().try_for_each(|event| {
+                check_event_size(mvcc, &self.event_limits, event, event.data.len() as u64)
+            }) {
+                results.push(Err(err));
+                continue;
+            }
             if let Some(cond) = condition {
                 let from = cond.after.map(|after| Position(after + 1));
-                let read_result1 = read_conditional(
-                    mvcc,
-                    &writer.dirty,
-                    writer.events_tree_root_id,
-                    writer.tags_tree_root_id,
-                    cond.fail_if_events_match.clone(),
-                    from,
-                    false,
-                    Some(1),
-                    force_sequential_read,
-                );
-                match read_result1 {
-                    Ok(found_vec) => {
-                        // Read didn't error...
-                        if let Some(matched) = found_vec.first() {
-                            // Found one event... consider if the request is idempotent...
-                            match is_request_idempotent(
-                                mvcc,
-                                &writer.dirty,
-                                writer.events_tree_root_id,
-                                writer.tags_tree_root_id,
-                                &events,
-                                cond.fail_if_events_match.clone(),
-                                from,
-                            ) {
-                                Ok(Some(last_recorded_position)) => {
-                                    results.push(Ok(last_recorded_position));
-                                }
-                                Ok(None) => {
-                                    // Propagate an integrity error for this item but continue with others
-                                    let msg = format!(
-                                        "condition: {:?} matched: {:?}, ",
-                                        cond.clone(),
-                                        matched,
-                                    );
-                                    results.push(Err(DCBError::IntegrityError(msg)));
-                                }
-                                Err(err) => {
-                                    // Propagate the error for this item but continue with others
-                                    results.push(Err(err));
-                                }
+                let conflict = if cond.fail_if_events_match.items.is_empty() {
+                    // An empty query matches every event, so the condition is an
+                    // expected head, which can be checked without reading events
+                    let head = writer.next_position.0.saturating_sub(1);
+                    let expected = cond.after.unwrap_or(0);
+                    Ok((head > expected).then(|| {
+                        format!(
+                            "expected head: {:?}, but head has advanced to {head}",
+                            cond.after
+                        )
+                    }))
+                } else {
+                    // Check condition using read_conditional (limit 1), starting after the provided position
+                    read_conditional(
+                        mvcc,
+                        &writer.dirty,
+                        writer.events_tree_root_id,
+                        writer.tags_tree_root_id,
+                        cond.fail_if_events_match.clone(),
+                        from,
+                        false,
+                        Some(1),
+                        force_sequential_read,
+                    )
+                    .map(|found_vec| {
+                        found_vec.first().map(|matched| {
+                            format!("condition: {:?} matched: {:?}, ", cond.clone(), matched)
+                        })
+                    })
+                };
+                match conflict {
+                    Ok(Some(msg)) => {
+                        // Found a conflicting event... consider if the request is idempotent...
+                        match is_request_idempotent(
+                            mvcc,
+                            &writer.dirty,
+                            writer.events_tree_root_id,
+                            writer.tags_tree_root_id,
+                            &events,
+                            cond.fail_if_events_match.clone(),
+                            from,
+                        ) {
+                            Ok(Some(last_recorded_position)) => {
+                                results.push(Ok(positions_ending_at(
+                                    last_recorded_position,
+                                    events.len(),
+                                )));
+                            }
+                            Ok(None) => {
+                                // Propagate an integrity error for this item but continue with others
+                                results.push(Err(DCBError::IntegrityError(msg)));
+                            }
+                            Err(err) => {
+                                // Propagate the error for this item but continue with others
+                                results.push(Err(err));
                             }
-                            continue;
                         }
+                        continue;
                     }
+                    Ok(None) => {}
                     Err(e) => {
                         // Propagate the read error for this item but continue with others
                         results.push(Err(e));
@@ -120,12 +543,19 @@ impl UmaDB {
             }
 
             if events.is_empty() {
-                results.push(Ok(0));
+                results.push(Ok(vec![]));
                 continue;
             }
 
             // Append unconditionally
-            match unconditional_append(mvcc, &mut writer, events) {
+            let appended = if self.deduplicate_uuids {
+                append_skipping_existing_uuids(mvcc, &mut writer, events)
+            } else {
+                let count = events.len();
+                unconditional_append(mvcc, &mut writer, events)
+                    .map(|last| positions_ending_at(last, count))
+            };
+            match appended {
                 Ok(last) => results.push(Ok(last)),
                 Err(e) => {
                     // Record error for this item and continue
@@ -156,7 +586,10 @@ impl DCBEventStoreSync for UmaDB {
         let last_committed_position = reader.next_position.0.saturating_sub(1);
 
         // Build query and after
-        let q = query.unwrap_or(DCBQuery { items: vec![] });
+        let q = query.unwrap_or(DCBQuery {
+            items: vec![],
+            case_insensitive: false,
+        });
         let from = start.map(Position);
 
         // Delegate to read_conditional
@@ -213,6 +646,19 @@ impl DCBEventStoreSync for UmaDB {
             Err(e) => Err(e),
         }
     }
+
+    fn append_with_positions(
+        &self,
+        events: Vec<DCBEvent>,
+        condition: Option<DCBAppendCondition>,
+    ) -> DCBResult<Vec<u64>> {
+        if events.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut results = self.append_batch_with_positions(vec![(events, condition)], false)?;
+        debug_assert_eq!(results.len(), 1);
+        results.remove(0)
+    }
 }
 
 struct ReadResponse {
@@ -241,22 +687,89 @@ impl DCBReadResponseSync for ReadResponse {
     }
 }
 
+// Milliseconds since the Unix epoch, recorded as the commit time of appended events
+fn commit_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Checks that the event is within the limits, and that its type and tags fit a
+/// leaf on their own, as they must when its data is stored in an overflow chain.
+pub fn check_event_size(
+    mvcc: &Mvcc,
+    limits: &EventLimits,
+    event: &DCBEvent,
+    data_len: u64,
+) -> DCBResult<()> {
+    let metadata_len = event.event_type.len() + event.tags.iter().map(String::len).sum::<usize>();
+    if let Some(max) = limits.max_metadata_bytes
+        && metadata_len > max
+    {
+        return Err(DCBError::InvalidArgument(format!(
+            "event type and {} tags are {metadata_len} bytes, at most {max} are allowed",
+            event.tags.len()
+        )));
+    }
+    if let Some(max) = limits.max_data_bytes
+        && data_len > max
+    {
+        return Err(DCBError::InvalidArgument(format!(
+            "event data is {data_len} bytes, at most {max} are allowed"
+        )));
+    }
+    let leaf = EventLeafNode::new(
+        vec![Position(u64::MAX)],
+        vec![EventValue::Overflow {
+            event_type: event.event_type.clone(),
+            data_len,
+            tags: event.tags.clone(),
+            root_id: PageID(u64::MAX),
+            uuid: event.uuid,
+            timestamp: Some(u64::MAX),
+            client_timestamp: event.client_timestamp,
+            tsn: Some(u64::MAX),
+        }],
+    );
+    let size = leaf.calc_serialized_size();
+    if size > mvcc.max_node_size {
+        return Err(DCBError::InvalidArgument(format!(
+            "event type and {} tags need {size} bytes, at most {} fit a page",
+            event.tags.len(),
+            mvcc.max_node_size
+        )));
+    }
+    Ok(())
+}
+
 /// Append events unconditionally to the database.
 ///
 /// For each event, this will:
 /// - issue a position from the writer
 /// - append an EventRecord to the event tree
-/// - insert the position for each tag into the tags tree
+/// - insert the position for each tag, and for the UUID, into the tags tree
 ///
-/// Caller is responsible for committing the writer.
+/// The events are given the current time as their commit time. Caller is
+/// responsible for committing the writer.
 pub fn unconditional_append(
     mvcc: &Mvcc,
     writer: &mut Writer,
     events: Vec<DCBEvent>,
+) -> DCBResult<u64> {
+    let timestamp = Some(commit_timestamp());
+    append_with_commit_timestamps(mvcc, writer, events.into_iter().map(|ev| (ev, timestamp)))
+}
+
+/// Appends events unconditionally like unconditional_append(), but with the given
+/// commit times, such as those of the events in an export.
+pub(crate) fn append_with_commit_timestamps(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    events: impl IntoIterator<Item = (DCBEvent, Option<u64>)>,
 ) -> DCBResult<u64> {
     let mut last_pos_u64: u64 = 0;
 
-    for ev in events.into_iter() {
+    for (ev, timestamp) in events {
         let position = writer.issue_position();
         last_pos_u64 = position.0;
         // Index tags before moving an event record into event_tree_append
@@ -264,11 +777,17 @@ pub fn unconditional_append(
             let tag_hash: TagHash = tag_to_hash(tag);
             tags_tree_insert(mvcc, writer, tag_hash, position)?;
         }
+        if let Some(uuid) = ev.uuid {
+            tags_tree_insert(mvcc, writer, uuid_to_hash(&uuid), position)?;
+        }
         let record = EventRecord {
             event_type: ev.event_type,
             data: ev.data,
             tags: ev.tags,
             uuid: ev.uuid,
+            timestamp,
+            client_timestamp: ev.client_timestamp,
+            tsn: Some(writer.tsn.0),
         };
         event_tree_append(mvcc, writer, record, position)?;
     }
@@ -276,74 +795,272 @@ pub fn unconditional_append(
     Ok(last_pos_u64)
 }
 
-/// Read events using the tags index by merging per-tag iterators, grouping by position,
-/// filtering by tag and type matches, and then looking up the event record.
-pub fn read_conditional(
+/// The data of a committed event, read a chunk at a time from the snapshot of the
+/// reader it holds.
+pub struct CommittedEventData<'a> {
+    chunks: EventDataChunks<'a>,
+    _reader: Reader,
+}
+
+impl Iterator for CommittedEventData<'_> {
+    type Item = DCBResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chunks.next()
+    }
+}
+
+/// Returns the data of the event at the position in the reader's snapshot, a chunk at
+/// a time, or None if there is no event at the position.
+pub fn committed_event_data(
+    mvcc: &Mvcc,
+    reader: Reader,
+    position: u64,
+) -> DCBResult<Option<CommittedEventData<'_>>> {
+    if position == 0 || position >= reader.next_position.0 {
+        return Ok(None);
+    }
+    let value = event_tree_lookup_value(
+        mvcc,
+        &HashMap::new(),
+        reader.events_tree_root_id,
+        Position(position),
+    )?;
+    Ok(Some(CommittedEventData {
+        chunks: EventDataChunks::new(mvcc, value),
+        _reader: reader,
+    }))
+}
+
+/// Append events like unconditional_append, except that events whose UUID is
+/// already stored (including earlier in the writer) are skipped. Returns the
+/// position of each event, whether it was skipped or appended.
+///
+/// Caller is responsible for committing the writer.
+pub fn append_skipping_existing_uuids(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    events: Vec<DCBEvent>,
+) -> DCBResult<Vec<u64>> {
+    let mut positions = Vec::with_capacity(events.len());
+    for ev in events.into_iter() {
+        let existing = match ev.uuid {
+            Some(uuid) => find_position_by_uuid(
+                mvcc,
+                &writer.dirty,
+                writer.events_tree_root_id,
+                writer.tags_tree_root_id,
+                uuid,
+            )?,
+            None => None,
+        };
+        let position = match existing {
+            Some(position) => position,
+            None => unconditional_append(mvcc, writer, vec![ev])?,
+        };
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+/// Finds the position of the event with the given UUID, using the UUID's entry
+/// in the tags tree and checking the event itself to rule out hash collisions.
+pub fn find_position_by_uuid(
     mvcc: &Mvcc,
     dirty: &HashMap<PageID, Page>,
     events_tree_root_id: PageID,
     tags_tree_root_id: PageID,
-    query: DCBQuery,
-    start: Option<Position>,
-    backwards: bool,
-    limit: Option<u32>,
-    force_sequential_read: bool,
-) -> DCBResult<Vec<DCBSequencedEvent>> {
-    const SCAN_BATCH_SIZE: u32 = 256;
-    // Special case: explicit zero limit
-    if let Some(0) = limit {
-        return Ok(Vec::new());
-    }
+    uuid: Uuid,
+) -> DCBResult<Option<u64>> {
+    Ok(
+        find_event_by_uuid(mvcc, dirty, events_tree_root_id, tags_tree_root_id, uuid)?
+            .map(|(position, _)| position.0),
+    )
+}
 
-    // If no items, return all events with after/limit respected via sequential scan
-    if query.items.is_empty() {
-        let mut iter = EventIterator::new(mvcc, dirty, events_tree_root_id, start, backwards);
-        let mut out: Vec<DCBSequencedEvent> = Vec::new();
-        'outer_all: loop {
-            let batch = iter.next_batch(limit.unwrap_or(SCAN_BATCH_SIZE))?;
-            if batch.is_empty() {
-                break;
-            }
-            for (pos, rec) in batch.into_iter() {
-                out.push(DCBSequencedEvent {
-                    position: pos.0,
-                    event: DCBEvent {
-                        event_type: rec.event_type,
-                        data: rec.data,
-                        tags: rec.tags,
-                        uuid: rec.uuid,
-                    },
-                });
-                if let Some(lim) = limit
-                    && out.len() >= lim as usize
-                {
-                    break 'outer_all;
-                }
-            }
+/// Finds the event with the given UUID and its position, as for find_position_by_uuid().
+pub fn find_event_by_uuid(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    tags_tree_root_id: PageID,
+    uuid: Uuid,
+) -> DCBResult<Option<(Position, EventRecord)>> {
+    let candidates = TagsTreeIterator::new(
+        mvcc,
+        dirty,
+        tags_tree_root_id,
+        uuid_to_hash(&uuid),
+        None,
+        false,
+    );
+    for position in candidates {
+        let record = event_tree_lookup(mvcc, dirty, events_tree_root_id, position)?;
+        if record.uuid == Some(uuid) {
+            return Ok(Some((position, record)));
         }
-        return Ok(out);
     }
+    Ok(None)
+}
 
-    // All query items must have at least one tag to use the tag index path.
+/// Count events matching the query. Only the types and tags of the events are
+/// read, so no event data is decompressed and no overflow chain is followed. Tag
+/// queries use the tags index, and an empty query is counted from the next position.
+pub fn count_conditional(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    tags_tree_root_id: PageID,
+    next_position: Position,
+    query: DCBQuery,
+) -> DCBResult<u64> {
+    if query.items.is_empty() {
+        return Ok(next_position.0.saturating_sub(1));
+    }
+    let mut count = 0u64;
     let all_items_have_tags = query.items.iter().all(|it| !it.tags.is_empty());
-    if !all_items_have_tags || force_sequential_read {
-        // Fallback: sequentially scan all events and apply the same matching logic
-        let mut iter = EventIterator::new(mvcc, dirty, events_tree_root_id, start, backwards);
-        let mut out: Vec<DCBSequencedEvent> = Vec::new();
-        let matches_item = |rec: &EventRecord| -> bool {
-            for item in &query.items {
-                let type_ok =
-                    item.types.is_empty() || item.types.iter().any(|t| t == &rec.event_type);
-                if !type_ok {
-                    continue;
+    if !all_items_have_tags || query.case_insensitive {
+        let matcher = query.matcher();
+        event_tree_visit_types_and_tags(
+            mvcc,
+            dirty,
+            events_tree_root_id,
+            None,
+            &mut |_, event_type, tags| {
+                if matcher.matches(event_type, tags) {
+                    count += 1;
                 }
-                let tags_ok = item.tags.iter().all(|t| rec.tags.iter().any(|et| et == t));
-                if type_ok && tags_ok {
-                    return true;
+            },
+        )?;
+        return Ok(count);
+    }
+
+    let candidates: Vec<(Position, Vec<usize>)> =
+        tag_index_candidates(mvcc, dirty, tags_tree_root_id, &query, None, false).collect();
+    let positions: Vec<Position> = candidates.iter().map(|(pos, _)| *pos).collect();
+    let mut candidates = candidates.iter().peekable();
+    event_tree_visit_types_and_tags(
+        mvcc,
+        dirty,
+        events_tree_root_id,
+        Some(&positions),
+        &mut |pos, event_type, tags| {
+            while candidates.next_if(|(p, _)| *p < pos).is_some() {}
+            // Verifying the actual event tags guards against tag-hash collisions
+            if let Some((_, matching_qiis)) = candidates.next_if(|(p, _)| *p == pos)
+                && matching_qiis
+                    .iter()
+                    .any(|&qii| query.items[qii].matches(event_type, tags))
+            {
+                count += 1;
+            }
+        },
+    )?;
+    Ok(count)
+}
+
+/// Sequentially scans the events after `after`, testing each against all the queries,
+/// and returns up to `limit` of those matching any, with the indices of the queries
+/// each matched. A query with no items matches every event.
+pub fn read_multi_conditional(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    queries: &[DCBQuery],
+    after: Option<Position>,
+    limit: Option<u32>,
+) -> DCBResult<Vec<DCBMultiQueryEvent>> {
+    const SCAN_BATCH_SIZE: u32 = 256;
+    let mut out: Vec<DCBMultiQueryEvent> = Vec::new();
+    if queries.is_empty() || limit == Some(0) {
+        return Ok(out);
+    }
+    let matchers: Vec<DCBQueryMatcher> = queries.iter().map(DCBQuery::matcher).collect();
+    let start = after.map(|after| Position(after.0 + 1));
+    let mut iter = EventIterator::new(mvcc, dirty, events_tree_root_id, start, false);
+    loop {
+        let batch = iter.next_batch(SCAN_BATCH_SIZE)?;
+        if batch.is_empty() {
+            return Ok(out);
+        }
+        for (pos, rec) in batch.into_iter() {
+            let query_indices: Vec<usize> = matchers
+                .iter()
+                .enumerate()
+                .filter(|(_, matcher)| matcher.matches(&rec.event_type, &rec.tags))
+                .map(|(index, _)| index)
+                .collect();
+            if query_indices.is_empty() {
+                continue;
+            }
+            out.push(DCBMultiQueryEvent {
+                event: rec.into_sequenced_event(pos),
+                query_indices,
+            });
+            if let Some(lim) = limit
+                && out.len() >= lim as usize
+            {
+                return Ok(out);
+            }
+        }
+    }
+}
+
+/// Read events using the tags index by merging per-tag iterators, grouping by position,
+/// filtering by tag and type matches, and then looking up the event record.
+#[allow(clippy::too_many_arguments)]
+pub fn read_conditional(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    tags_tree_root_id: PageID,
+    query: DCBQuery,
+    start: Option<Position>,
+    backwards: bool,
+    limit: Option<u32>,
+    force_sequential_read: bool,
+) -> DCBResult<Vec<DCBSequencedEvent>> {
+    const SCAN_BATCH_SIZE: u32 = 256;
+    // Special case: explicit zero limit
+    if let Some(0) = limit {
+        return Ok(Vec::new());
+    }
+
+    // If no items, return all events with after/limit respected via sequential scan
+    if query.items.is_empty() {
+        let mut iter = EventIterator::new(mvcc, dirty, events_tree_root_id, start, backwards);
+        let mut out: Vec<DCBSequencedEvent> = Vec::new();
+        'outer_all: loop {
+            let batch = iter.next_batch(limit.unwrap_or(SCAN_BATCH_SIZE))?;
+            if batch.is_empty() {
+                break;
+            }
+            for (pos, rec) in batch.into_iter() {
+                out.push(rec.into_sequenced_event(pos));
+                if let Some(lim) = limit
+                    && out.len() >= lim as usize
+                {
+                    break 'outer_all;
                 }
             }
-            false
-        };
+        }
+        return Ok(out);
+    }
+
+    // All query items must have at least one tag to use the tag index path, and
+    // the index holds the hashes of tags as they were stored, so their exact case
+    let all_items_have_tags = query.items.iter().all(|it| !it.tags.is_empty());
+    if !all_items_have_tags || force_sequential_read || query.case_insensitive {
+        // Fallback: sequentially scan all events and apply the same matching logic,
+        // skipping leaves without the tags when every item has exact tags
+        let mut iter = EventIterator::new(mvcc, dirty, events_tree_root_id, start, backwards);
+        if all_items_have_tags && !query.case_insensitive {
+            iter = iter.with_tag_sets(query.items.iter().map(|it| it.tags.clone()).collect());
+        }
+        let mut out: Vec<DCBSequencedEvent> = Vec::new();
+        let matcher = query.matcher();
+        let matches_item =
+            |rec: &EventRecord| -> bool { matcher.matches(&rec.event_type, &rec.tags) };
         'outer_fallback: loop {
             let batch = iter.next_batch(SCAN_BATCH_SIZE)?;
             if batch.is_empty() {
@@ -351,15 +1068,7 @@ pub fn read_conditional(
             }
             for (pos, rec) in batch.into_iter() {
                 if matches_item(&rec) {
-                    out.push(DCBSequencedEvent {
-                        position: pos.0,
-                        event: DCBEvent {
-                            event_type: rec.event_type,
-                            data: rec.data,
-                            tags: rec.tags,
-                            uuid: rec.uuid,
-                        },
-                    });
+                    out.push(rec.into_sequenced_event(pos));
                     if let Some(lim) = limit
                         && out.len() >= lim as usize
                     {
@@ -371,6 +1080,42 @@ pub fn read_conditional(
         return Ok(out);
     }
 
+    let mut out: Vec<DCBSequencedEvent> = Vec::new();
+    let candidates = tag_index_candidates(mvcc, dirty, tags_tree_root_id, &query, start, backwards);
+    for (pos, matching_qiis) in candidates {
+        // Lookup the event record at position
+        let rec = event_tree_lookup(mvcc, dirty, events_tree_root_id, pos)?;
+
+        // Verifying the actual event tags guards against tag-hash collisions
+        let match_ok = matching_qiis
+            .iter()
+            .any(|&qii| query.items[qii].matches(&rec.event_type, &rec.tags));
+        if !match_ok {
+            continue;
+        }
+
+        out.push(rec.into_sequenced_event(pos));
+        if let Some(lim) = limit
+            && out.len() >= lim as usize
+        {
+            break;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Yields the positions the tags index has for every tag of some query item, in
+/// position order, with the indices of those items. The positions are found by
+/// tag hash, so the tags of each event must still be checked against the items.
+fn tag_index_candidates<'a>(
+    mvcc: &'a Mvcc,
+    dirty: &'a HashMap<PageID, Page>,
+    tags_tree_root_id: PageID,
+    query: &DCBQuery,
+    start: Option<Position>,
+    backwards: bool,
+) -> impl Iterator<Item = (Position, Vec<usize>)> + 'a {
     // Invert query: tag -> list of query item indices that require this tag
     let mut tag_qiis: HashMap<String, Vec<usize>> = HashMap::with_capacity(query.items.len() * 2);
     let mut qi_tags: Vec<HashSet<String>> = Vec::with_capacity(query.items.len());
@@ -426,7 +1171,7 @@ pub fn read_conditional(
     // Merge iterators ordered by position
     let merged = tag_iters
         .into_iter()
-        .kmerge_by(|a, b| if !backwards { a.0 < b.0 } else { a.0 > b.0 });
+        .kmerge_by(move |a, b| if !backwards { a.0 < b.0 } else { a.0 > b.0 });
 
     // Group by position, collecting tags and qiids
     struct GroupByPositionIterator<I>
@@ -491,59 +1236,17 @@ pub fn read_conditional(
         }
     }
 
-    let mut out: Vec<DCBSequencedEvent> = Vec::new();
-    for (pos, tags_present, qiis_present) in GroupByPositionIterator::new(merged) {
+    GroupByPositionIterator::new(merged).filter_map(move |(pos, tags_present, qiis_present)| {
         // Find any query item whose required tag set is subset of tags_present
         let matching_qiis: Vec<usize> = qiis_present
             .iter()
             .copied()
             .filter(|&qii| qi_tags[qii].is_subset(&tags_present))
             .collect();
-        if matching_qiis.is_empty() {
-            continue;
-        }
-
-        // Lookup the event record at position
-        let rec = event_tree_lookup(mvcc, dirty, events_tree_root_id, pos)?;
-
-        // Check type and actual tag matching against any of the matching items to avoid hash-collision false positives
-        let mut match_ok = false;
-        'matchcheck: for qii in matching_qiis.iter().copied() {
-            let item = &query.items[qii];
-            // Type must match (or be unspecified)
-            let type_ok = item.types.is_empty() || item.types.iter().any(|t| t == &rec.event_type);
-            if !type_ok {
-                continue;
-            }
-            // Verify actual event tags contain all item tags (guards against tag-hash collisions)
-            let tags_ok = item.tags.iter().all(|t| rec.tags.iter().any(|et| et == t));
-            if tags_ok {
-                match_ok = true;
-                break 'matchcheck;
-            }
-        }
-        if !match_ok {
-            continue;
-        }
-
-        out.push(DCBSequencedEvent {
-            position: pos.0,
-            event: DCBEvent {
-                event_type: rec.event_type,
-                data: rec.data,
-                tags: rec.tags,
-                uuid: rec.uuid,
-            },
-        });
-        if let Some(lim) = limit
-            && out.len() >= lim as usize
-        {
-            break;
-        }
-    }
-
-    Ok(out)
+        (!matching_qiis.is_empty()).then_some((pos, matching_qiis))
+    })
 }
+
 /// Compute a TagHash ([u8; 8]) from a tag string using a stable 64-bit hash.
 #[inline(always)]
 pub fn tag_to_hash(tag: &str) -> TagHash {
@@ -567,6 +1270,20 @@ pub fn tag_to_hash(tag: &str) -> TagHash {
     value.to_le_bytes()
 }
 
+/// Compute the TagHash under which an event's UUID is indexed in the tags tree.
+/// Tag reads check the actual event tags, so a collision with a tag's hash is harmless.
+pub fn uuid_to_hash(uuid: &Uuid) -> TagHash {
+    let (high, low) = uuid.as_u64_pair();
+    (high ^ low).to_le_bytes()
+}
+
+/// Compute the TagHash under which an overflow chain's data is indexed in the tags
+/// tree, from its SHA-256 digest. A match is only shared once the data compares equal.
+pub fn payload_to_hash(data: &[u8]) -> TagHash {
+    let digest = ring::digest::digest(&ring::digest::SHA256, data);
+    digest.as_ref()[..8].try_into().unwrap()
+}
+
 pub fn is_request_idempotent(
     mvcc: &Arc<Mvcc>,
     dirty: &HashMap<PageID, Page>,
@@ -633,6 +1350,7 @@ pub fn is_request_idempotent(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::events_tree_nodes::OverflowWriter;
     use crate::page::Page;
     use serial_test::serial;
     use std::collections::HashMap;
@@ -669,7 +1387,7 @@ mod tests {
 
     // Helper to produce a deterministic set of 10 events with shared tags and unique types
     fn standard_events() -> Vec<DCBEvent> {
-        let shared_tags = vec![
+        let shared_tags = [
             "alpha".to_string(),
             "beta".to_string(),
             "gamma".to_string(),
@@ -685,6 +1403,7 @@ mod tests {
                 data: vec![i, i + 1, i + 2],
                 tags: vec![t1, t2],
                 uuid: None,
+                client_timestamp: None,
             });
         }
         input
@@ -694,7 +1413,9 @@ mod tests {
     fn setup_db_with_standard_events() -> (tempfile::TempDir, Mvcc, Vec<DCBEvent>) {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-api-test.db");
-        let db = Mvcc::new(db_path.as_ref(), DEFAULT_PAGE_SIZE, VERBOSE).unwrap();
+        let db = Mvcc::new(db_path.as_ref(), DEFAULT_PAGE_SIZE, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
         let input = standard_events();
         let mut writer = db.writer().unwrap();
         let last = unconditional_append(&db, &mut writer, input.clone()).unwrap();
@@ -709,16 +1430,19 @@ mod tests {
     #[test]
     #[serial]
     fn empty_query_after_and_limit() {
-        let (_tmp, mut mvcc, input) = setup_db_with_standard_events();
+        let (_tmp, mvcc, input) = setup_db_with_standard_events();
 
         // after = 0 -> all
         let reader = mvcc.reader().unwrap();
 
         let all = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(1)),
             false,
             None,
@@ -730,10 +1454,13 @@ mod tests {
         // after = first -> tail
         let first = all[0].position;
         let tail = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(first + 1)),
             false,
             None,
@@ -744,10 +1471,13 @@ mod tests {
         // after = last -> empty
         let last = all.last().unwrap().position;
         let none = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(last + 1)),
             false,
             None,
@@ -757,10 +1487,13 @@ mod tests {
 
         // limits
         let lim0 = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(1)),
             false,
             Some(0),
@@ -768,10 +1501,13 @@ mod tests {
         .unwrap();
         assert!(lim0.is_empty());
         let lim3 = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(1)),
             false,
             Some(3),
@@ -779,10 +1515,13 @@ mod tests {
         .unwrap();
         assert_eq!(lim3.len(), 3);
         let lim20 = read_conditional(
-            &mut mvcc,
+            &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(1)),
             false,
             Some(20),
@@ -794,16 +1533,17 @@ mod tests {
     #[test]
     #[serial]
     fn tags_only_single_tag_after_and_limit() {
-        let (_tmp, mut db, _input) = setup_db_with_standard_events();
+        let (_tmp, db, _input) = setup_db_with_standard_events();
         let qi = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec![],
                 tags: vec!["alpha".to_string()],
             }],
+            case_insensitive: false,
         };
         let reader = db.reader().unwrap();
         let res = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -822,7 +1562,7 @@ mod tests {
         // after combinations
         let positions: Vec<u64> = res.iter().map(|e| e.position).collect();
         let after_first = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -833,7 +1573,7 @@ mod tests {
         .unwrap();
         assert_eq!(after_first.len(), positions.len() - 1);
         let after_last = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -846,7 +1586,7 @@ mod tests {
 
         // limits
         let lim0 = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -857,7 +1597,7 @@ mod tests {
         .unwrap();
         assert!(lim0.is_empty());
         let lim1 = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -868,7 +1608,7 @@ mod tests {
         .unwrap();
         assert_eq!(lim1.len(), 1);
         let lim10 = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi,
@@ -883,16 +1623,17 @@ mod tests {
     #[test]
     #[serial]
     fn tags_only_multi_tag_and() {
-        let (_tmp, mut db, _input) = setup_db_with_standard_events();
+        let (_tmp, db, _input) = setup_db_with_standard_events();
         let qi = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec![],
                 tags: vec!["alpha".to_string(), "gamma".to_string()],
             }],
+            case_insensitive: false,
         };
         let reader = db.reader().unwrap();
         let res = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi,
@@ -915,16 +1656,17 @@ mod tests {
     #[test]
     #[serial]
     fn types_plus_tags_index_path() {
-        let (_tmp, mut db, _input) = setup_db_with_standard_events();
+        let (_tmp, db, _input) = setup_db_with_standard_events();
         let qi = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec!["Type0".to_string()],
                 tags: vec!["alpha".to_string()],
             }],
+            case_insensitive: false,
         };
         let reader = db.reader().unwrap();
         let res = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi,
@@ -941,16 +1683,17 @@ mod tests {
     #[test]
     #[serial]
     fn or_semantics_and_deduplication() {
-        let (_tmp, mut db, _input) = setup_db_with_standard_events();
+        let (_tmp, db, _input) = setup_db_with_standard_events();
         let alpha_only = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec![],
                 tags: vec!["alpha".to_string()],
             }],
+            case_insensitive: false,
         };
         let reader = db.reader().unwrap();
         let alpha_positions: Vec<u64> = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             alpha_only.clone(),
@@ -975,9 +1718,10 @@ mod tests {
                     tags: vec!["alpha".to_string(), "gamma".to_string()],
                 },
             ],
+            case_insensitive: false,
         };
         let res = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             query,
@@ -995,7 +1739,9 @@ mod tests {
     fn fallback_types_only_after_and_limit() {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-fallback-types-only.db");
-        let mut db = Mvcc::new(db_path.as_ref(), DEFAULT_PAGE_SIZE, VERBOSE).unwrap();
+        let db = Mvcc::new(db_path.as_ref(), DEFAULT_PAGE_SIZE, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
 
         // Use a smaller custom set to make counts obvious
         let events = vec![
@@ -1004,18 +1750,21 @@ mod tests {
                 data: vec![1],
                 tags: vec!["x".to_string()],
                 uuid: None,
+                client_timestamp: None,
             },
             DCBEvent {
                 event_type: "TypeB".to_string(),
                 data: vec![2],
                 tags: vec!["y".to_string()],
                 uuid: None,
+                client_timestamp: None,
             },
             DCBEvent {
                 event_type: "TypeA".to_string(),
                 data: vec![3],
                 tags: vec!["z".to_string()],
                 uuid: None,
+                client_timestamp: None,
             },
         ];
         let mut writer = db.writer().unwrap();
@@ -1031,10 +1780,11 @@ mod tests {
                 types: vec!["TypeA".to_string()],
                 tags: vec![],
             }],
+            case_insensitive: false,
         };
         let reader = db.reader().unwrap();
         let res = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -1049,7 +1799,7 @@ mod tests {
         // After skip first matching
         let first_pos = res[0].position;
         let res_after = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -1062,7 +1812,7 @@ mod tests {
 
         // Limit 1
         let res_lim1 = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi,
@@ -1077,18 +1827,19 @@ mod tests {
     #[test]
     #[serial]
     fn fallback_empty_item_matches_all() {
-        let (_tmp, mut db, input) = setup_db_with_standard_events();
+        let (_tmp, db, input) = setup_db_with_standard_events();
         // An empty item (no types, no tags) should match all events via fallback path
         let qi = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec![],
                 tags: vec![],
             }],
+            case_insensitive: false,
         };
 
         let reader = db.reader().unwrap();
         let all = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -1102,7 +1853,7 @@ mod tests {
         // After and limit still apply
         let first = all[1].position;
         let tail = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi.clone(),
@@ -1113,7 +1864,7 @@ mod tests {
         .unwrap();
         assert_eq!(tail.len(), input.len() - 1);
         let lim5 = read_conditional(
-            &mut db,
+            &db,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
             qi,
@@ -1141,12 +1892,14 @@ mod tests {
                 data: vec![1],
                 tags: vec!["foo".to_string()],
                 uuid: None,
+                client_timestamp: None,
             },
             DCBEvent {
                 event_type: "TypeB".to_string(),
                 data: vec![2],
                 tags: vec!["bar".to_string(), "foo".to_string()],
                 uuid: None,
+                client_timestamp: None,
             },
         ];
         let last = store.append(events.clone(), None).unwrap();
@@ -1174,6 +1927,7 @@ mod tests {
                 types: vec![],
                 tags: vec!["foo".to_string()],
             }],
+            case_insensitive: false,
         };
         let mut resp2 = store.read(Some(query), None, false, None, false).unwrap();
         let out2 = resp2.next_batch().unwrap();
@@ -1196,6 +1950,7 @@ mod tests {
                     types: vec![],
                     tags: vec!["foo".to_string()],
                 }],
+                case_insensitive: false,
             },
             after: Some(last),
         };
@@ -1206,6 +1961,7 @@ mod tests {
                     data: vec![3],
                     tags: vec!["baz".to_string()],
                     uuid: None,
+                    client_timestamp: None,
                 }],
                 Some(cond_pass),
             )
@@ -1220,6 +1976,7 @@ mod tests {
                     types: vec![],
                     tags: vec!["foo".to_string()],
                 }],
+                case_insensitive: false,
             },
             after: Some(0),
         };
@@ -1230,6 +1987,7 @@ mod tests {
                 data: vec![4],
                 tags: vec!["qux".to_string()],
                 uuid: None,
+                client_timestamp: None,
             }],
             Some(cond_fail),
         );
@@ -1251,18 +2009,21 @@ mod tests {
             data: b"1".to_vec(),
             tags: vec!["t1".into()],
             uuid: None,
+            client_timestamp: None,
         };
         let e2 = DCBEvent {
             event_type: "B".into(),
             data: b"2".to_vec(),
             tags: vec!["t2".into()],
             uuid: None,
+            client_timestamp: None,
         };
         let e3 = DCBEvent {
             event_type: "C".into(),
             data: b"3".to_vec(),
             tags: vec!["t3".into()],
             uuid: None,
+            client_timestamp: None,
         };
 
         // Batch: first succeeds, second fails due to condition matching any event, third succeeds (after high position)
@@ -1322,18 +2083,21 @@ mod tests {
             data: b"one".to_vec(),
             tags: vec!["x".into()],
             uuid: None,
+            client_timestamp: None,
         };
         let e2 = DCBEvent {
             event_type: "T".into(),
             data: b"two".to_vec(),
             tags: vec!["y".into()],
             uuid: None,
+            client_timestamp: None,
         };
         let e3 = DCBEvent {
             event_type: "T".into(),
             data: b"three".to_vec(),
             tags: vec!["z".into()],
             uuid: None,
+            client_timestamp: None,
         };
 
         let query_tag_x = DCBQuery {
@@ -1341,6 +2105,7 @@ mod tests {
                 types: vec![],
                 tags: vec!["x".into()],
             }],
+            case_insensitive: false,
         };
 
         let items = vec![
@@ -1406,6 +2171,7 @@ mod tests {
             data: b"sm".to_vec(),
             tags: vec!["tS".into()],
             uuid: None,
+            client_timestamp: None,
         };
         // Large data to ensure it spills into event overflow pages
         let big_data_len = DEFAULT_PAGE_SIZE * 3; // 3 pages worth to be safe
@@ -1414,24 +2180,28 @@ mod tests {
             data: vec![0xAB; big_data_len],
             tags: vec!["tB".into()],
             uuid: None,
+            client_timestamp: None,
         };
         let filler1 = DCBEvent {
             event_type: "X".into(),
             data: b"x".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
         };
         let filler2 = DCBEvent {
             event_type: "Y".into(),
             data: b"y".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
         };
         let final_ok = DCBEvent {
             event_type: "C".into(),
             data: b"c".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
         };
 
         // Queries by type only (no tags) to force fallback path over events tree (which reads from dirty pages)
@@ -1440,12 +2210,14 @@ mod tests {
                 types: vec!["S".into()],
                 tags: vec![],
             }],
+            case_insensitive: false,
         };
         let q_type_b = DCBQuery {
             items: vec![DCBQueryItem {
                 types: vec!["B".into()],
                 tags: vec![],
             }],
+            case_insensitive: false,
         };
 
         let items = vec![
@@ -1537,6 +2309,7 @@ mod tests {
             data: b"sm".to_vec(),
             tags: vec!["x".into()],
             uuid: None,
+            client_timestamp: None,
         };
         // Big overflow event: type "B" with tag "y" and large payload to exercise overflow pages
         let big_data_len = DEFAULT_PAGE_SIZE * 3; // ensure multiple overflow pages
@@ -1545,6 +2318,7 @@ mod tests {
             data: vec![0xCD; big_data_len],
             tags: vec!["y".into()],
             uuid: None,
+            client_timestamp: None,
         };
         // Fillers that will be conditioned out
         let filler1 = DCBEvent {
@@ -1552,112 +2326,1127 @@ mod tests {
             data: b"x".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
         };
         let filler2 = DCBEvent {
             event_type: "Y".into(),
             data: b"y".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
         };
         let final_ok = DCBEvent {
             event_type: "C".into(),
             data: b"c".to_vec(),
             tags: vec![],
             uuid: None,
+            client_timestamp: None,
+        };
+
+        // Conditions combining tags and types so the tags index is used and the type filter applies after lookup
+        let q_s_and_x = DCBQuery {
+            items: vec![DCBQueryItem {
+                types: vec!["S".into()],
+                tags: vec!["x".into()],
+            }],
+            case_insensitive: false,
+        };
+        let q_b_and_y = DCBQuery {
+            items: vec![DCBQueryItem {
+                types: vec!["B".into()],
+                tags: vec!["y".into()],
+            }],
+            case_insensitive: false,
+        };
+
+        let items = vec![
+            // 1) Append small S@x
+            (vec![small.clone()], None),
+            // 2) Should fail because S@x exists in dirty pages (tags path + type filter)
+            (
+                vec![filler1.clone()],
+                Some(DCBAppendCondition {
+                    fail_if_events_match: q_s_and_x.clone(),
+                    after: None,
+                }),
+            ),
+            // 3) Append big B@y (overflow)
+            (vec![big.clone()], None),
+            // 4) Should fail because B@y exists in dirty pages (tags path + type filter and overflow read)
+            (
+                vec![filler2.clone()],
+                Some(DCBAppendCondition {
+                    fail_if_events_match: q_b_and_y.clone(),
+                    after: None,
+                }),
+            ),
+            // 5) Should succeed because after=Some(2) ignores positions <= 2 (small at 1, big at 2)
+            (
+                vec![final_ok.clone()],
+                Some(DCBAppendCondition {
+                    fail_if_events_match: q_b_and_y.clone(),
+                    after: Some(2),
+                }),
+            ),
+        ];
+
+        let results = store.append_batch(items, false).unwrap();
+        assert_eq!(results.len(), 5);
+        match &results[0] {
+            Ok(pos) => assert_eq!(*pos, 1),
+            other => panic!("unexpected for item0: {:?}", other),
+        }
+        match &results[1] {
+            Err(DCBError::IntegrityError(_)) => {}
+            other => panic!("expected IntegrityError for item1, got {:?}", other),
+        }
+        match &results[2] {
+            Ok(pos) => assert_eq!(*pos, 2),
+            other => panic!("unexpected for item2: {:?}", other),
+        }
+        match &results[3] {
+            Err(DCBError::IntegrityError(_)) => {}
+            other => panic!("expected IntegrityError for item3, got {:?}", other),
+        }
+        match &results[4] {
+            Ok(pos) => assert_eq!(*pos, 3),
+            other => panic!("unexpected for item4: {:?}", other),
+        }
+
+        // Verify committed state and order
+        let (events, head) = store.read_with_head(None, None, false, None).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].event.event_type, small.event_type);
+        assert_eq!(events[1].event.event_type, big.event_type);
+        assert_eq!(events[2].event.event_type, final_ok.event_type);
+        assert_eq!(head, Some(3));
+
+        // Query by combined type+tag should return exactly one for each
+        let (small_combined, _) = store
+            .read_with_head(Some(q_s_and_x.clone()), None, false, None)
+            .unwrap();
+        assert_eq!(small_combined.len(), 1);
+        assert_eq!(small_combined[0].event.event_type, "S");
+        assert!(small_combined[0].event.tags.iter().any(|t| t == "x"));
+
+        let (big_combined, _) = store
+            .read_with_head(Some(q_b_and_y.clone()), None, false, None)
+            .unwrap();
+        assert_eq!(big_combined.len(), 1);
+        assert_eq!(big_combined[0].event.event_type, "B");
+        assert!(big_combined[0].event.tags.iter().any(|t| t == "y"));
+        assert_eq!(big_combined[0].event.data.len(), big_data_len);
+        assert!(big_combined[0].event.data.iter().all(|&b| b == 0xCD));
+    }
+
+    #[test]
+    fn test_append_with_expected_head_races() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        store
+            .append(vec![DCBEvent::new().event_type("initial")], None)
+            .unwrap();
+        let head = store.head().unwrap();
+        assert_eq!(Some(1), head);
+
+        // Two clients race to append with the same expected head
+        let barrier = std::sync::Barrier::new(2);
+        let results: Vec<DCBResult<u64>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = ["client1", "client2"]
+                .into_iter()
+                .map(|client| {
+                    let (store, barrier) = (&store, &barrier);
+                    scope.spawn(move || {
+                        barrier.wait();
+                        store.append(
+                            vec![DCBEvent::new().event_type(client)],
+                            Some(DCBAppendCondition::expected_head(head)),
+                        )
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // Exactly one append succeeds
+        assert_eq!(1, results.iter().filter(|r| r.is_ok()).count());
+        let ok = results.iter().find_map(|r| r.as_ref().ok()).unwrap();
+        assert_eq!(2, *ok);
+        match results.iter().find_map(|r| r.as_ref().err()).unwrap() {
+            DCBError::IntegrityError(msg) => {
+                assert!(msg.contains("expected head: Some(1)"), "{msg}")
+            }
+            other => panic!("Expected IntegrityError, got {other:?}"),
+        }
+        assert_eq!(Some(2), store.head().unwrap());
+    }
+
+    #[test]
+    fn test_scan_parallel_count_matches_serial_count() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new_with_durability(temp_dir.path(), DurabilityMode::NoSync).unwrap();
+        let event_types = ["Created", "Updated", "Deleted"];
+        for batch in 0..100 {
+            let events = (0..1000)
+                .map(|i| DCBEvent::new().event_type(event_types[(batch * 1000 + i) % 3]))
+                .collect();
+            store.append(events, None).unwrap();
+        }
+        let reader = store.mvcc.reader().unwrap();
+        let root = store.mvcc.read_page(reader.events_tree_root_id).unwrap();
+        assert!(matches!(root.node, Node::EventInternal(_)));
+
+        let count_per_type = |counts: HashMap<String, u64>, event: &DCBSequencedEvent| {
+            let mut counts = counts;
+            *counts.entry(event.event.event_type.clone()).or_default() += 1;
+            counts
+        };
+        let serial = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .map(|event| event.unwrap())
+            .fold(HashMap::new(), |counts, event| {
+                count_per_type(counts, &event)
+            });
+        let parallel = store
+            .scan_parallel(
+                None,
+                |counts, event| count_per_type(counts, &event),
+                |mut a, b| {
+                    for (event_type, count) in b {
+                        *a.entry(event_type).or_default() += count;
+                    }
+                    a
+                },
+            )
+            .unwrap();
+        assert_eq!(100_000, parallel.values().sum::<u64>());
+        assert_eq!(serial, parallel);
+
+        // Matching items are folded in position order
+        let query = DCBQuery::new().item(DCBQueryItem::new().types(["Deleted"]));
+        let positions = store
+            .scan_parallel(
+                Some(query.clone()),
+                |mut positions: Vec<u64>, event| {
+                    positions.push(event.position);
+                    positions
+                },
+                |mut a, b| {
+                    a.extend(b);
+                    a
+                },
+            )
+            .unwrap();
+        let expected: Vec<u64> = store
+            .read(Some(query), None, false, None, false)
+            .unwrap()
+            .map(|event| event.unwrap().position)
+            .collect();
+        assert_eq!(expected, positions);
+    }
+
+    #[test]
+    fn test_tag_positions() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let tagged: [&[&str]; 5] = [
+            &["order:1", "customer:1"],
+            &["order:2", "customer:1"],
+            &["order:1"],
+            &[],
+            &["customer:1", "order:3"],
+        ];
+        let events = tagged
+            .iter()
+            .map(|tags| DCBEvent::new().event_type("E").tags(tags.iter().copied()))
+            .collect();
+        store.append(events, None).unwrap();
+
+        let positions = |tag: &str, start: Option<u64>, backwards: bool| -> Vec<u64> {
+            store
+                .tag_positions(tag, start, backwards)
+                .unwrap()
+                .collect()
+        };
+        assert_eq!(vec![1, 3], positions("order:1", None, false));
+        assert_eq!(vec![2], positions("order:2", None, false));
+        assert_eq!(vec![1, 2, 5], positions("customer:1", None, false));
+        assert_eq!(vec![2, 5], positions("customer:1", Some(2), false));
+        assert_eq!(vec![2, 1], positions("customer:1", Some(4), true));
+        assert!(positions("order:4", None, false).is_empty());
+
+        // Later appends extend the index
+        store
+            .append(
+                vec![DCBEvent::new().event_type("E").tags(["order:1"])],
+                None,
+            )
+            .unwrap();
+        assert_eq!(vec![1, 3, 6], positions("order:1", None, false));
+    }
+
+    #[test]
+    fn test_count_matches_events_read() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        assert_eq!(0, store.count(None).unwrap());
+
+        // More matches than one counting batch
+        let events = (0..1000)
+            .map(|i| {
+                let tags = if i % 3 == 0 {
+                    vec!["third"]
+                } else {
+                    vec!["other"]
+                };
+                let event_type = if i % 2 == 0 { "Even" } else { "Odd" };
+                DCBEvent::new().event_type(event_type).tags(tags)
+            })
+            .collect();
+        store.append(events, None).unwrap();
+
+        let queries = [
+            None,
+            Some(DCBQuery::new().item(DCBQueryItem::new().tags(["third"]))),
+            Some(DCBQuery::new().item(DCBQueryItem::new().types(["Even"]))),
+            Some(DCBQuery::new().item(DCBQueryItem::new().types(["Odd"]).tags(["third"]))),
+            Some(
+                DCBQuery::new()
+                    .item(DCBQueryItem::new().tags(["third"]))
+                    .item(DCBQueryItem::new().tags(["other"]).types(["Even"])),
+            ),
+            Some(DCBQuery::new().item(DCBQueryItem::new().tags(["missing"]))),
+        ];
+        for query in queries {
+            let (events, _) = store
+                .read_with_head(query.clone(), None, false, None)
+                .unwrap();
+            assert_eq!(
+                events.len() as u64,
+                store.count(query.clone()).unwrap(),
+                "{query:?}"
+            );
+        }
+        assert_eq!(1000, store.count(None).unwrap());
+        assert_eq!(
+            334,
+            store
+                .count(Some(
+                    DCBQuery::new().item(DCBQueryItem::new().tags(["third"]))
+                ))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_count_reads_no_event_data() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("count.db");
+        let mvcc = Arc::new(
+            Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::default())
+                .unwrap()
+                .with_compression_level(3)
+                .unwrap(),
+        );
+        let store = UmaDB::from_arc(mvcc.clone());
+        let events = (0..10)
+            .map(|i| {
+                // Alternately compressed inline and overflowing
+                let data = if i % 2 == 0 {
+                    vec![b'x'; 1000]
+                } else {
+                    // Incompressible, from a linear congruential generator
+                    let mut state = i as u32;
+                    (0..100_000)
+                        .map(|_| {
+                            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                            (state >> 24) as u8
+                        })
+                        .collect()
+                };
+                let tag = if i < 5 { "first" } else { "second" };
+                DCBEvent::new().event_type("E").tags([tag]).data(data)
+            })
+            .collect();
+        store.append(events, None).unwrap();
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        let root_id = match crate::events_tree::event_tree_lookup_value(
+            &mvcc,
+            &HashMap::new(),
+            header.events_tree_root_id,
+            Position(2),
+        )
+        .unwrap()
+        {
+            EventValue::Overflow { root_id, .. } => root_id,
+            other => panic!("Expected Overflow, got {other:?}"),
+        };
+        drop(store);
+        drop(mvcc);
+
+        // Damage the overflow chain of the event at position 2
+        let mut bytes = std::fs::read(&db_path).unwrap();
+        bytes[root_id.0 as usize * DEFAULT_PAGE_SIZE + crate::page::PAGE_HEADER_SIZE + 10] ^= 1;
+        std::fs::write(&db_path, bytes).unwrap();
+
+        let store = UmaDB::new(&db_path).unwrap();
+        assert!(store.read_with_head(None, None, false, None).is_err());
+        let queries = [
+            (DCBQuery::new().item(DCBQueryItem::new().tags(["first"])), 5),
+            (DCBQuery::new().item(DCBQueryItem::new().types(["E"])), 10),
+            (
+                DCBQuery::new()
+                    .item(DCBQueryItem::new().tags(["FIRST"]))
+                    .case_insensitive(true),
+                5,
+            ),
+        ];
+        for (query, expected) in queries {
+            assert_eq!(
+                expected,
+                store.count(Some(query.clone())).unwrap(),
+                "{query:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_backwards() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let events = (0..100)
+            .map(|i| {
+                // Tagged by the parity of the event's position
+                let tag = if (i + 1) % 2 == 0 { "even" } else { "odd" };
+                DCBEvent::new().event_type("E").tags([tag])
+            })
+            .collect();
+        store.append(events, None).unwrap();
+
+        let positions = |query: Option<DCBQuery>, before: Option<u64>, limit: Option<u32>| {
+            store
+                .read_backwards(query, before, limit)
+                .unwrap()
+                .map(|e| e.unwrap().position)
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(
+            (91..=100).rev().collect::<Vec<_>>(),
+            positions(None, None, Some(10))
+        );
+        assert_eq!(
+            (40..50).rev().collect::<Vec<_>>(),
+            positions(None, Some(50), Some(10))
+        );
+        assert!(positions(None, Some(1), None).is_empty());
+
+        let even = DCBQuery::new().item(DCBQueryItem::new().tags(["even"]));
+        assert_eq!(
+            vec![100, 98, 96],
+            positions(Some(even.clone()), None, Some(3))
+        );
+        assert_eq!(vec![6, 4, 2], positions(Some(even), Some(8), None));
+    }
+
+    #[test]
+    fn test_read_range() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let events = (0..20).map(|_| DCBEvent::new().event_type("E")).collect();
+        store.append(events, None).unwrap();
+
+        let positions = |start: u64, end: u64| {
+            store
+                .read_range(start, end)
+                .unwrap()
+                .map(|e| e.unwrap().position)
+                .collect::<Vec<u64>>()
+        };
+        // Start is inclusive and end is exclusive
+        assert_eq!(vec![5, 6, 7], positions(5, 8));
+        assert_eq!(vec![5], positions(5, 6));
+        assert!(positions(5, 5).is_empty());
+        assert!(positions(8, 5).is_empty());
+        // Positions start at 1, and the range may extend past the head
+        assert_eq!(vec![1, 2], positions(0, 3));
+        assert_eq!(vec![19, 20], positions(19, 100));
+        assert!(positions(21, 30).is_empty());
+    }
+
+    #[test]
+    fn test_read_page() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let events = (0..250)
+            .map(|_| DCBEvent::new().event_type("E").tags(["t"]))
+            .collect();
+        store.append(events, None).unwrap();
+
+        // Page through with a token, resuming each page after the last
+        let query = DCBQuery::new().item(DCBQueryItem::new().tags(["t"]));
+        let mut token: Option<Vec<u8>> = None;
+        let mut pages: Vec<Vec<u64>> = vec![];
+        loop {
+            let page = store
+                .read_page(Some(query.clone()), token.as_deref(), 100)
+                .unwrap();
+            pages.push(page.events.iter().map(|e| e.position).collect());
+            token = page.next_token;
+            if token.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            vec![
+                (1..=100).collect::<Vec<u64>>(),
+                (101..=200).collect(),
+                (201..=250).collect()
+            ],
+            pages
+        );
+
+        // An exactly full last page has no token
+        let page = store.read_page(None, None, 250).unwrap();
+        assert_eq!(250, page.events.len());
+        assert!(page.next_token.is_none());
+
+        // Tokens are rejected for a different query, or when malformed
+        let token = store
+            .read_page(None, None, 100)
+            .unwrap()
+            .next_token
+            .unwrap();
+        match store.read_page(Some(query), Some(&token), 100) {
+            Err(DCBError::DeserializationError(msg)) => {
+                assert!(msg.contains("different query"), "{msg}")
+            }
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+        assert!(store.read_page(None, Some(&token[..5]), 100).is_err());
+        assert_eq!(
+            101,
+            store.read_page(None, Some(&token), 100).unwrap().events[0].position
+        );
+    }
+
+    #[test]
+    fn test_append_with_positions() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        store
+            .append(vec![DCBEvent::new().event_type("E")], None)
+            .unwrap();
+
+        let events = (0..5).map(|_| DCBEvent::new().event_type("E")).collect();
+        assert_eq!(
+            vec![2, 3, 4, 5, 6],
+            store.append_with_positions(events, None).unwrap()
+        );
+        assert!(
+            store
+                .append_with_positions(vec![], None)
+                .unwrap()
+                .is_empty()
+        );
+
+        // Skipped events report their existing positions
+        let store = store.with_uuid_deduplication(true);
+        let uuid = Uuid::new_v4();
+        let event = |uuid: Option<Uuid>| DCBEvent {
+            uuid,
+            ..DCBEvent::new().event_type("E")
+        };
+        assert_eq!(
+            vec![7, 8],
+            store
+                .append_with_positions(vec![event(Some(uuid)), event(None)], None)
+                .unwrap()
+        );
+        assert_eq!(
+            vec![9, 7],
+            store
+                .append_with_positions(vec![event(None), event(Some(uuid))], None)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_append_with_uuid_deduplication() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path())
+            .unwrap()
+            .with_uuid_deduplication(true);
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+        let event = |uuid: Option<Uuid>| DCBEvent {
+            uuid,
+            ..DCBEvent::new().event_type("E").tags(["t"])
+        };
+
+        assert_eq!(1, store.append(vec![event(Some(uuid1))], None).unwrap());
+
+        // Retrying returns the existing position without storing another event
+        assert_eq!(1, store.append(vec![event(Some(uuid1))], None).unwrap());
+        assert_eq!(Some(1), store.head().unwrap());
+
+        // Only new events are appended, also within one batch, and events
+        // without a UUID are always appended
+        let events = vec![event(Some(uuid1)), event(Some(uuid2)), event(Some(uuid2))];
+        assert_eq!(2, store.append(events, None).unwrap());
+        assert_eq!(
+            3,
+            store
+                .append(vec![event(None), event(Some(uuid1))], None)
+                .unwrap()
+        );
+        assert_eq!(
+            4,
+            store
+                .append(vec![event(Some(uuid1)), event(None)], None)
+                .unwrap()
+        );
+
+        let (events, head) = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .collect_with_head()
+            .unwrap();
+        assert_eq!(Some(4), head);
+        let uuids: Vec<Option<Uuid>> = events.iter().map(|e| e.event.uuid).collect();
+        assert_eq!(vec![Some(uuid1), Some(uuid2), None, None], uuids);
+
+        // UUID entries in the tags tree don't show up in tag reads
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            store
+                .tag_positions("t", None, false)
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+
+        // Without deduplication the same UUID is stored again
+        let store = UmaDB::from_arc(store.mvcc.clone());
+        assert_eq!(5, store.append(vec![event(Some(uuid1))], None).unwrap());
+    }
+
+    #[test]
+    fn test_append_rejects_oversized_events() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        store
+            .append(vec![DCBEvent::new().event_type("Before")], None)
+            .unwrap();
+
+        // Tags that can't fit a page even with the data in an overflow chain
+        let tags: Vec<String> = (0..10_000).map(|i| format!("tag-{i}")).collect();
+        let event = DCBEvent::new().event_type("Tagged").tags(tags);
+        let small = DCBEvent::new().event_type("Small");
+        let result = store.append(vec![small.clone(), event.clone()], None);
+        assert!(matches!(result, Err(DCBError::InvalidArgument(_))));
+        let result = store.append_stream(event, 10, vec![Ok(vec![0u8; 10])]);
+        assert!(matches!(result, Err(DCBError::InvalidArgument(_))));
+        // No event of the rejected item was appended, and the store is still usable
+        assert_eq!(Some(1), store.head().unwrap());
+        assert_eq!(2, store.append(vec![small.clone()], None).unwrap());
+
+        // Configured limits on the metadata and the data
+        let store = UmaDB::from_arc(store.mvcc.clone()).with_event_limits(EventLimits {
+            max_metadata_bytes: Some(10),
+            max_data_bytes: Some(100),
+        });
+        let result = store.append(vec![small.clone().tags(["long-tag"])], None);
+        assert!(matches!(result, Err(DCBError::InvalidArgument(_))));
+        let result = store.append(vec![small.clone().data(vec![0u8; 101])], None);
+        assert!(matches!(result, Err(DCBError::InvalidArgument(_))));
+        let result = store.append_stream(small.clone(), 101, vec![Ok(vec![0u8; 101])]);
+        assert!(matches!(result, Err(DCBError::InvalidArgument(_))));
+        assert_eq!(
+            3,
+            store
+                .append(vec![small.data(vec![0u8; 100])], None)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_append_stream() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path())
+            .unwrap()
+            .with_uuid_deduplication(true);
+        store
+            .append(vec![DCBEvent::new().event_type("Before")], None)
+            .unwrap();
+
+        // Chunks that don't line up with the overflow nodes
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let uuid = Uuid::new_v4();
+        let event = DCBEvent::new()
+            .event_type("Streamed")
+            .tags(["s"])
+            .uuid(uuid);
+        let chunks = data.chunks(3000).map(|chunk| Ok(chunk.to_vec()));
+        let position = store
+            .append_stream(event.clone(), data.len() as u64, chunks)
+            .unwrap();
+        assert_eq!(2, position);
+        assert_eq!(Some(2), store.head().unwrap());
+
+        let found = store.read_by_uuid(uuid).unwrap().unwrap();
+        assert_eq!(2, found.position);
+        assert_eq!("Streamed", found.event.event_type);
+        assert_eq!(vec!["s".to_string()], found.event.tags);
+        assert_eq!(data, found.event.data);
+        let tagged = store
+            .read(
+                Some(DCBQuery::new().item(DCBQueryItem::new().tags(["s"]))),
+                None,
+                false,
+                None,
+                false,
+            )
+            .unwrap()
+            .collect_with_head()
+            .unwrap()
+            .0;
+        assert_eq!(1, tagged.len());
+
+        // The UUID is already stored, so the event isn't appended again
+        let chunks = data.chunks(3000).map(|chunk| Ok(chunk.to_vec()));
+        assert_eq!(
+            2,
+            store
+                .append_stream(event, data.len() as u64, chunks)
+                .unwrap()
+        );
+
+        // Data that doesn't add up to the declared length is rejected, and the
+        // chain's pages are only written to the database by commit
+        let db_file = || std::fs::read(temp_dir.path().join(DEFAULT_DB_FILENAME)).unwrap();
+        let before = db_file();
+        let event = DCBEvent::new().event_type("Streamed");
+        for declared in [data.len() as u64 - 1, data.len() as u64 + 1] {
+            let chunks = data.chunks(3000).map(|chunk| Ok(chunk.to_vec()));
+            let result = store.append_stream(event.clone(), declared, chunks);
+            assert!(matches!(result, Err(DCBError::SerializationError(_))));
+        }
+        let chunks = vec![
+            Ok(vec![1u8]),
+            Err(DCBError::TransportError("gone".to_string())),
+        ];
+        let result = store.append_stream(event.clone(), 2, chunks);
+        assert!(matches!(result, Err(DCBError::TransportError(_))));
+        let result = store.append_stream(event.data(vec![1]), 0, Vec::new());
+        assert!(matches!(result, Err(DCBError::SerializationError(_))));
+        assert_eq!(Some(2), store.head().unwrap());
+        assert!(before == db_file());
+        // The spilled pages went with their temporary file
+        assert_eq!(1, std::fs::read_dir(temp_dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn test_read_multi_attributes_events_to_queries() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let events = vec![
+            DCBEvent::new().event_type("Opened").tags(["account-1"]),
+            DCBEvent::new().event_type("Opened").tags(["account-2"]),
+            DCBEvent::new()
+                .event_type("Transferred")
+                .tags(["account-1", "account-2"]),
+            DCBEvent::new().event_type("Closed").tags(["account-3"]),
+            DCBEvent::new().event_type("Closed").tags(["account-1"]),
+        ];
+        store.append(events, None).unwrap();
+
+        let by_tag = |tag: &str| DCBQuery::new().item(DCBQueryItem::new().tags([tag]));
+        let queries = vec![
+            by_tag("account-1"),
+            by_tag("account-2"),
+            DCBQuery::new().item(DCBQueryItem::new().types(["Transferred", "Closed"])),
+        ];
+        let matched = |after| -> Vec<(u64, Vec<usize>)> {
+            store
+                .read_multi(&queries, after)
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.event.position, e.query_indices))
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                (1, vec![0]),
+                (2, vec![1]),
+                (3, vec![0, 1, 2]),
+                (4, vec![2]),
+                (5, vec![0, 2]),
+            ],
+            matched(None)
+        );
+        assert_eq!(vec![(4, vec![2]), (5, vec![0, 2])], matched(Some(3)));
+
+        // Each event is returned once, however many queries it matches
+        let queries = vec![DCBQuery::new(), DCBQuery::new()];
+        let all = store.read_multi(&queries, None).unwrap();
+        assert_eq!(5, all.len());
+        assert!(all.iter().all(|e| e.query_indices == vec![0, 1]));
+        assert!(store.read_multi(&[], None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_case_insensitive_queries() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        store
+            .append(
+                vec![
+                    DCBEvent::new().event_type("orderplaced").tags(["Order-1"]),
+                    DCBEvent::new().event_type("OrderShipped").tags(["order-1"]),
+                    DCBEvent::new().event_type("OrderPlaced").tags(["order-2"]),
+                ],
+                None,
+            )
+            .unwrap();
+        let read_positions = |query: DCBQuery| -> Vec<u64> {
+            store
+                .read(Some(query), None, false, None, false)
+                .unwrap()
+                .map(|e| e.unwrap().position)
+                .collect()
+        };
+
+        // Tags only, which would otherwise be looked up in the tags index
+        let by_tag = DCBQuery::new().item(DCBQueryItem::new().tags(["ORDER-1"]));
+        assert!(read_positions(by_tag.clone()).is_empty());
+        assert_eq!(vec![1, 2], read_positions(by_tag.case_insensitive(true)));
+
+        let by_type = DCBQuery::new().item(DCBQueryItem::new().types(["OrderPlaced"]));
+        assert_eq!(vec![3], read_positions(by_type.clone()));
+        assert_eq!(
+            vec![1, 3],
+            read_positions(by_type.clone().case_insensitive(true))
+        );
+        assert_eq!(
+            2,
+            store.count(Some(by_type.case_insensitive(true))).unwrap()
+        );
+
+        // The stored casing is kept
+        let mut first = store.read(None, None, false, Some(1), false).unwrap();
+        let (events, _) = first.collect_with_head().unwrap();
+        assert_eq!("orderplaced", events[0].event.event_type);
+        assert_eq!(vec!["Order-1".to_string()], events[0].event.tags);
+
+        // Append conditions match the same way
+        let condition = DCBAppendCondition {
+            fail_if_events_match: DCBQuery::new()
+                .item(DCBQueryItem::new().tags(["order-2"].map(str::to_uppercase)))
+                .case_insensitive(true),
+            after: None,
+        };
+        assert!(matches!(
+            store.append(vec![DCBEvent::new().event_type("E")], Some(condition)),
+            Err(DCBError::IntegrityError(_))
+        ));
+    }
+
+    #[test]
+    fn test_in_memory_store_reads_like_a_file() {
+        let temp_dir = tempdir().unwrap();
+        let in_memory = UmaDB::from_arc(Arc::new(Mvcc::new_in_memory(DEFAULT_PAGE_SIZE).unwrap()));
+        let in_file = UmaDB::new(temp_dir.path()).unwrap();
+        for store in [&in_memory, &in_file] {
+            for i in 0..200usize {
+                // Some data is big enough for overflow pages
+                let data = if i % 20 == 0 {
+                    vec![i as u8; DEFAULT_PAGE_SIZE * 3]
+                } else {
+                    format!("data-{i}").into_bytes()
+                };
+                let event = DCBEvent::new()
+                    .event_type(format!("Type{}", i % 3))
+                    .tags([format!("tag-{}", i % 7)])
+                    .data(data);
+                store.append(vec![event], None).unwrap();
+            }
+            let condition = DCBAppendCondition {
+                fail_if_events_match: DCBQuery::new().item(DCBQueryItem::new().tags(["tag-0"])),
+                after: Some(100),
+            };
+            let result = store.append(vec![DCBEvent::new().event_type("E")], Some(condition));
+            assert!(matches!(result, Err(DCBError::IntegrityError(_))));
+        }
+
+        let read = |store: &UmaDB, query: Option<DCBQuery>, backwards: bool| {
+            store
+                .read(query, None, backwards, None, false)
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (e.position, e.event.event_type, e.event.tags, e.event.data)
+                })
+                .collect::<Vec<_>>()
+        };
+        let by_tag = DCBQuery::new().item(DCBQueryItem::new().tags(["tag-3"]));
+        let by_type = DCBQuery::new().item(DCBQueryItem::new().types(["Type1"]));
+        for (query, backwards) in [(None, false), (Some(by_tag), false), (Some(by_type), true)] {
+            let expected = read(&in_file, query.clone(), backwards);
+            assert!(!expected.is_empty());
+            assert_eq!(expected, read(&in_memory, query, backwards));
+        }
+        assert_eq!(in_file.head().unwrap(), in_memory.head().unwrap());
+        assert!(in_memory.mvcc.stats().unwrap().file_size > 0);
+    }
+
+    #[test]
+    fn test_client_and_commit_timestamps() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let client_timestamp = 1_000_000_000_000;
+        let before = commit_timestamp();
+        store
+            .append(
+                vec![
+                    DCBEvent::new()
+                        .event_type("Backdated")
+                        .client_timestamp(client_timestamp),
+                    DCBEvent::new()
+                        .event_type("LargeBackdated")
+                        .data(vec![7u8; 50_000])
+                        .client_timestamp(client_timestamp + 1),
+                    DCBEvent::new().event_type("Undated"),
+                ],
+                None,
+            )
+            .unwrap();
+        let after = commit_timestamp();
+
+        let events: Vec<DCBSequencedEvent> = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        let clients: Vec<_> = events.iter().map(|e| e.event.client_timestamp).collect();
+        assert_eq!(
+            vec![Some(client_timestamp), Some(client_timestamp + 1), None],
+            clients
+        );
+        for event in &events {
+            let committed = event.commit_timestamp.unwrap();
+            assert!((before..=after).contains(&committed));
+            assert_ne!(event.event.client_timestamp, Some(committed));
+        }
+        // The events of an append share a commit time
+        assert_eq!(events[0].commit_timestamp, events[2].commit_timestamp);
+    }
+
+    #[test]
+    fn test_events_carry_tsn_of_their_commit() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        store
+            .append(
+                vec![
+                    DCBEvent::new().event_type("A1"),
+                    DCBEvent::new().event_type("A2").data(vec![7u8; 50_000]),
+                ],
+                None,
+            )
+            .unwrap();
+        let (_, first) = store.head_with_tsn().unwrap().unwrap();
+        store
+            .append_stream(
+                DCBEvent::new().event_type("B1"),
+                3,
+                [Ok(vec![1u8, 2]), Ok(vec![3u8])],
+            )
+            .unwrap();
+        let (_, second) = store.head_with_tsn().unwrap().unwrap();
+        assert!(second > first);
+
+        let tsns: Vec<_> = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .map(|e| e.unwrap().tsn)
+            .collect();
+        assert_eq!(vec![Some(first.0), Some(first.0), Some(second.0)], tsns);
+    }
+
+    #[test]
+    fn test_read_time_range() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        // One event a second, alternating between two types
+        let mut writer = store.mvcc.writer().unwrap();
+        let events = (0..10u64).map(|i| {
+            let event_type = if i % 2 == 0 { "Even" } else { "Odd" };
+            let event = DCBEvent::new().event_type(event_type).data(vec![i as u8]);
+            (event, Some(1_000_000 + i * 1000))
+        });
+        append_with_commit_timestamps(&store.mvcc, &mut writer, events).unwrap();
+        // And one appended before commit times were recorded
+        append_with_commit_timestamps(
+            &store.mvcc,
+            &mut writer,
+            [(DCBEvent::new().event_type("Even"), None)],
+        )
+        .unwrap();
+        store.mvcc.commit(&mut writer).unwrap();
+
+        let positions = |from, to, query| -> Vec<u64> {
+            store
+                .read_time_range(from, to, query)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.position)
+                .collect()
         };
+        assert_eq!(vec![3, 4, 5, 6], positions(1_002_000, 1_006_000, None));
+        assert_eq!(vec![3, 4, 5, 6], positions(1_001_001, 1_005_001, None));
+        let even = DCBQuery::new().item(DCBQueryItem::new().types(["Even"]));
+        assert_eq!(vec![3, 5], positions(1_002_000, 1_006_000, Some(even)));
+        assert_eq!((1..=10).collect::<Vec<_>>(), positions(0, u64::MAX, None));
+        assert!(positions(1_010_000, u64::MAX, None).is_empty());
+        assert!(positions(1_005_000, 1_005_000, None).is_empty());
+
+        let events = store.read_time_range(1_009_000, 1_010_000, None).unwrap();
+        assert_eq!(vec![9u8], events[0].event.data);
+        assert_eq!(Some(1_009_000), events[0].commit_timestamp);
+    }
 
-        // Conditions combining tags and types so the tags index is used and the type filter applies after lookup
-        let q_s_and_x = DCBQuery {
-            items: vec![DCBQueryItem {
-                types: vec!["S".into()],
-                tags: vec!["x".into()],
-            }],
-        };
-        let q_b_and_y = DCBQuery {
-            items: vec![DCBQueryItem {
-                types: vec!["B".into()],
-                tags: vec!["y".into()],
-            }],
+    #[test]
+    fn test_read_as_of() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = Mvcc::new(
+            &temp_dir.path().join(DEFAULT_DB_FILENAME),
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::default(),
+        )
+        .unwrap()
+        .with_history_retention(3)
+        .unwrap();
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        let event = |event_type: &str| DCBEvent::new().event_type(event_type).tags(["t"]);
+        let types = |tsn, query| -> DCBResult<Vec<String>> {
+            Ok(store
+                .read_as_of(tsn, query)?
+                .into_iter()
+                .map(|e| e.event.event_type)
+                .collect())
         };
 
-        let items = vec![
-            // 1) Append small S@x
-            (vec![small.clone()], None),
-            // 2) Should fail because S@x exists in dirty pages (tags path + type filter)
-            (
-                vec![filler1.clone()],
-                Some(DCBAppendCondition {
-                    fail_if_events_match: q_s_and_x.clone(),
-                    after: None,
-                }),
-            ),
-            // 3) Append big B@y (overflow)
-            (vec![big.clone()], None),
-            // 4) Should fail because B@y exists in dirty pages (tags path + type filter and overflow read)
-            (
-                vec![filler2.clone()],
-                Some(DCBAppendCondition {
-                    fail_if_events_match: q_b_and_y.clone(),
-                    after: None,
-                }),
-            ),
-            // 5) Should succeed because after=Some(2) ignores positions <= 2 (small at 1, big at 2)
-            (
-                vec![final_ok.clone()],
-                Some(DCBAppendCondition {
-                    fail_if_events_match: q_b_and_y.clone(),
-                    after: Some(2),
-                }),
-            ),
-        ];
+        store.append(vec![event("A1"), event("A2")], None).unwrap();
+        let (_, first) = store.head_with_tsn().unwrap().unwrap();
+        store.append(vec![event("B1")], None).unwrap();
+        let (_, second) = store.head_with_tsn().unwrap().unwrap();
+
+        assert_eq!(vec!["A1", "A2"], types(first, None).unwrap());
+        assert_eq!(vec!["A1", "A2", "B1"], types(second, None).unwrap());
+        let tagged = DCBQuery::new().item(DCBQueryItem::new().tags(["t"]));
+        assert_eq!(vec!["A1", "A2"], types(first, Some(tagged)).unwrap());
+
+        // The snapshot outlives commits that replace its pages, until it falls out of
+        // the history
+        store.append(vec![event("C1")], None).unwrap();
+        assert_eq!(vec!["A1", "A2"], types(first, None).unwrap());
+        store.append(vec![event("D1")], None).unwrap();
+        assert!(matches!(
+            types(first, None),
+            Err(DCBError::OutOfRetention(_))
+        ));
+        assert_eq!(3, types(second, None).unwrap().len());
+    }
 
-        let results = store.append_batch(items, false).unwrap();
-        assert_eq!(results.len(), 5);
-        match &results[0] {
-            Ok(pos) => assert_eq!(*pos, 1),
-            other => panic!("unexpected for item0: {:?}", other),
-        }
-        match &results[1] {
-            Err(DCBError::IntegrityError(_)) => {}
-            other => panic!("expected IntegrityError for item1, got {:?}", other),
-        }
-        match &results[2] {
-            Ok(pos) => assert_eq!(*pos, 2),
-            other => panic!("unexpected for item2: {:?}", other),
-        }
-        match &results[3] {
-            Err(DCBError::IntegrityError(_)) => {}
-            other => panic!("expected IntegrityError for item3, got {:?}", other),
-        }
-        match &results[4] {
-            Ok(pos) => assert_eq!(*pos, 3),
-            other => panic!("unexpected for item4: {:?}", other),
-        }
+    #[test]
+    fn test_read_event_data() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let large: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        store
+            .append(
+                vec![
+                    DCBEvent::new().event_type("Small").data(vec![1, 2, 3]),
+                    DCBEvent::new().event_type("Large").data(large.clone()),
+                ],
+                None,
+            )
+            .unwrap();
 
-        // Verify committed state and order
-        let (events, head) = store.read_with_head(None, None, false, None).unwrap();
-        assert_eq!(events.len(), 3);
-        assert_eq!(events[0].event.event_type, small.event_type);
-        assert_eq!(events[1].event.event_type, big.event_type);
-        assert_eq!(events[2].event.event_type, final_ok.event_type);
-        assert_eq!(head, Some(3));
+        let chunks: Vec<Vec<u8>> = store
+            .read_event_data(1)
+            .unwrap()
+            .unwrap()
+            .collect::<DCBResult<_>>()
+            .unwrap();
+        assert_eq!(vec![vec![1, 2, 3]], chunks);
+
+        // A chunk for each page of the overflow chain
+        let chunks: Vec<Vec<u8>> = store
+            .read_event_data(2)
+            .unwrap()
+            .unwrap()
+            .collect::<DCBResult<_>>()
+            .unwrap();
+        assert_eq!(
+            large.len().div_ceil(store.mvcc.overflow_chunk_size()),
+            chunks.len()
+        );
+        assert_eq!(large, chunks.concat());
 
-        // Query by combined type+tag should return exactly one for each
-        let (small_combined, _) = store
-            .read_with_head(Some(q_s_and_x.clone()), None, false, None)
+        assert!(store.read_event_data(0).unwrap().is_none());
+        assert!(store.read_event_data(3).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_head_with_tsn_matches_the_commit() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        assert_eq!(None, store.head_with_tsn().unwrap());
+
+        for i in 1..=3u64 {
+            let mut writer = store.mvcc.writer().unwrap();
+            unconditional_append(
+                &store.mvcc,
+                &mut writer,
+                vec![DCBEvent::new().event_type("Event")],
+            )
             .unwrap();
-        assert_eq!(small_combined.len(), 1);
-        assert_eq!(small_combined[0].event.event_type, "S");
-        assert!(small_combined[0].event.tags.iter().any(|t| t == "x"));
+            let tsn = store.mvcc.commit(&mut writer).unwrap();
+            assert_eq!(Some((i, tsn)), store.head_with_tsn().unwrap());
+        }
+    }
 
-        let (big_combined, _) = store
-            .read_with_head(Some(q_b_and_y.clone()), None, false, None)
+    #[test]
+    fn test_read_by_uuid() {
+        let temp_dir = tempdir().unwrap();
+        let store = UmaDB::new(temp_dir.path()).unwrap();
+        let uuid = Uuid::new_v4();
+        let large = vec![7u8; 64 * 1024];
+        store
+            .append(vec![DCBEvent::new().event_type("Other")], None)
             .unwrap();
-        assert_eq!(big_combined.len(), 1);
-        assert_eq!(big_combined[0].event.event_type, "B");
-        assert!(big_combined[0].event.tags.iter().any(|t| t == "y"));
-        assert_eq!(big_combined[0].event.data.len(), big_data_len);
-        assert!(big_combined[0].event.data.iter().all(|&b| b == 0xCD));
+        store
+            .append(
+                vec![
+                    DCBEvent::new()
+                        .event_type("Target")
+                        .data(large.clone())
+                        .tags(["t"])
+                        .uuid(uuid),
+                ],
+                None,
+            )
+            .unwrap();
+
+        let found = store.read_by_uuid(uuid).unwrap().unwrap();
+        assert_eq!(2, found.position);
+        assert_eq!("Target", found.event.event_type);
+        assert_eq!(large, found.event.data);
+        assert_eq!(vec!["t".to_string()], found.event.tags);
+        assert_eq!(Some(uuid), found.event.uuid);
+
+        assert!(store.read_by_uuid(Uuid::new_v4()).unwrap().is_none());
     }
 
     #[test]
@@ -1666,7 +3455,10 @@ mod tests {
         let store = UmaDB::new(temp_dir.path()).unwrap();
 
         let condition1 = Some(DCBAppendCondition {
-            fail_if_events_match: DCBQuery { items: vec![] },
+            fail_if_events_match: DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             after: None,
         });
 
@@ -1675,6 +3467,7 @@ mod tests {
             data: b"data1".to_vec(),
             tags: vec!["tag1".to_string()],
             uuid: Some(Uuid::new_v4()),
+            client_timestamp: None,
         };
 
         let mut commit_position1 = store
@@ -1707,6 +3500,7 @@ mod tests {
             data: b"data2".to_vec(),
             tags: vec!["tag2".to_string()],
             uuid: Some(Uuid::new_v4()),
+            client_timestamp: None,
         };
 
         let mut commit_position2 = store.append(vec![event2.clone()], None).unwrap();
@@ -1762,7 +3556,10 @@ mod tests {
             &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(1)),
             false,
             None,
@@ -1777,7 +3574,10 @@ mod tests {
             &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             None,
             true,
             None,
@@ -1794,7 +3594,10 @@ mod tests {
             &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(last)),
             true,
             None,
@@ -1808,7 +3611,10 @@ mod tests {
             &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             Some(Position(last - 1)),
             true,
             None,
@@ -1823,7 +3629,10 @@ mod tests {
             &mvcc,
             reader.events_tree_root_id,
             reader.tags_tree_root_id,
-            DCBQuery { items: vec![] },
+            DCBQuery {
+                items: vec![],
+                case_insensitive: false,
+            },
             None,
             true,
             Some(3),
@@ -1843,6 +3652,7 @@ mod tests {
                 types: vec![],
                 tags: vec!["alpha".to_string()],
             }],
+            case_insensitive: false,
         };
 
         let fwd = read_conditional(
@@ -1901,6 +3711,7 @@ mod tests {
                 types: vec![],
                 tags: vec!["alpha".to_string(), "gamma".to_string()],
             }],
+            case_insensitive: false,
         };
 
         // Forwards baseline
@@ -2005,6 +3816,7 @@ mod tests {
                     tags: vec!["beta".to_string(), "delta".to_string()],
                 },
             ],
+            case_insensitive: false,
         };
 
         // Forwards baseline
@@ -2058,4 +3870,631 @@ mod tests {
         assert_eq!(back_lim1.len(), 1);
         assert_eq!(back_lim1[0].position, *fwd_rev.first().unwrap());
     }
+
+    #[test]
+    #[serial]
+    fn test_wal_commits_survive_losing_unsynced_database_writes() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("wal-test.db");
+        let wal_path = crate::wal::Wal::path_for(&db_path);
+        let config = WalConfig {
+            checkpoint_bytes: u64::MAX,
+            checkpoint_interval: None,
+        };
+        let read_all = |store: &UmaDB| -> Vec<(u64, String, Vec<String>)> {
+            store
+                .read(None, None, false, None, false)
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (e.position, e.event.event_type, e.event.tags)
+                })
+                .collect()
+        };
+
+        // The start of the file as it was synced when the database was created
+        let synced_len = 256 * DEFAULT_PAGE_SIZE;
+        let store = UmaDB::new_with_wal(&db_path, config).unwrap();
+        let mut synced = vec![0u8; synced_len];
+        std::fs::File::open(&db_path)
+            .unwrap()
+            .read_exact(&mut synced)
+            .unwrap();
+
+        // Commits are synced to the log, not to the database file
+        for i in 0..3 {
+            let event = DCBEvent::new()
+                .event_type("E")
+                .tags([format!("t{i}")])
+                .data(format!("data-{i}").into_bytes());
+            store.append(vec![event], None).unwrap();
+        }
+        let expected = read_all(&store);
+        assert_eq!(3, expected.len());
+        assert!(
+            !crate::wal::Wal::read_committed_pages(&wal_path)
+                .unwrap()
+                .is_empty()
+        );
+        // Dropping the store would checkpoint the log, which a killed process can't
+        std::mem::forget(store);
+
+        // Lose the writes to the database file that weren't synced, as if the
+        // process was killed and the machine lost power before a checkpoint
+        let lose_unsynced_writes = || {
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&db_path)
+                .unwrap();
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.write_all(&synced).unwrap();
+            file.sync_all().unwrap();
+        };
+        lose_unsynced_writes();
+
+        // Without the log the commits are gone
+        let wal_bytes = std::fs::read(&wal_path).unwrap();
+        std::fs::remove_file(&wal_path).unwrap();
+        {
+            let store = UmaDB::new(&db_path).unwrap();
+            assert!(read_all(&store).is_empty());
+        }
+
+        // Replaying the log on open restores them, and empties the log
+        lose_unsynced_writes();
+        std::fs::write(&wal_path, &wal_bytes).unwrap();
+        let store = UmaDB::new_with_wal(&db_path, config).unwrap();
+        assert_eq!(expected, read_all(&store));
+        assert_eq!(0, std::fs::metadata(&wal_path).unwrap().len());
+        assert_eq!(
+            vec![2],
+            store
+                .tag_positions("t1", None, false)
+                .unwrap()
+                .collect::<Vec<u64>>()
+        );
+
+        // Later commits build on the replayed ones
+        let event = DCBEvent::new().event_type("E").data(b"after".to_vec());
+        assert_eq!(4, store.append(vec![event], None).unwrap());
+        store.mvcc.checkpoint_now().unwrap();
+        assert_eq!(0, std::fs::metadata(&wal_path).unwrap().len());
+        assert_eq!(4, read_all(&store).len());
+    }
+
+    #[test]
+    #[serial]
+    fn test_wal_background_checkpoint_syncs_earlier_commits() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("wal-test.db");
+        let wal_path = crate::wal::Wal::path_for(&db_path);
+        let config = WalConfig {
+            checkpoint_bytes: u64::MAX,
+            checkpoint_interval: Some(std::time::Duration::from_millis(20)),
+        };
+        let store = UmaDB::new_with_wal(&db_path, config).unwrap();
+        for i in 0..3 {
+            let event = DCBEvent::new()
+                .event_type("E")
+                .data(format!("data-{i}").into_bytes());
+            store.append(vec![event], None).unwrap();
+        }
+
+        // The background thread empties the log once it has synced the database file
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while std::fs::metadata(&wal_path).unwrap().len() > 0 {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "log was not checkpointed"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // Dropping the store stops and joins the thread
+        drop(store);
+
+        // The database file holds the commits without the log
+        std::fs::remove_file(&wal_path).unwrap();
+        let store = UmaDB::new(&db_path).unwrap();
+        let positions: Vec<u64> = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .map(|e| e.unwrap().position)
+            .collect();
+        assert_eq!(vec![1, 2, 3], positions);
+    }
+
+    #[test]
+    #[serial]
+    fn test_subscribe_head() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("head-test.db");
+        let store = UmaDB::new(&db_path).unwrap();
+        let mut head = store.mvcc.subscribe_head();
+        assert_eq!(Position(0), *head.borrow_and_update());
+
+        store
+            .append(vec![DCBEvent::new().event_type("E"); 3], None)
+            .unwrap();
+        assert!(head.has_changed().unwrap());
+        assert_eq!(Position(3), *head.borrow_and_update());
+
+        // A failed append commits nothing
+        let condition = DCBAppendCondition {
+            fail_if_events_match: DCBQuery::new(),
+            after: None,
+        };
+        assert!(
+            store
+                .append(vec![DCBEvent::new().event_type("E")], Some(condition))
+                .is_err()
+        );
+        assert!(!head.has_changed().unwrap());
+        drop(store);
+
+        // Reopening starts from the committed head
+        let store = UmaDB::new(&db_path).unwrap();
+        assert_eq!(Position(3), *store.mvcc.subscribe_head().borrow());
+    }
+
+    #[test]
+    #[serial]
+    fn test_close_makes_commits_durable() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("close-test.db");
+        let wal_path = crate::wal::Wal::path_for(&db_path);
+        let config = WalConfig {
+            checkpoint_bytes: u64::MAX,
+            checkpoint_interval: Some(std::time::Duration::from_secs(3600)),
+        };
+        let mvcc = Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync)
+            .unwrap()
+            .with_wal(config)
+            .unwrap();
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        for i in 0..3 {
+            let event = DCBEvent::new()
+                .event_type("E")
+                .data(format!("data-{i}").into_bytes());
+            store.append(vec![event], None).unwrap();
+        }
+        assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+
+        // Joins the checkpoint thread without waiting out its interval
+        let started = std::time::Instant::now();
+        let mvcc = Arc::try_unwrap(store.mvcc).ok().unwrap();
+        mvcc.close().unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(60));
+        assert_eq!(0, std::fs::metadata(&wal_path).unwrap().len());
+
+        // The database file holds the commits without the log
+        std::fs::remove_file(&wal_path).unwrap();
+        let store = UmaDB::new(&db_path).unwrap();
+        let data: Vec<Vec<u8>> = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .map(|e| e.unwrap().event.data)
+            .collect();
+        assert_eq!(
+            vec![b"data-0".to_vec(), b"data-1".to_vec(), b"data-2".to_vec()],
+            data
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_compact_reclaims_freed_pages() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("compact-test.db");
+        let mvcc =
+            Arc::new(Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap());
+        let store = UmaDB::from_arc(mvcc.clone());
+
+        // Holding a reader stops writers reusing the pages each commit frees
+        let reader = mvcc.reader().unwrap();
+        for i in 0..300 {
+            // Every tenth event's data is big enough to use overflow pages
+            let data = if i % 10 == 0 {
+                vec![i as u8; DEFAULT_PAGE_SIZE * 2]
+            } else {
+                format!("data-{i}").into_bytes()
+            };
+            let event = DCBEvent::new()
+                .event_type("E")
+                .tags([format!("t{}", i % 3)])
+                .data(data);
+            store.append(vec![event], None).unwrap();
+        }
+        drop(reader);
+
+        let read_all = |store: &UmaDB| -> Vec<(u64, String, Vec<String>, Vec<u8>)> {
+            store
+                .read(None, None, false, None, false)
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (e.position, e.event.event_type, e.event.tags, e.event.data)
+                })
+                .collect()
+        };
+        let events_before = read_all(&store);
+        let t1_before: Vec<u64> = store.tag_positions("t1", None, false).unwrap().collect();
+        let stats_before = mvcc.stats().unwrap();
+        assert!(stats_before.free_pages > stats_before.total_pages / 2);
+        drop(store);
+
+        let mut mvcc = Arc::try_unwrap(mvcc).ok().unwrap();
+        let reclaimed = mvcc.compact().unwrap();
+        // Measured before reading, since mapping pages extends the file again
+        let file_size = std::fs::metadata(&db_path).unwrap().len();
+        assert_eq!(stats_before.file_size - file_size, reclaimed);
+        assert!(file_size * 2 < stats_before.file_size);
+        let stats_after = mvcc.stats().unwrap();
+        assert_eq!(0, stats_after.free_pages);
+        assert_eq!(stats_before.event_count, stats_after.event_count);
+        assert!(stats_after.total_pages * 2 < stats_before.total_pages);
+
+        // All live events read back, from the events tree and the tags tree
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        assert_eq!(events_before, read_all(&store));
+        let t1_after: Vec<u64> = store.tag_positions("t1", None, false).unwrap().collect();
+        assert_eq!(t1_before, t1_after);
+
+        // And appends continue from the same position, after reopening too
+        store
+            .append(vec![DCBEvent::new().event_type("E").tags(["t1"])], None)
+            .unwrap();
+        drop(store);
+        let store = UmaDB::new(&db_path).unwrap();
+        assert_eq!(Some(301), store.head().unwrap());
+        assert_eq!(events_before[..], read_all(&store)[..300]);
+    }
+
+    #[test]
+    fn test_backup_to_copies_a_snapshot_while_appending() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("backup-source.db");
+        let backup_path = temp_dir.path().join("backup.db");
+        let mvcc =
+            Arc::new(Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap());
+        let store = UmaDB::from_arc(mvcc.clone());
+        let event = |i: usize| {
+            // Every tenth event's data is big enough to use overflow pages
+            let data = if i.is_multiple_of(10) {
+                vec![i as u8; DEFAULT_PAGE_SIZE * 2]
+            } else {
+                format!("data-{i}").into_bytes()
+            };
+            DCBEvent::new()
+                .event_type("E")
+                .tags([format!("t{}", i % 3)])
+                .data(data)
+        };
+        for batch in 0..20 {
+            let events = (0..100).map(|i| event(batch * 100 + i)).collect();
+            store.append(events, None).unwrap();
+        }
+
+        // Commits carry on while the backup is written
+        let backup = {
+            let mvcc = mvcc.clone();
+            let backup_path = backup_path.clone();
+            std::thread::spawn(move || mvcc.backup_to(&backup_path))
+        };
+        for batch in 20..40 {
+            let events = (0..25)
+                .map(|i| event(2000 + (batch - 20) * 25 + i))
+                .collect();
+            store.append(events, None).unwrap();
+        }
+        let backed_up = backup.join().unwrap().unwrap();
+        assert!((2000..=2500).contains(&backed_up));
+        assert!(backed_up.is_multiple_of(25));
+        assert_eq!(Some(2500), store.head().unwrap());
+
+        // The backup opens as a database with only the snapshot's events
+        let read_all = |store: &UmaDB| -> Vec<(u64, Vec<String>, Vec<u8>)> {
+            store
+                .read(None, None, false, None, false)
+                .unwrap()
+                .map(|e| {
+                    let e = e.unwrap();
+                    (e.position, e.event.tags, e.event.data)
+                })
+                .collect()
+        };
+        let backup_size = std::fs::metadata(&backup_path).unwrap().len();
+        assert!(backup_size < mvcc.stats().unwrap().file_size);
+        let restored = UmaDB::new(&backup_path).unwrap();
+        assert_eq!(Some(backed_up), restored.head().unwrap());
+        assert_eq!(
+            read_all(&store)[..backed_up as usize],
+            read_all(&restored)[..]
+        );
+        let t1: Vec<u64> = store
+            .tag_positions("t1", None, false)
+            .unwrap()
+            .take_while(|p| *p <= backed_up)
+            .collect();
+        assert_eq!(
+            t1,
+            restored
+                .tag_positions("t1", None, false)
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+
+        // An existing file isn't overwritten
+        assert!(matches!(mvcc.backup_to(&backup_path), Err(DCBError::Io(_))));
+    }
+
+    #[test]
+    fn test_overflow_chunk_size_splits_data_into_smaller_nodes() {
+        let temp_dir = tempdir().unwrap();
+        let data: Vec<u8> = (0..20_000).map(|i| (i % 251) as u8).collect();
+        let append = |mvcc: Mvcc| {
+            let mvcc = Arc::new(mvcc);
+            let store = UmaDB::from_arc(mvcc.clone());
+            store
+                .append(
+                    vec![DCBEvent::new().event_type("Big").data(data.clone())],
+                    None,
+                )
+                .unwrap();
+            let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+            assert_eq!(data, events[0].event.data);
+            mvcc.stats().unwrap().total_pages
+        };
+
+        let default_path = temp_dir.path().join("default-chunks.db");
+        let mvcc = Mvcc::new(&default_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap();
+        assert_eq!(
+            OverflowWriter::payload_cap_for_page_size(DEFAULT_PAGE_SIZE),
+            mvcc.overflow_chunk_size()
+        );
+        let default_pages = append(mvcc);
+
+        let small_path = temp_dir.path().join("small-chunks.db");
+        let mvcc = Mvcc::new(&small_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync)
+            .unwrap()
+            .with_overflow_chunk_size(1000)
+            .unwrap();
+        assert_eq!(1000, mvcc.overflow_chunk_size());
+        let small_pages = append(mvcc);
+        assert_eq!(15, small_pages - default_pages);
+
+        // The chunk size is recorded, so the chain still reads with the default chunk size
+        let mvcc = Mvcc::new(&small_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap();
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        assert_eq!(1000, header.overflow_chunk_size);
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+        assert_eq!(data, events[0].event.data);
+
+        // A chunk may not be empty
+        let mvcc = Mvcc::new(&default_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap();
+        assert!(matches!(
+            mvcc.with_overflow_chunk_size(0),
+            Err(DCBError::InternalError(_))
+        ));
+    }
+
+    #[test]
+    fn test_overflow_chunks_of_several_pages_are_on_consecutive_pages() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("large-chunks.db");
+        let payload_cap = OverflowWriter::payload_cap_for_page_size(DEFAULT_PAGE_SIZE);
+        let chunk_size = 2 * payload_cap + 1000;
+        let mvcc = Arc::new(
+            Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync)
+                .unwrap()
+                .with_overflow_chunk_size(chunk_size)
+                .unwrap(),
+        );
+        let store = UmaDB::from_arc(mvcc.clone());
+        // Two whole chunks of three pages, and one of a page
+        let data: Vec<u8> = (0..2 * chunk_size + 10).map(|i| (i % 251) as u8).collect();
+        store
+            .append(
+                vec![DCBEvent::new().event_type("Big").data(data.clone())],
+                None,
+            )
+            .unwrap();
+        let chunks = data.chunks(3000).map(|chunk| Ok(chunk.to_vec()));
+        store
+            .append_stream(DCBEvent::new().event_type("Big"), data.len() as u64, chunks)
+            .unwrap();
+
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        for position in [Position(1), Position(2)] {
+            let mut page_id = match event_tree_lookup_value(
+                &mvcc,
+                &HashMap::new(),
+                header.events_tree_root_id,
+                position,
+            )
+            .unwrap()
+            {
+                EventValue::Overflow { root_id, .. } => root_id,
+                other => panic!("Expected Overflow, got {other:?}"),
+            };
+            let mut chain = Vec::new();
+            while page_id != PageID(0) {
+                let Node::EventOverflow(node) = mvcc.read_page(page_id).unwrap().node else {
+                    panic!("Expected EventOverflow node");
+                };
+                chain.push((page_id, node.data.len()));
+                page_id = node.next;
+            }
+            let sizes: Vec<usize> = chain.iter().map(|&(_, len)| len).collect();
+            assert_eq!(
+                vec![
+                    payload_cap,
+                    payload_cap,
+                    1000,
+                    payload_cap,
+                    payload_cap,
+                    1000,
+                    10
+                ],
+                sizes
+            );
+            for chunk in [&chain[0..3], &chain[3..6]] {
+                assert_eq!(chunk[0].0.0 + 1, chunk[1].0.0);
+                assert_eq!(chunk[1].0.0 + 1, chunk[2].0.0);
+            }
+        }
+        let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+        assert_eq!(
+            vec![data.clone(), data.clone()],
+            events.into_iter().map(|e| e.event.data).collect::<Vec<_>>()
+        );
+        drop(store);
+        drop(mvcc);
+
+        // The fewest bytes per node are recorded, so the chains still read with the
+        // default chunk size
+        let mvcc = Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync).unwrap();
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        assert_eq!(chunk_size / 3, header.overflow_chunk_size as usize);
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+        assert_eq!(2, events.len());
+        assert!(events.iter().all(|e| e.event.data == data));
+    }
+
+    #[test]
+    fn test_encrypted_database_has_no_plaintext_on_disk() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("encrypted.db");
+        let secret = b"top-secret-payload".to_vec();
+        let big_secret = secret.repeat(1000);
+        let events = vec![
+            DCBEvent::new()
+                .event_type("Secret")
+                .data(secret.clone())
+                .tags(["classified"]),
+            DCBEvent::new()
+                .event_type("BigSecret")
+                .data(big_secret.clone()),
+        ];
+        {
+            let store = UmaDB::new_with_encryption(&db_path, b"correct horse").unwrap();
+            store.append(events, None).unwrap();
+        }
+
+        let bytes = std::fs::read(&db_path).unwrap();
+        assert!(!bytes.windows(secret.len()).any(|w| w == secret));
+        assert!(!bytes.windows(10).any(|w| w == b"classified"));
+
+        // Reads return the payloads with the key
+        let read_data = |store: &UmaDB| -> Vec<Vec<u8>> {
+            let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+            events.into_iter().map(|e| e.event.data).collect()
+        };
+        let store = UmaDB::new_with_encryption(&db_path, b"correct horse").unwrap();
+        assert_eq!(vec![secret.clone(), big_secret.clone()], read_data(&store));
+        let query = DCBQuery::new().item(DCBQueryItem::new().tags(["classified"]));
+        let (tagged, _) = store
+            .read_with_head(Some(query), None, false, None)
+            .unwrap();
+        assert_eq!(1, tagged.len());
+        drop(store);
+
+        // But not with another key, or without one
+        assert!(matches!(
+            UmaDB::new_with_encryption(&db_path, b"wrong horse"),
+            Err(DCBError::EncryptionError(_))
+        ));
+        assert!(matches!(
+            UmaDB::new(&db_path),
+            Err(DCBError::EncryptionError(_))
+        ));
+
+        // A compacted database is still encrypted with the same key
+        let mut mvcc = Mvcc::new_with_encryption(
+            &db_path,
+            DEFAULT_PAGE_SIZE,
+            DurabilityMode::NoSync,
+            b"correct horse",
+        )
+        .unwrap();
+        mvcc.compact().unwrap();
+        drop(mvcc);
+        let bytes = std::fs::read(&db_path).unwrap();
+        assert!(!bytes.windows(secret.len()).any(|w| w == secret));
+        let store = UmaDB::new_with_encryption(&db_path, b"correct horse").unwrap();
+        assert_eq!(vec![secret, big_secret], read_data(&store));
+    }
+
+    #[test]
+    fn test_overflow_dedup_shares_the_chain_of_identical_data() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("dedup.db");
+        let mvcc = Arc::new(
+            Mvcc::new(&db_path, DEFAULT_PAGE_SIZE, DurabilityMode::NoSync)
+                .unwrap()
+                .with_overflow_dedup(true),
+        );
+        let store = UmaDB::from_arc(mvcc.clone());
+        let blob: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut other = blob.clone();
+        other[0] ^= 1;
+        let append = |data: &[u8]| {
+            let before = mvcc.stats().unwrap().total_pages;
+            store
+                .append(
+                    vec![DCBEvent::new().event_type("Blob").data(data.to_vec())],
+                    None,
+                )
+                .unwrap();
+            mvcc.stats().unwrap().total_pages - before
+        };
+        let chain_pages = blob
+            .len()
+            .div_ceil(OverflowWriter::payload_cap_for_page_size(DEFAULT_PAGE_SIZE))
+            as u64;
+
+        // The second copy only writes the pages of the events and tags trees
+        assert!(append(&blob) >= chain_pages);
+        assert!(append(&blob) < 10);
+        // Data with the same length but different bytes gets a chain of its own
+        assert!(append(&other) >= chain_pages);
+
+        let root_ids: Vec<PageID> = (1..=3)
+            .map(|position| {
+                let (_, header) = mvcc.get_latest_header().unwrap();
+                match crate::events_tree::event_tree_lookup_value(
+                    &mvcc,
+                    &HashMap::new(),
+                    header.events_tree_root_id,
+                    Position(position),
+                )
+                .unwrap()
+                {
+                    crate::events_tree_nodes::EventValue::Overflow { root_id, .. } => root_id,
+                    other => panic!("Expected Overflow, got {other:?}"),
+                }
+            })
+            .collect();
+        assert_eq!(root_ids[0], root_ids[1]);
+        assert_ne!(root_ids[0], root_ids[2]);
+
+        let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+        let data: Vec<Vec<u8>> = events.into_iter().map(|e| e.event.data).collect();
+        assert_eq!(vec![blob.clone(), blob.clone(), other.clone()], data);
+        assert!(mvcc.verify().unwrap().is_ok());
+
+        // Compaction keeps the chain shared
+        drop(store);
+        let mut mvcc = Arc::try_unwrap(mvcc).ok().unwrap();
+        let pages_before = mvcc.stats().unwrap().total_pages;
+        mvcc.compact().unwrap();
+        assert!(mvcc.stats().unwrap().total_pages <= pages_before);
+        assert!(mvcc.verify().unwrap().is_ok());
+        let store = UmaDB::from_arc(Arc::new(mvcc));
+        let (events, _) = store.read_with_head(None, None, false, None).unwrap();
+        let data: Vec<Vec<u8>> = events.into_iter().map(|e| e.event.data).collect();
+        assert_eq!(vec![blob.clone(), blob, other], data);
+    }
 }
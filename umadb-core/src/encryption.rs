@@ -0,0 +1,316 @@
+//! Encryption of page contents at rest
+//!
+//! Pages other than the two header pages are encrypted with AES-256-GCM, with a
+//! key derived from the provided key by PBKDF2-HMAC-SHA256. The KDF parameters are
+//! recorded in the header, so that the database can be reopened with the same key.
+//!
+//! An encrypted page has the usual page header, except that its checksum is zero,
+//! because the authentication tag detects corruption instead. The body is the
+//! nonce, then the encrypted node, then the tag:
+//!
+//! - 1 byte node type
+//! - 4 bytes zero
+//! - 4 bytes body length
+//! - 12 bytes nonce
+//! - encrypted node
+//! - 16 bytes tag
+//!
+//! The nonce is chosen at random each time a page is written. A page ID can be
+//! written more than once with the same contents, as when a commit fails and the
+//! next one reuses its page IDs, so a nonce derived from the page can't be relied
+//! on to be unique. The node type and page ID are authenticated too, so pages
+//! can't be swapped around.
+
+use crate::common::PageID;
+use crate::node::Node;
+use crate::page::{PAGE_HEADER_SIZE, Page};
+use byteorder::{ByteOrder, LittleEndian};
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::digest::{SHA256, digest};
+use ring::pbkdf2;
+use std::num::NonZeroU32;
+use umadb_dcb::{DCBError, DCBResult};
+
+/// Number of bytes encryption adds to each page, for the nonce and the tag.
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_LEN + TAG_SIZE;
+
+/// Size of serialized EncryptionParams.
+pub const ENCRYPTION_PARAMS_SIZE: usize = 1 + 4 + SALT_SIZE + 8 + TAG_SIZE;
+
+/// PBKDF2 iterations used for new databases.
+#[cfg(not(test))]
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+// Key derivation is slow in unoptimized builds
+#[cfg(test)]
+pub const DEFAULT_KDF_ITERATIONS: u32 = 1_000;
+
+const TAG_SIZE: usize = 16;
+const SALT_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+const KDF_PBKDF2_HMAC_SHA256: u8 = 1;
+// Used as the page ID of the key check, which no page has
+const KEY_CHECK_ID: PageID = PageID(u64::MAX);
+
+/// Parameters of an encrypted database, recorded in its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionParams {
+    pub kdf_iterations: u32,
+    pub kdf_salt: [u8; SALT_SIZE],
+    /// Distinguishes the key check of this file from those of copies of it.
+    pub file_id: u64,
+    /// Tag of an empty message, to check the key when the database is opened.
+    pub key_check: [u8; TAG_SIZE],
+}
+
+impl EncryptionParams {
+    /// Writes the serialized parameters into the start of `buf`.
+    pub fn serialize_into(&self, buf: &mut [u8]) {
+        buf[0] = KDF_PBKDF2_HMAC_SHA256;
+        buf[1..5].copy_from_slice(&self.kdf_iterations.to_le_bytes());
+        buf[5..21].copy_from_slice(&self.kdf_salt);
+        buf[21..29].copy_from_slice(&self.file_id.to_le_bytes());
+        buf[29..45].copy_from_slice(&self.key_check);
+    }
+
+    /// Expects a slice with ENCRYPTION_PARAMS_SIZE bytes.
+    pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        if slice.len() != ENCRYPTION_PARAMS_SIZE {
+            return Err(DCBError::DeserializationError(format!(
+                "Expected {ENCRYPTION_PARAMS_SIZE} bytes of encryption parameters, got {}",
+                slice.len()
+            )));
+        }
+        if slice[0] != KDF_PBKDF2_HMAC_SHA256 {
+            return Err(DCBError::DeserializationError(format!(
+                "Unknown key derivation function: {}",
+                slice[0]
+            )));
+        }
+        Ok(Self {
+            kdf_iterations: LittleEndian::read_u32(&slice[1..5]),
+            kdf_salt: slice[5..21].try_into().unwrap(),
+            file_id: LittleEndian::read_u64(&slice[21..29]),
+            key_check: slice[29..45].try_into().unwrap(),
+        })
+    }
+}
+
+/// Encrypts and decrypts the pages of one database file.
+#[derive(Clone)]
+pub struct PageCipher {
+    key: LessSafeKey,
+    params: EncryptionParams,
+}
+
+impl PageCipher {
+    /// Derives a key for a new database from the provided key, with a new salt.
+    pub fn create(key: &[u8]) -> DCBResult<Self> {
+        Self::with_params(
+            key,
+            EncryptionParams {
+                kdf_iterations: DEFAULT_KDF_ITERATIONS,
+                kdf_salt: rand::random(),
+                file_id: 0,
+                key_check: [0; TAG_SIZE],
+            },
+        )
+        .map(|cipher| cipher.for_new_file())
+    }
+
+    /// Derives the key of an existing database, and checks it is the key the
+    /// database was created with.
+    pub fn open(key: &[u8], params: &EncryptionParams) -> DCBResult<Self> {
+        let cipher = Self::with_params(key, params.clone())?;
+        if cipher.key_check() != params.key_check {
+            return Err(DCBError::EncryptionError(
+                "The key isn't the key the database was encrypted with".to_string(),
+            ));
+        }
+        Ok(cipher)
+    }
+
+    fn with_params(key: &[u8], params: EncryptionParams) -> DCBResult<Self> {
+        let iterations = NonZeroU32::new(params.kdf_iterations).ok_or_else(|| {
+            DCBError::EncryptionError("Key derivation needs at least one iteration".to_string())
+        })?;
+        let mut derived = [0u8; KEY_SIZE];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &params.kdf_salt,
+            key,
+            &mut derived,
+        );
+        let key = UnboundKey::new(&AES_256_GCM, &derived)
+            .map_err(|_| DCBError::EncryptionError("Invalid AES-256-GCM key".to_string()))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            params,
+        })
+    }
+
+    /// Returns a cipher with the same key for a copy of the database, with a new file ID.
+    pub fn for_new_file(&self) -> Self {
+        let mut cipher = self.clone();
+        cipher.params.file_id = rand::random();
+        cipher.params.key_check = cipher.key_check();
+        cipher
+    }
+
+    pub fn params(&self) -> &EncryptionParams {
+        &self.params
+    }
+
+    fn key_check(&self) -> [u8; TAG_SIZE] {
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(
+                self.key_check_nonce(),
+                Aad::from(b"umadb key check"),
+                &mut [],
+            )
+            .expect("an empty message can be sealed");
+        tag.as_ref().try_into().unwrap()
+    }
+
+    // Only ever seals an empty message, so repeating it reveals nothing
+    fn key_check_nonce(&self) -> Nonce {
+        let mut input = [0u8; 16];
+        input[0..8].copy_from_slice(&self.params.file_id.to_le_bytes());
+        input[8..16].copy_from_slice(&KEY_CHECK_ID.0.to_le_bytes());
+        let hash = digest(&SHA256, &input);
+        Nonce::try_assume_unique_for_key(&hash.as_ref()[..NONCE_LEN]).unwrap()
+    }
+
+    fn aad(page_id: PageID, node_type: u8) -> [u8; 9] {
+        let mut aad = [0u8; 9];
+        aad[0] = node_type;
+        aad[1..].copy_from_slice(&page_id.0.to_le_bytes());
+        aad
+    }
+
+    /// Encrypts, in place, a page serialized into `buf` by `serialize_page_into`.
+    pub fn seal_page(&self, page_id: PageID, buf: &mut [u8]) -> DCBResult<()> {
+        let node_type = buf[0];
+        let node_len = LittleEndian::read_u32(&buf[5..PAGE_HEADER_SIZE]) as usize;
+        let body_len = ENCRYPTION_OVERHEAD + node_len;
+        if PAGE_HEADER_SIZE + body_len > buf.len() {
+            return Err(DCBError::PageOverflow(
+                page_id.0,
+                node_len,
+                buf.len()
+                    .saturating_sub(PAGE_HEADER_SIZE + ENCRYPTION_OVERHEAD),
+            ));
+        }
+        let node_start = PAGE_HEADER_SIZE + NONCE_LEN;
+        buf.copy_within(PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + node_len, node_start);
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        buf[PAGE_HEADER_SIZE..node_start].copy_from_slice(&nonce);
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(Self::aad(page_id, node_type)),
+                &mut buf[node_start..node_start + node_len],
+            )
+            .map_err(|_| DCBError::EncryptionError(format!("Unable to encrypt {page_id:?}")))?;
+        buf[node_start + node_len..PAGE_HEADER_SIZE + body_len].copy_from_slice(tag.as_ref());
+        buf[1..5].fill(0);
+        buf[5..PAGE_HEADER_SIZE].copy_from_slice(&(body_len as u32).to_le_bytes());
+        Ok(())
+    }
+
+    /// Decrypts and deserializes a page sealed by `seal_page`. A page that has been
+    /// altered, or wasn't written by this cipher, fails with a checksum mismatch.
+    pub fn open_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<Page> {
+        let (node_type, node_bytes) = self.open_node_bytes(page_id, page_data)?;
+        let node = Node::deserialize(node_type, &node_bytes)?;
+        Ok(Page::new(page_id, node))
+    }
+
+    /// Decrypts a page sealed by `seal_page`, as `open_page` does, and returns its
+    /// node type and the serialized node, without deserializing the node.
+    pub fn open_node_bytes(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<(u8, Vec<u8>)> {
+        if page_data.len() < PAGE_HEADER_SIZE + ENCRYPTION_OVERHEAD {
+            return Err(DCBError::UnexpectedEndOfData(format!(
+                "encrypted page (page ID: {page_id:?})"
+            )));
+        }
+        let node_type = page_data[0];
+        let body_len = LittleEndian::read_u32(&page_data[5..PAGE_HEADER_SIZE]) as usize;
+        if body_len < ENCRYPTION_OVERHEAD || PAGE_HEADER_SIZE + body_len > page_data.len() {
+            return Err(DCBError::ChecksumMismatch { page_id: page_id.0 });
+        }
+        let node_start = PAGE_HEADER_SIZE + NONCE_LEN;
+        let nonce = Nonce::try_assume_unique_for_key(&page_data[PAGE_HEADER_SIZE..node_start])
+            .map_err(|_| DCBError::ChecksumMismatch { page_id: page_id.0 })?;
+        let mut in_out = page_data[node_start..PAGE_HEADER_SIZE + body_len].to_vec();
+        let node_len = self
+            .key
+            .open_in_place(nonce, Aad::from(Self::aad(page_id, node_type)), &mut in_out)
+            .map_err(|_| DCBError::ChecksumMismatch { page_id: page_id.0 })?
+            .len();
+        in_out.truncate(node_len);
+        Ok((node_type, in_out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events_tree_nodes::EventOverflowNode;
+    use crate::page::serialize_page_into;
+
+    #[test]
+    fn test_sealed_page_opens_and_detects_tampering() {
+        let cipher = PageCipher::create(b"secret").unwrap();
+        let node = Node::EventOverflow(EventOverflowNode {
+            next: PageID(0),
+            data: b"plaintext payload".to_vec(),
+        });
+        let mut buf = vec![0u8; 256];
+        serialize_page_into(&mut buf, &node).unwrap();
+        cipher.seal_page(PageID(7), &mut buf).unwrap();
+        assert!(!buf.windows(9).any(|w| w == b"plaintext"));
+
+        let page = cipher.open_page(PageID(7), &buf).unwrap();
+        match page.node {
+            Node::EventOverflow(node) => assert_eq!(b"plaintext payload".to_vec(), node.data),
+            other => panic!("Expected EventOverflow, got {other:?}"),
+        }
+
+        // Writing the same page again uses another nonce
+        let mut again = vec![0u8; 256];
+        serialize_page_into(&mut again, &node).unwrap();
+        cipher.seal_page(PageID(7), &mut again).unwrap();
+        let nonce = PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + NONCE_LEN;
+        assert_ne!(buf[nonce.clone()], again[nonce]);
+        assert!(cipher.open_page(PageID(7), &again).is_ok());
+
+        // At another page ID, with a flipped bit, or under another key it doesn't open
+        assert!(matches!(
+            cipher.open_page(PageID(8), &buf),
+            Err(DCBError::ChecksumMismatch { page_id: 8 })
+        ));
+        let mut tampered = buf.clone();
+        tampered[PAGE_HEADER_SIZE + NONCE_LEN] ^= 1;
+        assert!(matches!(
+            cipher.open_page(PageID(7), &tampered),
+            Err(DCBError::ChecksumMismatch { page_id: 7 })
+        ));
+        let other = PageCipher::create(b"secret").unwrap();
+        assert!(other.open_page(PageID(7), &buf).is_err());
+
+        // The parameters reopen the key, which must be the same key
+        let mut params = vec![0u8; ENCRYPTION_PARAMS_SIZE];
+        cipher.params().serialize_into(&mut params);
+        let params = EncryptionParams::from_slice(&params).unwrap();
+        assert_eq!(cipher.params(), &params);
+        let reopened = PageCipher::open(b"secret", &params).unwrap();
+        assert!(reopened.open_page(PageID(7), &buf).is_ok());
+        assert!(matches!(
+            PageCipher::open(b"wrong", &params),
+            Err(DCBError::EncryptionError(_))
+        ));
+    }
+}
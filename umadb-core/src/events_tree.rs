@@ -1,63 +1,216 @@
 use crate::common::PageID;
 use crate::common::Position;
+use crate::common::SplitStrategy;
+use crate::db::payload_to_hash;
 use crate::events_tree_nodes::{
-    EventInternalNode, EventLeafNode, EventOverflowNode, EventRecord, EventValue,
+    EventInternalNode, EventLeafNode, EventOverflowNode, EventRecord, EventTags, EventValue,
+    OverflowReader, OverflowWriter,
 };
 use crate::mvcc::{Mvcc, Writer};
-use crate::node::Node;
-use crate::page::{PAGE_HEADER_SIZE, Page};
+use crate::node::{Node, PAGE_TYPE_EVENT_INTERNAL, PAGE_TYPE_EVENT_LEAF};
+use crate::page::Page;
+use crate::tags_tree::{TagsTreeIterator, tags_tree_insert};
 use std::collections::HashMap;
 use umadb_dcb::{DCBError, DCBResult};
 
 // Helpers for storing large event data across overflow pages
 fn write_overflow_chain(mvcc: &Mvcc, writer: &mut Writer, data: &[u8]) -> DCBResult<PageID> {
-    // Maximum payload per overflow page: page_size - header - next pointer (8 bytes)
-    let payload_cap = mvcc.page_size.saturating_sub(PAGE_HEADER_SIZE + 8);
-    if payload_cap == 0 {
-        return Err(DCBError::DatabaseCorrupted(
-            "Page size too small to store overflow data".to_string(),
-        ));
+    let overflow_writer = mvcc.overflow_writer()?;
+    let (root_id, nodes) = overflow_writer.write(data, |n| writer.alloc_page_ids(n));
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "write_overflow_chain",
+        root_id = root_id.0,
+        bytes = data.len(),
+        pages = nodes.len(),
+    )
+    .entered();
+    for (page_id, node) in nodes {
+        writer.insert_dirty(Page::new(page_id, Node::EventOverflow(node)))?;
     }
-    // Split data into chunks from end to start so we can set next ids easily
-    let mut chunks: Vec<&[u8]> = Vec::new();
-    let mut i = 0;
-    while i < data.len() {
-        let end = (i + payload_cap).min(data.len());
-        chunks.push(&data[i..end]);
-        i = end;
+    Ok(root_id)
+}
+
+/// Writes event data to an overflow chain as it arrives, so that the data is never
+/// held in memory as a whole. Each node is spilled by the writer as soon as the next
+/// one is allocated, rather than kept with its dirty pages, and only written to the
+/// database when the writer is committed. Chains written this way are not shared by
+/// overflow dedup. The pages of each chunk are allocated when it starts, for the
+/// length of data it was declared to have.
+pub struct OverflowChainStream {
+    overflow_writer: OverflowWriter,
+    declared_len: u64,
+    root_id: PageID,
+    page_id: PageID,
+    // Allocated page IDs for the rest of the current chunk
+    chunk_page_ids: std::ops::Range<u64>,
+    // Data bytes the current chunk has room for
+    chunk_left: usize,
+    data: Vec<u8>,
+    data_len: u64,
+}
+
+impl OverflowChainStream {
+    pub fn new(mvcc: &Mvcc, writer: &mut Writer, declared_len: u64) -> DCBResult<Self> {
+        let overflow_writer = mvcc.overflow_writer()?;
+        let payload_cap = overflow_writer.payload_cap();
+        let mut stream = Self {
+            overflow_writer,
+            declared_len,
+            root_id: PageID(0),
+            page_id: PageID(0),
+            chunk_page_ids: 0..0,
+            chunk_left: 0,
+            data: Vec::with_capacity(payload_cap),
+            data_len: 0,
+        };
+        stream.root_id = stream.start_chunk(writer);
+        stream.page_id = stream.root_id;
+        Ok(stream)
     }
-    if chunks.is_empty() {
-        // Store an empty chunk page to indicate zero-length data
-        let page_id = writer.alloc_page_id();
-        let node = EventOverflowNode {
-            next: PageID(0),
-            data: Vec::new(),
+
+    /// Returns the number of data bytes written so far.
+    pub fn data_len(&self) -> u64 {
+        self.data_len
+    }
+
+    /// Appends a chunk of data to the chain.
+    pub fn write(&mut self, mvcc: &Mvcc, writer: &mut Writer, mut chunk: &[u8]) -> DCBResult<()> {
+        while !chunk.is_empty() {
+            let room = self
+                .chunk_left
+                .min(self.overflow_writer.payload_cap() - self.data.len());
+            if room == 0 {
+                // A chunk with room left has a page allocated for it
+                let next = match self.chunk_left {
+                    0 => self.start_chunk(writer),
+                    _ => PageID(self.chunk_page_ids.next().expect("chunk has pages left")),
+                };
+                self.write_node(mvcc, writer, next)?;
+                self.page_id = next;
+                continue;
+            }
+            let take = chunk.len().min(room);
+            self.data.extend_from_slice(&chunk[..take]);
+            self.data_len += take as u64;
+            self.chunk_left -= take;
+            chunk = &chunk[take..];
+        }
+        Ok(())
+    }
+
+    /// Writes the last node, and returns the root page ID and length of the chain.
+    pub fn finish(mut self, mvcc: &Mvcc, writer: &mut Writer) -> DCBResult<(PageID, u64)> {
+        self.write_node(mvcc, writer, PageID(0))?;
+        Ok((self.root_id, self.data_len))
+    }
+
+    // Allocates the pages of the next chunk, and returns the first. Data beyond the
+    // declared length is given a page at a time.
+    fn start_chunk(&mut self, writer: &mut Writer) -> PageID {
+        let declared_left = self.declared_len.saturating_sub(self.data_len);
+        let chunk_len = match declared_left {
+            0 => self.overflow_writer.payload_cap(),
+            left => (left.min(self.overflow_writer.chunk_size() as u64)) as usize,
         };
-        let page = Page::new(page_id, Node::EventOverflow(node));
-        writer.insert_dirty(page)?;
-        return Ok(page_id);
+        let pages = self.overflow_writer.chunk_pages(chunk_len);
+        let first = writer.alloc_page_ids(pages);
+        self.chunk_page_ids = first.0 + 1..first.0 + pages as u64;
+        self.chunk_left = chunk_len;
+        first
     }
-    let mut next_id = PageID(0);
-    for chunk in chunks.iter().rev() {
-        let page_id = writer.alloc_page_id();
+
+    fn write_node(&mut self, mvcc: &Mvcc, writer: &mut Writer, next: PageID) -> DCBResult<()> {
         let node = EventOverflowNode {
-            next: next_id,
-            data: (*chunk).to_vec(),
+            next,
+            data: std::mem::take(&mut self.data),
         };
-        let page = Page::new(page_id, Node::EventOverflow(node));
-        writer.insert_dirty(page)?;
-        next_id = page_id;
+        let page = Page::new(self.page_id, Node::EventOverflow(node));
+        writer.spill_page(mvcc, &page)?;
+        self.data.reserve(self.overflow_writer.payload_cap());
+        Ok(())
     }
-    Ok(next_id)
+}
+
+// Writes the record's data to an overflow chain and returns the value that refers to it.
+// With overflow dedup, a chain already holding the same data is shared instead, and a
+// new chain is indexed in the tags tree by the hash of its data, at this position.
+fn overflow_value(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    record: EventRecord,
+    position: Position,
+) -> DCBResult<EventValue> {
+    let root_id = if mvcc.overflow_dedup() {
+        let hash = payload_to_hash(&record.data);
+        match find_overflow_chain(mvcc, writer, hash, &record.data)? {
+            Some(root_id) => root_id,
+            None => {
+                let root_id = write_overflow_chain(mvcc, writer, &record.data)?;
+                tags_tree_insert(mvcc, writer, hash, position)?;
+                root_id
+            }
+        }
+    } else {
+        write_overflow_chain(mvcc, writer, &record.data)?
+    };
+    Ok(EventValue::Overflow {
+        event_type: record.event_type,
+        data_len: record.data.len() as u64,
+        tags: record.tags,
+        root_id,
+        uuid: record.uuid,
+        timestamp: record.timestamp,
+        client_timestamp: record.client_timestamp,
+        tsn: record.tsn,
+    })
+}
+
+// Finds an overflow chain holding the data among the events indexed by its hash
+fn find_overflow_chain(
+    mvcc: &Mvcc,
+    writer: &Writer,
+    hash: [u8; 8],
+    data: &[u8],
+) -> DCBResult<Option<PageID>> {
+    let candidates = TagsTreeIterator::new(
+        mvcc,
+        &writer.dirty,
+        writer.tags_tree_root_id,
+        hash,
+        None,
+        false,
+    );
+    for position in candidates {
+        if let EventValue::Overflow {
+            data_len, root_id, ..
+        } = event_tree_lookup_value(mvcc, &writer.dirty, writer.events_tree_root_id, position)?
+            && data_len == data.len() as u64
+            && read_overflow_chain(mvcc, &writer.dirty, root_id, data_len)? == data
+        {
+            return Ok(Some(root_id));
+        }
+    }
+    Ok(None)
 }
 
 fn read_overflow_chain(
     mvcc: &Mvcc,
     dirty: &HashMap<PageID, Page>,
-    mut page_id: PageID,
+    root_id: PageID,
+    data_len: u64,
 ) -> DCBResult<Vec<u8>> {
-    let mut out: Vec<u8> = Vec::new();
-    while page_id.0 != 0 {
+    // Chains may have been written with smaller chunks than this Mvcc writes
+    let payload_cap = mvcc.min_overflow_chunk_size;
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "read_overflow_chain",
+        root_id = root_id.0,
+        bytes = data_len,
+        pages = data_len.div_ceil(payload_cap as u64),
+    )
+    .entered();
+    OverflowReader::new(root_id, data_len, payload_cap, |page_id| {
         // Prefer the dirty (unflushed) page if present; otherwise read from disk
         let page = if let Some(p) = dirty.get(&page_id) {
             p.clone()
@@ -65,18 +218,65 @@ fn read_overflow_chain(
             mvcc.read_page(page_id)?
         };
         match page.node {
-            Node::EventOverflow(node) => {
-                out.extend_from_slice(&node.data);
-                page_id = node.next;
-            }
-            _ => {
-                return Err(DCBError::DatabaseCorrupted(
-                    "Expected EventOverflow node".to_string(),
-                ));
+            Node::EventOverflow(node) => Ok(node),
+            _ => Err(DCBError::DatabaseCorrupted(
+                "Expected EventOverflow node".to_string(),
+            )),
+        }
+    })
+    .read_to_end()
+}
+
+/// The data of an event, read a chunk at a time: a page of its overflow chain at
+/// a time, or all at once if it is stored in its leaf.
+pub struct EventDataChunks<'a> {
+    inline: Option<Vec<u8>>,
+    overflow: Option<OverflowReader<OverflowFetch<'a>>>,
+}
+
+type OverflowFetch<'a> = Box<dyn FnMut(PageID) -> DCBResult<EventOverflowNode> + Send + 'a>;
+
+impl<'a> EventDataChunks<'a> {
+    pub fn new(mvcc: &'a Mvcc, value: EventValue) -> Self {
+        match value {
+            EventValue::Inline(record) | EventValue::Compressed { record, .. } => Self {
+                inline: Some(record.data),
+                overflow: None,
+            },
+            EventValue::Overflow {
+                data_len, root_id, ..
+            } => {
+                let fetch: OverflowFetch<'a> =
+                    Box::new(move |page_id| match mvcc.read_page(page_id)?.node {
+                        Node::EventOverflow(node) => Ok(node),
+                        _ => Err(DCBError::DatabaseCorrupted(
+                            "Expected EventOverflow node".to_string(),
+                        )),
+                    });
+                let reader =
+                    OverflowReader::new(root_id, data_len, mvcc.min_overflow_chunk_size, fetch);
+                Self {
+                    inline: None,
+                    overflow: Some(reader),
+                }
             }
         }
     }
-    Ok(out)
+}
+
+impl Iterator for EventDataChunks<'_> {
+    type Item = DCBResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(data) = self.inline.take() {
+            return Some(Ok(data));
+        }
+        let result = self.overflow.as_mut()?.next_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.overflow = None;
+        }
+        result
+    }
 }
 
 fn materialize_event_value(
@@ -86,29 +286,69 @@ fn materialize_event_value(
 ) -> DCBResult<EventRecord> {
     match value {
         EventValue::Inline(rec) => Ok(rec.clone()),
+        EventValue::Compressed { record, .. } => Ok(record.clone()),
         EventValue::Overflow {
             event_type,
             data_len,
             tags,
             root_id,
             uuid,
+            timestamp,
+            client_timestamp,
+            tsn,
         } => {
-            let data = read_overflow_chain(mvcc, dirty, *root_id)?;
-            if (data.len() as u64) != *data_len {
-                return Err(DCBError::DatabaseCorrupted(
-                    "Overflow data length mismatch".to_string(),
-                ));
-            }
+            let data = read_overflow_chain(mvcc, dirty, *root_id, *data_len)?;
             Ok(EventRecord {
                 event_type: event_type.clone(),
                 data,
                 tags: tags.clone(),
                 uuid: *uuid,
+                timestamp: *timestamp,
+                client_timestamp: *client_timestamp,
+                tsn: *tsn,
             })
         }
     }
 }
 
+/// Moves the upper half of the keys of a split leaf to the front of the new leaf,
+/// which holds the key that didn't fit. Returns the new leaf and its first key, or
+/// None, leaving the split leaf untouched, if it wouldn't fit with them.
+fn move_upper_half(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    leaf_page_id: PageID,
+    new_leaf_page: &Page,
+) -> DCBResult<Option<(Position, Page)>> {
+    let Node::EventLeaf(new_leaf) = &new_leaf_page.node else {
+        return Ok(None);
+    };
+    let leaf_page = writer.get_mut_dirty(leaf_page_id)?;
+    let Node::EventLeaf(leaf) = &mut leaf_page.node else {
+        return Err(DCBError::DatabaseCorrupted(
+            "Expected EventLeaf node".to_string(),
+        ));
+    };
+    let keep = (leaf.keys().len() + new_leaf.keys().len()) / 2;
+    if keep == 0 || keep >= leaf.keys().len() {
+        return Ok(None);
+    }
+    let first_key = leaf.keys()[keep];
+    let mut keys = leaf.keys()[keep..].to_vec();
+    let mut values = leaf.values()[keep..].to_vec();
+    keys.extend_from_slice(new_leaf.keys());
+    values.extend_from_slice(new_leaf.values());
+    let moved = Page::new(
+        new_leaf_page.page_id,
+        Node::EventLeaf(EventLeafNode::new(keys, values)),
+    );
+    if !mvcc.fits(&moved) {
+        return Ok(None);
+    }
+    leaf.truncate(keep);
+    Ok(Some((first_key, moved)))
+}
+
 /// Append an event to the root event leaf page.
 ///
 /// This function obtains a mutable reference to a dirty copy of the root event
@@ -119,26 +359,80 @@ pub fn event_tree_append(
     writer: &mut Writer,
     event: EventRecord,
     position: Position,
+) -> DCBResult<()> {
+    if mvcc.verbose {
+        println!("Appending event: {position:?} {event:?}");
+    }
+    append_to_last_leaf(mvcc, writer, position, |mvcc, writer| {
+        // Compress inline data if enabled, then decide inline vs overflow based on
+        // the length of the stored data before mut-borrowing the page
+        let pending_value = match mvcc.compression_level {
+            Some(level) => EventValue::compressed(event, level)?,
+            None => EventValue::Inline(event),
+        };
+        let inline_threshold = mvcc.inline_threshold();
+        Ok(match pending_value {
+            EventValue::Inline(event) if event.data.len() > inline_threshold => {
+                overflow_value(mvcc, writer, event, position)?
+            }
+            EventValue::Compressed { record, compressed }
+                if compressed.len() > inline_threshold =>
+            {
+                overflow_value(mvcc, writer, record, position)?
+            }
+            value => value,
+        })
+    })
+}
+
+/// Append an event value that is already stored, such as an overflow value whose
+/// chain was written by an OverflowChainStream.
+pub fn event_tree_append_value(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    value: EventValue,
+    position: Position,
+) -> DCBResult<()> {
+    append_to_last_leaf(mvcc, writer, position, |_, _| Ok(value))
+}
+
+// Appends the value made by make_value, once the position is known to follow the
+// last one, to the last leaf, splitting nodes as needed
+fn append_to_last_leaf(
+    mvcc: &Mvcc,
+    writer: &mut Writer,
+    position: Position,
+    make_value: impl FnOnce(&Mvcc, &mut Writer) -> DCBResult<EventValue>,
 ) -> DCBResult<()> {
     let verbose = mvcc.verbose;
     if verbose {
-        println!("Appending event: {position:?} {event:?}");
         println!("Root is {:?}", writer.events_tree_root_id);
     }
     // Get the current root page id for the event tree
     let mut current_page_id: PageID = writer.events_tree_root_id;
 
     // Traverse the tree to find a leaf node
+    #[cfg(feature = "tracing")]
+    let descent = tracing::trace_span!(
+        "events_tree_descent",
+        position = position.0,
+        depth = tracing::field::Empty,
+    )
+    .entered();
     let mut stack: Vec<PageID> = Vec::new();
+    // The greatest position in the tree, which the new one must follow
+    let mut last_position: Option<Position> = None;
     loop {
         let current_page_ref = writer.get_page_ref(mvcc, current_page_id)?;
-        if matches!(current_page_ref.node, Node::EventLeaf(_)) {
+        if let Node::EventLeaf(leaf_node) = &current_page_ref.node {
+            last_position = leaf_node.keys().last().copied().or(last_position);
             break;
         }
         if let Node::EventInternal(internal_node) = &current_page_ref.node {
             if verbose {
                 println!("{:?} is internal node", current_page_ref.page_id);
             }
+            last_position = internal_node.keys.last().copied().or(last_position);
             stack.push(current_page_id);
             current_page_id = *internal_node
                 .child_ids
@@ -150,23 +444,24 @@ pub fn event_tree_append(
             ));
         }
     }
+    #[cfg(feature = "tracing")]
+    {
+        descent.record("depth", stack.len() + 1);
+        drop(descent);
+    }
     if verbose {
         println!("{current_page_id:?} is leaf node");
     }
+    if let Some(last_position) = last_position
+        && position <= last_position
+    {
+        return Err(DCBError::IntegrityViolation {
+            expected: format!("a position after {}", last_position.0),
+            actual: format!("position {}", position.0),
+        });
+    }
 
-    // Decide inline vs overflow based on data length before mut-borrowing the page
-    let pending_value = if event.data.len() > u16::MAX as usize {
-        let root_id = write_overflow_chain(mvcc, writer, &event.data)?;
-        EventValue::Overflow {
-            event_type: event.event_type.clone(),
-            data_len: event.data.len() as u64,
-            tags: event.tags.clone(),
-            root_id,
-            uuid: event.uuid,
-        }
-    } else {
-        EventValue::Inline(event)
-    };
+    let pending_value = make_value(mvcc, writer)?;
 
     // Make the leaf page dirty
     let dirty_page_id = { writer.get_dirty_page_id(current_page_id)? };
@@ -186,12 +481,10 @@ pub fn event_tree_append(
         let dirty_leaf_page = writer.get_mut_dirty(dirty_page_id)?;
         match &mut dirty_leaf_page.node {
             Node::EventLeaf(node) => {
-                node.keys.push(position);
-                node.values.push(pending_value);
+                node.push(position, pending_value);
 
                 // Check if the leaf needs splitting by estimating the serialized size
-                let serialized_size = dirty_leaf_page.calc_serialized_size();
-                if serialized_size > mvcc.page_size {
+                if !mvcc.fits(dirty_leaf_page) {
                     if let Node::EventLeaf(dirty_leaf_node) = &mut dirty_leaf_page.node {
                         let (last_key, last_value) = dirty_leaf_node.pop_last_key_and_value()?;
                         if verbose {
@@ -223,27 +516,13 @@ pub fn event_tree_append(
     if let Some((last_key, mut last_value)) = popped {
         // Build new leaf node; convert to overflow if needed to fit
         let new_leaf_page_id = writer.alloc_page_id();
-        let mut new_leaf_node = EventLeafNode {
-            keys: vec![last_key],
-            values: vec![last_value.clone()],
-        };
+        let mut new_leaf_node = EventLeafNode::new(vec![last_key], vec![last_value.clone()]);
         let mut new_leaf_page = Page::new(new_leaf_page_id, Node::EventLeaf(new_leaf_node.clone()));
-        let serialized_size = new_leaf_page.calc_serialized_size();
-        if serialized_size > mvcc.page_size
-            && let EventValue::Inline(rec) = last_value
+        if !mvcc.fits(&new_leaf_page)
+            && let EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } = last_value
         {
-            let root_id = write_overflow_chain(mvcc, writer, &rec.data)?;
-            last_value = EventValue::Overflow {
-                event_type: rec.event_type,
-                data_len: rec.data.len() as u64,
-                tags: rec.tags,
-                root_id,
-                uuid: rec.uuid,
-            };
-            new_leaf_node = EventLeafNode {
-                keys: vec![last_key],
-                values: vec![last_value.clone()],
-            };
+            last_value = overflow_value(mvcc, writer, rec, last_key)?;
+            new_leaf_node = EventLeafNode::new(vec![last_key], vec![last_value.clone()]);
             new_leaf_page = Page::new(new_leaf_page_id, Node::EventLeaf(new_leaf_node.clone()));
             // serialized_size = new_leaf_page.calc_serialized_size();
         }
@@ -253,6 +532,14 @@ pub fn event_tree_append(
         //         mvcc.page_size
         //     )));
         // }
+        let mut promoted_key = last_key;
+        if mvcc.split_strategy() == SplitStrategy::Middle
+            && let Some((first_key, moved)) =
+                move_upper_half(mvcc, writer, dirty_page_id, &new_leaf_page)?
+        {
+            promoted_key = first_key;
+            new_leaf_page = moved;
+        }
         if verbose {
             println!(
                 "Created new leaf {:?}: {:?}",
@@ -261,9 +548,9 @@ pub fn event_tree_append(
         }
         writer.insert_dirty(new_leaf_page)?;
         if verbose {
-            println!("Promoting {last_key:?} and {new_leaf_page_id:?}");
+            println!("Promoting {promoted_key:?} and {new_leaf_page_id:?}");
         }
-        split_info = Some((last_key, new_leaf_page_id));
+        split_info = Some((promoted_key, new_leaf_page_id));
     }
 
     // Propagate splits and replacements up the stack
@@ -318,7 +605,7 @@ pub fn event_tree_append(
 
         // Check if the internal page needs splitting
 
-        if dirty_internal_page.calc_serialized_size() > mvcc.page_size {
+        if !mvcc.fits(dirty_internal_page) {
             if let Node::EventInternal(dirty_internal_node) = &mut dirty_internal_page.node {
                 if verbose {
                     println!("Splitting internal {dirty_page_id:?}...");
@@ -331,8 +618,9 @@ pub fn event_tree_append(
                     ));
                 }
 
-                // Move the right-most key to a new node. Promote the next right-most key.
-                let (promoted_key, new_keys, new_child_ids) = dirty_internal_node.split_off()?;
+                // Move keys to a new node, as the split strategy says, and promote the key before them.
+                let (promoted_key, new_keys, new_child_ids) =
+                    dirty_internal_node.split_off(mvcc.split_strategy())?;
 
                 // Ensure old node maintain the B-tree invariant: n keys should have n+1 child pointers
                 assert_eq!(
@@ -415,38 +703,141 @@ pub fn event_tree_lookup(
     events_tree_root_id: PageID,
     position: Position,
 ) -> DCBResult<EventRecord> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("events_tree_lookup", position = position.0).entered();
+    let value = event_tree_lookup_value(mvcc, dirty, events_tree_root_id, position)?;
+    materialize_event_value(mvcc, dirty, &value)
+}
+
+/// Looks up the stored value of the event at the position, without reading its
+/// overflow chain.
+pub fn event_tree_lookup_value(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    position: Position,
+) -> DCBResult<EventValue> {
+    let child_for = |internal: &EventInternalNode| {
+        if internal.child_ids.len() != internal.keys.len() + 1 {
+            return Err(DCBError::DatabaseCorrupted(
+                "Child index out of bounds in event tree".to_string(),
+            ));
+        }
+        Ok(internal.child_for(position))
+    };
+    let not_found =
+        || DCBError::DatabaseCorrupted(format!("Event at position {position:?} not found"));
     let mut current_page_id: PageID = events_tree_root_id;
     loop {
-        // Prefer the dirty (unflushed) page if present; otherwise read from disk
-        let page = if let Some(p) = dirty.get(&current_page_id) {
-            p.clone()
-        } else {
-            mvcc.read_page(current_page_id)?
-        };
-        match &page.node {
-            Node::EventInternal(internal) => {
-                // Choose child based on upper bound of position in separator keys
-                let idx = match internal.keys.binary_search(&position) {
-                    Ok(i) => i + 1,
-                    Err(i) => i,
-                };
-                if idx >= internal.child_ids.len() {
-                    return Err(DCBError::DatabaseCorrupted(
-                        "Child index out of bounds in event tree".to_string(),
-                    ));
+        // Prefer the dirty (unflushed) page if present; otherwise read a stored
+        // leaf as a view, and convert only the value at the position
+        if let Some(page) = dirty.get(&current_page_id) {
+            match &page.node {
+                Node::EventInternal(internal) => current_page_id = child_for(internal)?,
+                Node::EventLeaf(leaf) => {
+                    return leaf.lookup(position).cloned().ok_or_else(not_found);
+                }
+                _ => {
+                    return Err(DCBError::DatabaseCorrupted(format!(
+                        "Expected EventInternal or EventLeaf node in event tree, got {}",
+                        page.node.type_name()
+                    )));
                 }
-                current_page_id = internal.child_ids[idx];
             }
-            Node::EventLeaf(leaf) => {
-                return match leaf.keys.binary_search(&position) {
-                    Ok(i) => {
-                        let rec = materialize_event_value(mvcc, dirty, &leaf.values[i])?;
-                        Ok(rec)
+        } else {
+            let found = mvcc.with_node_bytes(current_page_id, |node_type, node_bytes| {
+                match node_type {
+                    PAGE_TYPE_EVENT_INTERNAL => {
+                        current_page_id = child_for(&EventInternalNode::from_slice(node_bytes)?)?;
+                        Ok(None)
                     }
-                    Err(_) => Err(DCBError::DatabaseCorrupted(format!(
-                        "Event at position {position:?} not found",
+                    PAGE_TYPE_EVENT_LEAF => match EventLeafNode::view(node_bytes)?.lookup(position)? {
+                        Some(value) => Ok(Some(value.into_owned()?)),
+                        None => Err(not_found()),
+                    },
+                    _ => Err(DCBError::DatabaseCorrupted(format!(
+                        "Expected EventInternal or EventLeaf node in event tree, got type {node_type}"
                     ))),
-                };
+                }
+            })?;
+            if let Some(value) = found {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Calls `visit` with the position, type and tags of each event in the tree, in
+/// position order. If `positions` is given, only the events at those positions are
+/// visited, and the positions must be ascending. Stored leaves are read as views,
+/// so no event data is decompressed and no overflow chain is read.
+pub fn event_tree_visit_types_and_tags(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    events_tree_root_id: PageID,
+    positions: Option<&[Position]>,
+    visit: &mut dyn FnMut(Position, &str, EventTags<'_>),
+) -> DCBResult<()> {
+    let Some(positions) = positions else {
+        return visit_event_subtree(mvcc, dirty, events_tree_root_id, None, visit);
+    };
+    let mut i = 0;
+    while i < positions.len() {
+        // Visit the leaf holding the next position, and every position it holds
+        let start = i;
+        let mut last = None;
+        visit_event_subtree(
+            mvcc,
+            dirty,
+            events_tree_root_id,
+            Some(positions[i]),
+            &mut |position, event_type, tags| {
+                while i < positions.len() && positions[i] < position {
+                    i += 1;
+                }
+                if i < positions.len() && positions[i] == position {
+                    visit(position, event_type, tags);
+                    i += 1;
+                }
+                last = Some(position);
+            },
+        )?;
+        // Skip the positions the leaf would have held, so each leaf is read once
+        while i < positions.len() && last.is_some_and(|last| positions[i] <= last) {
+            i += 1;
+        }
+        i = i.max(start + 1);
+    }
+    Ok(())
+}
+
+// Visits the events under the page, or only those in the leaf for `target`
+fn visit_event_subtree(
+    mvcc: &Mvcc,
+    dirty: &HashMap<PageID, Page>,
+    page_id: PageID,
+    target: Option<Position>,
+    visit: &mut dyn FnMut(Position, &str, EventTags<'_>),
+) -> DCBResult<()> {
+    let children = |internal: &EventInternalNode| {
+        if internal.child_ids.len() != internal.keys.len() + 1 {
+            return Err(DCBError::DatabaseCorrupted(
+                "Child index out of bounds in event tree".to_string(),
+            ));
+        }
+        Ok(match target {
+            Some(position) => vec![internal.child_for(position)],
+            None => internal.child_ids.clone(),
+        })
+    };
+    let child_ids = if let Some(page) = dirty.get(&page_id) {
+        match &page.node {
+            Node::EventInternal(internal) => children(internal)?,
+            Node::EventLeaf(leaf) => {
+                for (position, value) in leaf.iter() {
+                    visit(position, value.event_type(), EventTags::Owned(value.tags()));
+                }
+                Vec::new()
             }
             _ => {
                 return Err(DCBError::DatabaseCorrupted(format!(
@@ -455,7 +846,29 @@ pub fn event_tree_lookup(
                 )));
             }
         }
+    } else {
+        mvcc.with_node_bytes(page_id, |node_type, node_bytes| match node_type {
+            PAGE_TYPE_EVENT_INTERNAL => children(&EventInternalNode::from_slice(node_bytes)?),
+            PAGE_TYPE_EVENT_LEAF => {
+                for item in EventLeafNode::view(node_bytes)?.iter() {
+                    let (position, value) = item?;
+                    visit(
+                        position,
+                        value.event_type(),
+                        EventTags::Stored(value.tags()),
+                    );
+                }
+                Ok(Vec::new())
+            }
+            _ => Err(DCBError::DatabaseCorrupted(format!(
+                "Expected EventInternal or EventLeaf node in event tree, got type {node_type}"
+            ))),
+        })?
+    };
+    for child_id in child_ids {
+        visit_event_subtree(mvcc, dirty, child_id, target, visit)?;
     }
+    Ok(())
 }
 
 pub struct EventIterator<'a> {
@@ -465,6 +878,7 @@ pub struct EventIterator<'a> {
     pub page_cache: HashMap<PageID, Page>,
     pub start: Option<Position>, // inclusive position, better for binary search
     pub backwards: bool,
+    pub tag_sets: Option<Vec<Vec<String>>>,
 }
 
 impl<'a> EventIterator<'a> {
@@ -483,9 +897,17 @@ impl<'a> EventIterator<'a> {
             page_cache: HashMap::new(),
             start,
             backwards,
+            tag_sets: None,
         }
     }
 
+    /// Skips the leaves in which, according to their bloom filters, no event can
+    /// have all the tags of any of the given sets.
+    pub fn with_tag_sets(mut self, tag_sets: Vec<Vec<String>>) -> Self {
+        self.tag_sets = Some(tag_sets);
+        self
+    }
+
     pub fn next_batch(&mut self, batch_size: u32) -> DCBResult<Vec<(Position, EventRecord)>> {
         let mut result: Vec<(Position, EventRecord)> = Vec::with_capacity(batch_size as usize);
         if batch_size == 0 {
@@ -505,18 +927,24 @@ impl<'a> EventIterator<'a> {
             let mut push_child: Option<(PageID, Option<usize>)> = None; // (child_id, stacked_keys_idx)
             let mut emit_event: Option<(Position, EventRecord)> = None;
 
+            if !self.dirty.contains_key(&page_id) && !self.page_cache.contains_key(&page_id) {
+                match self.read_stored_page(page_id)? {
+                    Some(page) => {
+                        self.page_cache.insert(page_id, page);
+                    }
+                    // A leaf without the tags
+                    None => continue,
+                }
+            }
+
             {
-                // Obtain the current page (from dirty, or page cache, or deserialize).
-                let page_ref: &Page = if let Some(p) = self.dirty.get(&page_id) {
-                    p
-                } else if let Some(p) = self.page_cache.get(&page_id) {
-                    p
-                } else {
-                    let page = self.mvcc.read_page(page_id)?;
-                    self.page_cache.insert(page_id, page);
-                    self.page_cache
+                // Obtain the current page (from dirty, or page cache).
+                let page_ref: &Page = match self.dirty.get(&page_id) {
+                    Some(p) => p,
+                    None => self
+                        .page_cache
                         .get(&page_id)
-                        .expect("page should be in cache")
+                        .expect("page should be in cache"),
                 };
 
                 match &page_ref.node {
@@ -529,10 +957,7 @@ impl<'a> EventIterator<'a> {
                             // println!(" - from: {:?}", self.from);
 
                             stacked_idx = match &self.start {
-                                Some(from) => match internal.keys.binary_search(from) {
-                                    Ok(i) => Some(i + 1),
-                                    Err(i) => Some(i),
-                                },
+                                Some(from) => Some(internal.child_idx_for(*from)),
                                 None => {
                                     if !self.backwards {
                                         Some(0)
@@ -578,11 +1003,12 @@ impl<'a> EventIterator<'a> {
                         if stacked_idx.is_none() {
                             // println!(" - first visit");
                             // println!(" - keys: {:?}", leaf.keys.clone());
-                            let values_len = leaf.values.len();
+                            let values_len = leaf.values().len();
+                            let ruled_out = self.rules_out(|tag| leaf.might_contain_tag(tag));
 
-                            stacked_idx = if values_len > 0 {
+                            stacked_idx = if values_len > 0 && !ruled_out {
                                 match &self.start {
-                                    Some(from) => match leaf.keys.binary_search(from) {
+                                    Some(from) => match leaf.keys().binary_search(from) {
                                         Ok(i) => Some(i),
                                         Err(i) => {
                                             if !self.backwards {
@@ -607,18 +1033,18 @@ impl<'a> EventIterator<'a> {
 
                         if let Some(values_idx) = stacked_idx {
                             // println!(" - values index: {} / {}", values_idx + 1, leaf.values.len());
-                            if values_idx < leaf.values.len() {
-                                let event_position = leaf.keys[values_idx];
+                            if values_idx < leaf.values().len() {
+                                let event_position = leaf.keys()[values_idx];
                                 let event_record = materialize_event_value(
                                     self.mvcc,
                                     self.dirty,
-                                    &leaf.values[values_idx],
+                                    &leaf.values()[values_idx],
                                 )?;
                                 // println!(" - emit event position: {:?}", event_position.clone());
                                 emit_event = Some((event_position, event_record));
 
                                 if !self.backwards {
-                                    if values_idx + 1 < leaf.values.len() {
+                                    if values_idx + 1 < leaf.values().len() {
                                         // Revisit this leaf.
                                         push_revisit = Some((page_id, Some(values_idx + 1)));
                                         // println!(" - not last value, will revisit");
@@ -642,7 +1068,7 @@ impl<'a> EventIterator<'a> {
                                 remove_page = true;
                             }
                         } else {
-                            // No leaf values.
+                            // No leaf values, or none with the tags.
                             remove_page = true;
                         }
                     }
@@ -672,11 +1098,54 @@ impl<'a> EventIterator<'a> {
         }
         Ok(result)
     }
+
+    // True if the leaf can't have an event with all the tags of any tag set
+    fn rules_out(&self, might_contain_tag: impl Fn(&str) -> bool) -> bool {
+        self.tag_sets.as_ref().is_some_and(|sets| {
+            sets.iter()
+                .all(|set| set.iter().any(|tag| !might_contain_tag(tag)))
+        })
+    }
+
+    // Reads a stored page. A leaf is read as a view, so that a leaf without the
+    // tags is skipped, returning None, before its values are decoded, and only
+    // the values from the start position are converted.
+    fn read_stored_page(&self, page_id: PageID) -> DCBResult<Option<Page>> {
+        self.mvcc.with_node_bytes(page_id, |node_type, node_bytes| {
+            if node_type != PAGE_TYPE_EVENT_LEAF {
+                return Ok(Some(Page::new(
+                    page_id,
+                    Node::deserialize(node_type, node_bytes)?,
+                )));
+            }
+            let view = EventLeafNode::view(node_bytes)?;
+            if self.rules_out(|tag| view.might_contain_tag(tag)) {
+                return Ok(None);
+            }
+            let mut keys = Vec::with_capacity(view.len());
+            let mut values = Vec::with_capacity(view.len());
+            for item in view.iter() {
+                let (key, value) = item?;
+                match self.start {
+                    Some(start) if self.backwards && key > start => break,
+                    Some(start) if !self.backwards && key < start => continue,
+                    _ => {}
+                }
+                keys.push(key);
+                values.push(value.into_owned()?);
+            }
+            Ok(Some(Page::new(
+                page_id,
+                Node::EventLeaf(EventLeafNode::new(keys, values)),
+            )))
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mvcc::DurabilityMode;
     use crate::node::Node;
     use rand::random;
     use serial_test::serial;
@@ -688,7 +1157,9 @@ mod tests {
     fn construct_db(page_size: usize) -> (tempfile::TempDir, Mvcc) {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-        let db = Mvcc::new(&db_path, page_size, VERBOSE).unwrap();
+        let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
         (temp_dir, db)
     }
 
@@ -710,6 +1181,9 @@ mod tests {
             data: vec![1, 2, 3, 4],
             tags: vec!["users".to_string(), "creation".to_string()],
             uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
         };
 
         // Call append_event
@@ -721,10 +1195,10 @@ mod tests {
         let page = writer.dirty.get(&new_root_id).unwrap();
         match &page.node {
             Node::EventLeaf(node) => {
-                assert_eq!(vec![position], node.keys);
+                assert_eq!(vec![position], node.keys());
                 assert_eq!(
                     vec![crate::events_tree_nodes::EventValue::Inline(record.clone())],
-                    node.values
+                    node.values()
                 );
             }
             _ => panic!("Expected EventLeaf node"),
@@ -738,16 +1212,64 @@ mod tests {
         let persisted_page = db.read_page(header.events_tree_root_id).unwrap();
         match &persisted_page.node {
             Node::EventLeaf(node) => {
-                assert_eq!(vec![position], node.keys);
+                assert_eq!(vec![position], node.keys());
                 assert_eq!(
                     vec![crate::events_tree_nodes::EventValue::Inline(record)],
-                    node.values
+                    node.values()
                 );
             }
             _ => panic!("Expected EventLeaf node after commit"),
         }
     }
 
+    #[test]
+    #[serial]
+    fn test_append_rejects_position_not_after_the_last() {
+        let (_temp_dir, db) = construct_db(256);
+        let record = EventRecord {
+            event_type: "Ordered".to_string(),
+            data: vec![1, 2, 3, 4],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let mut writer = db.writer().unwrap();
+        for _ in 0..100 {
+            let position = writer.issue_position();
+            event_tree_append(&db, &mut writer, record.clone(), position).unwrap();
+        }
+        db.commit(&mut writer).unwrap();
+
+        // The last position again, and an earlier one, with the root now internal
+        let mut writer = db.writer().unwrap();
+        assert!(matches!(
+            writer
+                .get_page_ref(&db, writer.events_tree_root_id)
+                .unwrap()
+                .node,
+            Node::EventInternal(_)
+        ));
+        for position in [Position(100), Position(10)] {
+            let result = event_tree_append(&db, &mut writer, record.clone(), position);
+            match result {
+                Err(DCBError::IntegrityViolation { expected, actual }) => {
+                    assert_eq!("a position after 100", expected);
+                    assert_eq!(format!("position {}", position.0), actual);
+                }
+                other => panic!("Expected IntegrityViolation, got {other:?}"),
+            }
+        }
+        assert!(writer.dirty.is_empty());
+
+        let position = writer.issue_position();
+        assert_eq!(Position(101), position);
+        event_tree_append(&db, &mut writer, record.clone(), position).unwrap();
+        let result = event_tree_append(&db, &mut writer, record, position);
+        assert!(matches!(result, Err(DCBError::IntegrityViolation { .. })));
+    }
+
     #[test]
     #[serial]
     fn test_insert_events_until_split_leaf_one_writer() {
@@ -769,6 +1291,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -777,11 +1302,8 @@ mod tests {
 
             // Check if we've split the leaf
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
-            match &root_page.node {
-                Node::EventInternal(_) => {
-                    has_split_leaf = true;
-                }
-                _ => {}
+            if let Node::EventInternal(_) = &root_page.node {
+                has_split_leaf = true;
             }
         }
 
@@ -807,12 +1329,12 @@ mod tests {
 
             // Check that the keys are properly ordered
             if i > 0 {
-                assert_eq!(root_node.keys[i - 1], child_node.keys[0]);
+                assert_eq!(root_node.keys[i - 1], child_node.keys()[0]);
             }
 
             // Check each key and value in the child
-            for (k, &key) in child_node.keys.iter().enumerate() {
-                let record = &child_node.values[k];
+            for (k, &key) in child_node.keys().iter().enumerate() {
+                let record = &child_node.values()[k];
                 let (appended_position, appended_record) = copy_inserted.remove(0);
                 assert_eq!(appended_position, key);
                 assert_eq!(appended_record, record.clone());
@@ -841,6 +1363,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -849,11 +1374,8 @@ mod tests {
 
             // Check if we've split the leaf
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
-            match &root_page.node {
-                Node::EventInternal(_) => {
-                    has_split_leaf = true;
-                }
-                _ => {}
+            if let Node::EventInternal(_) = &root_page.node {
+                has_split_leaf = true;
             }
 
             db.commit(&mut writer).unwrap();
@@ -884,12 +1406,12 @@ mod tests {
 
             // Check that the keys are properly ordered
             if i > 0 {
-                assert_eq!(root_node.keys[i - 1], child_node.keys[0]);
+                assert_eq!(root_node.keys[i - 1], child_node.keys()[0]);
             }
 
             // Check each key and value in the child
-            for (k, &key) in child_node.keys.iter().enumerate() {
-                let record = &child_node.values[k];
+            for (k, &key) in child_node.keys().iter().enumerate() {
+                let record = &child_node.values()[k];
                 let (appended_position, appended_record) = copy_inserted.remove(0);
                 assert_eq!(appended_position, key);
                 assert_eq!(appended_record, record.clone());
@@ -918,6 +1440,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -927,20 +1452,15 @@ mod tests {
             // Check if we've split an internal node
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
             match &root_page.node {
-                Node::EventInternal(root_node) => {
+                Node::EventInternal(root_node)
                     // Check if the first child is an internal node
-                    if !root_node.child_ids.is_empty() {
+                    if !root_node.child_ids.is_empty() => {
                         let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::EventInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
+                        if let Some(child_page) = writer.dirty.get(&child_id)
+                            && let Node::EventInternal(_) = &child_page.node {
+                                has_split_internal = true;
                             }
-                        }
                     }
-                }
                 _ => {}
             }
         }
@@ -975,8 +1495,8 @@ mod tests {
                 };
 
                 // Check each key and value in the child
-                for (k, &key) in grand_child_node.keys.iter().enumerate() {
-                    let record = &grand_child_node.values[k];
+                for (k, &key) in grand_child_node.keys().iter().enumerate() {
+                    let record = &grand_child_node.values()[k];
                     let (appended_position, appended_record) = copy_inserted.remove(0);
                     assert_eq!(appended_position, key);
                     assert_eq!(appended_record, record.clone());
@@ -1006,6 +1526,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -1015,20 +1538,15 @@ mod tests {
             // Check if the root is an internal node
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
             match &root_page.node {
-                Node::EventInternal(root_node) => {
+                Node::EventInternal(root_node)
                     // Check if the first child is an internal node
-                    if !root_node.child_ids.is_empty() {
+                    if !root_node.child_ids.is_empty() => {
                         let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::EventInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
+                        if let Some(child_page) = writer.dirty.get(&child_id)
+                            && let Node::EventInternal(_) = &child_page.node {
+                                has_split_internal = true;
                             }
-                        }
                     }
-                }
                 _ => {}
             }
             db.commit(&mut writer).unwrap();
@@ -1067,8 +1585,8 @@ mod tests {
                 };
 
                 // Check each key and value in the child
-                for (k, &key) in grand_child_node.keys.iter().enumerate() {
-                    let record = &grand_child_node.values[k];
+                for (k, &key) in grand_child_node.keys().iter().enumerate() {
+                    let record = &grand_child_node.values()[k];
                     let (appended_position, appended_record) = copy_inserted.remove(0);
                     // println!("Checking appended event: {appended_position:?} {appended_record:?}");
                     assert_eq!(appended_position, key);
@@ -1100,6 +1618,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -1109,20 +1630,15 @@ mod tests {
             // Check if the root is an internal node
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
             match &root_page.node {
-                Node::EventInternal(root_node) => {
+                Node::EventInternal(root_node)
                     // Check if the first child is an internal node
-                    if !root_node.child_ids.is_empty() {
+                    if !root_node.child_ids.is_empty() => {
                         let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::EventInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
+                        if let Some(child_page) = writer.dirty.get(&child_id)
+                            && let Node::EventInternal(_) = &child_page.node {
+                                has_split_internal = true;
                             }
-                        }
                     }
-                }
                 _ => {}
             }
             db.commit(&mut writer).unwrap();
@@ -1226,6 +1742,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -1235,20 +1754,15 @@ mod tests {
             // Check if the root is an internal node
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
             match &root_page.node {
-                Node::EventInternal(root_node) => {
+                Node::EventInternal(root_node)
                     // Check if the first child is an internal node
-                    if !root_node.child_ids.is_empty() {
+                    if !root_node.child_ids.is_empty() => {
                         let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::EventInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
+                        if let Some(child_page) = writer.dirty.get(&child_id)
+                            && let Node::EventInternal(_) = &child_page.node {
+                                has_split_internal = true;
                             }
-                        }
                     }
-                }
                 _ => {}
             }
             db.commit(&mut writer).unwrap();
@@ -1345,6 +1859,9 @@ mod tests {
                 data: (0..8).map(|_| random::<u8>()).collect(),
                 tags: vec!["users".to_string(), "creation".to_string()],
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             };
             appended.push((position, record.clone()));
 
@@ -1354,20 +1871,15 @@ mod tests {
             // Check if the root is an internal node
             let root_page = writer.dirty.get(&writer.events_tree_root_id).unwrap();
             match &root_page.node {
-                Node::EventInternal(root_node) => {
+                Node::EventInternal(root_node)
                     // Check if the first child is an internal node
-                    if !root_node.child_ids.is_empty() {
+                    if !root_node.child_ids.is_empty() => {
                         let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::EventInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
+                        if let Some(child_page) = writer.dirty.get(&child_id)
+                            && let Node::EventInternal(_) = &child_page.node {
+                                has_split_internal = true;
                             }
-                        }
                     }
-                }
                 _ => {}
             }
             db.commit(&mut writer).unwrap();
@@ -1443,6 +1955,102 @@ mod tests {
         }
     }
 
+    #[test]
+    #[serial]
+    fn test_read_events_with_tag_sets_skips_leaves() {
+        let (_tmp, db) = construct_db(512);
+        let mut writer = db.writer().unwrap();
+        let mut appended = Vec::new();
+        for i in 0..200 {
+            let position = writer.issue_position();
+            let record = EventRecord {
+                event_type: "E".to_string(),
+                tags: vec![if i < 190 { "early" } else { "late" }.to_string()],
+                ..Default::default()
+            };
+            appended.push((position, record.clone()));
+            event_tree_append(&db, &mut writer, record, position).unwrap();
+        }
+        db.commit(&mut writer).unwrap();
+
+        let reader = db.reader().unwrap();
+        let dirty = HashMap::new();
+        let scan = |tag_sets: Vec<Vec<String>>| {
+            let mut iter = EventIterator::new(&db, &dirty, reader.events_tree_root_id, None, false)
+                .with_tag_sets(tag_sets);
+            let mut scanned = Vec::new();
+            loop {
+                let batch = iter.next_batch(16).unwrap();
+                if batch.is_empty() {
+                    return scanned;
+                }
+                scanned.extend(batch);
+            }
+        };
+
+        // Leaves holding only early events are skipped, and every late event is read
+        let late = scan(vec![vec!["late".to_string()]]);
+        assert!(
+            late.len() < appended.len() / 2,
+            "{} events read",
+            late.len()
+        );
+        assert_eq!(
+            &appended[190..],
+            &late[late.len() - 10..],
+            "late events should all be read"
+        );
+
+        // No event has both tags, but leaves with both kinds of event are still read
+        let both = scan(vec![vec!["early".to_string(), "late".to_string()]]);
+        assert!(both.len() <= late.len());
+
+        // A leaf is read if any one of the sets may be in it
+        let either = scan(vec![vec!["absent".to_string()], vec!["early".to_string()]]);
+        assert_eq!(appended[..190], either[..190]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_read_events_converts_stored_values_from_the_start() {
+        let (_tmp, db) = construct_db(512);
+        let mut writer = db.writer().unwrap();
+        for _ in 0..10 {
+            let position = writer.issue_position();
+            let record = EventRecord::builder().event_type("E").build();
+            event_tree_append(&db, &mut writer, record, position).unwrap();
+        }
+        db.commit(&mut writer).unwrap();
+
+        let reader = db.reader().unwrap();
+        let dirty = HashMap::new();
+        for (backwards, expected) in [(false, 4..=10), (true, 1..=4)] {
+            let mut iter = EventIterator::new(
+                &db,
+                &dirty,
+                reader.events_tree_root_id,
+                Some(Position(4)),
+                backwards,
+            );
+            iter.next_batch(1).unwrap();
+            // The stored leaf holds only the values that will be visited
+            let Some(Node::EventLeaf(leaf)) = iter.page_cache.values().next().map(|p| &p.node)
+            else {
+                panic!("Expected the leaf in the iterator's cache");
+            };
+            let keys: Vec<u64> = leaf.keys().iter().map(|k| k.0).collect();
+            assert_eq!(expected.collect::<Vec<_>>(), keys);
+        }
+
+        // Lookups read the stored leaf as a view
+        let value = event_tree_lookup_value(&db, &dirty, reader.events_tree_root_id, Position(7));
+        assert_eq!("E", value.unwrap().event_type());
+        assert!(matches!(
+            event_tree_lookup_value(&db, &dirty, reader.events_tree_root_id, Position(11)),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+    }
+
     #[test]
     #[serial]
     fn test_large_event_data_exact_page_size() {
@@ -1456,6 +2064,9 @@ mod tests {
             data: data.clone(),
             tags: vec![],
             uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
         };
         event_tree_append(&db, &mut writer, event.clone(), pos).unwrap();
         db.commit(&mut writer).unwrap();
@@ -1475,7 +2086,7 @@ mod tests {
                 let leaf_id = *internal.child_ids.last().unwrap();
                 let leaf_page = db.read_page(leaf_id).unwrap();
                 match leaf_page.node {
-                    Node::EventLeaf(leaf) => match &leaf.values[0] {
+                    Node::EventLeaf(leaf) => match &leaf.values()[0] {
                         EventValue::Overflow { data_len, .. } => {
                             assert_eq!(*data_len as usize, data.len())
                         }
@@ -1484,7 +2095,7 @@ mod tests {
                     _ => panic!("Expected EventLeaf child"),
                 }
             }
-            Node::EventLeaf(leaf) => match &leaf.values[0] {
+            Node::EventLeaf(leaf) => match &leaf.values()[0] {
                 EventValue::Overflow { data_len, .. } => assert_eq!(*data_len as usize, data.len()),
                 _ => panic!("Expected Overflow for large event"),
             },
@@ -1504,6 +2115,9 @@ mod tests {
             data: data.clone(),
             tags: vec![],
             uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
         };
         event_tree_append(&db, &mut writer, event.clone(), pos).unwrap();
         db.commit(&mut writer).unwrap();
@@ -1517,7 +2131,7 @@ mod tests {
         // Ensure overflow in leaf
         let (_hdr_id, header) = db.get_latest_header().unwrap();
         let root = db.read_page(header.events_tree_root_id).unwrap();
-        let check_leaf = |leaf: &EventLeafNode| match &leaf.values[0] {
+        let check_leaf = |leaf: &EventLeafNode| match &leaf.values()[0] {
             EventValue::Overflow { data_len, .. } => assert_eq!(*data_len as usize, data.len()),
             _ => panic!("Expected Overflow for very large event"),
         };
@@ -1535,6 +2149,198 @@ mod tests {
         }
     }
 
+    #[test]
+    #[serial]
+    fn test_compressed_event_is_stored_inline() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE)
+            .with_compression_level(3)
+            .unwrap();
+        assert!(matches!(
+            Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+                .unwrap()
+                .with_compression_level(zstd::compression_level_range().end() + 1),
+            Err(DCBError::InternalError(_))
+        ));
+        let mut writer = db.writer().unwrap();
+        let pos = writer.issue_position();
+        let data = vec![0xAB; 64 * 1024];
+        let event = EventRecord {
+            event_type: "Compressible".into(),
+            data: data.clone(),
+            tags: vec!["t1".into()],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        event_tree_append(&db, &mut writer, event.clone(), pos).unwrap();
+        db.commit(&mut writer).unwrap();
+
+        // Lookup should return the decompressed payload
+        let reader = db.reader().unwrap();
+        let dirty = HashMap::new();
+        let got = event_tree_lookup(&db, &dirty, reader.events_tree_root_id, pos).unwrap();
+        assert_eq!(event, got);
+
+        // The compressed payload fits in the root leaf without overflow pages
+        let (_hdr_id, header) = db.get_latest_header().unwrap();
+        let root = db.read_page(header.events_tree_root_id).unwrap();
+        match root.node {
+            Node::EventLeaf(leaf) => match &leaf.values()[0] {
+                EventValue::Compressed { compressed, .. } => assert!(compressed.len() < 4096),
+                _ => panic!("Expected Compressed value"),
+            },
+            _ => panic!("Expected EventLeaf root"),
+        }
+    }
+
+    // Key counts of the leaves and of the internal nodes, left to right
+    fn node_occupancies(
+        db: &Mvcc,
+        page_id: PageID,
+        leaves: &mut Vec<usize>,
+        internals: &mut Vec<usize>,
+    ) {
+        match db.read_page(page_id).unwrap().node {
+            Node::EventLeaf(leaf) => leaves.push(leaf.keys().len()),
+            Node::EventInternal(internal) => {
+                internals.push(internal.keys.len());
+                for child_id in internal.child_ids {
+                    node_occupancies(db, child_id, leaves, internals);
+                }
+            }
+            _ => panic!("Expected event tree node"),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_split_strategy_decides_node_occupancy() {
+        let mut occupancies = Vec::new();
+        for strategy in [SplitStrategy::RightBiased, SplitStrategy::Middle] {
+            let temp_dir = tempdir().unwrap();
+            let db_path = temp_dir.path().join("mvcc-test.db");
+            let db = Mvcc::new(&db_path, 256, DurabilityMode::Sync)
+                .unwrap()
+                .with_verbose(VERBOSE)
+                .with_split_strategy(strategy);
+            assert_eq!(strategy, db.split_strategy());
+            let mut writer = db.writer().unwrap();
+            let event = EventRecord {
+                event_type: "Split".into(),
+                data: vec![0xAB; 8],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            };
+            for _ in 0..1000 {
+                let pos = writer.issue_position();
+                event_tree_append(&db, &mut writer, event.clone(), pos).unwrap();
+            }
+            db.commit(&mut writer).unwrap();
+
+            let reader = db.reader().unwrap();
+            let dirty = HashMap::new();
+            for pos in [1, 500, 1000] {
+                let got = event_tree_lookup(&db, &dirty, reader.events_tree_root_id, Position(pos));
+                assert_eq!(event, got.unwrap());
+            }
+            let (mut leaves, mut internals) = (Vec::new(), Vec::new());
+            node_occupancies(&db, reader.events_tree_root_id, &mut leaves, &mut internals);
+            assert_eq!(1000, leaves.iter().sum::<usize>());
+            occupancies.push((leaves, internals));
+        }
+        let (right_leaves, right_internals) = &occupancies[0];
+        let (middle_leaves, middle_internals) = &occupancies[1];
+
+        // Right-biased splits leave every leaf but the last one full
+        let full = right_leaves[0];
+        assert!(
+            right_leaves[..right_leaves.len() - 1]
+                .iter()
+                .all(|n| *n == full)
+        );
+        // Middle splits leave them half full
+        let half = full.div_ceil(2);
+        assert!(
+            middle_leaves[..middle_leaves.len() - 1]
+                .iter()
+                .all(|n| *n == half)
+        );
+        assert!(middle_leaves.len() > right_leaves.len());
+
+        // And the same for the internal nodes below the root
+        let full = right_internals[1];
+        let right_internals = &right_internals[1..right_internals.len() - 1];
+        assert!(right_internals.iter().all(|n| *n == full));
+        let middle_internals = &middle_internals[1..middle_internals.len() - 1];
+        assert!(middle_internals.iter().all(|n| *n < full));
+    }
+
+    #[test]
+    #[serial]
+    fn test_inline_threshold_decides_inline_or_overflow() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        assert_eq!(u16::MAX as usize, db.inline_threshold());
+        assert!(matches!(
+            db.with_inline_threshold(u16::MAX as usize + 1),
+            Err(DCBError::InternalError(_))
+        ));
+
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE)
+            .with_inline_threshold(100)
+            .unwrap();
+        assert_eq!(100, db.inline_threshold());
+        let mut writer = db.writer().unwrap();
+        let mut events = Vec::new();
+        for len in [100, 101] {
+            let pos = writer.issue_position();
+            let event = EventRecord {
+                event_type: "Threshold".into(),
+                data: vec![0xEF; len],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            };
+            event_tree_append(&db, &mut writer, event.clone(), pos).unwrap();
+            events.push((pos, event));
+        }
+        db.commit(&mut writer).unwrap();
+
+        let reader = db.reader().unwrap();
+        let dirty = HashMap::new();
+        for (pos, event) in &events {
+            let got = event_tree_lookup(&db, &dirty, reader.events_tree_root_id, *pos).unwrap();
+            assert_eq!(*event, got);
+        }
+        match db.read_page(reader.events_tree_root_id).unwrap().node {
+            Node::EventLeaf(leaf) => {
+                assert!(
+                    matches!(&leaf.values()[0], EventValue::Inline(rec) if rec.data.len() == 100)
+                );
+                assert!(matches!(
+                    &leaf.values()[1],
+                    EventValue::Overflow { data_len: 101, .. }
+                ));
+            }
+            _ => panic!("Expected EventLeaf root"),
+        }
+    }
+
     // #[test]
     // fn benchmark_append_and_lookup_varied_sizes() {
     //     // Benchmark-like test; prints durations for different sizes. Run with:
@@ -1,17 +1,102 @@
 use crate::common::PageID;
 use crate::common::Position;
+use crate::common::SplitStrategy;
+use crate::common::{NODE_FORMAT_VERSION, read_node_format_version, strip_node_format_version};
+use crate::page::max_node_size;
 use bitflags::bitflags;
 use byteorder::{ByteOrder, LittleEndian};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
 use umadb_dcb::DCBError;
 use umadb_dcb::DCBResult;
+use umadb_dcb::{DCBEvent, DCBSequencedEvent};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct EventRecord {
     pub event_type: String,
     pub data: Vec<u8>,
     pub tags: Vec<String>,
     pub uuid: Option<Uuid>,
+    // Commit time in milliseconds since the Unix epoch
+    pub timestamp: Option<u64>,
+    // Time of the event given by the client, in milliseconds since the Unix epoch
+    pub client_timestamp: Option<u64>,
+    // Tsn of the commit that appended the event
+    pub tsn: Option<u64>,
+}
+
+impl EventRecord {
+    /// Returns a builder for a record with no data, tags, UUID or timestamp.
+    pub fn builder() -> EventRecordBuilder {
+        EventRecordBuilder::default()
+    }
+
+    /// Converts the record into the event at `position` returned by reads.
+    pub fn into_sequenced_event(self, position: Position) -> DCBSequencedEvent {
+        DCBSequencedEvent {
+            event: DCBEvent {
+                event_type: self.event_type,
+                data: self.data,
+                tags: self.tags,
+                uuid: self.uuid,
+                client_timestamp: self.client_timestamp,
+            },
+            position: position.0,
+            commit_timestamp: self.timestamp,
+            tsn: self.tsn,
+        }
+    }
+}
+
+/// Builds an EventRecord one field at a time.
+#[derive(Debug, Clone, Default)]
+pub struct EventRecordBuilder {
+    record: EventRecord,
+}
+
+impl EventRecordBuilder {
+    pub fn event_type<S: Into<String>>(mut self, event_type: S) -> Self {
+        self.record.event_type = event_type.into();
+        self
+    }
+
+    pub fn data<D: Into<Vec<u8>>>(mut self, data: D) -> Self {
+        self.record.data = data.into();
+        self
+    }
+
+    /// Adds a tag after those already added.
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.record.tags.push(tag.into());
+        self
+    }
+
+    pub fn uuid(mut self, uuid: Uuid) -> Self {
+        self.record.uuid = Some(uuid);
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.record.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn client_timestamp(mut self, client_timestamp: u64) -> Self {
+        self.record.client_timestamp = Some(client_timestamp);
+        self
+    }
+
+    pub fn tsn(mut self, tsn: u64) -> Self {
+        self.record.tsn = Some(tsn);
+        self
+    }
+
+    pub fn build(self) -> EventRecord {
+        self.record
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -24,450 +109,1411 @@ pub enum EventValue {
         tags: Vec<String>,
         root_id: PageID,
         uuid: Option<Uuid>,
+        timestamp: Option<u64>,
+        client_timestamp: Option<u64>,
+        tsn: Option<u64>,
+    },
+    // Inline data stored zstd-compressed. The record holds the decompressed event,
+    // and the compressed payload is kept so re-serializing never recompresses.
+    Compressed {
+        record: EventRecord,
+        compressed: Vec<u8>,
+    },
+}
+
+impl EventValue {
+    pub fn event_type(&self) -> &str {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => &rec.event_type,
+            EventValue::Overflow { event_type, .. } => event_type,
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => &rec.tags,
+            EventValue::Overflow { tags, .. } => tags,
+        }
+    }
+
+    pub fn uuid(&self) -> Option<Uuid> {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => rec.uuid,
+            EventValue::Overflow { uuid, .. } => *uuid,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => rec.timestamp,
+            EventValue::Overflow { timestamp, .. } => *timestamp,
+        }
+    }
+
+    pub fn client_timestamp(&self) -> Option<u64> {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => {
+                rec.client_timestamp
+            }
+            EventValue::Overflow {
+                client_timestamp, ..
+            } => *client_timestamp,
+        }
+    }
+
+    pub fn tsn(&self) -> Option<u64> {
+        match self {
+            EventValue::Inline(rec) | EventValue::Compressed { record: rec, .. } => rec.tsn,
+            EventValue::Overflow { tsn, .. } => *tsn,
+        }
+    }
+
+    /// Compresses the record's data with zstd at the given level.
+    ///
+    /// Falls back to `EventValue::Inline` when compression doesn't make the
+    /// payload smaller or the compressed payload is too long to be stored inline.
+    pub fn compressed(record: EventRecord, level: i32) -> DCBResult<Self> {
+        let compressed = zstd::bulk::compress(&record.data, level).map_err(|err| {
+            DCBError::SerializationError(format!("Failed to compress event data: {err}"))
+        })?;
+        if compressed.len() < record.data.len() && compressed.len() <= u16::MAX as usize {
+            Ok(EventValue::Compressed { record, compressed })
+        } else {
+            Ok(EventValue::Inline(record))
+        }
+    }
+}
+
+impl PartialEq<EventValue> for EventRecord {
+    fn eq(&self, other: &EventValue) -> bool {
+        match other {
+            EventValue::Inline(rec) => self == rec,
+            EventValue::Compressed { record, .. } => self == record,
+            EventValue::Overflow {
+                event_type,
+                data_len,
+                tags,
+                ..
+            } => {
+                &self.event_type == event_type
+                    && &self.tags == tags
+                    && (self.data.len() as u64) == *data_len
+            }
+        }
+    }
+}
+
+impl PartialEq<EventRecord> for EventValue {
+    fn eq(&self, other: &EventRecord) -> bool {
+        other == self
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventValueFlags: u8 {
+        const OVERFLOW      = 0b0000_0001; // event payload in overflow node
+        const HAS_UUID      = 0b0000_0010; // event includes UUID field
+        const COMPRESSED    = 0b0000_0100; // inline payload is zstd-compressed
+        const HAS_TIMESTAMP = 0b0000_1000; // event includes commit timestamp
+        const HAS_CLIENT_TIMESTAMP = 0b0001_0000; // event includes client timestamp
+        const HAS_TSN       = 0b0010_0000; // event includes Tsn of its commit
+    }
+}
+
+/// Latest version of the EventLeafNode layout. Version 1 stores keys as 8-byte
+/// values and strings in each value. Version 2 adds a leaf-local string table
+/// referenced by u16 ids. Version 3 stores keys as varint deltas from the previous
+/// key, and an empty string table means strings are stored in each value.
+/// Version 4 adds a bloom filter over the tags after the string table.
+pub const EVENT_LEAF_FORMAT_VERSION: u8 = 4;
+
+// Smallest serialized value: flags, event type, data length and tag count
+const MIN_EVENT_VALUE_SIZE: usize = 1 + 2 + 2 + 2;
+
+#[derive(Clone)]
+pub struct EventLeafNode {
+    keys: Vec<Position>,
+    values: Vec<EventValue>,
+    // Worked out when the size is first needed, then kept up to date as keys and
+    // values are added and removed, so that an append doesn't re-read the leaf
+    encoding: OnceLock<LeafEncoding>,
+    tag_bloom: OnceLock<Vec<u8>>,
+}
+
+impl fmt::Debug for EventLeafNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLeafNode")
+            .field("keys", &self.keys)
+            .field("values", &self.values)
+            .finish()
+    }
+}
+
+impl PartialEq for EventLeafNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys && self.values == other.values
+    }
+}
+
+impl Eq for EventLeafNode {}
+
+impl EventLeafNode {
+    pub fn new(keys: Vec<Position>, values: Vec<EventValue>) -> Self {
+        EventLeafNode {
+            keys,
+            values,
+            encoding: OnceLock::new(),
+            tag_bloom: OnceLock::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[Position] {
+        &self.keys
+    }
+
+    pub fn values(&self) -> &[EventValue] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn into_parts(self) -> (Vec<Position>, Vec<EventValue>) {
+        (self.keys, self.values)
+    }
+
+    pub fn calc_serialized_size(&self) -> usize {
+        self.encoding().serialized_size()
+    }
+
+    fn encoding(&self) -> &LeafEncoding {
+        self.encoding
+            .get_or_init(|| LeafEncoding::new(&self.keys, &self.values))
+    }
+
+    /// Returns false if no value in the leaf has the tag. May return true for a
+    /// tag that is absent, with the same answer as the serialized bloom filter.
+    pub fn might_contain_tag(&self, tag: &str) -> bool {
+        tag_bloom_contains(self.tag_bloom(), tag)
+    }
+
+    fn tag_bloom(&self) -> &[u8] {
+        self.tag_bloom.get_or_init(|| {
+            let encoding = self.encoding();
+            build_tag_bloom(encoding.distinct_tags, encoding.tags())
+        })
+    }
+
+    /// Serialization into the provided buffer. Returns number of bytes written.
+    pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
+        buf[0] = EVENT_LEAF_FORMAT_VERSION;
+        let mut i = 1usize;
+        // keys_len
+        let klen = self.keys.len() as u16;
+        buf[i..i + 2].copy_from_slice(&klen.to_le_bytes());
+        i += 2;
+        // keys, as varint deltas from the previous key
+        let mut prev = 0u64;
+        for key in &self.keys {
+            i = write_varint(buf, i, key.0.wrapping_sub(prev));
+            prev = key.0;
+        }
+        // string table, left empty when strings are stored in each value
+        let table = self
+            .encoding()
+            .uses_string_table()
+            .then(|| StringTable::build(&self.values));
+        match &table {
+            Some(table) => i = table.serialize_into(buf, i),
+            None => {
+                buf[i..i + 2].copy_from_slice(&0u16.to_le_bytes());
+                i += 2;
+            }
+        }
+        // bloom filter over the tags
+        let bloom = self.tag_bloom();
+        buf[i..i + 2].copy_from_slice(&(bloom.len() as u16).to_le_bytes());
+        i += 2;
+        buf[i..i + bloom.len()].copy_from_slice(bloom);
+        i += bloom.len();
+        let write_string = |buf: &mut [u8], i: usize, s: &str| -> usize {
+            match &table {
+                Some(table) => {
+                    buf[i..i + 2].copy_from_slice(&table.id(s).to_le_bytes());
+                    i + 2
+                }
+                None => {
+                    buf[i..i + 2].copy_from_slice(&(s.len() as u16).to_le_bytes());
+                    buf[i + 2..i + 2 + s.len()].copy_from_slice(s.as_bytes());
+                    i + 2 + s.len()
+                }
+            }
+        };
+        // values
+        for value in &self.values {
+            let mut flags = EventValueFlags::empty();
+            match value {
+                EventValue::Inline(_) => {}
+                EventValue::Compressed { .. } => flags |= EventValueFlags::COMPRESSED,
+                EventValue::Overflow { .. } => flags |= EventValueFlags::OVERFLOW,
+            }
+            if value.uuid().is_some() {
+                flags |= EventValueFlags::HAS_UUID;
+            }
+            if value.timestamp().is_some() {
+                flags |= EventValueFlags::HAS_TIMESTAMP;
+            }
+            if value.client_timestamp().is_some() {
+                flags |= EventValueFlags::HAS_CLIENT_TIMESTAMP;
+            }
+            if value.tsn().is_some() {
+                flags |= EventValueFlags::HAS_TSN;
+            }
+            buf[i] = flags.bits();
+            i += 1;
+            i = write_string(buf, i, value.event_type());
+            match value {
+                EventValue::Inline(EventRecord { data, .. })
+                | EventValue::Compressed {
+                    compressed: data, ..
+                } => {
+                    let dlen = data.len() as u16;
+                    buf[i..i + 2].copy_from_slice(&dlen.to_le_bytes());
+                    i += 2;
+                    buf[i..i + data.len()].copy_from_slice(data);
+                    i += data.len();
+                }
+                EventValue::Overflow { data_len, .. } => {
+                    buf[i..i + 8].copy_from_slice(&data_len.to_le_bytes());
+                    i += 8;
+                }
+            }
+            let tags = value.tags();
+            let tlen = tags.len() as u16;
+            buf[i..i + 2].copy_from_slice(&tlen.to_le_bytes());
+            i += 2;
+            for tag in tags {
+                i = write_string(buf, i, tag);
+            }
+            if let EventValue::Overflow { root_id, .. } = value {
+                buf[i..i + 8].copy_from_slice(&root_id.0.to_le_bytes());
+                i += 8;
+            }
+            if let Some(uuid) = value.uuid() {
+                buf[i..i + 16].copy_from_slice(uuid.as_bytes());
+                i += 16;
+            }
+            if let Some(timestamp) = value.timestamp() {
+                buf[i..i + 8].copy_from_slice(&timestamp.to_le_bytes());
+                i += 8;
+            }
+            if let Some(client_timestamp) = value.client_timestamp() {
+                buf[i..i + 8].copy_from_slice(&client_timestamp.to_le_bytes());
+                i += 8;
+            }
+            if let Some(tsn) = value.tsn() {
+                buf[i..i + 8].copy_from_slice(&tsn.to_le_bytes());
+                i += 8;
+            }
+        }
+        i
+    }
+
+    /// Creates an owned EventLeafNode from a byte slice.
+    pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let view = Self::view(slice)?;
+        let mut keys = Vec::with_capacity(view.len());
+        let mut values = Vec::with_capacity(view.len());
+        for item in view.iter() {
+            let (key, value) = item?;
+            keys.push(key);
+            values.push(value.into_owned()?);
+        }
+        if keys.len() != view.len() || values.len() != keys.len() {
+            return Err(DCBError::DeserializationError(format!(
+                "EventLeafNode has {} keys but {} values",
+                view.len(),
+                values.len()
+            )));
+        }
+        // Out of order keys aren't rejected here, so that verify() can report them
+        Ok(EventLeafNode::new(keys, values))
+    }
+
+    /// Checks that the keys are strictly ascending and that there is a value for
+    /// each key, returning a DatabaseCorrupted error describing the first violation.
+    pub fn check_invariants(&self) -> DCBResult<()> {
+        if self.keys.len() != self.values.len() {
+            return Err(DCBError::DatabaseCorrupted(format!(
+                "EventLeafNode has {} keys but {} values",
+                self.keys.len(),
+                self.values.len()
+            )));
+        }
+        for pair in self.keys.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(DCBError::DatabaseCorrupted(format!(
+                    "EventLeafNode has duplicate key {:?}",
+                    pair[0]
+                )));
+            }
+            if pair[0] > pair[1] {
+                return Err(DCBError::DatabaseCorrupted(format!(
+                    "EventLeafNode keys out of order: {:?} then {:?}",
+                    pair[0], pair[1]
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a borrowed view of a serialized EventLeafNode.
+    ///
+    /// Only the keys and the string table are read up front. Values are decoded
+    /// lazily by the view's iterator, borrowing strings and data from the slice.
+    pub fn view(slice: &[u8]) -> DCBResult<EventLeafNodeRef<'_>> {
+        let (version, slice) =
+            read_node_format_version("EventLeafNode", slice, EVENT_LEAF_FORMAT_VERSION)?;
+
+        // Check if the slice has at least 2 bytes for keys_len
+        if slice.len() < 2 {
+            return Err(DCBError::DeserializationError(format!(
+                "Expected at least 2 bytes, got {}",
+                slice.len()
+            )));
+        }
+
+        // Extract the length of the keys (first 2 bytes)
+        let keys_len = LittleEndian::read_u16(&slice[0..2]) as usize;
+        let varint_keys = version >= 3;
+
+        // Reject a keys_len that can't fit in the slice before reading anything else
+        let min_key_size = if varint_keys { 1 } else { 8 };
+        let min_expected_size = 2 + keys_len * (min_key_size + MIN_EVENT_VALUE_SIZE);
+        if slice.len() < min_expected_size {
+            return Err(DCBError::DeserializationError(format!(
+                "EventLeafNode claims {} keys, which needs at least {} bytes, got {}",
+                keys_len,
+                min_expected_size,
+                slice.len()
+            )));
+        }
+
+        // Find the end of the keys
+        let keys_end = if varint_keys {
+            let mut reader = SliceReader::new(&slice[2..]);
+            for _ in 0..keys_len {
+                reader.read_varint("keys")?;
+            }
+            2 + reader.offset
+        } else {
+            2 + (keys_len * 8)
+        };
+        if slice.len() < keys_end {
+            return Err(DCBError::DeserializationError(format!(
+                "Expected at least {} bytes for keys, got {}",
+                keys_end,
+                slice.len()
+            )));
+        }
+
+        // Version 1 has no string table, and in version 3 an empty table means
+        // strings are stored in each value
+        let mut reader = SliceReader::new(&slice[keys_end..]);
+        let table = match version {
+            1 => None,
+            2 => Some(skip_string_table(&mut reader)?),
+            _ => Some(skip_string_table(&mut reader)?).filter(|&(count, _)| count > 0),
+        };
+
+        // Versions before 4 have no bloom filter
+        let tag_bloom = if version >= 4 {
+            let len = reader.read_u16("bloom filter length")? as usize;
+            Some(reader.read_bytes(len, "bloom filter")?)
+        } else {
+            None
+        };
+
+        Ok(EventLeafNodeRef {
+            keys: &slice[2..keys_end],
+            keys_len,
+            varint_keys,
+            values: &reader.slice[reader.offset..],
+            table,
+            table_entries: OnceCell::new(),
+            tag_bloom,
+        })
+    }
+
+    /// Iterates over the leaf's keys and values in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (Position, &EventValue)> {
+        debug_assert_eq!(self.keys.len(), self.values.len());
+        self.keys.iter().copied().zip(self.values.iter())
+    }
+
+    /// Finds the value stored for the given position.
+    pub fn lookup(&self, pos: Position) -> Option<&EventValue> {
+        self.lookup_idx(pos).ok().map(|idx| &self.values[idx])
+    }
+
+    /// Binary searches the sorted keys for the given position. Returns `Ok` with
+    /// the index of a matching key, or `Err` with the index where it would be inserted.
+    pub fn lookup_idx(&self, pos: Position) -> Result<usize, usize> {
+        self.keys.binary_search(&pos)
+    }
+
+    /// Appends a key, which must be greater than the keys of this leaf, and its value.
+    pub fn push(&mut self, key: Position, value: EventValue) {
+        if let Some(encoding) = self.encoding.get_mut() {
+            encoding.add(self.keys.last().copied(), key, &value);
+        }
+        self.tag_bloom.take();
+        self.keys.push(key);
+        self.values.push(value);
+    }
+
+    pub fn pop_last_key_and_value(&mut self) -> DCBResult<(Position, EventValue)> {
+        if self.keys.is_empty() || self.values.is_empty() {
+            return Err(DCBError::DatabaseCorrupted(
+                "EventLeafNode has no keys to pop".to_string(),
+            ));
+        }
+        let last_key = self.keys.pop().expect("checked above");
+        let last_value = self.values.pop().expect("checked above");
+        if let Some(encoding) = self.encoding.get_mut() {
+            encoding.remove(self.keys.last().copied(), last_key, &last_value);
+        }
+        self.tag_bloom.take();
+        Ok((last_key, last_value))
+    }
+
+    pub fn pop_first_key_and_value(&mut self) -> DCBResult<(Position, EventValue)> {
+        if self.keys.is_empty() || self.values.is_empty() {
+            return Err(DCBError::DatabaseCorrupted(
+                "EventLeafNode has no keys to pop".to_string(),
+            ));
+        }
+        let first_key = self.keys.remove(0);
+        let first_value = self.values.remove(0);
+        if let Some(encoding) = self.encoding.get_mut() {
+            encoding.remove(None, first_key, &first_value);
+            // The next key is now stored from zero rather than from the first key
+            if let Some(&next_key) = self.keys.first() {
+                encoding.keys_size -= key_size(Some(first_key), next_key);
+                encoding.keys_size += key_size(None, next_key);
+            }
+        }
+        self.tag_bloom.take();
+        Ok((first_key, first_value))
+    }
+
+    /// Removes the keys and values after the first `len`.
+    pub fn truncate(&mut self, len: usize) {
+        while self.keys.len() > len {
+            self.pop_last_key_and_value()
+                .expect("leaf has keys beyond len");
+        }
+    }
+
+    /// Appends the keys and values of the right sibling, whose keys must all be
+    /// greater than the keys of this leaf.
+    pub fn merge(&mut self, right: Self) {
+        debug_assert!(
+            match (self.keys.last(), right.keys.first()) {
+                (Some(last), Some(first)) => last < first,
+                _ => true,
+            },
+            "EventLeafNode merge requires the right sibling's keys to be greater"
+        );
+        for (key, value) in right {
+            self.push(key, value);
+        }
+    }
+}
+
+impl IntoIterator for EventLeafNode {
+    type Item = (Position, EventValue);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<Position>, std::vec::IntoIter<EventValue>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        debug_assert_eq!(self.keys.len(), self.values.len());
+        self.keys.into_iter().zip(self.values)
+    }
+}
+
+/// Borrowed view of a serialized EventLeafNode, see `EventLeafNode::view`.
+#[derive(Debug, Clone)]
+pub struct EventLeafNodeRef<'a> {
+    keys: &'a [u8],
+    keys_len: usize,
+    varint_keys: bool,
+    values: &'a [u8],
+    // Number of strings and their entries, which are only indexed once the values
+    // are iterated
+    table: Option<(usize, &'a [u8])>,
+    table_entries: OnceCell<Vec<Option<&'a str>>>,
+    tag_bloom: Option<&'a [u8]>,
+}
+
+impl<'a> EventLeafNodeRef<'a> {
+    pub fn len(&self) -> usize {
+        self.keys_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys_len == 0
+    }
+
+    /// Returns false if no value in the leaf has the tag, without decoding the
+    /// values. Leaves written before the bloom filter was added always return true.
+    pub fn might_contain_tag(&self, tag: &str) -> bool {
+        self.tag_bloom
+            .is_none_or(|bloom| tag_bloom_contains(bloom, tag))
+    }
+
+    /// Iterates over the leaf's keys and values in key order.
+    pub fn iter(&self) -> EventLeafNodeRefIter<'_> {
+        EventLeafNodeRefIter {
+            keys: SliceReader::new(self.keys),
+            remaining: self.keys_len,
+            varint_keys: self.varint_keys,
+            prev_key: 0,
+            values: SliceReader::new(self.values),
+            table: self.table.map(|(count, entries)| {
+                self.table_entries
+                    .get_or_init(|| read_string_table(count, entries))
+                    .as_slice()
+            }),
+            failed: false,
+        }
+    }
+
+    /// Finds the value stored for the given position, decoding only the values
+    /// up to it.
+    pub fn lookup(&self, pos: Position) -> DCBResult<Option<EventValueRef<'_>>> {
+        for item in self.iter() {
+            let (key, value) = item?;
+            if key >= pos {
+                return Ok((key == pos).then_some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub struct EventLeafNodeRefIter<'a> {
+    keys: SliceReader<'a>,
+    remaining: usize,
+    varint_keys: bool,
+    prev_key: u64,
+    values: SliceReader<'a>,
+    table: Option<&'a [Option<&'a str>]>,
+    failed: bool,
+}
+
+impl<'a> EventLeafNodeRefIter<'a> {
+    fn read_next(&mut self) -> DCBResult<(Position, EventValueRef<'a>)> {
+        let key = if self.varint_keys {
+            self.prev_key.wrapping_add(self.keys.read_varint("keys")?)
+        } else {
+            self.keys.read_u64("keys")?
+        };
+        self.prev_key = key;
+        let value = EventValueRef::read(&mut self.values, self.table)
+            .map_err(|err| with_context(err, &format!("event at position {key}")))?;
+        Ok((Position(key), value))
+    }
+}
+
+impl<'a> Iterator for EventLeafNodeRefIter<'a> {
+    type Item = DCBResult<(Position, EventValueRef<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let item = self.read_next();
+        // Values are variable length, so nothing after a bad value can be read
+        self.failed = item.is_err();
+        Some(item)
+    }
+}
+
+/// Borrowed counterpart of EventValue, pointing into a serialized leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventValueRef<'a> {
+    Inline {
+        event_type: &'a str,
+        data: &'a [u8],
+        tags: EventTagsRef<'a>,
+        uuid: Option<Uuid>,
+        timestamp: Option<u64>,
+        client_timestamp: Option<u64>,
+        tsn: Option<u64>,
+    },
+    // The data is still zstd-compressed, use into_owned() to decompress
+    Compressed {
+        event_type: &'a str,
+        compressed: &'a [u8],
+        tags: EventTagsRef<'a>,
+        uuid: Option<Uuid>,
+        timestamp: Option<u64>,
+        client_timestamp: Option<u64>,
+        tsn: Option<u64>,
+    },
+    Overflow {
+        event_type: &'a str,
+        data_len: u64,
+        tags: EventTagsRef<'a>,
+        root_id: PageID,
+        uuid: Option<Uuid>,
+        timestamp: Option<u64>,
+        client_timestamp: Option<u64>,
+        tsn: Option<u64>,
     },
 }
 
-impl PartialEq<EventValue> for EventRecord {
-    fn eq(&self, other: &EventValue) -> bool {
-        match other {
-            EventValue::Inline(rec) => self == rec,
-            EventValue::Overflow {
-                event_type,
-                data_len,
-                tags,
-                ..
-            } => {
-                &self.event_type == event_type
-                    && &self.tags == tags
-                    && (self.data.len() as u64) == *data_len
+impl<'a> EventValueRef<'a> {
+    pub fn event_type(&self) -> &'a str {
+        match self {
+            EventValueRef::Inline { event_type, .. }
+            | EventValueRef::Compressed { event_type, .. }
+            | EventValueRef::Overflow { event_type, .. } => event_type,
+        }
+    }
+
+    pub fn tags(&self) -> EventTagsRef<'a> {
+        match self {
+            EventValueRef::Inline { tags, .. }
+            | EventValueRef::Compressed { tags, .. }
+            | EventValueRef::Overflow { tags, .. } => *tags,
+        }
+    }
+
+    pub fn uuid(&self) -> Option<Uuid> {
+        match self {
+            EventValueRef::Inline { uuid, .. }
+            | EventValueRef::Compressed { uuid, .. }
+            | EventValueRef::Overflow { uuid, .. } => *uuid,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<u64> {
+        match self {
+            EventValueRef::Inline { timestamp, .. }
+            | EventValueRef::Compressed { timestamp, .. }
+            | EventValueRef::Overflow { timestamp, .. } => *timestamp,
+        }
+    }
+
+    pub fn client_timestamp(&self) -> Option<u64> {
+        match self {
+            EventValueRef::Inline {
+                client_timestamp, ..
+            }
+            | EventValueRef::Compressed {
+                client_timestamp, ..
+            }
+            | EventValueRef::Overflow {
+                client_timestamp, ..
+            } => *client_timestamp,
+        }
+    }
+
+    pub fn tsn(&self) -> Option<u64> {
+        match self {
+            EventValueRef::Inline { tsn, .. }
+            | EventValueRef::Compressed { tsn, .. }
+            | EventValueRef::Overflow { tsn, .. } => *tsn,
+        }
+    }
+
+    /// Copies the borrowed value into an owned EventValue, decompressing if needed.
+    pub fn into_owned(self) -> DCBResult<EventValue> {
+        Ok(match self {
+            EventValueRef::Inline {
+                event_type,
+                data,
+                tags,
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            } => EventValue::Inline(EventRecord {
+                event_type: event_type.to_string(),
+                data: data.to_vec(),
+                tags: tags.to_vec(),
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            }),
+            EventValueRef::Compressed {
+                event_type,
+                compressed,
+                tags,
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            } => EventValue::Compressed {
+                record: EventRecord {
+                    event_type: event_type.to_string(),
+                    data: decompress_event_data(compressed)?,
+                    tags: tags.to_vec(),
+                    uuid,
+                    timestamp,
+                    client_timestamp,
+                    tsn,
+                },
+                compressed: compressed.to_vec(),
+            },
+            EventValueRef::Overflow {
+                event_type,
+                data_len,
+                tags,
+                root_id,
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            } => EventValue::Overflow {
+                event_type: event_type.to_string(),
+                data_len,
+                tags: tags.to_vec(),
+                root_id,
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            },
+        })
+    }
+
+    fn read(reader: &mut SliceReader<'a>, table: Option<&'a [Option<&'a str>]>) -> DCBResult<Self> {
+        // Unknown bits are ignored so that newer writers can introduce flags
+        let flags = EventValueFlags::from_bits_truncate(reader.read_u8("value kind")?);
+        let event_type = read_string(reader, table, "event_type")?;
+
+        if !flags.contains(EventValueFlags::OVERFLOW) {
+            // Inline: data_len u16 + data bytes + tags + optional uuid, timestamps and Tsn
+            let data_len = reader.read_u16("data length")? as usize;
+            let data = reader.read_bytes(data_len, "data")?;
+            let tags = EventTagsRef::read(reader, table)?;
+            let uuid = Self::read_uuid(reader, flags)?;
+            let timestamp = Self::read_timestamp(reader, flags, EventValueFlags::HAS_TIMESTAMP)?;
+            let client_timestamp =
+                Self::read_timestamp(reader, flags, EventValueFlags::HAS_CLIENT_TIMESTAMP)?;
+            let tsn = Self::read_timestamp(reader, flags, EventValueFlags::HAS_TSN)?;
+            if flags.contains(EventValueFlags::COMPRESSED) {
+                Ok(EventValueRef::Compressed {
+                    event_type,
+                    compressed: data,
+                    tags,
+                    uuid,
+                    timestamp,
+                    client_timestamp,
+                    tsn,
+                })
+            } else {
+                Ok(EventValueRef::Inline {
+                    event_type,
+                    data,
+                    tags,
+                    uuid,
+                    timestamp,
+                    client_timestamp,
+                    tsn,
+                })
+            }
+        } else {
+            // Overflow: data_len u64 + tags + root_id + optional uuid, timestamps and Tsn
+            let data_len = reader.read_u64("overflow data_len")?;
+            let tags = EventTagsRef::read(reader, table)?;
+            let root_id = PageID(reader.read_u64("overflow root_id")?);
+            let uuid = Self::read_uuid(reader, flags)?;
+            let timestamp = Self::read_timestamp(reader, flags, EventValueFlags::HAS_TIMESTAMP)?;
+            let client_timestamp =
+                Self::read_timestamp(reader, flags, EventValueFlags::HAS_CLIENT_TIMESTAMP)?;
+            let tsn = Self::read_timestamp(reader, flags, EventValueFlags::HAS_TSN)?;
+            Ok(EventValueRef::Overflow {
+                event_type,
+                data_len,
+                tags,
+                root_id,
+                uuid,
+                timestamp,
+                client_timestamp,
+                tsn,
+            })
+        }
+    }
+
+    fn read_uuid(reader: &mut SliceReader<'a>, flags: EventValueFlags) -> DCBResult<Option<Uuid>> {
+        if !flags.contains(EventValueFlags::HAS_UUID) {
+            return Ok(None);
+        }
+        let bytes = reader.read_bytes(16, "UUID")?;
+        Uuid::from_slice(bytes)
+            .map(Some)
+            .map_err(|err| DCBError::InvalidUuid(err.to_string()))
+    }
+
+    fn read_timestamp(
+        reader: &mut SliceReader<'a>,
+        flags: EventValueFlags,
+        flag: EventValueFlags,
+    ) -> DCBResult<Option<u64>> {
+        if !flags.contains(flag) {
+            return Ok(None);
+        }
+        Ok(Some(reader.read_u64("timestamp")?))
+    }
+}
+
+/// Borrowed tags of an EventValueRef, stored either as length-prefixed UTF-8
+/// strings or as ids into the leaf's string table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTagsRef<'a> {
+    count: usize,
+    bytes: &'a [u8],
+    table: Option<&'a [Option<&'a str>]>,
+}
+
+impl<'a> EventTagsRef<'a> {
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> EventTagsIter<'a> {
+        EventTagsIter(TagsIter::Stored {
+            reader: SliceReader::new(self.bytes),
+            table: self.table,
+            remaining: self.count,
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<String> {
+        self.iter().map(str::to_string).collect()
+    }
+
+    fn read(reader: &mut SliceReader<'a>, table: Option<&'a [Option<&'a str>]>) -> DCBResult<Self> {
+        let count = reader.read_u16("number of tags")? as usize;
+        let start = reader.offset;
+        for idx in 0..count {
+            read_string(reader, table, "tag")
+                .map_err(|err| with_context(err, &format!("tag {idx}")))?;
+        }
+        Ok(EventTagsRef {
+            count,
+            bytes: &reader.slice[start..reader.offset],
+            table,
+        })
+    }
+}
+
+impl<'a> IntoIterator for EventTagsRef<'a> {
+    type Item = &'a str;
+    type IntoIter = EventTagsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Tags of an event in an owned leaf or in a serialized one, which can be
+/// matched against a query without copying them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTags<'a> {
+    Owned(&'a [String]),
+    Stored(EventTagsRef<'a>),
+}
+
+impl<'a> IntoIterator for EventTags<'a> {
+    type Item = &'a str;
+    type IntoIter = EventTagsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            EventTags::Owned(tags) => EventTagsIter(TagsIter::Owned(tags.iter())),
+            EventTags::Stored(tags) => tags.iter(),
+        }
+    }
+}
+
+/// Iterator over the tags of an event.
+pub struct EventTagsIter<'a>(TagsIter<'a>);
+
+enum TagsIter<'a> {
+    Owned(std::slice::Iter<'a, String>),
+    Stored {
+        reader: SliceReader<'a>,
+        table: Option<&'a [Option<&'a str>]>,
+        remaining: usize,
+    },
+}
+
+impl<'a> Iterator for EventTagsIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match &mut self.0 {
+            TagsIter::Owned(tags) => tags.next().map(String::as_str),
+            TagsIter::Stored {
+                reader,
+                table,
+                remaining,
+            } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                *remaining -= 1;
+                Some(read_string(reader, *table, "tag").expect("tags checked when read"))
+            }
+        }
+    }
+}
+
+// Sizes of the parts of a leaf's serialization, and the number of times each
+// event type and tag is used, which decide whether it has a string table
+#[derive(Debug, Clone, Default)]
+struct LeafEncoding {
+    keys_size: usize,
+    // Flags, data, tag counts, UUIDs, timestamps and Tsns
+    values_size: usize,
+    // Event types and tags, when stored in each value
+    inline_strings_size: usize,
+    // Event types and tags, when stored as ids into the string table
+    string_ids_size: usize,
+    // Entries of the string table, one for each distinct string
+    table_entries_size: usize,
+    string_uses: HashMap<String, StringUses>,
+    // Strings used as a tag at least once, which set the bloom filter's size
+    distinct_tags: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StringUses {
+    all: usize,
+    as_tag: usize,
+}
+
+impl LeafEncoding {
+    fn new(keys: &[Position], values: &[EventValue]) -> Self {
+        let mut encoding = LeafEncoding::default();
+        let mut prev_key = None;
+        for (&key, value) in keys.iter().zip(values) {
+            encoding.add(prev_key, key, value);
+            prev_key = Some(key);
+        }
+        encoding
+    }
+
+    fn serialized_size(&self) -> usize {
+        // 1 byte for format version + 2 bytes for keys_len, and 2 bytes for the
+        // bloom filter length + bytes for the filter
+        1 + 2
+            + self.keys_size
+            + self.strings_size()
+            + 2
+            + tag_bloom_size(self.distinct_tags)
+            + self.values_size
+    }
+
+    fn tags(&self) -> impl Iterator<Item = &str> {
+        self.string_uses
+            .iter()
+            .filter(|(_, uses)| uses.as_tag > 0)
+            .map(|(s, _)| s.as_str())
+    }
+
+    // Strings are stored in each value rather than in a table, unless the table is
+    // smaller, as it is for all but leaves with few values
+    fn uses_string_table(&self) -> bool {
+        self.table_size() < self.inline_strings_size()
+    }
+
+    fn strings_size(&self) -> usize {
+        self.table_size().min(self.inline_strings_size())
+    }
+
+    fn table_size(&self) -> usize {
+        // 2 bytes for number of strings
+        2 + self.table_entries_size + self.string_ids_size
+    }
+
+    fn inline_strings_size(&self) -> usize {
+        // 2 bytes for an empty string table
+        2 + self.inline_strings_size
+    }
+
+    fn add(&mut self, prev_key: Option<Position>, key: Position, value: &EventValue) {
+        self.keys_size += key_size(prev_key, key);
+        self.values_size += value_size(value);
+        self.inline_strings_size += value_inline_strings_size(value);
+        self.string_ids_size += value_string_ids_size(value);
+        self.add_string(value.event_type(), false);
+        for tag in value.tags() {
+            self.add_string(tag, true);
+        }
+    }
+
+    fn remove(&mut self, prev_key: Option<Position>, key: Position, value: &EventValue) {
+        self.keys_size -= key_size(prev_key, key);
+        self.values_size -= value_size(value);
+        self.inline_strings_size -= value_inline_strings_size(value);
+        self.string_ids_size -= value_string_ids_size(value);
+        self.remove_string(value.event_type(), false);
+        for tag in value.tags() {
+            self.remove_string(tag, true);
+        }
+    }
+
+    fn add_string(&mut self, s: &str, is_tag: bool) {
+        let uses = match self.string_uses.get_mut(s) {
+            Some(uses) => uses,
+            None => {
+                // 2 bytes for length + bytes for the string
+                self.table_entries_size += 2 + s.len();
+                self.string_uses.entry(s.to_string()).or_default()
+            }
+        };
+        uses.all += 1;
+        if is_tag {
+            uses.as_tag += 1;
+            if uses.as_tag == 1 {
+                self.distinct_tags += 1;
             }
         }
     }
-}
 
-impl PartialEq<EventRecord> for EventValue {
-    fn eq(&self, other: &EventRecord) -> bool {
-        other == self
+    fn remove_string(&mut self, s: &str, is_tag: bool) {
+        let uses = self
+            .string_uses
+            .get_mut(s)
+            .expect("removed strings were added");
+        uses.all -= 1;
+        if is_tag {
+            uses.as_tag -= 1;
+            if uses.as_tag == 0 {
+                self.distinct_tags -= 1;
+            }
+        }
+        if uses.all == 0 {
+            self.string_uses.remove(s);
+            self.table_entries_size -= 2 + s.len();
+        }
     }
 }
 
-bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub struct EventValueFlags: u8 {
-        const OVERFLOW      = 0b0000_0001; // event payload in overflow node
-        const HAS_UUID      = 0b0000_0010; // event includes UUID field
+// Bytes of a key, stored as a varint delta from the previous key
+fn key_size(prev_key: Option<Position>, key: Position) -> usize {
+    varint_size(key.0.wrapping_sub(prev_key.map_or(0, |prev| prev.0)))
+}
+
+// Bytes of a value other than its event type and tags
+fn value_size(value: &EventValue) -> usize {
+    // 1 byte for flags
+    let mut size = 1;
+    match value {
+        EventValue::Inline(rec) => {
+            // 2 bytes for data length + bytes for the data
+            size += 2 + rec.data.len();
+        }
+        EventValue::Compressed { compressed, .. } => {
+            // 2 bytes for data length + bytes for the compressed data
+            size += 2 + compressed.len();
+        }
+        EventValue::Overflow { .. } => {
+            // 8 bytes for data_len (u64) + 8 bytes for root_id
+            size += 8 + 8;
+        }
+    }
+    // 2 bytes for number of tags
+    size += 2;
+    if value.uuid().is_some() {
+        size += 16;
+    }
+    if value.timestamp().is_some() {
+        size += 8;
     }
+    if value.client_timestamp().is_some() {
+        size += 8;
+    }
+    if value.tsn().is_some() {
+        size += 8;
+    }
+    size
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct EventLeafNode {
-    pub keys: Vec<Position>,
-    pub values: Vec<EventValue>,
+// Bytes of a value's event type and tags, with 2 bytes for the length of each
+fn value_inline_strings_size(value: &EventValue) -> usize {
+    2 + value.event_type().len() + value.tags().iter().map(|t| 2 + t.len()).sum::<usize>()
 }
 
-impl EventLeafNode {
-    pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes for keys_len
-        let mut total_size = 2;
+// Bytes of a value's event type and tags, with 2 bytes for the id of each
+fn value_string_ids_size(value: &EventValue) -> usize {
+    2 + value.tags().len() * 2
+}
 
-        // 8 bytes for each Position in keys
-        total_size += self.keys.len() * 8;
+/// Leaf-local table of the distinct event type and tag strings, in first-seen order.
+struct StringTable<'a> {
+    strings: Vec<&'a str>,
+    ids: HashMap<&'a str, u16>,
+}
 
-        // For each value
-        for value in &self.values {
-            // 1 byte for discriminator
-            total_size += 1;
-            match value {
-                EventValue::Inline(rec) => {
-                    // 2 bytes for event_type length + bytes for the string
-                    total_size += 2 + rec.event_type.len();
-                    // 2 bytes for data length + bytes for the data
-                    total_size += 2 + rec.data.len();
-                    // 2 bytes for number of tags
-                    total_size += 2;
-                    // For each tag: 2 bytes for length + bytes for the string
-                    for tag in &rec.tags {
-                        total_size += 2 + tag.len();
-                    }
-                    if rec.uuid.is_some() {
-                        total_size += 16;
-                    }
-                }
-                EventValue::Overflow {
-                    event_type,
-                    data_len: _,
-                    tags,
-                    root_id: _,
-                    uuid,
-                } => {
-                    // 2 bytes for event_type length + bytes for the string
-                    total_size += 2 + event_type.len();
-                    // 8 bytes for data_len (u64)
-                    total_size += 8;
-                    // 2 bytes for number of tags
-                    total_size += 2;
-                    // For each tag: 2 bytes for length + bytes for the string
-                    for tag in tags {
-                        total_size += 2 + tag.len();
-                    }
-                    // 8 bytes for root_id
-                    total_size += 8;
-                    if uuid.is_some() {
-                        total_size += 16;
-                    }
-                }
+impl<'a> StringTable<'a> {
+    fn build(values: &'a [EventValue]) -> Self {
+        let mut table = StringTable {
+            strings: Vec::new(),
+            ids: HashMap::new(),
+        };
+        for value in values {
+            table.insert(value.event_type());
+            for tag in value.tags() {
+                table.insert(tag);
             }
         }
+        table
+    }
 
-        total_size
+    fn insert(&mut self, s: &'a str) {
+        if !self.ids.contains_key(s) {
+            self.ids.insert(s, self.strings.len() as u16);
+            self.strings.push(s);
+        }
     }
 
-    /// No-allocation serialization into the provided buffer. Returns number of bytes written.
-    pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
-        let mut i = 0usize;
-        // keys_len
-        let klen = self.keys.len() as u16;
-        buf[i..i + 2].copy_from_slice(&klen.to_le_bytes());
+    fn id(&self, s: &str) -> u16 {
+        self.ids[s]
+    }
+
+    fn serialize_into(&self, buf: &mut [u8], mut i: usize) -> usize {
+        let slen = self.strings.len() as u16;
+        buf[i..i + 2].copy_from_slice(&slen.to_le_bytes());
         i += 2;
-        // keys
-        for key in &self.keys {
-            let b = key.0.to_le_bytes();
-            buf[i..i + 8].copy_from_slice(&b);
-            i += 8;
-        }
-        // values
-        for value in &self.values {
-            let mut flags = EventValueFlags::empty();
-            match value {
-                EventValue::Inline(rec) => {
-                    if rec.uuid.is_some() {
-                        flags |= EventValueFlags::HAS_UUID;
-                    }
-                    buf[i] = flags.bits();
-                    i += 1;
-                    let et_len = rec.event_type.len() as u16;
-                    buf[i..i + 2].copy_from_slice(&et_len.to_le_bytes());
-                    i += 2;
-                    let s = rec.event_type.as_bytes();
-                    buf[i..i + s.len()].copy_from_slice(s);
-                    i += s.len();
-                    let dlen = rec.data.len() as u16;
-                    buf[i..i + 2].copy_from_slice(&dlen.to_le_bytes());
-                    i += 2;
-                    buf[i..i + rec.data.len()].copy_from_slice(&rec.data);
-                    i += rec.data.len();
-                    let tlen = rec.tags.len() as u16;
-                    buf[i..i + 2].copy_from_slice(&tlen.to_le_bytes());
-                    i += 2;
-                    for tag in &rec.tags {
-                        let tl = tag.len() as u16;
-                        buf[i..i + 2].copy_from_slice(&tl.to_le_bytes());
-                        i += 2;
-                        let tb = tag.as_bytes();
-                        buf[i..i + tb.len()].copy_from_slice(tb);
-                        i += tb.len();
-                    }
-                    if let Some(uuid) = rec.uuid {
-                        buf[i..i + 16].copy_from_slice(uuid.as_bytes());
-                        i += 16;
-                    }
-                }
-                EventValue::Overflow {
-                    event_type,
-                    data_len,
-                    tags,
-                    root_id,
-                    uuid,
-                } => {
-                    flags |= EventValueFlags::OVERFLOW;
-                    if uuid.is_some() {
-                        flags |= EventValueFlags::HAS_UUID;
-                    }
-                    buf[i] = flags.bits();
-                    i += 1;
-                    let et_len = event_type.len() as u16;
-                    buf[i..i + 2].copy_from_slice(&et_len.to_le_bytes());
-                    i += 2;
-                    let s = event_type.as_bytes();
-                    buf[i..i + s.len()].copy_from_slice(s);
-                    i += s.len();
-                    buf[i..i + 8].copy_from_slice(&data_len.to_le_bytes());
-                    i += 8;
-                    let tlen = tags.len() as u16;
-                    buf[i..i + 2].copy_from_slice(&tlen.to_le_bytes());
-                    i += 2;
-                    for tag in tags {
-                        let tl = tag.len() as u16;
-                        buf[i..i + 2].copy_from_slice(&tl.to_le_bytes());
-                        i += 2;
-                        let tb = tag.as_bytes();
-                        buf[i..i + tb.len()].copy_from_slice(tb);
-                        i += tb.len();
-                    }
-                    buf[i..i + 8].copy_from_slice(&root_id.0.to_le_bytes());
-                    i += 8;
-                    if uuid.is_some() {
-                        buf[i..i + 16].copy_from_slice(uuid.unwrap().as_bytes());
-                        i += 16;
-                    }
-                }
-            }
+        for s in &self.strings {
+            let len = s.len() as u16;
+            buf[i..i + 2].copy_from_slice(&len.to_le_bytes());
+            i += 2;
+            buf[i..i + s.len()].copy_from_slice(s.as_bytes());
+            i += s.len();
         }
         i
     }
+}
 
-    pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
-        // Check if the slice has at least 2 bytes for keys_len
-        if slice.len() < 2 {
-            return Err(DCBError::DeserializationError(format!(
-                "Expected at least 2 bytes, got {}",
-                slice.len()
-            )));
-        }
+// About 1% false positives with 10 bits per tag and 7 hashes
+const TAG_BLOOM_BITS_PER_TAG: usize = 10;
+const TAG_BLOOM_HASHES: u64 = 7;
 
-        // Extract the length of the keys (first 2 bytes)
-        let keys_len = LittleEndian::read_u16(&slice[0..2]) as usize;
+/// Number of bytes in the bloom filter of a leaf with the given number of distinct tags.
+fn tag_bloom_size(num_tags: usize) -> usize {
+    (num_tags * TAG_BLOOM_BITS_PER_TAG).div_ceil(8)
+}
 
-        // Calculate the minimum expected size for the keys
-        let min_expected_size = 2 + (keys_len * 8);
-        if slice.len() < min_expected_size {
-            return Err(DCBError::DeserializationError(format!(
-                "Expected at least {} bytes for keys, got {}",
-                min_expected_size,
-                slice.len()
-            )));
+/// Bit indexes for a tag in a bloom filter with the given number of bits,
+/// using double hashing over two stable crc32 hashes.
+fn tag_bloom_bits(tag: &str, num_bits: usize) -> impl Iterator<Item = usize> {
+    let h1 = crc32fast::hash(tag.as_bytes()) as u64;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(tag.as_bytes());
+    hasher.update(&[0x9E, 0x37, 0x79, 0xB9]);
+    // Odd, so that the bits differ for each hash
+    let h2 = (hasher.finalize() as u64) | 1;
+    (0..TAG_BLOOM_HASHES)
+        .map(move |k| (h1.wrapping_add(k.wrapping_mul(h2)) % num_bits as u64) as usize)
+}
+
+fn build_tag_bloom<'a>(num_tags: usize, tags: impl Iterator<Item = &'a str>) -> Vec<u8> {
+    let mut bloom = vec![0u8; tag_bloom_size(num_tags)];
+    let num_bits = bloom.len() * 8;
+    for tag in tags {
+        for bit in tag_bloom_bits(tag, num_bits) {
+            bloom[bit / 8] |= 1 << (bit % 8);
         }
+    }
+    bloom
+}
 
-        // Extract the keys (8 bytes each)
-        let mut keys = Vec::with_capacity(keys_len);
-        for i in 0..keys_len {
-            let start = 2 + (i * 8);
-            let position = LittleEndian::read_u64(&slice[start..start + 8]);
-            keys.push(Position(position));
+/// An empty filter is for a leaf without tags, so contains nothing.
+fn tag_bloom_contains(bloom: &[u8], tag: &str) -> bool {
+    let num_bits = bloom.len() * 8;
+    num_bits > 0 && tag_bloom_bits(tag, num_bits).all(|bit| bloom[bit / 8] & (1 << (bit % 8)) != 0)
+}
+
+// Checks the string table's entries are all there, returning the number of
+// strings and the bytes of their entries
+fn skip_string_table<'a>(reader: &mut SliceReader<'a>) -> DCBResult<(usize, &'a [u8])> {
+    let count = reader.read_u16("string table length")? as usize;
+    let start = reader.offset;
+    for idx in 0..count {
+        let len = reader.read_u16("string table entry length")? as usize;
+        reader
+            .read_bytes(len, "string table entry")
+            .map_err(|err| with_context(err, &format!("entry {idx}")))?;
+    }
+    Ok((count, &reader.slice[start..reader.offset]))
+}
+
+// Entries that aren't valid UTF-8 are None, which fails the event type or tag
+// that uses them, naming its event
+fn read_string_table(count: usize, entries: &[u8]) -> Vec<Option<&str>> {
+    let mut reader = SliceReader::new(entries);
+    (0..count)
+        .map(|_| {
+            let len = reader
+                .read_u16("string table entry length")
+                .expect("table checked when viewed") as usize;
+            let bytes = reader
+                .read_bytes(len, "string table entry")
+                .expect("table checked when viewed");
+            std::str::from_utf8(bytes).ok()
+        })
+        .collect()
+}
+
+/// Appends where a deserialization error happened, so a bad tag or string can be found.
+fn with_context(err: DCBError, context: &str) -> DCBError {
+    match err {
+        DCBError::DeserializationError(msg) => {
+            DCBError::DeserializationError(format!("{msg} ({context})"))
+        }
+        DCBError::UnexpectedEndOfData(what) => {
+            DCBError::UnexpectedEndOfData(format!("{what} ({context})"))
         }
+        other => other,
+    }
+}
 
-        // Extract the values (EventValue)
-        let mut values = Vec::with_capacity(keys_len);
-        let mut offset = 2 + (keys_len * 8);
-
-        for _ in 0..keys_len {
-            // Read discriminator (1 byte)
-            if offset + 1 > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading value kind".to_string(),
-                ));
-            }
-
-            let flags = EventValueFlags::from_bits(slice[offset]).ok_or(
-                DCBError::DeserializationError("unknown flag bits set".to_string()),
-            )?;
-            offset += 1;
-
-            // Extract event_type length (2 bytes)
-            if offset + 2 > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading event_type length".to_string(),
-                ));
-            }
-            let event_type_len = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-            offset += 2;
-            if offset + event_type_len > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading event_type".to_string(),
-                ));
-            }
-            let event_type = match std::str::from_utf8(&slice[offset..offset + event_type_len]) {
-                Ok(s) => s.to_string(),
-                Err(_) => {
-                    return Err(DCBError::DeserializationError(
-                        "Invalid UTF-8 sequence in event_type".to_string(),
-                    ));
-                }
-            };
-            offset += event_type_len;
+/// Reads a string stored inline (no table) or as an id into the string table.
+fn read_string<'a>(
+    reader: &mut SliceReader<'a>,
+    table: Option<&'a [Option<&'a str>]>,
+    what: &str,
+) -> DCBResult<&'a str> {
+    let len_or_id = reader.read_u16(what)? as usize;
+    match table {
+        None => reader.read_str(len_or_id, what),
+        Some(table) => match table.get(len_or_id) {
+            Some(Some(string)) => Ok(string),
+            Some(None) => Err(DCBError::DeserializationError(format!(
+                "Invalid UTF-8 sequence in {what}"
+            ))),
+            None => Err(DCBError::DeserializationError(format!(
+                "Invalid string id {len_or_id} in {what}"
+            ))),
+        },
+    }
+}
 
-            let overflow = flags.contains(EventValueFlags::OVERFLOW);
-            let has_uuid = flags.contains(EventValueFlags::HAS_UUID);
+/// Bounds-checked cursor over a serialized node.
+struct SliceReader<'a> {
+    slice: &'a [u8],
+    offset: usize,
+}
 
-            if !overflow {
-                // Inline: data_len u16 + data bytes
-                if offset + 2 > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading data length".to_string(),
-                    ));
-                }
-                let data_len = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-                offset += 2;
-                if offset + data_len > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading data".to_string(),
-                    ));
-                }
-                let data = slice[offset..offset + data_len].to_vec();
-                offset += data_len;
-
-                // num tags
-                if offset + 2 > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading number of tags".to_string(),
-                    ));
-                }
-                let num_tags = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-                offset += 2;
-                let mut tags = Vec::with_capacity(num_tags);
-                for _ in 0..num_tags {
-                    if offset + 2 > slice.len() {
-                        return Err(DCBError::DeserializationError(
-                            "Unexpected end of data while reading tag length".to_string(),
-                        ));
-                    }
-                    let tag_len = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-                    offset += 2;
-                    if offset + tag_len > slice.len() {
-                        return Err(DCBError::DeserializationError(
-                            "Unexpected end of data while reading tag".to_string(),
-                        ));
-                    }
-                    let tag = match std::str::from_utf8(&slice[offset..offset + tag_len]) {
-                        Ok(s) => s.to_string(),
-                        Err(_) => {
-                            return Err(DCBError::DeserializationError(
-                                "Invalid UTF-8 sequence in tag".to_string(),
-                            ));
-                        }
-                    };
-                    offset += tag_len;
-                    tags.push(tag);
-                }
+impl<'a> SliceReader<'a> {
+    fn new(slice: &'a [u8]) -> Self {
+        SliceReader { slice, offset: 0 }
+    }
 
-                let uuid = {
-                    if has_uuid {
-                        if offset + 16 > slice.len() {
-                            return Err(DCBError::DeserializationError(
-                                "Unexpected end of data while reading UUID".to_string(),
-                            ));
-                        }
-
-                        match Uuid::from_slice(&slice[offset..offset + 16]) {
-                            Ok(uuid) => {
-                                offset += 16;
-                                Some(uuid)
-                            }
-                            Err(err) => {
-                                return Err(DCBError::DeserializationError(
-                                    format!("Invalid UUID sequence: {err} ").to_string(),
-                                ));
-                            }
-                        }
-                    } else {
-                        None
-                    }
-                };
+    fn read_bytes(&mut self, len: usize, what: &str) -> DCBResult<&'a [u8]> {
+        if self.offset + len > self.slice.len() {
+            return Err(DCBError::UnexpectedEndOfData(what.to_string()));
+        }
+        let bytes = &self.slice[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
 
-                values.push(EventValue::Inline(EventRecord {
-                    event_type,
-                    data,
-                    tags,
-                    uuid,
-                }));
-            } else {
-                // Overflow: data_len u64 + tags + root_id
-                if offset + 8 > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading overflow data_len".to_string(),
-                    ));
-                }
-                let data_len = LittleEndian::read_u64(&slice[offset..offset + 8]);
-                offset += 8;
+    fn read_u8(&mut self, what: &str) -> DCBResult<u8> {
+        Ok(self.read_bytes(1, what)?[0])
+    }
 
-                if offset + 2 > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading number of tags".to_string(),
-                    ));
-                }
-                let num_tags = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-                offset += 2;
-                let mut tags = Vec::with_capacity(num_tags);
-                for _ in 0..num_tags {
-                    if offset + 2 > slice.len() {
-                        return Err(DCBError::DeserializationError(
-                            "Unexpected end of data while reading tag length".to_string(),
-                        ));
-                    }
-                    let tag_len = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-                    offset += 2;
-                    if offset + tag_len > slice.len() {
-                        return Err(DCBError::DeserializationError(
-                            "Unexpected end of data while reading tag".to_string(),
-                        ));
-                    }
-                    let tag = match std::str::from_utf8(&slice[offset..offset + tag_len]) {
-                        Ok(s) => s.to_string(),
-                        Err(_) => {
-                            return Err(DCBError::DeserializationError(
-                                "Invalid UTF-8 sequence in tag".to_string(),
-                            ));
-                        }
-                    };
-                    offset += tag_len;
-                    tags.push(tag);
-                }
-                if offset + 8 > slice.len() {
-                    return Err(DCBError::DeserializationError(
-                        "Unexpected end of data while reading overflow root_id".to_string(),
-                    ));
-                }
-                let root_id = PageID(LittleEndian::read_u64(&slice[offset..offset + 8]));
-                offset += 8;
-
-                let uuid = {
-                    if has_uuid {
-                        if offset + 16 > slice.len() {
-                            return Err(DCBError::DeserializationError(
-                                "Unexpected end of data while reading UUID".to_string(),
-                            ));
-                        }
-
-                        match Uuid::from_slice(&slice[offset..offset + 16]) {
-                            Ok(uuid) => {
-                                offset += 16;
-                                Some(uuid)
-                            }
-                            Err(err) => {
-                                return Err(DCBError::DeserializationError(
-                                    format!("Invalid UUID sequence: {err} ").to_string(),
-                                ));
-                            }
-                        }
-                    } else {
-                        None
-                    }
-                };
+    fn read_u16(&mut self, what: &str) -> DCBResult<u16> {
+        Ok(LittleEndian::read_u16(self.read_bytes(2, what)?))
+    }
 
-                values.push(EventValue::Overflow {
-                    event_type,
-                    data_len,
-                    tags,
-                    root_id,
-                    uuid,
-                });
+    fn read_u64(&mut self, what: &str) -> DCBResult<u64> {
+        Ok(LittleEndian::read_u64(self.read_bytes(8, what)?))
+    }
+
+    fn read_varint(&mut self, what: &str) -> DCBResult<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8(what)?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
             }
         }
+        Err(DCBError::DeserializationError(format!(
+            "Varint too long while reading {what}"
+        )))
+    }
+
+    fn read_str(&mut self, len: usize, what: &str) -> DCBResult<&'a str> {
+        std::str::from_utf8(self.read_bytes(len, what)?).map_err(|_| {
+            DCBError::DeserializationError(format!("Invalid UTF-8 sequence in {what}"))
+        })
+    }
+}
 
-        Ok(EventLeafNode { keys, values })
+/// Number of bytes used by an unsigned LEB128 varint.
+fn varint_size(mut value: u64) -> usize {
+    let mut size = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        size += 1;
     }
+    size
+}
 
-    pub fn pop_last_key_and_value(&mut self) -> DCBResult<(Position, EventValue)> {
-        let last_key = self
-            .keys
-            .pop()
-            .expect("EventLeafNode should have some keys");
-        let last_value = self
-            .values
-            .pop()
-            .expect("EventLeafNode should have some values");
-        Ok((last_key, last_value))
+/// Writes an unsigned LEB128 varint at offset `i`. Returns the new offset.
+fn write_varint(buf: &mut [u8], mut i: usize, mut value: u64) -> usize {
+    while value >= 0x80 {
+        buf[i] = (value as u8) | 0x80;
+        value >>= 7;
+        i += 1;
+    }
+    buf[i] = value as u8;
+    i + 1
+}
+
+// Decompresses into a buffer of the data length recorded in the zstd frame, so
+// that a payload which decompresses to more than that is rejected
+fn decompress_event_data(compressed: &[u8]) -> DCBResult<Vec<u8>> {
+    let error = |reason: String| {
+        DCBError::DeserializationError(format!("Failed to decompress event data: {reason}"))
+    };
+    let data_len = match zstd::zstd_safe::get_frame_content_size(compressed) {
+        Ok(Some(data_len)) => data_len,
+        Ok(None) => return Err(error("its length isn't recorded".to_string())),
+        Err(_) => return Err(error("it isn't a zstd frame".to_string())),
+    };
+    let data_len = usize::try_from(data_len)
+        .map_err(|_| error(format!("its length of {data_len} bytes is too long")))?;
+    let data =
+        zstd::bulk::decompress(compressed, data_len).map_err(|err| error(err.to_string()))?;
+    if data.len() != data_len {
+        return Err(error(format!(
+            "it is {} bytes long rather than {data_len}",
+            data.len()
+        )));
     }
+    Ok(data)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -478,18 +1524,20 @@ pub struct EventOverflowNode {
 
 impl EventOverflowNode {
     pub fn calc_serialized_size(&self) -> usize {
-        // 8 bytes for next + data bytes
-        8 + self.data.len()
+        // 1 byte for format version + 8 bytes for next + data bytes
+        1 + 8 + self.data.len()
     }
 
     pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
         let size = self.calc_serialized_size();
-        buf[0..8].copy_from_slice(&self.next.0.to_le_bytes());
-        buf[8..size].copy_from_slice(&self.data);
+        buf[0] = NODE_FORMAT_VERSION;
+        buf[1..9].copy_from_slice(&self.next.0.to_le_bytes());
+        buf[9..size].copy_from_slice(&self.data);
         size
     }
 
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let slice = strip_node_format_version("EventOverflowNode", slice)?;
         if slice.len() < 8 {
             return Err(DCBError::DeserializationError(
                 "Overflow node too small".to_string(),
@@ -501,6 +1549,210 @@ impl EventOverflowNode {
     }
 }
 
+/// Splits event data into a chain of EventOverflowNodes.
+///
+/// Each node holds at most `payload_cap` bytes. The data is split into chunks of
+/// `chunk_size` bytes, which is `payload_cap` unless it is set otherwise. A chunk
+/// smaller than that has a node of its own, and a larger one fills as many nodes
+/// as it needs, on consecutive pages. Empty data is stored as a single empty node,
+/// so an overflow value always has a root page.
+pub struct OverflowWriter {
+    payload_cap: usize,
+    chunk_size: usize,
+}
+
+impl OverflowWriter {
+    pub fn new(payload_cap: usize) -> DCBResult<Self> {
+        if payload_cap == 0 {
+            return Err(DCBError::DatabaseCorrupted(
+                "Page size too small to store overflow data".to_string(),
+            ));
+        }
+        Ok(Self {
+            payload_cap,
+            chunk_size: payload_cap,
+        })
+    }
+
+    /// Splits the data into chunks of `chunk_size` bytes rather than a node each.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> DCBResult<Self> {
+        if chunk_size == 0 {
+            return Err(DCBError::InternalError(
+                "Overflow chunk size must be at least 1 byte".to_string(),
+            ));
+        }
+        self.chunk_size = chunk_size;
+        Ok(self)
+    }
+
+    /// Returns the most data bytes each node holds.
+    pub fn payload_cap(&self) -> usize {
+        self.payload_cap
+    }
+
+    /// Returns the number of data bytes in each chunk, but the last.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the number of data bytes an overflow node can hold in a page of `page_size` bytes.
+    pub fn payload_cap_for_page_size(page_size: usize) -> usize {
+        Self::payload_cap_for_node_size(max_node_size(page_size))
+    }
+
+    /// Returns the number of data bytes an overflow node of at most `max_node_size` bytes can hold.
+    pub fn payload_cap_for_node_size(max_node_size: usize) -> usize {
+        // Format version byte and next pointer
+        max_node_size.saturating_sub(1 + 8)
+    }
+
+    /// Returns the fewest data bytes the nodes of a chain hold on average, but for
+    /// its last chunk, which bounds the length of a chain when it is read.
+    pub fn min_bytes_per_node(&self) -> usize {
+        self.chunk_size / self.chunk_pages(self.chunk_size)
+    }
+
+    /// Returns the number of nodes a chunk of `chunk_len` bytes fills.
+    pub fn chunk_pages(&self, chunk_len: usize) -> usize {
+        chunk_len.div_ceil(self.payload_cap).max(1)
+    }
+
+    /// Returns the number of nodes needed to store `data_len` bytes.
+    pub fn page_count(&self, data_len: usize) -> usize {
+        let full_chunks = data_len / self.chunk_size;
+        let last_chunk = data_len % self.chunk_size;
+        let last_pages = if last_chunk == 0 && full_chunks > 0 {
+            0
+        } else {
+            self.chunk_pages(last_chunk)
+        };
+        full_chunks * self.chunk_pages(self.chunk_size) + last_pages
+    }
+
+    /// Returns the root (first) page ID and the linked nodes, in chain order. The
+    /// nodes of each chunk are given the consecutive page IDs that start from the
+    /// one `alloc_page_ids(n)` returns for a chunk of `n` nodes.
+    pub fn write(
+        &self,
+        data: &[u8],
+        mut alloc_page_ids: impl FnMut(usize) -> PageID,
+    ) -> (PageID, Vec<(PageID, EventOverflowNode)>) {
+        let mut page_ids = Vec::with_capacity(self.page_count(data.len()));
+        let mut pieces = Vec::with_capacity(page_ids.capacity());
+        let mut chunks = data.chunks(self.chunk_size);
+        let first_chunk: &[u8] = chunks.next().unwrap_or_default();
+        for chunk in std::iter::once(first_chunk).chain(chunks) {
+            let pages = self.chunk_pages(chunk.len());
+            let first = alloc_page_ids(pages);
+            page_ids.extend((first.0..first.0 + pages as u64).map(PageID));
+            if chunk.is_empty() {
+                pieces.push(chunk);
+            } else {
+                pieces.extend(chunk.chunks(self.payload_cap));
+            }
+        }
+        let nodes = page_ids
+            .iter()
+            .zip(pieces)
+            .enumerate()
+            .map(|(i, (page_id, data))| {
+                let next = page_ids.get(i + 1).copied().unwrap_or(PageID(0));
+                let data = data.to_vec();
+                (*page_id, EventOverflowNode { next, data })
+            })
+            .collect();
+        (page_ids[0], nodes)
+    }
+}
+
+/// Reassembles event data stored across a chain of EventOverflowNodes.
+///
+/// Pages are fetched one at a time with the given closure, so large payloads can
+/// be streamed chunk by chunk. The total length is checked against `data_len`,
+/// and the walk is bounded by the number of pages `data_len` needs at
+/// `payload_cap` bytes per page, so a corrupted chain with a cycle can't loop forever.
+/// Chains with chunks of several pages are bounded by their fewest bytes per node,
+/// as OverflowWriter::min_bytes_per_node() returns.
+pub struct OverflowReader<F> {
+    fetch: F,
+    next: PageID,
+    data_len: u64,
+    read_len: u64,
+    max_pages: u64,
+    pages_read: u64,
+    done: bool,
+}
+
+impl<F> OverflowReader<F>
+where
+    F: FnMut(PageID) -> DCBResult<EventOverflowNode>,
+{
+    pub fn new(root_id: PageID, data_len: u64, payload_cap: usize, fetch: F) -> Self {
+        let max_pages = data_len.div_ceil(payload_cap.max(1) as u64).max(1);
+        Self {
+            fetch,
+            next: root_id,
+            data_len,
+            read_len: 0,
+            max_pages,
+            pages_read: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the data of the next page in the chain, or None after the last page.
+    pub fn next_chunk(&mut self) -> DCBResult<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.next.0 == 0 {
+            self.done = true;
+            if self.read_len != self.data_len {
+                return Err(DCBError::DatabaseCorrupted(format!(
+                    "Overflow data length mismatch: expected {}, got {}",
+                    self.data_len, self.read_len
+                )));
+            }
+            return Ok(None);
+        }
+        if self.pages_read >= self.max_pages {
+            self.done = true;
+            return Err(DCBError::DatabaseCorrupted(
+                "overflow chain longer than expected".to_string(),
+            ));
+        }
+        let page_id = self.next;
+        let node = (self.fetch)(page_id)?;
+        self.pages_read += 1;
+        self.read_len += node.data.len() as u64;
+        if self.read_len > self.data_len {
+            self.done = true;
+            return Err(DCBError::DatabaseCorrupted(format!(
+                "Overflow data longer than expected {} bytes",
+                self.data_len
+            )));
+        }
+        if node.next == page_id {
+            self.done = true;
+            return Err(DCBError::DatabaseCorrupted(format!(
+                "Overflow page {} links to itself",
+                page_id.0
+            )));
+        }
+        self.next = node.next;
+        Ok(Some(node.data))
+    }
+
+    /// Reads the remaining chain and returns the concatenated data.
+    pub fn read_to_end(mut self) -> DCBResult<Vec<u8>> {
+        let mut out = Vec::new();
+        while let Some(chunk) = self.next_chunk()? {
+            out.extend_from_slice(&chunk);
+        }
+        Ok(out)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EventInternalNode {
     pub keys: Vec<Position>,
@@ -509,8 +1761,8 @@ pub struct EventInternalNode {
 
 impl EventInternalNode {
     pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes for keys_len
-        let mut total_size = 2;
+        // 1 byte for format version + 2 bytes for keys_len
+        let mut total_size = 1 + 2;
 
         // 8 bytes for each Position in keys
         total_size += self.keys.len() * 8;
@@ -522,7 +1774,8 @@ impl EventInternalNode {
     }
 
     pub fn serialize_into(&self, buf: &mut [u8]) -> DCBResult<usize> {
-        let mut i = 0usize;
+        buf[0] = NODE_FORMAT_VERSION;
+        let mut i = 1usize;
         let klen = self.keys.len() as u16;
         buf[i..i + 2].copy_from_slice(&klen.to_le_bytes());
         i += 2;
@@ -538,6 +1791,8 @@ impl EventInternalNode {
     }
 
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let slice = strip_node_format_version("EventInternalNode", slice)?;
+
         // Check if the slice has at least 2 bytes for keys_len
         if slice.len() < 2 {
             return Err(DCBError::DeserializationError(format!(
@@ -593,6 +1848,23 @@ impl EventInternalNode {
 
         Ok(EventInternalNode { keys, child_ids })
     }
+
+    /// Returns the index into `child_ids` of the child whose range contains `pos`.
+    ///
+    /// Keys are lower bounds of the following child, so a position equal to a key
+    /// routes to the right of it.
+    pub fn child_idx_for(&self, pos: Position) -> usize {
+        match self.keys.binary_search(&pos) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// Returns the child page ID whose range contains `pos`.
+    pub fn child_for(&self, pos: Position) -> PageID {
+        self.child_ids[self.child_idx_for(pos)]
+    }
+
     pub fn replace_last_child_id(&mut self, old_id: PageID, new_id: PageID) -> DCBResult<()> {
         // Replace the last child ID.
         let last_idx = self.child_ids.len() - 1;
@@ -612,18 +1884,102 @@ impl EventInternalNode {
         self.child_ids.push(promoted_page_id);
         Ok(())
     }
-    pub fn split_off(&mut self) -> DCBResult<(Position, Vec<Position>, Vec<PageID>)> {
-        let middle_idx = self.keys.len() - 2;
+    /// Splits off the keys and children of a new right sibling, returning the key to
+    /// promote with them. The split point is chosen by the strategy.
+    pub fn split_off(
+        &mut self,
+        strategy: SplitStrategy,
+    ) -> DCBResult<(Position, Vec<Position>, Vec<PageID>)> {
+        if self.keys.len() < 2 {
+            return Err(DCBError::DatabaseCorrupted(
+                "Cannot split internal node with fewer than two keys".to_string(),
+            ));
+        }
+        let middle_idx = match strategy {
+            SplitStrategy::RightBiased => self.keys.len() - 2,
+            SplitStrategy::Middle => self.keys.len() / 2,
+        };
         let promoted_key = self.keys.remove(middle_idx);
         let new_keys = self.keys.split_off(middle_idx);
         let new_child_ids = self.child_ids.split_off(middle_idx + 1);
         Ok((promoted_key, new_keys, new_child_ids))
     }
+
+    /// Appends the right sibling, pulling down the separator key between the two
+    /// nodes from their parent. The separator must be greater than the keys of
+    /// this node, and less than or equal to the keys of the right sibling.
+    pub fn merge(&mut self, separator: Position, right: Self) {
+        debug_assert!(
+            self.keys.last().is_none_or(|last| *last < separator)
+                && right.keys.first().is_none_or(|first| separator <= *first),
+            "EventInternalNode merge requires the separator to order the siblings"
+        );
+        self.keys.push(separator);
+        self.keys.extend(right.keys);
+        self.child_ids.extend(right.child_ids);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::page::PAGE_HEADER_SIZE;
+    use umadb_dcb::DCBQueryItem;
+
+    #[test]
+    fn test_event_leaf_check_invariants() {
+        let value = |i: u8| {
+            EventValue::Inline(EventRecord::builder().event_type("E").data(vec![i]).build())
+        };
+        let message = |node: &EventLeafNode| match node.check_invariants() {
+            Err(DCBError::DatabaseCorrupted(message)) => message,
+            other => panic!("Expected DatabaseCorrupted, got {other:?}"),
+        };
+
+        let mut node = EventLeafNode::new(
+            vec![Position(1), Position(2), Position(5)],
+            vec![value(1), value(2), value(5)],
+        );
+        assert!(node.check_invariants().is_ok());
+        let empty = EventLeafNode::new(Vec::new(), Vec::new());
+        assert!(empty.check_invariants().is_ok());
+
+        node.keys[1] = Position(7);
+        assert_eq!(
+            "EventLeafNode keys out of order: Position(7) then Position(5)",
+            message(&node)
+        );
+        node.keys[1] = Position(5);
+        assert_eq!(
+            "EventLeafNode has duplicate key Position(5)",
+            message(&node)
+        );
+        node.keys[1] = Position(2);
+        node.values.pop();
+        assert_eq!("EventLeafNode has 3 keys but 2 values", message(&node));
+    }
+
+    #[test]
+    fn test_event_record_builder() {
+        let record = EventRecord::builder()
+            .event_type("OrderPlaced")
+            .data(String::from("{}"))
+            .tag("order:1")
+            .tag("customer:2")
+            .build();
+        assert_eq!(
+            EventRecord {
+                event_type: "OrderPlaced".to_string(),
+                data: b"{}".to_vec(),
+                tags: vec!["order:1".to_string(), "customer:2".to_string()],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            },
+            record
+        );
+    }
 
     #[test]
     fn test_event_internal_serialize() {
@@ -663,17 +2019,42 @@ mod tests {
         assert_eq!(PageID(400), deserialized.child_ids[3]);
     }
 
+    #[test]
+    fn test_event_internal_node_child_for() {
+        let node = EventInternalNode {
+            keys: vec![Position(1000), Position(2000), Position(3000)],
+            child_ids: vec![PageID(100), PageID(200), PageID(300), PageID(400)],
+        };
+
+        // Before the first key
+        assert_eq!(PageID(100), node.child_for(Position(0)));
+        assert_eq!(PageID(100), node.child_for(Position(999)));
+
+        // On and between keys
+        assert_eq!(PageID(200), node.child_for(Position(1000)));
+        assert_eq!(PageID(200), node.child_for(Position(1999)));
+        assert_eq!(PageID(300), node.child_for(Position(2000)));
+        assert_eq!(PageID(300), node.child_for(Position(2500)));
+
+        // On and after the last key
+        assert_eq!(PageID(400), node.child_for(Position(3000)));
+        assert_eq!(PageID(400), node.child_for(Position(u64::MAX)));
+    }
+
     #[test]
     fn test_event_leaf_serialize_without_uuid() {
         // Create an EventLeafNode with known values
-        let leaf_node = EventLeafNode {
-            keys: vec![Position(1000), Position(2000), Position(3000)],
-            values: vec![
+        let leaf_node = EventLeafNode::new(
+            vec![Position(1000), Position(2000), Position(3000)],
+            vec![
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_1".to_string(),
                     data: vec![1, 0, 0, 0], // 100 as little-endian bytes
                     tags: vec!["tag1".to_string(), "tag2".to_string(), "tag3".to_string()],
                     uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_2".to_string(),
@@ -685,15 +2066,21 @@ mod tests {
                         "tag7".to_string(),
                     ],
                     uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_3".to_string(),
                     data: vec![3, 0, 0, 0], // 300 as little-endian bytes
                     tags: vec!["tag8".to_string(), "tag9".to_string()],
                     uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
             ],
-        };
+        );
 
         // Serialize the EventLeafNode
         let mut serialized = vec![0u8; leaf_node.calc_serialized_size()];
@@ -769,14 +2156,17 @@ mod tests {
         let uuid1 = Uuid::new_v4();
         let uuid2 = Uuid::new_v4();
         let uuid3 = Uuid::new_v4();
-        let leaf_node = EventLeafNode {
-            keys: vec![Position(1000), Position(2000), Position(3000)],
-            values: vec![
+        let leaf_node = EventLeafNode::new(
+            vec![Position(1000), Position(2000), Position(3000)],
+            vec![
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_1".to_string(),
                     data: vec![1, 0, 0, 0], // 100 as little-endian bytes
                     tags: vec!["tag1".to_string(), "tag2".to_string(), "tag3".to_string()],
                     uuid: Some(uuid1),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_2".to_string(),
@@ -788,15 +2178,21 @@ mod tests {
                         "tag7".to_string(),
                     ],
                     uuid: Some(uuid2),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
                 EventValue::Inline(EventRecord {
                     event_type: "event_type_3".to_string(),
                     data: vec![3, 0, 0, 0], // 300 as little-endian bytes
                     tags: vec!["tag8".to_string(), "tag9".to_string()],
                     uuid: Some(uuid3),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
                 }),
             ],
-        };
+        );
 
         // Serialize the EventLeafNode
         let mut serialized = vec![0u8; leaf_node.calc_serialized_size()];
@@ -868,16 +2264,19 @@ mod tests {
 
     #[test]
     fn test_event_leaf_serialize_with_overflow_single_without_uuid() {
-        let leaf_node = EventLeafNode {
-            keys: vec![Position(111)],
-            values: vec![EventValue::Overflow {
+        let leaf_node = EventLeafNode::new(
+            vec![Position(111)],
+            vec![EventValue::Overflow {
                 event_type: "over_evt".to_string(),
                 data_len: 1234567,
                 tags: vec!["a".to_string(), "b".to_string()],
                 root_id: PageID(123),
                 uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             }],
-        };
+        );
         // Serialize
         let mut serialized = vec![0u8; leaf_node.calc_serialized_size()];
         leaf_node.serialize_into(&mut serialized);
@@ -898,6 +2297,7 @@ mod tests {
                 tags,
                 root_id,
                 uuid,
+                ..
             } => {
                 assert_eq!("over_evt", event_type);
                 assert_eq!(1234567, *data_len);
@@ -912,16 +2312,19 @@ mod tests {
     #[test]
     fn test_event_leaf_serialize_with_overflow_single_with_uuid() {
         let uuid1 = Uuid::new_v4();
-        let leaf_node = EventLeafNode {
-            keys: vec![Position(111)],
-            values: vec![EventValue::Overflow {
+        let leaf_node = EventLeafNode::new(
+            vec![Position(111)],
+            vec![EventValue::Overflow {
                 event_type: "over_evt".to_string(),
                 data_len: 1234567,
                 tags: vec!["a".to_string(), "b".to_string()],
                 root_id: PageID(123),
                 uuid: Some(uuid1),
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
             }],
-        };
+        );
         // Serialize
         let mut serialized = vec![0u8; leaf_node.calc_serialized_size()];
         leaf_node.serialize_into(&mut serialized);
@@ -942,6 +2345,7 @@ mod tests {
                 tags,
                 root_id,
                 uuid,
+                ..
             } => {
                 assert_eq!("over_evt", event_type);
                 assert_eq!(1234567, *data_len);
@@ -960,6 +2364,9 @@ mod tests {
             data: vec![1, 2, 3],
             tags: vec!["x".to_string()],
             uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
         });
         let overflow = EventValue::Overflow {
             event_type: "overflow_evt".to_string(),
@@ -967,11 +2374,14 @@ mod tests {
             tags: vec!["y".to_string(), "z".to_string()],
             root_id: PageID(999),
             uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
         };
-        let leaf_node = EventLeafNode {
-            keys: vec![Position(10), Position(20)],
-            values: vec![inline.clone(), overflow.clone()],
-        };
+        let leaf_node = EventLeafNode::new(
+            vec![Position(10), Position(20)],
+            vec![inline.clone(), overflow.clone()],
+        );
         let mut serialized = vec![0u8; leaf_node.calc_serialized_size()];
         leaf_node.serialize_into(&mut serialized);
         let deserialized = EventLeafNode::from_slice(&serialized).unwrap();
@@ -994,6 +2404,7 @@ mod tests {
                 tags,
                 root_id,
                 uuid,
+                ..
             } => {
                 assert_eq!("overflow_evt", event_type);
                 assert_eq!(9999, *data_len);
@@ -1013,10 +2424,1258 @@ mod tests {
         };
         let mut ser = vec![0u8; node.calc_serialized_size()];
         node.serialize_into(&mut ser);
-        assert_eq!(8 + 4, ser.len()); // 8 bytes next + 4 bytes data
+        assert_eq!(1 + 8 + 4, ser.len()); // 1 byte version + 8 bytes next + 4 bytes data
         let de = EventOverflowNode::from_slice(&ser).unwrap();
         assert_eq!(node, de);
         assert_eq!(PageID(77), de.next);
         assert_eq!(vec![7, 8, 9, 10], de.data);
     }
+
+    #[test]
+    fn test_event_nodes_reject_unknown_format_version() {
+        let node = EventOverflowNode {
+            next: PageID(0),
+            data: vec![1, 2, 3],
+        };
+        let mut ser = vec![0u8; node.calc_serialized_size()];
+        node.serialize_into(&mut ser);
+        assert_eq!(NODE_FORMAT_VERSION, ser[0]);
+        ser[0] = 9;
+
+        match EventOverflowNode::from_slice(&ser) {
+            Err(DCBError::DeserializationError(msg)) => assert!(msg.contains("version 9")),
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+        assert!(EventLeafNode::from_slice(&ser).is_err());
+        assert!(EventInternalNode::from_slice(&ser).is_err());
+    }
+
+    #[test]
+    fn test_event_leaf_ignores_unknown_flag_bits() {
+        let uuid = Uuid::new_v4();
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(2)],
+            vec![
+                EventValue::Inline(EventRecord {
+                    event_type: "Inline".to_string(),
+                    data: vec![1, 2, 3],
+                    tags: vec!["t1".to_string()],
+                    uuid: Some(uuid),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+                EventValue::Overflow {
+                    event_type: "Big".to_string(),
+                    data_len: 100_000,
+                    tags: vec![],
+                    root_id: PageID(42),
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                },
+            ],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+
+        // Set a reserved bit on both value flag bytes (strings are stored in
+        // each value for a leaf this small, after two 1-byte keys, an empty table
+        // and a 2-byte bloom filter for the one tag)
+        let first_flags = 1 + 2 + 2 + 2 + (2 + 2);
+        assert_eq!(EventValueFlags::HAS_UUID.bits(), ser[first_flags]);
+        ser[first_flags] |= 0b1000_0000;
+        let second_flags = first_flags + 1 + (2 + 6) + (2 + 3) + 2 + (2 + 2) + 16;
+        assert_eq!(EventValueFlags::OVERFLOW.bits(), ser[second_flags]);
+        ser[second_flags] |= 0b1000_0000;
+
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(leaf, de);
+    }
+
+    #[test]
+    fn test_event_leaf_serialize_compressed_roundtrip() {
+        // 64KB of highly compressible JSON-ish data
+        let data: Vec<u8> =
+            br#"{"name":"widget","count":1}"#.iter().copied().cycle().take(64 * 1024).collect();
+        let record = EventRecord {
+            event_type: "Compressible".to_string(),
+            data: data.clone(),
+            tags: vec!["t1".to_string()],
+            uuid: Some(Uuid::new_v4()),
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let value = EventValue::compressed(record.clone(), 3).unwrap();
+        assert!(matches!(value, EventValue::Compressed { .. }));
+        let leaf = EventLeafNode::new(vec![Position(1)], vec![value]);
+
+        let size = leaf.calc_serialized_size();
+        let mut ser = vec![0u8; size];
+        assert_eq!(size, leaf.serialize_into(&mut ser));
+        assert!(ser.len() * 10 < data.len());
+
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(leaf, de);
+        match &de.values[0] {
+            EventValue::Compressed { record: got, .. } => assert_eq!(&record, got),
+            _ => panic!("Expected Compressed value"),
+        }
+    }
+
+    #[test]
+    fn test_event_leaf_rejects_data_longer_than_recorded() {
+        let record = EventRecord {
+            event_type: "Compressible".to_string(),
+            data: vec![7; 4096],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let EventValue::Compressed {
+            record,
+            mut compressed,
+        } = EventValue::compressed(record, 3).unwrap()
+        else {
+            panic!("Expected Compressed value");
+        };
+        // A second frame decompresses past the length recorded in the first
+        compressed.extend(zstd::bulk::compress(&record.data, 3).unwrap());
+        let leaf = EventLeafNode::new(
+            vec![Position(1)],
+            vec![EventValue::Compressed { record, compressed }],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+        assert!(matches!(
+            EventLeafNode::from_slice(&ser),
+            Err(DCBError::DeserializationError(_))
+        ));
+
+        // Frames written without their length can't be bounded, so aren't read
+        let data = vec![7; 4096];
+        let leaf = EventLeafNode::new(
+            vec![Position(1)],
+            vec![EventValue::Compressed {
+                compressed: zstd::stream::encode_all(&data[..], 3).unwrap(),
+                record: EventRecord {
+                    event_type: "Compressible".to_string(),
+                    data,
+                    tags: vec![],
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                },
+            }],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+        assert!(matches!(
+            EventLeafNode::from_slice(&ser),
+            Err(DCBError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_event_value_compressed_falls_back_to_inline() {
+        let record = EventRecord {
+            event_type: "Tiny".to_string(),
+            data: vec![1, 2, 3],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let value = EventValue::compressed(record.clone(), 3).unwrap();
+        assert_eq!(EventValue::Inline(record), value);
+    }
+
+    #[test]
+    fn test_event_leaf_view_borrows_values() {
+        let uuid = Uuid::new_v4();
+        let leaf = EventLeafNode::new(
+            vec![Position(3), Position(7)],
+            vec![
+                EventValue::Inline(EventRecord {
+                    event_type: "Inline".to_string(),
+                    data: vec![1, 2, 3],
+                    tags: vec!["t1".to_string(), "t2".to_string()],
+                    uuid: Some(uuid),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+                EventValue::Overflow {
+                    event_type: "Big".to_string(),
+                    data_len: 100_000,
+                    tags: vec!["t3".to_string()],
+                    root_id: PageID(42),
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                },
+            ],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+
+        let view = EventLeafNode::view(&ser).unwrap();
+        assert_eq!(2, view.len());
+        let items: Vec<(Position, EventValueRef)> = view.iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(Position(3), items[0].0);
+        match items[0].1 {
+            EventValueRef::Inline {
+                event_type,
+                data,
+                tags,
+                uuid: got_uuid,
+                ..
+            } => {
+                assert_eq!("Inline", event_type);
+                assert_eq!(&[1, 2, 3], data);
+                assert_eq!(vec!["t1", "t2"], tags.iter().collect::<Vec<_>>());
+                assert_eq!(Some(uuid), got_uuid);
+            }
+            _ => panic!("Expected Inline at index 0"),
+        }
+        assert_eq!(Position(7), items[1].0);
+        assert_eq!("Big", items[1].1.event_type());
+        assert_eq!(vec!["t3"], items[1].1.tags().iter().collect::<Vec<_>>());
+        assert!(matches!(
+            items[1].1,
+            EventValueRef::Overflow {
+                data_len: 100_000,
+                root_id: PageID(42),
+                ..
+            }
+        ));
+
+        // Stored tags are matched against a query without copying them
+        let item = DCBQueryItem::new().tags(["t1", "t2"]);
+        assert!(item.matches("Inline", EventTags::Stored(items[0].1.tags())));
+        assert!(item.matches("Inline", EventTags::Owned(leaf.values[0].tags())));
+        assert!(!item.matches("Big", EventTags::Stored(items[1].1.tags())));
+
+        // Converting the borrowed values gives the same node as from_slice
+        let owned: Vec<EventValue> = items
+            .into_iter()
+            .map(|(_, v)| v.into_owned().unwrap())
+            .collect();
+        assert_eq!(leaf.values, owned);
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_view_truncated_value() {
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(2)],
+            vec![
+                EventValue::Inline(EventRecord {
+                    event_type: "A".to_string(),
+                    data: vec![1],
+                    tags: vec![],
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+                EventValue::Inline(EventRecord {
+                    event_type: "B".to_string(),
+                    data: vec![2, 3, 4, 5],
+                    tags: vec![],
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+            ],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+        ser.truncate(ser.len() - 3);
+
+        let view = EventLeafNode::view(&ser).unwrap();
+        let mut iter = view.iter();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next(),
+            Some(Err(DCBError::UnexpectedEndOfData(_)))
+        ));
+        assert!(iter.next().is_none());
+        assert!(EventLeafNode::from_slice(&ser).is_err());
+    }
+
+    #[test]
+    fn test_event_leaf_string_table_shrinks_repeated_strings() {
+        let event_type = "OrderLineItemAdded".to_string();
+        let tags = vec![
+            "order:0f8c2d1e".to_string(),
+            "customer:7a91b3".to_string(),
+            "region:eu-west-1".to_string(),
+        ];
+        let leaf = EventLeafNode::new(
+            (1..=70).map(Position).collect(),
+            (0..70u8)
+                .map(|i| {
+                    EventValue::Inline(EventRecord {
+                        event_type: event_type.clone(),
+                        data: vec![i; 8],
+                        tags: tags.clone(),
+                        uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
+                    })
+                })
+                .collect(),
+        );
+
+        // Size with strings stored in every value (format version 1)
+        let per_value_strings =
+            2 + event_type.len() + tags.iter().map(|t| 2 + t.len()).sum::<usize>();
+        let version_1_size = 1 + 2 + 70 * 8 + 70 * (1 + per_value_strings + (2 + 8) + 2);
+
+        let size = leaf.calc_serialized_size();
+        assert!(size * 3 < version_1_size, "{size} vs {version_1_size}");
+
+        let mut ser = vec![0u8; size];
+        assert_eq!(size, leaf.serialize_into(&mut ser));
+        assert_eq!(EVENT_LEAF_FORMAT_VERSION, ser[0]);
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_reads_format_version_1() {
+        // Version 1 stores the event type and tags inline in each value
+        let mut ser = vec![1u8];
+        ser.extend_from_slice(&1u16.to_le_bytes());
+        ser.extend_from_slice(&42u64.to_le_bytes());
+        ser.push(0); // flags
+        ser.extend_from_slice(&1u16.to_le_bytes());
+        ser.extend_from_slice(b"E");
+        ser.extend_from_slice(&2u16.to_le_bytes());
+        ser.extend_from_slice(&[7, 8]);
+        ser.extend_from_slice(&1u16.to_le_bytes());
+        ser.extend_from_slice(&3u16.to_le_bytes());
+        ser.extend_from_slice(b"tag");
+
+        let leaf = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(vec![Position(42)], leaf.keys);
+        assert_eq!(
+            vec![EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                data: vec![7, 8],
+                tags: vec!["tag".to_string()],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })],
+            leaf.values
+        );
+    }
+
+    #[test]
+    fn test_event_leaf_size_is_kept_up_to_date() {
+        let value = |i: u64| {
+            EventValue::Inline(EventRecord {
+                event_type: format!("E{}", i % 3),
+                data: vec![0; i as usize % 7],
+                tags: vec![format!("t{}", i % 5), "shared".to_string()],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })
+        };
+        // The size of a leaf rebuilt from scratch, without the one worked out so far
+        let fresh_size = |leaf: &EventLeafNode| {
+            EventLeafNode::new(leaf.keys.clone(), leaf.values.clone()).calc_serialized_size()
+        };
+
+        let mut leaf = EventLeafNode::new(vec![], vec![]);
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        for i in 1..=40 {
+            // Few values store strings in each value, more use the string table
+            leaf.push(Position(i * 300), value(i));
+            assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        }
+        leaf.pop_last_key_and_value().unwrap();
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        leaf.pop_first_key_and_value().unwrap();
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        leaf.truncate(10);
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        leaf.merge(EventLeafNode::new(
+            (100..110).map(|i| Position(i * 300)).collect(),
+            (100..110).map(value).collect(),
+        ));
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+        leaf.truncate(1);
+        assert_eq!(fresh_size(&leaf), leaf.calc_serialized_size());
+
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_varint_keys() {
+        let leaf = EventLeafNode::new(
+            (1_000_000..1_001_000).map(Position).collect(),
+            (0..1000)
+                .map(|_| {
+                    EventValue::Inline(EventRecord {
+                        event_type: "E".to_string(),
+                        data: vec![],
+                        tags: vec![],
+                        uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
+                    })
+                })
+                .collect(),
+        );
+        assert!(leaf.encoding().keys_size < 1536);
+
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+        let view = EventLeafNode::view(&ser).unwrap();
+        assert!(view.keys.len() < 1536);
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_varint_keys_roundtrip_edge_values() {
+        let leaf = EventLeafNode::new(
+            vec![
+                Position(0),
+                Position(127),
+                Position(128),
+                Position(u64::MAX),
+            ],
+            (0..4)
+                .map(|_| {
+                    EventValue::Inline(EventRecord {
+                        event_type: "E".to_string(),
+                        data: vec![],
+                        tags: vec![],
+                        uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
+                    })
+                })
+                .collect(),
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_lookup() {
+        let value = |n: u8| {
+            EventValue::Inline(EventRecord {
+                event_type: format!("E{n}"),
+                data: vec![n],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })
+        };
+        let leaf = EventLeafNode::new(
+            vec![Position(10), Position(20), Position(30)],
+            vec![value(1), value(2), value(3)],
+        );
+
+        // Present keys
+        assert_eq!(Some(&value(1)), leaf.lookup(Position(10)));
+        assert_eq!(Some(&value(2)), leaf.lookup(Position(20)));
+        assert_eq!(Some(&value(3)), leaf.lookup(Position(30)));
+        assert_eq!(Ok(1), leaf.lookup_idx(Position(20)));
+
+        // Absent keys below, between and above existing keys
+        assert_eq!(None, leaf.lookup(Position(5)));
+        assert_eq!(Err(0), leaf.lookup_idx(Position(5)));
+        assert_eq!(None, leaf.lookup(Position(25)));
+        assert_eq!(Err(2), leaf.lookup_idx(Position(25)));
+        assert_eq!(None, leaf.lookup(Position(35)));
+        assert_eq!(Err(3), leaf.lookup_idx(Position(35)));
+
+        // Empty leaf
+        let empty = EventLeafNode::new(vec![], vec![]);
+        assert_eq!(None, empty.lookup(Position(1)));
+        assert_eq!(Err(0), empty.lookup_idx(Position(1)));
+    }
+
+    #[test]
+    fn test_event_leaf_rejects_keys_len_larger_than_slice() {
+        // Version byte, keys_len of 60000, then a few bytes of garbage
+        let mut slice = vec![EVENT_LEAF_FORMAT_VERSION];
+        slice.extend_from_slice(&60000u16.to_le_bytes());
+        slice.extend_from_slice(&[0u8; 7]);
+        assert_eq!(10, slice.len());
+
+        match EventLeafNode::from_slice(&slice) {
+            Err(DCBError::DeserializationError(msg)) => assert!(msg.contains("60000 keys")),
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+        assert!(EventLeafNode::view(&slice).is_err());
+    }
+
+    #[test]
+    fn test_event_leaf_reports_which_tag_is_not_utf8() {
+        // Version 3 leaf with one key (5), no string table and inline tags of
+        // "ok" and 0xFF 0xFE
+        let mut slice = vec![3];
+        slice.extend_from_slice(&1u16.to_le_bytes());
+        slice.push(5);
+        slice.extend_from_slice(&0u16.to_le_bytes());
+        slice.push(0);
+        slice.extend_from_slice(&1u16.to_le_bytes());
+        slice.extend_from_slice(b"E");
+        slice.extend_from_slice(&0u16.to_le_bytes());
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(b"ok");
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(&[0xFF, 0xFE]);
+
+        // The tag isn't left out, which would lose it when the leaf is rewritten
+        match EventLeafNode::from_slice(&slice) {
+            Err(DCBError::DeserializationError(msg)) => {
+                assert!(msg.contains("UTF-8"), "{msg}");
+                assert!(msg.contains("tag 1"), "{msg}");
+                assert!(msg.contains("position 5"), "{msg}");
+            }
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+
+        // Likewise for a tag in the string table, with "ok" as entry 0 and 0xFF 0xFE
+        // as entry 1
+        let mut slice = vec![3];
+        slice.extend_from_slice(&1u16.to_le_bytes());
+        slice.push(5);
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(b"ok");
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(&[0xFF, 0xFE]);
+        slice.push(0);
+        slice.extend_from_slice(&0u16.to_le_bytes());
+        slice.extend_from_slice(&0u16.to_le_bytes());
+        slice.extend_from_slice(&2u16.to_le_bytes());
+        slice.extend_from_slice(&0u16.to_le_bytes());
+        slice.extend_from_slice(&1u16.to_le_bytes());
+        match EventLeafNode::from_slice(&slice) {
+            Err(DCBError::DeserializationError(msg)) => {
+                assert!(msg.contains("UTF-8"), "{msg}");
+                assert!(msg.contains("tag 1"), "{msg}");
+                assert!(msg.contains("position 5"), "{msg}");
+            }
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_event_leaf_tag_bloom() {
+        let values = (0..50)
+            .map(|i| {
+                EventValue::Inline(EventRecord {
+                    event_type: "E".to_string(),
+                    data: vec![],
+                    tags: vec![format!("order:{i}"), "shared".to_string()],
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                })
+            })
+            .collect();
+        let mut leaf = EventLeafNode::new((1..=50).map(Position).collect(), values);
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        let view = EventLeafNode::view(&ser).unwrap();
+
+        // Tags that are present always match
+        for tag in (0..50)
+            .map(|i| format!("order:{i}"))
+            .chain(["shared".to_string()])
+        {
+            assert!(leaf.might_contain_tag(&tag), "{tag}");
+            assert!(view.might_contain_tag(&tag), "{tag}");
+        }
+
+        // Absent tags mostly don't, and the serialized filter agrees with the leaf
+        let absent: Vec<String> = (0..1000).map(|i| format!("customer:{i}")).collect();
+        for tag in &absent {
+            assert_eq!(leaf.might_contain_tag(tag), view.might_contain_tag(tag));
+        }
+        let false_positives = absent.iter().filter(|t| view.might_contain_tag(t)).count();
+        assert!(false_positives < 50, "{false_positives} false positives");
+
+        // The filter survives a roundtrip through the owned node
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        let mut ser2 = vec![0u8; de.calc_serialized_size()];
+        de.serialize_into(&mut ser2);
+        assert_eq!(ser, ser2);
+
+        // The filter is rebuilt when the leaf changes
+        assert!(!leaf.might_contain_tag("added"));
+        leaf.push(
+            Position(51),
+            EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                tags: vec!["added".to_string()],
+                ..Default::default()
+            }),
+        );
+        assert!(leaf.might_contain_tag("added"));
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        assert!(
+            EventLeafNode::view(&ser)
+                .unwrap()
+                .might_contain_tag("added")
+        );
+    }
+
+    #[test]
+    fn test_event_leaf_tag_bloom_empty_and_version_3() {
+        let leaf = EventLeafNode::new(
+            vec![Position(1)],
+            vec![EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                data: vec![],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })],
+        );
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut ser);
+        // A leaf without tags has an empty filter, which contains nothing
+        assert!(!leaf.might_contain_tag("t"));
+        assert!(!EventLeafNode::view(&ser).unwrap().might_contain_tag("t"));
+
+        // Without the filter's length (after the version, keys_len, key and empty
+        // string table) it is a version 3 leaf, which may contain any tag
+        ser[0] = 3;
+        ser.drain(1 + 2 + 1 + 2..1 + 2 + 1 + 2 + 2);
+        let view = EventLeafNode::view(&ser).unwrap();
+        assert!(view.might_contain_tag("t"));
+        assert_eq!(leaf, EventLeafNode::from_slice(&ser).unwrap());
+    }
+
+    #[test]
+    fn test_event_leaf_serialize_timestamp_roundtrip() {
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(2), Position(3), Position(4)],
+            vec![
+                EventValue::Inline(EventRecord {
+                    event_type: "WithTimestamp".to_string(),
+                    data: vec![1, 2, 3],
+                    tags: vec!["t".to_string()],
+                    uuid: None,
+                    timestamp: Some(1_700_000_000_123),
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+                EventValue::Inline(EventRecord {
+                    event_type: "WithoutTimestamp".to_string(),
+                    data: vec![4, 5, 6],
+                    tags: vec![],
+                    uuid: Some(Uuid::new_v4()),
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                }),
+                EventValue::Overflow {
+                    event_type: "OverflowWithTimestamp".to_string(),
+                    data_len: 10_000,
+                    tags: vec![],
+                    root_id: PageID(42),
+                    uuid: Some(Uuid::new_v4()),
+                    timestamp: Some(u64::MAX),
+                    client_timestamp: None,
+                    tsn: None,
+                },
+                EventValue::Overflow {
+                    event_type: "OverflowWithoutTimestamp".to_string(),
+                    data_len: 20_000,
+                    tags: vec![],
+                    root_id: PageID(43),
+                    uuid: None,
+                    timestamp: None,
+                    client_timestamp: None,
+                    tsn: None,
+                },
+            ],
+        );
+
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        let n = leaf.serialize_into(&mut ser);
+        assert_eq!(ser.len(), n);
+
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(leaf, de);
+        assert_eq!(Some(1_700_000_000_123), de.values[0].timestamp());
+        assert_eq!(None, de.values[1].timestamp());
+        assert_eq!(Some(u64::MAX), de.values[2].timestamp());
+        assert_eq!(None, de.values[3].timestamp());
+
+        // The timestamp costs 8 bytes only when present
+        let (keys, mut values) = leaf.clone().into_parts();
+        for value in &mut values {
+            match value {
+                EventValue::Inline(rec) => rec.timestamp = None,
+                EventValue::Overflow { timestamp, .. } => *timestamp = None,
+                EventValue::Compressed { .. } => unreachable!(),
+            }
+        }
+        let without = EventLeafNode::new(keys, values);
+        assert_eq!(
+            leaf.calc_serialized_size() - 16,
+            without.calc_serialized_size()
+        );
+    }
+
+    #[test]
+    fn test_event_leaf_serialize_client_timestamp_roundtrip() {
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(2), Position(3)],
+            vec![
+                EventValue::Inline(
+                    EventRecord::builder()
+                        .event_type("ClientOnly")
+                        .client_timestamp(1_500_000_000_000)
+                        .build(),
+                ),
+                EventValue::Inline(
+                    EventRecord::builder()
+                        .event_type("Both")
+                        .uuid(Uuid::new_v4())
+                        .timestamp(1_700_000_000_000)
+                        .client_timestamp(1_500_000_000_000)
+                        .build(),
+                ),
+                EventValue::Overflow {
+                    event_type: "OverflowBoth".to_string(),
+                    data_len: 10_000,
+                    tags: vec!["t".to_string()],
+                    root_id: PageID(42),
+                    uuid: None,
+                    timestamp: Some(2),
+                    client_timestamp: Some(1),
+                    tsn: None,
+                },
+            ],
+        );
+
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(leaf, de);
+        assert_eq!(None, de.values[0].timestamp());
+        assert_eq!(Some(1_500_000_000_000), de.values[0].client_timestamp());
+        assert_eq!(Some(1_700_000_000_000), de.values[1].timestamp());
+        assert_eq!(Some(2), de.values[2].timestamp());
+        assert_eq!(Some(1), de.values[2].client_timestamp());
+
+        let view = EventLeafNode::view(&ser).unwrap();
+        let clients: Vec<_> = view
+            .iter()
+            .map(|item| item.unwrap().1.client_timestamp())
+            .collect();
+        assert_eq!(
+            vec![Some(1_500_000_000_000), Some(1_500_000_000_000), Some(1)],
+            clients
+        );
+    }
+
+    #[test]
+    fn test_event_leaf_serialize_tsn_roundtrip() {
+        let compressible = EventRecord::builder()
+            .event_type("Compressed")
+            .data(vec![7u8; 1000])
+            .timestamp(1_700_000_000_000)
+            .tsn(u64::MAX)
+            .build();
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(2), Position(3), Position(4)],
+            vec![
+                EventValue::Inline(EventRecord::builder().event_type("Untracked").build()),
+                EventValue::Inline(
+                    EventRecord::builder()
+                        .event_type("Inline")
+                        .client_timestamp(1_500_000_000_000)
+                        .tsn(3)
+                        .build(),
+                ),
+                EventValue::compressed(compressible, 3).unwrap(),
+                EventValue::Overflow {
+                    event_type: "Overflow".to_string(),
+                    data_len: 10_000,
+                    tags: vec![],
+                    root_id: PageID(42),
+                    uuid: Some(Uuid::new_v4()),
+                    timestamp: Some(2),
+                    client_timestamp: Some(1),
+                    tsn: Some(4),
+                },
+            ],
+        );
+        assert!(matches!(leaf.values[2], EventValue::Compressed { .. }));
+
+        let mut ser = vec![0u8; leaf.calc_serialized_size()];
+        assert_eq!(ser.len(), leaf.serialize_into(&mut ser));
+        let de = EventLeafNode::from_slice(&ser).unwrap();
+        assert_eq!(leaf, de);
+        let tsns: Vec<_> = de.values.iter().map(|value| value.tsn()).collect();
+        assert_eq!(vec![None, Some(3), Some(u64::MAX), Some(4)], tsns);
+        assert_eq!(Some(1), de.values[3].client_timestamp());
+
+        let view = EventLeafNode::view(&ser).unwrap();
+        let tsns: Vec<_> = view.iter().map(|item| item.unwrap().1.tsn()).collect();
+        assert_eq!(vec![None, Some(3), Some(u64::MAX), Some(4)], tsns);
+    }
+
+    #[test]
+    fn test_event_leaf_pop_first_key_and_value() {
+        let mut leaf = EventLeafNode::new(
+            vec![Position(1), Position(2), Position(3)],
+            (1..=3)
+                .map(|i| {
+                    EventValue::Inline(EventRecord {
+                        event_type: format!("Type{i}"),
+                        data: vec![i as u8],
+                        tags: vec![],
+                        uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
+                    })
+                })
+                .collect(),
+        );
+
+        for i in 1..=3u64 {
+            let (key, value) = leaf.pop_first_key_and_value().unwrap();
+            assert_eq!(Position(i), key);
+            assert_eq!(format!("Type{i}"), value.event_type());
+        }
+        assert!(leaf.keys.is_empty());
+        assert!(leaf.values.is_empty());
+
+        // Popping from an empty leaf is an error, not a panic
+        assert!(matches!(
+            leaf.pop_first_key_and_value(),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+    }
+
+    #[test]
+    fn test_event_leaf_pop_last_key_and_value_when_empty() {
+        let mut leaf = EventLeafNode::new(
+            vec![Position(1)],
+            vec![EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                data: vec![],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })],
+        );
+        assert_eq!(Position(1), leaf.pop_last_key_and_value().unwrap().0);
+        assert!(matches!(
+            leaf.pop_last_key_and_value(),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+    }
+
+    #[test]
+    fn test_event_leaf_iter_in_key_order() {
+        let value = |n: u8| {
+            EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                data: vec![n],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })
+        };
+        let leaf = EventLeafNode::new(
+            vec![Position(1), Position(5), Position(9)],
+            vec![value(1), value(5), value(9)],
+        );
+
+        let borrowed: Vec<(Position, &EventValue)> = leaf.iter().collect();
+        assert_eq!(
+            vec![
+                (Position(1), &value(1)),
+                (Position(5), &value(5)),
+                (Position(9), &value(9)),
+            ],
+            borrowed
+        );
+
+        let owned: Vec<(Position, EventValue)> = leaf.into_iter().collect();
+        assert_eq!(
+            vec![
+                (Position(1), value(1)),
+                (Position(5), value(5)),
+                (Position(9), value(9)),
+            ],
+            owned
+        );
+    }
+
+    #[test]
+    fn test_event_leaf_merge() {
+        let value = |n: u8| {
+            EventValue::Inline(EventRecord {
+                event_type: "E".to_string(),
+                data: vec![n],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })
+        };
+        let mut left = EventLeafNode::new(vec![Position(1), Position(2)], vec![value(1), value(2)]);
+        let right = EventLeafNode::new(vec![Position(5), Position(8)], vec![value(5), value(8)]);
+        left.merge(right);
+        assert_eq!(
+            vec![Position(1), Position(2), Position(5), Position(8)],
+            left.keys
+        );
+        assert_eq!(vec![value(1), value(2), value(5), value(8)], left.values);
+
+        // Merging an empty sibling leaves the node unchanged
+        let before = left.clone();
+        left.merge(EventLeafNode::new(vec![], vec![]));
+        assert_eq!(before, left);
+    }
+
+    #[test]
+    fn test_event_internal_merge_pulls_down_separator() {
+        let mut left = EventInternalNode {
+            keys: vec![Position(10)],
+            child_ids: vec![PageID(1), PageID(2)],
+        };
+        let right = EventInternalNode {
+            keys: vec![Position(30), Position(40)],
+            child_ids: vec![PageID(3), PageID(4), PageID(5)],
+        };
+        left.merge(Position(20), right);
+        assert_eq!(
+            vec![Position(10), Position(20), Position(30), Position(40)],
+            left.keys
+        );
+        assert_eq!(
+            vec![PageID(1), PageID(2), PageID(3), PageID(4), PageID(5)],
+            left.child_ids
+        );
+        assert_eq!(left.keys.len() + 1, left.child_ids.len());
+    }
+
+    #[test]
+    fn test_event_internal_split_off_by_strategy() {
+        let node = EventInternalNode {
+            keys: (1..=6).map(Position).collect(),
+            child_ids: (10..=16).map(PageID).collect(),
+        };
+
+        let mut right = node.clone();
+        let (promoted, keys, child_ids) = right.split_off(SplitStrategy::RightBiased).unwrap();
+        assert_eq!(Position(5), promoted);
+        assert_eq!(vec![Position(6)], keys);
+        assert_eq!(vec![PageID(15), PageID(16)], child_ids);
+        assert_eq!(4, right.keys.len());
+
+        let mut middle = node.clone();
+        let (promoted, keys, child_ids) = middle.split_off(SplitStrategy::Middle).unwrap();
+        assert_eq!(Position(4), promoted);
+        assert_eq!(vec![Position(5), Position(6)], keys);
+        assert_eq!(vec![PageID(14), PageID(15), PageID(16)], child_ids);
+        assert_eq!(vec![Position(1), Position(2), Position(3)], middle.keys);
+        assert_eq!(4, middle.child_ids.len());
+    }
+
+    #[test]
+    fn test_event_internal_split_off_rejects_fewer_than_two_keys() {
+        let mut empty = EventInternalNode {
+            keys: vec![],
+            child_ids: vec![PageID(1)],
+        };
+        assert!(matches!(
+            empty.split_off(SplitStrategy::RightBiased),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+
+        let mut one_key = EventInternalNode {
+            keys: vec![Position(10)],
+            child_ids: vec![PageID(1), PageID(2)],
+        };
+        assert!(matches!(
+            one_key.split_off(SplitStrategy::Middle),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+        // The node is left untouched
+        assert_eq!(vec![Position(10)], one_key.keys);
+        assert_eq!(vec![PageID(1), PageID(2)], one_key.child_ids);
+    }
+
+    fn overflow_chain(chunks: &[&[u8]]) -> HashMap<PageID, EventOverflowNode> {
+        // Pages 10, 11, 12, ... linked in order, the last one ending the chain
+        let mut pages = HashMap::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = if i + 1 < chunks.len() {
+                PageID(11 + i as u64)
+            } else {
+                PageID(0)
+            };
+            pages.insert(
+                PageID(10 + i as u64),
+                EventOverflowNode {
+                    next,
+                    data: chunk.to_vec(),
+                },
+            );
+        }
+        pages
+    }
+
+    #[test]
+    fn test_overflow_reader_reassembles_three_pages() {
+        let pages = overflow_chain(&[&[1, 2, 3], &[4, 5, 6], &[7]]);
+        let fetch = |id: PageID| pages.get(&id).cloned().ok_or(DCBError::PageNotFound(id.0));
+
+        let data = OverflowReader::new(PageID(10), 7, 3, fetch)
+            .read_to_end()
+            .unwrap();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7], data);
+
+        // Streaming returns the chunks in order
+        let mut reader = OverflowReader::new(PageID(10), 7, 3, fetch);
+        assert_eq!(Some(vec![1, 2, 3]), reader.next_chunk().unwrap());
+        assert_eq!(Some(vec![4, 5, 6]), reader.next_chunk().unwrap());
+        assert_eq!(Some(vec![7]), reader.next_chunk().unwrap());
+        assert_eq!(None, reader.next_chunk().unwrap());
+    }
+
+    #[test]
+    fn test_overflow_reader_rejects_length_mismatch() {
+        let pages = overflow_chain(&[&[1, 2, 3], &[4, 5, 6], &[7]]);
+        let fetch = |id: PageID| pages.get(&id).cloned().ok_or(DCBError::PageNotFound(id.0));
+
+        // Chain shorter than data_len
+        let result = OverflowReader::new(PageID(10), 8, 3, fetch).read_to_end();
+        assert!(matches!(result, Err(DCBError::DatabaseCorrupted(_))));
+
+        // Chain longer than data_len
+        let result = OverflowReader::new(PageID(10), 6, 3, fetch).read_to_end();
+        assert!(matches!(result, Err(DCBError::DatabaseCorrupted(_))));
+    }
+
+    #[test]
+    fn test_overflow_writer_empty_data() {
+        let writer = OverflowWriter::new(8).unwrap();
+        let mut next_id = 100;
+        let (root_id, nodes) = writer.write(&[], |_| {
+            next_id += 1;
+            PageID(next_id)
+        });
+        assert_eq!(PageID(101), root_id);
+        assert_eq!(
+            vec![(
+                PageID(101),
+                EventOverflowNode {
+                    next: PageID(0),
+                    data: vec![],
+                }
+            )],
+            nodes
+        );
+    }
+
+    #[test]
+    fn test_overflow_writer_exactly_one_page() {
+        let writer = OverflowWriter::new(8).unwrap();
+        let data: Vec<u8> = (0..8).collect();
+        let mut next_id = 100;
+        let (root_id, nodes) = writer.write(&data, |_| {
+            next_id += 1;
+            PageID(next_id)
+        });
+        assert_eq!(PageID(101), root_id);
+        assert_eq!(1, nodes.len());
+        assert_eq!(PageID(0), nodes[0].1.next);
+        assert_eq!(data, nodes[0].1.data);
+    }
+
+    #[test]
+    fn test_overflow_writer_one_byte_over_page() {
+        let writer = OverflowWriter::new(8).unwrap();
+        let data: Vec<u8> = (0..9).collect();
+        let mut next_id = 100;
+        let (root_id, nodes) = writer.write(&data, |_| {
+            next_id += 1;
+            PageID(next_id)
+        });
+        assert_eq!(PageID(101), root_id);
+        assert_eq!(
+            vec![
+                (
+                    PageID(101),
+                    EventOverflowNode {
+                        next: PageID(102),
+                        data: (0..8).collect(),
+                    }
+                ),
+                (
+                    PageID(102),
+                    EventOverflowNode {
+                        next: PageID(0),
+                        data: vec![8],
+                    }
+                ),
+            ],
+            nodes
+        );
+
+        // Reading the chain back gives the original data
+        let pages: HashMap<PageID, EventOverflowNode> = nodes.into_iter().collect();
+        let read = OverflowReader::new(root_id, data.len() as u64, 8, |id: PageID| {
+            pages.get(&id).cloned().ok_or(DCBError::PageNotFound(id.0))
+        })
+        .read_to_end()
+        .unwrap();
+        assert_eq!(data, read);
+    }
+
+    #[test]
+    fn test_overflow_writer_chunks_of_several_nodes() {
+        let writer = OverflowWriter::new(4).unwrap().with_chunk_size(10).unwrap();
+        assert_eq!(3, writer.chunk_pages(10));
+        assert_eq!(3, writer.min_bytes_per_node());
+        assert_eq!(4, writer.page_count(13));
+        assert_eq!(6, writer.page_count(20));
+        let data: Vec<u8> = (0..13).collect();
+        let mut allocs = Vec::new();
+        let (root_id, nodes) = writer.write(&data, |n| {
+            allocs.push(n);
+            PageID(100 * allocs.len() as u64)
+        });
+        // Each chunk is allocated consecutive pages
+        assert_eq!(vec![3, 1], allocs);
+        assert_eq!(PageID(100), root_id);
+        let chain: Vec<(PageID, PageID, usize)> = nodes
+            .iter()
+            .map(|(page_id, node)| (*page_id, node.next, node.data.len()))
+            .collect();
+        assert_eq!(
+            vec![
+                (PageID(100), PageID(101), 4),
+                (PageID(101), PageID(102), 4),
+                (PageID(102), PageID(200), 2),
+                (PageID(200), PageID(0), 3),
+            ],
+            chain
+        );
+
+        // The chain is bounded by the fewest bytes per node
+        let pages: HashMap<PageID, EventOverflowNode> = nodes.into_iter().collect();
+        let read = OverflowReader::new(root_id, 13, writer.min_bytes_per_node(), |id: PageID| {
+            pages.get(&id).cloned().ok_or(DCBError::PageNotFound(id.0))
+        })
+        .read_to_end()
+        .unwrap();
+        assert_eq!(data, read);
+        assert!(OverflowWriter::new(4).unwrap().with_chunk_size(0).is_err());
+    }
+
+    #[test]
+    fn test_overflow_writer_rejects_zero_capacity() {
+        assert!(matches!(
+            OverflowWriter::new(0),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+        assert!(
+            OverflowWriter::new(OverflowWriter::payload_cap_for_page_size(
+                PAGE_HEADER_SIZE + 9
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_overflow_reader_rejects_self_referential_node() {
+        // An empty node pointing at itself never grows the byte count
+        let fetch = |id: PageID| {
+            Ok(EventOverflowNode {
+                next: id,
+                data: vec![],
+            })
+        };
+        let result = OverflowReader::new(PageID(10), 0, 8, fetch).read_to_end();
+        assert!(matches!(result, Err(DCBError::DatabaseCorrupted(_))));
+
+        // A non-empty node pointing at itself
+        let fetch = |id: PageID| {
+            Ok(EventOverflowNode {
+                next: id,
+                data: vec![1, 2],
+            })
+        };
+        let result = OverflowReader::new(PageID(10), 1000, 8, fetch).read_to_end();
+        assert!(matches!(result, Err(DCBError::DatabaseCorrupted(_))));
+    }
+
+    #[test]
+    fn test_overflow_reader_rejects_chain_longer_than_expected() {
+        // Two empty pages cycling between each other
+        let mut pages = HashMap::new();
+        pages.insert(
+            PageID(10),
+            EventOverflowNode {
+                next: PageID(11),
+                data: vec![],
+            },
+        );
+        pages.insert(
+            PageID(11),
+            EventOverflowNode {
+                next: PageID(10),
+                data: vec![],
+            },
+        );
+        let fetch = |id: PageID| pages.get(&id).cloned().ok_or(DCBError::PageNotFound(id.0));
+        let result = OverflowReader::new(PageID(10), 16, 8, fetch).read_to_end();
+        match result {
+            Err(DCBError::DatabaseCorrupted(msg)) => {
+                assert_eq!("overflow chain longer than expected", msg)
+            }
+            other => panic!("Expected DatabaseCorrupted, got {other:?}"),
+        }
+    }
 }
@@ -1,7 +1,371 @@
-use crate::common::{PageID, Tsn};
+use crate::common::{
+    NODE_FORMAT_VERSION, PageID, Tsn, read_node_format_version, strip_node_format_version,
+};
 use byteorder::{ByteOrder, LittleEndian};
 use umadb_dcb::{DCBError, DCBResult};
 
+/// Latest version of the layout of free-list nodes that list page IDs, the
+/// FreeListLeafNode and FreeListTsnLeafNode. Version 1 lists each page ID as 8
+/// bytes. Version 2 coalesces runs of consecutive page IDs into ranges: an entry
+/// with the top bit set is the start of a range, followed by its count as a u32.
+pub const FREE_LIST_LEAF_FORMAT_VERSION: u8 = 2;
+
+// Marks an encoded page ID as the start of a range
+const PAGE_ID_RANGE_FLAG: u64 = 1 << 63;
+
+/// A run of `count` consecutive page IDs from `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageIdRange {
+    pub start: PageID,
+    pub count: u64,
+}
+
+impl PageIdRange {
+    /// Carves the first page ID out of the range.
+    pub fn pop_first(&mut self) -> Option<PageID> {
+        if self.count == 0 {
+            return None;
+        }
+        let page_id = self.start;
+        self.start = PageID(page_id.0 + 1);
+        self.count -= 1;
+        Some(page_id)
+    }
+
+    fn serialized_size(&self) -> usize {
+        if self.count == 1 { 8 } else { 8 + 4 }
+    }
+
+    fn end(&self) -> u64 {
+        self.start.0 + self.count
+    }
+}
+
+impl Iterator for PageIdRange {
+    type Item = PageID;
+
+    fn next(&mut self) -> Option<PageID> {
+        self.pop_first()
+    }
+}
+
+// Ranges are at most u32::MAX page IDs, as their count is stored as a u32
+const MAX_PAGE_ID_RANGE_COUNT: u64 = u32::MAX as u64;
+
+/// Ascending page IDs, kept as the ranges of consecutive page IDs they are
+/// serialized as, with the number of page IDs and the size of the ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageIdRanges {
+    ranges: Vec<PageIdRange>,
+    len: usize,
+    // Bytes of the entries, not counting the u16 entry count
+    entries_size: usize,
+}
+
+impl PageIdRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of page IDs.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[PageIdRange] {
+        &self.ranges
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PageID> + '_ {
+        self.ranges.iter().copied().flatten()
+    }
+
+    pub fn to_vec(&self) -> Vec<PageID> {
+        self.iter().collect()
+    }
+
+    pub fn first(&self) -> Option<PageID> {
+        self.ranges.first().map(|range| range.start)
+    }
+
+    /// Returns whether the page ID is in one of the ranges.
+    pub fn contains(&self, id: PageID) -> bool {
+        self.find(id).is_ok()
+    }
+
+    /// Inserts the page ID, extending or joining the ranges next to it. Inserting
+    /// an ID that is already present is a no-op, and returns false.
+    pub fn insert(&mut self, id: PageID) -> bool {
+        let Err(idx) = self.find(id) else {
+            return false;
+        };
+        let prev = idx
+            .checked_sub(1)
+            .map(|i| self.ranges[i])
+            .filter(|prev| prev.end() == id.0 && prev.count < MAX_PAGE_ID_RANGE_COUNT);
+        let next = self
+            .ranges
+            .get(idx)
+            .copied()
+            .filter(|next| next.start.0 == id.0 + 1 && next.count < MAX_PAGE_ID_RANGE_COUNT);
+        match (prev, next) {
+            (Some(prev), Some(next)) if prev.count + 1 + next.count <= MAX_PAGE_ID_RANGE_COUNT => {
+                let joined = PageIdRange {
+                    start: prev.start,
+                    count: prev.count + 1 + next.count,
+                };
+                self.splice(idx - 1..idx + 1, [joined]);
+            }
+            (Some(prev), _) => {
+                let extended = PageIdRange {
+                    start: prev.start,
+                    count: prev.count + 1,
+                };
+                self.splice(idx - 1..idx, [extended]);
+            }
+            (None, Some(next)) => {
+                let extended = PageIdRange {
+                    start: id,
+                    count: next.count + 1,
+                };
+                self.splice(idx..idx + 1, [extended]);
+            }
+            (None, None) => self.splice(
+                idx..idx,
+                [PageIdRange {
+                    start: id,
+                    count: 1,
+                }],
+            ),
+        }
+        true
+    }
+
+    /// Removes the page ID, splitting the range it is in. Returns whether it was present.
+    pub fn remove(&mut self, id: PageID) -> bool {
+        let Ok(idx) = self.find(id) else {
+            return false;
+        };
+        let range = self.ranges[idx];
+        let before = PageIdRange {
+            start: range.start,
+            count: id.0 - range.start.0,
+        };
+        let after = PageIdRange {
+            start: PageID(id.0 + 1),
+            count: range.end() - id.0 - 1,
+        };
+        self.splice(
+            idx..idx + 1,
+            [before, after].into_iter().filter(|range| range.count > 0),
+        );
+        true
+    }
+
+    /// Removes and returns the last page ID, or None if there are none.
+    pub fn pop_last(&mut self) -> Option<PageID> {
+        let idx = self.ranges.len().checked_sub(1)?;
+        let mut range = self.ranges[idx];
+        range.count -= 1;
+        self.splice(idx..idx + 1, Some(range).filter(|range| range.count > 0));
+        Some(PageID(range.end()))
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Splits off the ranges from `at`, returning them.
+    pub fn split_off_ranges(&mut self, at: usize) -> Self {
+        let right: Self = self.ranges[at..].iter().copied().collect();
+        self.splice(at..self.ranges.len(), []);
+        right
+    }
+
+    // Size of the serialized ranges, including the u16 entry count
+    fn serialized_size(&self) -> usize {
+        2 + self.entries_size
+    }
+
+    // Index of the range holding the page ID, or of where a range for it would go
+    fn find(&self, id: PageID) -> Result<usize, usize> {
+        self.ranges.binary_search_by(|range| {
+            if range.end() <= id.0 {
+                std::cmp::Ordering::Less
+            } else if range.start > id {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+    }
+
+    // Appends a range after the others, joining it with the last where it can
+    fn push_range(&mut self, range: PageIdRange) {
+        let idx = self.ranges.len();
+        match self.ranges.last() {
+            Some(&last)
+                if last.end() == range.start.0
+                    && last.count + range.count <= MAX_PAGE_ID_RANGE_COUNT =>
+            {
+                let joined = PageIdRange {
+                    start: last.start,
+                    count: last.count + range.count,
+                };
+                self.splice(idx - 1..idx, [joined]);
+            }
+            _ => self.splice(idx..idx, [range]),
+        }
+    }
+
+    // Replaces ranges, keeping the number of page IDs and size of the entries
+    fn splice(
+        &mut self,
+        replaced: std::ops::Range<usize>,
+        ranges: impl IntoIterator<Item = PageIdRange>,
+    ) {
+        let (mut len, mut entries_size) = (0, 0);
+        let added = ranges.into_iter().inspect(|range| {
+            len += range.count as usize;
+            entries_size += range.serialized_size();
+        });
+        for range in self.ranges.splice(replaced, added) {
+            self.len -= range.count as usize;
+            self.entries_size -= range.serialized_size();
+        }
+        self.len += len;
+        self.entries_size += entries_size;
+    }
+}
+
+impl FromIterator<PageIdRange> for PageIdRanges {
+    fn from_iter<I: IntoIterator<Item = PageIdRange>>(iter: I) -> Self {
+        let mut page_ids = Self::new();
+        for range in iter {
+            page_ids.push_range(range);
+        }
+        page_ids
+    }
+}
+
+impl FromIterator<PageID> for PageIdRanges {
+    /// Page IDs that aren't ascending are inserted where they go.
+    fn from_iter<I: IntoIterator<Item = PageID>>(iter: I) -> Self {
+        let mut page_ids = Self::new();
+        for id in iter {
+            if page_ids.ranges.last().is_some_and(|last| last.end() > id.0) {
+                page_ids.insert(id);
+            } else {
+                page_ids.push_range(PageIdRange {
+                    start: id,
+                    count: 1,
+                });
+            }
+        }
+        page_ids
+    }
+}
+
+impl From<Vec<PageID>> for PageIdRanges {
+    fn from(page_ids: Vec<PageID>) -> Self {
+        page_ids.into_iter().collect()
+    }
+}
+
+impl IntoIterator for PageIdRanges {
+    type Item = PageID;
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<PageIdRange>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter().flatten()
+    }
+}
+
+impl<'a> IntoIterator for &'a PageIdRanges {
+    type Item = PageID;
+    type IntoIter = std::iter::Flatten<std::iter::Copied<std::slice::Iter<'a, PageIdRange>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.iter().copied().flatten()
+    }
+}
+
+fn serialize_page_ids_into(page_ids: &PageIdRanges, buf: &mut [u8]) -> usize {
+    buf[0..2].copy_from_slice(&(page_ids.ranges.len() as u16).to_le_bytes());
+    let mut i = 2;
+    for range in &page_ids.ranges {
+        if range.count == 1 {
+            buf[i..i + 8].copy_from_slice(&range.start.0.to_le_bytes());
+            i += 8;
+        } else {
+            buf[i..i + 8].copy_from_slice(&(range.start.0 | PAGE_ID_RANGE_FLAG).to_le_bytes());
+            buf[i + 8..i + 12].copy_from_slice(&(range.count as u32).to_le_bytes());
+            i += 12;
+        }
+    }
+    i
+}
+
+// Reads a list of page IDs starting with its u16 entry count, returning the
+// page IDs and the number of bytes read. Ranges are kept as ranges, so their
+// counts are only checked here against the largest page ID, and against the
+// next page ID where their pages are reused or verified.
+fn page_ids_from_slice(version: u8, slice: &[u8]) -> DCBResult<(PageIdRanges, usize)> {
+    if slice.len() < 2 {
+        return Err(DCBError::UnexpectedEndOfData("page_ids length".to_string()));
+    }
+    let entries = LittleEndian::read_u16(&slice[0..2]) as usize;
+    let mut offset = 2;
+    let mut ranges = Vec::with_capacity(entries);
+    for _ in 0..entries {
+        if offset + 8 > slice.len() {
+            return Err(DCBError::UnexpectedEndOfData("page_ids".to_string()));
+        }
+        let value = LittleEndian::read_u64(&slice[offset..offset + 8]);
+        offset += 8;
+        if version >= 2 && value & PAGE_ID_RANGE_FLAG != 0 {
+            if offset + 4 > slice.len() {
+                return Err(DCBError::UnexpectedEndOfData("page ID range".to_string()));
+            }
+            let count = LittleEndian::read_u32(&slice[offset..offset + 4]) as u64;
+            offset += 4;
+            let start = value & !PAGE_ID_RANGE_FLAG;
+            if count == 0 || start + count > PAGE_ID_RANGE_FLAG {
+                return Err(DCBError::DeserializationError(format!(
+                    "Invalid range of {count} page IDs from {start}"
+                )));
+            }
+            ranges.push(PageIdRange {
+                start: PageID(start),
+                count,
+            });
+        } else {
+            ranges.push(PageIdRange {
+                start: PageID(value),
+                count: 1,
+            });
+        }
+    }
+    let page_ids = if version >= 2 {
+        // Lookups and inserts rely on the ranges being ascending and apart
+        if let Some(w) = ranges.windows(2).find(|w| w[0].end() > w[1].start.0) {
+            return Err(DCBError::DeserializationError(format!(
+                "Page IDs from {:?} overlap or precede those from {:?}",
+                w[1].start, w[0].start
+            )));
+        }
+        ranges.into_iter().collect()
+    } else {
+        // Version 1 kept inline page IDs in the order they were freed
+        ranges.into_iter().map(|range| range.start).collect()
+    };
+    Ok((page_ids, offset))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FreeListLeafNode {
     pub keys: Vec<Tsn>,
@@ -10,29 +374,51 @@ pub struct FreeListLeafNode {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FreeListLeafValue {
-    pub page_ids: Vec<PageID>,
+    pub page_ids: PageIdRanges,
     pub root_id: PageID,
 }
 
+impl FreeListLeafValue {
+    /// Inserts the page ID into the inline list at its sorted position, keeping
+    /// page_ids ascending. Inserting an ID that is already present is a no-op.
+    pub fn insert_page_id(&mut self, id: PageID) {
+        self.page_ids.insert(id);
+    }
+
+    /// Returns whether the page ID is in the inline list.
+    pub fn contains(&self, id: PageID) -> bool {
+        self.page_ids.contains(id)
+    }
+
+    /// Removes the page ID from the inline list, keeping the others sorted.
+    /// Returns whether it was present.
+    pub fn remove_page_id(&mut self, id: PageID) -> bool {
+        self.page_ids.remove(id)
+    }
+
+    /// Removes and returns any page ID from the inline list, or None if it is empty.
+    pub fn pop_any(&mut self) -> Option<PageID> {
+        self.page_ids.pop_last()
+    }
+}
+
 impl FreeListLeafNode {
     /// Calculates the size needed to serialize the FreeListLeafNode
     ///
     /// # Returns
     /// * `usize` - The size in bytes
     pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes for keys_len
-        let mut total_size = 2;
+        // 1 byte for format version + 2 bytes for keys_len
+        let mut total_size = 1 + 2;
 
         // 8 bytes for each TSN in keys
         total_size += self.keys.len() * 8;
 
         // For each value:
         for value in &self.values {
-            // 2 bytes for page_ids length
-            total_size += 2;
-
-            // 8 bytes for each PageID in page_ids
-            total_size += value.page_ids.len() * 8;
+            // 2 bytes for the number of entries, then 8 bytes for each single
+            // PageID and 12 bytes for each range in page_ids
+            total_size += value.page_ids.serialized_size();
 
             // 8 bytes for root_id (PageID), PageID(0) indicates no subtree
             total_size += 8;
@@ -42,7 +428,8 @@ impl FreeListLeafNode {
     }
 
     pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
-        let mut i = 0usize;
+        buf[0] = FREE_LIST_LEAF_FORMAT_VERSION;
+        let mut i = 1usize;
         let klen = self.keys.len() as u16;
         buf[i..i + 2].copy_from_slice(&klen.to_le_bytes());
         i += 2;
@@ -51,13 +438,7 @@ impl FreeListLeafNode {
             i += 8;
         }
         for value in &self.values {
-            let plen = value.page_ids.len() as u16;
-            buf[i..i + 2].copy_from_slice(&plen.to_le_bytes());
-            i += 2;
-            for page_id in &value.page_ids {
-                buf[i..i + 8].copy_from_slice(&page_id.0.to_le_bytes());
-                i += 8;
-            }
+            i += serialize_page_ids_into(&value.page_ids, &mut buf[i..]);
             buf[i..i + 8].copy_from_slice(&value.root_id.0.to_le_bytes());
             i += 8;
         }
@@ -72,6 +453,8 @@ impl FreeListLeafNode {
     /// # Returns
     /// * `Result<Self>` - The deserialized FreeListLeafNode or an error
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let (version, slice) =
+            read_node_format_version("FreeListLeafNode", slice, FREE_LIST_LEAF_FORMAT_VERSION)?;
         // Check if the slice has at least 2 bytes for keys_len
         if slice.len() < 2 {
             return Err(DCBError::DeserializationError(format!(
@@ -106,35 +489,12 @@ impl FreeListLeafNode {
         let mut offset = 2 + (keys_len * 8);
 
         for _ in 0..keys_len {
-            if offset + 2 > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading page_ids length".to_string(),
-                ));
-            }
-
-            // Extract the length of page_ids (2 bytes)
-            let page_ids_len = LittleEndian::read_u16(&slice[offset..offset + 2]) as usize;
-            offset += 2;
-
-            if offset + (page_ids_len * 8) > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading page_ids".to_string(),
-                ));
-            }
-
-            // Extract the page_ids (8 bytes each)
-            let mut page_ids = Vec::with_capacity(page_ids_len);
-            for j in 0..page_ids_len {
-                let start = offset + (j * 8);
-                let page_id = LittleEndian::read_u64(&slice[start..start + 8]);
-                page_ids.push(PageID(page_id));
-            }
-            offset += page_ids_len * 8;
+            // Extract the page_ids, their length first (2 bytes)
+            let (page_ids, len) = page_ids_from_slice(version, &slice[offset..])?;
+            offset += len;
 
             if offset + 8 > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading root_id".to_string(),
-                ));
+                return Err(DCBError::UnexpectedEndOfData("root_id".to_string()));
             }
 
             // Extract the root_id (always 8 bytes); PageID(0) indicates no subtree
@@ -158,7 +518,7 @@ impl FreeListLeafNode {
         // New TSN, add a new entry
         self.keys.push(tsn);
         self.values.push(FreeListLeafValue {
-            page_ids: vec![page_id],
+            page_ids: vec![page_id].into(),
             root_id: PageID(0),
         });
     }
@@ -169,21 +529,39 @@ impl FreeListLeafNode {
         self.calc_serialized_size() + 8 <= max_node_size
     }
 
+    /// Returns true if calculated size with n new page_ids doesn't exceed the given size
+    pub fn would_fit_n_page_ids(&self, n: usize, max_node_size: usize) -> bool {
+        // Grows by at most 8 bytes for each extra PageID
+        self.calc_serialized_size() + n * 8 <= max_node_size
+    }
+
     pub fn push_new_page_id(&mut self, idx: usize, page_id: PageID) {
-        self.values[idx].page_ids.push(page_id);
+        self.values[idx].insert_page_id(page_id);
     }
 
     pub fn pop_last_key_and_value(&mut self) -> DCBResult<(Tsn, FreeListLeafValue)> {
-        let last_key = self
-            .keys
-            .pop()
-            .expect("FreeListLeafNode should have at least one key");
-        let last_value = self
-            .values
-            .pop()
-            .expect("FreeListLeafNode should have at least one value");
+        let last_key = self.keys.pop().ok_or_else(|| {
+            DCBError::DatabaseCorrupted("FreeListLeafNode has no keys to pop".to_string())
+        })?;
+        let last_value = self.values.pop().ok_or_else(|| {
+            DCBError::DatabaseCorrupted("FreeListLeafNode has no values to pop".to_string())
+        })?;
         Ok((last_key, last_value))
     }
+
+    /// Appends the keys and values of the right sibling, whose TSNs must all be
+    /// greater than the TSNs of this leaf.
+    pub fn merge(&mut self, right: Self) {
+        debug_assert!(
+            match (self.keys.last(), right.keys.first()) {
+                (Some(last), Some(first)) => last < first,
+                _ => true,
+            },
+            "FreeListLeafNode merge requires the right sibling's keys to be greater"
+        );
+        self.keys.extend(right.keys);
+        self.values.extend(right.values);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -198,8 +576,8 @@ impl FreeListInternalNode {
     /// # Returns
     /// * `usize` - The size in bytes
     pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes for keys_len
-        let mut total_size = 2;
+        // 1 byte for format version + 2 bytes for keys_len
+        let mut total_size = 1 + 2;
 
         // 8 bytes for each TSN in keys
         total_size += self.keys.len() * 8;
@@ -214,7 +592,8 @@ impl FreeListInternalNode {
     }
 
     pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
-        let mut i = 0usize;
+        buf[0] = NODE_FORMAT_VERSION;
+        let mut i = 1usize;
         let klen = self.keys.len() as u16;
         buf[i..i + 2].copy_from_slice(&klen.to_le_bytes());
         i += 2;
@@ -240,6 +619,7 @@ impl FreeListInternalNode {
     /// # Returns
     /// * `Result<Self>` - The deserialized FreeListInternalNode or an error
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let slice = strip_node_format_version("FreeListInternalNode", slice)?;
         // Check if the slice has at least 2 bytes for keys_len
         if slice.len() < 2 {
             return Err(DCBError::DeserializationError(format!(
@@ -272,8 +652,8 @@ impl FreeListInternalNode {
         // Extract the length of child_ids (2 bytes)
         let offset = 2 + (keys_len * 8);
         if offset + 2 > slice.len() {
-            return Err(DCBError::DeserializationError(
-                "Unexpected end of data while reading child_ids length".to_string(),
+            return Err(DCBError::UnexpectedEndOfData(
+                "child_ids length".to_string(),
             ));
         }
 
@@ -322,69 +702,82 @@ impl FreeListInternalNode {
     }
 
     pub(crate) fn split_off(&mut self) -> DCBResult<(Tsn, Vec<Tsn>, Vec<PageID>)> {
+        if self.keys.len() < 2 {
+            return Err(DCBError::DatabaseCorrupted(
+                "Cannot split internal node with fewer than two keys".to_string(),
+            ));
+        }
         let middle_idx = self.keys.len() - 2;
         let promoted_key = self.keys.remove(middle_idx);
         let new_keys = self.keys.split_off(middle_idx);
         let new_child_ids = self.child_ids.split_off(middle_idx + 1);
         Ok((promoted_key, new_keys, new_child_ids))
     }
+
+    /// Appends the right sibling, pulling down the separator TSN between the two
+    /// nodes from their parent. The separator must be greater than the keys of
+    /// this node, and less than or equal to the keys of the right sibling.
+    pub fn merge(&mut self, separator: Tsn, right: Self) {
+        debug_assert!(
+            self.keys.last().is_none_or(|last| *last < separator)
+                && right.keys.first().is_none_or(|first| separator <= *first),
+            "FreeListInternalNode merge requires the separator to order the siblings"
+        );
+        self.keys.push(separator);
+        self.keys.extend(right.keys);
+        self.child_ids.extend(right.child_ids);
+    }
 }
 
 // TSN-subtree: stores page IDs for a single TSN when inline list overflows
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FreeListTsnLeafNode {
-    pub page_ids: Vec<PageID>,
+    pub page_ids: PageIdRanges,
 }
 
 impl FreeListTsnLeafNode {
     pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes for page_ids length + 8 bytes per page id
-        2 + self.page_ids.len() * 8
+        // 1 byte for format version + 2 bytes for the number of entries + 8 bytes
+        // per single page id and 12 bytes per range
+        1 + self.page_ids.serialized_size()
     }
 
     pub fn serialize_into(&self, dst: &mut [u8]) -> usize {
-        let mut i = 0usize;
-        let plen = self.page_ids.len() as u16;
-        dst[i..i + 2].copy_from_slice(&plen.to_le_bytes());
-        i += 2;
-        for page_id in &self.page_ids {
-            dst[i..i + 8].copy_from_slice(&page_id.0.to_le_bytes());
-            i += 8;
-        }
-        i
+        dst[0] = FREE_LIST_LEAF_FORMAT_VERSION;
+        1 + serialize_page_ids_into(&self.page_ids, &mut dst[1..])
     }
 
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
-        if slice.len() < 2 {
-            return Err(DCBError::DeserializationError(format!(
-                "Expected at least 2 bytes, got {}",
-                slice.len()
-            )));
-        }
-        let plen = LittleEndian::read_u16(&slice[0..2]) as usize;
-        let need = 2 + plen * 8;
-        if slice.len() < need {
-            return Err(DCBError::DeserializationError(format!(
-                "Expected at least {} bytes, got {}",
-                need,
-                slice.len()
-            )));
-        }
-        let mut page_ids = Vec::with_capacity(plen);
-        let mut offset = 2;
-        for _ in 0..plen {
-            let v = LittleEndian::read_u64(&slice[offset..offset + 8]);
-            page_ids.push(PageID(v));
-            offset += 8;
-        }
+        let (version, slice) =
+            read_node_format_version("FreeListTsnLeafNode", slice, FREE_LIST_LEAF_FORMAT_VERSION)?;
+        let (page_ids, _) = page_ids_from_slice(version, slice)?;
         Ok(FreeListTsnLeafNode { page_ids })
     }
 
     /// Returns true if calculated size with new page_id doesn't exceed the given size
     pub fn would_fit_new_page_id(&self, max_node_size: usize) -> bool {
-        // Only grows by 8 bytes for the extra PageID
+        // Grows by at most 8 bytes: a new single PageID, or 4 bytes when it
+        // turns a single PageID into a range, or nothing when it extends a range
         self.calc_serialized_size() + 8 <= max_node_size
     }
+
+    /// Returns true if calculated size with n new page_ids doesn't exceed the given size
+    pub fn would_fit_n_page_ids(&self, n: usize, max_node_size: usize) -> bool {
+        // Grows by at most 8 bytes for each extra PageID, as above
+        self.calc_serialized_size() + n * 8 <= max_node_size
+    }
+
+    /// Removes the page ID, such as when the freed page is reused. Returns
+    /// whether it was present.
+    pub fn remove_page_id(&mut self, id: PageID) -> bool {
+        self.page_ids.remove(id)
+    }
+
+    /// Returns true once every page ID has been removed, when the leaf can be
+    /// freed from its TSN subtree.
+    pub fn is_empty(&self) -> bool {
+        self.page_ids.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -395,12 +788,13 @@ pub struct FreeListTsnInternalNode {
 
 impl FreeListTsnInternalNode {
     pub fn calc_serialized_size(&self) -> usize {
-        // 2 bytes keys len + 8 per key + 2 bytes children len + 8 per child
-        2 + self.keys.len() * 8 + 2 + self.child_ids.len() * 8
+        // 1 byte for format version + 2 bytes keys len + 8 per key + 2 bytes children len + 8 per child
+        1 + 2 + self.keys.len() * 8 + 2 + self.child_ids.len() * 8
     }
 
     pub fn serialize_into(&self, dst: &mut [u8]) -> usize {
-        let mut i = 0usize;
+        dst[0] = NODE_FORMAT_VERSION;
+        let mut i = 1usize;
         let klen = self.keys.len() as u16;
         dst[i..i + 2].copy_from_slice(&klen.to_le_bytes());
         i += 2;
@@ -419,6 +813,7 @@ impl FreeListTsnInternalNode {
     }
 
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
+        let slice = strip_node_format_version("FreeListTsnInternalNode", slice)?;
         if slice.len() < 2 {
             return Err(DCBError::DeserializationError(
                 "Expected at least 2 bytes".to_string(),
@@ -458,11 +853,12 @@ impl FreeListTsnInternalNode {
 
 #[cfg(test)]
 mod tests {
-    use crate::common::{PageID, Tsn};
+    use crate::common::{NODE_FORMAT_VERSION, PageID, Tsn};
     use crate::free_lists_tree_nodes::{
-        FreeListInternalNode, FreeListLeafNode, FreeListLeafValue, FreeListTsnInternalNode,
-        FreeListTsnLeafNode,
+        FREE_LIST_LEAF_FORMAT_VERSION, FreeListInternalNode, FreeListLeafNode, FreeListLeafValue,
+        FreeListTsnInternalNode, FreeListTsnLeafNode, PageIdRange, PageIdRanges,
     };
+    use umadb_dcb::DCBError;
 
     #[test]
     fn test_freelist_leaf_serialize() {
@@ -471,15 +867,15 @@ mod tests {
             keys: vec![Tsn(10), Tsn(20), Tsn(30)],
             values: vec![
                 FreeListLeafValue {
-                    page_ids: vec![PageID(100), PageID(101)],
+                    page_ids: vec![PageID(100), PageID(101)].into(),
                     root_id: PageID(200),
                 },
                 FreeListLeafValue {
-                    page_ids: vec![PageID(102), PageID(103), PageID(104)],
+                    page_ids: vec![PageID(102), PageID(103), PageID(104)].into(),
                     root_id: PageID(0),
                 },
                 FreeListLeafValue {
-                    page_ids: vec![PageID(105)],
+                    page_ids: vec![PageID(105)].into(),
                     root_id: PageID(300),
                 },
             ],
@@ -510,20 +906,20 @@ mod tests {
 
         // Check first value
         assert_eq!(2, deserialized.values[0].page_ids.len());
-        assert_eq!(PageID(100), deserialized.values[0].page_ids[0]);
-        assert_eq!(PageID(101), deserialized.values[0].page_ids[1]);
+        assert_eq!(PageID(100), deserialized.values[0].page_ids.to_vec()[0]);
+        assert_eq!(PageID(101), deserialized.values[0].page_ids.to_vec()[1]);
         assert_eq!(PageID(200), deserialized.values[0].root_id);
 
         // Check second value
         assert_eq!(3, deserialized.values[1].page_ids.len());
-        assert_eq!(PageID(102), deserialized.values[1].page_ids[0]);
-        assert_eq!(PageID(103), deserialized.values[1].page_ids[1]);
-        assert_eq!(PageID(104), deserialized.values[1].page_ids[2]);
+        assert_eq!(PageID(102), deserialized.values[1].page_ids.to_vec()[0]);
+        assert_eq!(PageID(103), deserialized.values[1].page_ids.to_vec()[1]);
+        assert_eq!(PageID(104), deserialized.values[1].page_ids.to_vec()[2]);
         assert_eq!(PageID(0), deserialized.values[1].root_id);
 
         // Check third value
         assert_eq!(1, deserialized.values[2].page_ids.len());
-        assert_eq!(PageID(105), deserialized.values[2].page_ids[0]);
+        assert_eq!(PageID(105), deserialized.values[2].page_ids.to_vec()[0]);
         assert_eq!(PageID(300), deserialized.values[2].root_id);
     }
 
@@ -543,22 +939,25 @@ mod tests {
         assert!(!serialized.is_empty());
 
         // Verify the serialized output has the correct structure
-        // First 2 bytes: keys_len (3) = [3, 0] in little-endian
-        assert_eq!(&[3, 0], &serialized[0..2]);
+        // First byte: format version
+        assert_eq!(NODE_FORMAT_VERSION, serialized[0]);
+
+        // Next 2 bytes: keys_len (3) = [3, 0] in little-endian
+        assert_eq!(&[3, 0], &serialized[1..3]);
 
         // Next 24 bytes: 3 TSNs (8 bytes each)
-        assert_eq!(&10u64.to_le_bytes(), &serialized[2..10]);
-        assert_eq!(&20u64.to_le_bytes(), &serialized[10..18]);
-        assert_eq!(&30u64.to_le_bytes(), &serialized[18..26]);
+        assert_eq!(&10u64.to_le_bytes(), &serialized[3..11]);
+        assert_eq!(&20u64.to_le_bytes(), &serialized[11..19]);
+        assert_eq!(&30u64.to_le_bytes(), &serialized[19..27]);
 
         // Next 2 bytes: child_ids_len (4) = [4, 0] in little-endian
-        assert_eq!(&[4, 0], &serialized[26..28]);
+        assert_eq!(&[4, 0], &serialized[27..29]);
 
         // Next 32 bytes: 4 PageIDs (8 bytes each)
-        assert_eq!(&100u64.to_le_bytes(), &serialized[28..36]);
-        assert_eq!(&200u64.to_le_bytes(), &serialized[36..44]);
-        assert_eq!(&300u64.to_le_bytes(), &serialized[44..52]);
-        assert_eq!(&400u64.to_le_bytes(), &serialized[52..60]);
+        assert_eq!(&100u64.to_le_bytes(), &serialized[29..37]);
+        assert_eq!(&200u64.to_le_bytes(), &serialized[37..45]);
+        assert_eq!(&300u64.to_le_bytes(), &serialized[45..53]);
+        assert_eq!(&400u64.to_le_bytes(), &serialized[53..61]);
 
         // Deserialize back to a FreeListInternalNode using from_slice
         let deserialized = FreeListInternalNode::from_slice(&serialized)
@@ -587,29 +986,30 @@ mod tests {
     fn test_freelist_tsn_leaf_serialize() {
         // Create a FreeListTsnLeafNode with known values
         let node = FreeListTsnLeafNode {
-            page_ids: vec![PageID(11), PageID(22), PageID(33), PageID(44)],
+            page_ids: vec![PageID(11), PageID(22), PageID(33), PageID(44)].into(),
         };
 
         // Serialize
         let mut serialized = vec![0u8; node.calc_serialized_size()];
         node.serialize_into(&mut serialized);
 
-        // Validate structure: 2 bytes length, then 4 page IDs
-        assert_eq!(&[4, 0], &serialized[0..2]);
-        assert_eq!(&11u64.to_le_bytes(), &serialized[2..10]);
-        assert_eq!(&22u64.to_le_bytes(), &serialized[10..18]);
-        assert_eq!(&33u64.to_le_bytes(), &serialized[18..26]);
-        assert_eq!(&44u64.to_le_bytes(), &serialized[26..34]);
+        // Validate structure: format version, 2 bytes length, then 4 page IDs
+        assert_eq!(FREE_LIST_LEAF_FORMAT_VERSION, serialized[0]);
+        assert_eq!(&[4, 0], &serialized[1..3]);
+        assert_eq!(&11u64.to_le_bytes(), &serialized[3..11]);
+        assert_eq!(&22u64.to_le_bytes(), &serialized[11..19]);
+        assert_eq!(&33u64.to_le_bytes(), &serialized[19..27]);
+        assert_eq!(&44u64.to_le_bytes(), &serialized[27..35]);
 
         // Deserialize and round-trip compare
         let deserialized = FreeListTsnLeafNode::from_slice(&serialized)
             .expect("Failed to deserialize FreeListTsnLeafNode");
         assert_eq!(node, deserialized);
         assert_eq!(4, deserialized.page_ids.len());
-        assert_eq!(PageID(11), deserialized.page_ids[0]);
-        assert_eq!(PageID(22), deserialized.page_ids[1]);
-        assert_eq!(PageID(33), deserialized.page_ids[2]);
-        assert_eq!(PageID(44), deserialized.page_ids[3]);
+        assert_eq!(PageID(11), deserialized.page_ids.to_vec()[0]);
+        assert_eq!(PageID(22), deserialized.page_ids.to_vec()[1]);
+        assert_eq!(PageID(33), deserialized.page_ids.to_vec()[2]);
+        assert_eq!(PageID(44), deserialized.page_ids.to_vec()[3]);
     }
 
     #[test]
@@ -625,17 +1025,18 @@ mod tests {
         node.serialize_into(&mut serialized);
 
         // Validate structure
+        assert_eq!(NODE_FORMAT_VERSION, serialized[0]);
         // keys len = 3
-        assert_eq!(&[3, 0], &serialized[0..2]);
-        assert_eq!(&5u64.to_le_bytes(), &serialized[2..10]);
-        assert_eq!(&15u64.to_le_bytes(), &serialized[10..18]);
-        assert_eq!(&25u64.to_le_bytes(), &serialized[18..26]);
+        assert_eq!(&[3, 0], &serialized[1..3]);
+        assert_eq!(&5u64.to_le_bytes(), &serialized[3..11]);
+        assert_eq!(&15u64.to_le_bytes(), &serialized[11..19]);
+        assert_eq!(&25u64.to_le_bytes(), &serialized[19..27]);
         // children len = 4
-        assert_eq!(&[4, 0], &serialized[26..28]);
-        assert_eq!(&1000u64.to_le_bytes(), &serialized[28..36]);
-        assert_eq!(&2000u64.to_le_bytes(), &serialized[36..44]);
-        assert_eq!(&3000u64.to_le_bytes(), &serialized[44..52]);
-        assert_eq!(&4000u64.to_le_bytes(), &serialized[52..60]);
+        assert_eq!(&[4, 0], &serialized[27..29]);
+        assert_eq!(&1000u64.to_le_bytes(), &serialized[29..37]);
+        assert_eq!(&2000u64.to_le_bytes(), &serialized[37..45]);
+        assert_eq!(&3000u64.to_le_bytes(), &serialized[45..53]);
+        assert_eq!(&4000u64.to_le_bytes(), &serialized[53..61]);
 
         // Deserialize and round-trip compare
         let deserialized = FreeListTsnInternalNode::from_slice(&serialized)
@@ -651,4 +1052,397 @@ mod tests {
         assert_eq!(PageID(3000), deserialized.child_ids[2]);
         assert_eq!(PageID(4000), deserialized.child_ids[3]);
     }
+
+    #[test]
+    fn test_freelist_contiguous_page_ids_collapse_to_one_range() {
+        let page_ids: Vec<PageID> = (100..1100).map(PageID).collect();
+        assert_eq!(
+            vec![PageIdRange {
+                start: PageID(100),
+                count: 1000
+            }],
+            PageIdRanges::from(page_ids.clone()).ranges()
+        );
+
+        // A TSN leaf of a thousand freed pages encodes as a single range entry
+        let node = FreeListTsnLeafNode {
+            page_ids: page_ids.clone().into(),
+        };
+        assert_eq!(1 + 2 + 12, node.calc_serialized_size());
+        let mut serialized = vec![0u8; node.calc_serialized_size()];
+        assert_eq!(serialized.len(), node.serialize_into(&mut serialized));
+        assert_eq!(&[1, 0], &serialized[1..3]);
+        assert_eq!(node, FreeListTsnLeafNode::from_slice(&serialized).unwrap());
+
+        // As does the inline list of a leaf value, alongside single page IDs
+        let leaf = FreeListLeafNode {
+            keys: vec![Tsn(1), Tsn(2)],
+            values: vec![
+                FreeListLeafValue {
+                    page_ids: page_ids.clone().into(),
+                    root_id: PageID(0),
+                },
+                FreeListLeafValue {
+                    page_ids: vec![PageID(3), PageID(4), PageID(7), PageID(9)].into(),
+                    root_id: PageID(0),
+                },
+            ],
+        };
+        // Keys, then a range, then a range of two, two singles and root IDs
+        assert_eq!(
+            1 + 2 + 16 + (2 + 12 + 8) + (2 + 12 + 8 + 8 + 8),
+            leaf.calc_serialized_size()
+        );
+        let mut serialized = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut serialized);
+        assert_eq!(leaf, FreeListLeafNode::from_slice(&serialized).unwrap());
+
+        // The allocator carves single pages off the front of a range
+        let mut range = PageIdRanges::from(page_ids.clone()).ranges()[0];
+        assert_eq!(Some(PageID(100)), range.pop_first());
+        assert_eq!(Some(PageID(101)), range.pop_first());
+        assert_eq!(PageID(102), range.start);
+        assert_eq!(998, range.count);
+    }
+
+    #[test]
+    fn test_freelist_leaf_nodes_read_version_1() {
+        // Version 1 lists each page ID, and has no range entries
+        let mut slice = vec![NODE_FORMAT_VERSION, 2, 0];
+        slice.extend_from_slice(&5u64.to_le_bytes());
+        slice.extend_from_slice(&6u64.to_le_bytes());
+        assert_eq!(
+            vec![PageID(5), PageID(6)],
+            FreeListTsnLeafNode::from_slice(&slice)
+                .unwrap()
+                .page_ids
+                .to_vec()
+        );
+
+        let mut slice = vec![NODE_FORMAT_VERSION, 1, 0];
+        slice.extend_from_slice(&3u64.to_le_bytes());
+        slice.extend_from_slice(&[1, 0]);
+        slice.extend_from_slice(&8u64.to_le_bytes());
+        slice.extend_from_slice(&0u64.to_le_bytes());
+        let leaf = FreeListLeafNode::from_slice(&slice).unwrap();
+        assert_eq!(vec![Tsn(3)], leaf.keys);
+        assert_eq!(vec![PageID(8)], leaf.values[0].page_ids.to_vec());
+    }
+
+    #[test]
+    fn test_freelist_nodes_reject_invalid_ranges() {
+        let range = |start: u64, count: u32| {
+            let mut entry = (start | (1 << 63)).to_le_bytes().to_vec();
+            entry.extend_from_slice(&count.to_le_bytes());
+            entry
+        };
+        let tsn_leaf = |entries: &[Vec<u8>]| {
+            let mut slice = vec![FREE_LIST_LEAF_FORMAT_VERSION];
+            slice.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            slice.extend(entries.concat());
+            FreeListTsnLeafNode::from_slice(&slice)
+        };
+        assert_eq!(
+            (10..15).chain(20..21).map(PageID).collect::<Vec<_>>(),
+            tsn_leaf(&[range(10, 5), range(20, 1)])
+                .unwrap()
+                .page_ids
+                .to_vec()
+        );
+        for entries in [
+            vec![range(10, 0)],
+            vec![range(u64::MAX >> 1, 2)],
+            vec![range(20, 1), range(10, 5)],
+            vec![range(10, 5), range(12, 1)],
+        ] {
+            match tsn_leaf(&entries) {
+                Err(DCBError::DeserializationError(_)) => {}
+                other => panic!("Expected DeserializationError, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_freelist_page_id_ranges_split_and_join() {
+        let mut page_ids: PageIdRanges = (1..=5).map(PageID).collect();
+        assert_eq!(1, page_ids.ranges().len());
+        assert_eq!(2 + 12, page_ids.serialized_size());
+
+        // Removing from the middle splits the range, and re-inserting joins it
+        assert!(page_ids.remove(PageID(3)));
+        assert_eq!(
+            &[
+                PageIdRange {
+                    start: PageID(1),
+                    count: 2
+                },
+                PageIdRange {
+                    start: PageID(4),
+                    count: 2
+                }
+            ],
+            page_ids.ranges()
+        );
+        assert_eq!(4, page_ids.len());
+        assert_eq!(2 + 12 + 12, page_ids.serialized_size());
+        assert!(!page_ids.contains(PageID(3)));
+        assert!(page_ids.insert(PageID(3)));
+        assert!(!page_ids.insert(PageID(3)));
+        assert_eq!(1, page_ids.ranges().len());
+        assert_eq!(5, page_ids.len());
+
+        // Single page IDs either side extend the range
+        assert!(page_ids.insert(PageID(0)));
+        assert!(page_ids.insert(PageID(6)));
+        assert!(page_ids.insert(PageID(9)));
+        assert_eq!(2 + 12 + 8, page_ids.serialized_size());
+        assert_eq!(Some(PageID(9)), page_ids.pop_last());
+        assert_eq!(Some(PageID(6)), page_ids.pop_last());
+        assert_eq!((0..6).map(PageID).collect::<Vec<_>>(), page_ids.to_vec());
+        let right = page_ids.split_off_ranges(1);
+        assert!(right.is_empty());
+    }
+
+    #[test]
+    fn test_freelist_nodes_reject_unknown_format_version() {
+        let node = FreeListTsnLeafNode {
+            page_ids: vec![PageID(11)].into(),
+        };
+        let mut serialized = vec![0u8; node.calc_serialized_size()];
+        node.serialize_into(&mut serialized);
+        serialized[0] = 9;
+
+        match FreeListTsnLeafNode::from_slice(&serialized) {
+            Err(DCBError::DeserializationError(msg)) => assert!(msg.contains("version 9")),
+            other => panic!("Expected DeserializationError, got {other:?}"),
+        }
+        assert!(FreeListLeafNode::from_slice(&serialized).is_err());
+        assert!(FreeListInternalNode::from_slice(&serialized).is_err());
+        assert!(FreeListTsnInternalNode::from_slice(&serialized).is_err());
+    }
+
+    #[test]
+    fn test_freelist_leaf_value_remove_page_id() {
+        let new_value = || FreeListLeafValue {
+            page_ids: vec![PageID(3), PageID(5), PageID(7), PageID(9)].into(),
+            root_id: PageID(0),
+        };
+
+        let mut value = new_value();
+        assert!(value.remove_page_id(PageID(3)));
+        assert_eq!(
+            vec![PageID(5), PageID(7), PageID(9)],
+            value.page_ids.to_vec()
+        );
+
+        let mut value = new_value();
+        assert!(value.remove_page_id(PageID(7)));
+        assert_eq!(
+            vec![PageID(3), PageID(5), PageID(9)],
+            value.page_ids.to_vec()
+        );
+
+        let mut value = new_value();
+        assert!(value.remove_page_id(PageID(9)));
+        assert_eq!(
+            vec![PageID(3), PageID(5), PageID(7)],
+            value.page_ids.to_vec()
+        );
+
+        let mut value = new_value();
+        assert!(!value.remove_page_id(PageID(4)));
+        assert_eq!(new_value(), value);
+    }
+
+    #[test]
+    fn test_freelist_tsn_leaf_remove_page_id() {
+        let mut leaf = FreeListTsnLeafNode {
+            page_ids: vec![PageID(3), PageID(7), PageID(9)].into(),
+        };
+        assert!(!leaf.is_empty());
+        assert!(leaf.remove_page_id(PageID(3)));
+        assert_eq!(vec![PageID(7), PageID(9)], leaf.page_ids.to_vec());
+        assert!(!leaf.remove_page_id(PageID(3)));
+        assert!(!leaf.remove_page_id(PageID(4)));
+        assert!(leaf.remove_page_id(PageID(9)));
+        assert!(!leaf.is_empty());
+        assert!(leaf.remove_page_id(PageID(7)));
+        assert!(leaf.is_empty());
+        assert!(!leaf.remove_page_id(PageID(7)));
+    }
+
+    #[test]
+    fn test_freelist_leaf_value_pop_any() {
+        let mut value = FreeListLeafValue {
+            page_ids: vec![PageID(3), PageID(7)].into(),
+            root_id: PageID(0),
+        };
+        let mut popped = vec![value.pop_any().unwrap(), value.pop_any().unwrap()];
+        popped.sort();
+        assert_eq!(vec![PageID(3), PageID(7)], popped);
+        assert_eq!(None, value.pop_any());
+    }
+
+    #[test]
+    fn test_freelist_leaf_push_new_page_id_keeps_page_ids_sorted() {
+        let mut leaf = FreeListLeafNode {
+            keys: vec![],
+            values: vec![],
+        };
+        leaf.push_new_key_and_value(Tsn(1), PageID(8));
+        leaf.push_new_page_id(0, PageID(3));
+        leaf.push_new_page_id(0, PageID(12));
+        leaf.push_new_page_id(0, PageID(5));
+        leaf.push_new_page_id(0, PageID(5));
+        assert_eq!(
+            vec![PageID(3), PageID(5), PageID(8), PageID(12)],
+            leaf.values[0].page_ids.to_vec()
+        );
+
+        let mut serialized = vec![0u8; leaf.calc_serialized_size()];
+        leaf.serialize_into(&mut serialized);
+        assert_eq!(leaf, FreeListLeafNode::from_slice(&serialized).unwrap());
+    }
+
+    #[test]
+    fn test_freelist_would_fit_n_page_ids_matches_repeated_single_adds() {
+        let max_node_size = 256;
+        // Spaced page IDs are each listed singly, growing the node by the most
+        let spaced = |i: u64| PageID(100 + 2 * i);
+
+        let mut leaf = FreeListLeafNode {
+            keys: vec![Tsn(1)],
+            values: vec![FreeListLeafValue {
+                page_ids: vec![PageID(10)].into(),
+                root_id: PageID(0),
+            }],
+        };
+        let mut grown = leaf.clone();
+        let mut fitted = 0;
+        while grown.would_fit_new_page_id(max_node_size) {
+            grown.push_new_page_id(0, spaced(fitted as u64));
+            fitted += 1;
+        }
+        assert!(fitted > 0);
+        for n in 0..=fitted {
+            assert!(leaf.would_fit_n_page_ids(n, max_node_size), "n = {n}");
+        }
+        assert!(!leaf.would_fit_n_page_ids(fitted + 1, max_node_size));
+        leaf.push_new_page_id(0, PageID(12));
+        assert!(!leaf.would_fit_n_page_ids(fitted, max_node_size));
+
+        let tsn_leaf = FreeListTsnLeafNode {
+            page_ids: vec![PageID(10)].into(),
+        };
+        let mut grown = tsn_leaf.clone();
+        let mut fitted = 0;
+        while grown.would_fit_new_page_id(max_node_size) {
+            grown.page_ids.insert(spaced(fitted as u64));
+            fitted += 1;
+        }
+        assert!(fitted > 0);
+        for n in 0..=fitted {
+            assert!(tsn_leaf.would_fit_n_page_ids(n, max_node_size), "n = {n}");
+        }
+        assert!(!tsn_leaf.would_fit_n_page_ids(fitted + 1, max_node_size));
+    }
+
+    #[test]
+    fn test_freelist_leaf_value_contains() {
+        let mut value = FreeListLeafValue {
+            page_ids: vec![].into(),
+            root_id: PageID(0),
+        };
+        assert!(!value.contains(PageID(1)));
+        for id in [9, 2, 6] {
+            value.insert_page_id(PageID(id));
+        }
+        assert!(value.contains(PageID(2)));
+        assert!(value.contains(PageID(6)));
+        assert!(value.contains(PageID(9)));
+        assert!(!value.contains(PageID(1)));
+        assert!(!value.contains(PageID(7)));
+        assert!(!value.contains(PageID(10)));
+    }
+
+    #[test]
+    fn test_freelist_leaf_merge() {
+        let value = |id: u64| FreeListLeafValue {
+            page_ids: vec![PageID(id)].into(),
+            root_id: PageID(0),
+        };
+        let mut left = FreeListLeafNode {
+            keys: vec![Tsn(1), Tsn(3)],
+            values: vec![value(10), value(30)],
+        };
+        let right = FreeListLeafNode {
+            keys: vec![Tsn(4), Tsn(7)],
+            values: vec![value(40), value(70)],
+        };
+        left.merge(right);
+        assert_eq!(vec![Tsn(1), Tsn(3), Tsn(4), Tsn(7)], left.keys);
+        assert_eq!(
+            vec![value(10), value(30), value(40), value(70)],
+            left.values
+        );
+    }
+
+    #[test]
+    fn test_freelist_internal_merge_pulls_down_separator() {
+        let mut left = FreeListInternalNode {
+            keys: vec![Tsn(5)],
+            child_ids: vec![PageID(1), PageID(2)],
+        };
+        let right = FreeListInternalNode {
+            keys: vec![Tsn(15)],
+            child_ids: vec![PageID(3), PageID(4)],
+        };
+        left.merge(Tsn(10), right);
+        assert_eq!(vec![Tsn(5), Tsn(10), Tsn(15)], left.keys);
+        assert_eq!(
+            vec![PageID(1), PageID(2), PageID(3), PageID(4)],
+            left.child_ids
+        );
+    }
+
+    #[test]
+    fn test_freelist_internal_split_off_rejects_fewer_than_two_keys() {
+        let mut empty = FreeListInternalNode {
+            keys: vec![],
+            child_ids: vec![PageID(1)],
+        };
+        assert!(matches!(
+            empty.split_off(),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+
+        let mut one_key = FreeListInternalNode {
+            keys: vec![Tsn(5)],
+            child_ids: vec![PageID(1), PageID(2)],
+        };
+        assert!(matches!(
+            one_key.split_off(),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+        assert_eq!(vec![Tsn(5)], one_key.keys);
+    }
+
+    #[test]
+    fn test_freelist_leaf_pop_last_key_and_value_when_empty() {
+        let mut leaf = FreeListLeafNode {
+            keys: vec![Tsn(1)],
+            values: vec![FreeListLeafValue {
+                page_ids: vec![PageID(10)].into(),
+                root_id: PageID(0),
+            }],
+        };
+        let (tsn, value) = leaf.pop_last_key_and_value().unwrap();
+        assert_eq!(Tsn(1), tsn);
+        assert_eq!(vec![PageID(10)], value.page_ids.to_vec());
+
+        assert!(matches!(
+            leaf.pop_last_key_and_value(),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+    }
 }
@@ -1,8 +1,17 @@
 use crate::common::Position;
 use crate::common::{PageID, Tsn};
+use crate::encryption::{ENCRYPTION_PARAMS_SIZE, EncryptionParams};
 use byteorder::{ByteOrder, LittleEndian};
 use umadb_dcb::{DCBError, DCBResult};
 
+/// Size of a serialized HeaderNode of an unencrypted database.
+pub const HEADER_NODE_SIZE: usize = 54;
+
+// Headers written before the page size was recorded end after next_position, and
+// those written before the overflow chunk size was recorded end after page_size
+const HEADER_NODE_SIZE_WITHOUT_PAGE_SIZE: usize = 48;
+const HEADER_NODE_SIZE_WITHOUT_OVERFLOW_CHUNK_SIZE: usize = 52;
+
 // Node type definitions
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HeaderNode {
@@ -12,6 +21,14 @@ pub struct HeaderNode {
     pub tags_tree_root_id: PageID,
     pub next_page_id: PageID,
     pub next_position: Position,
+    /// Page size the database was created with, or 0 if the header predates it.
+    pub page_size: u32,
+    /// Fewest data bytes per node, on average, that overflow chains were written
+    /// with, or 0 if overflow nodes fill their pages, as they did before this was
+    /// recorded.
+    pub overflow_chunk_size: u16,
+    /// How the pages are encrypted, or None if they aren't.
+    pub encryption: Option<EncryptionParams>,
 }
 
 impl Default for HeaderNode {
@@ -23,17 +40,30 @@ impl Default for HeaderNode {
             tags_tree_root_id: PageID(0),
             next_page_id: PageID(0),
             next_position: Position(0),
+            page_size: 0,
+            overflow_chunk_size: 0,
+            encryption: None,
         }
     }
 }
 
 impl HeaderNode {
-    /// Writes the serialized HeaderNode into the provided buffer and returns the number of bytes written (48).
-    /// The buffer must be at least 48 bytes long.
+    /// Returns the size of the serialized HeaderNode, which is 54 bytes, followed by
+    /// the encryption parameters of an encrypted database.
+    pub fn serialized_size(&self) -> usize {
+        match self.encryption {
+            Some(_) => HEADER_NODE_SIZE + ENCRYPTION_PARAMS_SIZE,
+            None => HEADER_NODE_SIZE,
+        }
+    }
+
+    /// Writes the serialized HeaderNode into the provided buffer and returns the number of bytes written.
+    /// The buffer must be at least `serialized_size()` bytes long.
     pub fn serialize_into(&self, buf: &mut [u8]) -> usize {
         assert!(
-            buf.len() >= 48,
-            "HeaderNode::serialize_into dst must be at least 48 bytes"
+            buf.len() >= self.serialized_size(),
+            "HeaderNode::serialize_into dst must be at least {} bytes",
+            self.serialized_size()
         );
         // Write fields in little-endian order
         buf[0..8].copy_from_slice(&self.tsn.0.to_le_bytes());
@@ -42,17 +72,29 @@ impl HeaderNode {
         buf[24..32].copy_from_slice(&self.events_tree_root_id.0.to_le_bytes());
         buf[32..40].copy_from_slice(&self.tags_tree_root_id.0.to_le_bytes());
         buf[40..48].copy_from_slice(&self.next_position.0.to_le_bytes());
-        48
+        buf[48..52].copy_from_slice(&self.page_size.to_le_bytes());
+        buf[52..54].copy_from_slice(&self.overflow_chunk_size.to_le_bytes());
+        if let Some(encryption) = &self.encryption {
+            encryption.serialize_into(&mut buf[HEADER_NODE_SIZE..]);
+        }
+        self.serialized_size()
     }
 
     /// Creates a HeaderNode from a byte slice
-    /// Expects a slice with 48 bytes:
+    /// Expects a slice with 54 bytes:
     /// - 8 bytes for tsn
     /// - 8 bytes for next_page_id
     /// - 8 bytes for free_lists_tree_root_id
     /// - 8 bytes for events_tree_root_id
     /// - 8 bytes for tags_tree_root_id
     /// - 8 bytes for next_position
+    /// - 4 bytes for page_size
+    /// - 2 bytes for overflow_chunk_size
+    ///
+    /// followed by the encryption parameters of an encrypted database.
+    ///
+    /// A slice with 48 bytes, from before the page size was recorded, has a page_size of 0,
+    /// and one with 52 bytes or fewer has an overflow_chunk_size of 0.
     ///
     /// # Arguments
     /// * `slice` - The byte slice to deserialize from
@@ -60,9 +102,16 @@ impl HeaderNode {
     /// # Returns
     /// * `Result<Self>` - The deserialized HeaderNode or an error
     pub fn from_slice(slice: &[u8]) -> DCBResult<Self> {
-        if slice.len() != 48 {
+        if ![
+            HEADER_NODE_SIZE + ENCRYPTION_PARAMS_SIZE,
+            HEADER_NODE_SIZE,
+            HEADER_NODE_SIZE_WITHOUT_OVERFLOW_CHUNK_SIZE,
+            HEADER_NODE_SIZE_WITHOUT_PAGE_SIZE,
+        ]
+        .contains(&slice.len())
+        {
             return Err(DCBError::DeserializationError(format!(
-                "Expected 48 bytes, got {}",
+                "Expected {HEADER_NODE_SIZE} bytes, got {}",
                 slice.len()
             )));
         }
@@ -73,6 +122,21 @@ impl HeaderNode {
         let position_root_id = LittleEndian::read_u64(&slice[24..32]);
         let tags_root_id = LittleEndian::read_u64(&slice[32..40]);
         let next_position = LittleEndian::read_u64(&slice[40..48]);
+        let page_size = if slice.len() >= HEADER_NODE_SIZE_WITHOUT_OVERFLOW_CHUNK_SIZE {
+            LittleEndian::read_u32(&slice[48..52])
+        } else {
+            0
+        };
+        let overflow_chunk_size = if slice.len() >= HEADER_NODE_SIZE {
+            LittleEndian::read_u16(&slice[52..54])
+        } else {
+            0
+        };
+        let encryption = if slice.len() > HEADER_NODE_SIZE {
+            Some(EncryptionParams::from_slice(&slice[HEADER_NODE_SIZE..])?)
+        } else {
+            None
+        };
 
         Ok(HeaderNode {
             tsn: Tsn(tsn),
@@ -81,6 +145,9 @@ impl HeaderNode {
             events_tree_root_id: PageID(position_root_id),
             tags_tree_root_id: PageID(tags_root_id),
             next_position: Position(next_position),
+            page_size,
+            overflow_chunk_size,
+            encryption,
         })
     }
 }
@@ -98,14 +165,17 @@ mod tests {
             events_tree_root_id: PageID(789),
             tags_tree_root_id: PageID(321),
             next_position: Position(9876543210),
+            page_size: 4096,
+            overflow_chunk_size: 4000,
+            encryption: None,
         };
 
         // Serialize the HeaderNode
-        let mut serialized = [0u8; 48];
+        let mut serialized = [0u8; HEADER_NODE_SIZE];
         header_node.serialize_into(&mut serialized);
 
         // Verify the serialized output has the correct length
-        assert_eq!(48, serialized.len());
+        assert_eq!(54, serialized.len());
 
         // Verify the serialized output has the correct byte values
         // TSN(42) = 42u64 = [42, 0, 0, 0, 0, 0, 0, 0] in little-endian
@@ -126,6 +196,9 @@ mod tests {
         // next_position 9876543210u64 => little-endian bytes
         assert_eq!(&9876543210u64.to_le_bytes(), &serialized[40..48]);
 
+        // page_size 4096u32
+        assert_eq!(&4096u32.to_le_bytes(), &serialized[48..52]);
+
         // Deserialize back to a HeaderNode
         let deserialized =
             HeaderNode::from_slice(&serialized).expect("Failed to deserialize HeaderNode");
@@ -142,5 +215,68 @@ mod tests {
             deserialized.events_tree_root_id
         );
         assert_eq!(header_node.next_position, deserialized.next_position);
+        assert_eq!(header_node.page_size, deserialized.page_size);
+        assert_eq!(
+            header_node.overflow_chunk_size,
+            deserialized.overflow_chunk_size
+        );
+    }
+
+    #[test]
+    fn test_header_deserialize_without_page_size() {
+        let header_node = HeaderNode {
+            tsn: Tsn(7),
+            next_page_id: PageID(8),
+            free_lists_tree_root_id: PageID(2),
+            events_tree_root_id: PageID(3),
+            tags_tree_root_id: PageID(4),
+            next_position: Position(5),
+            page_size: 4096,
+            overflow_chunk_size: 4000,
+            encryption: None,
+        };
+        let mut serialized = [0u8; HEADER_NODE_SIZE];
+        header_node.serialize_into(&mut serialized);
+
+        // Older headers end before the page size
+        let deserialized = HeaderNode::from_slice(&serialized[..48]).unwrap();
+        assert_eq!(
+            HeaderNode {
+                page_size: 0,
+                overflow_chunk_size: 0,
+                ..header_node.clone()
+            },
+            deserialized
+        );
+        let deserialized = HeaderNode::from_slice(&serialized[..52]).unwrap();
+        assert_eq!(
+            HeaderNode {
+                overflow_chunk_size: 0,
+                ..header_node
+            },
+            deserialized
+        );
+        assert!(HeaderNode::from_slice(&serialized[..40]).is_err());
+    }
+
+    #[test]
+    fn test_header_with_encryption_round_trip() {
+        let header_node = HeaderNode {
+            tsn: Tsn(7),
+            page_size: 4096,
+            encryption: Some(EncryptionParams {
+                kdf_iterations: 1000,
+                kdf_salt: [3; 16],
+                file_id: 42,
+                key_check: [9; 16],
+            }),
+            ..HeaderNode::default()
+        };
+        let mut serialized = vec![0u8; header_node.serialized_size()];
+        assert_eq!(
+            HEADER_NODE_SIZE + ENCRYPTION_PARAMS_SIZE,
+            header_node.serialize_into(&mut serialized)
+        );
+        assert_eq!(header_node, HeaderNode::from_slice(&serialized).unwrap());
     }
 }
@@ -2,6 +2,7 @@
 
 pub mod common;
 pub mod db;
+pub mod encryption;
 pub mod events_tree;
 pub mod events_tree_nodes;
 pub mod free_lists_tree_nodes;
@@ -12,3 +13,5 @@ pub mod page;
 pub mod pager;
 pub mod tags_tree;
 pub mod tags_tree_nodes;
+pub mod verify;
+pub mod wal;
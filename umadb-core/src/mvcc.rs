@@ -1,40 +1,464 @@
 // use std::cell::RefCell;
 use crate::common::Position;
-use crate::common::{PageID, Tsn};
-use crate::events_tree_nodes::EventLeafNode;
+use crate::common::{PageID, SplitStrategy, Tsn};
+use crate::encryption::{ENCRYPTION_OVERHEAD, EncryptionParams, PageCipher};
+use crate::events_tree::EventIterator;
+use crate::events_tree_nodes::{EventLeafNode, EventValue, OverflowWriter};
 use crate::free_lists_tree_nodes::{
-    FreeListInternalNode, FreeListLeafNode, FreeListLeafValue, FreeListTsnLeafNode,
+    FreeListInternalNode, FreeListLeafNode, FreeListLeafValue, FreeListTsnLeafNode, PageIdRange,
+    PageIdRanges,
 };
 use crate::header_node::HeaderNode;
 use crate::node::Node;
-use crate::page::{PAGE_HEADER_SIZE, Page, serialize_page_into};
-use crate::pager::Pager;
+use crate::page::{Page, max_node_size, node_bytes, serialize_page_into};
+use crate::pager::{GrowthPolicy, MemoryPager, PageStore, Pager};
 use crate::tags_tree_nodes::TagsLeafNode;
-use umadb_dcb::{DCBError, DCBResult};
+use crate::wal::{Checkpointer, Wal, WalConfig};
+use umadb_dcb::ndjson::{parse_ndjson_event, write_ndjson_event};
+use umadb_dcb::{DCBError, DCBEvent, DCBResult, DbStats};
 // use rayon::prelude::*;
 // use std::os::unix::fs::FileExt; // For write_at on Unix
 use dashmap::DashMap;
-use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 // use crate::db::DEFAULT_PAGE_SIZE;
 
 const GET_LATEST_HEADER_RETRIES: usize = 5;
 const GET_LATEST_HEADER_DELAY: Duration = Duration::from_millis(10);
+// Commits alternate between two header pages, so a crash while one is being written
+// leaves the other intact. The TSN is the generation of a header: the latest commit
+// is the header with the higher TSN that passes its page checksum.
 const HEADER_PAGE_ID_0: PageID = PageID(0);
 const HEADER_PAGE_ID_1: PageID = PageID(1);
+// Inline data lengths are serialized as a u16
+const MAX_INLINE_THRESHOLD: usize = u16::MAX as usize;
 
 // thread_local! {
 //     static PAGE_BUF: RefCell<Vec<u8>> = RefCell::new(vec![0u8; DEFAULT_PAGE_SIZE]);
 // }
 
+/// How commits are synced to disk.
+///
+/// A commit writes the dirty pages, syncs, writes the new header, and syncs again,
+/// so that a header is never durable before the pages it points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurabilityMode {
+    /// Never sync. Fastest, but after a crash or power loss the database may
+    /// revert to an older commit, or be left with a header pointing at pages
+    /// that were never written. A process crash alone loses nothing, since the
+    /// writes are already in the OS page cache.
+    NoSync,
+    /// Sync with fdatasync, which skips metadata such as the modification time
+    /// but still syncs the file size. Commits survive power loss. Falls back to
+    /// fsync where fdatasync isn't available.
+    SyncData,
+    /// Sync file data and metadata with fsync. Commits survive power loss.
+    #[default]
+    Sync,
+}
+
+/// Which superseded commits keep their snapshots, so that they can be read with
+/// Mvcc::reader_as_of(). The pages of a retained snapshot aren't reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetentionPolicy {
+    /// Only the latest commit, and pages are reused once no reader holds them.
+    #[default]
+    Latest,
+    /// The latest `n` commits.
+    Commits(usize),
+    /// The commits made within the duration, and the latest one.
+    Duration(Duration),
+}
+
+/// Settings for group commit, where concurrent commits share one header write
+/// and one pair of syncs, then all return together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCommitConfig {
+    /// Largest number of commits synced together.
+    pub max_batch_size: usize,
+    /// How long the first commit of a batch waits for others to join it.
+    pub linger: Duration,
+}
+
+impl Default for GroupCommitConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 64,
+            linger: Duration::from_micros(500),
+        }
+    }
+}
+
+// Commit queue shared by writers when group commit is enabled
+struct GroupCommit {
+    config: GroupCommitConfig,
+    state: Mutex<GroupCommitState>,
+    cond: Condvar,
+}
+
+struct GroupCommitState {
+    // A writer has been issued and hasn't committed or been dropped yet
+    writer_active: bool,
+    // Latest commit whose pages are written but whose header isn't synced yet,
+    // which the next writer builds on
+    pending: Option<(PageID, HeaderNode)>,
+    // Number of commits queued since the last batch was taken
+    pending_count: usize,
+    // Latest header that has been synced, and the header page it is in. Each batch
+    // writes the other header page, so a torn write can't lose acknowledged commits.
+    synced: Option<(PageID, HeaderNode)>,
+    // A commit is collecting or syncing a batch
+    leading: bool,
+    // Incremented when syncing a batch fails. The writers issued before then built
+    // on the failed commits, so they fail too, with the error kept here.
+    epoch: u64,
+    failure: Option<String>,
+}
+
+impl GroupCommitState {
+    fn synced_tsn(&self) -> Tsn {
+        self.synced
+            .as_ref()
+            .map_or(Tsn(0), |(_, header)| header.tsn)
+    }
+
+    fn check_epoch(&self, epoch: u64) -> DCBResult<()> {
+        if epoch == self.epoch {
+            return Ok(());
+        }
+        Err(DCBError::InternalError(format!(
+            "Group commit failed: {}",
+            self.failure.as_deref().unwrap_or("unknown error")
+        )))
+    }
+}
+
+impl GroupCommit {
+    fn new(config: GroupCommitConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(GroupCommitState {
+                writer_active: false,
+                pending: None,
+                pending_count: 0,
+                synced: None,
+                leading: false,
+                epoch: 0,
+                failure: None,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Waits until no other writer is active, then returns the epoch and the pending
+    // commit to build on
+    fn acquire_writer(&self) -> (u64, Option<(PageID, HeaderNode)>) {
+        let mut state = self.state.lock().unwrap();
+        while state.writer_active {
+            state = self.cond.wait(state).unwrap();
+        }
+        state.writer_active = true;
+        (state.epoch, state.pending.clone())
+    }
+
+    fn release_writer(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.writer_active = false;
+        self.cond.notify_all();
+    }
+
+    // Records the latest header on disk, when no commits are pending
+    fn set_synced(&self, page_id: PageID, header: &HeaderNode) {
+        let mut state = self.state.lock().unwrap();
+        if header.tsn >= state.synced_tsn() {
+            state.synced = Some((page_id, header.clone()));
+        }
+    }
+
+    // Starts again from a header rewritten to both header pages, as by compaction
+    fn restart(&self, header: &HeaderNode) {
+        let mut state = self.state.lock().unwrap();
+        state.pending = None;
+        state.synced = Some((HEADER_PAGE_ID_0, header.clone()));
+    }
+
+    // Pages freed after this Tsn are still part of the latest synced commit
+    fn guard_tsn(&self) -> Option<Tsn> {
+        let state = self.state.lock().unwrap();
+        state.pending.as_ref().map(|_| state.synced_tsn())
+    }
+
+    // Queues a commit whose pages are written, then waits until its header is synced.
+    // Returns the header page the header of its batch was written to.
+    fn commit(
+        &self,
+        mvcc: &Mvcc,
+        epoch: u64,
+        header_page_id: PageID,
+        header: HeaderNode,
+    ) -> DCBResult<PageID> {
+        let tsn = header.tsn;
+        let mut state = self.state.lock().unwrap();
+        state.writer_active = false;
+        self.cond.notify_all();
+        state.check_epoch(epoch)?;
+        state.pending = Some((header_page_id, header));
+        state.pending_count += 1;
+
+        loop {
+            state.check_epoch(epoch)?;
+            if let Some((page_id, synced)) = &state.synced
+                && synced.tsn >= tsn
+            {
+                return Ok(*page_id);
+            }
+            if state.leading {
+                state = self.cond.wait(state).unwrap();
+                continue;
+            }
+
+            // Lead the next batch, giving other commits a chance to join it
+            state.leading = true;
+            let deadline = Instant::now() + self.config.linger;
+            while state.pending_count < self.config.max_batch_size {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+            }
+            let (pending_page_id, header) = state
+                .pending
+                .clone()
+                .expect("batch should have a pending commit");
+            // Only the latest header of the batch is written, so write it to the
+            // header page that doesn't hold the last synced header
+            let page_id = match &state.synced {
+                Some((HEADER_PAGE_ID_0, _)) => HEADER_PAGE_ID_1,
+                Some(_) => HEADER_PAGE_ID_0,
+                None => pending_page_id,
+            };
+            state.pending_count = 0;
+            drop(state);
+
+            let result = mvcc.write_header_durably(page_id, &header);
+
+            state = self.state.lock().unwrap();
+            state.leading = false;
+            match result {
+                Ok(()) => {
+                    if state.pending.as_ref().map(|(_, h)| h.tsn) == Some(header.tsn) {
+                        state.pending = None;
+                    }
+                    state.synced = Some((page_id, header));
+                }
+                Err(err) => {
+                    // Fail the batch and the commits built on it, and let the next
+                    // writer build on the last synced header instead
+                    state.epoch += 1;
+                    state.failure = Some(err.to_string());
+                    state.pending = state.synced.clone();
+                    state.pending_count = 0;
+                }
+            }
+            self.cond.notify_all();
+        }
+    }
+}
+
+/// Counters for the page cache, see `Mvcc::with_page_cache`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PageCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+// Decoded pages, evicted least recently used first once they take up more than the
+// budget. Each page is counted as a whole page, whatever its node's size.
+struct PageCache {
+    budget: usize,
+    page_size: usize,
+    state: Mutex<PageCacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct PageCacheState {
+    // Pages and the tick they were last used at
+    pages: HashMap<PageID, (Page, u64)>,
+    // Page IDs by the tick they were last used at, oldest first
+    lru: BTreeMap<u64, PageID>,
+    tick: u64,
+}
+
+impl PageCache {
+    fn new(budget: usize, page_size: usize) -> Self {
+        Self {
+            budget,
+            page_size,
+            state: Mutex::new(PageCacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, page_id: PageID) -> Option<Page> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let PageCacheState { pages, lru, .. } = &mut *state;
+        match pages.get_mut(&page_id) {
+            Some((page, last_used)) => {
+                lru.remove(last_used);
+                lru.insert(tick, page_id);
+                *last_used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(page.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(&self, page: Page) {
+        let max_entries = self.budget / self.page_size;
+        if max_entries == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((_, last_used)) = state.pages.remove(&page.page_id) {
+            state.lru.remove(&last_used);
+        }
+        while state.pages.len() >= max_entries {
+            let Some((_, oldest)) = state.lru.pop_first() else {
+                break;
+            };
+            state.pages.remove(&oldest);
+        }
+        state.lru.insert(tick, page.page_id);
+        state.pages.insert(page.page_id, (page, tick));
+    }
+
+    fn invalidate(&self, page_id: PageID) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, last_used)) = state.pages.remove(&page_id) {
+            state.lru.remove(&last_used);
+        }
+    }
+
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pages.clear();
+        state.lru.clear();
+    }
+
+    fn stats(&self) -> PageCacheStats {
+        let entries = self.state.lock().unwrap().pages.len();
+        PageCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries,
+            bytes: entries * self.page_size,
+        }
+    }
+}
+
+/// Counters for the durable header writes that complete commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommitSyncStats {
+    /// Number of headers written
+    pub count: u64,
+    /// Time spent syncing pages and writing headers, including any WAL checkpoints
+    pub total_time: Duration,
+}
+
+/// Shape of the events tree, see `Mvcc::tree_profile`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TreeProfile {
+    /// Number of levels, including the leaf level
+    pub height: u32,
+    /// The nodes at each level, from the root down
+    pub levels: Vec<TreeLevelProfile>,
+    /// Keys per node, over all nodes
+    pub avg_keys_per_node: f64,
+    /// Serialized size of a node as a fraction of the most that fits in a page,
+    /// over all nodes
+    pub avg_fill_factor: f64,
+}
+
+/// Nodes at one level of the events tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TreeLevelProfile {
+    pub internal_nodes: u64,
+    pub leaf_nodes: u64,
+}
+
+/// What a commit wrote to the database file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitStats {
+    pub tsn: Tsn,
+    /// Dirty and spilled pages written, not counting the header page
+    pub pages_written: usize,
+    /// Bytes of dirty pages and the header page written. With the write-ahead log
+    /// enabled, the same pages are also appended to the log.
+    pub bytes_written: usize,
+    /// Time spent syncing and writing the header, including waiting for a group commit
+    pub fsync_duration: Duration,
+}
+
+/// A commit that took longer than the slow commit threshold, with where its time went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowCommit {
+    pub tsn: Tsn,
+    /// Dirty and spilled pages written
+    pub dirty_pages: usize,
+    /// Bytes of dirty and spilled pages written
+    pub bytes: usize,
+    /// Time spent updating the free lists
+    pub free_lists_time: Duration,
+    /// Time spent writing dirty pages
+    pub write_time: Duration,
+    /// Time spent syncing and writing the header, including waiting for a group commit
+    pub sync_time: Duration,
+    pub total_time: Duration,
+}
+
+impl std::fmt::Display for SlowCommit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Slow commit {:?}: {} dirty page(s), {} bytes, took {:?} (free lists {:?}, write {:?}, sync {:?})",
+            self.tsn,
+            self.dirty_pages,
+            self.bytes,
+            self.total_time,
+            self.free_lists_time,
+            self.write_time,
+            self.sync_time
+        )
+    }
+}
+
 // Main MVCC structure
 pub struct Mvcc {
-    pub pager: Pager,
+    pub pager: Box<dyn PageStore>,
+    // Empty unless the pages are kept in a file
+    path: PathBuf,
     pub reader_tsns: Arc<DashMap<usize, Tsn>>,
     pub writer_lock: Mutex<()>,
     pub page_size: usize,
@@ -47,18 +471,176 @@ pub struct Mvcc {
     pub page_buf: Mutex<Vec<u8>>,
     reader_id_counter: AtomicUsize,
     pub verbose: bool,
+    // zstd level used to compress inline event data, None disables compression
+    pub compression_level: Option<i32>,
+    // Encrypts the pages other than the headers, None for an unencrypted database
+    encryption: Option<PageCipher>,
+    // Share the overflow chains of events with the same data
+    overflow_dedup: bool,
+    // Where full event tree nodes are split
+    split_strategy: SplitStrategy,
+    // Longest stored data of an event kept inline in its leaf
+    inline_threshold: usize,
+    // Data bytes in each chunk of the overflow chains that are written
+    overflow_chunk_size: usize,
+    // Fewest data bytes per node of the overflow chains in the file, which bounds
+    // the length of a chain when it is read, and is recorded in the header
+    pub(crate) min_overflow_chunk_size: usize,
+    pub durability: DurabilityMode,
+    group_commit: Option<Arc<GroupCommit>>,
+    // Write-ahead log that commits are synced to instead of the database file
+    wal: Option<Arc<Wal>>,
+    wal_config: WalConfig,
+    // Stops and joins its thread when dropped
+    checkpointer: Option<Checkpointer>,
+    page_cache: Option<PageCache>,
+    // Read pages through the pager's memory maps rather than with pread
+    use_mmap: bool,
+    commit_syncs: AtomicU64,
+    commit_sync_nanos: AtomicU64,
+    // Commits taking longer are logged, zero when disabled
+    slow_commit_threshold_nanos: AtomicU64,
+    last_slow_commit: Mutex<Option<SlowCommit>>,
+    // Set once closed, so that dropping doesn't sync again
+    closed: bool,
+    // Kept so that the file written by compaction grows the same way
+    growth_policy: GrowthPolicy,
+    // Position of the last committed event, for subscribe_head()
+    head: watch::Sender<Position>,
+    // Snapshots of recent commits and when they were made, oldest first
+    history: Mutex<VecDeque<(Instant, Reader)>>,
+    retention_policy: RetentionPolicy,
+    // Added to every durable header write, to simulate a stalled sync
+    #[cfg(test)]
+    sync_delay: Duration,
 }
 
 impl Mvcc {
-    pub fn new(path: &Path, page_size: usize, verbose: bool) -> DCBResult<Self> {
-        let pager = Pager::new(path, page_size)?;
+    pub fn new(path: &Path, page_size: usize, durability: DurabilityMode) -> DCBResult<Self> {
+        Self::open(
+            path,
+            page_size,
+            durability,
+            None,
+            GrowthPolicy::default(),
+            0,
+        )
+    }
+
+    /// Opens a database whose file is preallocated to at least `reserve_bytes`, so
+    /// that commits don't wait for the file to be extended until it is full. The
+    /// file's length is then its high-water mark, while logical_size() is the end
+    /// of the pages in use. Compaction doesn't keep the reserved tail.
+    pub fn new_with_capacity(
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        reserve_bytes: u64,
+    ) -> DCBResult<Self> {
+        Self::open(
+            path,
+            page_size,
+            durability,
+            None,
+            GrowthPolicy::default(),
+            reserve_bytes,
+        )
+    }
+
+    /// Opens a database whose file is extended according to `policy` when pages are
+    /// written past its end, rather than by a memory map window at a time.
+    pub fn new_with_growth_policy(
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        policy: GrowthPolicy,
+    ) -> DCBResult<Self> {
+        Self::open(path, page_size, durability, None, policy, 0)
+    }
+
+    /// Opens a database that keeps the snapshots of recent commits according to
+    /// `policy`, as with_retention_policy() does.
+    pub fn new_with_retention_policy(
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        policy: RetentionPolicy,
+    ) -> DCBResult<Self> {
+        Self::new(path, page_size, durability)?.with_retention_policy(policy)
+    }
+
+    /// Opens a database whose pages, other than the headers, are encrypted with a key
+    /// derived from `key`. A new database is created encrypted, and an existing one
+    /// must have been created with the same key.
+    pub fn new_with_encryption(
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        key: &[u8],
+    ) -> DCBResult<Self> {
+        Self::open(
+            path,
+            page_size,
+            durability,
+            Some(key),
+            GrowthPolicy::default(),
+            0,
+        )
+    }
+
+    /// Creates a database whose pages are held in memory, for tests that shouldn't
+    /// touch the disk. It commits and reads like a database in a file, but can't
+    /// have a write-ahead log or be compacted, and is gone when dropped.
+    pub fn new_in_memory(page_size: usize) -> DCBResult<Self> {
+        Self::new_with_page_store(
+            MemoryPager::new(page_size),
+            page_size,
+            DurabilityMode::NoSync,
+        )
+    }
+
+    /// Opens a database whose pages are kept in `store`, creating it if the store is
+    /// new. Only a database in a file can have a write-ahead log or be compacted.
+    pub fn new_with_page_store(
+        store: impl PageStore + 'static,
+        page_size: usize,
+        durability: DurabilityMode,
+    ) -> DCBResult<Self> {
+        Self::open_store(Box::new(store), Path::new(""), page_size, durability, None)
+    }
+
+    fn open(
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        encryption_key: Option<&[u8]>,
+        growth_policy: GrowthPolicy,
+        reserve_bytes: u64,
+    ) -> DCBResult<Self> {
+        let pager = Pager::new(path, page_size)?.with_growth_policy(growth_policy)?;
+        // Before a new database's first pages are written, which would extend it
+        pager.reserve(reserve_bytes)?;
+        let pager = Box::new(pager);
+        let mut mvcc = Self::open_store(pager, path, page_size, durability, encryption_key)?;
+        mvcc.growth_policy = growth_policy;
+        Ok(mvcc)
+    }
 
-        let mvcc = Self {
+    fn open_store(
+        pager: Box<dyn PageStore>,
+        path: &Path,
+        page_size: usize,
+        durability: DurabilityMode,
+        encryption_key: Option<&[u8]>,
+    ) -> DCBResult<Self> {
+        let payload_cap = OverflowWriter::payload_cap_for_page_size(page_size);
+        let mut mvcc = Self {
             pager,
+            path: path.to_path_buf(),
             reader_tsns: Arc::new(DashMap::new()),
             writer_lock: Mutex::new(()),
             page_size,
-            max_node_size: page_size - PAGE_HEADER_SIZE,
+            max_node_size: max_node_size(page_size),
             headers: Mutex::new(vec![
                 Page {
                     page_id: PageID(0),
@@ -72,10 +654,48 @@ impl Mvcc {
             header_page_buf: Mutex::new(vec![0u8; page_size]),
             page_buf: Mutex::new(vec![0u8; page_size]),
             reader_id_counter: AtomicUsize::new(0),
-            verbose,
+            verbose: false,
+            compression_level: None,
+            encryption: None,
+            inline_threshold: MAX_INLINE_THRESHOLD,
+            overflow_dedup: false,
+            split_strategy: SplitStrategy::default(),
+            overflow_chunk_size: payload_cap,
+            min_overflow_chunk_size: payload_cap,
+            durability,
+            group_commit: None,
+            wal: None,
+            wal_config: WalConfig::default(),
+            checkpointer: None,
+            page_cache: None,
+            use_mmap: true,
+            commit_syncs: AtomicU64::new(0),
+            commit_sync_nanos: AtomicU64::new(0),
+            slow_commit_threshold_nanos: AtomicU64::new(0),
+            last_slow_commit: Mutex::new(None),
+            closed: false,
+            growth_policy: GrowthPolicy::default(),
+            head: watch::Sender::new(Position(0)),
+            history: Mutex::new(VecDeque::new()),
+            retention_policy: RetentionPolicy::Latest,
+            #[cfg(test)]
+            sync_delay: Duration::ZERO,
         };
 
-        if mvcc.pager.is_file_new {
+        if mvcc.pager.is_new()? {
+            // A log left by a database that was removed doesn't belong to this one
+            if mvcc.pager.file_pager().is_some() {
+                match fs::remove_file(Wal::path_for(path)) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(err.into());
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(key) = encryption_key {
+                mvcc.set_cipher(PageCipher::create(key)?)?;
+            }
+
             // Initialize new database
             let initial_tsn = Tsn(0);
             let initial_free_lists_tree_root_id = PageID(2);
@@ -113,10 +733,7 @@ impl Mvcc {
             );
 
             // Create and write an empty events tree root page.
-            let event_leaf = EventLeafNode {
-                keys: Vec::new(),
-                values: Vec::new(),
-            };
+            let event_leaf = EventLeafNode::new(Vec::new(), Vec::new());
             let position_page = Page::new(initial_events_tree_root_id, Node::EventLeaf(event_leaf));
 
             // Create and write an empty tags tree root page.
@@ -127,138 +744,1190 @@ impl Mvcc {
             let tags_page = Page::new(initial_tags_tree_root_id, Node::TagsLeaf(tags_leaf));
 
             // Write all three initial root pages using the shared write_pages helper
-            let _ = mvcc.write_pages([&free_list_page, &position_page, &tags_page].into_iter())?;
+            let _ = mvcc.write_pages([&free_list_page, &position_page, &tags_page])?;
 
             // Sync the file to disk.
             mvcc.fsync()?;
+        } else {
+            mvcc.replay_wal()?;
+            mvcc.open_cipher(encryption_key)?;
+            mvcc.recover()?;
+            let (_, header) = mvcc.get_latest_header()?;
+            mvcc.head
+                .send_replace(Position(header.next_position.0.saturating_sub(1)));
+            if header.overflow_chunk_size != 0 {
+                mvcc.min_overflow_chunk_size = mvcc
+                    .min_overflow_chunk_size
+                    .min(header.overflow_chunk_size as usize);
+            }
         }
 
         Ok(mvcc)
     }
 
-    pub fn get_latest_header(&self) -> DCBResult<(PageID, HeaderNode)> {
-        for attempt in 0..GET_LATEST_HEADER_RETRIES {
-            let h0 = self.read_header(HEADER_PAGE_ID_0);
-            let h1 = self.read_header(HEADER_PAGE_ID_1);
+    // Encrypts the pages from now on, which leaves less room for nodes in each page
+    fn set_cipher(&mut self, cipher: PageCipher) -> DCBResult<()> {
+        let max_node_size = max_node_size(self.page_size).saturating_sub(ENCRYPTION_OVERHEAD);
+        let header_size = HeaderNode {
+            encryption: Some(cipher.params().clone()),
+            ..HeaderNode::default()
+        }
+        .serialized_size();
+        let payload_cap = OverflowWriter::payload_cap_for_node_size(max_node_size);
+        if header_size > self.max_node_size || payload_cap == 0 {
+            return Err(DCBError::EncryptionError(format!(
+                "A page size of {} is too small for an encrypted database",
+                self.page_size
+            )));
+        }
+        self.max_node_size = max_node_size;
+        self.overflow_chunk_size = payload_cap;
+        self.min_overflow_chunk_size = payload_cap;
+        self.encryption = Some(cipher);
+        Ok(())
+    }
 
-            match (h0, h1) {
-                (Ok(header0), Ok(header1)) => {
-                    if header1.tsn > header0.tsn {
-                        return Ok((HEADER_PAGE_ID_1, header1));
-                    } else {
-                        return Ok((HEADER_PAGE_ID_0, header0));
+    // Checks the key against the encryption parameters in the header of an existing
+    // database. They are the same in both headers, so the first readable one is used.
+    fn open_cipher(&mut self, key: Option<&[u8]>) -> DCBResult<()> {
+        let Some(header) = [HEADER_PAGE_ID_0, HEADER_PAGE_ID_1]
+            .into_iter()
+            .find_map(|page_id| self.read_header(page_id).ok())
+        else {
+            // Left to recover() to report
+            return Ok(());
+        };
+        if header.page_size != 0 && header.page_size as usize != self.page_size {
+            return Ok(());
+        }
+        match (&header.encryption, key) {
+            (Some(params), Some(key)) => self.set_cipher(PageCipher::open(key, params)?),
+            (None, None) => Ok(()),
+            (Some(_), None) => Err(DCBError::EncryptionError(
+                "The database is encrypted, but no key was given".to_string(),
+            )),
+            (None, Some(_)) => Err(DCBError::EncryptionError(
+                "A key was given, but the database isn't encrypted".to_string(),
+            )),
+        }
+    }
+
+    // Writes the pages of the complete commits in the write-ahead log into the
+    // database file again, then syncs it and empties the log
+    fn replay_wal(&self) -> DCBResult<()> {
+        let wal_path = Wal::path_for(&self.path);
+        if self.pager.file_pager().is_none() || !wal_path.exists() {
+            return Ok(());
+        }
+        let pages = Wal::read_committed_pages(&wal_path)?;
+        if self.verbose {
+            println!("Replaying {} page(s) from {:?}", pages.len(), wal_path);
+        }
+        for page in &pages {
+            self.pager.write_page(page.page_id, &page.data)?;
+        }
+        Wal::open(&wal_path)?.checkpoint(|| self.fsync_file())?;
+        Ok(())
+    }
+
+    /// Checks the committed headers of an existing database before it is used.
+    ///
+    /// A header is usable if its root pages are within the file and can be read as
+    /// the roots of their trees. If the latest header isn't usable, the previous one
+    /// is written over it, so that the database opens at the previous commit. A
+    /// header that fails its checksum, for example after a torn write, is already
+    /// passed over by `get_latest_header`.
+    fn recover(&self) -> DCBResult<()> {
+        let file_len = self.store_size()?;
+        let mut headers = Vec::with_capacity(2);
+        for page_id in [HEADER_PAGE_ID_0, HEADER_PAGE_ID_1] {
+            match self.read_header(page_id) {
+                Ok(header) => {
+                    // Page 0 is at the start of the file whatever the page size, so its
+                    // header can be read to check the database was created with this size
+                    if header.page_size != 0 && header.page_size as usize != self.page_size {
+                        return Err(DCBError::PageSizeMismatch(
+                            header.page_size as usize,
+                            self.page_size,
+                        ));
                     }
+                    headers.push((page_id, header));
                 }
-                (Ok(header0), Err(_)) => {
-                    return Ok((HEADER_PAGE_ID_0, header0));
-                }
-                (Err(_), Ok(header1)) => {
-                    return Ok((HEADER_PAGE_ID_1, header1));
-                }
-                (Err(e0), Err(e1)) => {
-                    if attempt + 1 < GET_LATEST_HEADER_RETRIES {
-                        if self.verbose {
-                            println!(
-                                "Both headers invalid on attempt {}: {:?} | {:?}. Retrying...",
-                                attempt + 1,
-                                e0,
-                                e1
-                            );
-                        }
-                        sleep(GET_LATEST_HEADER_DELAY);
-                        continue;
-                    } else {
-                        return Err(DCBError::DatabaseCorrupted(format!(
-                            "Both header pages appear corrupted after {} attempts: ({:?}) and ({:?})",
-                            GET_LATEST_HEADER_RETRIES, e0, e1
-                        )));
+                Err(err) => {
+                    if self.verbose {
+                        println!("Header {page_id:?} is unreadable: {err:?}");
                     }
                 }
             }
         }
-        // Unreachable due to returns in match and final error, but keep to satisfy type checker
-        Err(DCBError::DatabaseCorrupted(
-            "Unable to read a valid header".to_string(),
-        ))
-    }
-
-    pub fn read_header(&self, page_id: PageID) -> DCBResult<HeaderNode> {
-        let page = self.read_page(page_id)?;
-        match page.node {
-            Node::Header(node) => Ok(node),
-            _ => Err(DCBError::DatabaseCorrupted(
-                "Invalid header node type".to_string(),
-            )),
-        }
-    }
 
-    fn update_header(
-        &self,
-        page_id: PageID,
-        tsn: Tsn,
-        free_lists_tree_root_id: PageID,
-        events_tree_root_id: PageID,
-        tags_tree_root_id: PageID,
-        next_page_id: PageID,
-        next_position: Position,
-    ) -> DCBResult<()> {
-        let mut headers = self.headers.lock().unwrap();
-        let headers_idx = { if page_id == HEADER_PAGE_ID_0 { 0 } else { 1 } };
-        let header = &mut headers[headers_idx];
-        match &mut header.node {
-            Node::Header(node) => {
-                // Update node values.
-                node.tsn = tsn;
-                node.free_lists_tree_root_id = free_lists_tree_root_id;
-                node.events_tree_root_id = events_tree_root_id;
-                node.tags_tree_root_id = tags_tree_root_id;
-                node.next_page_id = next_page_id;
-                node.next_position = next_position;
+        // Latest first, preferring header 0 on a tie as get_latest_header does
+        headers.sort_by_key(|(page_id, header)| (std::cmp::Reverse(header.tsn), *page_id));
+        let Some(usable_idx) = headers
+            .iter()
+            .position(|(_, header)| self.is_header_usable(header, file_len))
+        else {
+            return Err(DCBError::DatabaseCorrupted(
+                "Neither database header is usable".to_string(),
+            ));
+        };
 
-                // Write node using pre-allocated buffer.
-                let mut buf = self.page_buf.lock().unwrap();
-                serialize_page_into(&mut buf, &header.node)?;
-                self.pager.write_page(page_id, &buf)?;
-                Ok(())
+        let (_, usable) = &headers[usable_idx];
+        for (page_id, header) in &headers[..usable_idx] {
+            if self.verbose {
+                println!(
+                    "Header {page_id:?} at {:?} is not usable, falling back to {:?}",
+                    header.tsn, usable.tsn
+                );
             }
-            _ => panic!("Shouldn't get here: header should be a header"),
+            self.update_header(
+                *page_id,
+                usable.tsn,
+                usable.free_lists_tree_root_id,
+                usable.events_tree_root_id,
+                usable.tags_tree_root_id,
+                usable.next_page_id,
+                usable.next_position,
+            )?;
+            self.fsync()?;
         }
+        Ok(())
     }
 
-    pub fn read_page(&self, page_id: PageID) -> DCBResult<Page> {
-        let mapped = self.pager.read_page_mmap_slice(page_id)?;
-        if self.verbose {
-            println!("Read {page_id:?} from file, deserializing...");
+    // Returns true if the header's root pages are within the file and are tree roots
+    fn is_header_usable(&self, header: &HeaderNode, file_len: u64) -> bool {
+        if header.next_page_id.0.saturating_mul(self.page_size as u64) > file_len {
+            return false;
         }
-        Page::deserialize(page_id, mapped.as_slice())
+        let is_root = |root_id: PageID, is_root_node: fn(&Node) -> bool| {
+            root_id > HEADER_PAGE_ID_1
+                && root_id < header.next_page_id
+                && self
+                    .read_page(root_id)
+                    .is_ok_and(|page| is_root_node(&page.node))
+        };
+        is_root(header.free_lists_tree_root_id, |node| {
+            matches!(node, Node::FreeListLeaf(_) | Node::FreeListInternal(_))
+        }) && is_root(header.events_tree_root_id, |node| {
+            matches!(node, Node::EventLeaf(_) | Node::EventInternal(_))
+        }) && is_root(header.tags_tree_root_id, |node| {
+            matches!(node, Node::TagsLeaf(_) | Node::TagsInternal(_))
+        })
     }
 
-    pub fn fsync(&self) -> DCBResult<()> {
-        self.pager.fsync()?;
-        Ok(())
+    /// Prints progress of writer operations to stdout, for debugging.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
     }
 
-    pub fn reader(&self) -> DCBResult<Reader> {
-        let (header_page_id, header_node) = self.get_latest_header()?;
+    /// Enables group commit, so that concurrent commits share their syncs.
+    ///
+    /// Writers are issued one at a time: `writer()` blocks until the previous
+    /// writer has been committed or dropped, and builds on its changes even if
+    /// they aren't synced yet. Readers only see commits once they are synced.
+    /// If syncing a batch fails, its commits fail, as do those of the writers
+    /// issued meanwhile, and later writers build on the last synced commit.
+    pub fn with_group_commit(mut self, config: GroupCommitConfig) -> Self {
+        self.group_commit = Some(Arc::new(GroupCommit::new(config)));
+        self
+    }
 
-        // Generate a unique ID for this reader using the counter (lock-free)
-        let reader_id = self.reader_id_counter.fetch_add(1, Ordering::Relaxed) + 1;
+    /// Enables zstd compression of inline event data at the given level, which must
+    /// be in the range zstd accepts.
+    pub fn with_compression_level(mut self, level: i32) -> DCBResult<Self> {
+        let levels = zstd::compression_level_range();
+        if !levels.contains(&level) {
+            return Err(DCBError::InternalError(format!(
+                "Compression level must be between {} and {}, got {level}",
+                levels.start(),
+                levels.end()
+            )));
+        }
+        self.compression_level = Some(level);
+        Ok(self)
+    }
 
-        // Register the reader TSN (lock-free concurrent insert)
-        self.reader_tsns.insert(reader_id, header_node.tsn);
+    /// Enables the write-ahead log, so that a commit syncs the pages it writes to a
+    /// sequential log instead of syncing the database file. The database file is
+    /// synced when the log is checkpointed, once it grows past the configured size.
+    /// The log is synced according to the durability mode. With a checkpoint interval,
+    /// a background thread also checkpoints the log, until the Mvcc is closed or dropped.
+    pub fn with_wal(mut self, config: WalConfig) -> DCBResult<Self> {
+        if self.pager.file_pager().is_none() {
+            return Err(DCBError::InternalError(
+                "Only a database in a file can have a write-ahead log".to_string(),
+            ));
+        }
+        self.wal = Some(Arc::new(Wal::open(&Wal::path_for(&self.path))?));
+        self.wal_config = config;
+        self.start_checkpointer()?;
+        Ok(self)
+    }
+
+    fn start_checkpointer(&mut self) -> DCBResult<()> {
+        if let (Some(wal), Some(interval)) = (&self.wal, self.wal_config.checkpoint_interval) {
+            let Some(pager) = self.pager.file_pager() else {
+                return Ok(());
+            };
+            let db_file = pager.writer.clone();
+            self.checkpointer = Some(Checkpointer::spawn(
+                wal.clone(),
+                move || db_file.sync_all(),
+                interval,
+                self.verbose,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Stores the data of events inline in their leaf when it is at most `bytes`
+    /// long, after any compression, and in an overflow chain when it is longer.
+    /// Data that doesn't fit in a leaf page is stored in an overflow chain anyway.
+    pub fn with_inline_threshold(mut self, bytes: usize) -> DCBResult<Self> {
+        if bytes > MAX_INLINE_THRESHOLD {
+            return Err(DCBError::InternalError(format!(
+                "Inline threshold must be at most {MAX_INLINE_THRESHOLD} bytes, got {bytes}"
+            )));
+        }
+        self.inline_threshold = bytes;
+        Ok(self)
+    }
+
+    /// Returns the longest event data that is stored inline rather than in an overflow chain.
+    pub fn inline_threshold(&self) -> usize {
+        self.inline_threshold
+    }
+
+    /// Shares overflow chains between events with the same data. The data of each new
+    /// chain is indexed by its hash in the tags tree, and an event whose data matches
+    /// an indexed chain refers to that chain rather than writing another.
+    pub fn with_overflow_dedup(mut self, enabled: bool) -> Self {
+        self.overflow_dedup = enabled;
+        self
+    }
+
+    /// Returns true if overflow chains are shared between events with the same data.
+    pub fn overflow_dedup(&self) -> bool {
+        self.overflow_dedup
+    }
+
+    /// Sets where full event tree nodes are split. The default right-biased split
+    /// suits appends, which always go to the right-most leaf.
+    pub fn with_split_strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.split_strategy = strategy;
+        self
+    }
+
+    /// Returns where full event tree nodes are split.
+    pub fn split_strategy(&self) -> SplitStrategy {
+        self.split_strategy
+    }
+
+    /// Splits the data of large events into chunks of `bytes` each, rather than
+    /// a page each. A chunk smaller than a page's payload has an overflow node of
+    /// its own, and a larger one fills as many nodes as it needs, on consecutive
+    /// pages, so that it is written and read sequentially. Chains written with
+    /// other chunk sizes, before or since, can still be read.
+    pub fn with_overflow_chunk_size(mut self, bytes: usize) -> DCBResult<Self> {
+        let payload_cap = OverflowWriter::payload_cap_for_node_size(self.max_node_size);
+        let min_bytes_per_node = OverflowWriter::new(payload_cap)?
+            .with_chunk_size(bytes)?
+            .min_bytes_per_node();
+        // Fewer bytes per node than a page holds are recorded in the header as a u16
+        if min_bytes_per_node < payload_cap && min_bytes_per_node > u16::MAX as usize {
+            return Err(DCBError::InternalError(format!(
+                "Overflow chunk size of {bytes} bytes would put {min_bytes_per_node} bytes in each node, \
+                 which must be a whole {payload_cap} bytes or at most {} for a page size of {}",
+                u16::MAX,
+                self.page_size
+            )));
+        }
+        self.overflow_chunk_size = bytes;
+        self.min_overflow_chunk_size = self.min_overflow_chunk_size.min(min_bytes_per_node);
+        Ok(self)
+    }
+
+    /// Returns the number of data bytes in each chunk of the overflow chains that are written.
+    pub fn overflow_chunk_size(&self) -> usize {
+        self.overflow_chunk_size
+    }
+
+    /// Returns a writer of overflow chains with the chunk size of this database.
+    pub(crate) fn overflow_writer(&self) -> DCBResult<OverflowWriter> {
+        OverflowWriter::new(OverflowWriter::payload_cap_for_node_size(
+            self.max_node_size,
+        ))?
+        .with_chunk_size(self.overflow_chunk_size)
+    }
+
+    /// Returns true if the page's node fits in a page of this database, which has less
+    /// room for it if the database is encrypted.
+    pub fn fits(&self, page: &Page) -> bool {
+        page.node.calc_serialized_size() <= self.max_node_size
+    }
+
+    // Zero for overflow nodes that fill their pages
+    fn recorded_overflow_chunk_size(&self) -> u16 {
+        if self.min_overflow_chunk_size
+            == OverflowWriter::payload_cap_for_node_size(self.max_node_size)
+        {
+            0
+        } else {
+            self.min_overflow_chunk_size as u16
+        }
+    }
+
+    fn encryption_params(&self) -> Option<EncryptionParams> {
+        self.encryption
+            .as_ref()
+            .map(|cipher| cipher.params().clone())
+    }
+
+    /// Enables a cache of decoded pages, which holds at most `budget_bytes` worth of
+    /// pages and evicts the least recently used first. Header pages aren't cached.
+    pub fn with_page_cache(mut self, budget_bytes: usize) -> Self {
+        self.page_cache = Some(PageCache::new(budget_bytes, self.page_size));
+        self
+    }
+
+    /// Chooses whether pages are read through memory maps of the file, which is the
+    /// default, or copied out of it with pread. Pages kept elsewhere are always copied.
+    pub fn with_mmap(mut self, enabled: bool) -> Self {
+        self.use_mmap = enabled;
+        self
+    }
+
+    /// Keeps snapshots of the latest `commits` commits, so that reader_as_of() can read
+    /// the database as it was at any of them. Pages reachable from a retained commit
+    /// aren't reused until it falls out of the history, so the file grows by the pages
+    /// those commits replaced. The history starts with the latest commit when this is
+    /// called, and isn't kept when the database is closed or compacted.
+    pub fn with_history_retention(self, commits: usize) -> DCBResult<Self> {
+        self.with_retention_policy(match commits {
+            0 => RetentionPolicy::Latest,
+            commits => RetentionPolicy::Commits(commits),
+        })
+    }
+
+    /// Like with_history_retention(), but keeps the snapshots of the commits the
+    /// policy retains, such as those made within a duration. Commits that fall out
+    /// are only forgotten when the next writer is made or commits.
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> DCBResult<Self> {
+        self.retention_policy = policy;
+        self.history.get_mut().unwrap().clear();
+        if policy != RetentionPolicy::Latest {
+            let (header_page_id, header) = self.get_latest_header()?;
+            let reader = self.new_reader(header_page_id, &header);
+            self.history
+                .get_mut()
+                .unwrap()
+                .push_back((Instant::now(), reader));
+        }
+        Ok(self)
+    }
+
+    // Forgets the snapshots of the commits the policy no longer retains
+    fn prune_history(&self, history: &mut VecDeque<(Instant, Reader)>) {
+        match self.retention_policy {
+            RetentionPolicy::Latest => history.clear(),
+            RetentionPolicy::Commits(n) => {
+                while history.len() > n {
+                    history.pop_front();
+                }
+            }
+            RetentionPolicy::Duration(duration) => {
+                while history.len() > 1 && history[0].0.elapsed() > duration {
+                    history.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Returns the Tsn of the oldest commit that reader_as_of() can read, which is the
+    /// latest one unless the retention policy keeps others. After compaction, that is
+    /// the compacted commit until later commits are retained.
+    pub fn oldest_tsn(&self) -> DCBResult<Tsn> {
+        let (_, header) = self.get_latest_header()?;
+        let mut history = self.history.lock().unwrap();
+        self.prune_history(&mut history);
+        Ok(history
+            .front()
+            .map_or(header.tsn, |(_, reader)| reader.tsn.min(header.tsn)))
+    }
+
+    /// Returns a reader of the snapshot of the commit with the given Tsn, which must
+    /// be the latest commit or one kept by the retention policy. Fails with
+    /// OutOfRetention for an older commit.
+    pub fn reader_as_of(&self, tsn: Tsn) -> DCBResult<Reader> {
+        let (header_page_id, header) = self.get_latest_header()?;
+        if header.tsn == tsn {
+            return Ok(self.new_reader(header_page_id, &header));
+        }
+        if tsn > header.tsn {
+            return Err(DCBError::InvalidArgument(format!(
+                "{tsn:?} is after the latest commit {:?}",
+                header.tsn
+            )));
+        }
+        let mut history = self.history.lock().unwrap();
+        self.prune_history(&mut history);
+        match history
+            .iter()
+            .map(|(_, reader)| reader)
+            .find(|reader| reader.tsn == tsn)
+        {
+            Some(retained) => Ok(self.register_reader(Reader {
+                reader_id: 0,
+                reader_tsns: Arc::clone(&self.reader_tsns),
+                ..*retained
+            })),
+            None => Err(DCBError::OutOfRetention(format!(
+                "{tsn:?} is older than the oldest retained commit {:?}",
+                history.front().map_or(header.tsn, |(_, reader)| reader.tsn)
+            ))),
+        }
+    }
+
+    /// Returns a receiver of the position of the last committed event, which is zero
+    /// while there are none. It is updated once each commit is durable, so that
+    /// embedding applications can catch up their projections without polling.
+    pub fn subscribe_head(&self) -> watch::Receiver<Position> {
+        self.head.subscribe()
+    }
+
+    /// Returns the counters for the durable header writes that complete commits.
+    pub fn commit_sync_stats(&self) -> CommitSyncStats {
+        CommitSyncStats {
+            count: self.commit_syncs.load(Ordering::Relaxed),
+            total_time: Duration::from_nanos(self.commit_sync_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Logs commits that take longer than `threshold`, with a breakdown of where the
+    /// time went. Logged through `tracing` when the tracing feature is enabled, and
+    /// printed otherwise only when verbose. Either way the latest is kept for
+    /// last_slow_commit(). A zero threshold stops the logging.
+    pub fn set_slow_commit_threshold(&self, threshold: Duration) {
+        self.slow_commit_threshold_nanos
+            .store(threshold.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the most recent commit that exceeded the slow commit threshold.
+    pub fn last_slow_commit(&self) -> Option<SlowCommit> {
+        self.last_slow_commit.lock().unwrap().clone()
+    }
+
+    /// Returns the page cache counters, or None if the cache isn't enabled.
+    pub fn page_cache_stats(&self) -> Option<PageCacheStats> {
+        self.page_cache.as_ref().map(PageCache::stats)
+    }
+
+    /// Makes every commit so far durable without committing again, so the Tsn and
+    /// header don't change. Commits write their pages and header before they return,
+    /// so there is nothing left to write, and the pages of uncommitted writers are not
+    /// included. The write-ahead log, if it is enabled, or else the database file, is
+    /// synced with fdatasync in SyncData mode and with fsync otherwise, even in NoSync
+    /// mode. After a crash or power loss the database then opens at the last commit
+    /// before the flush, or at a later one.
+    pub fn flush(&self) -> DCBResult<()> {
+        let data_only = self.durability == DurabilityMode::SyncData;
+        match &self.wal {
+            Some(wal) => wal.sync(data_only)?,
+            None if data_only => self.pager.sync_data()?,
+            None => self.pager.sync()?,
+        }
+        Ok(())
+    }
+
+    /// Syncs the database file and empties the write-ahead log, if it is enabled.
+    /// Fails instead with the error of a background checkpoint that failed since
+    /// the last call, so that a log that isn't being emptied doesn't go unnoticed.
+    pub fn checkpoint_now(&self) -> DCBResult<()> {
+        self.take_checkpoint_error()?;
+        if let Some(wal) = &self.wal {
+            wal.checkpoint(|| self.fsync_file())?;
+        }
+        Ok(())
+    }
+
+    /// Stops the background checkpoint thread, checkpoints the write-ahead log, and
+    /// syncs the database file, even in NoSync mode, so that every commit is durable
+    /// once this returns. Dropping the database does the same, but can only log an error.
+    pub fn close(mut self) -> DCBResult<()> {
+        self.close_inner()
+    }
+
+    fn close_inner(&mut self) -> DCBResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let background = self.take_checkpoint_error();
+        // Joins the thread, so it can't checkpoint concurrently
+        self.checkpointer = None;
+        self.checkpoint_now()?;
+        match self.durability {
+            DurabilityMode::SyncData => self.pager.sync_data()?,
+            DurabilityMode::NoSync | DurabilityMode::Sync => self.pager.sync()?,
+        }
+        background
+    }
+
+    // Fails with the error of a background checkpoint that failed since last asked
+    fn take_checkpoint_error(&self) -> DCBResult<()> {
+        match self
+            .checkpointer
+            .as_ref()
+            .and_then(Checkpointer::take_error)
+        {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the header of the latest commit, and the page it is on. A header page
+    /// that fails its checksum is passed over in favour of the other one.
+    pub fn get_latest_header(&self) -> DCBResult<(PageID, HeaderNode)> {
+        for attempt in 0..GET_LATEST_HEADER_RETRIES {
+            let h0 = self.read_header(HEADER_PAGE_ID_0);
+            let h1 = self.read_header(HEADER_PAGE_ID_1);
+
+            match (h0, h1) {
+                (Ok(header0), Ok(header1)) => {
+                    if header1.tsn > header0.tsn {
+                        return Ok((HEADER_PAGE_ID_1, header1));
+                    } else {
+                        return Ok((HEADER_PAGE_ID_0, header0));
+                    }
+                }
+                (Ok(header0), Err(_)) => {
+                    return Ok((HEADER_PAGE_ID_0, header0));
+                }
+                (Err(_), Ok(header1)) => {
+                    return Ok((HEADER_PAGE_ID_1, header1));
+                }
+                (Err(e0), Err(e1)) => {
+                    if attempt + 1 < GET_LATEST_HEADER_RETRIES {
+                        if self.verbose {
+                            println!(
+                                "Both headers invalid on attempt {}: {:?} | {:?}. Retrying...",
+                                attempt + 1,
+                                e0,
+                                e1
+                            );
+                        }
+                        sleep(GET_LATEST_HEADER_DELAY);
+                        continue;
+                    } else {
+                        return Err(DCBError::DatabaseCorrupted(format!(
+                            "Both header pages appear corrupted after {} attempts: ({:?}) and ({:?})",
+                            GET_LATEST_HEADER_RETRIES, e0, e1
+                        )));
+                    }
+                }
+            }
+        }
+        // Unreachable due to returns in match and final error, but keep to satisfy type checker
+        Err(DCBError::DatabaseCorrupted(
+            "Unable to read a valid header".to_string(),
+        ))
+    }
+
+    pub fn read_header(&self, page_id: PageID) -> DCBResult<HeaderNode> {
+        let page = self.read_page(page_id)?;
+        match page.node {
+            Node::Header(node) => Ok(node),
+            _ => Err(DCBError::DatabaseCorrupted(
+                "Invalid header node type".to_string(),
+            )),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_header(
+        &self,
+        page_id: PageID,
+        tsn: Tsn,
+        free_lists_tree_root_id: PageID,
+        events_tree_root_id: PageID,
+        tags_tree_root_id: PageID,
+        next_page_id: PageID,
+        next_position: Position,
+    ) -> DCBResult<()> {
+        let mut headers = self.headers.lock().unwrap();
+        let headers_idx = { if page_id == HEADER_PAGE_ID_0 { 0 } else { 1 } };
+        let header = &mut headers[headers_idx];
+        match &mut header.node {
+            Node::Header(node) => {
+                // Update node values.
+                node.tsn = tsn;
+                node.free_lists_tree_root_id = free_lists_tree_root_id;
+                node.events_tree_root_id = events_tree_root_id;
+                node.tags_tree_root_id = tags_tree_root_id;
+                node.next_page_id = next_page_id;
+                node.next_position = next_position;
+                node.page_size = self.page_size as u32;
+                node.overflow_chunk_size = self.recorded_overflow_chunk_size();
+                node.encryption = self.encryption_params();
+
+                // Write node using pre-allocated buffer.
+                let mut buf = self.page_buf.lock().unwrap();
+                serialize_page_into(&mut buf, &header.node)?;
+                self.pager.write_page(page_id, &buf)?;
+                Ok(())
+            }
+            _ => panic!("Shouldn't get here: header should be a header"),
+        }
+    }
+
+    /// Returns storage statistics as of the latest commit, counting free pages
+    /// by walking the free lists tree.
+    pub fn stats(&self) -> DCBResult<DbStats> {
+        // Hold a reader so the pages being walked aren't reused meanwhile, taking
+        // another if its header page was rewritten before it could be read
+        let (_reader, header) = loop {
+            let reader = self.reader()?;
+            let header = self.read_header(reader.header_page_id)?;
+            if header.tsn == reader.tsn {
+                break (reader, header);
+            }
+        };
+
+        let mut free_pages = 0u64;
+        let mut stack = vec![header.free_lists_tree_root_id];
+        while let Some(page_id) = stack.pop() {
+            match self.read_page(page_id)?.node {
+                Node::FreeListInternal(node) => stack.extend(node.child_ids),
+                Node::FreeListLeaf(node) => {
+                    for value in node.values {
+                        free_pages += value.page_ids.len() as u64;
+                        if value.root_id != PageID(0) {
+                            stack.push(value.root_id);
+                        }
+                    }
+                }
+                Node::FreeListTsnInternal(node) => stack.extend(node.child_ids),
+                Node::FreeListTsnLeaf(node) => free_pages += node.page_ids.len() as u64,
+                other => {
+                    return Err(DCBError::DatabaseCorrupted(format!(
+                        "Invalid node type in free list tree: {}",
+                        other.type_name()
+                    )));
+                }
+            }
+        }
+
+        // The events tree is balanced, so descend along its first children
+        let mut events_tree_height = 1;
+        let mut page_id = header.events_tree_root_id;
+        loop {
+            match self.read_page(page_id)?.node {
+                Node::EventInternal(node) => {
+                    page_id = node.child_ids.first().copied().ok_or_else(|| {
+                        DCBError::DatabaseCorrupted(format!(
+                            "Event internal node {page_id:?} has no children"
+                        ))
+                    })?;
+                    events_tree_height += 1;
+                }
+                Node::EventLeaf(_) => break,
+                other => {
+                    return Err(DCBError::DatabaseCorrupted(format!(
+                        "Invalid node type in events tree: {}",
+                        other.type_name()
+                    )));
+                }
+            }
+        }
+
+        Ok(DbStats {
+            total_pages: header.next_page_id.0,
+            free_pages,
+            event_count: header.next_position.0.saturating_sub(1),
+            events_tree_height,
+            file_size: self.store_size()?,
+        })
+    }
+
+    /// Returns the number of bytes up to the end of the last page allocated as of the
+    /// latest commit. The file can be longer, since it is extended ahead of use.
+    pub fn logical_size(&self) -> DCBResult<u64> {
+        let (_, header) = self.get_latest_header()?;
+        Ok(header.next_page_id.0 * self.page_size as u64)
+    }
+
+    /// Returns the height of the events tree as of the latest commit, with its node
+    /// counts at each level and how full the nodes are, walking the tree once.
+    /// Overflow chains are not counted.
+    pub fn tree_profile(&self) -> DCBResult<TreeProfile> {
+        let reader = self.reader()?;
+        let mut profile = TreeProfile::default();
+        let (mut nodes, mut keys, mut bytes) = (0u64, 0u64, 0u64);
+        let mut level_page_ids = vec![reader.events_tree_root_id];
+        while !level_page_ids.is_empty() {
+            let mut level = TreeLevelProfile::default();
+            let mut next_level_page_ids = Vec::new();
+            for page_id in level_page_ids {
+                let page = self.read_page(page_id)?;
+                bytes += page.node.calc_serialized_size() as u64;
+                match page.node {
+                    Node::EventInternal(node) => {
+                        level.internal_nodes += 1;
+                        keys += node.keys.len() as u64;
+                        next_level_page_ids.extend(node.child_ids);
+                    }
+                    Node::EventLeaf(node) => {
+                        level.leaf_nodes += 1;
+                        keys += node.keys().len() as u64;
+                    }
+                    other => {
+                        return Err(DCBError::DatabaseCorrupted(format!(
+                            "Invalid node type in events tree: {}",
+                            other.type_name()
+                        )));
+                    }
+                }
+            }
+            nodes += level.internal_nodes + level.leaf_nodes;
+            profile.levels.push(level);
+            level_page_ids = next_level_page_ids;
+        }
+        profile.height = profile.levels.len() as u32;
+        profile.avg_keys_per_node = keys as f64 / nodes as f64;
+        profile.avg_fill_factor = bytes as f64 / (nodes * self.max_node_size as u64) as f64;
+        Ok(profile)
+    }
+
+    /// Writes the committed events to `writer` as newline-delimited JSON, in position
+    /// order, with the data of overflowed events reassembled. Events are read a batch
+    /// at a time, so the store isn't buffered. Returns the number of events written.
+    pub fn export_ndjson<W: Write>(&self, writer: W) -> DCBResult<u64> {
+        const EXPORT_BATCH_SIZE: u32 = 256;
+        // Holding the reader keeps the snapshot's pages from being reused
+        let reader = self.reader()?;
+        let dirty = HashMap::new();
+        let mut iter = EventIterator::new(self, &dirty, reader.events_tree_root_id, None, false);
+        let mut writer = BufWriter::new(writer);
+        let mut count = 0;
+        loop {
+            let batch = iter.next_batch(EXPORT_BATCH_SIZE)?;
+            if batch.is_empty() {
+                break;
+            }
+            for (position, rec) in batch {
+                let event = rec.into_sequenced_event(position);
+                write_ndjson_event(&mut writer, &event)?;
+                count += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Appends the events in newline-delimited JSON from `reader`, as written by
+    /// export_ndjson(), committing a large batch of events at a time. Positions in
+    /// the input must increase, and the events are given the database's next
+    /// positions, so importing an export into an empty database keeps its positions.
+    /// The events keep their commit times. Blank lines are skipped. Returns the
+    /// number of events imported.
+    ///
+    /// Batches committed before an invalid line is found are kept.
+    pub fn import_ndjson<R: Read>(&self, reader: R) -> DCBResult<u64> {
+        const IMPORT_COMMIT_EVENTS: usize = 10_000;
+        let mut batch = Vec::with_capacity(IMPORT_COMMIT_EVENTS);
+        let mut last_position = 0;
+        let mut count = 0;
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = parse_ndjson_event(&line)?;
+            if event.position <= last_position {
+                return Err(DCBError::DeserializationError(format!(
+                    "NDJSON event at position {} doesn't follow position {last_position}",
+                    event.position
+                )));
+            }
+            last_position = event.position;
+            batch.push((event.event, event.commit_timestamp));
+            if batch.len() == IMPORT_COMMIT_EVENTS {
+                count += self.import_batch(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            count += self.import_batch(batch)?;
+        }
+        Ok(count)
+    }
+
+    fn import_batch(&self, events: Vec<(DCBEvent, Option<u64>)>) -> DCBResult<u64> {
+        let count = events.len() as u64;
+        let mut writer = self.writer()?;
+        crate::db::append_with_commit_timestamps(self, &mut writer, events)?;
+        self.commit(&mut writer)?;
+        Ok(count)
+    }
+
+    /// Rewrites the live pages of the latest commit into a fresh file, without
+    /// the free list, and swaps it in place of the database file. Returns the
+    /// number of bytes by which the file shrank. The file grows again by the
+    /// growth policy as later commits write past its end.
+    ///
+    /// Requires exclusive access: fails if any reader holds a snapshot, since
+    /// the swapped-in file renumbers the pages.
+    pub fn compact(&mut self) -> DCBResult<u64> {
+        if self.pager.file_pager().is_none() {
+            return Err(DCBError::InternalError(
+                "Only a database in a file can be compacted".to_string(),
+            ));
+        }
+        // The readers of the history are registered too, but don't stop compaction
+        if self.reader_tsns.len() > self.history.get_mut().unwrap().len() {
+            return Err(DCBError::InternalError(
+                "Can't compact while readers hold snapshots".to_string(),
+            ));
+        }
+        self.take_checkpoint_error()?;
+        // The compacted file is synced, and the page IDs in the log don't apply to it
+        self.checkpointer = None;
+        let result = self.swap_in_compacted_copy();
+        if result.is_err() && self.checkpointer.is_none() {
+            self.start_checkpointer()?;
+        }
+        result
+    }
 
-        // Create the reader with the unique ID
-        let reader = Reader {
+    // Writes the compacted copy and swaps it in, for compact()
+    fn swap_in_compacted_copy(&mut self) -> DCBResult<u64> {
+        self.checkpoint_now()?;
+        let (_, header) = self.get_latest_header()?;
+        let old_size = self.store_size()?;
+
+        let mut compact_path = self.path.clone().into_os_string();
+        compact_path.push(".compact");
+        let compact_path = PathBuf::from(compact_path);
+        if compact_path.exists() {
+            fs::remove_file(&compact_path)?;
+        }
+
+        let cipher = self.encryption.as_ref().map(PageCipher::for_new_file);
+        let compacted = self.write_compacted_copy(&header, &compact_path, cipher.as_ref())?;
+
+        // Open the copy before it is renamed, since once it is in place nothing may
+        // fail until the state matches it
+        let pager =
+            Pager::new(&compact_path, self.page_size)?.with_growth_policy(self.growth_policy)?;
+        let new_size = pager.writer.metadata()?.len();
+        fs::rename(&compact_path, &self.path)?;
+
+        self.pager = Box::new(pager);
+        // The snapshots in the history refer to pages that were renumbered
+        self.history.get_mut().unwrap().clear();
+        self.encryption = cipher;
+        if let Some(cache) = &self.page_cache {
+            cache.clear();
+        }
+        *self.headers.lock().unwrap() = vec![
+            Page::new(HEADER_PAGE_ID_0, Node::Header(compacted.clone())),
+            Page::new(HEADER_PAGE_ID_1, Node::Header(compacted.clone())),
+        ];
+        if let Some(group) = &self.group_commit {
+            group.restart(&compacted);
+        }
+        // The history starts again from the compacted commit
+        if self.retention_policy != RetentionPolicy::Latest {
+            let reader = self.new_reader(HEADER_PAGE_ID_0, &compacted);
+            self.history
+                .get_mut()
+                .unwrap()
+                .push_back((Instant::now(), reader));
+        }
+
+        // The compacted file is in use, even if making the rename durable fails
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        self.start_checkpointer()?;
+        Ok(old_size.saturating_sub(new_size))
+    }
+
+    /// Copies the pages reachable from the given header into a new file at `path`,
+    /// renumbered from page 3 and with an empty free list, and returns the header
+    /// written into the new file. The new file is synced. An encrypted copy needs a
+    /// cipher of its own, for its new file ID.
+    fn write_compacted_copy(
+        &self,
+        header: &HeaderNode,
+        path: &Path,
+        cipher: Option<&PageCipher>,
+    ) -> DCBResult<HeaderNode> {
+        // Pages 0 and 1 hold the headers and page 2 an empty free lists tree
+        let mut compactor = Compactor {
+            mvcc: self,
+            copied_chains: HashMap::new(),
+            cipher,
+            pager: Pager::new(path, self.page_size)?.with_growth_policy(self.growth_policy)?,
+            next_page_id: PageID(3),
+        };
+        let free_lists_tree_root_id = PageID(2);
+        compactor.write(Page::new(
+            free_lists_tree_root_id,
+            Node::FreeListLeaf(FreeListLeafNode {
+                keys: Vec::new(),
+                values: Vec::new(),
+            }),
+        ))?;
+        let events_tree_root_id = compactor.copy_tree(header.events_tree_root_id)?;
+        let tags_tree_root_id = compactor.copy_tree(header.tags_tree_root_id)?;
+        let compacted = HeaderNode {
+            tsn: header.tsn,
+            free_lists_tree_root_id,
+            events_tree_root_id,
+            tags_tree_root_id,
+            next_page_id: compactor.next_page_id,
+            next_position: header.next_position,
+            page_size: self.page_size as u32,
+            overflow_chunk_size: self.recorded_overflow_chunk_size(),
+            encryption: cipher.map(|cipher| cipher.params().clone()),
+        };
+
+        // Write the headers only once the pages they point to are synced
+        let pager = compactor.pager;
+        pager.fsync()?;
+        for page_id in [HEADER_PAGE_ID_0, HEADER_PAGE_ID_1] {
+            let mut buf = self.page_buf.lock().unwrap();
+            serialize_page_into(&mut buf, &Node::Header(compacted.clone()))?;
+            pager.write_page(page_id, &buf)?;
+        }
+        // Drop the space preallocated beyond the last page
+        pager
+            .writer
+            .set_len(compacted.next_page_id.0 * self.page_size as u64)?;
+        pager.fsync()?;
+        Ok(compacted)
+    }
+
+    /// Writes a copy of a snapshot of the latest commit to a new database file at
+    /// `path`, with only the pages reachable from it, while commits carry on. Later
+    /// commits aren't in the copy, and it has no free list, so it is usually smaller.
+    /// Fails if `path` exists. Returns the number of events in the backup.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> DCBResult<u64> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(DCBError::Io(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Backup file {path:?} already exists"),
+            )));
+        }
+        // Holding the reader keeps the snapshot's pages from being reused while
+        // they are copied, taking another if its header page was rewritten first
+        let (_reader, header) = loop {
+            let reader = self.reader()?;
+            let header = self.read_header(reader.header_page_id)?;
+            if header.tsn == reader.tsn {
+                break (reader, header);
+            }
+        };
+        let cipher = self.encryption.as_ref().map(PageCipher::for_new_file);
+        if let Err(err) = self.write_compacted_copy(&header, path, cipher.as_ref()) {
+            let _ = fs::remove_file(path);
+            return Err(err);
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(header.next_position.0.saturating_sub(1))
+    }
+
+    pub fn read_page(&self, page_id: PageID) -> DCBResult<Page> {
+        // Headers are rewritten in place by every commit, so they are always read
+        let cache = self
+            .page_cache
+            .as_ref()
+            .filter(|_| page_id > HEADER_PAGE_ID_1);
+        if let Some(page) = cache.and_then(|cache| cache.get(page_id)) {
+            return Ok(page);
+        }
+        if self.verbose {
+            println!("Read {page_id:?} from file, deserializing...");
+        }
+        let deserialize = |data: &[u8]| match &self.encryption {
+            Some(cipher) if page_id > HEADER_PAGE_ID_1 => cipher.open_page(page_id, data),
+            _ => Page::deserialize(page_id, data),
+        };
+        let page = match self.pager.file_pager() {
+            Some(pager) if self.use_mmap => {
+                let mapped = pager.read_page_mmap_slice(page_id)?;
+                deserialize(mapped.as_slice())?
+            }
+            _ => {
+                let data = self.pager.read_page(page_id)?;
+                deserialize(&data)?
+            }
+        };
+        if let Some(cache) = cache {
+            cache.insert(page.clone());
+        }
+        Ok(page)
+    }
+
+    /// Calls `read` with the node type and serialized node of a page, checked as
+    /// read_page() checks them, without deserializing the node or using the cache.
+    pub fn with_node_bytes<T>(
+        &self,
+        page_id: PageID,
+        read: impl FnOnce(u8, &[u8]) -> DCBResult<T>,
+    ) -> DCBResult<T> {
+        let open = |data: &[u8]| match &self.encryption {
+            Some(cipher) if page_id > HEADER_PAGE_ID_1 => {
+                let (node_type, node_bytes) = cipher.open_node_bytes(page_id, data)?;
+                read(node_type, &node_bytes)
+            }
+            _ => {
+                let (node_type, node_bytes) = node_bytes(page_id, data)?;
+                read(node_type, node_bytes)
+            }
+        };
+        match self.pager.file_pager() {
+            Some(pager) if self.use_mmap => {
+                let mapped = pager.read_page_mmap_slice(page_id)?;
+                open(mapped.as_slice())
+            }
+            _ => {
+                let data = self.pager.read_page(page_id)?;
+                open(&data)
+            }
+        }
+    }
+
+    pub fn fsync(&self) -> DCBResult<()> {
+        self.pager.sync()
+    }
+
+    // Syncs the database file before the write-ahead log is emptied
+    fn fsync_file(&self) -> std::io::Result<()> {
+        match self.pager.file_pager() {
+            Some(pager) => pager.fsync(),
+            None => Ok(()),
+        }
+    }
+
+    // Size of the database file, or of the pages in another store, in bytes
+    fn store_size(&self) -> DCBResult<u64> {
+        match self.pager.file_pager() {
+            Some(pager) => Ok(pager.writer.metadata()?.len()),
+            None => Ok(self.pager.len_pages()? * self.page_size as u64),
+        }
+    }
+
+    // Syncs the written pages, then writes and syncs the header that points to them
+    fn write_header_durably(&self, page_id: PageID, header: &HeaderNode) -> DCBResult<()> {
+        let started = Instant::now();
+        #[cfg(test)]
+        std::thread::sleep(self.sync_delay);
+        let result = match &self.wal {
+            Some(wal) => self.write_header_to_wal(wal, page_id, header),
+            None => self.write_header_to_file(page_id, header),
+        };
+        self.commit_syncs.fetch_add(1, Ordering::Relaxed);
+        self.commit_sync_nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn write_header_to_file(&self, page_id: PageID, header: &HeaderNode) -> DCBResult<()> {
+        // Sync the pages to disk before the header points to them
+        self.sync_commit()?;
+
+        // Mutate the owned header instance and serialize into the preallocated buffer
+        self.update_header(
+            page_id,
+            header.tsn,
+            header.free_lists_tree_root_id,
+            header.events_tree_root_id,
+            header.tags_tree_root_id,
+            header.next_page_id,
+            header.next_position,
+        )?;
+
+        // Sync the header to disk
+        self.sync_commit()
+    }
+
+    // Logs and syncs the header after the pages already logged, which makes the commit
+    // durable, then writes it into the database file without syncing the file
+    fn write_header_to_wal(
+        &self,
+        wal: &Wal,
+        page_id: PageID,
+        header: &HeaderNode,
+    ) -> DCBResult<()> {
+        {
+            let mut buf = self.header_page_buf.lock().unwrap();
+            serialize_page_into(&mut buf, &Node::Header(header.clone()))?;
+            wal.append_commit(page_id, &buf)?;
+        }
+        match self.durability {
+            DurabilityMode::NoSync => {}
+            DurabilityMode::SyncData => wal.sync(true)?,
+            DurabilityMode::Sync => wal.sync(false)?,
+        }
+
+        self.update_header(
+            page_id,
+            header.tsn,
+            header.free_lists_tree_root_id,
+            header.events_tree_root_id,
+            header.tags_tree_root_id,
+            header.next_page_id,
+            header.next_position,
+        )?;
+
+        if wal.len()? >= self.wal_config.checkpoint_bytes {
+            self.checkpoint_now()?;
+        }
+        Ok(())
+    }
+
+    // Syncs written pages according to the durability mode
+    fn sync_commit(&self) -> DCBResult<()> {
+        match self.durability {
+            DurabilityMode::NoSync => {}
+            DurabilityMode::SyncData => self.pager.sync_data()?,
+            DurabilityMode::Sync => self.pager.sync()?,
+        }
+        Ok(())
+    }
+
+    pub fn reader(&self) -> DCBResult<Reader> {
+        let (header_page_id, header_node) = self.get_latest_header()?;
+        Ok(self.new_reader(header_page_id, &header_node))
+    }
+
+    // Registers a reader of the snapshot of the header
+    fn new_reader(&self, header_page_id: PageID, header: &HeaderNode) -> Reader {
+        self.register_reader(Reader {
             header_page_id,
-            tsn: header_node.tsn,
-            events_tree_root_id: header_node.events_tree_root_id,
-            tags_tree_root_id: header_node.tags_tree_root_id,
-            next_position: header_node.next_position,
-            reader_id,
+            tsn: header.tsn,
+            events_tree_root_id: header.events_tree_root_id,
+            tags_tree_root_id: header.tags_tree_root_id,
+            next_position: header.next_position,
+            reader_id: 0,
             reader_tsns: Arc::clone(&self.reader_tsns),
-        };
+        })
+    }
+
+    // Gives the reader a unique ID, and registers its TSN so that the pages of its
+    // snapshot aren't reused
+    fn register_reader(&self, mut reader: Reader) -> Reader {
+        // Generate a unique ID for this reader using the counter (lock-free)
+        reader.reader_id = self.reader_id_counter.fetch_add(1, Ordering::Relaxed) + 1;
 
-        Ok(reader)
+        // Register the reader TSN (lock-free concurrent insert)
+        self.reader_tsns.insert(reader.reader_id, reader.tsn);
+        reader
     }
 
     pub fn writer(&self) -> DCBResult<Writer> {
@@ -267,8 +1936,34 @@ impl Mvcc {
             println!("Constructing writer...");
         }
 
+        // With group commit, wait for the previous writer, and build on its commit
+        // even if it isn't synced yet
+        let (group_slot, pending) = match &self.group_commit {
+            Some(group) => {
+                let (epoch, pending) = group.acquire_writer();
+                (Some((group.clone(), epoch)), pending)
+            }
+            None => (None, None),
+        };
+
         // Get the latest header
-        let (header_page_id, header_node) = self.get_latest_header()?;
+        let (header_page_id, header_node) = match pending {
+            Some(pending) => pending,
+            None => match self.get_latest_header() {
+                Ok(latest) => {
+                    if let Some((group, _)) = &group_slot {
+                        group.set_synced(latest.0, &latest.1);
+                    }
+                    latest
+                }
+                Err(err) => {
+                    if let Some((group, _)) = group_slot {
+                        group.release_writer();
+                    }
+                    return Err(err);
+                }
+            },
+        };
 
         // Create the writer
         let mut writer = Writer::new(
@@ -281,19 +1976,24 @@ impl Mvcc {
             header_node.next_position,
             self.verbose,
         );
+        writer.max_node_size = self.max_node_size;
+        writer.group_slot = group_slot;
 
         if self.verbose {
             println!("Constructed writer with {:?}", writer.tsn);
         }
 
+        // Forget the commits that are no longer retained, so their pages can be reused
+        self.prune_history(&mut self.history.lock().unwrap());
+
         // Find the reusable page IDs.
         writer.find_reusable_page_ids(self)?;
 
         Ok(writer)
     }
 
-    /// Write one or more pages to disk using the shared preallocated page buffer.
-    /// Returns the number of pages written.
+    /// Write one or more pages to disk using the shared preallocated page buffer,
+    /// encrypted if the database is. Returns the number of pages written.
     pub fn write_pages<'a, I>(&self, pages: I) -> DCBResult<usize>
     where
         I: IntoIterator<Item = &'a Page>,
@@ -301,16 +2001,71 @@ impl Mvcc {
         let mut buf = self.page_buf.lock().unwrap();
         let mut count = 0usize;
         for page in pages {
-            page.serialize_into(&mut buf)?;
-            self.pager.write_page(page.page_id, &buf)?;
-            if self.verbose {
-                println!("Wrote {:?} to file", page.page_id);
-            }
+            self.serialize_page(page, &mut buf)?;
+            self.write_serialized_page(page.page_id, &buf)?;
             count += 1;
         }
         Ok(count)
     }
 
+    // Serializes the page into a page-sized buffer, encrypted if the database is
+    fn serialize_page(&self, page: &Page, buf: &mut [u8]) -> DCBResult<()> {
+        page.serialize_into(buf)?;
+        if let Some(cipher) = &self.encryption {
+            cipher.seal_page(page.page_id, buf)?;
+        }
+        Ok(())
+    }
+
+    fn write_serialized_page(&self, page_id: PageID, buf: &[u8]) -> DCBResult<()> {
+        self.pager.write_page(page_id, buf)?;
+        // Only pages no reader can see are rewritten, so nothing reads the old version
+        if let Some(cache) = &self.page_cache {
+            cache.invalidate(page_id);
+        }
+        if let Some(wal) = &self.wal {
+            wal.append_page(page_id, buf)?;
+        }
+        if self.verbose {
+            println!("Wrote {page_id:?} to file");
+        }
+        Ok(())
+    }
+
+    // Copies the spilled pages of a writer into the file, in the order they were spilled
+    fn write_spilled_pages(&self, spilled: SpilledPages) -> DCBResult<usize> {
+        let SpilledPages {
+            mut file,
+            page_ids,
+            mut buf,
+        } = spilled;
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        for page_id in &page_ids {
+            reader.read_exact(&mut buf)?;
+            self.write_serialized_page(*page_id, &buf)?;
+        }
+        Ok(page_ids.len())
+    }
+
+    /// Creates a file for temporary data, next to the database file if it has one.
+    /// The file is removed as soon as it's created, so it goes when it's closed.
+    pub fn create_temp_file(&self) -> DCBResult<fs::File> {
+        let mut path = if self.path.as_os_str().is_empty() {
+            std::env::temp_dir().join("umadb").into_os_string()
+        } else {
+            self.path.clone().into_os_string()
+        };
+        path.push(format!(".tmp-{:016x}", rand::random::<u64>()));
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        fs::remove_file(&path)?;
+        Ok(file)
+    }
+
     // pub fn write_pages_parallel<'a, I>(&self, pages: I) -> DCBResult<usize>
     // where
     //     I: IntoIterator<Item = &'a Page> + Send,
@@ -348,7 +2103,24 @@ impl Mvcc {
     //     count
     // }
 
-    pub fn commit(&self, writer: &mut Writer) -> DCBResult<()> {
+    /// Commits the writer's changes and returns the Tsn assigned to the transaction.
+    pub fn commit(&self, writer: &mut Writer) -> DCBResult<Tsn> {
+        self.commit_with_stats(writer).map(|stats| stats.tsn)
+    }
+
+    /// Like commit(), but returns how much was written and how long syncing took.
+    pub fn commit_with_stats(&self, writer: &mut Writer) -> DCBResult<CommitStats> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "commit",
+            tsn = writer.tsn.0,
+            dirty_pages = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+        )
+        .entered();
+
+        let started = Instant::now();
+
         // Process reused and freed page IDs
         if self.verbose {
             println!();
@@ -372,6 +2144,19 @@ impl Mvcc {
             }
         }
 
+        let free_lists_done = Instant::now();
+        // The dirty pages, and those spilled to a temporary file
+        let pages_written = writer.dirty.len()
+            + writer
+                .spilled
+                .as_ref()
+                .map_or(0, |spilled| spilled.page_ids.len());
+        #[cfg(feature = "tracing")]
+        {
+            span.record("dirty_pages", pages_written);
+            span.record("bytes", pages_written * self.page_size);
+        }
+
         // Write all dirty pages (except for the header page) to the file
         if !writer.dirty.is_empty() {
             let count = {
@@ -385,36 +2170,107 @@ impl Mvcc {
                 self.write_pages(writer.dirty.values())?
             };
             if self.verbose {
-                println!("Wrote {} dirty page(s) to file", count);
+                println!("Wrote {} dirty page(s) to file", count);
+            }
+        }
+        if let Some(spilled) = writer.spilled.take() {
+            let count = self.write_spilled_pages(spilled)?;
+            if self.verbose {
+                println!("Wrote {count} spilled page(s) to file");
+            }
+        }
+        let write_done = Instant::now();
+
+        let header_page_id = if writer.header_page_id == HEADER_PAGE_ID_0 {
+            HEADER_PAGE_ID_1
+        } else {
+            HEADER_PAGE_ID_0
+        };
+        let header = HeaderNode {
+            tsn: writer.tsn,
+            free_lists_tree_root_id: writer.free_lists_tree_root_id,
+            events_tree_root_id: writer.events_tree_root_id,
+            tags_tree_root_id: writer.tags_tree_root_id,
+            next_page_id: writer.next_page_id,
+            next_position: writer.next_position,
+            page_size: self.page_size as u32,
+            overflow_chunk_size: self.recorded_overflow_chunk_size(),
+            encryption: self.encryption_params(),
+        };
+
+        // Registered before the header is written, so that no later commit can reuse
+        // the pages of this one before it is retained
+        let mut retained = (self.retention_policy != RetentionPolicy::Latest)
+            .then(|| self.new_reader(header_page_id, &header));
+
+        match writer.group_slot.take() {
+            // Share the header write and syncs with concurrent commits
+            Some((group, epoch)) => {
+                let written_page_id = group.commit(self, epoch, header_page_id, header)?;
+                if let Some(reader) = &mut retained {
+                    reader.header_page_id = written_page_id;
+                }
+            }
+            None => self.write_header_durably(header_page_id, &header)?,
+        }
+        let header_done = Instant::now();
+        if let Some(reader) = retained {
+            let mut history = self.history.lock().unwrap();
+            history.push_back((Instant::now(), reader));
+            self.prune_history(&mut history);
+        }
+
+        // Group commits can return out of order, and the head never moves back
+        let head = Position(writer.next_position.0.saturating_sub(1));
+        self.head.send_if_modified(|current| {
+            let advanced = head > *current;
+            if advanced {
+                *current = head;
+            }
+            advanced
+        });
+
+        let threshold = self.slow_commit_threshold_nanos.load(Ordering::Relaxed);
+        let finished = Instant::now();
+        let total_time = finished - started;
+        if threshold > 0 && total_time > Duration::from_nanos(threshold) {
+            let slow = SlowCommit {
+                tsn: writer.tsn,
+                dirty_pages: pages_written,
+                bytes: pages_written * self.page_size,
+                free_lists_time: free_lists_done - started,
+                write_time: write_done - free_lists_done,
+                sync_time: finished - write_done,
+                total_time,
+            };
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                tsn = slow.tsn.0,
+                dirty_pages = slow.dirty_pages,
+                bytes = slow.bytes,
+                free_lists_time = ?slow.free_lists_time,
+                write_time = ?slow.write_time,
+                sync_time = ?slow.sync_time,
+                total_time = ?slow.total_time,
+                "slow commit"
+            );
+            #[cfg(not(feature = "tracing"))]
+            if self.verbose {
+                println!("{slow}");
             }
+            *self.last_slow_commit.lock().unwrap() = Some(slow);
         }
 
-        // Sync the file to disk
-        self.fsync()?;
-
-        // Mutate the owned header instance and serialize into the preallocated buffer
-        self.update_header(
-            if writer.header_page_id == HEADER_PAGE_ID_0 {
-                HEADER_PAGE_ID_1
-            } else {
-                HEADER_PAGE_ID_0
-            },
-            writer.tsn,
-            writer.free_lists_tree_root_id,
-            writer.events_tree_root_id,
-            writer.tags_tree_root_id,
-            writer.next_page_id,
-            writer.next_position,
-        )?;
-
-        // Sync the file to disk
-        self.fsync()?;
-
         if self.verbose {
             println!("Committed writer with {:?}", writer.tsn);
         }
 
-        Ok(())
+        Ok(CommitStats {
+            tsn: writer.tsn,
+            pages_written,
+            bytes_written: (pages_written + 1) * self.page_size,
+            fsync_duration: header_done - write_done,
+        })
     }
 }
 
@@ -427,15 +2283,50 @@ pub struct Writer {
     pub events_tree_root_id: PageID,
     pub tags_tree_root_id: PageID,
     pub next_position: Position,
-    pub reusable_page_ids: VecDeque<(PageID, Tsn)>,
+    pub reusable_page_ids: VecDeque<(PageIdRange, Tsn)>,
     pub freed_page_ids: VecDeque<PageID>,
     pub deserialized: HashMap<PageID, Page>,
     pub dirty: HashMap<PageID, Page>,
     pub reused_page_ids: VecDeque<(PageID, Tsn)>,
     pub verbose: bool,
+    // Largest node that fits in a page, checked when pages are made dirty
+    pub max_node_size: usize,
+    // Held while the writer is active when group commit is enabled, with the
+    // epoch the writer was issued in
+    group_slot: Option<(Arc<GroupCommit>, u64)>,
+    // Pages written by commit after the dirty pages, see spill_page()
+    spilled: Option<SpilledPages>,
+}
+
+// Serialized pages kept in a temporary file until the writer is committed
+struct SpilledPages {
+    file: fs::File,
+    page_ids: Vec<PageID>,
+    buf: Vec<u8>,
+}
+
+impl Drop for Mvcc {
+    fn drop(&mut self) {
+        if let Err(err) = self.close_inner() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %err, "failed to sync the database when dropped");
+            #[cfg(not(feature = "tracing"))]
+            eprintln!("Failed to sync the database when dropped: {err}");
+        }
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Let the next writer go ahead if this one wasn't committed
+        if let Some((group, _)) = self.group_slot.take() {
+            group.release_writer();
+        }
+    }
 }
 
 impl Writer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         header_page_id: PageID,
         tsn: Tsn,
@@ -460,7 +2351,28 @@ impl Writer {
             dirty: HashMap::new(),
             reused_page_ids: VecDeque::new(),
             verbose,
+            max_node_size: usize::MAX,
+            group_slot: None,
+            spilled: None,
+        }
+    }
+
+    /// Discards the writer's changes without committing.
+    ///
+    /// Nothing is written to the file until commit, so the committed state is
+    /// untouched. Page IDs allocated past the committed `next_page_id` are issued
+    /// again to the next writer, and reused page IDs stay in the free lists, since
+    /// they are only removed from the free lists by commit.
+    pub fn abort(mut self) {
+        if self.verbose {
+            println!("Aborting writer with {:?}", self.tsn);
         }
+        self.dirty.clear();
+        self.deserialized.clear();
+        self.freed_page_ids.clear();
+        self.reused_page_ids.clear();
+        self.reusable_page_ids.clear();
+        // Dropping the writer lets the next writer go ahead under group commit
     }
 
     /// Returns the current issue position and increments the next position.
@@ -525,12 +2437,99 @@ impl Writer {
         if self.dirty.contains_key(&page.page_id) {
             return Err(DCBError::PageAlreadyDirty(page.page_id.0));
         }
+        let node_size = page.node.calc_serialized_size();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            page_id = page.page_id.0,
+            node_type = page.node.type_name(),
+            node_size,
+            tsn = self.tsn.0,
+            "insert_dirty"
+        );
+        if node_size > self.max_node_size {
+            return Err(DCBError::PageOverflow(
+                page.page_id.0,
+                node_size,
+                self.max_node_size,
+            ));
+        }
         self.dirty.insert(page.page_id, page);
         Ok(())
     }
 
+    /// Inserts many dirty pages, with the same checks as insert_dirty(), and returns
+    /// how many were added. Stops at the first page that is rejected, leaving the
+    /// pages before it inserted.
+    pub fn insert_dirty_batch(
+        &mut self,
+        pages: impl IntoIterator<Item = Page>,
+    ) -> DCBResult<usize> {
+        let pages = pages.into_iter();
+        self.dirty.reserve(pages.size_hint().0);
+        let mut count = 0;
+        for page in pages {
+            self.insert_dirty(page)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Keeps a new page to be written by commit, as insert_dirty() does, but
+    /// serialized into a temporary file rather than in memory. Spilled pages can't
+    /// be read or changed by the writer, so this is for pages nothing else in the
+    /// commit needs, such as the overflow chain of a streamed event.
+    pub fn spill_page(&mut self, mvcc: &Mvcc, page: &Page) -> DCBResult<()> {
+        let node_size = page.node.calc_serialized_size();
+        if node_size > self.max_node_size {
+            return Err(DCBError::PageOverflow(
+                page.page_id.0,
+                node_size,
+                self.max_node_size,
+            ));
+        }
+        let spilled = match &mut self.spilled {
+            Some(spilled) => spilled,
+            None => self.spilled.insert(SpilledPages {
+                file: mvcc.create_temp_file()?,
+                page_ids: Vec::new(),
+                buf: vec![0u8; mvcc.page_size],
+            }),
+        };
+        mvcc.serialize_page(page, &mut spilled.buf)?;
+        spilled.file.write_all(&spilled.buf)?;
+        spilled.page_ids.push(page.page_id);
+        Ok(())
+    }
+
+    /// Allocates `n` consecutive page IDs and returns the first, carving them from
+    /// the next reusable range if it is long enough, or else extending the file.
+    pub fn alloc_page_ids(&mut self, n: usize) -> PageID {
+        let n = n as u64;
+        match self.reusable_page_ids.front() {
+            Some((range, _)) if n > 1 && range.count < n => {
+                let first = self.next_page_id;
+                self.next_page_id = PageID(first.0 + n);
+                first
+            }
+            _ => {
+                let first = self.alloc_page_id();
+                for _ in 1..n {
+                    self.alloc_page_id();
+                }
+                first
+            }
+        }
+    }
+
+    /// Allocates a page ID, reusing a freed page that no reader can still see, as
+    /// found by find_reusable_page_ids(), before extending the file.
     pub fn alloc_page_id(&mut self) -> PageID {
-        if let Some((free_page_id, tsn)) = self.reusable_page_ids.pop_front() {
+        if let Some((range, tsn)) = self.reusable_page_ids.front_mut() {
+            let tsn = *tsn;
+            let free_page_id = range.pop_first().expect("reusable ranges aren't empty");
+            if range.count == 0 {
+                self.reusable_page_ids.pop_front();
+            }
             self.reused_page_ids.push_back((free_page_id, tsn));
             return free_page_id;
         }
@@ -590,9 +2589,31 @@ impl Writer {
         }
     }
 
+    /// The page IDs found by find_reusable_page_ids() that are yet to be
+    /// allocated, with the TSNs that freed them.
+    pub fn reusable_pages(&self) -> impl Iterator<Item = (PageID, Tsn)> + '_ {
+        self.reusable_page_ids
+            .iter()
+            .flat_map(|&(range, tsn)| range.map(move |page_id| (page_id, tsn)))
+    }
+
     pub fn find_reusable_page_ids(&mut self, mvcc: &Mvcc) -> DCBResult<()> {
         let verbose = self.verbose;
-        let mut reusable_page_ids: VecDeque<(PageID, Tsn)> = VecDeque::new();
+        let mut reusable_page_ids: VecDeque<(PageIdRange, Tsn)> = VecDeque::new();
+        // Ranges are carved up by the allocator, so they must be within the file
+        let next_page_id = self.next_page_id;
+        let mut push_reusable = |page_ids: &PageIdRanges, tsn: Tsn| {
+            for &range in page_ids.ranges() {
+                if range.start.0 + range.count > next_page_id.0 {
+                    return Err(DCBError::DatabaseCorrupted(format!(
+                        "Free list for {tsn:?} has {} page IDs from {:?}, beyond the next page ID {:?}",
+                        range.count, range.start, next_page_id
+                    )));
+                }
+                reusable_page_ids.push_back((range, tsn));
+            }
+            Ok(())
+        };
         // Get free page IDs
         if verbose {
             println!("Finding reusable page IDs for TSN {:?}...", self.tsn);
@@ -600,6 +2621,16 @@ impl Writer {
 
         // Find the smallest reader TSN (lock-free iteration over concurrent map)
         let smallest_reader_tsn = mvcc.reader_tsns.iter().map(|r| *r.value()).min();
+        // With group commit, pages freed by commits that aren't synced yet are
+        // still part of the latest synced commit, so they can't be reused
+        let guard_tsn = mvcc
+            .group_commit
+            .as_ref()
+            .and_then(|group| group.guard_tsn());
+        let smallest_reader_tsn = match (smallest_reader_tsn, guard_tsn) {
+            (Some(reader_tsn), Some(guard_tsn)) => Some(reader_tsn.min(guard_tsn)),
+            (reader_tsn, guard_tsn) => reader_tsn.or(guard_tsn),
+        };
         if verbose {
             println!("Smallest reader TSN: {smallest_reader_tsn:?}");
         }
@@ -652,9 +2683,7 @@ impl Writer {
 
                         let leaf_value = &node.values[i];
                         if leaf_value.root_id == PageID(0) {
-                            for &page_id in &leaf_value.page_ids {
-                                reusable_page_ids.push_back((page_id, tsn));
-                            }
+                            push_reusable(&leaf_value.page_ids, tsn)?;
                         } else {
                             // Traverse into the TSN-subtree rooted at root_id using the same (node, idx)
                             // iterative DFS pattern as the main FreeList traversal. This preserves
@@ -690,9 +2719,7 @@ impl Writer {
                                         }
                                     }
                                     Node::FreeListTsnLeaf(tsn_leaf) => {
-                                        for &pid in &tsn_leaf.page_ids {
-                                            reusable_page_ids.push_back((pid, tsn));
-                                        }
+                                        push_reusable(&tsn_leaf.page_ids, tsn)?;
                                     }
                                     other => {
                                         return Err(DCBError::DatabaseCorrupted(format!(
@@ -846,28 +2873,20 @@ impl Writer {
                 };
                 let last_idx = leaf_ro.keys.len() - 1;
                 let mut page_ids = leaf_ro.values[last_idx].page_ids.clone();
-                page_ids.push(freed_page_id);
-                // sort + dedup the inline ids plus the new id
-                page_ids.sort_by_key(|pid| pid.0);
-                page_ids.dedup();
+                page_ids.insert(freed_page_id);
                 // Build the initial TSN-subtree: start with a single leaf that fits, then insert the rest
                 // Create an empty leaf and add as many as fit by size
-                let mut initial_ids: Vec<PageID> = Vec::new();
                 let mut tmp_leaf = FreeListTsnLeafNode {
-                    page_ids: Vec::new(),
+                    page_ids: PageIdRanges::new(),
                 };
                 for pid in &page_ids {
-                    let mut candidate = tmp_leaf.clone();
-                    candidate.page_ids.push(*pid);
-                    let candidate_page = Page::new(PageID(0), Node::FreeListTsnLeaf(candidate));
-                    if candidate_page.calc_serialized_size() <= mvcc.page_size {
-                        tmp_leaf.page_ids.push(*pid);
-                        initial_ids.push(*pid);
-                    } else {
+                    if !tmp_leaf.would_fit_new_page_id(mvcc.max_node_size) {
                         break;
                     }
+                    tmp_leaf.page_ids.insert(pid);
                 }
-                if initial_ids.is_empty() {
+                let num_initial_ids = tmp_leaf.page_ids.len();
+                if num_initial_ids == 0 {
                     return Err(DCBError::InternalError(
                         "Page size too small for TSN-subtree leaf with one PageID".to_string(),
                     ));
@@ -878,7 +2897,7 @@ impl Writer {
                 // Root id starts as this leaf
                 let mut tsn_root_id = tsn_leaf_id;
                 // Insert remaining ids via general insert, root may change
-                for pid in page_ids.into_iter().filter(|p| !initial_ids.contains(p)) {
+                for pid in page_ids.into_iter().skip(num_initial_ids) {
                     tsn_root_id = self.tsn_subtree_insert(mvcc, tsn_root_id, pid)?;
                 }
                 // Now mutate the freelist leaf to clear inline and set root
@@ -934,7 +2953,7 @@ impl Writer {
                         );
                     }
                     // Move the overflowing TSN to a new page and append there
-                    popped_value.page_ids.push(freed_page_id);
+                    popped_value.insert_page_id(freed_page_id);
                     let new_leaf_node = FreeListLeafNode {
                         keys: vec![popped_key],
                         values: vec![popped_value],
@@ -962,7 +2981,7 @@ impl Writer {
                     let new_leaf_node = FreeListLeafNode {
                         keys: vec![tsn],
                         values: vec![FreeListLeafValue {
-                            page_ids: vec![freed_page_id],
+                            page_ids: vec![freed_page_id].into(),
                             root_id: PageID(0),
                         }],
                     };
@@ -1042,7 +3061,7 @@ impl Writer {
 
             // Check if the internal page needs splitting
 
-            if dirty_internal_page.calc_serialized_size() > mvcc.page_size {
+            if !mvcc.fits(dirty_internal_page) {
                 if let Node::FreeListInternal(dirty_internal_node) = &mut dirty_internal_page.node {
                     if verbose {
                         println!("Splitting internal {dirty_page_id:?}...");
@@ -1180,23 +3199,22 @@ impl Writer {
                     "Expected TSN-subtree leaf".to_string(),
                 ));
             };
-            // Binary search by key
-            match leaf.page_ids.binary_search_by(|pid| pid.0.cmp(&key.0)) {
-                Ok(_) => {
+            match leaf.page_ids.insert(key) {
+                false => {
                     // Duplicate; nothing to do
                     if verbose {
                         println!("Duplicate PageID {:?} ignored in TSN-subtree", key);
                     }
                 }
-                Err(ins) => {
-                    leaf.page_ids.insert(ins, key);
+                true => {
                     if leaf.calc_serialized_size() <= mvcc.max_node_size {
                         return Ok(root_id);
                     }
-                    // Split the leaf in half without cloning the entire vector.
-                    let mid = leaf.page_ids.len() / 2; // left gets floor(n/2), right gets ceil(n/2)
-                    let right_ids: Vec<PageID> = leaf.page_ids.split_off(mid);
-                    let promoted_key = right_ids[0];
+                    // Split the leaf's ranges in half, which it has at least two of to
+                    // overflow; left gets floor(n/2), right gets ceil(n/2)
+                    let mid = leaf.page_ids.ranges().len() / 2;
+                    let right_ids = leaf.page_ids.split_off_ranges(mid);
+                    let promoted_key = right_ids.first().expect("right half has ranges");
                     // Create right leaf page
                     let right_leaf_id = self.alloc_page_id();
                     let right_leaf_node = FreeListTsnLeafNode {
@@ -1371,13 +3389,7 @@ impl Writer {
             let dirty_leaf_page = self.get_mut_dirty(dirty_page_id)?;
             if let Node::FreeListLeaf(dirty_leaf_node) = &mut dirty_leaf_page.node {
                 let leaf_value = &mut dirty_leaf_node.values[0];
-                if let Some(pos) = leaf_value
-                    .page_ids
-                    .iter()
-                    .position(|&id| id == used_page_id)
-                {
-                    leaf_value.page_ids.remove(pos);
-                } else {
+                if !leaf_value.remove_page_id(used_page_id) {
                     return Err(DCBError::DatabaseCorrupted(format!(
                         "{used_page_id:?} not found in {tsn:?}"
                     )));
@@ -1417,13 +3429,7 @@ impl Writer {
             let mut tsn_child_leaf_became_empty = false;
             match &mut dirty_tsn_root_page.node {
                 Node::FreeListTsnLeaf(tsn_leaf_node) => {
-                    if let Some(pos) = tsn_leaf_node
-                        .page_ids
-                        .iter()
-                        .position(|&id| id == used_page_id)
-                    {
-                        tsn_leaf_node.page_ids.remove(pos);
-                    } else {
+                    if !tsn_leaf_node.remove_page_id(used_page_id) {
                         return Err(DCBError::DatabaseCorrupted(format!(
                             "{used_page_id:?} not found in TSN-subtree for {tsn:?}"
                         )));
@@ -1433,7 +3439,7 @@ impl Writer {
                             "Removed {used_page_id:?} from TSN-subtree leaf {dirty_tsn_root_id:?} for {tsn:?}"
                         );
                     }
-                    if tsn_leaf_node.page_ids.is_empty() {
+                    if tsn_leaf_node.is_empty() {
                         tsn_leaf_became_empty = true;
                         removed_page_ids.push(dirty_tsn_root_id);
                     }
@@ -1483,11 +3489,7 @@ impl Writer {
                         let child_page = self.get_mut_dirty(dirty_child_id)?;
                         match &mut child_page.node {
                             Node::FreeListTsnLeaf(leaf_node) => {
-                                if let Some(pos) =
-                                    leaf_node.page_ids.iter().position(|&id| id == used_page_id)
-                                {
-                                    leaf_node.page_ids.remove(pos);
-                                } else {
+                                if !leaf_node.remove_page_id(used_page_id) {
                                     return Err(DCBError::DatabaseCorrupted(format!(
                                         "{used_page_id:?} not found in TSN-subtree for {tsn:?}"
                                     )));
@@ -1497,7 +3499,7 @@ impl Writer {
                                         "Removed {used_page_id:?} from TSN-subtree leaf {dirty_child_id:?} for {tsn:?}"
                                     );
                                 }
-                                if leaf_node.page_ids.is_empty() {
+                                if leaf_node.is_empty() {
                                     tsn_child_leaf_became_empty = true;
                                     removed_page_ids.push(dirty_child_id);
                                 }
@@ -1610,7 +3612,13 @@ impl Writer {
                         tsn.0, dirty_leaf_node
                     )));
                 }
-                if tsn_leaf_became_empty {
+                if tsn_leaf_became_empty && !dirty_leaf_node.values[0].page_ids.is_empty() {
+                    // The TSN-subtree was freed, but the entry still lists page IDs inline
+                    dirty_leaf_node.values[0].root_id = PageID(0);
+                    if verbose {
+                        println!("Freed TSN-subtree of {tsn:?} in {dirty_page_id:?}");
+                    }
+                } else if tsn_leaf_became_empty {
                     // Remove the TSN entry entirely
                     dirty_leaf_node.keys.remove(0);
                     dirty_leaf_node.values.remove(0);
@@ -1756,6 +3764,120 @@ enum FreePageIDInsertStrategy {
     CreateAndPromoteFreeListLeaf,
 }
 
+// Copies the pages of a tree into a fresh file, renumbering them consecutively
+struct Compactor<'a> {
+    mvcc: &'a Mvcc,
+    // New root IDs of the overflow chains copied so far, so shared chains stay shared
+    copied_chains: HashMap<PageID, PageID>,
+    cipher: Option<&'a PageCipher>,
+    pager: Pager,
+    next_page_id: PageID,
+}
+
+impl Compactor<'_> {
+    fn alloc_page_id(&mut self) -> PageID {
+        let page_id = self.next_page_id;
+        self.next_page_id = PageID(page_id.0 + 1);
+        page_id
+    }
+
+    fn write(&self, page: Page) -> DCBResult<()> {
+        let mut buf = self.mvcc.page_buf.lock().unwrap();
+        page.serialize_into(&mut buf)?;
+        if let Some(cipher) = self.cipher {
+            cipher.seal_page(page.page_id, &mut buf)?;
+        }
+        self.pager.write_page(page.page_id, &buf)
+    }
+
+    // Returns the new page ID of the copied tree's root
+    fn copy_tree(&mut self, page_id: PageID) -> DCBResult<PageID> {
+        let new_page_id = self.alloc_page_id();
+        let node = match self.mvcc.read_page(page_id)?.node {
+            Node::EventInternal(mut node) => {
+                for child_id in node.child_ids.iter_mut() {
+                    *child_id = self.copy_tree(*child_id)?;
+                }
+                Node::EventInternal(node)
+            }
+            Node::EventLeaf(node) => {
+                let (keys, mut values) = node.into_parts();
+                for value in values.iter_mut() {
+                    if let EventValue::Overflow { root_id, .. } = value {
+                        *root_id = match self.copied_chains.get(root_id) {
+                            Some(new_root_id) => *new_root_id,
+                            None => {
+                                let new_root_id = self.copy_overflow_chain(*root_id)?;
+                                self.copied_chains.insert(*root_id, new_root_id);
+                                new_root_id
+                            }
+                        };
+                    }
+                }
+                Node::EventLeaf(EventLeafNode::new(keys, values))
+            }
+            Node::TagsInternal(mut node) => {
+                for child_id in node.child_ids.iter_mut() {
+                    *child_id = self.copy_tree(*child_id)?;
+                }
+                Node::TagsInternal(node)
+            }
+            Node::TagsLeaf(mut node) => {
+                for value in node.values.iter_mut() {
+                    if value.root_id != PageID(0) {
+                        value.root_id = self.copy_tree(value.root_id)?;
+                    }
+                }
+                Node::TagsLeaf(node)
+            }
+            Node::TagInternal(mut node) => {
+                for child_id in node.child_ids.iter_mut() {
+                    *child_id = self.copy_tree(*child_id)?;
+                }
+                Node::TagInternal(node)
+            }
+            node @ Node::TagLeaf(_) => node,
+            other => {
+                return Err(DCBError::DatabaseCorrupted(format!(
+                    "Invalid node type in events or tags tree: {}",
+                    other.type_name()
+                )));
+            }
+        };
+        self.write(Page::new(new_page_id, node))?;
+        Ok(new_page_id)
+    }
+
+    // Overflow chains can be long, so they are copied in a loop
+    fn copy_overflow_chain(&mut self, root_id: PageID) -> DCBResult<PageID> {
+        let new_root_id = self.alloc_page_id();
+        let (mut page_id, mut new_page_id) = (root_id, new_root_id);
+        loop {
+            let mut node = match self.mvcc.read_page(page_id)?.node {
+                Node::EventOverflow(node) => node,
+                other => {
+                    return Err(DCBError::DatabaseCorrupted(format!(
+                        "Invalid node type in overflow chain: {}",
+                        other.type_name()
+                    )));
+                }
+            };
+            let next = node.next;
+            let new_next = if next == PageID(0) {
+                PageID(0)
+            } else {
+                self.alloc_page_id()
+            };
+            node.next = new_next;
+            self.write(Page::new(new_page_id, Node::EventOverflow(node)))?;
+            if next == PageID(0) {
+                return Ok(new_root_id);
+            }
+            (page_id, new_page_id) = (next, new_next);
+        }
+    }
+}
+
 // Reader transaction
 pub struct Reader {
     pub header_page_id: PageID,
@@ -1767,6 +3889,17 @@ pub struct Reader {
     reader_tsns: Arc<DashMap<usize, Tsn>>,
 }
 
+impl Reader {
+    /// Reads a page as of this reader's snapshot.
+    ///
+    /// Pages reachable from the reader's roots are not reused by writers while
+    /// the reader is alive, so the snapshot stays consistent however many
+    /// commits happen after it was taken.
+    pub fn read_page(&self, mvcc: &Mvcc, page_id: PageID) -> DCBResult<Page> {
+        mvcc.read_page(page_id)
+    }
+}
+
 impl Drop for Reader {
     fn drop(&mut self) {
         // Remove reader TSN from the concurrent map (lock-free)
@@ -1778,6 +3911,7 @@ impl Drop for Reader {
 mod tests {
     use super::*;
     use crate::free_lists_tree_nodes::FreeListLeafValue;
+    use crate::page::PAGE_HEADER_SIZE;
     use serial_test::serial;
     use tempfile::tempdir;
 
@@ -1785,62 +3919,543 @@ mod tests {
 
     #[test]
     #[serial]
-    fn test_mvcc_init() {
+    fn test_mvcc_init() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+
+        {
+            let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+                .unwrap()
+                .with_verbose(VERBOSE);
+            assert!(db.pager.is_new().unwrap());
+        }
+
+        {
+            let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+                .unwrap()
+                .with_verbose(VERBOSE);
+            assert!(!db.pager.is_new().unwrap());
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_write_transaction_incrementing_tsn_and_alternating_header() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+
+        {
+            let mut writer = db.writer().unwrap();
+            assert_eq!(Tsn(1), writer.tsn);
+            assert_eq!(PageID(0), writer.header_page_id);
+            db.commit(&mut writer).unwrap();
+        }
+
+        {
+            let mut writer = db.writer().unwrap();
+            assert_eq!(Tsn(2), writer.tsn);
+            assert_eq!(PageID(1), writer.header_page_id);
+            db.commit(&mut writer).unwrap();
+        }
+
+        {
+            let mut writer = db.writer().unwrap();
+            assert_eq!(Tsn(3), writer.tsn);
+            assert_eq!(PageID(0), writer.header_page_id);
+            db.commit(&mut writer).unwrap();
+        }
+
+        {
+            let mut writer = db.writer().unwrap();
+            assert_eq!(Tsn(4), writer.tsn);
+            assert_eq!(PageID(1), writer.header_page_id);
+            db.commit(&mut writer).unwrap();
+        }
+
+        {
+            let mut writer = db.writer().unwrap();
+            assert_eq!(Tsn(5), writer.tsn);
+            assert_eq!(PageID(0), writer.header_page_id);
+            db.commit(&mut writer).unwrap();
+        }
+        assert_eq!(5, db.commit_sync_stats().count);
+    }
+
+    #[test]
+    #[serial]
+    fn test_page_cache_hits_hot_leaf_and_is_invalidated_by_rewrite() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE)
+            .with_page_cache(2 * 4096);
+        let (_, header) = db.get_latest_header().unwrap();
+        let leaf_id = header.events_tree_root_id;
+
+        let first = db.read_page(leaf_id).unwrap();
+        let second = db.read_page(leaf_id).unwrap();
+        assert_eq!(format!("{first:?}"), format!("{second:?}"));
+        let stats = db.page_cache_stats().unwrap();
+        assert_eq!((1, 1, 1), (stats.hits, stats.misses, stats.entries));
+
+        // Header pages are never cached
+        db.read_page(HEADER_PAGE_ID_0).unwrap();
+        assert_eq!(1, db.page_cache_stats().unwrap().entries);
+
+        // Rewriting the page drops it from the cache
+        db.write_pages([&first]).unwrap();
+        assert_eq!(0, db.page_cache_stats().unwrap().entries);
+        db.read_page(leaf_id).unwrap();
+        assert_eq!(2, db.page_cache_stats().unwrap().misses);
+
+        // The least recently used page is evicted once the budget is used up
+        db.read_page(header.tags_tree_root_id).unwrap();
+        db.read_page(leaf_id).unwrap();
+        db.read_page(header.free_lists_tree_root_id).unwrap();
+        let stats = db.page_cache_stats().unwrap();
+        assert_eq!((2, 2 * 4096), (stats.entries, stats.bytes));
+        db.read_page(leaf_id).unwrap();
+        db.read_page(header.tags_tree_root_id).unwrap();
+        assert_eq!((3, 5), {
+            let stats = db.page_cache_stats().unwrap();
+            (stats.hits, stats.misses)
+        });
+    }
+
+    // Appends batches of 500 events, with some overflowed data and some UUIDs
+    fn append_export_events(store: &crate::db::UmaDB, batches: usize) {
+        use umadb_dcb::DCBEventStoreSync;
+
+        for batch in 0..batches {
+            let events = (0..500)
+                .map(|i| {
+                    // Every hundredth event overflows
+                    let data = if i % 100 == 0 {
+                        vec![batch as u8; 10_000]
+                    } else {
+                        format!("data-{batch}-{i}").into_bytes()
+                    };
+                    let event = DCBEvent::new()
+                        .event_type(format!("Type{}", i % 3))
+                        .data(data)
+                        .tags([format!("tag-{}", i % 7)]);
+                    if i % 2 == 0 {
+                        event.uuid(uuid::Uuid::new_v4())
+                    } else {
+                        event
+                    }
+                })
+                .collect();
+            store.append(events, None).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_export_ndjson_matches_read() {
+        use umadb_dcb::DCBEventStoreSync;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Arc::new(Mvcc::new(&db_path, 4096, DurabilityMode::NoSync).unwrap());
+        let store = crate::db::UmaDB::from_arc(db.clone());
+        append_export_events(&store, 6);
+
+        let mut out = Vec::new();
+        assert_eq!(3000, db.export_ndjson(&mut out).unwrap());
+        let text = String::from_utf8(out).unwrap();
+        let (expected, _) = store
+            .read(None, None, false, None, false)
+            .unwrap()
+            .collect_with_head()
+            .unwrap();
+        assert_eq!(expected.len(), text.lines().count());
+        for (line, expected) in text.lines().zip(&expected) {
+            let exported = umadb_dcb::ndjson::parse_ndjson_event(line).unwrap();
+            assert_eq!(expected.position, exported.position);
+            assert_eq!(expected.event.event_type, exported.event.event_type);
+            assert_eq!(expected.event.data, exported.event.data);
+            assert_eq!(expected.event.tags, exported.event.tags);
+            assert_eq!(expected.event.uuid, exported.event.uuid);
+        }
+    }
+
+    #[test]
+    fn test_import_ndjson_of_an_export_reads_the_same() {
+        use umadb_dcb::DCBEventStoreSync;
+
+        let temp_dir = tempdir().unwrap();
+        let source = Arc::new(
+            Mvcc::new(
+                &temp_dir.path().join("source.db"),
+                4096,
+                DurabilityMode::NoSync,
+            )
+            .unwrap(),
+        );
+        append_export_events(&crate::db::UmaDB::from_arc(source.clone()), 25);
+        let mut exported = Vec::new();
+        assert_eq!(12_500, source.export_ndjson(&mut exported).unwrap());
+
+        let target = Arc::new(
+            Mvcc::new(
+                &temp_dir.path().join("target.db"),
+                4096,
+                DurabilityMode::NoSync,
+            )
+            .unwrap(),
+        );
+        assert_eq!(12_500, target.import_ndjson(&exported[..]).unwrap());
+        let mut reexported = Vec::new();
+        target.export_ndjson(&mut reexported).unwrap();
+        assert!(exported == reexported);
+
+        // Tags are indexed as the events are imported
+        let query = umadb_dcb::DCBQuery::new().item(umadb_dcb::DCBQueryItem::new().tags(["tag-3"]));
+        let read_tagged = |db: &Arc<Mvcc>| {
+            crate::db::UmaDB::from_arc(db.clone())
+                .read(Some(query.clone()), None, false, None, false)
+                .unwrap()
+                .map(|e| e.unwrap().position)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(read_tagged(&source), read_tagged(&target));
+
+        // Positions that don't increase are rejected
+        let lines: Vec<&str> = std::str::from_utf8(&exported).unwrap().lines().collect();
+        let out_of_order = format!("{}\n{}\n", lines[1], lines[0]);
+        let empty = Mvcc::new(
+            &temp_dir.path().join("empty.db"),
+            4096,
+            DurabilityMode::NoSync,
+        )
+        .unwrap();
+        assert!(matches!(
+            empty.import_ndjson(out_of_order.as_bytes()),
+            Err(DCBError::DeserializationError(_))
+        ));
+        assert_eq!(1, empty.get_latest_header().unwrap().1.next_position.0);
+    }
+
+    #[test]
+    #[serial]
+    fn test_slow_commits_are_recorded_over_the_threshold() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let mut db = Mvcc::new(&db_path, 4096, DurabilityMode::NoSync).unwrap();
+        db.sync_delay = Duration::from_millis(50);
+
+        // Nothing is recorded until a threshold is set
+        let mut writer = db.writer().unwrap();
+        db.commit(&mut writer).unwrap();
+        assert_eq!(None, db.last_slow_commit());
+
+        db.set_slow_commit_threshold(Duration::from_millis(20));
+        let mut writer = db.writer().unwrap();
+        let tsn = writer.tsn;
+        db.commit(&mut writer).unwrap();
+        let slow = db.last_slow_commit().unwrap();
+        assert_eq!(tsn, slow.tsn);
+        assert_eq!(slow.dirty_pages * 4096, slow.bytes);
+        assert!(slow.sync_time >= Duration::from_millis(50));
+        assert!(slow.total_time >= slow.sync_time);
+        assert!(
+            slow.to_string()
+                .starts_with(&format!("Slow commit {tsn:?}"))
+        );
+
+        // Commits under the threshold aren't recorded
+        db.sync_delay = Duration::ZERO;
+        db.set_slow_commit_threshold(Duration::from_secs(10));
+        let mut writer = db.writer().unwrap();
+        db.commit(&mut writer).unwrap();
+        assert_eq!(Some(tsn), db.last_slow_commit().map(|slow| slow.tsn));
+    }
+
+    #[test]
+    #[serial]
+    fn test_pread_reads_match_mmap_reads() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let mmap_db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync).unwrap();
+        let mut writer = mmap_db.writer().unwrap();
+        mmap_db.commit(&mut writer).unwrap();
+        let pread_db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_mmap(false);
+        let (_, header) = pread_db.get_latest_header().unwrap();
+        for page_id in [
+            HEADER_PAGE_ID_0,
+            header.free_lists_tree_root_id,
+            header.events_tree_root_id,
+            header.tags_tree_root_id,
+        ] {
+            assert_eq!(
+                format!("{:?}", mmap_db.read_page(page_id).unwrap()),
+                format!("{:?}", pread_db.read_page(page_id).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_open_selects_older_header_when_newer_fails_checksum() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let page_size = 4096;
+        {
+            let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+            for _ in 0..3 {
+                let mut writer = db.writer().unwrap();
+                db.commit(&mut writer).unwrap();
+            }
+            assert_eq!(Tsn(2), db.read_header(HEADER_PAGE_ID_0).unwrap().tsn);
+            assert_eq!(Tsn(3), db.read_header(HEADER_PAGE_ID_1).unwrap().tsn);
+        }
+
+        // Flip a byte of the newer header's checksum
+        {
+            use std::io::{Read, Seek, SeekFrom, Write};
+            let mut file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&db_path)
+                .unwrap();
+            let crc_offset = HEADER_PAGE_ID_1.0 * page_size as u64 + 1;
+            let mut byte = [0u8; 1];
+            file.seek(SeekFrom::Start(crc_offset)).unwrap();
+            file.read_exact(&mut byte).unwrap();
+            file.seek(SeekFrom::Start(crc_offset)).unwrap();
+            file.write_all(&[byte[0] ^ 0xff]).unwrap();
+            file.sync_all().unwrap();
+        }
+
+        let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+        assert!(db.read_header(HEADER_PAGE_ID_1).is_err());
+        let (page_id, header) = db.get_latest_header().unwrap();
+        assert_eq!((HEADER_PAGE_ID_0, Tsn(2)), (page_id, header.tsn));
+
+        // The next commit is written over the corrupted header
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(3), db.commit(&mut writer).unwrap());
+        assert_eq!(Tsn(3), db.read_header(HEADER_PAGE_ID_1).unwrap().tsn);
+        assert_eq!(
+            (HEADER_PAGE_ID_1, Tsn(3)),
+            db.get_latest_header()
+                .map(|(page_id, header)| (page_id, header.tsn))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_commit_returns_increasing_tsns() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+
+        let mut writer = db.writer().unwrap();
+        let tsn1 = db.commit(&mut writer).unwrap();
+        let mut writer = db.writer().unwrap();
+        let tsn2 = db.commit(&mut writer).unwrap();
+
+        assert_eq!(Tsn(1), tsn1);
+        assert!(tsn2 > tsn1);
+        assert_eq!(tsn2, db.reader().unwrap().tsn);
+    }
+
+    #[test]
+    #[serial]
+    fn test_commit_with_each_durability_mode() {
+        for mode in [
+            DurabilityMode::NoSync,
+            DurabilityMode::SyncData,
+            DurabilityMode::Sync,
+        ] {
+            let temp_dir = tempdir().unwrap();
+            let db_path = temp_dir.path().join("mvcc-test.db");
+            {
+                let db = Mvcc::new(&db_path, 4096, mode).unwrap();
+                assert_eq!(mode, db.durability);
+                let mut writer = db.writer().unwrap();
+                assert_eq!(Tsn(1), db.commit(&mut writer).unwrap());
+            }
+
+            // Committed state is visible after reopening
+            let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync).unwrap();
+            assert_eq!(Tsn(1), db.reader().unwrap().tsn);
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_group_commit_concurrent_writers() {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-
-        {
-            let db = Mvcc::new(&db_path, 4096, VERBOSE).unwrap();
-            assert!(db.pager.is_file_new);
-        }
-
-        {
-            let db = Mvcc::new(&db_path, 4096, VERBOSE).unwrap();
-            assert!(!db.pager.is_file_new);
-        }
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE)
+            .with_group_commit(GroupCommitConfig {
+                max_batch_size: 4,
+                linger: Duration::from_millis(5),
+            });
+
+        const NUM_THREADS: usize = 8;
+        let tsns: Vec<Tsn> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..NUM_THREADS)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut writer = db.writer().unwrap();
+                        let page_id = writer.alloc_page_id();
+                        let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+                        writer
+                            .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+                            .unwrap();
+                        db.commit(&mut writer).unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // Every commit got its own Tsn, and all of them are visible to readers
+        let mut sorted = tsns.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(NUM_THREADS, sorted.len());
+        assert_eq!(Tsn(NUM_THREADS as u64), *sorted.last().unwrap());
+        assert_eq!(Tsn(NUM_THREADS as u64), db.reader().unwrap().tsn);
+
+        // A dropped writer doesn't block the next one
+        drop(db.writer().unwrap());
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(NUM_THREADS as u64 + 1), db.commit(&mut writer).unwrap());
     }
 
     #[test]
     #[serial]
-    fn test_write_transaction_incrementing_tsn_and_alternating_header() {
+    fn test_reader_keeps_snapshot_while_writer_commits() {
+        use crate::events_tree::{EventIterator, event_tree_append};
+        use crate::events_tree_nodes::EventRecord;
+
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-        let db = Mvcc::new(&db_path, 4096, VERBOSE).unwrap();
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        let record = |i: u8| EventRecord {
+            event_type: format!("Event{i}"),
+            data: vec![i],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let read_all = |reader: &Reader| {
+            let no_dirty = HashMap::new();
+            let mut events =
+                EventIterator::new(&db, &no_dirty, reader.events_tree_root_id, None, false);
+            events.next_batch(100).unwrap()
+        };
 
-        {
-            let mut writer = db.writer().unwrap();
-            assert_eq!(Tsn(1), writer.tsn);
-            assert_eq!(PageID(0), writer.header_page_id);
-            db.commit(&mut writer).unwrap();
-        }
+        let mut writer = db.writer().unwrap();
+        let position = writer.issue_position();
+        event_tree_append(&db, &mut writer, record(1), position).unwrap();
+        db.commit(&mut writer).unwrap();
 
-        {
-            let mut writer = db.writer().unwrap();
-            assert_eq!(Tsn(2), writer.tsn);
-            assert_eq!(PageID(1), writer.header_page_id);
-            db.commit(&mut writer).unwrap();
-        }
+        let reader = db.reader().unwrap();
+        assert_eq!(Tsn(1), reader.tsn);
 
-        {
+        // Commit more events after the reader was opened
+        for i in 2..=5 {
             let mut writer = db.writer().unwrap();
-            assert_eq!(Tsn(3), writer.tsn);
-            assert_eq!(PageID(0), writer.header_page_id);
+            let position = writer.issue_position();
+            event_tree_append(&db, &mut writer, record(i), position).unwrap();
             db.commit(&mut writer).unwrap();
         }
 
-        {
-            let mut writer = db.writer().unwrap();
-            assert_eq!(Tsn(4), writer.tsn);
-            assert_eq!(PageID(1), writer.header_page_id);
-            db.commit(&mut writer).unwrap();
-        }
+        // The reader still sees its snapshot
+        let events = read_all(&reader);
+        assert_eq!(1, events.len());
+        assert_eq!(record(1), events[0].1);
+        assert!(matches!(
+            reader
+                .read_page(&db, reader.events_tree_root_id)
+                .unwrap()
+                .node,
+            Node::EventLeaf(_)
+        ));
+
+        // A new reader sees all commits
+        let latest = db.reader().unwrap();
+        assert_eq!(Tsn(5), latest.tsn);
+        assert_eq!(5, read_all(&latest).len());
+    }
 
-        {
-            let mut writer = db.writer().unwrap();
-            assert_eq!(Tsn(5), writer.tsn);
-            assert_eq!(PageID(0), writer.header_page_id);
-            db.commit(&mut writer).unwrap();
+    #[test]
+    #[serial]
+    fn test_writer_abort_leaves_committed_state_untouched() {
+        use crate::events_tree::{EventIterator, event_tree_append};
+        use crate::events_tree_nodes::EventRecord;
+
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        let record = EventRecord {
+            event_type: "Event".to_string(),
+            data: vec![1, 2, 3],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+
+        let mut writer = db.writer().unwrap();
+        let position = writer.issue_position();
+        event_tree_append(&db, &mut writer, record.clone(), position).unwrap();
+        db.commit(&mut writer).unwrap();
+        let before = db.reader().unwrap();
+        let before_tsn = before.tsn;
+        let before_root = before.events_tree_root_id;
+        drop(before);
+
+        // Make changes, then abort
+        let mut writer = db.writer().unwrap();
+        let next_page_id = writer.next_page_id;
+        for _ in 0..10 {
+            let position = writer.issue_position();
+            event_tree_append(&db, &mut writer, record.clone(), position).unwrap();
         }
+        let page_id = writer.alloc_page_id();
+        let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+        writer
+            .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+            .unwrap();
+        assert!(!writer.dirty.is_empty());
+        writer.abort();
+
+        // The next reader sees no change
+        let reader = db.reader().unwrap();
+        assert_eq!(before_tsn, reader.tsn);
+        assert_eq!(before_root, reader.events_tree_root_id);
+        let no_dirty = HashMap::new();
+        let mut events =
+            EventIterator::new(&db, &no_dirty, reader.events_tree_root_id, None, false);
+        assert_eq!(vec![(position, record)], events.next_batch(100).unwrap());
+
+        // The next writer starts from the committed state
+        let writer = db.writer().unwrap();
+        assert_eq!(Tsn(before_tsn.0 + 1), writer.tsn);
+        assert_eq!(next_page_id, writer.next_page_id);
     }
 
     #[test]
@@ -1848,7 +4463,9 @@ mod tests {
     fn test_read_transaction_header_and_tsn() {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-        let db = Mvcc::new(&db_path, 4096, VERBOSE).unwrap();
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
 
         // Initial reader should see TSN 0
         {
@@ -1934,106 +4551,907 @@ mod tests {
 
     #[test]
     #[serial]
-    fn test_copy_on_write_page_reuse() {
+    fn test_commit_reuses_freed_pages_before_growing_the_file() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 512, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        let leaf_pages = |writer: &mut Writer| -> Vec<Page> {
+            (0..50u64)
+                .map(|i| {
+                    let leaf = EventLeafNode::new(vec![Position(i + 1)], vec![]);
+                    Page::new(writer.alloc_page_id(), Node::EventLeaf(leaf))
+                })
+                .collect()
+        };
+
+        let mut writer = db.writer().unwrap();
+        let pages = leaf_pages(&mut writer);
+        let page_ids: Vec<PageID> = pages.iter().map(|page| page.page_id).collect();
+        writer.insert_dirty_batch(pages).unwrap();
+        db.commit(&mut writer).unwrap();
+
+        let mut writer = db.writer().unwrap();
+        for page_id in &page_ids {
+            writer.append_freed_page_id(*page_id);
+        }
+        db.commit(&mut writer).unwrap();
+        let file_len = std::fs::metadata(&db_path).unwrap().len();
+
+        // With no readers, the freed pages are reused for the new data
+        let mut writer = db.writer().unwrap();
+        let next_page_id = writer.next_page_id;
+        let pages = leaf_pages(&mut writer);
+        assert!(pages.iter().all(|page| page.page_id < next_page_id));
+        writer.insert_dirty_batch(pages).unwrap();
+        db.commit(&mut writer).unwrap();
+
+        let (_, header) = db.get_latest_header().unwrap();
+        assert_eq!(next_page_id, header.next_page_id);
+        assert_eq!(file_len, std::fs::metadata(&db_path).unwrap().len());
+    }
+
+    #[test]
+    #[serial]
+    fn test_copy_on_write_page_reuse() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        // First transaction
+        {
+            let mut writer = db.writer().unwrap();
+
+            // Check there are no free pages
+            assert_eq!(0, writer.reusable_pages().count());
+
+            // Check the free list root is page 2
+            assert_eq!(PageID(2), writer.free_lists_tree_root_id);
+
+            // Check the position root is page 3
+            assert_eq!(PageID(3), writer.events_tree_root_id);
+
+            // Allocate and insert free page ID.
+            let free_page_id = writer.alloc_page_id();
+            assert_eq!(PageID(5), free_page_id);
+            writer
+                .insert_freed_page_id(&db, writer.tsn, free_page_id)
+                .unwrap();
+
+            // Check the dirty page IDs
+            assert_eq!(1, writer.dirty.len());
+            assert_eq!(PageID(6), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+
+            // Check the freed page IDs
+            assert_eq!(1, writer.freed_page_ids.len());
+            assert_eq!(PageID(2), writer.freed_page_ids[0]);
+
+            db.commit(&mut writer).unwrap();
+        }
+
+        // Second transaction
+        {
+            let mut writer = db.writer().unwrap();
+
+            // Check there are two free pages
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((PageID(2), Tsn(1)), writer.reusable_pages().next().unwrap());
+            assert_eq!((PageID(5), Tsn(1)), writer.reusable_pages().nth(1).unwrap());
+
+            // Check the free list root is page 2
+            assert_eq!(PageID(6), writer.free_lists_tree_root_id);
+
+            // Check the position root is page 3
+            assert_eq!(PageID(3), writer.events_tree_root_id);
+
+            // Allocate and insert free page ID.
+            let free_page_id = writer.alloc_page_id();
+            assert_eq!(PageID(2), free_page_id);
+            writer
+                .insert_freed_page_id(&db, writer.tsn, free_page_id)
+                .unwrap();
+
+            // Check the dirty page IDs
+            assert_eq!(1, writer.dirty.len());
+            assert_eq!(PageID(5), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+
+            // Check the freed page IDs
+            assert_eq!(1, writer.freed_page_ids.len());
+            assert_eq!(PageID(6), writer.freed_page_ids[0]);
+
+            db.commit(&mut writer).unwrap();
+        }
+
+        // Third transaction
+        {
+            let mut writer = db.writer().unwrap();
+
+            // Check there are two free pages
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((PageID(2), Tsn(2)), writer.reusable_pages().next().unwrap());
+            assert_eq!((PageID(6), Tsn(2)), writer.reusable_pages().nth(1).unwrap());
+
+            // Check the free list root is page 5
+            assert_eq!(PageID(5), writer.free_lists_tree_root_id);
+
+            // Check the position root is page 3
+            assert_eq!(PageID(3), writer.events_tree_root_id);
+
+            // Allocate and insert free page ID.
+            let free_page_id = writer.alloc_page_id();
+            assert_eq!(PageID(2), free_page_id);
+            writer
+                .insert_freed_page_id(&db, writer.tsn, free_page_id)
+                .unwrap();
+
+            // Check the dirty page IDs
+            assert_eq!(1, writer.dirty.len());
+            assert_eq!(PageID(6), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+
+            // Check the freed page IDs
+            assert_eq!(1, writer.freed_page_ids.len());
+            assert_eq!(PageID(5), writer.freed_page_ids[0]);
+
+            db.commit(&mut writer).unwrap();
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_reopen_with_different_page_size_is_an_error() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        {
+            let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync).unwrap();
+            let (_, header) = db.get_latest_header().unwrap();
+            assert_eq!(4096, header.page_size);
+        }
+
+        match Mvcc::new(&db_path, 8192, DurabilityMode::Sync) {
+            Err(DCBError::PageSizeMismatch(stored, given)) => {
+                assert_eq!(4096, stored);
+                assert_eq!(8192, given);
+            }
+            Err(other) => panic!("Expected PageSizeMismatch, got {other:?}"),
+            Ok(_) => panic!("Expected PageSizeMismatch, got a database"),
+        }
+
+        // Reopening with the original page size still works
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync).unwrap();
+        let (_, header) = db.get_latest_header().unwrap();
+        assert_eq!(Tsn(0), header.tsn);
+    }
+
+    #[test]
+    #[serial]
+    fn test_flush_then_crash_reopens_at_the_last_commit() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let event = || DCBEvent::new().event_type("E").data(b"data".to_vec());
+
+        // Growing the file by a few pages keeps the copy of it below small
+        let db = Mvcc::new_with_growth_policy(
+            &db_path,
+            4096,
+            DurabilityMode::NoSync,
+            GrowthPolicy::Fixed { pages: 16 },
+        )
+        .unwrap();
+        let mut writer = db.writer().unwrap();
+        crate::db::unconditional_append(&db, &mut writer, vec![event(), event()]).unwrap();
+        db.commit(&mut writer).unwrap();
+        let (_, committed) = db.get_latest_header().unwrap();
+
+        // Flushing doesn't commit
+        db.flush().unwrap();
+        assert_eq!(committed, db.get_latest_header().unwrap().1);
+        // The file as it was synced by the flush
+        let synced = fs::read(&db_path).unwrap();
+
+        // A later commit isn't synced in NoSync mode, and a writer isn't committed
+        let mut writer = db.writer().unwrap();
+        crate::db::unconditional_append(&db, &mut writer, vec![event(), event()]).unwrap();
+        db.commit(&mut writer).unwrap();
+        let mut writer = db.writer().unwrap();
+        crate::db::unconditional_append(&db, &mut writer, vec![event()]).unwrap();
+        std::mem::forget(writer);
+        std::mem::forget(db);
+
+        // Lose the writes that weren't synced, as if the machine lost power
+        let file = fs::OpenOptions::new().write(true).open(&db_path).unwrap();
+        file.set_len(synced.len() as u64).unwrap();
+        std::os::unix::fs::FileExt::write_all_at(&file, &synced, 0).unwrap();
+        file.sync_all().unwrap();
+        drop(file);
+
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::NoSync).unwrap();
+        let (_, header) = db.get_latest_header().unwrap();
+        assert_eq!(committed.tsn, header.tsn);
+        assert_eq!(Position(3), header.next_position);
+    }
+
+    #[test]
+    #[serial]
+    fn test_open_recovers_from_unusable_latest_header() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let page_size = 4096;
+        let corrupt_header = |page_id: PageID| {
+            // Tear the header by zeroing the end of its node
+            use std::io::{Seek, SeekFrom, Write};
+            let mut file = fs::OpenOptions::new().write(true).open(&db_path).unwrap();
+            file.seek(SeekFrom::Start(page_id.0 * page_size as u64 + 40))
+                .unwrap();
+            file.write_all(&[0u8; 20]).unwrap();
+            file.sync_all().unwrap();
+        };
+
+        // The first commit is written to header 1
+        {
+            let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+            let mut writer = db.writer().unwrap();
+            db.commit(&mut writer).unwrap();
+            assert_eq!(
+                (HEADER_PAGE_ID_1, Tsn(1)),
+                db.get_latest_header()
+                    .map(|(page_id, header)| (page_id, header.tsn))
+                    .unwrap()
+            );
+        }
+
+        // A torn latest header falls back to the previous commit
+        corrupt_header(HEADER_PAGE_ID_1);
+        {
+            let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+            let (page_id, header) = db.get_latest_header().unwrap();
+            assert_eq!((HEADER_PAGE_ID_0, Tsn(0)), (page_id, header.tsn));
+
+            // A header that points past the end of the file is also passed over
+            let mut writer = db.writer().unwrap();
+            db.commit(&mut writer).unwrap();
+            db.update_header(
+                HEADER_PAGE_ID_1,
+                Tsn(2),
+                header.free_lists_tree_root_id,
+                PageID(1_000_000_000),
+                header.tags_tree_root_id,
+                PageID(1_000_000_001),
+                header.next_position,
+            )
+            .unwrap();
+            db.fsync().unwrap();
+        }
+        {
+            let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+            let (_, header) = db.get_latest_header().unwrap();
+            assert_eq!(Tsn(0), header.tsn);
+            assert_eq!(PageID(3), header.events_tree_root_id);
+            // Both headers now hold the recovered commit
+            assert_eq!(header, db.read_header(HEADER_PAGE_ID_1).unwrap());
+        }
+
+        // With neither header usable, the database can't be opened
+        corrupt_header(HEADER_PAGE_ID_0);
+        corrupt_header(HEADER_PAGE_ID_1);
+        assert!(matches!(
+            Mvcc::new(&db_path, page_size, DurabilityMode::Sync),
+            Err(DCBError::DatabaseCorrupted(_))
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_insert_dirty_rejects_node_too_big_for_page() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 512, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+        assert_eq!(512 - PAGE_HEADER_SIZE, Node::max_payload_for(512));
+
+        let mut writer = db.writer().unwrap();
+        let leaf = EventLeafNode::new(
+            vec![Position(1)],
+            vec![EventValue::Inline(crate::events_tree_nodes::EventRecord {
+                event_type: "E".to_string(),
+                data: vec![0u8; 1024],
+                tags: vec![],
+                uuid: None,
+                timestamp: None,
+                client_timestamp: None,
+                tsn: None,
+            })],
+        );
+        let size = leaf.calc_serialized_size();
+        let page_id = writer.alloc_page_id();
+        let result = writer.insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)));
+        match result {
+            Err(DCBError::PageOverflow(id, node_size, max)) => {
+                assert_eq!(page_id.0, id);
+                assert_eq!(size, node_size);
+                assert_eq!(Node::max_payload_for(512), max);
+            }
+            other => panic!("Expected PageOverflow, got {other:?}"),
+        }
+        assert!(writer.dirty.is_empty());
+    }
+
+    // Keeps pages in a map shared with the test, counting the syncs, which fail
+    // while fail_syncs is set
+    #[derive(Clone, Default)]
+    struct FakePageStore {
+        pages: Arc<Mutex<HashMap<PageID, Vec<u8>>>>,
+        syncs: Arc<AtomicU64>,
+        fail_syncs: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl PageStore for FakePageStore {
+        fn read_page(&self, page_id: PageID) -> DCBResult<Vec<u8>> {
+            self.pages
+                .lock()
+                .unwrap()
+                .get(&page_id)
+                .cloned()
+                .ok_or_else(|| DCBError::InternalError(format!("No page {page_id:?}")))
+        }
+
+        fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()> {
+            self.pages
+                .lock()
+                .unwrap()
+                .insert(page_id, page_data.to_vec());
+            Ok(())
+        }
+
+        fn sync(&self) -> DCBResult<()> {
+            if self.fail_syncs.load(Ordering::SeqCst) {
+                return Err(DCBError::Io(std::io::Error::other("sync failed")));
+            }
+            self.syncs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn len_pages(&self) -> DCBResult<u64> {
+            let pages = self.pages.lock().unwrap();
+            Ok(pages.keys().map(|page_id| page_id.0 + 1).max().unwrap_or(0))
+        }
+    }
+
+    #[test]
+    fn test_new_with_growth_policy() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("grow.db");
+        let page_size = 1024;
+        let db = Mvcc::new_with_growth_policy(
+            &db_path,
+            page_size,
+            DurabilityMode::NoSync,
+            GrowthPolicy::Fixed { pages: 64 },
+        )
+        .unwrap();
+        let file_len = || fs::metadata(&db_path).unwrap().len();
+        assert_eq!(64 * page_size as u64, file_len());
+
+        let mut writer = db.writer().unwrap();
+        for _ in 0..100 {
+            let page_id = writer.alloc_page_id();
+            let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+            writer
+                .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+                .unwrap();
+        }
+        db.commit(&mut writer).unwrap();
+        assert_eq!(128 * page_size as u64, file_len());
+    }
+
+    #[test]
+    fn test_new_with_capacity() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("reserved.db");
+        let page_size = 1024;
+        let reserve_bytes = 1024 * 1024 + 1;
+        let db =
+            Mvcc::new_with_capacity(&db_path, page_size, DurabilityMode::NoSync, reserve_bytes)
+                .unwrap();
+        let reserved_len = 1025 * page_size as u64;
+        assert_eq!(reserved_len, fs::metadata(&db_path).unwrap().len());
+        assert_eq!(5 * page_size as u64, db.logical_size().unwrap());
+
+        // Commits within the reservation don't extend the file
+        let mut writer = db.writer().unwrap();
+        for _ in 0..100 {
+            let page_id = writer.alloc_page_id();
+            let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+            writer
+                .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+                .unwrap();
+        }
+        db.commit(&mut writer).unwrap();
+        assert_eq!(reserved_len, fs::metadata(&db_path).unwrap().len());
+        assert!(db.logical_size().unwrap() >= 105 * page_size as u64);
+    }
+
+    #[test]
+    fn test_group_commit_batch_keeps_the_synced_header() {
+        let store = FakePageStore::default();
+        let db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync).unwrap();
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(1), db.commit(&mut writer).unwrap());
+        let (synced_page_id, _) = db.get_latest_header().unwrap();
+        drop(db);
+
+        // Two commits synced together, so an even-sized batch
+        let db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync)
+            .unwrap()
+            .with_group_commit(GroupCommitConfig {
+                max_batch_size: 2,
+                linger: Duration::from_secs(10),
+            });
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    let mut writer = db.writer().unwrap();
+                    db.commit(&mut writer).unwrap();
+                });
+            }
+        });
+        assert_eq!(1, db.commit_syncs.load(Ordering::Relaxed));
+        assert_eq!(Tsn(3), db.get_latest_header().unwrap().1.tsn);
+        assert_eq!(Tsn(1), db.read_header(synced_page_id).unwrap().tsn);
+        drop(db);
+
+        // Tearing the batch's header page leaves the commit synced before it
+        let written_page_id = PageID(1 - synced_page_id.0);
+        store
+            .pages
+            .lock()
+            .unwrap()
+            .get_mut(&written_page_id)
+            .unwrap()[PAGE_HEADER_SIZE] ^= 1;
+        let db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync).unwrap();
+        assert_eq!(
+            (synced_page_id, Tsn(1)),
+            db.get_latest_header()
+                .map(|(page_id, header)| (page_id, header.tsn))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_group_commit_failure_only_fails_its_batch() {
+        let store = FakePageStore::default();
+        let mut db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync)
+            .unwrap()
+            .with_group_commit(GroupCommitConfig {
+                max_batch_size: 1,
+                linger: Duration::ZERO,
+            });
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(1), db.commit(&mut writer).unwrap());
+
+        // The second writer is issued while the first commit syncs, so builds on it
+        store.fail_syncs.store(true, Ordering::SeqCst);
+        db.sync_delay = Duration::from_millis(100);
+        let results: Vec<DCBResult<Tsn>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut writer = db.writer().unwrap();
+                        db.commit(&mut writer)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(results.iter().all(|result| result.is_err()), "{results:?}");
+        store.fail_syncs.store(false, Ordering::SeqCst);
+        db.sync_delay = Duration::ZERO;
+
+        // Later writers build on the last synced commit
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(2), db.commit(&mut writer).unwrap());
+        assert_eq!(Tsn(2), db.get_latest_header().unwrap().1.tsn);
+        let mut writer = db.writer().unwrap();
+        assert_eq!(Tsn(3), db.commit(&mut writer).unwrap());
+    }
+
+    #[test]
+    fn test_commit_to_a_page_store() {
+        let store = FakePageStore::default();
+        let db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync).unwrap();
+        // The headers and the roots of the three trees
+        assert_eq!(5, store.pages.lock().unwrap().len());
+
+        let mut writer = db.writer().unwrap();
+        let page_id = writer.alloc_page_id();
+        let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+        writer
+            .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+            .unwrap();
+        let syncs = store.syncs.load(Ordering::SeqCst);
+        let tsn = db.commit(&mut writer).unwrap();
+        assert!(store.syncs.load(Ordering::SeqCst) > syncs);
+        assert!(store.pages.lock().unwrap().contains_key(&page_id));
+        assert!(db.with_wal(WalConfig::default()).is_err());
+
+        // Opening the store again finds the commit
+        let db = Mvcc::new_with_page_store(store.clone(), 512, DurabilityMode::Sync).unwrap();
+        let (_, header) = db.get_latest_header().unwrap();
+        assert_eq!(tsn, header.tsn);
+        match db.read_page(page_id).unwrap().node {
+            Node::EventLeaf(leaf) => assert!(leaf.keys().is_empty()),
+            other => panic!("Expected EventLeaf, got {other:?}"),
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_commit_with_stats() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let page_size = 4096;
+        let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync).unwrap();
+
+        let mut writer = db.writer().unwrap();
+        for _ in 0..3 {
+            let page_id = writer.alloc_page_id();
+            let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+            writer
+                .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+                .unwrap();
+        }
+        let stats = db.commit_with_stats(&mut writer).unwrap();
+        assert_eq!(Tsn(1), stats.tsn);
+        assert_eq!(3, stats.pages_written);
+        // The dirty pages and the header page
+        assert_eq!(4 * page_size, stats.bytes_written);
+        assert_eq!(stats.tsn, db.get_latest_header().unwrap().1.tsn);
+
+        // A commit with no dirty pages still writes the header
+        let mut writer = db.writer().unwrap();
+        let stats = db.commit_with_stats(&mut writer).unwrap();
+        assert_eq!(
+            (Tsn(2), 0, page_size),
+            (stats.tsn, stats.pages_written, stats.bytes_written)
+        );
+
+        // Spilled pages are counted with the dirty pages
+        let mut writer = db.writer().unwrap();
+        for _ in 0..2 {
+            let page_id = writer.alloc_page_id();
+            let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+            writer
+                .spill_page(&db, &Page::new(page_id, Node::EventLeaf(leaf)))
+                .unwrap();
+        }
+        let page_id = writer.alloc_page_id();
+        let leaf = EventLeafNode::new(Vec::new(), Vec::new());
+        writer
+            .insert_dirty(Page::new(page_id, Node::EventLeaf(leaf)))
+            .unwrap();
+        let stats = db.commit_with_stats(&mut writer).unwrap();
+        assert_eq!(
+            (3, 4 * page_size),
+            (stats.pages_written, stats.bytes_written)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_retention_policy() {
+        use crate::events_tree::{EventIterator, event_tree_append};
+        use crate::events_tree_nodes::EventRecord;
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-        let db = Mvcc::new(&db_path, 4096, VERBOSE).unwrap();
-        // First transaction
-        {
+        let db = Mvcc::new_with_retention_policy(
+            &db_path,
+            4096,
+            DurabilityMode::NoSync,
+            RetentionPolicy::Commits(2),
+        )
+        .unwrap();
+        let record = |i: u8| EventRecord {
+            event_type: format!("Event{i}"),
+            data: vec![i],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        let read_all = |reader: &Reader| {
+            let no_dirty = HashMap::new();
+            let mut events =
+                EventIterator::new(&db, &no_dirty, reader.events_tree_root_id, None, false);
+            events.next_batch(100).unwrap()
+        };
+        let append = |i: u8| {
             let mut writer = db.writer().unwrap();
+            let position = writer.issue_position();
+            event_tree_append(&db, &mut writer, record(i), position).unwrap();
+            db.commit(&mut writer).unwrap()
+        };
 
-            // Check there are no free pages
-            assert_eq!(0, writer.reusable_page_ids.len());
-
-            // Check the free list root is page 2
-            assert_eq!(PageID(2), writer.free_lists_tree_root_id);
-
-            // Check the position root is page 3
-            assert_eq!(PageID(3), writer.events_tree_root_id);
-
-            // Allocate and insert free page ID.
-            let free_page_id = writer.alloc_page_id();
-            assert_eq!(PageID(5), free_page_id);
-            writer
-                .insert_freed_page_id(&db, writer.tsn, free_page_id)
-                .unwrap();
-
-            // Check the dirty page IDs
-            assert_eq!(1, writer.dirty.len());
-            assert_eq!(PageID(6), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+        let first = append(1);
+        let pinned = db.reader_as_of(first).unwrap();
+        append(2);
+        assert_eq!(1, read_all(&db.reader_as_of(first).unwrap()).len());
 
-            // Check the freed page IDs
-            assert_eq!(1, writer.freed_page_ids.len());
-            assert_eq!(PageID(2), writer.freed_page_ids[0]);
+        // The pinned reader still reads its snapshot after it falls out of retention
+        for i in 3..=20 {
+            append(i);
+        }
+        assert!(matches!(
+            db.reader_as_of(first),
+            Err(DCBError::OutOfRetention(_))
+        ));
+        let events = read_all(&pinned);
+        assert_eq!(
+            (Position(1), vec![1u8]),
+            (events[0].0, events[0].1.data.clone())
+        );
+        assert_eq!(1, events.len());
 
-            db.commit(&mut writer).unwrap();
+        // Once it's dropped, the pages outside retention are reused
+        drop(pinned);
+        for i in 21..=30 {
+            append(i);
+        }
+        let next_page_id = db.get_latest_header().unwrap().1.next_page_id;
+        for i in 31..=60 {
+            append(i);
         }
+        assert_eq!(next_page_id, db.get_latest_header().unwrap().1.next_page_id);
+
+        // With a duration, commits are forgotten once they're older than it
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::NoSync)
+            .unwrap()
+            .with_retention_policy(RetentionPolicy::Duration(Duration::from_millis(100)))
+            .unwrap();
+        let first = db.get_latest_header().unwrap().1.tsn;
+        let mut writer = db.writer().unwrap();
+        let second = db.commit(&mut writer).unwrap();
+        assert_eq!(first, db.reader_as_of(first).unwrap().tsn);
+        sleep(Duration::from_millis(150));
+        let mut writer = db.writer().unwrap();
+        db.commit(&mut writer).unwrap();
+        assert!(db.reader_as_of(first).is_err());
+        assert!(db.reader_as_of(second).is_err());
+    }
 
-        // Second transaction
-        {
+    #[test]
+    #[serial]
+    fn test_rejected_compaction_keeps_the_history() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let mut db = Mvcc::new(&db_path, 4096, DurabilityMode::NoSync)
+            .unwrap()
+            .with_history_retention(10)
+            .unwrap();
+        let event = || DCBEvent::new().event_type("E").data(b"data".to_vec());
+        let mut tsns = Vec::new();
+        for _ in 0..3 {
             let mut writer = db.writer().unwrap();
+            crate::db::unconditional_append(&db, &mut writer, vec![event()]).unwrap();
+            tsns.push(db.commit(&mut writer).unwrap());
+        }
 
-            // Check there are two free pages
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((PageID(5), Tsn(1)), writer.reusable_page_ids[0]);
-            assert_eq!((PageID(2), Tsn(1)), writer.reusable_page_ids[1]);
-
-            // Check the free list root is page 2
-            assert_eq!(PageID(6), writer.free_lists_tree_root_id);
-
-            // Check the position root is page 3
-            assert_eq!(PageID(3), writer.events_tree_root_id);
-
-            // Allocate and insert free page ID.
-            let free_page_id = writer.alloc_page_id();
-            assert_eq!(PageID(5), free_page_id);
-            writer
-                .insert_freed_page_id(&db, writer.tsn, free_page_id)
-                .unwrap();
-
-            // Check the dirty page IDs
-            assert_eq!(1, writer.dirty.len());
-            assert_eq!(PageID(2), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+        let reader = db.reader().unwrap();
+        assert!(matches!(db.compact(), Err(DCBError::InternalError(_))));
+        drop(reader);
+        assert_eq!(tsns[0], db.reader_as_of(tsns[0]).unwrap().tsn);
+        let oldest = db.oldest_tsn().unwrap();
+        assert!(oldest <= tsns[0]);
+        assert_eq!(oldest, db.reader_as_of(oldest).unwrap().tsn);
+
+        // Once compacted, the oldest readable commit is the compacted one
+        db.compact().unwrap();
+        let latest = db.get_latest_header().unwrap().1.tsn;
+        assert_eq!(latest, db.oldest_tsn().unwrap());
+        assert!(matches!(
+            db.reader_as_of(tsns[0]),
+            Err(DCBError::OutOfRetention(_))
+        ));
+        let mut writer = db.writer().unwrap();
+        crate::db::unconditional_append(&db, &mut writer, vec![event()]).unwrap();
+        db.commit(&mut writer).unwrap();
+        assert_eq!(latest, db.oldest_tsn().unwrap());
+        assert_eq!(latest, db.reader_as_of(latest).unwrap().tsn);
+    }
 
-            // Check the freed page IDs
-            assert_eq!(1, writer.freed_page_ids.len());
-            assert_eq!(PageID(6), writer.freed_page_ids[0]);
+    #[test]
+    #[serial]
+    fn test_compacting_past_a_tsn_puts_it_out_of_retention() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let mut db = Mvcc::new_with_retention_policy(
+            &db_path,
+            4096,
+            DurabilityMode::NoSync,
+            RetentionPolicy::Commits(10),
+        )
+        .unwrap();
+        let event = || DCBEvent::new().event_type("E").data(b"data".to_vec());
+        let mut tsns = Vec::new();
+        for _ in 0..3 {
+            let mut writer = db.writer().unwrap();
+            crate::db::unconditional_append(&db, &mut writer, vec![event()]).unwrap();
+            tsns.push(db.commit(&mut writer).unwrap());
+        }
+        assert_eq!(Tsn(0), db.oldest_tsn().unwrap());
+        assert_eq!(tsns[0], db.reader_as_of(tsns[0]).unwrap().tsn);
+
+        db.compact().unwrap();
+        let latest = db.get_latest_header().unwrap().1.tsn;
+        assert_eq!(latest, db.oldest_tsn().unwrap());
+        assert!(matches!(
+            db.reader_as_of(tsns[0]),
+            Err(DCBError::OutOfRetention(_))
+        ));
+        assert_eq!(latest, db.reader_as_of(latest).unwrap().tsn);
+        // A commit that hasn't been made isn't a retention problem
+        assert!(matches!(
+            db.reader_as_of(Tsn(latest.0 + 1)),
+            Err(DCBError::InvalidArgument(_))
+        ));
+    }
 
-            db.commit(&mut writer).unwrap();
+    #[test]
+    #[serial]
+    fn test_insert_dirty_batch() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 512, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+
+        let mut writer = db.writer().unwrap();
+        let pages: Vec<Page> = (0..1000u64)
+            .map(|i| {
+                let leaf = EventLeafNode::new(
+                    vec![Position(i + 1)],
+                    vec![EventValue::Inline(crate::events_tree_nodes::EventRecord {
+                        event_type: "E".to_string(),
+                        data: i.to_le_bytes().to_vec(),
+                        tags: vec![],
+                        uuid: None,
+                        timestamp: None,
+                        client_timestamp: None,
+                        tsn: None,
+                    })],
+                );
+                Page::new(writer.alloc_page_id(), Node::EventLeaf(leaf))
+            })
+            .collect();
+        let page_ids: Vec<PageID> = pages.iter().map(|page| page.page_id).collect();
+        assert_eq!(1000, writer.insert_dirty_batch(pages).unwrap());
+        assert_eq!(1000, writer.dirty.len());
+        db.commit(&mut writer).unwrap();
+
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let page = db.read_page(*page_id).unwrap();
+            match page.node {
+                Node::EventLeaf(leaf) => assert_eq!(vec![Position(i as u64 + 1)], leaf.keys()),
+                other => panic!("Expected EventLeaf, got {other:?}"),
+            }
         }
 
-        // Third transaction
+        // A page that is already dirty is rejected, as by insert_dirty()
+        let mut writer = db.writer().unwrap();
+        let page_id = writer.alloc_page_id();
+        let page = Page::new(page_id, Node::EventLeaf(EventLeafNode::new(vec![], vec![])));
+        let result = writer.insert_dirty_batch([page.clone(), page]);
+        assert!(matches!(result, Err(DCBError::PageAlreadyDirty(id)) if id == page_id.0));
+        assert_eq!(1, writer.dirty.len());
+    }
+
+    #[test]
+    #[serial]
+    fn test_stats_counts_pages_and_free_pages() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 4096, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+
+        // A new database has the two headers and three empty tree roots
+        let stats = db.stats().unwrap();
+        assert_eq!(5, stats.total_pages);
+        assert_eq!(0, stats.free_pages);
+        assert_eq!(0, stats.event_count);
+        assert_eq!(1, stats.events_tree_height);
+        assert!(stats.file_size >= 5 * 4096);
+
+        // Allocate and free enough pages to overflow a leaf into a TSN subtree
+        let num_freed = 1000;
         {
             let mut writer = db.writer().unwrap();
+            for _ in 0..num_freed {
+                let page_id = writer.alloc_page_id();
+                writer
+                    .insert_freed_page_id(&db, writer.tsn, page_id)
+                    .unwrap();
+            }
+            db.commit(&mut writer).unwrap();
+        }
 
-            // Check there are two free pages
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((PageID(5), Tsn(2)), writer.reusable_page_ids[0]);
-            assert_eq!((PageID(6), Tsn(2)), writer.reusable_page_ids[1]);
-
-            // Check the free list root is page 2
-            assert_eq!(PageID(2), writer.free_lists_tree_root_id);
-
-            // Check the position root is page 3
-            assert_eq!(PageID(3), writer.events_tree_root_id);
-
-            // Allocate and insert free page ID.
-            let free_page_id = writer.alloc_page_id();
-            assert_eq!(PageID(5), free_page_id);
-            writer
-                .insert_freed_page_id(&db, writer.tsn, free_page_id)
-                .unwrap();
+        let stats = db.stats().unwrap();
+        let (_, header) = db.get_latest_header().unwrap();
+        assert_eq!(header.next_page_id.0, stats.total_pages);
+        assert!(stats.total_pages > 5 + num_freed);
+        assert!(stats.file_size >= stats.total_pages * 4096);
 
-            // Check the dirty page IDs
-            assert_eq!(1, writer.dirty.len());
-            assert_eq!(PageID(6), *writer.dirty.keys().collect::<Vec<_>>()[0]);
+        // With no readers, every page in the free list is reusable by the next writer
+        assert!(stats.free_pages >= num_freed);
+        let writer = db.writer().unwrap();
+        assert_eq!(stats.free_pages, writer.reusable_pages().count() as u64);
+    }
 
-            // Check the freed page IDs
-            assert_eq!(1, writer.freed_page_ids.len());
-            assert_eq!(PageID(2), writer.freed_page_ids[0]);
+    #[test]
+    #[serial]
+    fn test_tree_profile() {
+        use crate::events_tree::event_tree_append;
+        use crate::events_tree_nodes::EventRecord;
 
-            db.commit(&mut writer).unwrap();
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("mvcc-test.db");
+        let db = Mvcc::new(&db_path, 256, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
+
+        // A new database has just the empty root leaf
+        let profile = db.tree_profile().unwrap();
+        assert_eq!(1, profile.height);
+        assert_eq!(
+            vec![TreeLevelProfile {
+                internal_nodes: 0,
+                leaf_nodes: 1
+            }],
+            profile.levels
+        );
+        assert_eq!(0.0, profile.avg_keys_per_node);
+
+        // Leaves of 256 byte pages hold 14 of these events
+        let mut writer = db.writer().unwrap();
+        let event = EventRecord {
+            event_type: "Profile".into(),
+            data: vec![0xAB; 8],
+            tags: vec![],
+            uuid: None,
+            timestamp: None,
+            client_timestamp: None,
+            tsn: None,
+        };
+        for _ in 0..1000 {
+            let position = writer.issue_position();
+            event_tree_append(&db, &mut writer, event.clone(), position).unwrap();
         }
+        db.commit(&mut writer).unwrap();
+
+        let profile = db.tree_profile().unwrap();
+        assert_eq!(3, profile.height);
+        assert_eq!(db.stats().unwrap().events_tree_height, profile.height);
+        assert_eq!(
+            vec![
+                TreeLevelProfile {
+                    internal_nodes: 1,
+                    leaf_nodes: 0
+                },
+                TreeLevelProfile {
+                    internal_nodes: 6,
+                    leaf_nodes: 0
+                },
+                TreeLevelProfile {
+                    internal_nodes: 0,
+                    leaf_nodes: 1000usize.div_ceil(14) as u64
+                },
+            ],
+            profile.levels
+        );
+        assert!(profile.avg_keys_per_node > 10.0);
+        assert!(profile.avg_fill_factor > 0.8 && profile.avg_fill_factor <= 1.0);
     }
 
     // FreeListTree tests
@@ -2042,11 +5460,20 @@ mod tests {
         use serial_test::serial;
         use tempfile::tempdir;
 
+        // Allocates page IDs with gaps between them, so that freeing them lists
+        // each page ID rather than coalescing them into ranges
+        fn alloc_spaced_page_id(writer: &mut Writer) -> PageID {
+            writer.alloc_page_id();
+            writer.alloc_page_id()
+        }
+
         // Helper function to create a test database with a specified page size
         fn construct_mvcc(page_size: usize) -> (tempfile::TempDir, Mvcc) {
             let temp_dir = tempdir().unwrap();
             let db_path = temp_dir.path().join("mvcc-test.db");
-            let db = Mvcc::new(&db_path, page_size, VERBOSE).unwrap();
+            let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync)
+                .unwrap()
+                .with_verbose(VERBOSE);
             (temp_dir, db)
         }
 
@@ -2056,7 +5483,7 @@ mod tests {
             let (_temp_dir, db) = construct_mvcc(64);
             // New writer invokes find_reusable_page_ids in Mvcc::writer
             let writer = db.writer().unwrap();
-            assert_eq!(0, writer.reusable_page_ids.len());
+            assert_eq!(0, writer.reusable_pages().count());
         }
 
         #[test]
@@ -2069,9 +5496,91 @@ mod tests {
 
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((free_pid1, tsn), writer.reusable_page_ids[0]);
-            assert_eq!((free_pid2, tsn), writer.reusable_page_ids[1]);
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((free_pid1, tsn), writer.reusable_pages().next().unwrap());
+            assert_eq!((free_pid2, tsn), writer.reusable_pages().nth(1).unwrap());
+        }
+
+        #[test]
+        #[serial]
+        fn test_alloc_page_id_carves_reusable_pages_from_ranges() {
+            let (_temp_dir, db) = construct_mvcc(64);
+            let mut writer = db.writer().unwrap();
+            let freed: Vec<PageID> = (0..5).map(|_| writer.alloc_page_id()).collect();
+            let leaf = FreeListLeafNode {
+                keys: vec![Tsn(7)],
+                values: vec![FreeListLeafValue {
+                    page_ids: freed.clone().into(),
+                    root_id: PageID(0),
+                }],
+            };
+            let root_id = writer.alloc_page_id();
+            writer
+                .insert_dirty(Page::new(root_id, Node::FreeListLeaf(leaf)))
+                .unwrap();
+            writer.free_lists_tree_root_id = root_id;
+
+            // Consecutive freed pages are found as one range and allocated in order
+            writer.find_reusable_page_ids(&db).unwrap();
+            assert_eq!(1, writer.reusable_page_ids.len());
+            for &page_id in &freed {
+                assert_eq!(page_id, writer.alloc_page_id());
+            }
+            assert!(writer.reusable_page_ids.is_empty());
+            assert_eq!(
+                freed,
+                writer
+                    .reused_page_ids
+                    .iter()
+                    .map(|&(p, _)| p)
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(PageID(root_id.0 + 1), writer.alloc_page_id());
+        }
+
+        #[test]
+        #[serial]
+        fn test_alloc_page_ids_skips_reusable_ranges_that_are_too_short() {
+            let (_temp_dir, db) = construct_mvcc(64);
+            let mut writer = db.writer().unwrap();
+            let next_page_id = writer.next_page_id;
+            writer.reusable_page_ids.push_back((
+                PageIdRange {
+                    start: PageID(next_page_id.0 + 100),
+                    count: 2,
+                },
+                Tsn(1),
+            ));
+
+            // Three consecutive pages extend the file, leaving the range of two
+            assert_eq!(next_page_id, writer.alloc_page_ids(3));
+            assert_eq!(PageID(next_page_id.0 + 100), writer.alloc_page_ids(2));
+            assert!(writer.reusable_page_ids.is_empty());
+            assert_eq!(PageID(next_page_id.0 + 3), writer.alloc_page_id());
+        }
+
+        #[test]
+        #[serial]
+        fn test_find_reusable_page_ids_rejects_ranges_past_next_page_id() {
+            let (_temp_dir, db) = construct_mvcc(64);
+            let mut writer = db.writer().unwrap();
+            let root_id = writer.alloc_page_id();
+            let leaf = FreeListLeafNode {
+                keys: vec![Tsn(7)],
+                values: vec![FreeListLeafValue {
+                    page_ids: (root_id.0..root_id.0 + 1000).map(PageID).collect(),
+                    root_id: PageID(0),
+                }],
+            };
+            writer
+                .insert_dirty(Page::new(root_id, Node::FreeListLeaf(leaf)))
+                .unwrap();
+            writer.free_lists_tree_root_id = root_id;
+
+            match writer.find_reusable_page_ids(&db) {
+                Err(DCBError::DatabaseCorrupted(msg)) => assert!(msg.contains("beyond"), "{msg}"),
+                other => panic!("Expected DatabaseCorrupted, got {other:?}"),
+            }
         }
 
         #[test]
@@ -2086,11 +5595,11 @@ mod tests {
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
             // Expect entries from leaf1 then leaf2
-            assert_eq!(4, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn1), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn1), writer.reusable_page_ids[1]);
-            assert_eq!((pid3, tsn2), writer.reusable_page_ids[2]);
-            assert_eq!((pid4, tsn2), writer.reusable_page_ids[3]);
+            assert_eq!(4, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn1), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn1), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid3, tsn2), writer.reusable_pages().nth(2).unwrap());
+            assert_eq!((pid4, tsn2), writer.reusable_pages().nth(3).unwrap());
         }
 
         #[test]
@@ -2105,15 +5614,15 @@ mod tests {
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
             // Expect entries from leaf1 then leaf2
-            assert_eq!(8, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn1), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn1), writer.reusable_page_ids[1]);
-            assert_eq!((pid3, tsn2), writer.reusable_page_ids[2]);
-            assert_eq!((pid4, tsn2), writer.reusable_page_ids[3]);
-            assert_eq!((pid5, tsn3), writer.reusable_page_ids[4]);
-            assert_eq!((pid6, tsn3), writer.reusable_page_ids[5]);
-            assert_eq!((pid7, tsn4), writer.reusable_page_ids[6]);
-            assert_eq!((pid8, tsn4), writer.reusable_page_ids[7]);
+            assert_eq!(8, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn1), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn1), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid3, tsn2), writer.reusable_pages().nth(2).unwrap());
+            assert_eq!((pid4, tsn2), writer.reusable_pages().nth(3).unwrap());
+            assert_eq!((pid5, tsn3), writer.reusable_pages().nth(4).unwrap());
+            assert_eq!((pid6, tsn3), writer.reusable_pages().nth(5).unwrap());
+            assert_eq!((pid7, tsn4), writer.reusable_pages().nth(6).unwrap());
+            assert_eq!((pid8, tsn4), writer.reusable_pages().nth(7).unwrap());
         }
 
         #[test]
@@ -2127,9 +5636,9 @@ mod tests {
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
             // Expect entries from leaf1 then leaf2
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn), writer.reusable_page_ids[1]);
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn), writer.reusable_pages().nth(1).unwrap());
         }
 
         #[test]
@@ -2144,11 +5653,11 @@ mod tests {
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
             // Expect entries from leaf1 then leaf2
-            assert_eq!(4, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn), writer.reusable_page_ids[1]);
-            assert_eq!((pid3, tsn), writer.reusable_page_ids[2]);
-            assert_eq!((pid4, tsn), writer.reusable_page_ids[3]);
+            assert_eq!(4, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid3, tsn), writer.reusable_pages().nth(2).unwrap());
+            assert_eq!((pid4, tsn), writer.reusable_pages().nth(3).unwrap());
         }
 
         #[test]
@@ -2163,15 +5672,15 @@ mod tests {
             // Recompute
             writer.find_reusable_page_ids(&db).unwrap();
             // Expect entries from leaf1 then leaf2
-            assert_eq!(8, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn), writer.reusable_page_ids[1]);
-            assert_eq!((pid3, tsn), writer.reusable_page_ids[2]);
-            assert_eq!((pid4, tsn), writer.reusable_page_ids[3]);
-            assert_eq!((pid5, tsn), writer.reusable_page_ids[4]);
-            assert_eq!((pid6, tsn), writer.reusable_page_ids[5]);
-            assert_eq!((pid7, tsn), writer.reusable_page_ids[6]);
-            assert_eq!((pid8, tsn), writer.reusable_page_ids[7]);
+            assert_eq!(8, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid3, tsn), writer.reusable_pages().nth(2).unwrap());
+            assert_eq!((pid4, tsn), writer.reusable_pages().nth(3).unwrap());
+            assert_eq!((pid5, tsn), writer.reusable_pages().nth(4).unwrap());
+            assert_eq!((pid6, tsn), writer.reusable_pages().nth(5).unwrap());
+            assert_eq!((pid7, tsn), writer.reusable_pages().nth(6).unwrap());
+            assert_eq!((pid8, tsn), writer.reusable_pages().nth(7).unwrap());
         }
 
         fn build_free_list_tree_leaf(writer: &mut Writer) -> (Tsn, PageID, PageID) {
@@ -2182,7 +5691,7 @@ mod tests {
             let leaf = FreeListLeafNode {
                 keys: vec![tsn],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![free_pid1, free_pid2],
+                    page_ids: vec![free_pid1, free_pid2].into(),
                     root_id: PageID(0),
                 }],
             };
@@ -2210,14 +5719,14 @@ mod tests {
             let leaf1 = FreeListLeafNode {
                 keys: vec![tsn1],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid1, pid2],
+                    page_ids: vec![pid1, pid2].into(),
                     root_id: PageID(0),
                 }],
             };
             let leaf2 = FreeListLeafNode {
                 keys: vec![tsn2],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid3, pid4],
+                    page_ids: vec![pid3, pid4].into(),
                     root_id: PageID(0),
                 }],
             };
@@ -2279,28 +5788,28 @@ mod tests {
             let leaf1 = FreeListLeafNode {
                 keys: vec![tsn1],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid1, pid2],
+                    page_ids: vec![pid1, pid2].into(),
                     root_id: PageID(0),
                 }],
             };
             let leaf2 = FreeListLeafNode {
                 keys: vec![tsn2],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid3, pid4],
+                    page_ids: vec![pid3, pid4].into(),
                     root_id: PageID(0),
                 }],
             };
             let leaf3 = FreeListLeafNode {
                 keys: vec![tsn3],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid5, pid6],
+                    page_ids: vec![pid5, pid6].into(),
                     root_id: PageID(0),
                 }],
             };
             let leaf4 = FreeListLeafNode {
                 keys: vec![tsn4],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![pid7, pid8],
+                    page_ids: vec![pid7, pid8].into(),
                     root_id: PageID(0),
                 }],
             };
@@ -2357,7 +5866,7 @@ mod tests {
             let pid1 = writer.alloc_page_id();
             let pid2 = writer.alloc_page_id();
             let tsn_sub_leaf = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid1, pid2],
+                page_ids: vec![pid1, pid2].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2370,7 +5879,7 @@ mod tests {
             let leaf = FreeListLeafNode {
                 keys: vec![tsn],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![],
+                    page_ids: vec![].into(),
                     root_id: tsn_sub_leaf_id,
                 }],
             };
@@ -2391,7 +5900,7 @@ mod tests {
             let pid1 = writer.alloc_page_id();
             let pid2 = writer.alloc_page_id();
             let tsn_sub_leaf1 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid1, pid2],
+                page_ids: vec![pid1, pid2].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2405,7 +5914,7 @@ mod tests {
             let pid3 = writer.alloc_page_id();
             let pid4 = writer.alloc_page_id();
             let tsn_sub_leaf2 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid3, pid4],
+                page_ids: vec![pid3, pid4].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2432,7 +5941,7 @@ mod tests {
             let leaf = FreeListLeafNode {
                 keys: vec![tsn],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![],
+                    page_ids: vec![].into(),
                     root_id: tsn_sub_internal_id,
                 }],
             };
@@ -2463,7 +5972,7 @@ mod tests {
             let pid1 = writer.alloc_page_id();
             let pid2 = writer.alloc_page_id();
             let tsn_sub_leaf1 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid1, pid2],
+                page_ids: vec![pid1, pid2].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2477,7 +5986,7 @@ mod tests {
             let pid3 = writer.alloc_page_id();
             let pid4 = writer.alloc_page_id();
             let tsn_sub_leaf2 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid3, pid4],
+                page_ids: vec![pid3, pid4].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2504,7 +6013,7 @@ mod tests {
             let pid5 = writer.alloc_page_id();
             let pid6 = writer.alloc_page_id();
             let tsn_sub_leaf3 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid5, pid6],
+                page_ids: vec![pid5, pid6].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2518,7 +6027,7 @@ mod tests {
             let pid7 = writer.alloc_page_id();
             let pid8 = writer.alloc_page_id();
             let tsn_sub_leaf4 = crate::free_lists_tree_nodes::FreeListTsnLeafNode {
-                page_ids: vec![pid7, pid8],
+                page_ids: vec![pid7, pid8].into(),
             };
             writer
                 .insert_dirty(Page::new(
@@ -2558,7 +6067,7 @@ mod tests {
             let leaf = FreeListLeafNode {
                 keys: vec![tsn],
                 values: vec![FreeListLeafValue {
-                    page_ids: vec![],
+                    page_ids: vec![].into(),
                     root_id: tsn_sub_internal_id3,
                 }],
             };
@@ -2574,7 +6083,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_insert_freed_page_id_to_empty_leaf_root() {
-            let (_temp_dir, mut db) = construct_mvcc(64);
+            let (_temp_dir, db) = construct_mvcc(64);
 
             // Get latest header
             let (header_page_id, header_node) = db.get_latest_header().unwrap();
@@ -2610,7 +6119,7 @@ mod tests {
             // Insert the allocated page ID in the free list tree
             let current_tsn = writer.tsn;
             writer
-                .insert_freed_page_id(&mut db, current_tsn, page_id)
+                .insert_freed_page_id(&db, current_tsn, page_id)
                 .unwrap();
 
             // Check the root page has been CoW-ed
@@ -2632,7 +6141,7 @@ mod tests {
                     assert_eq!(expected_keys, node.keys);
 
                     let expected_values = vec![FreeListLeafValue {
-                        page_ids: vec![header_node.next_page_id],
+                        page_ids: vec![header_node.next_page_id].into(),
                         root_id: PageID(0),
                     }];
                     assert_eq!(expected_values, node.values);
@@ -2644,7 +6153,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_remove_freed_page_id_from_root_leaf_root() {
-            let (_temp_dir, mut db) = construct_mvcc(64);
+            let (_temp_dir, db) = construct_mvcc(64);
 
             // First, insert a page ID
             let mut writer;
@@ -2665,7 +6174,7 @@ mod tests {
                 // Insert the page ID
                 inserted_tsn = writer.tsn;
                 writer
-                    .insert_freed_page_id(&mut db, inserted_tsn, inserted_page_id)
+                    .insert_freed_page_id(&db, inserted_tsn, inserted_page_id)
                     .unwrap();
 
                 // Commit the transaction
@@ -2682,7 +6191,7 @@ mod tests {
                 writer = db.writer().unwrap();
 
                 // Check there are no free pages (because we blocked them with a reader)
-                assert_eq!(0, writer.reusable_page_ids.len());
+                assert_eq!(0, writer.reusable_pages().count());
 
                 // Remember the initial root ID and next page ID
                 let initial_root_id = writer.free_lists_tree_root_id;
@@ -2712,7 +6221,7 @@ mod tests {
                         assert_eq!(expected_keys, node.keys);
 
                         let expected_values = vec![FreeListLeafValue {
-                            page_ids: vec![previous_root_id],
+                            page_ids: vec![previous_root_id].into(),
                             root_id: PageID(0),
                         }];
                         assert_eq!(expected_values, node.values);
@@ -2725,7 +6234,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_insert_freed_page_ids_until_split_leaf() {
-            let (_temp_dir, mut db) = construct_mvcc(64);
+            let (_temp_dir, db) = construct_mvcc(64);
 
             // Get latest header
             let (header_page_id, header_node) = db.get_latest_header().unwrap();
@@ -2750,12 +6259,12 @@ mod tests {
             while !has_split_leaf {
                 // Allocate and insert the first page ID
                 let page_id1 = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut db, tsn, page_id1).unwrap();
+                writer.insert_freed_page_id(&db, tsn, page_id1).unwrap();
                 inserted.push((tsn, page_id1));
 
                 // Allocate and insert second page ID
                 let page_id2 = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut db, tsn, page_id2).unwrap();
+                writer.insert_freed_page_id(&db, tsn, page_id2).unwrap();
                 inserted.push((tsn, page_id2));
 
                 // Increment TSN for next iteration
@@ -2763,11 +6272,8 @@ mod tests {
 
                 // Check if we've split the leaf
                 let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
-                match &root_page.node {
-                    Node::FreeListInternal(_) => {
-                        has_split_leaf = true;
-                    }
-                    _ => {}
+                if let Node::FreeListInternal(_) = &root_page.node {
+                    has_split_leaf = true;
                 }
             }
 
@@ -2812,7 +6318,7 @@ mod tests {
 
                 // Check each key and value in the child
                 for (k, &key) in child_node.keys.iter().enumerate() {
-                    for &value in &child_node.values[k].page_ids {
+                    for value in &child_node.values[k].page_ids {
                         let (inserted_tsn, inserted_page_id) = copy_inserted.remove(0);
                         assert_eq!(inserted_tsn, key);
                         assert_eq!(inserted_page_id, value);
@@ -2870,11 +6376,8 @@ mod tests {
 
                 // Check if we've split the leaf
                 let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
-                match &root_page.node {
-                    Node::FreeListInternal(_) => {
-                        has_split_leaf = true;
-                    }
-                    _ => {}
+                if let Node::FreeListInternal(_) = &root_page.node {
+                    has_split_leaf = true;
                 }
 
                 if !has_split_leaf {
@@ -2887,11 +6390,8 @@ mod tests {
 
                     // Check if we've split the leaf
                     let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
-                    match &root_page.node {
-                        Node::FreeListInternal(_) => {
-                            has_split_leaf = true;
-                        }
-                        _ => {}
+                    if let Node::FreeListInternal(_) = &root_page.node {
+                        has_split_leaf = true;
                     }
                 }
 
@@ -2960,8 +6460,9 @@ mod tests {
                 }
             }
 
-            // We have split a leaf node, so now we have 8 active pages
-            assert_eq!(8, active_page_ids.len());
+            // We have split a leaf node once, so now we have 7 active pages
+            assert_eq!(2, root_node.child_ids.len());
+            assert_eq!(7, active_page_ids.len());
 
             // Audit page IDs
             let mut all_page_ids = active_page_ids.clone();
@@ -2986,7 +6487,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_remove_freed_page_ids_from_split_leaf() {
-            let (_temp_dir, mut db) = construct_mvcc(128);
+            let (_temp_dir, db) = construct_mvcc(128);
 
             // First, insert page IDs until we split a leaf
             if VERBOSE {
@@ -3013,21 +6514,18 @@ mod tests {
 
                     // Allocate and insert the first page ID
                     let page_id1 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id1).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id1).unwrap();
                     inserted.push((tsn, page_id1));
 
                     // Allocate and insert second page ID
                     let page_id2 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id2).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id2).unwrap();
                     inserted.push((tsn, page_id2));
 
                     // Check if we've split the leaf
                     let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
-                    match &root_page.node {
-                        Node::FreeListInternal(_) => {
-                            has_split_leaf = true;
-                        }
-                        _ => {}
+                    if let Node::FreeListInternal(_) = &root_page.node {
+                        has_split_leaf = true;
                     }
                 }
 
@@ -3096,7 +6594,7 @@ mod tests {
                         assert_eq!(expected_keys, node.keys);
 
                         let expected_values = vec![FreeListLeafValue {
-                            page_ids: vec![previous_root_id],
+                            page_ids: vec![previous_root_id].into(),
                             root_id: PageID(0),
                         }];
                         assert_eq!(expected_values, node.values);
@@ -3146,7 +6644,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_insert_freed_page_ids_until_split_internal() {
-            let (_temp_dir, mut db) = construct_mvcc(64);
+            let (_temp_dir, db) = construct_mvcc(64);
 
             // Get latest header
             let (header_page_id, header_node) = db.get_latest_header().unwrap();
@@ -3172,12 +6670,12 @@ mod tests {
             while !has_split_internal {
                 // Allocate and insert the first page ID
                 let page_id1 = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut db, tsn, page_id1).unwrap();
+                writer.insert_freed_page_id(&db, tsn, page_id1).unwrap();
                 inserted.push((tsn, page_id1));
 
                 // Allocate and insert second page ID
                 let page_id2 = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut db, tsn, page_id2).unwrap();
+                writer.insert_freed_page_id(&db, tsn, page_id2).unwrap();
                 inserted.push((tsn, page_id2));
 
                 // Increment TSN for next iteration
@@ -3186,20 +6684,15 @@ mod tests {
                 // Check if we've split an internal node
                 let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
                 match &root_page.node {
-                    Node::FreeListInternal(root_node) => {
+                    Node::FreeListInternal(root_node)
                         // Check if the first child is an internal node
-                        if !root_node.child_ids.is_empty() {
+                        if !root_node.child_ids.is_empty() => {
                             let child_id = root_node.child_ids[0];
-                            if let Some(child_page) = writer.dirty.get(&child_id) {
-                                match &child_page.node {
-                                    Node::FreeListInternal(_) => {
-                                        has_split_internal = true;
-                                    }
-                                    _ => {}
+                            if let Some(child_page) = writer.dirty.get(&child_id)
+                                && let Node::FreeListInternal(_) = &child_page.node {
+                                    has_split_internal = true;
                                 }
-                            }
                         }
-                    }
                     _ => {}
                 }
                 if inserted.len() > 100 {
@@ -3272,7 +6765,7 @@ mod tests {
 
                     // Check each key and value in the grandchild
                     for (k, &key) in grand_child_node.keys.iter().enumerate() {
-                        for &value in &grand_child_node.values[k].page_ids {
+                        for value in &grand_child_node.values[k].page_ids {
                             // Find the matching inserted item
                             let pos = inserted.iter().position(|&(t, p)| t == key && p == value);
                             if let Some(idx) = pos {
@@ -3304,7 +6797,7 @@ mod tests {
         #[test]
         #[serial]
         fn test_remove_freed_page_ids_from_split_internal() {
-            let (_temp_dir, mut db) = construct_mvcc(64);
+            let (_temp_dir, db) = construct_mvcc(64);
 
             // First, insert page IDs until we split an internal node
             let mut inserted: Vec<(Tsn, PageID)> = Vec::new();
@@ -3329,31 +6822,26 @@ mod tests {
 
                     // Allocate and insert the first page ID
                     let page_id1 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id1).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id1).unwrap();
                     inserted.push((tsn, page_id1));
 
                     // Allocate and insert second page ID
                     let page_id2 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id2).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id2).unwrap();
                     inserted.push((tsn, page_id2));
 
                     // Check if we've split an internal node
                     let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
                     match &root_page.node {
-                        Node::FreeListInternal(root_node) => {
+                        Node::FreeListInternal(root_node)
                             // Check if the first child is an internal node
-                            if !root_node.child_ids.is_empty() {
+                            if !root_node.child_ids.is_empty() => {
                                 let child_id = root_node.child_ids[0];
-                                if let Some(child_page) = writer.dirty.get(&child_id) {
-                                    match &child_page.node {
-                                        Node::FreeListInternal(_) => {
-                                            has_split_internal = true;
-                                        }
-                                        _ => {}
+                                if let Some(child_page) = writer.dirty.get(&child_id)
+                                    && let Node::FreeListInternal(_) = &child_page.node {
+                                        has_split_internal = true;
                                     }
-                                }
                             }
-                        }
                         _ => {}
                     }
                 }
@@ -3413,7 +6901,7 @@ mod tests {
                         assert_eq!(expected_keys, node.keys);
 
                         let expected_values = vec![FreeListLeafValue {
-                            page_ids: vec![previous_root_id],
+                            page_ids: vec![previous_root_id].into(),
                             root_id: PageID(0),
                         }];
                         assert_eq!(expected_values, node.values);
@@ -3473,7 +6961,7 @@ mod tests {
             // so that its parent internal node (which is the root internal node here)
             // will not already have the old child page ID replaced with a dirty
             // child page ID.
-            let (_temp_dir, mut db) = construct_mvcc(128);
+            let (_temp_dir, db) = construct_mvcc(128);
 
             // First, insert page IDs until we split an internal node
             let mut inserted: Vec<(Tsn, PageID)> = Vec::new();
@@ -3493,31 +6981,26 @@ mod tests {
 
                     // Allocate and insert the first page ID
                     let page_id1 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id1).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id1).unwrap();
                     inserted.push((tsn, page_id1));
 
                     // Allocate and insert second page ID
                     let page_id2 = writer.alloc_page_id();
-                    writer.insert_freed_page_id(&mut db, tsn, page_id2).unwrap();
+                    writer.insert_freed_page_id(&db, tsn, page_id2).unwrap();
                     inserted.push((tsn, page_id2));
 
                     // Check if we've split an internal node
                     let root_page = writer.dirty.get(&writer.free_lists_tree_root_id).unwrap();
                     match &root_page.node {
-                        Node::FreeListInternal(root_node) => {
+                        Node::FreeListInternal(root_node)
                             // Check if the first child is an internal node
-                            if !root_node.child_ids.is_empty() {
+                            if !root_node.child_ids.is_empty() => {
                                 let child_id = root_node.child_ids[0];
-                                if let Some(child_page) = writer.dirty.get(&child_id) {
-                                    match &child_page.node {
-                                        Node::FreeListInternal(_) => {
-                                            has_split_internal = true;
-                                        }
-                                        _ => {}
+                                if let Some(child_page) = writer.dirty.get(&child_id)
+                                    && let Node::FreeListInternal(_) = &child_page.node {
+                                        has_split_internal = true;
                                     }
-                                }
                             }
-                        }
                         _ => {}
                     }
                 }
@@ -3529,7 +7012,7 @@ mod tests {
             // Get all the free page IDs.
             db.reader_tsns.remove(&0);
             let writer = db.writer().unwrap();
-            let reusable_page_ids = writer.reusable_page_ids.clone();
+            let reusable_page_ids: Vec<_> = writer.reusable_pages().collect();
 
             // Block inserted page IDs from being reused
             db.reader_tsns.insert(0, Tsn(0));
@@ -3547,7 +7030,7 @@ mod tests {
         fn test_insert_freed_page_ids_overflow_single_key_moves_to_tsn_subtree() {
             // Use tiny pages to force the inline list to overflow quickly
             let page_size = 64;
-            let (_temp_dir, mut mvcc) = construct_mvcc(page_size);
+            let (_temp_dir, mvcc) = construct_mvcc(page_size);
 
             // Start a writer
             let mut writer = mvcc.writer().unwrap();
@@ -3557,8 +7040,8 @@ mod tests {
             // takes 52 bytes, and adding the 5th (+8) would exceed capacity.
             let mut inserted_count: usize = 0;
             loop {
-                let pid = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut mvcc, tsn, pid).unwrap();
+                let pid = alloc_spaced_page_id(&mut writer);
+                writer.insert_freed_page_id(&mvcc, tsn, pid).unwrap();
                 inserted_count += 1;
                 let dirty_page_id = {
                     let mut keys = writer.dirty.keys();
@@ -3576,17 +7059,17 @@ mod tests {
             }
 
             // The next insert for the same TSN should succeed by creating a TSN-subtree
-            let pid5 = writer.alloc_page_id();
-            writer.insert_freed_page_id(&mut mvcc, tsn, pid5).unwrap();
+            let pid5 = alloc_spaced_page_id(&mut writer);
+            writer.insert_freed_page_id(&mvcc, tsn, pid5).unwrap();
             inserted_count += 1;
 
             // Insert two more page IDs for the same TSN into the TSN-subtree
-            let pid6 = writer.alloc_page_id();
-            writer.insert_freed_page_id(&mut mvcc, tsn, pid6).unwrap();
+            let pid6 = alloc_spaced_page_id(&mut writer);
+            writer.insert_freed_page_id(&mvcc, tsn, pid6).unwrap();
             inserted_count += 1;
 
-            let pid7 = writer.alloc_page_id();
-            writer.insert_freed_page_id(&mut mvcc, tsn, pid7).unwrap();
+            let pid7 = alloc_spaced_page_id(&mut writer);
+            writer.insert_freed_page_id(&mvcc, tsn, pid7).unwrap();
             inserted_count += 1;
 
             // Verify that the leaf now points to a TSN-subtree for this TSN, and that the TSN-subtree root is an internal node (leaf split)
@@ -3628,8 +7111,8 @@ mod tests {
                     guard < 200,
                     "guard hit while waiting for TSN-subtree internal split"
                 );
-                let pid = writer.alloc_page_id();
-                writer.insert_freed_page_id(&mut mvcc, tsn, pid).unwrap();
+                let pid = alloc_spaced_page_id(&mut writer);
+                writer.insert_freed_page_id(&mvcc, tsn, pid).unwrap();
                 extra_inserts += 1;
 
                 // Refresh tsn_root_id from the FreeList leaf since the root may change when it splits
@@ -3683,15 +7166,15 @@ mod tests {
             }
 
             // Now add another PageID to the internal->internal->leaf.
-            let pid = writer.alloc_page_id();
-            writer.insert_freed_page_id(&mut mvcc, tsn, pid).unwrap();
+            let pid = alloc_spaced_page_id(&mut writer);
+            writer.insert_freed_page_id(&mvcc, tsn, pid).unwrap();
             extra_inserts += 1;
 
             // Verify all page IDs are discoverable via find_reusable_page_ids
             writer.find_reusable_page_ids(&mvcc).unwrap();
             assert_eq!(
                 inserted_count + extra_inserts,
-                writer.reusable_page_ids.len()
+                writer.reusable_pages().count()
             );
             for &(_pid, _tsn) in writer.reusable_page_ids.iter() {
                 assert_eq!(tsn, _tsn);
@@ -3707,14 +7190,14 @@ mod tests {
             let (pid1, pid2, tsn1) = build_free_list_tree_leaf_tsn_subtree_leaf(&mut writer);
 
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn1), writer.reusable_page_ids[0]);
-            assert_eq!((pid2, tsn1), writer.reusable_page_ids[1]);
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn1), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid2, tsn1), writer.reusable_pages().nth(1).unwrap());
 
             writer.remove_free_page_id(&db, tsn1, pid1).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(1, writer.reusable_page_ids.len());
-            assert_eq!((pid2, tsn1), writer.reusable_page_ids[0]);
+            assert_eq!(1, writer.reusable_pages().count());
+            assert_eq!((pid2, tsn1), writer.reusable_pages().next().unwrap());
         }
 
         #[test]
@@ -3727,8 +7210,8 @@ mod tests {
 
             writer.remove_free_page_id(&db, tsn1, pid2).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(1, writer.reusable_page_ids.len());
-            assert_eq!((pid1, tsn1), writer.reusable_page_ids[0]);
+            assert_eq!(1, writer.reusable_pages().count());
+            assert_eq!((pid1, tsn1), writer.reusable_pages().next().unwrap());
         }
 
         #[test]
@@ -3736,7 +7219,7 @@ mod tests {
         fn test_tsn_subtree_random_order_insert_and_ordering() {
             // Moderate page size to force multiple leaves and internals
             let page_size = 256;
-            let (_temp_dir, mut db) = construct_mvcc(page_size);
+            let (_temp_dir, db) = construct_mvcc(page_size);
             let mut writer = db.writer().unwrap();
             let tsn = writer.tsn;
 
@@ -3753,15 +7236,15 @@ mod tests {
             }
             // Insert them all for the same TSN in random order
             for pid in &pids {
-                writer.insert_freed_page_id(&mut db, tsn, *pid).unwrap();
+                writer.insert_freed_page_id(&db, tsn, *pid).unwrap();
             }
             // Insert a duplicate of the first ID and ensure it is ignored
             let dup = pids[0];
-            writer.insert_freed_page_id(&mut db, tsn, dup).unwrap();
+            writer.insert_freed_page_id(&db, tsn, dup).unwrap();
 
             // Verify via reusable_page_ids
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(n, writer.reusable_page_ids.len());
+            assert_eq!(n, writer.reusable_pages().count());
             for &(_pid, _tsn) in writer.reusable_page_ids.iter() {
                 assert_eq!(tsn, _tsn);
             }
@@ -3771,11 +7254,12 @@ mod tests {
             let mut tsn_root_id = PageID(0);
             for page_id in writer.dirty.keys().cloned().collect::<Vec<_>>() {
                 let page = writer.get_mut_dirty(page_id).unwrap();
-                if let Node::FreeListLeaf(leaf_node) = &page.node {
-                    if !leaf_node.keys.is_empty() && leaf_node.keys[0] == tsn {
-                        tsn_root_id = leaf_node.values[0].root_id;
-                        break;
-                    }
+                if let Node::FreeListLeaf(leaf_node) = &page.node
+                    && !leaf_node.keys.is_empty()
+                    && leaf_node.keys[0] == tsn
+                {
+                    tsn_root_id = leaf_node.values[0].root_id;
+                    break;
                 }
             }
             assert_ne!(PageID(0), tsn_root_id);
@@ -3807,15 +7291,15 @@ mod tests {
         fn test_upgrade_inline_to_tsn_subtree_with_random_inserts_and_duplicates() {
             // Small page size to quickly overflow inline and create a TSN-subtree
             let page_size = 96;
-            let (_temp_dir, mut mvcc) = construct_mvcc(page_size);
+            let (_temp_dir, mvcc) = construct_mvcc(page_size);
             let mut writer = mvcc.writer().unwrap();
             let tsn = writer.tsn;
 
             // Insert a few to fill inline list close to capacity
             let mut inline_ids = Vec::new();
             loop {
-                let pid = writer.alloc_page_id();
-                let res = writer.insert_freed_page_id(&mut mvcc, tsn, pid);
+                let pid = alloc_spaced_page_id(&mut writer);
+                let res = writer.insert_freed_page_id(&mvcc, tsn, pid);
                 if res.is_err() {
                     panic!("unexpected error inserting into inline");
                 }
@@ -3823,17 +7307,16 @@ mod tests {
                 // Heuristic to break when the next inline would likely overflow and cause move-to-subtree
                 let dirty_id = writer.dirty.keys().cloned().next().unwrap();
                 if let Node::FreeListLeaf(leaf_node) = &writer.get_mut_dirty(dirty_id).unwrap().node
+                    && !leaf_node.would_fit_new_page_id(mvcc.max_node_size)
                 {
-                    if !leaf_node.would_fit_new_page_id(mvcc.max_node_size) {
-                        break;
-                    }
+                    break;
                 }
             }
 
             // Now insert a batch of random ids including some duplicates
             let mut extra_ids = Vec::new();
             for _ in 0..30 {
-                extra_ids.push(writer.alloc_page_id());
+                extra_ids.push(alloc_spaced_page_id(&mut writer));
             }
             // Shuffle simple
             for i in 0..extra_ids.len() {
@@ -3849,7 +7332,7 @@ mod tests {
             }
 
             for pid in &extra_ids {
-                writer.insert_freed_page_id(&mut mvcc, tsn, *pid).unwrap();
+                writer.insert_freed_page_id(&mvcc, tsn, *pid).unwrap();
             }
 
             // Verify no duplicates and all ids present
@@ -3862,8 +7345,7 @@ mod tests {
             }
             expected.sort_by_key(|p| p.0);
             expected.dedup();
-            let mut actual: Vec<PageID> =
-                writer.reusable_page_ids.iter().map(|(p, _)| *p).collect();
+            let mut actual: Vec<PageID> = writer.reusable_pages().map(|(p, _)| p).collect();
             actual.sort_by_key(|p| p.0);
             assert_eq!(expected, actual);
         }
@@ -3879,7 +7361,7 @@ mod tests {
             writer.remove_free_page_id(&db, tsn1, pid1).unwrap();
             writer.remove_free_page_id(&db, tsn1, pid2).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(0, writer.reusable_page_ids.len());
+            assert_eq!(0, writer.reusable_pages().count());
 
             assert_eq!(2, writer.freed_page_ids.len());
         }
@@ -3895,31 +7377,31 @@ mod tests {
 
             writer.remove_free_page_id(&db, tsn1, pid1).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(3, writer.reusable_page_ids.len());
-            assert_eq!((pid2, tsn1), writer.reusable_page_ids[0]);
-            assert_eq!((pid3, tsn1), writer.reusable_page_ids[1]);
-            assert_eq!((pid4, tsn1), writer.reusable_page_ids[2]);
+            assert_eq!(3, writer.reusable_pages().count());
+            assert_eq!((pid2, tsn1), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid3, tsn1), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid4, tsn1), writer.reusable_pages().nth(2).unwrap());
 
             assert_eq!(1, writer.freed_page_ids.len());
 
             writer.remove_free_page_id(&db, tsn1, pid2).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(2, writer.reusable_page_ids.len());
-            assert_eq!((pid3, tsn1), writer.reusable_page_ids[0]);
-            assert_eq!((pid4, tsn1), writer.reusable_page_ids[1]);
+            assert_eq!(2, writer.reusable_pages().count());
+            assert_eq!((pid3, tsn1), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid4, tsn1), writer.reusable_pages().nth(1).unwrap());
 
             assert_eq!(3, writer.freed_page_ids.len());
 
             writer.remove_free_page_id(&db, tsn1, pid3).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(1, writer.reusable_page_ids.len());
-            assert_eq!((pid4, tsn1), writer.reusable_page_ids[0]);
+            assert_eq!(1, writer.reusable_pages().count());
+            assert_eq!((pid4, tsn1), writer.reusable_pages().next().unwrap());
 
             assert_eq!(3, writer.freed_page_ids.len());
 
             writer.remove_free_page_id(&db, tsn1, pid4).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(0, writer.reusable_page_ids.len());
+            assert_eq!(0, writer.reusable_pages().count());
 
             assert_eq!(4, writer.freed_page_ids.len());
         }
@@ -3935,18 +7417,87 @@ mod tests {
 
             writer.remove_free_page_id(&db, tsn, pid1).unwrap();
             writer.find_reusable_page_ids(&db).unwrap();
-            assert_eq!(7, writer.reusable_page_ids.len());
-            assert_eq!((pid2, tsn), writer.reusable_page_ids[0]);
-            assert_eq!((pid3, tsn), writer.reusable_page_ids[1]);
-            assert_eq!((pid4, tsn), writer.reusable_page_ids[2]);
-            assert_eq!((pid5, tsn), writer.reusable_page_ids[3]);
-            assert_eq!((pid6, tsn), writer.reusable_page_ids[4]);
-            assert_eq!((pid7, tsn), writer.reusable_page_ids[5]);
-            assert_eq!((pid8, tsn), writer.reusable_page_ids[6]);
+            assert_eq!(7, writer.reusable_pages().count());
+            assert_eq!((pid2, tsn), writer.reusable_pages().next().unwrap());
+            assert_eq!((pid3, tsn), writer.reusable_pages().nth(1).unwrap());
+            assert_eq!((pid4, tsn), writer.reusable_pages().nth(2).unwrap());
+            assert_eq!((pid5, tsn), writer.reusable_pages().nth(3).unwrap());
+            assert_eq!((pid6, tsn), writer.reusable_pages().nth(4).unwrap());
+            assert_eq!((pid7, tsn), writer.reusable_pages().nth(5).unwrap());
+            assert_eq!((pid8, tsn), writer.reusable_pages().nth(6).unwrap());
 
             assert_eq!(1, writer.freed_page_ids.len());
         }
 
+        #[test]
+        #[serial]
+        fn test_draining_a_tsn_subtree_frees_its_pages() {
+            let (_temp_dir, db) = construct_mvcc(128);
+            let mut writer = db.writer().unwrap();
+
+            let (pid1, pid2, pid3, pid4, pid5, pid6, pid7, pid8, tsn) =
+                build_free_list_tree_leaf_tsn_subtree_internal_internal_leaf(&mut writer);
+            let Node::FreeListLeaf(leaf) = &writer
+                .get_page_ref(&db, writer.free_lists_tree_root_id)
+                .unwrap()
+                .node
+            else {
+                panic!("Expected FreeListLeaf node");
+            };
+            let mut subtree_page_ids = Vec::new();
+            let mut stack = vec![leaf.values[0].root_id];
+            while let Some(page_id) = stack.pop() {
+                subtree_page_ids.push(page_id);
+                if let Node::FreeListTsnInternal(internal) =
+                    &writer.get_page_ref(&db, page_id).unwrap().node
+                {
+                    stack.extend(internal.child_ids.iter().copied());
+                }
+            }
+            assert_eq!(7, subtree_page_ids.len());
+
+            writer.freed_page_ids.clear();
+            for pid in [pid1, pid2, pid3, pid4, pid5, pid6, pid7, pid8] {
+                writer.remove_free_page_id(&db, tsn, pid).unwrap();
+            }
+            writer.find_reusable_page_ids(&db).unwrap();
+            assert!(writer.reusable_page_ids.is_empty());
+            for page_id in subtree_page_ids {
+                assert!(writer.freed_page_ids.contains(&page_id), "{page_id:?}");
+            }
+
+            // An entry that also lists page IDs inline keeps them, without the subtree
+            let mut writer = db.writer().unwrap();
+            let (pid1, pid2, tsn) = build_free_list_tree_leaf_tsn_subtree_leaf(&mut writer);
+            let inline_pid = writer.alloc_page_id();
+            let root_id = writer.free_lists_tree_root_id;
+            let Node::FreeListLeaf(leaf) = &mut writer.get_mut_dirty(root_id).unwrap().node else {
+                panic!("Expected FreeListLeaf node");
+            };
+            let tsn_sub_leaf_id = leaf.values[0].root_id;
+            leaf.values[0].page_ids.insert(inline_pid);
+
+            writer.freed_page_ids.clear();
+            writer.remove_free_page_id(&db, tsn, pid1).unwrap();
+            writer.remove_free_page_id(&db, tsn, pid2).unwrap();
+            assert!(writer.freed_page_ids.contains(&tsn_sub_leaf_id));
+            let Node::FreeListLeaf(leaf) = &writer
+                .get_page_ref(&db, writer.free_lists_tree_root_id)
+                .unwrap()
+                .node
+            else {
+                panic!("Expected FreeListLeaf node");
+            };
+            assert_eq!(vec![tsn], leaf.keys);
+            assert_eq!(
+                FreeListLeafValue {
+                    page_ids: vec![inline_pid].into(),
+                    root_id: PageID(0),
+                },
+                leaf.values[0]
+            );
+        }
+
         #[test]
         #[serial]
         fn test_remove_freed_page_id_cow_does_not_leak_ids_in_tsn_subtree() {
@@ -3969,8 +7520,8 @@ mod tests {
             let mut w2 = db.writer().unwrap();
             // Find one reusable pid to remove via API, ensure tree populated
             w2.find_reusable_page_ids(&db).unwrap();
-            assert!(w2.reusable_page_ids.len() >= 1);
-            let (remove_pid, remove_tsn) = w2.reusable_page_ids[0];
+            assert!(!w2.reusable_page_ids.is_empty());
+            let (remove_pid, remove_tsn) = w2.reusable_pages().next().unwrap();
             assert_eq!(remove_tsn, w1_tsn); // All built under this tsn in the helper
             w2.remove_free_page_id(&db, remove_tsn, remove_pid).unwrap();
 
@@ -3988,7 +7539,7 @@ mod tests {
             // Traverse free list tree from current root, reading nodes from disk or cache
             let mut stack: Vec<PageID> = vec![w2.free_lists_tree_root_id];
             while let Some(pid) = stack.pop() {
-                if active.contains(&pid) == false {
+                if !active.contains(&pid) {
                     active.push(pid);
                 }
                 let node_owned = { w2.get_page_ref(&db, pid).unwrap().node.clone() };
@@ -4001,7 +7552,7 @@ mod tests {
                     Node::FreeListLeaf(leaf) => {
                         // Any inline free page IDs are considered freed
                         for val in &leaf.values {
-                            for &p in &val.page_ids {
+                            for p in &val.page_ids {
                                 freed_tree.push(p);
                             }
                         }
@@ -4010,7 +7561,7 @@ mod tests {
                             if val.root_id != PageID(0) {
                                 let mut tsn_stack: Vec<PageID> = vec![val.root_id];
                                 while let Some(tid) = tsn_stack.pop() {
-                                    if active.contains(&tid) == false {
+                                    if !active.contains(&tid) {
                                         active.push(tid);
                                     }
                                     let tnode = { w2.get_page_ref(&db, tid).unwrap().node.clone() };
@@ -4021,7 +7572,7 @@ mod tests {
                                             }
                                         }
                                         Node::FreeListTsnLeaf(tleaf) => {
-                                            for &p in &tleaf.page_ids {
+                                            for p in &tleaf.page_ids {
                                                 freed_tree.push(p);
                                             }
                                         }
@@ -4053,8 +7604,8 @@ mod tests {
             // Include the reusable ids present in the tree itself
             freed.extend(freed_tree);
             // Include the reusable ids tracked by the writer snapshot
-            for (pid, _tsn) in w2.reusable_page_ids.iter() {
-                freed.push(*pid);
+            for (pid, _tsn) in w2.reusable_pages() {
+                freed.push(pid);
             }
 
             // Validate coverage
@@ -3,6 +3,7 @@ use crate::free_lists_tree_nodes::{
     FreeListInternalNode, FreeListLeafNode, FreeListTsnInternalNode, FreeListTsnLeafNode,
 };
 use crate::header_node::HeaderNode;
+use crate::page::max_node_size;
 use crate::tags_tree_nodes::{TagInternalNode, TagLeafNode, TagsInternalNode, TagsLeafNode};
 use umadb_dcb::{DCBError, DCBResult};
 
@@ -10,8 +11,8 @@ use umadb_dcb::{DCBError, DCBResult};
 const PAGE_TYPE_HEADER: u8 = b'1';
 const PAGE_TYPE_FREELIST_LEAF: u8 = b'2';
 const PAGE_TYPE_FREELIST_INTERNAL: u8 = b'3';
-const PAGE_TYPE_EVENT_LEAF: u8 = b'4';
-const PAGE_TYPE_EVENT_INTERNAL: u8 = b'5';
+pub(crate) const PAGE_TYPE_EVENT_LEAF: u8 = b'4';
+pub(crate) const PAGE_TYPE_EVENT_INTERNAL: u8 = b'5';
 const PAGE_TYPE_TAGS_LEAF: u8 = b'6';
 const PAGE_TYPE_TAGS_INTERNAL: u8 = b'7';
 const PAGE_TYPE_TAG_LEAF: u8 = b'8';
@@ -38,6 +39,12 @@ pub enum Node {
 }
 
 impl Node {
+    /// Returns the largest serialized node that fits in a page of the given size,
+    /// after the page header.
+    pub fn max_payload_for(page_size: usize) -> usize {
+        max_node_size(page_size)
+    }
+
     pub fn get_type_byte(&self) -> u8 {
         match self {
             Node::Header(_) => PAGE_TYPE_HEADER,
@@ -74,7 +81,7 @@ impl Node {
 
     pub fn calc_serialized_size(&self) -> usize {
         match self {
-            Node::Header(_) => 48, // HeaderNode has a fixed size of 48 bytes (includes root_tags_tree_id and next_position)
+            Node::Header(node) => node.serialized_size(),
             Node::FreeListLeaf(node) => node.calc_serialized_size(),
             Node::FreeListInternal(node) => node.calc_serialized_size(),
             Node::EventLeaf(node) => node.calc_serialized_size(),
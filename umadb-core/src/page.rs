@@ -16,6 +16,12 @@ const HEADER_LAYOUT_NODE_TYPE_BYTE: usize = 0;
 const HEADER_LAYOUT_CRC_BYTES: Range<usize> = 1..5;
 const HEADER_LAYOUT_BODY_LEN_BYTES: Range<usize> = 5..9;
 
+/// Returns the number of bytes available for a serialized node in a page of
+/// `page_size` bytes. Every check of whether a node fits in a page uses this.
+pub fn max_node_size(page_size: usize) -> usize {
+    page_size.saturating_sub(PAGE_HEADER_SIZE)
+}
+
 // Implementation for Page
 impl Page {
     pub fn new(page_id: PageID, node: Node) -> Self {
@@ -28,6 +34,12 @@ impl Page {
         PAGE_HEADER_SIZE + self.node.calc_serialized_size()
     }
 
+    /// Returns true if the page's node fits in a page of `page_size` bytes.
+    #[inline]
+    pub fn fits(&self, page_size: usize) -> bool {
+        self.node.calc_serialized_size() <= max_node_size(page_size)
+    }
+
     /// Serialized page (header + body + zero padding) into `buf`.
     pub fn serialize_into(&self, buf: &mut [u8]) -> DCBResult<()> {
         serialize_page_into(buf, &self.node)?;
@@ -36,42 +48,46 @@ impl Page {
 
     #[inline]
     pub fn deserialize(page_id: PageID, page_data: &[u8]) -> DCBResult<Self> {
-        if page_data.len() < PAGE_HEADER_SIZE {
-            return Err(DCBError::DatabaseCorrupted(
-                "Page data too short".to_string(),
-            ));
-        }
-
-        // Extract header information with minimal bounds checks
-        let header = &page_data[..PAGE_HEADER_SIZE];
-        let node_type = header[HEADER_LAYOUT_NODE_TYPE_BYTE];
-        let crc = u32::from_le_bytes(header[HEADER_LAYOUT_CRC_BYTES].try_into().unwrap());
-        let data_len =
-            u32::from_le_bytes(header[HEADER_LAYOUT_BODY_LEN_BYTES].try_into().unwrap()) as usize;
-
-        if PAGE_HEADER_SIZE + data_len > page_data.len() {
-            return Err(DCBError::DatabaseCorrupted(
-                "Page data length mismatch".to_string(),
-            ));
-        }
+        let (node_type, data) = node_bytes(page_id, page_data)?;
+        let node = Node::deserialize(node_type, data)?;
+        Ok(Self { page_id, node })
+    }
+}
 
-        // Extract the data
-        let data = &page_data[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + data_len];
+/// Checks the header and checksum of a serialized page, and returns its node type
+/// and the serialized node, without deserializing the node.
+#[inline]
+pub fn node_bytes(page_id: PageID, page_data: &[u8]) -> DCBResult<(u8, &[u8])> {
+    if page_data.len() < PAGE_HEADER_SIZE {
+        return Err(DCBError::UnexpectedEndOfData(format!(
+            "page header (page ID: {page_id:?})"
+        )));
+    }
 
-        // Verify CRC
-        let calculated_crc = calc_crc(data);
+    // Extract header information with minimal bounds checks
+    let header = &page_data[..PAGE_HEADER_SIZE];
+    let node_type = header[HEADER_LAYOUT_NODE_TYPE_BYTE];
+    let crc = u32::from_le_bytes(header[HEADER_LAYOUT_CRC_BYTES].try_into().unwrap());
+    let data_len =
+        u32::from_le_bytes(header[HEADER_LAYOUT_BODY_LEN_BYTES].try_into().unwrap()) as usize;
+
+    if PAGE_HEADER_SIZE + data_len > page_data.len() {
+        return Err(DCBError::UnexpectedEndOfData(format!(
+            "page body (page ID: {page_id:?})"
+        )));
+    }
 
-        if calculated_crc != crc {
-            return Err(DCBError::DatabaseCorrupted(format!(
-                "CRC mismatch (page ID: {page_id:?})"
-            )));
-        }
+    // Extract the data
+    let data = &page_data[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + data_len];
 
-        // Deserialize the node
-        let node = Node::deserialize(node_type, data)?;
+    // Verify CRC
+    let calculated_crc = calc_crc(data);
 
-        Ok(Self { page_id, node })
+    if calculated_crc != crc {
+        return Err(DCBError::ChecksumMismatch { page_id: page_id.0 });
     }
+
+    Ok((node_type, data))
 }
 
 pub fn serialize_page_into(buf: &mut [u8], node_ref: &Node) -> Result<(), DCBError> {
@@ -120,6 +136,24 @@ mod tests {
     use crate::common::{PageID, Tsn};
     use crate::header_node::HeaderNode;
 
+    #[test]
+    fn test_max_node_size_is_page_size_minus_header() {
+        for page_size in [64, 512, 4096, 16384] {
+            assert_eq!(page_size - PAGE_HEADER_SIZE, max_node_size(page_size));
+            assert_eq!(max_node_size(page_size), Node::max_payload_for(page_size));
+        }
+        // Pages smaller than the header have no room for a node
+        assert_eq!(0, max_node_size(PAGE_HEADER_SIZE - 1));
+
+        let page = Page::new(
+            PageID(1),
+            Node::TagLeaf(crate::tags_tree_nodes::TagLeafNode { positions: vec![] }),
+        );
+        let page_size = page.calc_serialized_size();
+        assert!(page.fits(page_size));
+        assert!(!page.fits(page_size - 1));
+    }
+
     #[test]
     fn test_page_serialization_and_size() {
         // Create a HeaderNode (simplest node type)
@@ -130,6 +164,9 @@ mod tests {
             events_tree_root_id: PageID(789),
             tags_tree_root_id: PageID(1011),
             next_position: Position(1234),
+            page_size: 4096,
+            overflow_chunk_size: 0,
+            encryption: None,
         });
 
         // Create a Page with the node
@@ -169,4 +206,40 @@ mod tests {
             page.node, deserialized.node
         );
     }
+
+    #[test]
+    fn test_deserialize_errors_are_structured() {
+        let page = Page::new(
+            PageID(7),
+            Node::Header(HeaderNode {
+                tsn: Tsn(1),
+                next_page_id: PageID(5),
+                free_lists_tree_root_id: PageID(2),
+                events_tree_root_id: PageID(3),
+                tags_tree_root_id: PageID(4),
+                next_position: Position(1),
+                page_size: 4096,
+                overflow_chunk_size: 0,
+                encryption: None,
+            }),
+        );
+        let mut page_buf = vec![0u8; 4096];
+        page.serialize_into(&mut page_buf).unwrap();
+
+        let mut corrupted = page_buf.clone();
+        corrupted[PAGE_HEADER_SIZE] ^= 0xff;
+        assert!(matches!(
+            Page::deserialize(PageID(7), &corrupted),
+            Err(DCBError::ChecksumMismatch { page_id: 7 })
+        ));
+
+        assert!(matches!(
+            Page::deserialize(PageID(7), &page_buf[..PAGE_HEADER_SIZE - 1]),
+            Err(DCBError::UnexpectedEndOfData(_))
+        ));
+        assert!(matches!(
+            Page::deserialize(PageID(7), &page_buf[..PAGE_HEADER_SIZE + 10]),
+            Err(DCBError::UnexpectedEndOfData(_))
+        ));
+    }
 }
@@ -11,6 +11,18 @@ use std::path::Path;
 use std::sync::{Arc, RwLock};
 use umadb_dcb::{DCBError, DCBResult};
 
+/// How much the database file is extended by when a page is written past its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrowthPolicy {
+    /// Extends the file by one memory map window, which is at least 256 MiB.
+    #[default]
+    MmapWindow,
+    /// Extends the file by a fixed number of pages.
+    Fixed { pages: u64 },
+    /// Doubles the length of the file, but by at most `max_pages` pages.
+    Double { max_pages: u64 },
+}
+
 // Pager for file I/O
 pub struct Pager {
     pub reader: Arc<File>,
@@ -22,6 +34,7 @@ pub struct Pager {
     mmap_pages_per_map: usize,
     // Cache of memory maps, keyed by map identifier (floor(page_id / mmap_pages_per_map)).
     mmaps: RwLock<HashMap<u64, Arc<Mmap>>>,
+    growth_policy: GrowthPolicy,
 }
 
 // Implementation for Pager
@@ -80,9 +93,45 @@ impl Pager {
             is_file_new,
             mmap_pages_per_map,
             mmaps: RwLock::new(HashMap::new()),
+            growth_policy: GrowthPolicy::default(),
         })
     }
 
+    /// Sets how much the file is extended by when it is too short for a page.
+    pub fn with_growth_policy(mut self, policy: GrowthPolicy) -> DCBResult<Self> {
+        if matches!(
+            policy,
+            GrowthPolicy::Fixed { pages: 0 } | GrowthPolicy::Double { max_pages: 0 }
+        ) {
+            return Err(DCBError::InternalError(format!(
+                "Growth policy must extend the file by at least one page, got {policy:?}"
+            )));
+        }
+        self.growth_policy = policy;
+        Ok(self)
+    }
+
+    /// Preallocates the file to at least `len` bytes, rounded up to whole pages, so
+    /// that writing pages within it never waits for the file to be extended.
+    pub fn reserve(&self, len: u64) -> io::Result<()> {
+        let page_size = self.page_size as u64;
+        let reserved_len = len.div_ceil(page_size) * page_size;
+        let file_len = self.writer.metadata()?.len();
+        if reserved_len > file_len {
+            preallocate(&self.writer, reserved_len - file_len)?;
+        }
+        Ok(())
+    }
+
+    // Number of pages to extend a file of `file_pages` pages by
+    fn growth_pages(&self, file_pages: u64) -> u64 {
+        match self.growth_policy {
+            GrowthPolicy::MmapWindow => self.mmap_pages_per_map as u64,
+            GrowthPolicy::Fixed { pages } => pages,
+            GrowthPolicy::Double { max_pages } => file_pages.clamp(1, max_pages),
+        }
+    }
+
     fn gcd(mut a: usize, mut b: usize) -> usize {
         while b != 0 {
             let t = b;
@@ -224,109 +273,61 @@ impl Pager {
         let pages_per_map = self.mmap_pages_per_map as u64;
         let map_id = page_id.0 / pages_per_map;
         let map_offset = map_id * pages_per_map * page_size_u64;
-        let within = (offset - map_offset) as usize;
+        let start = (offset - map_offset) as usize;
+        let stop = start + self.page_size;
+        let mapped_page = |mmap: Arc<Mmap>| MappedPage {
+            mmap,
+            start,
+            len: self.page_size,
+        };
 
-        // Fast path: if mapping exists, do not obtain file lock; take Arc clone and release map lock
+        // Fast path: if a mapping covers the page, take an Arc clone and release the map lock
         if let Some(mmap_arc) = {
             let maps = self.mmaps.read().unwrap();
             maps.get(&map_id).cloned()
-        } {
-            let start = within;
-            let stop = start + self.page_size;
-            if stop > mmap_arc.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    format!("Page {page_id:?} not found"),
-                ));
-            }
-            return Ok(MappedPage {
-                mmap: mmap_arc,
-                start,
-                len: self.page_size,
-            });
+        } && stop <= mmap_arc.len()
+        {
+            return Ok(mapped_page(mmap_arc));
         }
 
-        // Slow path: need to create the mapping with double-checked locking
+        // Slow path: the page must be within the file, which the growth policy
+        // extends, and the file isn't extended here
         let file = self.reader.clone();
         let file_len = file.metadata()?.len();
-
-        // Re-check if mapping appeared while we acquired the file lock
-        if let Some(mmap_arc) = {
-            let maps = self.mmaps.read().unwrap();
-            maps.get(&map_id).cloned()
-        } {
-            let start = within;
-            let stop = start + self.page_size;
-            if stop > mmap_arc.len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    format!("Page {page_id:?} not found"),
-                ));
-            }
-            return Ok(MappedPage {
-                mmap: mmap_arc,
-                start,
-                len: self.page_size,
-            });
-        }
-
-        // Calculate standard mapping window length (does not depend on current file length)
-        let max_len = pages_per_map * page_size_u64;
-
-        // Before mapping, ensure the requested page is within the current file length.
-        let page_end = offset + page_size_u64;
-        if page_end > file_len {
+        if offset + page_size_u64 > file_len {
             return Err(io::Error::new(
                 io::ErrorKind::UnexpectedEof,
                 format!("Page {page_id:?} not found"),
             ));
         }
 
-        // Ensure the underlying file is large enough to permit a full standard-length mapping.
-        let required_len = map_offset + max_len;
-        if file_len < required_len {
-            file.set_len(required_len)?;
+        // Windows are mapped up to the end of the file, and are mapped again, longer,
+        // once the file has grown past them. Pages already handed out keep the
+        // shorter mapping alive until they are dropped.
+        let map_len = (file_len - map_offset).min(pages_per_map * page_size_u64);
+        let mut maps = self.mmaps.write().unwrap();
+        // Another thread could have mapped the page meanwhile
+        if let Some(existing) = maps.get(&map_id)
+            && stop <= existing.len()
+        {
+            return Ok(mapped_page(existing.clone()));
         }
 
-        // Create the mmap and insert it, but guard with a double-check
-        let mmap_new = unsafe {
+        // Safety: the map is read-only and nothing is ever written through it. Pages are
+        // written with pwrite on the writer's descriptor, which the shared mapping sees,
+        // and a page is only rewritten once no reader can reach it, so a slice handed out
+        // by this map doesn't change while it is being decoded. The map ends within the
+        // file, which is never truncated while mapped.
+        let mmap_arc = Arc::new(unsafe {
             MmapOptions::new()
                 .offset(map_offset)
-                .len(max_len as usize)
+                .len(map_len as usize)
                 .map(&*file)?
-        };
-        // mmap_new.advise(Advice::Random)?;
-        // mmap_new.advise(Advice::WillNeed)?;
-        let mmap_arc = {
-            let mut maps = self.mmaps.write().unwrap();
-            // Another thread could have inserted meanwhile
-            if let Some(existing) = maps.get(&map_id) {
-                existing.clone()
-            } else {
-                let arc = Arc::new(mmap_new);
-                maps.insert(map_id, arc.clone());
-                // println!(
-                //     "Created new mmap: map_id={} offset={} len={}",
-                //     map_id, map_offset, max_len
-                // );
-                arc
-            }
-        };
-
-        // Now form the mapped page view
-        let start = within;
-        let stop = start + self.page_size;
-        if stop > mmap_arc.len() {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                format!("Page {page_id:?} not found"),
-            ));
-        }
-        Ok(MappedPage {
-            mmap: mmap_arc,
-            start,
-            len: self.page_size,
-        })
+        });
+        // mmap_arc.advise(Advice::Random)?;
+        // mmap_arc.advise(Advice::WillNeed)?;
+        maps.insert(map_id, mmap_arc.clone());
+        Ok(mapped_page(mmap_arc))
     }
 
     pub fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()> {
@@ -341,14 +342,14 @@ impl Pager {
         }
 
         // Check the page doesn't overflow the file size.
+        let page_size = self.page_size as u64;
         let file_len = self.writer.metadata()?.len();
-        if self.page_size as u64 * (page_id.0 + 1) > file_len
-            && let Err(err) = preallocate(
-                &self.writer,
-                (self.mmap_pages_per_map * self.page_size) as u64,
-            )
-        {
-            return Err(DCBError::Io(err));
+        let required_len = page_size * (page_id.0 + 1);
+        if required_len > file_len {
+            let grown_len = file_len + page_size * self.growth_pages(file_len / page_size);
+            if let Err(err) = preallocate(&self.writer, grown_len.max(required_len) - file_len) {
+                return Err(DCBError::Io(err));
+            }
         }
 
         // Write the page data
@@ -380,6 +381,22 @@ impl Pager {
         Ok(())
     }
 
+    pub fn fdatasync(&self) -> io::Result<()> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        unsafe {
+            let result = libc::fdatasync(self.writer_raw_fd);
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            self.fsync()
+        }
+    }
+
     #[cfg(test)]
     pub fn debug_mmap_count(&self) -> usize {
         self.mmaps.read().unwrap().len()
@@ -426,10 +443,8 @@ pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
         let length = len as i64;
 
         match fcntl::posix_fallocate(file, offset, length) {
-            Ok(()) => return Ok(()),
-            Err(e) => {
-                return Err(io::Error::from_raw_os_error(e as i32));
-            }
+            Ok(()) => Ok(()),
+            Err(e) => Err(io::Error::from_raw_os_error(e as i32)),
         }
     }
 
@@ -496,9 +511,145 @@ pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
     }
 }
 
+/// Pages held in a Vec rather than in a file, so that tests of the layers above
+/// don't touch the disk
+pub struct MemoryPager {
+    pub page_size: usize,
+    pages: RwLock<Vec<Vec<u8>>>,
+}
+
+impl MemoryPager {
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size,
+            pages: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn read_page(&self, page_id: PageID) -> io::Result<Vec<u8>> {
+        self.pages
+            .read()
+            .unwrap()
+            .get(page_id.0 as usize)
+            .cloned()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Page {page_id:?} not found"),
+                )
+            })
+    }
+
+    pub fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()> {
+        if page_data.len() != self.page_size {
+            return Err(DCBError::InternalError(format!(
+                "Page size mismatch: page_id={:?} size={} > PAGE_SIZE={}",
+                page_id,
+                page_data.len(),
+                self.page_size
+            )));
+        }
+        let mut pages = self.pages.write().unwrap();
+        let index = page_id.0 as usize;
+        // Pages skipped over read as zeros, like the unwritten pages of a file
+        if index >= pages.len() {
+            pages.resize(index + 1, vec![0u8; self.page_size]);
+        }
+        pages[index].copy_from_slice(page_data);
+        Ok(())
+    }
+}
+
+/// Where the pages of a database are kept. Pages are read and written as the bytes
+/// they are stored as, after serialization and any encryption.
+pub trait PageStore: Send + Sync {
+    fn read_page(&self, page_id: PageID) -> DCBResult<Vec<u8>>;
+
+    fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()>;
+
+    /// Writes the pages in order, stopping at the first that fails
+    fn write_pages(&self, pages: &[(PageID, &[u8])]) -> DCBResult<()> {
+        for (page_id, page_data) in pages {
+            self.write_page(*page_id, page_data)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the pages written so far durable
+    fn sync(&self) -> DCBResult<()>;
+
+    /// Makes the pages written so far durable, but not necessarily metadata such
+    /// as the modification time
+    fn sync_data(&self) -> DCBResult<()> {
+        self.sync()
+    }
+
+    /// Returns the number of pages the store has room for, written or not
+    fn len_pages(&self) -> DCBResult<u64>;
+
+    /// Returns true if the store held no database when it was opened
+    fn is_new(&self) -> DCBResult<bool> {
+        Ok(self.len_pages()? == 0)
+    }
+
+    /// Returns the pager if the pages are kept in a file, which can also be mapped,
+    /// logged to a write-ahead log and compacted
+    fn file_pager(&self) -> Option<&Pager> {
+        None
+    }
+}
+
+impl PageStore for Pager {
+    fn read_page(&self, page_id: PageID) -> DCBResult<Vec<u8>> {
+        Ok(Pager::read_page(self, page_id)?)
+    }
+
+    fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()> {
+        Pager::write_page(self, page_id, page_data)
+    }
+
+    fn sync(&self) -> DCBResult<()> {
+        Ok(self.fsync()?)
+    }
+
+    fn sync_data(&self) -> DCBResult<()> {
+        Ok(self.fdatasync()?)
+    }
+
+    fn len_pages(&self) -> DCBResult<u64> {
+        Ok(self.writer.metadata()?.len() / self.page_size as u64)
+    }
+
+    fn is_new(&self) -> DCBResult<bool> {
+        Ok(self.is_file_new)
+    }
+
+    fn file_pager(&self) -> Option<&Pager> {
+        Some(self)
+    }
+}
+
+impl PageStore for MemoryPager {
+    fn read_page(&self, page_id: PageID) -> DCBResult<Vec<u8>> {
+        Ok(MemoryPager::read_page(self, page_id)?)
+    }
+
+    fn write_page(&self, page_id: PageID, page_data: &[u8]) -> DCBResult<()> {
+        MemoryPager::write_page(self, page_id, page_data)
+    }
+
+    fn sync(&self) -> DCBResult<()> {
+        Ok(())
+    }
+
+    fn len_pages(&self) -> DCBResult<u64> {
+        Ok(self.pages.read().unwrap().len() as u64)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Pager;
+    use super::{GrowthPolicy, MemoryPager, PageStore, Pager};
     use crate::common::PageID;
     use std::path::PathBuf;
     use tempfile::tempdir;
@@ -521,6 +672,74 @@ mod tests {
         assert!(matches!(err, Err(DCBError::InternalError(_))));
     }
 
+    #[test]
+    fn test_file_grows_by_the_growth_policy() {
+        let page_size = 512usize;
+        let dir = tempdir().expect("tempdir");
+        let page = vec![7u8; page_size];
+        let file_pages = |pager: &Pager| pager.writer.metadata().unwrap().len() / page_size as u64;
+
+        let pager = Pager::new(&dir.path().join("fixed.db"), page_size)
+            .unwrap()
+            .with_growth_policy(GrowthPolicy::Fixed { pages: 16 })
+            .unwrap();
+        let mut lengths = Vec::new();
+        for page_id in 0..40 {
+            pager.write_page(PageID(page_id), &page).unwrap();
+            lengths.push(file_pages(&pager));
+        }
+        lengths.dedup();
+        assert_eq!(vec![16, 32, 48], lengths);
+        // A page beyond the next increment still fits
+        pager.write_page(PageID(100), &page).unwrap();
+        assert_eq!(101, file_pages(&pager));
+        assert_eq!(page, pager.read_page(PageID(100)).unwrap());
+
+        let pager = Pager::new(&dir.path().join("double.db"), page_size)
+            .unwrap()
+            .with_growth_policy(GrowthPolicy::Double { max_pages: 8 })
+            .unwrap();
+        let mut lengths = Vec::new();
+        for page_id in 0..30 {
+            pager.write_page(PageID(page_id), &page).unwrap();
+            lengths.push(file_pages(&pager));
+        }
+        lengths.dedup();
+        assert_eq!(vec![1, 2, 4, 8, 16, 24, 32], lengths);
+
+        assert!(
+            Pager::new(&dir.path().join("zero.db"), page_size)
+                .unwrap()
+                .with_growth_policy(GrowthPolicy::Fixed { pages: 0 })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_memory_pager_reads_written_pages() {
+        let page_size = 1024usize;
+        let store: Box<dyn PageStore> = Box::new(MemoryPager::new(page_size));
+        assert!(store.is_new().unwrap());
+        assert!(store.file_pager().is_none());
+
+        let data = (0..page_size).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+        store.write_page(PageID(2), &data).expect("write page 2");
+        assert_eq!(data, store.read_page(PageID(2)).expect("read 2"));
+        // Pages skipped over are zeros, and pages beyond the last aren't there
+        assert_eq!(
+            vec![0u8; page_size],
+            store.read_page(PageID(0)).expect("read 0")
+        );
+        assert!(store.read_page(PageID(3)).is_err());
+        assert_eq!(3, store.len_pages().unwrap());
+        assert!(!store.is_new().unwrap());
+
+        assert!(matches!(
+            store.write_page(PageID(0), &data[..100]),
+            Err(DCBError::InternalError(_))
+        ));
+    }
+
     #[test]
     fn mmap_read_matches_normal_read() {
         let page_size = 1024usize;
@@ -568,6 +787,36 @@ mod tests {
         assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
     }
 
+    #[test]
+    fn mmap_maps_up_to_the_file_length_and_remaps_as_it_grows() {
+        let page_size = 1024usize;
+        let path = temp_file_path("pager_mmap_grow.db");
+        let pager = Pager::new(&path, page_size)
+            .expect("pager new")
+            .with_growth_policy(GrowthPolicy::Fixed { pages: 4 })
+            .unwrap();
+        let file_len = || std::fs::metadata(&path).unwrap().len();
+
+        pager.write_page(PageID(0), &vec![1u8; page_size]).unwrap();
+        assert_eq!(4 * page_size as u64, file_len());
+        let first = pager.read_page_mmap_slice(PageID(0)).expect("read0m");
+        // Reading doesn't extend the file to the length of a window
+        assert_eq!(4 * page_size as u64, file_len());
+
+        // A page written after the file has grown past the mapping is mapped again
+        pager.write_page(PageID(5), &vec![5u8; page_size]).unwrap();
+        assert_eq!(8 * page_size as u64, file_len());
+        let fifth = pager.read_page_mmap_slice(PageID(5)).expect("read5m");
+        assert_eq!(&vec![5u8; page_size][..], fifth.as_slice());
+        assert_eq!(&vec![1u8; page_size][..], first.as_slice());
+        assert_eq!(1, pager.debug_mmap_count());
+        assert_eq!(8 * page_size as u64, file_len());
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            pager.read_page_mmap_slice(PageID(8)).unwrap_err().kind()
+        );
+    }
+
     #[test]
     fn mmap_reuse_within_same_map() {
         // Try a few page sizes to ensure we get at least 2 pages per mmap window
@@ -184,9 +184,7 @@ pub fn tags_tree_insert(
                 match &mut leaf_page.node {
                     Node::TagLeaf(tleaf) => {
                         tleaf.positions.push(pos);
-                        let page_bytes =
-                            crate::page::PAGE_HEADER_SIZE + tleaf.calc_serialized_size();
-                        if page_bytes > mvcc.page_size {
+                        if tleaf.calc_serialized_size() > mvcc.max_node_size {
                             // Move last pos to a new right leaf
                             let last_pos = tleaf
                                 .pop_last_position()
@@ -256,7 +254,7 @@ pub fn tags_tree_insert(
 
                 // Now check for internal overflow and split if needed
                 let parent_page = writer.get_mut_dirty(dirty_parent_id)?;
-                let needs_split = parent_page.calc_serialized_size() > mvcc.page_size;
+                let needs_split = !mvcc.fits(parent_page);
                 if needs_split {
                     if let Node::TagInternal(internal) = &mut parent_page.node {
                         if internal.keys.len() < 3 || internal.child_ids.len() < 4 {
@@ -326,10 +324,8 @@ pub fn tags_tree_insert(
 
     // If we appended inline, check if the page overflowed and migrate positions to a per-tag TagLeaf page
     if let Some(i) = inline_appended_index.take() {
-        let sz = writer
-            .get_page_ref(mvcc, dirty_leaf_page_id)?
-            .calc_serialized_size();
-        if sz > mvcc.page_size {
+        let fits = mvcc.fits(writer.get_page_ref(mvcc, dirty_leaf_page_id)?);
+        if !fits {
             if verbose {
                 println!("Migrating inline positions to per-tag TagLeafNode for index {i}",);
             }
@@ -347,12 +343,11 @@ pub fn tags_tree_insert(
             // Create per-tag page or split into an internal if needed
             let new_root_id = {
                 let mut pos_vec = positions;
-                let page_bytes = crate::page::PAGE_HEADER_SIZE
-                    + TagLeafNode {
-                        positions: pos_vec.clone(),
-                    }
-                    .calc_serialized_size();
-                if page_bytes <= mvcc.page_size {
+                let node_size = TagLeafNode {
+                    positions: pos_vec.clone(),
+                }
+                .calc_serialized_size();
+                if node_size <= mvcc.max_node_size {
                     let tag_leaf_id = writer.alloc_page_id();
                     let tag_leaf_page = Page::new(
                         tag_leaf_id,
@@ -365,12 +360,11 @@ pub fn tags_tree_insert(
                     let last_pos = pos_vec.pop().ok_or_else(|| {
                         DCBError::DatabaseCorrupted("No positions to split".to_string())
                     })?;
-                    let left_bytes = crate::page::PAGE_HEADER_SIZE
-                        + TagLeafNode {
-                            positions: pos_vec.clone(),
-                        }
-                        .calc_serialized_size();
-                    if left_bytes > mvcc.page_size {
+                    let left_size = TagLeafNode {
+                        positions: pos_vec.clone(),
+                    }
+                    .calc_serialized_size();
+                    if left_size > mvcc.max_node_size {
                         return Err(DCBError::DatabaseCorrupted(
                             "Recursive per-tag split not implemented".to_string(),
                         ));
@@ -422,7 +416,7 @@ pub fn tags_tree_insert(
     // Check if leaf overflows
     let needs_split = {
         let page = writer.get_mut_dirty(dirty_leaf_page_id)?;
-        page.calc_serialized_size() > mvcc.page_size
+        !mvcc.fits(page)
     };
     if needs_split {
         let leaf_page = writer.get_mut_dirty(dirty_leaf_page_id)?;
@@ -510,7 +504,7 @@ pub fn tags_tree_insert(
         }
 
         // Now check for internal overflow after any insertion
-        let needs_split = parent_page.calc_serialized_size() > mvcc.page_size;
+        let needs_split = !mvcc.fits(parent_page);
         if needs_split {
             if let Node::TagsInternal(internal) = &mut parent_page.node {
                 if verbose {
@@ -883,14 +877,14 @@ impl<'a> TagsTreeIterator<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mvcc::Mvcc;
+    use crate::mvcc::{DurabilityMode, Mvcc};
     use tempfile::{TempDir, tempdir};
 
     static VERBOSE: bool = false;
 
     // Test helper: iterate a range of `from` positions and assert lookup returns
     // the positions that were inserted for the given tag and are >= `from`.
-    fn verify_tag_lookup_range(db: &Mvcc, tag: TagHash, inserted: &Vec<Position>, backwards: bool) {
+    fn verify_tag_lookup_range(db: &Mvcc, tag: TagHash, inserted: &[Position], backwards: bool) {
         // Try a range that goes slightly below the first position and past the last.
         let len = inserted.len();
         for i in 0..(len + 3) {
@@ -920,7 +914,9 @@ mod tests {
     fn construct_db(page_size: usize) -> (TempDir, Mvcc) {
         let temp_dir = tempdir().unwrap();
         let db_path = temp_dir.path().join("mvcc-test.db");
-        let db = Mvcc::new(&db_path, page_size, VERBOSE).unwrap();
+        let db = Mvcc::new(&db_path, page_size, DurabilityMode::Sync)
+            .unwrap()
+            .with_verbose(VERBOSE);
         (temp_dir, db)
     }
 
@@ -1046,11 +1042,8 @@ mod tests {
 
             // Check if we've split the leaf
             let root_page = writer.dirty.get(&writer.tags_tree_root_id).unwrap();
-            match &root_page.node {
-                Node::TagsInternal(_) => {
-                    has_split_leaf = true;
-                }
-                _ => {}
+            if let Node::TagsInternal(_) = &root_page.node {
+                has_split_leaf = true;
             }
         }
 
@@ -1092,8 +1085,8 @@ mod tests {
         // Also verify lookup across a range of 'from' positions for a representative tag.
         db.commit(&mut writer).unwrap();
         let (tag_last, pos_last) = appended.last().cloned().unwrap();
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], false);
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], true);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], false);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], true);
     }
 
     #[test]
@@ -1122,17 +1115,12 @@ mod tests {
             // Check if we've split an internal node: root is internal and its first child is also internal
             let root_page = writer.dirty.get(&writer.tags_tree_root_id).unwrap();
             match &root_page.node {
-                Node::TagsInternal(root_node) => {
-                    if !root_node.child_ids.is_empty() {
-                        let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::TagsInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
-                            }
-                        }
+                Node::TagsInternal(root_node) if !root_node.child_ids.is_empty() => {
+                    let child_id = root_node.child_ids[0];
+                    if let Some(child_page) = writer.dirty.get(&child_id)
+                        && let Node::TagsInternal(_) = &child_page.node
+                    {
+                        has_split_internal = true;
                     }
                 }
                 _ => {}
@@ -1190,8 +1178,8 @@ mod tests {
         }
         // Also verify 'from' filtering using a representative tag
         let (tag_last, pos_last) = appended.last().cloned().unwrap();
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], false);
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], true);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], false);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], true);
     }
 
     #[test]
@@ -1220,17 +1208,12 @@ mod tests {
             // Check if the root is an internal node and its first child is also internal
             let root_page = writer.dirty.get(&writer.tags_tree_root_id).unwrap();
             match &root_page.node {
-                Node::TagsInternal(root_node) => {
-                    if !root_node.child_ids.is_empty() {
-                        let child_id = root_node.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&child_id) {
-                            match &child_page.node {
-                                Node::TagsInternal(_) => {
-                                    has_split_internal = true;
-                                }
-                                _ => {}
-                            }
-                        }
+                Node::TagsInternal(root_node) if !root_node.child_ids.is_empty() => {
+                    let child_id = root_node.child_ids[0];
+                    if let Some(child_page) = writer.dirty.get(&child_id)
+                        && let Node::TagsInternal(_) = &child_page.node
+                    {
+                        has_split_internal = true;
                     }
                 }
                 _ => {}
@@ -1241,7 +1224,7 @@ mod tests {
         // Check keys and values of all pages
         let mut copy_inserted = appended.clone();
         // Sort expected values by TagHash lexicographic order (array cmp)
-        copy_inserted.sort_by(|a, b| a.0.cmp(&b.0));
+        copy_inserted.sort_by_key(|a| a.0);
 
         // Start a writer to access the latest root id
         let writer = db.writer().unwrap();
@@ -1293,8 +1276,8 @@ mod tests {
         }
         // Also verify 'from' filtering using a representative tag
         let (tag_last, pos_last) = appended.last().cloned().unwrap();
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], false);
-        verify_tag_lookup_range(&db, tag_last, &vec![pos_last], true);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], false);
+        verify_tag_lookup_range(&db, tag_last, &[pos_last], true);
     }
 
     #[test]
@@ -1589,11 +1572,11 @@ mod tests {
                 Node::TagInternal(root_internal) => {
                     if !root_internal.child_ids.is_empty() {
                         let first_child_id = root_internal.child_ids[0];
-                        if let Some(child_page) = writer.dirty.get(&first_child_id) {
-                            if matches!(child_page.node, Node::TagInternal(_)) {
-                                // Success: per-tag internal has split creating a second level
-                                break;
-                            }
+                        if let Some(child_page) = writer.dirty.get(&first_child_id)
+                            && matches!(child_page.node, Node::TagInternal(_))
+                        {
+                            // Success: per-tag internal has split creating a second level
+                            break;
                         }
                     }
                 }
@@ -101,9 +101,7 @@ impl TagsLeafNode {
         let mut offset = keys_bytes;
         for _ in 0..keys_len {
             if offset + 10 > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading value header".to_string(),
-                ));
+                return Err(DCBError::UnexpectedEndOfData("value header".to_string()));
             }
             // root_id (8 bytes)
             let root_id_u64 = LittleEndian::read_u64(&slice[offset..offset + 8]);
@@ -116,9 +114,7 @@ impl TagsLeafNode {
             // positions
             let need = positions_len * 8;
             if offset + need > slice.len() {
-                return Err(DCBError::DeserializationError(
-                    "Unexpected end of data while reading positions".to_string(),
-                ));
+                return Err(DCBError::UnexpectedEndOfData("positions".to_string()));
             }
             let mut positions = Vec::with_capacity(positions_len);
             for i in 0..positions_len {
@@ -395,6 +391,29 @@ impl TagInternalNode {
     }
 }
 
+impl TagInternalNode {
+    pub(crate) fn split_off(&mut self) -> DCBResult<(Position, Vec<Position>, Vec<PageID>)> {
+        // Split by moving half of the child_ids to a new node.
+        // Promote the separator key which is the minimum key of the new right subtree.
+        let total_children = self.child_ids.len();
+        if total_children < 4 || self.keys.len() + 1 != total_children {
+            return Err(DCBError::DatabaseCorrupted(
+                "Cannot split internal node with insufficient arity".to_string(),
+            ));
+        }
+        let mid = total_children / 2; // number of children to keep on the left
+        // The promoted key is the minimum key in the new right subtree, which is keys[mid - 1]
+        let promoted_key = self.keys[mid - 1];
+        // Right side keys: those corresponding to the right children (excluding the promoted key)
+        let new_keys = self.keys.split_off(mid);
+        // Now truncate left keys to exclude the promoted key and any moved to the right
+        self.keys.truncate(mid - 1);
+        // Split child IDs: move right half to the new node
+        let new_child_ids = self.child_ids.split_off(mid);
+        Ok((promoted_key, new_keys, new_child_ids))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{
@@ -542,26 +561,3 @@ mod tests {
         assert_eq!(node, de);
     }
 }
-
-impl TagInternalNode {
-    pub(crate) fn split_off(&mut self) -> DCBResult<(Position, Vec<Position>, Vec<PageID>)> {
-        // Split by moving half of the child_ids to a new node.
-        // Promote the separator key which is the minimum key of the new right subtree.
-        let total_children = self.child_ids.len();
-        if total_children < 4 || self.keys.len() + 1 != total_children {
-            return Err(DCBError::DatabaseCorrupted(
-                "Cannot split internal node with insufficient arity".to_string(),
-            ));
-        }
-        let mid = total_children / 2; // number of children to keep on the left
-        // The promoted key is the minimum key in the new right subtree, which is keys[mid - 1]
-        let promoted_key = self.keys[mid - 1];
-        // Right side keys: those corresponding to the right children (excluding the promoted key)
-        let new_keys = self.keys.split_off(mid);
-        // Now truncate left keys to exclude the promoted key and any moved to the right
-        self.keys.truncate(mid - 1);
-        // Split child IDs: move right half to the new node
-        let new_child_ids = self.child_ids.split_off(mid);
-        Ok((promoted_key, new_keys, new_child_ids))
-    }
-}
@@ -0,0 +1,620 @@
+// Integrity check of a database file.
+//
+// Walks every tree reachable from the latest header, checking that keys are
+// sorted within and across nodes, that internal nodes have one more child than
+// keys, that overflow chains end and hold as much data as their events say, and
+// that no page is referenced twice or is both live and in the free list.
+use crate::common::{PageID, Position};
+use crate::events_tree_nodes::EventValue;
+use crate::free_lists_tree_nodes::PageIdRanges;
+use crate::mvcc::Mvcc;
+use crate::node::Node;
+use std::collections::HashMap;
+use std::fmt;
+use umadb_dcb::{DCBError, DCBResult};
+
+/// A problem found by Mvcc::verify(), on the given page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyProblem {
+    pub page_id: PageID,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page {:?}: {}", self.page_id, self.message)
+    }
+}
+
+/// The result of Mvcc::verify().
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Pages reachable from the header, including the free lists tree
+    pub live_pages: u64,
+    /// Pages in the free list
+    pub free_pages: u64,
+    /// Pages that are both live and in the free list, so would be overwritten
+    pub double_allocated: Vec<PageID>,
+    pub problems: Vec<VerifyProblem>,
+}
+
+impl VerifyReport {
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+// One bit for each page ID in the file
+struct PageBitset {
+    words: Vec<u64>,
+}
+
+impl PageBitset {
+    fn new(next_page_id: PageID) -> Self {
+        Self {
+            words: vec![0; next_page_id.0.div_ceil(64) as usize],
+        }
+    }
+
+    // Returns false if the page was already in the set
+    fn insert(&mut self, page_id: PageID) -> bool {
+        let (word, bit) = ((page_id.0 / 64) as usize, page_id.0 % 64);
+        let was_set = self.words[word] & (1 << bit) != 0;
+        self.words[word] |= 1 << bit;
+        !was_set
+    }
+
+    fn contains(&self, page_id: PageID) -> bool {
+        self.words[(page_id.0 / 64) as usize] & (1 << (page_id.0 % 64)) != 0
+    }
+
+    fn len(&self) -> u64 {
+        self.words.iter().map(|word| word.count_ones() as u64).sum()
+    }
+}
+
+struct Verifier<'a> {
+    mvcc: &'a Mvcc,
+    next_page_id: PageID,
+    // Pages referenced so far, which are all checked to be inside the file
+    live: PageBitset,
+    // Data lengths of the overflow chains walked so far, by root ID, since events
+    // with the same data may share a chain
+    overflow_roots: HashMap<PageID, u64>,
+    // Last key of the previous leaf, for checking keys across leaves
+    last_event_key: Option<Position>,
+    problems: Vec<VerifyProblem>,
+}
+
+impl Verifier<'_> {
+    fn problem(&mut self, page_id: PageID, message: String) {
+        self.problems.push(VerifyProblem { page_id, message });
+    }
+
+    // Returns the page's node if it can be visited, otherwise records why not
+    fn visit(&mut self, page_id: PageID, referrer: PageID) -> Option<Node> {
+        if page_id.0 < 2 || page_id >= self.next_page_id {
+            self.problem(
+                referrer,
+                format!(
+                    "references page {page_id:?} outside 2..{:?}",
+                    self.next_page_id.0
+                ),
+            );
+            return None;
+        }
+        if !self.live.insert(page_id) {
+            self.problem(page_id, format!("referenced again by page {referrer:?}"));
+            return None;
+        }
+        match self.mvcc.read_page(page_id) {
+            Ok(page) => Some(page.node),
+            Err(err) => {
+                self.problem(page_id, format!("can't be read: {err}"));
+                None
+            }
+        }
+    }
+
+    fn check_sorted<K: Ord + fmt::Debug>(&mut self, page_id: PageID, keys: &[K]) {
+        if let Some(i) = keys.windows(2).position(|w| w[0] >= w[1]) {
+            self.problem(
+                page_id,
+                format!("keys out of order: {:?} then {:?}", keys[i], keys[i + 1]),
+            );
+        }
+    }
+
+    fn check_children(&mut self, page_id: PageID, keys: usize, children: usize) {
+        if children != keys + 1 {
+            self.problem(page_id, format!("{keys} keys but {children} children"));
+        }
+    }
+
+    // Positions in the subtree must be within [lower, upper)
+    fn events_tree(
+        &mut self,
+        page_id: PageID,
+        referrer: PageID,
+        lower: Option<Position>,
+        upper: Option<Position>,
+    ) {
+        match self.visit(page_id, referrer) {
+            Some(Node::EventInternal(node)) => {
+                self.check_children(page_id, node.keys.len(), node.child_ids.len());
+                self.check_sorted(page_id, &node.keys);
+                for (i, child_id) in node.child_ids.iter().enumerate() {
+                    let child_lower = if i == 0 {
+                        lower
+                    } else {
+                        node.keys.get(i - 1).copied()
+                    };
+                    let child_upper = node.keys.get(i).copied().or(upper);
+                    self.events_tree(*child_id, page_id, child_lower, child_upper);
+                }
+            }
+            Some(Node::EventLeaf(node)) => {
+                if let Err(err) = node.check_invariants() {
+                    let message = match err {
+                        DCBError::DatabaseCorrupted(message) => message,
+                        other => other.to_string(),
+                    };
+                    self.problem(page_id, message);
+                }
+                if let (Some(last), Some(first)) = (self.last_event_key, node.keys().first())
+                    && *first <= last
+                {
+                    self.problem(
+                        page_id,
+                        format!("first key {first:?} not after previous leaf's {last:?}"),
+                    );
+                }
+                if let Some(key) = node.keys().iter().find(|key| {
+                    lower.is_some_and(|lower| **key < lower)
+                        || upper.is_some_and(|upper| **key >= upper)
+                }) {
+                    self.problem(
+                        page_id,
+                        format!("key {key:?} outside its parent's range {lower:?}..{upper:?}"),
+                    );
+                }
+                if let Some(last) = node.keys().last() {
+                    self.last_event_key = Some(*last);
+                }
+                for value in node.values() {
+                    if let EventValue::Overflow {
+                        root_id, data_len, ..
+                    } = value
+                    {
+                        self.overflow_chain(*root_id, page_id, *data_len);
+                    }
+                }
+            }
+            Some(other) => self.problem(
+                page_id,
+                format!("{} node in events tree", other.type_name()),
+            ),
+            None => {}
+        }
+    }
+
+    fn overflow_chain(&mut self, root_id: PageID, referrer: PageID, data_len: u64) {
+        match self.overflow_roots.insert(root_id, data_len) {
+            Some(shared_len) if shared_len == data_len => return,
+            Some(shared_len) => {
+                self.problem(
+                    root_id,
+                    format!(
+                        "shared overflow chain for events with {shared_len} and {data_len} bytes"
+                    ),
+                );
+                return;
+            }
+            None => {}
+        }
+        let mut total = 0u64;
+        let (mut page_id, mut prev) = (root_id, referrer);
+        while page_id != PageID(0) {
+            match self.visit(page_id, prev) {
+                Some(Node::EventOverflow(node)) => {
+                    total += node.data.len() as u64;
+                    prev = page_id;
+                    page_id = node.next;
+                }
+                Some(other) => {
+                    self.problem(
+                        page_id,
+                        format!("{} node in overflow chain", other.type_name()),
+                    );
+                    return;
+                }
+                // Also ends a chain that loops back on itself
+                None => return,
+            }
+        }
+        if total != data_len {
+            self.problem(
+                root_id,
+                format!("overflow chain holds {total} bytes, event has {data_len}"),
+            );
+        }
+    }
+
+    fn tags_tree(&mut self, page_id: PageID, referrer: PageID) {
+        match self.visit(page_id, referrer) {
+            Some(Node::TagsInternal(node)) => {
+                self.check_children(page_id, node.keys.len(), node.child_ids.len());
+                self.check_sorted(page_id, &node.keys);
+                for child_id in node.child_ids {
+                    self.tags_tree(child_id, page_id);
+                }
+            }
+            Some(Node::TagsLeaf(node)) => {
+                self.check_sorted(page_id, &node.keys);
+                for value in node.values {
+                    self.check_sorted(page_id, &value.positions);
+                    if value.root_id != PageID(0) {
+                        self.tag_tree(value.root_id, page_id);
+                    }
+                }
+            }
+            Some(other) => {
+                self.problem(page_id, format!("{} node in tags tree", other.type_name()))
+            }
+            None => {}
+        }
+    }
+
+    fn tag_tree(&mut self, page_id: PageID, referrer: PageID) {
+        match self.visit(page_id, referrer) {
+            Some(Node::TagInternal(node)) => {
+                self.check_children(page_id, node.keys.len(), node.child_ids.len());
+                self.check_sorted(page_id, &node.keys);
+                for child_id in node.child_ids {
+                    self.tag_tree(child_id, page_id);
+                }
+            }
+            Some(Node::TagLeaf(node)) => self.check_sorted(page_id, &node.positions),
+            Some(other) => self.problem(
+                page_id,
+                format!("{} node in a tag's positions tree", other.type_name()),
+            ),
+            None => {}
+        }
+    }
+
+    // Adds the listed page IDs to free, but only up to the next page ID, as a
+    // corrupted range could otherwise list billions of them
+    fn free_page_ids(
+        &mut self,
+        page_id: PageID,
+        page_ids: &PageIdRanges,
+        free: &mut Vec<(PageID, PageID)>,
+    ) {
+        for range in page_ids.ranges() {
+            let end = PageID(range.start.0 + range.count);
+            if end > self.next_page_id {
+                self.problem(
+                    page_id,
+                    format!(
+                        "frees {} pages from {:?}, beyond the next page ID {:?}",
+                        range.count, range.start, self.next_page_id
+                    ),
+                );
+            }
+            let end = end.min(self.next_page_id).0.max(range.start.0);
+            free.extend((range.start.0..end).map(|id| (PageID(id), page_id)));
+        }
+    }
+
+    // Returns the free page IDs in the free lists tree, with the pages that list them
+    fn free_lists_tree(&mut self, page_id: PageID, referrer: PageID) -> Vec<(PageID, PageID)> {
+        let mut free = Vec::new();
+        match self.visit(page_id, referrer) {
+            Some(Node::FreeListInternal(node)) => {
+                self.check_children(page_id, node.keys.len(), node.child_ids.len());
+                self.check_sorted(page_id, &node.keys);
+                for child_id in node.child_ids {
+                    free.extend(self.free_lists_tree(child_id, page_id));
+                }
+            }
+            Some(Node::FreeListLeaf(node)) => {
+                self.check_sorted(page_id, &node.keys);
+                for value in node.values {
+                    self.free_page_ids(page_id, &value.page_ids, &mut free);
+                    if value.root_id != PageID(0) {
+                        free.extend(self.free_lists_tree(value.root_id, page_id));
+                    }
+                }
+            }
+            Some(Node::FreeListTsnInternal(node)) => {
+                self.check_children(page_id, node.keys.len(), node.child_ids.len());
+                self.check_sorted(page_id, &node.keys);
+                for child_id in node.child_ids {
+                    free.extend(self.free_lists_tree(child_id, page_id));
+                }
+            }
+            Some(Node::FreeListTsnLeaf(node)) => {
+                let starts: Vec<PageID> = node.page_ids.ranges().iter().map(|r| r.start).collect();
+                self.check_sorted(page_id, &starts);
+                self.free_page_ids(page_id, &node.page_ids, &mut free);
+            }
+            Some(other) => self.problem(
+                page_id,
+                format!("{} node in free lists tree", other.type_name()),
+            ),
+            None => {}
+        }
+        free
+    }
+}
+
+impl Mvcc {
+    /// Checks the trees reachable from the latest commit, and returns the problems
+    /// found. Errors are only returned if no snapshot can be taken.
+    pub fn verify(&self) -> DCBResult<VerifyReport> {
+        // Hold a reader so the pages being walked aren't reused meanwhile, taking
+        // another if its header page was rewritten before it could be read
+        let (reader, header) = loop {
+            let reader = self.reader()?;
+            let header = self.read_header(reader.header_page_id)?;
+            if header.tsn == reader.tsn {
+                break (reader, header);
+            }
+        };
+        let mut verifier = Verifier {
+            mvcc: self,
+            next_page_id: header.next_page_id,
+            live: PageBitset::new(header.next_page_id),
+            overflow_roots: HashMap::new(),
+            last_event_key: None,
+            problems: Vec::new(),
+        };
+        let header_page_id = reader.header_page_id;
+        verifier.events_tree(header.events_tree_root_id, header_page_id, None, None);
+        if let Some(last) = verifier.last_event_key
+            && last >= header.next_position
+        {
+            verifier.problem(
+                header_page_id,
+                format!(
+                    "next position {:?} isn't after the last event's {last:?}",
+                    header.next_position
+                ),
+            );
+        }
+        verifier.tags_tree(header.tags_tree_root_id, header_page_id);
+        let free = verifier.free_lists_tree(header.free_lists_tree_root_id, header_page_id);
+
+        let mut free_ids = PageBitset::new(header.next_page_id);
+        let mut double_allocated = Vec::new();
+        for (page_id, listed_by) in free {
+            if page_id.0 < 2 || page_id >= header.next_page_id {
+                verifier.problem(
+                    listed_by,
+                    format!(
+                        "frees page {page_id:?} outside 2..{:?}",
+                        header.next_page_id.0
+                    ),
+                );
+            } else if verifier.live.contains(page_id) {
+                verifier.problem(page_id, "both live and in the free list".to_string());
+                double_allocated.push(page_id);
+            } else if !free_ids.insert(page_id) {
+                verifier.problem(page_id, "in the free list more than once".to_string());
+            }
+        }
+        drop(reader);
+
+        Ok(VerifyReport {
+            live_pages: verifier.live.len(),
+            free_pages: free_ids.len(),
+            double_allocated,
+            problems: verifier.problems,
+        })
+    }
+    /// Returns the pages that are both reachable from the latest commit and in the
+    /// free list, which later commits would overwrite. Walks the trees as verify().
+    pub fn double_allocated_pages(&self) -> DCBResult<Vec<PageID>> {
+        Ok(self.verify()?.double_allocated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::UmaDB;
+    use crate::events_tree_nodes::EventLeafNode;
+    use crate::free_lists_tree_nodes::PageIdRange;
+    use crate::mvcc::DurabilityMode;
+    use crate::page::Page;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use umadb_dcb::{DCBEvent, DCBEventStoreSync};
+
+    fn populated_db(path: &std::path::Path) -> Arc<Mvcc> {
+        let mvcc = Arc::new(Mvcc::new(path, 4096, DurabilityMode::NoSync).unwrap());
+        let store = UmaDB::from_arc(mvcc.clone());
+        for batch in 0..20 {
+            let events = (0..100)
+                .map(|i| {
+                    let data = if i == 0 {
+                        vec![batch as u8; 10_000]
+                    } else {
+                        format!("data-{batch}-{i}").into_bytes()
+                    };
+                    DCBEvent::new()
+                        .event_type("E")
+                        .tags([format!("t{}", i % 5)])
+                        .data(data)
+                })
+                .collect();
+            store.append(events, None).unwrap();
+        }
+        mvcc
+    }
+
+    #[test]
+    fn test_verify_passes_a_sound_database() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = populated_db(&temp_dir.path().join("verify.db"));
+        let report = mvcc.verify().unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        let stats = mvcc.stats().unwrap();
+        assert_eq!(stats.free_pages, report.free_pages);
+        assert!(report.live_pages > 20);
+    }
+
+    #[test]
+    fn test_verify_flags_a_corrupted_child_pointer() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = populated_db(&temp_dir.path().join("verify.db"));
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        let root_id = header.events_tree_root_id;
+        let root = mvcc.read_page(root_id).unwrap();
+        let Node::EventInternal(mut internal) = root.node.clone() else {
+            panic!("expected an internal root");
+        };
+        let (first, second) = (internal.child_ids[0], internal.child_ids[1]);
+
+        // Pointing the second child at the first reads the first leaf twice
+        internal.child_ids[1] = first;
+        mvcc.write_pages([&Page::new(root_id, Node::EventInternal(internal.clone()))])
+            .unwrap();
+        let report = mvcc.verify().unwrap();
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|p| p.page_id == first && p.message.contains("referenced again")),
+            "{:?}",
+            report.problems
+        );
+        // And the page it used to point to looks free and unreachable, not live
+        assert!(!report.problems.iter().any(|p| p.page_id == second));
+
+        // Pointing past the end of the file is flagged on the parent
+        internal.child_ids[1] = PageID(header.next_page_id.0 + 10);
+        mvcc.write_pages([&Page::new(root_id, Node::EventInternal(internal))])
+            .unwrap();
+        let report = mvcc.verify().unwrap();
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|p| p.page_id == root_id && p.message.contains("outside")),
+            "{:?}",
+            report.problems
+        );
+
+        // Restoring the pointer makes the database sound again
+        mvcc.write_pages([&root]).unwrap();
+        assert!(mvcc.verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_verify_flags_an_unsorted_leaf() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = populated_db(&temp_dir.path().join("verify.db"));
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        let Node::EventInternal(root) = mvcc.read_page(header.events_tree_root_id).unwrap().node
+        else {
+            panic!("expected an internal root");
+        };
+        let (leaf_id, leaf) = root
+            .child_ids
+            .iter()
+            .find_map(|&id| match mvcc.read_page(id).unwrap().node {
+                Node::EventLeaf(leaf) if leaf.len() > 1 => Some((id, leaf)),
+                _ => None,
+            })
+            .expect("a leaf with two keys");
+        let (mut keys, values) = leaf.into_parts();
+        keys.swap(0, 1);
+        let page = Page::new(leaf_id, Node::EventLeaf(EventLeafNode::new(keys, values)));
+        mvcc.write_pages([&page]).unwrap();
+
+        // The leaf can still be read, so that verify() reports it
+        let report = mvcc.verify().unwrap();
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|p| p.page_id == leaf_id && p.message.contains("keys out of order")),
+            "{:?}",
+            report.problems
+        );
+    }
+
+    #[test]
+    fn test_double_allocated_page_is_reported() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = populated_db(&temp_dir.path().join("verify.db"));
+        assert!(mvcc.double_allocated_pages().unwrap().is_empty());
+
+        // Find the first free list leaf that lists pages inline
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        let mut page = mvcc.read_page(header.free_lists_tree_root_id).unwrap();
+        while let Node::FreeListInternal(node) = &page.node {
+            page = mvcc.read_page(node.child_ids[0]).unwrap();
+        }
+        let Node::FreeListLeaf(leaf) = &mut page.node else {
+            panic!("expected a free list leaf");
+        };
+        let value = leaf
+            .values
+            .iter_mut()
+            .find(|value| !value.page_ids.is_empty())
+            .expect("freed pages");
+
+        // Listing the root of the live events tree as free too
+        let live_id = header.events_tree_root_id;
+        value.insert_page_id(live_id);
+        mvcc.write_pages([&page]).unwrap();
+        assert_eq!(vec![live_id], mvcc.double_allocated_pages().unwrap());
+        let report = mvcc.verify().unwrap();
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|p| p.page_id == live_id && p.message.contains("both live")),
+            "{:?}",
+            report.problems
+        );
+    }
+
+    #[test]
+    fn test_free_range_past_next_page_id_is_reported() {
+        let temp_dir = tempdir().unwrap();
+        let mvcc = populated_db(&temp_dir.path().join("verify.db"));
+        let (_, header) = mvcc.get_latest_header().unwrap();
+        let mut page = mvcc.read_page(header.free_lists_tree_root_id).unwrap();
+        while let Node::FreeListInternal(node) = &page.node {
+            page = mvcc.read_page(node.child_ids[0]).unwrap();
+        }
+        let Node::FreeListLeaf(leaf) = &mut page.node else {
+            panic!("expected a free list leaf");
+        };
+
+        // A range running a billion pages past the end of the file
+        let last = header.next_page_id.0 - 1;
+        leaf.values[0].page_ids = [PageIdRange {
+            start: PageID(last),
+            count: 1_000_000_000,
+        }]
+        .into_iter()
+        .collect();
+        mvcc.write_pages([&page]).unwrap();
+        let report = mvcc.verify().unwrap();
+        let listed_by = page.page_id;
+        assert!(
+            report
+                .problems
+                .iter()
+                .any(|p| p.page_id == listed_by && p.message.contains("beyond the next page ID")),
+            "{:?}",
+            report.problems
+        );
+    }
+}
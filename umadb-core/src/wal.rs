@@ -0,0 +1,374 @@
+// Write-ahead log for durable commits that don't sync the database file.
+//
+// In WAL mode a commit still writes its pages and header into the database file, so
+// that readers see them, but only the log is synced. Each page written into the
+// database file is also appended to the log, and the new header is appended as a
+// commit record, which is synced before the header is written into the database file.
+// A checkpoint syncs the database file and empties the log. When a database is
+// opened, the pages and headers of the complete commits in the log are written into
+// the database file again, which restores any writes lost since the last checkpoint.
+use crate::common::PageID;
+use byteorder::{ByteOrder, LittleEndian};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use umadb_dcb::DCBResult;
+
+/// Settings for the write-ahead log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalConfig {
+    /// Size the log may grow to before a commit checkpoints it.
+    pub checkpoint_bytes: u64,
+    /// How often a background thread checkpoints the log, None for no thread.
+    pub checkpoint_interval: Option<Duration>,
+}
+
+impl Default for WalConfig {
+    fn default() -> Self {
+        Self {
+            checkpoint_bytes: 64 * 1024 * 1024,
+            checkpoint_interval: None,
+        }
+    }
+}
+
+// Record types
+const RECORD_PAGE: u8 = 1;
+const RECORD_COMMIT: u8 = 2;
+
+// Record header: type(1) + page_id(8) + len(4) + crc(4), followed by the page image
+const RECORD_HEADER_SIZE: usize = 17;
+const RECORD_CRC_OFFSET: usize = 13;
+
+// The checksum covers the type, page ID and length before it as well as the page
+// image, so that a damaged header can't replay an image onto the wrong page
+fn record_crc(header: &[u8], data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header[..RECORD_CRC_OFFSET]);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// A page image in the log, to be written into the database file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalPage {
+    pub page_id: PageID,
+    pub data: Vec<u8>,
+}
+
+pub struct Wal {
+    // Held while appending and checkpointing, so a checkpoint can't drop a record
+    // whose page was written into the database file after it was synced
+    file: Mutex<File>,
+    path: PathBuf,
+}
+
+impl Wal {
+    /// Returns the path of the log for the database file at `db_path`.
+    pub fn path_for(db_path: &Path) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".wal");
+        PathBuf::from(path)
+    }
+
+    /// Opens the log, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current size of the log in bytes.
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.file.lock().unwrap().metadata()?.len())
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Appends the image of a page written into the database file.
+    pub fn append_page(&self, page_id: PageID, data: &[u8]) -> io::Result<()> {
+        self.append(RECORD_PAGE, page_id, data)
+    }
+
+    /// Appends the image of a header page, which completes the pages before it.
+    pub fn append_commit(&self, header_page_id: PageID, data: &[u8]) -> io::Result<()> {
+        self.append(RECORD_COMMIT, header_page_id, data)
+    }
+
+    fn append(&self, record_type: u8, page_id: PageID, data: &[u8]) -> io::Result<()> {
+        let mut record = vec![0u8; RECORD_HEADER_SIZE + data.len()];
+        record[0] = record_type;
+        LittleEndian::write_u64(&mut record[1..9], page_id.0);
+        LittleEndian::write_u32(&mut record[9..13], data.len() as u32);
+        let crc = record_crc(&record, data);
+        LittleEndian::write_u32(&mut record[RECORD_CRC_OFFSET..RECORD_HEADER_SIZE], crc);
+        record[RECORD_HEADER_SIZE..].copy_from_slice(data);
+        self.file.lock().unwrap().write_all(&record)
+    }
+
+    /// Syncs the appended records to disk, with fdatasync if `data_only`.
+    pub fn sync(&self, data_only: bool) -> io::Result<()> {
+        let file = self.file.lock().unwrap();
+        if data_only {
+            file.sync_data()
+        } else {
+            file.sync_all()
+        }
+    }
+
+    /// Syncs the database file with `sync_db`, then empties the log.
+    pub fn checkpoint(&self, sync_db: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let file = self.file.lock().unwrap();
+        sync_db()?;
+        file.set_len(0)?;
+        file.sync_all()
+    }
+
+    /// Reads the page images of the complete commits in the log at `path`, in the
+    /// order they were appended. Reading stops at the first record that is torn or
+    /// fails its checksum, and pages after the last commit record are left out.
+    pub fn read_committed_pages(path: &Path) -> DCBResult<Vec<WalPage>> {
+        let mut bytes = Vec::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        }
+
+        let mut pages = Vec::new();
+        let mut committed_len = 0;
+        let mut offset = 0;
+        while offset + RECORD_HEADER_SIZE <= bytes.len() {
+            let header = &bytes[offset..offset + RECORD_HEADER_SIZE];
+            let record_type = header[0];
+            let page_id = PageID(LittleEndian::read_u64(&header[1..9]));
+            let len = LittleEndian::read_u32(&header[9..13]) as usize;
+            let crc = LittleEndian::read_u32(&header[RECORD_CRC_OFFSET..]);
+            let start = offset + RECORD_HEADER_SIZE;
+            if start + len > bytes.len() {
+                break;
+            }
+            let data = &bytes[start..start + len];
+            if record_crc(header, data) != crc
+                || !matches!(record_type, RECORD_PAGE | RECORD_COMMIT)
+            {
+                break;
+            }
+            pages.push(WalPage {
+                page_id,
+                data: data.to_vec(),
+            });
+            if record_type == RECORD_COMMIT {
+                committed_len = pages.len();
+            }
+            offset = start + len;
+        }
+        pages.truncate(committed_len);
+        Ok(pages)
+    }
+}
+
+/// Background thread that checkpoints the log on an interval, until it is dropped.
+///
+/// Checkpoints hold the log's lock, as commits do while appending, so a
+/// checkpoint never drops a page that was written after the database file was synced.
+pub struct Checkpointer {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    // The error of the last checkpoint that failed, until it is taken
+    error: Arc<Mutex<Option<io::Error>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Checkpointer {
+    /// Spawns a thread that checkpoints `wal` every `interval` when it isn't empty,
+    /// syncing the database file with `sync_db` first.
+    pub fn spawn(
+        wal: Arc<Wal>,
+        sync_db: impl Fn() -> io::Result<()> + Send + 'static,
+        interval: Duration,
+        verbose: bool,
+    ) -> io::Result<Self> {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+        let handle = std::thread::Builder::new()
+            .name("umadb-checkpoint".to_string())
+            .spawn(move || {
+                let (stopped, cond) = &*thread_stop;
+                let mut stopped = stopped.lock().unwrap();
+                loop {
+                    // Stopping may have been asked for before the thread first waits
+                    stopped = cond
+                        .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+                        .unwrap()
+                        .0;
+                    if *stopped {
+                        break;
+                    }
+                    let result = match wal.is_empty() {
+                        Ok(true) => Ok(()),
+                        Ok(false) => wal.checkpoint(&sync_db),
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = result {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(error = %err, "background checkpoint failed");
+                        if verbose {
+                            println!("Background checkpoint failed: {err:?}");
+                        }
+                        *thread_error.lock().unwrap() = Some(err);
+                    }
+                }
+            })?;
+        Ok(Self {
+            stop,
+            error,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the error of the last background checkpoint that failed since this
+    /// was last called, if one did.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        let (stopped, cond) = &*self.stop;
+        *stopped.lock().unwrap() = true;
+        cond.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_committed_pages_in_append_order() {
+        let temp_dir = tempdir().unwrap();
+        let path = Wal::path_for(&temp_dir.path().join("uma.db"));
+        assert!(path.ends_with("uma.db.wal"));
+        let wal = Wal::open(&path).unwrap();
+        assert!(wal.is_empty().unwrap());
+
+        wal.append_page(PageID(5), &[1u8; 16]).unwrap();
+        wal.append_page(PageID(6), &[2u8; 16]).unwrap();
+        wal.append_commit(PageID(1), &[3u8; 16]).unwrap();
+        wal.append_page(PageID(5), &[4u8; 16]).unwrap();
+        wal.append_commit(PageID(0), &[5u8; 16]).unwrap();
+        // Pages of a commit whose header wasn't logged are left out
+        wal.append_page(PageID(7), &[6u8; 16]).unwrap();
+        wal.sync(false).unwrap();
+
+        let pages = Wal::read_committed_pages(&path).unwrap();
+        let ids: Vec<(PageID, u8)> = pages.iter().map(|p| (p.page_id, p.data[0])).collect();
+        assert_eq!(
+            vec![
+                (PageID(5), 1),
+                (PageID(6), 2),
+                (PageID(1), 3),
+                (PageID(5), 4),
+                (PageID(0), 5),
+            ],
+            ids
+        );
+
+        wal.checkpoint(|| Ok(())).unwrap();
+        assert!(wal.is_empty().unwrap());
+        assert!(Wal::read_committed_pages(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_committed_pages_stops_at_torn_record() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("uma.db.wal");
+        let wal = Wal::open(&path).unwrap();
+        wal.append_page(PageID(5), &[1u8; 16]).unwrap();
+        wal.append_commit(PageID(1), &[2u8; 16]).unwrap();
+        wal.append_page(PageID(6), &[3u8; 16]).unwrap();
+        wal.append_commit(PageID(0), &[4u8; 16]).unwrap();
+        let full_len = wal.len().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let committed_ids = |path: &Path| {
+            Wal::read_committed_pages(path)
+                .unwrap()
+                .iter()
+                .map(|p| p.page_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![PageID(5), PageID(1), PageID(6), PageID(0)],
+            committed_ids(&path)
+        );
+
+        // A record that fails its checksum ends the log
+        let mut corrupted = bytes.clone();
+        let third_record = 2 * (RECORD_HEADER_SIZE + 16);
+        corrupted[third_record + RECORD_HEADER_SIZE] ^= 0xff;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(vec![PageID(5), PageID(1)], committed_ids(&path));
+
+        // Including one whose page ID is damaged but whose image is intact
+        let mut corrupted = bytes.clone();
+        corrupted[third_record + 1] ^= 0x01;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(vec![PageID(5), PageID(1)], committed_ids(&path));
+
+        // As does a record that was cut short
+        std::fs::write(&path, &bytes[..full_len as usize - 4]).unwrap();
+        assert_eq!(vec![PageID(5), PageID(1)], committed_ids(&path));
+
+        // A missing log has no pages
+        assert!(
+            Wal::read_committed_pages(&temp_dir.path().join("missing.wal"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_failed_background_checkpoint_is_kept_until_taken() {
+        let temp_dir = tempdir().unwrap();
+        let wal = Arc::new(Wal::open(&temp_dir.path().join("uma.db.wal")).unwrap());
+        wal.append_commit(PageID(0), &[1u8; 16]).unwrap();
+        let checkpointer = Checkpointer::spawn(
+            wal.clone(),
+            || Err(io::Error::other("disk gone")),
+            Duration::from_millis(10),
+            false,
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+
+        let err = checkpointer.take_error().unwrap();
+        assert!(err.to_string().contains("disk gone"));
+        // The log is kept for the next checkpoint
+        assert!(!wal.is_empty().unwrap());
+        drop(checkpointer);
+    }
+}
@@ -10,6 +10,8 @@ use std::iter::Iterator;
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod ndjson;
+
 /// Non-async Rust interface for recording and retrieving events
 pub trait DCBEventStoreSync {
     /// Reads events from the store based on the provided query and constraints
@@ -40,6 +42,47 @@ pub trait DCBEventStoreSync {
         response.collect_with_head()
     }
 
+    /// Reads events matching the query in descending position order, from the
+    /// head or else from the last event before the given position
+    fn read_backwards(
+        &self,
+        query: Option<DCBQuery>,
+        before: Option<u64>,
+        limit: Option<u32>,
+    ) -> DCBResult<Box<dyn DCBReadResponseSync + 'static>> {
+        let start = before.map(|before| before.saturating_sub(1));
+        self.read(query, start, true, limit, false)
+    }
+
+    /// Reads the events with positions from start (inclusive) to end (exclusive)
+    fn read_range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> DCBResult<Box<dyn DCBReadResponseSync + 'static>> {
+        let (start, limit) = position_range_limit(start, end);
+        self.read(None, Some(start), false, Some(limit), false)
+    }
+
+    /// Reads a page of up to limit events matching the query, resuming after the
+    /// events of a previous page if given that page's token
+    fn read_page(
+        &self,
+        query: Option<DCBQuery>,
+        token: Option<&[u8]>,
+        limit: u32,
+    ) -> DCBResult<DCBReadPage> {
+        let query = query.unwrap_or_default();
+        let start = page_start(&query, token)?;
+        let (events, _) = self.read_with_head(
+            Some(query.clone()),
+            start,
+            false,
+            Some(limit.saturating_add(1)),
+        )?;
+        Ok(DCBReadPage::new(events, &query, limit))
+    }
+
     /// Returns the current head position of the event store, or None if empty
     ///
     /// Returns the value of last_committed_position, or None if last_committed_position is zero
@@ -53,6 +96,19 @@ pub trait DCBEventStoreSync {
         events: Vec<DCBEvent>,
         condition: Option<DCBAppendCondition>,
     ) -> DCBResult<u64>;
+
+    /// Appends given events to the event store, unless the condition fails
+    ///
+    /// Returns the position of each appended event, in order
+    fn append_with_positions(
+        &self,
+        events: Vec<DCBEvent>,
+        condition: Option<DCBAppendCondition>,
+    ) -> DCBResult<Vec<u64>> {
+        let count = events.len();
+        let last = self.append(events, condition)?;
+        Ok(positions_ending_at(last, count))
+    }
 }
 
 /// Response from a read operation, providing an iterator over sequenced events
@@ -96,6 +152,50 @@ pub trait DCBEventStoreAsync: Send + Sync {
         response.collect_with_head().await
     }
 
+    /// Reads events matching the query in descending position order, from the
+    /// head or else from the last event before the given position
+    async fn read_backwards<'a>(
+        &'a self,
+        query: Option<DCBQuery>,
+        before: Option<u64>,
+        limit: Option<u32>,
+    ) -> DCBResult<Box<dyn DCBReadResponseAsync + Send + 'static>> {
+        let start = before.map(|before| before.saturating_sub(1));
+        self.read(query, start, true, limit, false).await
+    }
+
+    /// Reads the events with positions from start (inclusive) to end (exclusive)
+    async fn read_range<'a>(
+        &'a self,
+        start: u64,
+        end: u64,
+    ) -> DCBResult<Box<dyn DCBReadResponseAsync + Send + 'static>> {
+        let (start, limit) = position_range_limit(start, end);
+        self.read(None, Some(start), false, Some(limit), false)
+            .await
+    }
+
+    /// Reads a page of up to limit events matching the query, resuming after the
+    /// events of a previous page if given that page's token
+    async fn read_page<'a>(
+        &'a self,
+        query: Option<DCBQuery>,
+        token: Option<&'a [u8]>,
+        limit: u32,
+    ) -> DCBResult<DCBReadPage> {
+        let query = query.unwrap_or_default();
+        let start = page_start(&query, token)?;
+        let (events, _) = self
+            .read_with_head(
+                Some(query.clone()),
+                start,
+                false,
+                Some(limit.saturating_add(1)),
+            )
+            .await?;
+        Ok(DCBReadPage::new(events, &query, limit))
+    }
+
     /// Returns the current head position of the event store, or None if empty
     ///
     /// Returns the value of last_committed_position, or None if last_committed_position is zero
@@ -109,6 +209,121 @@ pub trait DCBEventStoreAsync: Send + Sync {
         events: Vec<DCBEvent>,
         condition: Option<DCBAppendCondition>,
     ) -> DCBResult<u64>;
+
+    /// Appends given events to the event store, unless the condition fails
+    ///
+    /// Returns the position of each appended event, in order
+    async fn append_with_positions(
+        &self,
+        events: Vec<DCBEvent>,
+        condition: Option<DCBAppendCondition>,
+    ) -> DCBResult<Vec<u64>> {
+        let count = events.len();
+        let last = self.append(events, condition).await?;
+        Ok(positions_ending_at(last, count))
+    }
+}
+
+/// Storage statistics for a database, from stats()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DbStats {
+    /// Number of pages allocated in the database file
+    pub total_pages: u64,
+    /// Number of pages in the free list, waiting to be reused
+    pub free_pages: u64,
+    /// Number of events recorded
+    pub event_count: u64,
+    /// Number of levels in the events tree, including the leaf level
+    pub events_tree_height: u32,
+    /// Size of the database file in bytes
+    pub file_size: u64,
+}
+
+/// A page of events from read_page()
+#[derive(Debug, Clone)]
+pub struct DCBReadPage {
+    /// The events in the page
+    pub events: Vec<DCBSequencedEvent>,
+    /// Token for reading the next page, or None if this is the last page
+    pub next_token: Option<Vec<u8>>,
+}
+
+const PAGE_TOKEN_VERSION: u8 = 1;
+
+impl DCBReadPage {
+    // Expects up to limit + 1 events, the last of which only shows there's another page
+    fn new(mut events: Vec<DCBSequencedEvent>, query: &DCBQuery, limit: u32) -> Self {
+        let next_token = if events.len() > limit as usize {
+            events.truncate(limit as usize);
+            Some(encode_page_token(
+                events.last().map_or(0, |e| e.position),
+                query,
+            ))
+        } else {
+            None
+        };
+        Self { events, next_token }
+    }
+}
+
+/// A page token holds the last position of the page and the query, so a page
+/// can be resumed on another connection and the token checked against the query.
+fn encode_page_token(last_position: u64, query: &DCBQuery) -> Vec<u8> {
+    fn write_strings(buf: &mut Vec<u8>, strings: &[String]) {
+        buf.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        for s in strings {
+            buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+    }
+    let mut buf = vec![PAGE_TOKEN_VERSION];
+    buf.extend_from_slice(&last_position.to_le_bytes());
+    buf.extend_from_slice(&(query.items.len() as u32).to_le_bytes());
+    for item in &query.items {
+        write_strings(&mut buf, &item.types);
+        write_strings(&mut buf, &item.tags);
+    }
+    // Left off for case-sensitive queries, so their earlier tokens still match
+    if query.case_insensitive {
+        buf.push(1);
+    }
+    buf
+}
+
+/// Returns the position to resume reading from, after checking that the token
+/// was issued for the same query.
+fn page_start(query: &DCBQuery, token: Option<&[u8]>) -> DCBResult<Option<u64>> {
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    let invalid =
+        |reason: &str| DCBError::DeserializationError(format!("Invalid page token: {reason}"));
+    if token.first() != Some(&PAGE_TOKEN_VERSION) || token.len() < 9 {
+        return Err(invalid("unrecognized format"));
+    }
+    let mut last_position = [0u8; 8];
+    last_position.copy_from_slice(&token[1..9]);
+    let last_position = u64::from_le_bytes(last_position);
+    if encode_page_token(last_position, query) != token {
+        return Err(invalid("it was issued for a different query"));
+    }
+    Ok(Some(last_position + 1))
+}
+
+/// Positions start at 1 and have no gaps, so a range of positions is read as a
+/// start and a limit. Ranges longer than u32::MAX are cut short.
+fn position_range_limit(start: u64, end: u64) -> (u64, u32) {
+    let start = start.max(1);
+    let limit = end.saturating_sub(start).min(u32::MAX as u64) as u32;
+    (start, limit)
+}
+
+/// Positions of a batch of events appended together, which are consecutive
+pub fn positions_ending_at(last: u64, count: usize) -> Vec<u64> {
+    if count == 0 {
+        return Vec::new();
+    }
+    ((last + 1).saturating_sub(count as u64)..=last).collect()
 }
 
 /// Asynchronous response from a read operation, providing a stream of sequenced events
@@ -166,6 +381,22 @@ impl DCBQueryItem {
         self.tags = tags.into_iter().map(|s| s.into()).collect();
         self
     }
+
+    /// Returns true if the event type is one of the types (or no types are given)
+    /// and the event has all of the tags, which may be any cheaply cloned
+    /// collection of strings such as `&[String]`
+    pub fn matches<T>(&self, event_type: &str, tags: T) -> bool
+    where
+        T: IntoIterator + Clone,
+        T::Item: AsRef<str>,
+    {
+        let type_ok = self.types.is_empty() || self.types.iter().any(|t| t == event_type);
+        type_ok
+            && self
+                .tags
+                .iter()
+                .all(|t| tags.clone().into_iter().any(|et| et.as_ref() == t))
+    }
 }
 
 /// A query composed of multiple query items
@@ -173,12 +404,17 @@ impl DCBQueryItem {
 pub struct DCBQuery {
     /// List of query items, where events matching any item are included in results
     pub items: Vec<DCBQueryItem>,
+    /// Whether types and tags are matched regardless of case
+    pub case_insensitive: bool,
 }
 
 impl DCBQuery {
     /// Creates a new empty query
     pub fn new() -> Self {
-        Self { items: Vec::new() }
+        Self {
+            items: Vec::new(),
+            case_insensitive: false,
+        }
     }
 
     /// Creates a query with the specified items
@@ -188,6 +424,7 @@ impl DCBQuery {
     {
         Self {
             items: items.into_iter().collect(),
+            case_insensitive: false,
         }
     }
 
@@ -205,6 +442,92 @@ impl DCBQuery {
         self.items.extend(items);
         self
     }
+
+    /// Matches types and tags regardless of case, so that a tag "OrderPlaced"
+    /// matches an event tagged "orderplaced". Stored events keep their casing.
+    pub fn case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = enabled;
+        self
+    }
+
+    /// Returns true if any item matches the event, or if the query has no items
+    pub fn matches(&self, event: &DCBEvent) -> bool {
+        if self.case_insensitive {
+            return self.matcher().matches(&event.event_type, &event.tags);
+        }
+        self.items.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| item.matches(&event.event_type, &event.tags))
+    }
+
+    /// Returns the query prepared for matching many events
+    pub fn matcher(&self) -> DCBQueryMatcher {
+        DCBQueryMatcher::new(self)
+    }
+}
+
+/// A query prepared for matching many events, with the types and tags of a
+/// case-insensitive query lowercased once rather than for each event
+#[derive(Debug, Clone)]
+pub struct DCBQueryMatcher {
+    items: Vec<DCBQueryItem>,
+    case_insensitive: bool,
+}
+
+impl DCBQueryMatcher {
+    pub fn new(query: &DCBQuery) -> Self {
+        let lowercase = |strings: &[String]| -> Vec<String> {
+            strings.iter().map(|s| s.to_lowercase()).collect()
+        };
+        let items = if query.case_insensitive {
+            query
+                .items
+                .iter()
+                .map(|item| DCBQueryItem {
+                    types: lowercase(&item.types),
+                    tags: lowercase(&item.tags),
+                })
+                .collect()
+        } else {
+            query.items.clone()
+        };
+        Self {
+            items,
+            case_insensitive: query.case_insensitive,
+        }
+    }
+
+    /// Returns true if any item matches, or if the query has no items
+    pub fn matches<T>(&self, event_type: &str, tags: T) -> bool
+    where
+        T: IntoIterator + Clone,
+        T::Item: AsRef<str>,
+    {
+        self.items.is_empty()
+            || (0..self.items.len()).any(|index| self.item_matches(index, event_type, tags.clone()))
+    }
+
+    /// Returns true if the query's item at `index` matches
+    pub fn item_matches<T>(&self, index: usize, event_type: &str, tags: T) -> bool
+    where
+        T: IntoIterator + Clone,
+        T::Item: AsRef<str>,
+    {
+        let item = &self.items[index];
+        if !self.case_insensitive {
+            return item.matches(event_type, tags);
+        }
+        // The item is already lowercase, so only the event's side is lowered
+        let eq = |lower: &str, s: &str| lower.chars().eq(s.chars().flat_map(char::to_lowercase));
+        let type_ok = item.types.is_empty() || item.types.iter().any(|t| eq(t, event_type));
+        type_ok
+            && item
+                .tags
+                .iter()
+                .all(|t| tags.clone().into_iter().any(|et| eq(t, et.as_ref())))
+    }
 }
 
 /// Conditions that must be satisfied for an append operation to succeed
@@ -229,6 +552,12 @@ impl DCBAppendCondition {
         self.after = after;
         self
     }
+
+    /// Creates a condition that fails if any event has been appended after the
+    /// expected head position, for optimistic concurrency.
+    pub fn expected_head(head: Option<u64>) -> Self {
+        Self::new(DCBQuery::new()).after(head)
+    }
 }
 
 /// Represents an event in the event store
@@ -242,6 +571,8 @@ pub struct DCBEvent {
     pub tags: Vec<String>,
     /// Unique event ID
     pub uuid: Option<Uuid>,
+    /// Time of the event in milliseconds since the Unix epoch, as given by the client
+    pub client_timestamp: Option<u64>,
 }
 
 impl Default for DCBEvent {
@@ -258,6 +589,7 @@ impl DCBEvent {
             data: Vec::new(),
             tags: Vec::new(),
             uuid: None,
+            client_timestamp: None,
         }
     }
 
@@ -288,6 +620,68 @@ impl DCBEvent {
         self.uuid = Some(uuid);
         self
     }
+
+    /// Sets the client's time for this event, in milliseconds since the Unix epoch
+    pub fn client_timestamp(mut self, millis: u64) -> Self {
+        self.client_timestamp = Some(millis);
+        self
+    }
+
+    /// Returns a builder for an event, which can add tags one at a time
+    pub fn builder() -> DCBEventBuilder {
+        DCBEventBuilder::default()
+    }
+}
+
+/// Builds a DCBEvent, with no tags or UUID unless they are added
+#[derive(Debug, Clone, Default)]
+pub struct DCBEventBuilder {
+    event: DCBEvent,
+}
+
+impl DCBEventBuilder {
+    /// Sets the type of the event
+    pub fn event_type<S: Into<String>>(mut self, event_type: S) -> Self {
+        self.event.event_type = event_type.into();
+        self
+    }
+
+    /// Sets the data of the event
+    pub fn data<D: Into<Vec<u8>>>(mut self, data: D) -> Self {
+        self.event.data = data.into();
+        self
+    }
+
+    /// Adds a tag after those already added
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.event.tags.push(tag.into());
+        self
+    }
+
+    /// Sets the UUID of the event
+    pub fn uuid(mut self, uuid: Uuid) -> Self {
+        self.event.uuid = Some(uuid);
+        self
+    }
+
+    /// Sets the client's time of the event, in milliseconds since the Unix epoch
+    pub fn client_timestamp(mut self, millis: u64) -> Self {
+        self.event.client_timestamp = Some(millis);
+        self
+    }
+
+    /// Returns the event
+    pub fn build(self) -> DCBEvent {
+        self.event
+    }
+}
+
+/// Returns a new UUIDv7 for an event, which embeds the current time in milliseconds
+///
+/// UUIDs generated by the same process sort in the order they were created. The UUID
+/// is stored with the event whether its data is inline or in overflow pages.
+pub fn new_event_uuid() -> Uuid {
+    Uuid::now_v7()
 }
 
 /// An event with its position in the event sequence
@@ -297,6 +691,21 @@ pub struct DCBSequencedEvent {
     pub event: DCBEvent,
     /// Position of the event in the sequence
     pub position: u64,
+    /// Time the event was committed, in milliseconds since the Unix epoch, or None
+    /// for events appended before commit times were recorded
+    pub commit_timestamp: Option<u64>,
+    /// Tsn of the commit that appended the event, or None for events appended
+    /// before Tsns were recorded
+    pub tsn: Option<u64>,
+}
+
+/// An event from read_multi(), with the queries it matched
+#[derive(Debug, Clone)]
+pub struct DCBMultiQueryEvent {
+    /// The event with its position
+    pub event: DCBSequencedEvent,
+    /// Indices of the queries the event matched, in ascending order
+    pub query_indices: Vec<usize>,
 }
 
 // Error types
@@ -309,10 +718,14 @@ pub enum DCBError {
     // DCB domain errors
     #[error("Integrity error: condition failed: {0}")]
     IntegrityError(String),
+    #[error("Integrity violation: expected {expected}, got {actual}")]
+    IntegrityViolation { expected: String, actual: String },
     #[error("Corruption detected: {0}")]
     Corruption(String),
 
     // LMDB/Storage domain errors (unified into DCBError)
+    #[error("Event not found: {0}")]
+    EventNotFound(String),
     #[error("Page not found: {0:?}")]
     PageNotFound(u64),
     #[error("Dirty page not found: {0:?}")]
@@ -331,8 +744,26 @@ pub enum DCBError {
     PageAlreadyFreed(u64),
     #[error("Page already dirty: {0:?}")]
     PageAlreadyDirty(u64),
+    #[error("Page overflow: page {0:?} needs {1} bytes, at most {2} fit")]
+    PageOverflow(u64, usize, usize),
+    #[error("Page size mismatch: database has {0} byte pages, opened with {1}")]
+    PageSizeMismatch(usize, usize),
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    #[error("Checksum mismatch: page {page_id:?}")]
+    ChecksumMismatch { page_id: u64 },
+    #[error("Unexpected end of data while reading {0}")]
+    UnexpectedEndOfData(String),
+    #[error("Invalid UUID: {0}")]
+    InvalidUuid(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Out of retention: {0}")]
+    OutOfRetention(String),
     #[error("Transport error: {0}")]
     TransportError(String),
+    #[error("Timeout: {0}")]
+    Timeout(String),
     #[error("Cancelled by user")]
     CancelledByUser(),
 }
@@ -385,7 +816,7 @@ mod tests {
 
         fn next_batch(&mut self) -> DCBResult<Vec<DCBSequencedEvent>> {
             let mut batch = Vec::new();
-            while let Some(result) = self.next() {
+            for result in self.by_ref() {
                 match result {
                     Ok(event) => batch.push(event),
                     Err(err) => {
@@ -405,6 +836,7 @@ mod tests {
             data: vec![1, 2, 3],
             tags: vec!["tag1".to_string(), "tag2".to_string()],
             uuid: None,
+            client_timestamp: None,
         };
 
         let event2 = DCBEvent {
@@ -412,16 +844,21 @@ mod tests {
             data: vec![4, 5, 6],
             tags: vec!["tag2".to_string(), "tag3".to_string()],
             uuid: None,
+            client_timestamp: None,
         };
 
         let seq_event1 = DCBSequencedEvent {
             event: event1,
             position: 1,
+            commit_timestamp: None,
+            tsn: None,
         };
 
         let seq_event2 = DCBSequencedEvent {
             event: event2,
             position: 2,
+            commit_timestamp: None,
+            tsn: None,
         };
 
         // Create a test response
@@ -437,6 +874,75 @@ mod tests {
         assert!(response.next().is_none());
     }
 
+    #[test]
+    fn test_query_matching() {
+        let event = |event_type: &str, tags: &[&str]| {
+            DCBEvent::new()
+                .event_type(event_type)
+                .tags(tags.iter().copied())
+        };
+        let order_placed = event("OrderPlaced", &["order:1", "customer:1"]);
+        let order_shipped = event("OrderShipped", &["order:1"]);
+        let customer_renamed = event("CustomerRenamed", &["customer:1"]);
+
+        // All tags of an item must be present
+        let both_tags = DCBQuery::new().item(DCBQueryItem::new().tags(["order:1", "customer:1"]));
+        assert!(both_tags.matches(&order_placed));
+        assert!(!both_tags.matches(&order_shipped));
+        assert!(!both_tags.matches(&customer_renamed));
+
+        // Types and tags of an item must both match
+        let placed_order = DCBQuery::new().item(
+            DCBQueryItem::new()
+                .types(["OrderPlaced", "OrderCancelled"])
+                .tags(["order:1"]),
+        );
+        assert!(placed_order.matches(&order_placed));
+        assert!(!placed_order.matches(&order_shipped));
+
+        // Any item may match
+        let shipped_or_renamed = DCBQuery::new()
+            .item(DCBQueryItem::new().types(["OrderShipped"]))
+            .item(
+                DCBQueryItem::new()
+                    .tags(["customer:1"])
+                    .types(["CustomerRenamed"]),
+            );
+        assert!(!shipped_or_renamed.matches(&order_placed));
+        assert!(shipped_or_renamed.matches(&order_shipped));
+        assert!(shipped_or_renamed.matches(&customer_renamed));
+
+        // An empty query, or an empty item, matches every event
+        assert!(DCBQuery::new().matches(&order_placed));
+        assert!(
+            DCBQuery::new()
+                .item(DCBQueryItem::new())
+                .matches(&customer_renamed)
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_query_matching() {
+        let event = DCBEvent::new()
+            .event_type("orderplaced")
+            .tags(["Order:ÄB", "customer:1"]);
+        let query = DCBQuery::new().item(
+            DCBQueryItem::new()
+                .types(["OrderPlaced"])
+                .tags(["order:äb", "CUSTOMER:1"]),
+        );
+        assert!(!query.matches(&event));
+        assert!(!query.matcher().matches(&event.event_type, &event.tags));
+
+        let query = query.case_insensitive(true);
+        assert!(query.matches(&event));
+        let matcher = query.matcher();
+        assert!(matcher.matches(&event.event_type, &event.tags));
+        assert!(matcher.item_matches(0, "ORDERPLACED", &event.tags));
+        assert!(!matcher.item_matches(0, "OrderShipped", &event.tags));
+        assert!(!matcher.matches("OrderPlaced", &["order:äb".to_string()]));
+    }
+
     #[test]
     fn test_event_new() {
         let event1 = DCBEvent::default()
@@ -481,4 +987,30 @@ mod tests {
 
         println!("\nAll builder API tests passed!");
     }
+
+    #[test]
+    fn test_event_builder() {
+        let uuid = Uuid::new_v4();
+        let event = DCBEvent::builder()
+            .event_type("OrderPlaced")
+            .data("{}")
+            .tag("order:1")
+            .tag("customer:2")
+            .uuid(uuid)
+            .build();
+        assert_eq!(event.event_type, "OrderPlaced");
+        assert_eq!(event.data, b"{}".to_vec());
+        assert_eq!(event.tags, vec!["order:1", "customer:2"]);
+        assert_eq!(event.uuid, Some(uuid));
+        assert_eq!(DCBEvent::builder().build().tags.len(), 0);
+    }
+
+    #[test]
+    fn test_new_event_uuids_sort_in_creation_order() {
+        let first = new_event_uuid();
+        let second = new_event_uuid();
+        assert_eq!(Some(uuid::Version::SortRand), first.get_version());
+        assert!(first < second);
+        assert!(first.to_string() < second.to_string());
+    }
 }
@@ -0,0 +1,122 @@
+//! Newline-delimited JSON encoding of events, for exports and imports
+//!
+//! Each event is written as one JSON object on its own line, with the event's
+//! position, type, tags, base64-encoded data, UUID (or null), and the client and
+//! commit timestamps (or null).
+
+use crate::{DCBError, DCBEvent, DCBResult, DCBSequencedEvent};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+struct NdjsonEvent {
+    position: u64,
+    event_type: String,
+    tags: Vec<String>,
+    data: String,
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    client_timestamp: Option<u64>,
+    #[serde(default)]
+    commit_timestamp: Option<u64>,
+}
+
+/// Writes the event as a line of newline-delimited JSON
+pub fn write_ndjson_event<W: Write>(writer: &mut W, event: &DCBSequencedEvent) -> DCBResult<()> {
+    let record = NdjsonEvent {
+        position: event.position,
+        event_type: event.event.event_type.clone(),
+        tags: event.event.tags.clone(),
+        data: STANDARD.encode(&event.event.data),
+        uuid: event.event.uuid.map(|uuid| uuid.to_string()),
+        client_timestamp: event.event.client_timestamp,
+        commit_timestamp: event.commit_timestamp,
+    };
+    serde_json::to_writer(&mut *writer, &record)
+        .map_err(|e| DCBError::SerializationError(format!("NDJSON event: {e}")))?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Parses a line of newline-delimited JSON written by write_ndjson_event()
+pub fn parse_ndjson_event(line: &str) -> DCBResult<DCBSequencedEvent> {
+    let record: NdjsonEvent = serde_json::from_str(line)
+        .map_err(|e| DCBError::DeserializationError(format!("NDJSON event: {e}")))?;
+    let data = STANDARD.decode(&record.data).map_err(|e| {
+        DCBError::DeserializationError(format!("NDJSON event at position {}: {e}", record.position))
+    })?;
+    let uuid = match record.uuid {
+        Some(uuid) => Some(
+            Uuid::parse_str(&uuid)
+                .map_err(|_| DCBError::InvalidUuid(format!("{uuid:?} in NDJSON event")))?,
+        ),
+        None => None,
+    };
+    Ok(DCBSequencedEvent {
+        event: DCBEvent {
+            event_type: record.event_type,
+            data,
+            tags: record.tags,
+            uuid,
+            client_timestamp: record.client_timestamp,
+        },
+        position: record.position,
+        commit_timestamp: record.commit_timestamp,
+        // Tsns belong to the database that issued them, so none are exported
+        tsn: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ndjson_event_round_trip() {
+        let events = vec![
+            DCBSequencedEvent {
+                event: DCBEvent::new()
+                    .event_type("Binary")
+                    .data(vec![0u8, 255, 10, 13])
+                    .tags(["a", "b\n\"c\""])
+                    .uuid(Uuid::new_v4())
+                    .client_timestamp(1_600_000_000_000),
+                position: 1,
+                commit_timestamp: Some(1_700_000_000_000),
+                tsn: None,
+            },
+            DCBSequencedEvent {
+                event: DCBEvent::new().event_type("Empty"),
+                position: 2,
+                commit_timestamp: None,
+                tsn: None,
+            },
+        ];
+        let mut out = Vec::new();
+        for event in &events {
+            write_ndjson_event(&mut out, event).unwrap();
+        }
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(2, text.lines().count());
+
+        for (line, event) in text.lines().zip(&events) {
+            let parsed = parse_ndjson_event(line).unwrap();
+            assert_eq!(event.position, parsed.position);
+            assert_eq!(event.event.event_type, parsed.event.event_type);
+            assert_eq!(event.event.data, parsed.event.data);
+            assert_eq!(event.event.tags, parsed.event.tags);
+            assert_eq!(event.event.uuid, parsed.event.uuid);
+            assert_eq!(event.event.client_timestamp, parsed.event.client_timestamp);
+            assert_eq!(event.commit_timestamp, parsed.commit_timestamp);
+        }
+
+        assert!(matches!(
+            parse_ndjson_event("{\"position\": 1}"),
+            Err(DCBError::DeserializationError(_))
+        ));
+    }
+}
@@ -1,16 +1,22 @@
+pub use crate::umadb::append_stream_request_proto::Part as AppendStreamPartProto;
 pub use crate::umadb::uma_db_service_client::UmaDbServiceClient;
 pub use crate::umadb::uma_db_service_server::{UmaDbService, UmaDbServiceServer};
 pub use crate::umadb::{
-    AppendConditionProto, AppendRequestProto, AppendResponseProto, ErrorResponseProto, EventProto,
-    HeadRequestProto, HeadResponseProto, QueryItemProto, QueryProto, ReadRequestProto,
-    ReadResponseProto, SequencedEventProto,
+    AppendConditionProto, AppendRequestProto, AppendResponseProto, AppendStreamHeaderProto,
+    AppendStreamRequestProto, CountRequestProto, CountResponseProto, ErrorResponseProto,
+    EventProto, HeadRequestProto, HeadResponseProto, MultiQueryEventProto, OldestTsnRequestProto,
+    OldestTsnResponseProto, QueryItemProto, QueryProto, ReadAsOfRequestProto,
+    ReadByUuidRequestProto, ReadByUuidResponseProto, ReadEventDataRequestProto,
+    ReadEventDataResponseProto, ReadMultiRequestProto, ReadMultiResponseProto, ReadRequestProto,
+    ReadResponseProto, SequencedEventProto, StatsRequestProto, StatsResponseProto,
 };
 
 use prost::Message;
 use prost::bytes::Bytes;
 use tonic::{Code, Status};
 use umadb_dcb::{
-    DCBAppendCondition, DCBError, DCBEvent, DCBQuery, DCBQueryItem, DCBResult, DCBSequencedEvent,
+    DCBAppendCondition, DCBError, DCBEvent, DCBMultiQueryEvent, DCBQuery, DCBQueryItem, DCBResult,
+    DCBSequencedEvent, DbStats,
 };
 use uuid::Uuid;
 
@@ -30,9 +36,10 @@ impl TryFrom<EventProto> for DCBEvent {
             match Uuid::parse_str(&proto.uuid) {
                 Ok(uuid) => Some(uuid),
                 Err(_) => {
-                    return Err(DCBError::DeserializationError(
-                        "Invalid UUID in EventProto".to_string(),
-                    ));
+                    return Err(DCBError::InvalidUuid(format!(
+                        "{:?} in EventProto",
+                        proto.uuid
+                    )));
                 }
             }
         };
@@ -42,6 +49,7 @@ impl TryFrom<EventProto> for DCBEvent {
             tags: proto.tags,
             data: proto.data,
             uuid,
+            client_timestamp: proto.client_timestamp,
         })
     }
 }
@@ -53,6 +61,7 @@ impl From<DCBEvent> for EventProto {
             tags: event.tags,
             data: event.data,
             uuid: event.uuid.map(|u| u.to_string()).unwrap_or_default(),
+            client_timestamp: event.client_timestamp,
         }
     }
 }
@@ -79,6 +88,7 @@ impl From<QueryProto> for DCBQuery {
     fn from(proto: QueryProto) -> Self {
         DCBQuery {
             items: proto.items.into_iter().map(|item| item.into()).collect(),
+            case_insensitive: proto.case_insensitive,
         }
     }
 }
@@ -87,6 +97,7 @@ impl From<DCBQuery> for QueryProto {
     fn from(query: DCBQuery) -> Self {
         QueryProto {
             items: query.items.into_iter().map(|item| item.into()).collect(),
+            case_insensitive: query.case_insensitive,
         }
     }
 }
@@ -107,32 +118,142 @@ impl From<DCBSequencedEvent> for SequencedEventProto {
         SequencedEventProto {
             position: event.position,
             event: Some(event.event.into()),
+            commit_timestamp: event.commit_timestamp,
+            tsn: event.tsn,
+        }
+    }
+}
+
+impl TryFrom<SequencedEventProto> for DCBSequencedEvent {
+    type Error = DCBError;
+
+    fn try_from(proto: SequencedEventProto) -> DCBResult<Self> {
+        let event = proto.event.ok_or_else(|| {
+            DCBError::DeserializationError("Missing event in SequencedEventProto".to_string())
+        })?;
+        Ok(DCBSequencedEvent {
+            event: event.try_into()?,
+            position: proto.position,
+            commit_timestamp: proto.commit_timestamp,
+            tsn: proto.tsn,
+        })
+    }
+}
+
+impl From<DCBMultiQueryEvent> for MultiQueryEventProto {
+    fn from(event: DCBMultiQueryEvent) -> Self {
+        MultiQueryEventProto {
+            event: Some(event.event.into()),
+            query_indices: event.query_indices.into_iter().map(|i| i as u32).collect(),
+        }
+    }
+}
+
+impl TryFrom<MultiQueryEventProto> for DCBMultiQueryEvent {
+    type Error = DCBError;
+
+    fn try_from(proto: MultiQueryEventProto) -> DCBResult<Self> {
+        let event = proto.event.ok_or_else(|| {
+            DCBError::DeserializationError("Missing event in MultiQueryEventProto".to_string())
+        })?;
+        Ok(DCBMultiQueryEvent {
+            event: event.try_into()?,
+            query_indices: proto
+                .query_indices
+                .into_iter()
+                .map(|i| i as usize)
+                .collect(),
+        })
+    }
+}
+
+impl From<DbStats> for StatsResponseProto {
+    fn from(stats: DbStats) -> Self {
+        StatsResponseProto {
+            total_pages: stats.total_pages,
+            free_pages: stats.free_pages,
+            event_count: stats.event_count,
+            events_tree_height: stats.events_tree_height,
+            file_size: stats.file_size,
+        }
+    }
+}
+
+impl From<StatsResponseProto> for DbStats {
+    fn from(proto: StatsResponseProto) -> Self {
+        DbStats {
+            total_pages: proto.total_pages,
+            free_pages: proto.free_pages,
+            event_count: proto.event_count,
+            events_tree_height: proto.events_tree_height,
+            file_size: proto.file_size,
         }
     }
 }
 
 // Helper: map DCBError -> tonic::Status with structured details
+//
+// Failed conditions and out-of-order positions are FAILED_PRECONDITION, events
+// that can't be stored are INVALID_ARGUMENT and damaged data is DATA_LOSS, none of
+// which will succeed if retried, missing events are NOT_FOUND, and commits that are
+// no longer retained are OUT_OF_RANGE. Timeouts are DEADLINE_EXCEEDED and transport
+// errors UNAVAILABLE, which may. Everything else is INTERNAL.
 pub fn status_from_dcb_error(e: &DCBError) -> Status {
     let (code, error_type) = match e {
-        DCBError::IntegrityError(_) => (
+        DCBError::IntegrityError(_) | DCBError::IntegrityViolation { .. } => (
             Code::FailedPrecondition,
             umadb::error_response_proto::ErrorType::Integrity as i32,
         ),
         DCBError::Corruption(_)
         | DCBError::DatabaseCorrupted(_)
-        | DCBError::DeserializationError(_) => (
+        | DCBError::DeserializationError(_)
+        | DCBError::ChecksumMismatch { .. }
+        | DCBError::UnexpectedEndOfData(_) => (
             Code::DataLoss,
             umadb::error_response_proto::ErrorType::Corruption as i32,
         ),
-        DCBError::SerializationError(_) => (
+        DCBError::SerializationError(_) | DCBError::InvalidUuid(_) | DCBError::PageOverflow(..) => {
+            (
+                Code::InvalidArgument,
+                umadb::error_response_proto::ErrorType::Serialization as i32,
+            )
+        }
+        DCBError::InvalidArgument(_) => (
             Code::InvalidArgument,
-            umadb::error_response_proto::ErrorType::Serialization as i32,
+            umadb::error_response_proto::ErrorType::InvalidArgument as i32,
         ),
-        DCBError::InternalError(_) => (
+        DCBError::InternalError(_)
+        | DCBError::PageNotFound(_)
+        | DCBError::DirtyPageNotFound(_)
+        | DCBError::RootIDMismatch(..)
+        | DCBError::PageAlreadyFreed(_)
+        | DCBError::PageAlreadyDirty(_)
+        | DCBError::PageSizeMismatch(..)
+        | DCBError::EncryptionError(_) => (
             Code::Internal,
             umadb::error_response_proto::ErrorType::Internal as i32,
         ),
-        _ => (
+        DCBError::OutOfRetention(_) => (
+            Code::OutOfRange,
+            umadb::error_response_proto::ErrorType::OutOfRetention as i32,
+        ),
+        DCBError::EventNotFound(_) => (
+            Code::NotFound,
+            umadb::error_response_proto::ErrorType::NotFound as i32,
+        ),
+        DCBError::Timeout(_) => (
+            Code::DeadlineExceeded,
+            umadb::error_response_proto::ErrorType::Timeout as i32,
+        ),
+        DCBError::TransportError(_) => (
+            Code::Unavailable,
+            umadb::error_response_proto::ErrorType::Io as i32,
+        ),
+        DCBError::CancelledByUser() => (
+            Code::Cancelled,
+            umadb::error_response_proto::ErrorType::Io as i32,
+        ),
+        DCBError::Io(_) => (
             Code::Internal,
             umadb::error_response_proto::ErrorType::Io as i32,
         ),
@@ -166,6 +287,19 @@ pub fn dcb_error_from_status(status: Status) -> DCBError {
             x if x == umadb::error_response_proto::ErrorType::Internal as i32 => {
                 DCBError::InternalError(err.message)
             }
+            x if x == umadb::error_response_proto::ErrorType::Timeout as i32 => {
+                DCBError::Timeout(err.message)
+            }
+            x if x == umadb::error_response_proto::ErrorType::NotFound as i32 => {
+                DCBError::EventNotFound(err.message)
+            }
+            x if x == umadb::error_response_proto::ErrorType::InvalidArgument as i32 => {
+                DCBError::InvalidArgument(err.message)
+            }
+            x if x == umadb::error_response_proto::ErrorType::OutOfRetention as i32 => {
+                DCBError::OutOfRetention(err.message)
+            }
+            _ if status.code() == Code::Unavailable => DCBError::TransportError(err.message),
             _ => DCBError::Io(std::io::Error::other(err.message)),
         };
     }
@@ -175,6 +309,92 @@ pub fn dcb_error_from_status(status: Status) -> DCBError {
         Code::DataLoss => DCBError::Corruption(status.message().to_string()),
         Code::InvalidArgument => DCBError::SerializationError(status.message().to_string()),
         Code::Internal => DCBError::InternalError(status.message().to_string()),
+        Code::DeadlineExceeded => DCBError::Timeout(status.message().to_string()),
+        Code::NotFound => DCBError::EventNotFound(status.message().to_string()),
+        Code::OutOfRange => DCBError::OutOfRetention(status.message().to_string()),
+        Code::Unavailable => DCBError::TransportError(status.message().to_string()),
         _ => DCBError::Io(std::io::Error::other(format!("gRPC error: {}", status))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes_for_structured_errors() {
+        let status = status_from_dcb_error(&DCBError::ChecksumMismatch { page_id: 7 });
+        assert_eq!(Code::DataLoss, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::Corruption(msg) if msg.contains("page 7")
+        ));
+
+        let status = status_from_dcb_error(&DCBError::IntegrityViolation {
+            expected: "a position after 5".to_string(),
+            actual: "position 3".to_string(),
+        });
+        assert_eq!(Code::FailedPrecondition, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::IntegrityError(msg) if msg.contains("a position after 5")
+        ));
+
+        let status = status_from_dcb_error(&DCBError::Timeout("slow".to_string()));
+        assert_eq!(Code::DeadlineExceeded, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::Timeout(_)
+        ));
+        let status = status_from_dcb_error(&DCBError::PageOverflow(3, 5000, 4087));
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::SerializationError(_)
+        ));
+
+        let status = status_from_dcb_error(&DCBError::InvalidArgument("10000 tags".to_string()));
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::InvalidArgument(msg) if msg.contains("10000 tags")
+        ));
+
+        let status = status_from_dcb_error(&DCBError::OutOfRetention("Tsn(1)".to_string()));
+        assert_eq!(Code::OutOfRange, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::OutOfRetention(msg) if msg.contains("Tsn(1)")
+        ));
+
+        let status = status_from_dcb_error(&DCBError::EventNotFound("position 9".to_string()));
+        assert_eq!(Code::NotFound, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::EventNotFound(msg) if msg.contains("position 9")
+        ));
+
+        let status = status_from_dcb_error(&DCBError::TransportError("down".to_string()));
+        assert_eq!(Code::Unavailable, status.code());
+        assert!(matches!(
+            dcb_error_from_status(status),
+            DCBError::TransportError(_)
+        ));
+    }
+
+    #[test]
+    fn test_sequenced_event_timestamps_round_trip() {
+        let event = DCBSequencedEvent {
+            event: DCBEvent::new().event_type("E").client_timestamp(1),
+            position: 3,
+            commit_timestamp: Some(2),
+            tsn: Some(4),
+        };
+        let proto = SequencedEventProto::from(event);
+        assert_eq!(Some(1), proto.event.as_ref().unwrap().client_timestamp);
+        let event = DCBSequencedEvent::try_from(proto).unwrap();
+        assert_eq!(Some(1), event.event.client_timestamp);
+        assert_eq!(Some(2), event.commit_timestamp);
+        assert_eq!(Some(4), event.tsn);
+    }
+}
@@ -37,12 +37,13 @@ pub struct PyEvent {
 #[pymethods]
 impl PyEvent {
     #[new]
-    #[pyo3(signature = (event_type, data, tags=None, uuid=None))]
+    #[pyo3(signature = (event_type, data, tags=None, uuid=None, client_timestamp=None))]
     fn new(
         event_type: String,
         data: Vec<u8>,
         tags: Option<Vec<String>>,
         uuid: Option<String>,
+        client_timestamp: Option<u64>,
     ) -> PyResult<Self> {
         let uuid_parsed = if let Some(uuid_str) = uuid {
             Some(
@@ -59,6 +60,7 @@ impl PyEvent {
                 data,
                 tags: tags.unwrap_or_default(),
                 uuid: uuid_parsed,
+                client_timestamp,
             },
         })
     }
@@ -83,6 +85,11 @@ impl PyEvent {
         self.inner.uuid.map(|u| u.to_string())
     }
 
+    #[getter]
+    fn client_timestamp(&self) -> Option<u64> {
+        self.inner.client_timestamp
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "Event(event_type='{}', data=<{} bytes>, tags={:?}, uuid={:?})",
@@ -114,6 +121,16 @@ impl PySequencedEvent {
         self.inner.position
     }
 
+    #[getter]
+    fn commit_timestamp(&self) -> Option<u64> {
+        self.inner.commit_timestamp
+    }
+
+    #[getter]
+    fn tsn(&self) -> Option<u64> {
+        self.inner.tsn
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "SequencedEvent(position={}, event_type='{}')",
@@ -160,8 +177,8 @@ pub struct PyQuery {
 #[pymethods]
 impl PyQuery {
     #[new]
-    #[pyo3(signature = (items=None))]
-    fn new(items: Option<Vec<PyQueryItem>>) -> Self {
+    #[pyo3(signature = (items=None, case_insensitive=false))]
+    fn new(items: Option<Vec<PyQueryItem>>, case_insensitive: bool) -> Self {
         let query_items = items
             .unwrap_or_default()
             .into_iter()
@@ -169,7 +186,10 @@ impl PyQuery {
             .collect();
 
         PyQuery {
-            inner: DCBQuery { items: query_items },
+            inner: DCBQuery {
+                items: query_items,
+                case_insensitive,
+            },
         }
     }
 
@@ -1,31 +1,53 @@
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::thread;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::{mpsc, oneshot, watch};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::{Identity, ServerTlsConfig};
-use tonic::{Request, Response, Status, transport::Server};
+use tonic::{Request, Response, Status, Streaming, transport::Server};
 
 use umadb_core::db::{
-    DEFAULT_DB_FILENAME, DEFAULT_PAGE_SIZE, UmaDB, is_request_idempotent, read_conditional,
+    DEFAULT_DB_FILENAME, DEFAULT_PAGE_SIZE, UmaDB, committed_event_data, count_conditional,
+    find_event_by_uuid, is_request_idempotent, read_conditional, read_multi_conditional,
+};
+use umadb_core::mvcc::{DurabilityMode, Mvcc};
+use umadb_dcb::{
+    DCBAppendCondition, DCBError, DCBEvent, DCBMultiQueryEvent, DCBQuery, DCBResult,
+    DCBSequencedEvent, DbStats,
 };
-use umadb_core::mvcc::Mvcc;
-use umadb_dcb::{DCBAppendCondition, DCBError, DCBEvent, DCBQuery, DCBResult, DCBSequencedEvent};
 
 use tokio::runtime::Runtime;
-use umadb_core::common::Position;
+use umadb_core::common::{Position, Tsn};
 use umadb_proto::{
-    AppendRequestProto, AppendResponseProto, HeadRequestProto, HeadResponseProto, ReadRequestProto,
-    ReadResponseProto, SequencedEventProto, UmaDbService, UmaDbServiceServer,
-    status_from_dcb_error,
+    AppendRequestProto, AppendResponseProto, AppendStreamPartProto, AppendStreamRequestProto,
+    CountRequestProto, CountResponseProto, HeadRequestProto, HeadResponseProto,
+    MultiQueryEventProto, OldestTsnRequestProto, OldestTsnResponseProto, ReadAsOfRequestProto,
+    ReadByUuidRequestProto, ReadByUuidResponseProto, ReadEventDataRequestProto,
+    ReadEventDataResponseProto, ReadMultiRequestProto, ReadMultiResponseProto, ReadRequestProto,
+    ReadResponseProto, SequencedEventProto, StatsRequestProto, StatsResponseProto, UmaDbService,
+    UmaDbServiceServer, status_from_dcb_error,
 };
+use uuid::Uuid;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
 
 const APPEND_BATCH_MAX_EVENTS: usize = 2000;
 const READ_RESPONSE_BATCH_SIZE_DEFAULT: u32 = 100;
 const READ_RESPONSE_BATCH_SIZE_MAX: u32 = 5000;
+// Size of the chunks the writer thread reads a spooled append stream in
+const APPEND_STREAM_SPOOL_CHUNK_SIZE: usize = 1024 * 1024;
+// Chunks of streamed event data read ahead of the client
+const READ_EVENT_DATA_CHUNKS_BUFFERED: usize = 16;
+// Batches of a multi-query read scanned ahead of the client
+const READ_MULTI_BATCHES_BUFFERED: usize = 16;
+// Recent commits that can be read as of, whose replaced pages aren't reused meanwhile
+const HISTORY_RETENTION_COMMITS: usize = 64;
 
 // Optional TLS configuration helpers
 #[derive(Clone, Debug)]
@@ -60,7 +82,18 @@ pub async fn start_server<P: AsRef<Path> + Send + 'static>(
     addr: &str,
     shutdown_rx: oneshot::Receiver<()>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    start_server_internal(path, addr, shutdown_rx, None).await
+    start_server_internal(path, addr, shutdown_rx, None, None).await
+}
+
+/// Start server, and serve Prometheus metrics from GET /metrics on `metrics_addr`.
+#[cfg(feature = "metrics")]
+pub async fn start_server_with_metrics<P: AsRef<Path> + Send + 'static>(
+    path: P,
+    addr: &str,
+    metrics_addr: &str,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    start_server_internal(path, addr, shutdown_rx, None, Some(metrics_addr)).await
 }
 
 /// Start server with TLS using PEM-encoded cert and key.
@@ -72,7 +105,7 @@ pub async fn start_server_secure<P: AsRef<Path> + Send + 'static>(
     key_pem: Vec<u8>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let tls = ServerTlsOptions { cert_pem, key_pem };
-    start_server_internal(path, addr, shutdown_rx, Some(tls)).await
+    start_server_internal(path, addr, shutdown_rx, Some(tls), None).await
 }
 
 /// Convenience: load cert and key from filesystem paths
@@ -114,11 +147,22 @@ async fn start_server_internal<P: AsRef<Path> + Send + 'static>(
     addr: &str,
     shutdown_rx: oneshot::Receiver<()>,
     tls: Option<ServerTlsOptions>,
+    metrics_addr: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let addr = addr.parse()?;
     // Create a shutdown broadcast channel for terminating ongoing subscriptions
     let (srv_shutdown_tx, srv_shutdown_rx) = watch::channel(false);
     let server = UmaDBServer::new(path, srv_shutdown_rx)?;
+    #[cfg(feature = "metrics")]
+    if let Some(metrics_addr) = metrics_addr {
+        let listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+        println!("UmaDB metrics served on http://{metrics_addr}/metrics");
+        tokio::spawn(server.serve_metrics(listener));
+    }
+    #[cfg(not(feature = "metrics"))]
+    if metrics_addr.is_some() {
+        return Err("UmaDB server was built without the metrics feature".into());
+    }
     if tls.is_some() {
         println!("Started UmaDB server (with TLS) listening on {addr}");
     } else {
@@ -182,6 +226,21 @@ impl UmaDBServer {
     pub fn into_service(self) -> UmaDbServiceServer<Self> {
         UmaDbServiceServer::new(self)
     }
+
+    /// Returns a future that serves the server's metrics on the listener until the
+    /// server shuts down.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(
+        &self,
+        listener: tokio::net::TcpListener,
+    ) -> impl Future<Output = std::io::Result<()>> + Send + 'static {
+        metrics::serve(
+            listener,
+            self.request_handler.metrics.clone(),
+            self.request_handler.mvcc.clone(),
+            self.shutdown_watch_rx.clone(),
+        )
+    }
 }
 
 #[tonic::async_trait]
@@ -406,19 +465,214 @@ impl UmaDbService for UmaDBServer {
         }
     }
 
+    async fn append_stream(
+        &self,
+        request: Request<Streaming<AppendStreamRequestProto>>,
+    ) -> Result<Response<AppendResponseProto>, Status> {
+        let mut stream = request.into_inner();
+        let header = match stream.message().await? {
+            Some(AppendStreamRequestProto {
+                part: Some(AppendStreamPartProto::Header(header)),
+            }) => header,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "append stream must start with a header",
+                ));
+            }
+        };
+        let event: DCBEvent = header
+            .event
+            .unwrap_or_default()
+            .try_into()
+            .map_err(|e| status_from_dcb_error(&e))?;
+
+        // Spool the chunks to a temporary file as they arrive, so that the writer
+        // thread reads them at the speed of the disk rather than of the client
+        let spool = self
+            .request_handler
+            .mvcc
+            .create_temp_file()
+            .map_err(|e| status_from_dcb_error(&e))?;
+        let mut spool = tokio::fs::File::from_std(spool);
+        let mut spooled = 0u64;
+        while let Some(message) = stream.message().await? {
+            let AppendStreamRequestProto {
+                part: Some(AppendStreamPartProto::Chunk(chunk)),
+            } = message
+            else {
+                return Err(status_from_dcb_error(&DCBError::SerializationError(
+                    "append stream has a second header".to_string(),
+                )));
+            };
+            spooled += chunk.len() as u64;
+            if spooled > header.data_len {
+                return Err(status_from_dcb_error(&DCBError::SerializationError(
+                    format!(
+                        "streamed event data is longer than its declared {} bytes",
+                        header.data_len
+                    ),
+                )));
+            }
+            spool
+                .write_all(&chunk)
+                .await
+                .map_err(|e| status_from_dcb_error(&DCBError::Io(e)))?;
+        }
+        let spool = spool.into_std().await;
+        match self
+            .request_handler
+            .append_stream(event, header.data_len, spool)
+            .await
+        {
+            Ok(position) => Ok(Response::new(AppendResponseProto { position })),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        }
+    }
+
+    type ReadAsOfStream =
+        Pin<Box<dyn Stream<Item = Result<ReadResponseProto, Status>> + Send + 'static>>;
+
+    async fn read_as_of(
+        &self,
+        request: Request<ReadAsOfRequestProto>,
+    ) -> Result<Response<Self::ReadAsOfStream>, Status> {
+        let request = request.into_inner();
+        let batches = self
+            .request_handler
+            .read_as_of(request.tsn, request.query.map(DCBQuery::from))
+            .map_err(|e| status_from_dcb_error(&e))?;
+        let stream = ReceiverStream::new(batches).map(|batch| match batch {
+            Ok((events, head)) => Ok(ReadResponseProto {
+                events: events.into_iter().map(SequencedEventProto::from).collect(),
+                head,
+            }),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type ReadMultiStream =
+        Pin<Box<dyn Stream<Item = Result<ReadMultiResponseProto, Status>> + Send + 'static>>;
+
+    async fn read_multi(
+        &self,
+        request: Request<ReadMultiRequestProto>,
+    ) -> Result<Response<Self::ReadMultiStream>, Status> {
+        let request = request.into_inner();
+        let queries: Vec<DCBQuery> = request.queries.into_iter().map(DCBQuery::from).collect();
+        let batches = self.request_handler.read_multi(queries, request.after);
+        let stream = ReceiverStream::new(batches).map(|batch| match batch {
+            Ok(events) => Ok(ReadMultiResponseProto {
+                events: events.into_iter().map(MultiQueryEventProto::from).collect(),
+            }),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type ReadEventDataStream =
+        Pin<Box<dyn Stream<Item = Result<ReadEventDataResponseProto, Status>> + Send + 'static>>;
+
+    async fn read_event_data(
+        &self,
+        request: Request<ReadEventDataRequestProto>,
+    ) -> Result<Response<Self::ReadEventDataStream>, Status> {
+        let position = request.into_inner().position;
+        let chunks = self
+            .request_handler
+            .read_event_data(position)
+            .await
+            .map_err(|e| status_from_dcb_error(&e))?;
+        let stream = ReceiverStream::new(chunks).map(|chunk| match chunk {
+            Ok(chunk) => Ok(ReadEventDataResponseProto { chunk }),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
     async fn head(
         &self,
         _request: Request<HeadRequestProto>,
     ) -> Result<Response<HeadResponseProto>, Status> {
         // Call the event store head method
-        match self.request_handler.head().await {
-            Ok(position) => {
-                // Return the position as a response
-                Ok(Response::new(HeadResponseProto { position }))
+        match self.request_handler.head_with_tsn().await {
+            Ok((position, tsn)) => {
+                // Return the position and its commit's TSN as a response
+                Ok(Response::new(HeadResponseProto { position, tsn }))
             }
             Err(e) => Err(status_from_dcb_error(&e)),
         }
     }
+
+    async fn oldest_tsn(
+        &self,
+        _request: Request<OldestTsnRequestProto>,
+    ) -> Result<Response<OldestTsnResponseProto>, Status> {
+        match self.request_handler.oldest_tsn() {
+            Ok(tsn) => Ok(Response::new(OldestTsnResponseProto { tsn })),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        }
+    }
+
+    async fn count(
+        &self,
+        request: Request<CountRequestProto>,
+    ) -> Result<Response<CountResponseProto>, Status> {
+        let query = request.into_inner().query.map(|q| q.into());
+        match self.request_handler.count(query).await {
+            Ok(count) => Ok(Response::new(CountResponseProto { count })),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        }
+    }
+
+    async fn read_by_uuid(
+        &self,
+        request: Request<ReadByUuidRequestProto>,
+    ) -> Result<Response<ReadByUuidResponseProto>, Status> {
+        let uuid = request.into_inner().uuid;
+        let uuid = match Uuid::parse_str(&uuid) {
+            Ok(uuid) => uuid,
+            Err(_) => return Err(status_from_dcb_error(&DCBError::InvalidUuid(uuid))),
+        };
+        match self.request_handler.read_by_uuid(uuid).await {
+            Ok(event) => Ok(Response::new(ReadByUuidResponseProto {
+                event: event.map(SequencedEventProto::from),
+            })),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        }
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<StatsRequestProto>,
+    ) -> Result<Response<StatsResponseProto>, Status> {
+        match self.request_handler.stats().await {
+            Ok(stats) => Ok(Response::new(stats.into())),
+            Err(e) => Err(status_from_dcb_error(&e)),
+        }
+    }
+}
+
+// A batch of events read, with the head as of the snapshot they were read from
+type ReadBatch = (Vec<DCBSequencedEvent>, Option<u64>);
+
+// Reads the data spooled for an append stream back from the start, a chunk at a time
+fn spooled_chunks(mut spool: fs::File) -> impl Iterator<Item = DCBResult<Vec<u8>>> {
+    let mut rewound = spool.seek(SeekFrom::Start(0)).map(|_| ());
+    std::iter::from_fn(move || {
+        if let Err(err) = std::mem::replace(&mut rewound, Ok(())) {
+            return Some(Err(DCBError::Io(err)));
+        }
+        let mut chunk = vec![0u8; APPEND_STREAM_SPOOL_CHUNK_SIZE];
+        match spool.read(&mut chunk) {
+            Ok(0) => None,
+            Ok(len) => {
+                chunk.truncate(len);
+                Some(Ok(chunk))
+            }
+            Err(err) => Some(Err(DCBError::Io(err))),
+        }
+    })
 }
 
 // Message types for communication between the gRPC server and the request handler's writer thread
@@ -428,6 +682,13 @@ enum WriterRequest {
         condition: Option<DCBAppendCondition>,
         response_tx: oneshot::Sender<DCBResult<u64>>,
     },
+    AppendStream {
+        event: DCBEvent,
+        data_len: u64,
+        // Holds the whole of the event's data
+        spool: fs::File,
+        response_tx: oneshot::Sender<DCBResult<u64>>,
+    },
     Shutdown,
 }
 
@@ -436,6 +697,8 @@ struct RequestHandler {
     mvcc: Arc<Mvcc>,
     head_watch_tx: watch::Sender<Option<u64>>,
     writer_request_tx: mpsc::Sender<WriterRequest>,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<metrics::Metrics>,
 }
 
 impl RequestHandler {
@@ -451,7 +714,8 @@ impl RequestHandler {
             p.to_path_buf()
         };
         let mvcc = Arc::new(
-            Mvcc::new(&file_path, DEFAULT_PAGE_SIZE, false)
+            Mvcc::new(&file_path, DEFAULT_PAGE_SIZE, DurabilityMode::default())
+                .and_then(|mvcc| mvcc.with_history_retention(HISTORY_RETENTION_COMMITS))
                 .map_err(|e| std::io::Error::other(format!("Failed to init LMDB: {e:?}")))?,
         );
 
@@ -469,14 +733,25 @@ impl RequestHandler {
         let mvcc_for_writer = mvcc.clone();
         let head_tx_writer = head_tx.clone();
         thread::spawn(move || {
-            let db = UmaDB::from_arc(mvcc_for_writer);
+            // Appending events with UUIDs that are already recorded is a no-op,
+            // so clients can safely retry appends
+            let db = UmaDB::from_arc(mvcc_for_writer).with_uuid_deduplication(true);
 
             // Create a runtime for processing writer requests.
             let rt = Runtime::new().unwrap();
 
             // Process writer requests.
             rt.block_on(async {
-                while let Some(request) = request_rx.recv().await {
+                // A request taken from the channel while batching, to be handled next
+                let mut deferred: Option<WriterRequest> = None;
+                loop {
+                    let request = match deferred.take() {
+                        Some(request) => request,
+                        None => match request_rx.recv().await {
+                            Some(request) => request,
+                            None => break,
+                        },
+                    };
                     match request {
                         WriterRequest::Append {
                             events,
@@ -511,6 +786,10 @@ impl RequestHandler {
                                         responders.push(response_tx);
                                         total_events += ev_len;
                                     }
+                                    Ok(request @ WriterRequest::AppendStream { .. }) => {
+                                        deferred = Some(request);
+                                        break;
+                                    }
                                     Ok(WriterRequest::Shutdown) => {
                                         // Push back the shutdown signal by breaking and letting
                                         // outer loop handle after batch. We'll process the
@@ -530,8 +809,7 @@ impl RequestHandler {
                                     // Send individual results back to requesters
                                     // Also compute the new head as the maximum successful last position in this batch
                                     let mut max_ok: Option<u64> = None;
-                                    for (res, tx) in results.into_iter().zip(responders.into_iter())
-                                    {
+                                    for (res, tx) in results.into_iter().zip(responders) {
                                         if let Ok(v) = &res {
                                             max_ok = Some(max_ok.map_or(*v, |m| m.max(*v)));
                                         }
@@ -554,6 +832,15 @@ impl RequestHandler {
                                             DCBError::IntegrityError(s) => {
                                                 DCBError::IntegrityError(s.clone())
                                             }
+                                            DCBError::IntegrityViolation { expected, actual } => {
+                                                DCBError::IntegrityViolation {
+                                                    expected: expected.clone(),
+                                                    actual: actual.clone(),
+                                                }
+                                            }
+                                            DCBError::EventNotFound(s) => {
+                                                DCBError::EventNotFound(s.clone())
+                                            }
                                             DCBError::Corruption(s) => {
                                                 DCBError::Corruption(s.clone())
                                             }
@@ -584,9 +871,36 @@ impl RequestHandler {
                                             DCBError::PageAlreadyDirty(id) => {
                                                 DCBError::PageAlreadyDirty(*id)
                                             }
+                                            DCBError::PageOverflow(id, size, max) => {
+                                                DCBError::PageOverflow(*id, *size, *max)
+                                            }
+                                            DCBError::PageSizeMismatch(stored, given) => {
+                                                DCBError::PageSizeMismatch(*stored, *given)
+                                            }
+                                            DCBError::EncryptionError(s) => {
+                                                DCBError::EncryptionError(s.clone())
+                                            }
+                                            DCBError::ChecksumMismatch { page_id } => {
+                                                DCBError::ChecksumMismatch { page_id: *page_id }
+                                            }
+                                            DCBError::UnexpectedEndOfData(s) => {
+                                                DCBError::UnexpectedEndOfData(s.clone())
+                                            }
+                                            DCBError::InvalidUuid(s) => {
+                                                DCBError::InvalidUuid(s.clone())
+                                            }
+                                            DCBError::InvalidArgument(s) => {
+                                                DCBError::InvalidArgument(s.clone())
+                                            }
+                                            DCBError::OutOfRetention(s) => {
+                                                DCBError::OutOfRetention(s.clone())
+                                            }
                                             DCBError::TransportError(err) => {
                                                 DCBError::TransportError(err.clone())
                                             }
+                                            DCBError::Timeout(err) => {
+                                                DCBError::Timeout(err.clone())
+                                            }
                                             DCBError::CancelledByUser() => {
                                                 DCBError::CancelledByUser()
                                             }
@@ -602,6 +916,25 @@ impl RequestHandler {
                                 }
                             }
                         }
+                        WriterRequest::AppendStream {
+                            event,
+                            data_len,
+                            spool,
+                            response_tx,
+                        } => {
+                            let result = db.append_stream(event, data_len, spooled_chunks(spool));
+                            if let Ok(position) = &result {
+                                // An event deduplicated by its UUID may be behind the head
+                                head_tx_writer.send_if_modified(|head| {
+                                    let advanced = head.is_none_or(|head| head < *position);
+                                    if advanced {
+                                        *head = Some(*position);
+                                    }
+                                    advanced
+                                });
+                            }
+                            let _ = response_tx.send(result);
+                        }
                         WriterRequest::Shutdown => {
                             break;
                         }
@@ -614,6 +947,8 @@ impl RequestHandler {
             mvcc,
             head_watch_tx: head_tx,
             writer_request_tx: request_tx,
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(metrics::Metrics::new()),
         })
     }
 
@@ -624,10 +959,15 @@ impl RequestHandler {
         backwards: bool,
         limit: Option<u32>,
     ) -> DCBResult<(Vec<DCBSequencedEvent>, Option<u64>)> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
         let reader = self.mvcc.reader()?;
         let last_committed_position = reader.next_position.0.saturating_sub(1);
 
-        let q = query.unwrap_or(DCBQuery { items: vec![] });
+        let q = query.unwrap_or(DCBQuery {
+            items: vec![],
+            case_insensitive: false,
+        });
         let start_position = start.map(Position);
 
         let events = read_conditional(
@@ -640,8 +980,7 @@ impl RequestHandler {
             backwards,
             limit,
             false,
-        )
-        .map_err(|e| DCBError::Corruption(format!("{e}")))?;
+        )?;
 
         let head = if limit.is_none() {
             if last_committed_position == 0 {
@@ -653,21 +992,218 @@ impl RequestHandler {
             events.last().map(|e| e.position)
         };
 
+        #[cfg(feature = "metrics")]
+        self.metrics.record_read(events.len(), started.elapsed());
         Ok((events, head))
     }
 
     async fn head(&self) -> DCBResult<Option<u64>> {
-        let (_, header) = self
-            .mvcc
-            .get_latest_header()
-            .map_err(|e| DCBError::Corruption(format!("{e}")))?;
+        Ok(self.head_with_tsn().await?.0)
+    }
+
+    /// Returns the head position along with the TSN of the committed header it was
+    /// read from, so both describe the same state.
+    async fn head_with_tsn(&self) -> DCBResult<(Option<u64>, u64)> {
+        let (_, header) = self.mvcc.get_latest_header()?;
         let last = header.next_position.0.saturating_sub(1);
-        if last == 0 { Ok(None) } else { Ok(Some(last)) }
+        let position = if last == 0 { None } else { Some(last) };
+        Ok((position, header.tsn.0))
+    }
+
+    /// Returns the TSN of the oldest commit that read_as_of() can read.
+    fn oldest_tsn(&self) -> DCBResult<u64> {
+        Ok(self.mvcc.oldest_tsn()?.0)
+    }
+
+    async fn count(&self, query: Option<DCBQuery>) -> DCBResult<u64> {
+        let reader = self.mvcc.reader()?;
+        count_conditional(
+            &self.mvcc,
+            &std::collections::HashMap::new(),
+            reader.events_tree_root_id,
+            reader.tags_tree_root_id,
+            reader.next_position,
+            query.unwrap_or_default(),
+        )
+    }
+
+    /// Starts sending the events that match the query as of the commit with the TSN
+    /// to the returned channel, in batches with the head as of that commit, from a
+    /// blocking task. Fails if the commit isn't retained.
+    fn read_as_of(
+        &self,
+        tsn: u64,
+        query: Option<DCBQuery>,
+    ) -> DCBResult<mpsc::Receiver<DCBResult<ReadBatch>>> {
+        let reader = self.mvcc.reader_as_of(Tsn(tsn))?;
+        let mvcc = self.mvcc.clone();
+        let (batch_tx, batch_rx) = mpsc::channel(READ_MULTI_BATCHES_BUFFERED);
+        tokio::task::spawn_blocking(move || {
+            let last = reader.next_position.0.saturating_sub(1);
+            let head = if last == 0 { None } else { Some(last) };
+            let query = query.unwrap_or_default();
+            let mut start = None;
+            loop {
+                let batch = match read_conditional(
+                    &mvcc,
+                    &std::collections::HashMap::new(),
+                    reader.events_tree_root_id,
+                    reader.tags_tree_root_id,
+                    query.clone(),
+                    start,
+                    false,
+                    Some(READ_RESPONSE_BATCH_SIZE_DEFAULT),
+                    false,
+                ) {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        let _ = batch_tx.blocking_send(Err(e));
+                        return;
+                    }
+                };
+                let next = batch.last().map(|event| Position(event.position + 1));
+                let full = batch.len() == READ_RESPONSE_BATCH_SIZE_DEFAULT as usize;
+                // The first batch is sent even if empty, to give the client the head
+                if (start.is_none() || !batch.is_empty())
+                    && batch_tx.blocking_send(Ok((batch, head))).is_err()
+                {
+                    return;
+                }
+                match next {
+                    Some(next) if full => start = Some(next),
+                    _ => return,
+                }
+            }
+        });
+        Ok(batch_rx)
+    }
+
+    /// Starts sending the events after `after` that match any of the queries to the
+    /// returned channel, in batches, from a blocking task that scans one snapshot.
+    fn read_multi(
+        &self,
+        queries: Vec<DCBQuery>,
+        after: Option<u64>,
+    ) -> mpsc::Receiver<DCBResult<Vec<DCBMultiQueryEvent>>> {
+        let mvcc = self.mvcc.clone();
+        let (batch_tx, batch_rx) = mpsc::channel(READ_MULTI_BATCHES_BUFFERED);
+        tokio::task::spawn_blocking(move || {
+            let reader = match mvcc.reader() {
+                Ok(reader) => reader,
+                Err(e) => {
+                    let _ = batch_tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+            let mut after = after.map(Position);
+            loop {
+                let batch = match read_multi_conditional(
+                    &mvcc,
+                    &std::collections::HashMap::new(),
+                    reader.events_tree_root_id,
+                    &queries,
+                    after,
+                    Some(READ_RESPONSE_BATCH_SIZE_DEFAULT),
+                ) {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        let _ = batch_tx.blocking_send(Err(e));
+                        return;
+                    }
+                };
+                let last = match batch.last() {
+                    Some(last) => Position(last.event.position),
+                    None => return,
+                };
+                let full = batch.len() == READ_RESPONSE_BATCH_SIZE_DEFAULT as usize;
+                if batch_tx.blocking_send(Ok(batch)).is_err() || !full {
+                    return;
+                }
+                after = Some(last);
+            }
+        });
+        batch_rx
+    }
+
+    /// Starts sending the data of the event at the position to the returned channel,
+    /// a chunk at a time, from a blocking task. Fails if there is no event there.
+    async fn read_event_data(
+        &self,
+        position: u64,
+    ) -> DCBResult<mpsc::Receiver<DCBResult<Vec<u8>>>> {
+        let mvcc = self.mvcc.clone();
+        let (found_tx, found_rx) = oneshot::channel::<DCBResult<()>>();
+        let (chunk_tx, chunk_rx) = mpsc::channel(READ_EVENT_DATA_CHUNKS_BUFFERED);
+        tokio::task::spawn_blocking(move || {
+            let chunks = match mvcc
+                .reader()
+                .and_then(|reader| committed_event_data(&mvcc, reader, position))
+            {
+                Ok(Some(chunks)) => chunks,
+                Ok(None) => {
+                    let _ = found_tx.send(Err(DCBError::EventNotFound(format!(
+                        "no event at position {position}"
+                    ))));
+                    return;
+                }
+                Err(e) => {
+                    let _ = found_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = found_tx.send(Ok(()));
+            for chunk in chunks {
+                if chunk_tx.blocking_send(chunk).is_err() {
+                    break;
+                }
+            }
+        });
+        found_rx.await.map_err(|_| {
+            DCBError::Io(std::io::Error::other(
+                "Failed to receive event data from the reading task",
+            ))
+        })??;
+        Ok(chunk_rx)
     }
+
+    async fn read_by_uuid(&self, uuid: Uuid) -> DCBResult<Option<DCBSequencedEvent>> {
+        let reader = self.mvcc.reader()?;
+        let found = find_event_by_uuid(
+            &self.mvcc,
+            &std::collections::HashMap::new(),
+            reader.events_tree_root_id,
+            reader.tags_tree_root_id,
+            uuid,
+        )?;
+        Ok(found.map(|(position, rec)| rec.into_sequenced_event(position)))
+    }
+
+    async fn stats(&self) -> DCBResult<DbStats> {
+        self.mvcc.stats()
+    }
+
     pub async fn append(
         &self,
         events: Vec<DCBEvent>,
         condition: Option<DCBAppendCondition>,
+    ) -> DCBResult<u64> {
+        #[cfg(feature = "metrics")]
+        {
+            let started = std::time::Instant::now();
+            let num_events = events.len();
+            let result = self.append_events(events, condition).await;
+            self.metrics
+                .record_append(num_events, started.elapsed(), result.is_ok());
+            result
+        }
+        #[cfg(not(feature = "metrics"))]
+        self.append_events(events, condition).await
+    }
+
+    async fn append_events(
+        &self,
+        events: Vec<DCBEvent>,
+        condition: Option<DCBAppendCondition>,
     ) -> DCBResult<u64> {
         // Concurrent pre-check of the given condition using a reader in a blocking thread.
         let pre_append_decision = if let Some(mut given_condition) = condition {
@@ -768,6 +1304,33 @@ impl RequestHandler {
         }
     }
 
+    async fn append_stream(
+        &self,
+        event: DCBEvent,
+        data_len: u64,
+        spool: fs::File,
+    ) -> DCBResult<u64> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.writer_request_tx
+            .send(WriterRequest::AppendStream {
+                event,
+                data_len,
+                spool,
+                response_tx,
+            })
+            .await
+            .map_err(|_| {
+                DCBError::Io(std::io::Error::other(
+                    "Failed to send append stream request to EventStore thread",
+                ))
+            })?;
+        response_rx.await.map_err(|_| {
+            DCBError::Io(std::io::Error::other(
+                "Failed to receive append stream response from EventStore thread",
+            ))
+        })?
+    }
+
     fn watch_head(&self) -> watch::Receiver<Option<u64>> {
         self.head_watch_tx.subscribe()
     }
@@ -785,6 +1348,8 @@ impl Clone for RequestHandler {
             mvcc: self.mvcc.clone(),
             head_watch_tx: self.head_watch_tx.clone(),
             writer_request_tx: self.writer_request_tx.clone(),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
         }
     }
 }
@@ -0,0 +1,311 @@
+// Prometheus metrics for the server, which are enabled by the "metrics" feature.
+//
+// Requests are counted and timed as they are handled, and the storage gauges are read
+// from the database when the metrics are scraped. The metrics are served in the text
+// exposition format from GET /metrics on a separate listener.
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use umadb_core::mvcc::Mvcc;
+
+// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+// Requests with longer heads than this are rejected
+const MAX_REQUEST_HEAD_SIZE: usize = 8 * 1024;
+
+#[derive(Default)]
+struct Histogram {
+    // Observations in each bucket, not cumulative
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum = Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{name}_sum {}", sum.as_secs_f64());
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// Request counters and latencies recorded by the server.
+pub struct Metrics {
+    started: Instant,
+    append_requests: AtomicU64,
+    append_errors: AtomicU64,
+    appended_events: AtomicU64,
+    append_latency: Histogram,
+    read_requests: AtomicU64,
+    read_events: AtomicU64,
+    read_latency: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            append_requests: AtomicU64::new(0),
+            append_errors: AtomicU64::new(0),
+            appended_events: AtomicU64::new(0),
+            append_latency: Histogram::default(),
+            read_requests: AtomicU64::new(0),
+            read_events: AtomicU64::new(0),
+            read_latency: Histogram::default(),
+        }
+    }
+
+    /// Records an append request of `events` events, which failed unless `ok`.
+    pub fn record_append(&self, events: usize, elapsed: Duration, ok: bool) {
+        self.append_requests.fetch_add(1, Ordering::Relaxed);
+        if ok {
+            self.appended_events
+                .fetch_add(events as u64, Ordering::Relaxed);
+        } else {
+            self.append_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.append_latency.observe(elapsed);
+    }
+
+    /// Records a read of a batch of `events` events.
+    pub fn record_read(&self, events: usize, elapsed: Duration) {
+        self.read_requests.fetch_add(1, Ordering::Relaxed);
+        self.read_events.fetch_add(events as u64, Ordering::Relaxed);
+        self.read_latency.observe(elapsed);
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format, including the
+    /// storage gauges of the given database.
+    pub fn render(&self, mvcc: &Mvcc) -> String {
+        let mut out = String::new();
+        let counter = |out: &mut String, name: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {value}");
+        };
+        let gauge = |out: &mut String, name: &str, help: &str, value: f64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {value}");
+        };
+
+        let appended_events = self.appended_events.load(Ordering::Relaxed);
+        counter(
+            &mut out,
+            "umadb_append_requests_total",
+            "Append requests handled.",
+            self.append_requests.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "umadb_append_errors_total",
+            "Append requests that failed.",
+            self.append_errors.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "umadb_appended_events_total",
+            "Events appended.",
+            appended_events,
+        );
+        self.append_latency.render(
+            &mut out,
+            "umadb_append_duration_seconds",
+            "Time taken to handle append requests.",
+        );
+        let uptime = self.started.elapsed().as_secs_f64();
+        gauge(
+            &mut out,
+            "umadb_appended_events_per_second",
+            "Events appended per second since the server started.",
+            if uptime > 0.0 {
+                appended_events as f64 / uptime
+            } else {
+                0.0
+            },
+        );
+        counter(
+            &mut out,
+            "umadb_read_requests_total",
+            "Batches of events read.",
+            self.read_requests.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "umadb_read_events_total",
+            "Events read.",
+            self.read_events.load(Ordering::Relaxed),
+        );
+        self.read_latency.render(
+            &mut out,
+            "umadb_read_duration_seconds",
+            "Time taken to read batches of events.",
+        );
+
+        let sync_stats = mvcc.commit_sync_stats();
+        counter(
+            &mut out,
+            "umadb_commit_syncs_total",
+            "Commit headers written durably.",
+            sync_stats.count,
+        );
+        let _ = writeln!(
+            out,
+            "# HELP umadb_commit_sync_seconds_total Time spent syncing commits."
+        );
+        let _ = writeln!(out, "# TYPE umadb_commit_sync_seconds_total counter");
+        let _ = writeln!(
+            out,
+            "umadb_commit_sync_seconds_total {}",
+            sync_stats.total_time.as_secs_f64()
+        );
+
+        if let Some(cache) = mvcc.page_cache_stats() {
+            counter(
+                &mut out,
+                "umadb_page_cache_hits_total",
+                "Page reads served from the page cache.",
+                cache.hits,
+            );
+            counter(
+                &mut out,
+                "umadb_page_cache_misses_total",
+                "Page reads not served from the page cache.",
+                cache.misses,
+            );
+            let lookups = cache.hits + cache.misses;
+            gauge(
+                &mut out,
+                "umadb_page_cache_hit_ratio",
+                "Fraction of page reads served from the page cache.",
+                if lookups > 0 {
+                    cache.hits as f64 / lookups as f64
+                } else {
+                    0.0
+                },
+            );
+        }
+
+        // Storage gauges are left out if the database can't be read
+        if let Ok(stats) = mvcc.stats() {
+            gauge(
+                &mut out,
+                "umadb_free_pages",
+                "Pages in the free list.",
+                stats.free_pages as f64,
+            );
+            gauge(
+                &mut out,
+                "umadb_total_pages",
+                "Pages allocated in the database file.",
+                stats.total_pages as f64,
+            );
+            gauge(
+                &mut out,
+                "umadb_events_tree_height",
+                "Levels in the events tree, including the leaf level.",
+                stats.events_tree_height as f64,
+            );
+            gauge(
+                &mut out,
+                "umadb_events",
+                "Events recorded.",
+                stats.event_count as f64,
+            );
+        }
+        out
+    }
+}
+
+/// Serves GET /metrics on the listener until the server shuts down.
+pub async fn serve(
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    mvcc: Arc<Mvcc>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let (metrics, mvcc) = (metrics.clone(), mvcc.clone());
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, metrics, mvcc).await;
+                });
+            }
+            res = shutdown_rx.changed() => {
+                if res.is_err() || *shutdown_rx.borrow() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    metrics: Arc<Metrics>,
+    mvcc: Arc<Mvcc>,
+) -> std::io::Result<()> {
+    // Read the request head, the body of a GET has nothing we need
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || head.len() + n > MAX_REQUEST_HEAD_SIZE {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let request_line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|b| *b == b' ');
+    let is_metrics = parts.next() == Some(b"GET") && parts.next() == Some(b"/metrics");
+
+    let (status, content_type, body) = if is_metrics {
+        // Reading the storage gauges walks the free list, which is blocking I/O
+        let body = tokio::task::spawn_blocking(move || metrics.render(&mvcc))
+            .await
+            .map_err(std::io::Error::other)?;
+        ("200 OK", "text/plain; version=0.0.4", body)
+    } else {
+        ("404 Not Found", "text/plain", "Not Found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}